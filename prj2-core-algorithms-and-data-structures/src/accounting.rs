@@ -106,10 +106,44 @@ impl Accounts {
                 .map(|tx_deposit| (tx_withdraw, tx_deposit))
         } else if !self.accounts.contains_key(sender) {
             Err(ApplicationError::AccountNotFound(sender.to_string()))
-        } else {
+        } else if !self.accounts.contains_key(recipient) {
             Err(ApplicationError::AccountNotFound(recipient.to_string()))
+        } else {
+            Err(ApplicationError::AccountUnderFunded(
+                sender.to_string(),
+                amount,
+            ))
         }
     }
+
+    /// Makes all `transfers` of `(sender, recipient, amount)` in order, or none of them: when one fails, the ones
+    /// before it are reversed.
+    ///
+    /// # Errors
+    /// The error of the first transfer that failed
+    pub fn send_all(
+        &mut self,
+        transfers: &[(&str, &str, u64)],
+    ) -> Result<Vec<Tx>, ApplicationError> {
+        let mut txs = Vec::with_capacity(transfers.len() * 2);
+        for (i, (sender, recipient, amount)) in transfers.iter().enumerate() {
+            match self.send(sender, recipient, *amount) {
+                Ok((tx_withdraw, tx_deposit)) => {
+                    txs.push(tx_withdraw);
+                    txs.push(tx_deposit);
+                }
+                Err(e) => {
+                    // Undoing the transfers last to first returns every account to a balance it just had, so
+                    // sending back can't fail
+                    for (sender, recipient, amount) in transfers[..i].iter().rev() {
+                        self.send(recipient, sender, *amount)?;
+                    }
+                    return Err(e);
+                }
+            }
+        }
+        Ok(txs)
+    }
 }
 
 #[cfg(test)]
@@ -247,4 +281,40 @@ mod tests {
                 .collect();
         assert_eq!(accounts.accounts, expected);
     }
+
+    #[test]
+    fn test_accounts_send_all_fails_and_rolls_back() {
+        let mut accounts = Accounts::new();
+        accounts.deposit("a-key", 100).expect("Couldn't deposit");
+        accounts.deposit("b-key", 0).expect("Couldn't deposit");
+        accounts.deposit("c-key", 10).expect("Couldn't deposit");
+
+        // b-key can only pass on what it received if the first transfer stays
+        let actual = accounts.send_all(&[
+            ("a-key", "b-key", 50),
+            ("b-key", "c-key", 50),
+            ("c-key", "a-key", 70),
+        ]);
+        assert_eq!(
+            actual,
+            Err(ApplicationError::AccountUnderFunded(
+                "c-key".to_string(),
+                70
+            ))
+        );
+        let expected: HashMap<String, u64> = vec![
+            ("a-key".to_string(), 100),
+            ("b-key".to_string(), 0),
+            ("c-key".to_string(), 10),
+        ]
+        .into_iter()
+        .collect();
+        assert_eq!(accounts.accounts, expected);
+
+        let txs = accounts
+            .send_all(&[("a-key", "b-key", 50), ("b-key", "c-key", 50)])
+            .expect("Send failed");
+        assert_eq!(txs.len(), 4);
+        assert_eq!(accounts.balance_of("c-key"), Ok(&60));
+    }
 }
@@ -1,5 +1,5 @@
 use std::cmp::Ordering;
-use std::collections::{BTreeMap, BinaryHeap};
+use std::collections::{BTreeMap, VecDeque};
 
 use crate::{
     core::{Order, Receipt, Side},
@@ -8,15 +8,15 @@ use crate::{
 
 use super::PartialOrder;
 
-#[derive(Default, Debug)]
+#[derive(Clone, Default, Debug)]
 pub struct MatchingEngine {
     /// The last sequence number
     pub ordinal: u64,
 
-    /// The "Bid" or "Buy" side of the order book. Ordered by ordinal number.
-    pub bids: BTreeMap<u64, BinaryHeap<PartialOrder>>,
-    /// The "Ask" or "Sell" side of the order book. Ordered by ordinal number.
-    pub asks: BTreeMap<u64, BinaryHeap<PartialOrder>>,
+    /// The "Bid" or "Buy" side of the order book. Each price level is a queue, oldest order first.
+    pub bids: BTreeMap<u64, VecDeque<PartialOrder>>,
+    /// The "Ask" or "Sell" side of the order book. Each price level is a queue, oldest order first.
+    pub asks: BTreeMap<u64, VecDeque<PartialOrder>>,
 
     /// Previous matches for record keeping
     pub history: Vec<Receipt>,
@@ -54,15 +54,16 @@ impl MatchingEngine {
                 // The order wasn't fully matched
                 if matched_amount < original_amount {
                     partial.remaining = original_amount - matched_amount;
-                    let bids = self.bids.entry(partial.price).or_insert(vec![].into());
+                    let bids = self.bids.entry(partial.price).or_default();
 
-                    bids.push(partial);
+                    bids.push_back(partial);
                 }
                 receipt
             }
             Side::Sell => {
                 // Fetch all orders in the expected price range from this side of the orderbook
-                let orderbook_entry = self.bids.range_mut(partial.price..=u64::MAX);
+                // The highest bid first
+                let orderbook_entry = self.bids.range_mut(partial.price..=u64::MAX).rev();
 
                 let receipt = MatchingEngine::match_order(&partial, orderbook_entry, ordinal)?;
                 let matched_amount: u64 = receipt.matches.iter().map(|m| m.amount).sum();
@@ -71,8 +72,8 @@ impl MatchingEngine {
                 if matched_amount < original_amount {
                     partial.amount = original_amount - matched_amount;
                     let price = partial.price;
-                    let asks = self.asks.entry(price).or_insert(vec![].into());
-                    asks.push(partial);
+                    let asks = self.asks.entry(price).or_default();
+                    asks.push_back(partial);
                 }
                 receipt
             }
@@ -98,7 +99,7 @@ impl MatchingEngine {
         ordinal: u64,
     ) -> Result<Receipt, ApplicationError>
     where
-        T: Iterator<Item = (&'a u64, &'a mut BinaryHeap<PartialOrder>)>,
+        T: Iterator<Item = (&'a u64, &'a mut VecDeque<PartialOrder>)>,
     {
         let mut remaining_amount = order.amount;
         let mut matches = vec![];
@@ -108,7 +109,7 @@ impl MatchingEngine {
             match orderbook_entries.next() {
                 Some((_price, orderbook_entry)) => {
                     let mut self_signed = vec![];
-                    while let Some(partial_order) = orderbook_entry.pop() {
+                    while let Some(partial_order) = orderbook_entry.pop_front() {
                         if partial_order.signer == order.signer {
                             self_signed.push(partial_order.clone());
                             continue;
@@ -154,13 +155,15 @@ impl MatchingEngine {
                                     signer: partial_order.signer.clone(),
                                     ordinal,
                                 });
-                                orderbook_entry.push(partial);
+                                // What's left keeps its place at the front
+                                orderbook_entry.push_front(partial);
                                 remaining_amount = 0;
                             }
                         }
                     }
-                    for self_order in self_signed {
-                        orderbook_entry.push(self_order.clone());
+                    // The skipped orders go back ahead of the rest, in the order they were in
+                    for self_order in self_signed.into_iter().rev() {
+                        orderbook_entry.push_front(self_order);
                     }
                 }
                 // Nothing left to match with
@@ -375,6 +378,45 @@ mod tests {
         assert_eq!(matching_engine.bids.len(), 1);
     }
 
+    #[test]
+    fn test_MatchingEngine_process_matches_same_price_orders_first_in_first_out() {
+        let mut matching_engine = MatchingEngine::new();
+
+        // Larger and smaller orders at the same price, the oldest one has to go first
+        for (signer, amount) in [("ALICE", 1), ("BOB", 3), ("CHARLIE", 2), ("DAVE", 1)] {
+            matching_engine
+                .process(Order {
+                    price: 10,
+                    amount,
+                    side: Side::Sell,
+                    signer: signer.to_string(),
+                })
+                .unwrap();
+        }
+
+        let charlie_receipt = matching_engine
+            .process(Order {
+                price: 10,
+                amount: 5,
+                side: Side::Buy,
+                signer: "CHARLIE".to_string(),
+            })
+            .unwrap();
+        let makers: Vec<_> = charlie_receipt
+            .matches
+            .iter()
+            .map(|m| m.signer.as_str())
+            .collect();
+        assert_eq!(makers, vec!["ALICE", "BOB", "DAVE"]);
+
+        // The skipped order of the buyer is the only one left
+        let queue: Vec<_> = matching_engine.asks[&10]
+            .iter()
+            .map(|o| o.signer.as_str())
+            .collect();
+        assert_eq!(queue, vec!["CHARLIE"]);
+    }
+
     #[test]
     fn test_MatchingEngine_process_no_match() {
         let mut matching_engine = MatchingEngine::new();
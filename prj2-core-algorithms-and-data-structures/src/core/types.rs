@@ -1,5 +1,3 @@
-use std::cmp::Reverse;
-
 /// Simplified side of a position as well as order.
 #[derive(Clone, PartialOrd, PartialEq, Eq, Debug, Ord)]
 pub enum Side {
@@ -43,7 +41,7 @@ impl Order {
 }
 
 /// A position represents an unfilled order that is kept in the system for later filling.
-#[derive(Clone, PartialEq, Debug, Eq, Ord)]
+#[derive(Clone, PartialEq, Debug, Eq)]
 pub struct PartialOrder {
     /// Price per unit
     pub price: u64,
@@ -59,15 +57,8 @@ pub struct PartialOrder {
     pub ordinal: u64,
 }
 
-impl PartialOrd for PartialOrder {
-    fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
-        // this reverses the comparison to create a min heap
-        Reverse(self.ordinal).partial_cmp(&Reverse(other.ordinal))
-    }
-}
-
 /// A receipt issued to the caller for accepting an [`Order`]
-#[derive(Clone, PartialOrd, PartialEq, Eq, Debug)]
+#[derive(Clone, PartialEq, Eq, Debug)]
 pub struct Receipt {
     /// Sequence number
     pub ordinal: u64,
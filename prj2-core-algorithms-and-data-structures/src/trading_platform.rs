@@ -71,7 +71,7 @@ impl TradingPlatform {
         recipient: &str,
         amount: u64,
     ) -> Result<(Tx, Tx), ApplicationError> {
-        let tx = self.accounts.send(sender, recipient, amount).unwrap();
+        let tx = self.accounts.send(sender, recipient, amount)?;
 
         self.transaction_log.push(tx.0.clone());
         self.transaction_log.push(tx.1.clone());
@@ -80,6 +80,10 @@ impl TradingPlatform {
     }
 
     /// Process a given order and apply the outcome to the accounts involved. Note that there are very few safeguards in place.
+    ///
+    /// The matches are settled all-or-nothing: if one of them can't be settled, e.g. because a buyer withdrew the
+    /// funds for a resting order, the settlements before it are reversed, the order book is restored, and the error
+    /// is returned.
     pub fn order(&mut self, order: Order) -> Result<Receipt, ApplicationError> {
         // Check if the account exists
         self.accounts.balance_of(&order.signer)?;
@@ -89,34 +93,28 @@ impl TradingPlatform {
                 order.amount,
             ));
         }
-        let receipt = self.matching_engine.process(order.clone());
+        // Matching changes the book, so keep the previous one in case settlement fails
+        let book = self.matching_engine.clone();
+        let receipt = self.matching_engine.process(order.clone())?;
 
-        let _total_realized = receipt
-            .as_ref()
-            .unwrap()
+        let transfers: Vec<(&str, &str, u64)> = receipt
             .matches
             .iter()
-            .map(|m| m.price * m.amount)
-            .sum::<u64>();
-
-        match order.side {
-            Side::Buy => {
-                receipt.as_ref().unwrap().matches.iter().for_each(|m| {
-                    self.accounts
-                        .send(&order.signer, &m.signer, m.amount * m.price)
-                        .unwrap();
-                });
+            .map(|m| match order.side {
+                Side::Buy => (order.signer.as_str(), m.signer.as_str(), m.amount * m.price),
+                Side::Sell => (m.signer.as_str(), order.signer.as_str(), m.amount * m.price),
+            })
+            .collect();
+        match self.accounts.send_all(&transfers) {
+            Ok(txs) => {
+                self.transaction_log.extend(txs);
+                Ok(receipt)
             }
-            Side::Sell => {
-                receipt.as_ref().unwrap().matches.iter().for_each(|m| {
-                    self.accounts
-                        .send(&m.signer, &order.signer, m.amount * m.price)
-                        .unwrap();
-                });
+            Err(e) => {
+                self.matching_engine = book;
+                Err(e)
             }
         }
-
-        receipt
     }
 }
 
@@ -404,4 +402,43 @@ mod tests {
         assert_eq!(trading_platform.accounts.balance_of("ALICE"), Ok(&100));
         assert_eq!(trading_platform.accounts.balance_of("BOB"), Ok(&100));
     }
+
+    #[test]
+    fn test_TradingPlatform_order_failed_settlement_restores_accounts_and_book() {
+        let mut trading_platform = TradingPlatform::new();
+
+        // Set up accounts
+        assert!(trading_platform.accounts.deposit("ALICE", 100).is_ok());
+        assert!(trading_platform.accounts.deposit("BOB", 100).is_ok());
+        assert!(trading_platform.accounts.deposit("CHARLIE", 100).is_ok());
+
+        for signer in ["ALICE", "BOB"] {
+            trading_platform
+                .order(Order {
+                    price: 10,
+                    amount: 1,
+                    side: Side::Buy,
+                    signer: signer.to_string(),
+                })
+                .unwrap();
+        }
+        // BOB can't pay for the resting order anymore
+        assert!(trading_platform.withdraw("BOB", 100).is_ok());
+
+        assert_eq!(
+            trading_platform.order(Order {
+                price: 10,
+                amount: 2,
+                side: Side::Sell,
+                signer: "CHARLIE".to_string(),
+            }),
+            Err(ApplicationError::AccountUnderFunded("BOB".to_string(), 10))
+        );
+
+        // ALICE's settlement was reversed and both bids are still in the book
+        assert_eq!(trading_platform.accounts.balance_of("ALICE"), Ok(&100));
+        assert_eq!(trading_platform.accounts.balance_of("CHARLIE"), Ok(&100));
+        assert_eq!(trading_platform.matching_engine.bids[&10].len(), 2);
+        assert!(trading_platform.matching_engine.asks.is_empty());
+    }
 }
@@ -0,0 +1,182 @@
+use std::collections::BTreeMap;
+use std::fmt::Write;
+
+use octopus_common::types::{PartialOrder, Side};
+
+/// The quantity at a price, cumulated from the best price outwards
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub struct Level {
+    pub price: u64,
+    pub quantity: u64,
+    pub cumulative: u64,
+}
+
+/// Aggregates the open quantity per price level and returns up to `levels` bids (best first) and asks (best first)
+pub fn levels(orders: &[PartialOrder], levels: usize) -> (Vec<Level>, Vec<Level>) {
+    let mut bids = BTreeMap::new();
+    let mut asks = BTreeMap::new();
+    for order in orders {
+        let side = match order.side {
+            Side::Buy => &mut bids,
+            Side::Sell => &mut asks,
+        };
+        *side.entry(order.price).or_insert(0) += order.remaining;
+    }
+    (
+        cumulate(bids.into_iter().rev(), levels),
+        cumulate(asks.into_iter(), levels),
+    )
+}
+
+fn cumulate(quantities: impl Iterator<Item = (u64, u64)>, levels: usize) -> Vec<Level> {
+    let mut cumulative = 0;
+    quantities
+        .take(levels)
+        .map(|(price, quantity)| {
+            cumulative += quantity;
+            Level {
+                price,
+                quantity,
+                cumulative,
+            }
+        })
+        .collect()
+}
+
+/// Renders the levels as a horizontal bar chart with the asks on top, the bids below, and the best prices in the middle.
+/// The longest bar is `width` characters wide.
+pub fn render(bids: &[Level], asks: &[Level], width: usize) -> String {
+    let max = bids
+        .iter()
+        .chain(asks)
+        .map(|level| level.cumulative)
+        .max()
+        .unwrap_or(0);
+    let bar = |level: &Level| {
+        let length = (level.cumulative as u128 * width as u128)
+            .checked_div(max as u128)
+            .unwrap_or(0) as usize;
+        // Every level gets at least one character
+        "#".repeat(length.max(1))
+    };
+
+    let mut out = String::new();
+    let _ = writeln!(
+        out,
+        "{:>4} {:>10} {:>10} {:>10}",
+        "", "price", "quantity", "total"
+    );
+    for level in asks.iter().rev() {
+        let _ = writeln!(
+            out,
+            "{:>4} {:>10} {:>10} {:>10} {}",
+            "ask",
+            level.price,
+            level.quantity,
+            level.cumulative,
+            bar(level)
+        );
+    }
+    match (bids.first(), asks.first()) {
+        (Some(bid), Some(ask)) => {
+            let _ = writeln!(
+                out,
+                "{:-^40}",
+                format!(" spread {} ", ask.price.abs_diff(bid.price))
+            );
+        }
+        _ => {
+            let _ = writeln!(out, "{:-^40}", "");
+        }
+    }
+    for level in bids {
+        let _ = writeln!(
+            out,
+            "{:>4} {:>10} {:>10} {:>10} {}",
+            "bid",
+            level.price,
+            level.quantity,
+            level.cumulative,
+            bar(level)
+        );
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    // reduce the warnings for naming tests
+    #![allow(non_snake_case)]
+
+    use super::*;
+    use octopus_common::types::DEFAULT_MARKET;
+
+    fn order(price: u64, remaining: u64, side: Side) -> PartialOrder {
+        PartialOrder {
+            price,
+            amount: remaining,
+            remaining,
+            side,
+            signer: "ALICE".to_string(),
+            ordinal: 1,
+            order_id: 1,
+            expires_at: None,
+            market: DEFAULT_MARKET.to_string(),
+        }
+    }
+
+    #[test]
+    fn test_levels_cumulates_from_best_price() {
+        let orders = vec![
+            order(12, 5, Side::Sell),
+            order(11, 2, Side::Sell),
+            order(11, 3, Side::Sell),
+            order(9, 4, Side::Buy),
+            order(8, 6, Side::Buy),
+            order(7, 1, Side::Buy),
+        ];
+        let (bids, asks) = levels(&orders, 2);
+        assert_eq!(
+            bids,
+            vec![
+                Level {
+                    price: 9,
+                    quantity: 4,
+                    cumulative: 4
+                },
+                Level {
+                    price: 8,
+                    quantity: 6,
+                    cumulative: 10
+                }
+            ]
+        );
+        assert_eq!(
+            asks,
+            vec![
+                Level {
+                    price: 11,
+                    quantity: 5,
+                    cumulative: 5
+                },
+                Level {
+                    price: 12,
+                    quantity: 5,
+                    cumulative: 10
+                }
+            ]
+        );
+    }
+
+    #[test]
+    fn test_render_scales_bars_to_width() {
+        let (bids, asks) = levels(&[order(11, 4, Side::Sell), order(9, 2, Side::Buy)], 10);
+        let chart = render(&bids, &asks, 4);
+        let lines: Vec<&str> = chart.lines().collect();
+        assert_eq!(lines.len(), 4);
+        assert!(lines[1].starts_with(" ask         11          4          4 ####"));
+        assert!(lines[2].contains(" spread 2 "));
+        assert!(lines[3].ends_with(" ##"));
+        assert_eq!(render(&[], &[], 4).lines().count(), 2);
+    }
+}
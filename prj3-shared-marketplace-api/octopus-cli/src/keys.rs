@@ -0,0 +1,246 @@
+//! Local ed25519 keypairs for signing orders, one per account.
+//!
+//! A key file holds the hex-encoded 32 byte secret key and is only readable by its owner. The public key is derived
+//! from it whenever it's needed.
+use std::fs::{self, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use ed25519_dalek::{Signer, SigningKey};
+use octopus_common::types::{AmendRequest, Order, OrderId, OrderSignature};
+use rand::RngCore;
+
+/// The directory below `$HOME` keys are stored in unless another one is given
+pub const DEFAULT_KEYS_DIR: &str = ".octopus/keys";
+
+/// The default key directory, `None` without a home directory
+pub fn default_dir() -> Option<PathBuf> {
+    std::env::var_os("HOME").map(|home| Path::new(&home).join(DEFAULT_KEYS_DIR))
+}
+
+/// The key files of a directory
+#[derive(Debug, Clone)]
+pub struct KeyStore {
+    dir: PathBuf,
+}
+
+impl KeyStore {
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        KeyStore { dir: dir.into() }
+    }
+
+    /// The key file of `account`
+    /// # Errors
+    /// The account name would leave the key directory
+    pub fn path_of(&self, account: &str) -> io::Result<PathBuf> {
+        let valid = !account.is_empty()
+            && account
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_' || c == '.')
+            && !account.starts_with('.');
+        if !valid {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("'{}' can't be used as a key file name", account),
+            ));
+        }
+        Ok(self.dir.join(format!("{}.key", account)))
+    }
+
+    /// Creates a new keypair for `account`. An existing key is only replaced with `force`, it can't be recovered.
+    /// # Errors
+    /// - The account already has a key and `force` isn't set
+    /// - The key file can't be written
+    pub fn generate(&self, account: &str, force: bool) -> io::Result<SigningKey> {
+        let path = self.path_of(account)?;
+        let mut secret = [0; 32];
+        rand::thread_rng().fill_bytes(&mut secret);
+        let key = SigningKey::from_bytes(&secret);
+        create_private_dir(&self.dir)?;
+
+        let mut options = OpenOptions::new();
+        options.write(true);
+        if force {
+            options.create(true).truncate(true);
+        } else {
+            options.create_new(true);
+        }
+        #[cfg(unix)]
+        std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+        let mut file = options.open(&path).map_err(|e| {
+            if e.kind() == io::ErrorKind::AlreadyExists {
+                io::Error::new(
+                    e.kind(),
+                    format!("{} exists, use --force to replace it", path.display()),
+                )
+            } else {
+                e
+            }
+        })?;
+        // `mode` only applies to new files
+        #[cfg(unix)]
+        fs::set_permissions(&path, std::os::unix::fs::PermissionsExt::from_mode(0o600))?;
+        writeln!(file, "{}", hex::encode(key.to_bytes()))?;
+        Ok(key)
+    }
+
+    /// The keypair of `account`, `None` if it has none
+    /// # Errors
+    /// The key file can't be read or is malformed
+    pub fn load(&self, account: &str) -> io::Result<Option<SigningKey>> {
+        let path = self.path_of(account)?;
+        let contents = match fs::read_to_string(&path) {
+            Ok(contents) => contents,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(e),
+        };
+        let secret: [u8; 32] = hex::decode(contents.trim())
+            .ok()
+            .and_then(|bytes| bytes.try_into().ok())
+            .ok_or_else(|| {
+                io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("{} isn't a key file", path.display()),
+                )
+            })?;
+        Ok(Some(SigningKey::from_bytes(&secret)))
+    }
+}
+
+fn create_private_dir(dir: &Path) -> io::Result<()> {
+    let mut builder = fs::DirBuilder::new();
+    builder.recursive(true);
+    #[cfg(unix)]
+    std::os::unix::fs::DirBuilderExt::mode(&mut builder, 0o700);
+    builder.create(dir)
+}
+
+/// The hex-encoded public key of `key`, as registered with the server
+pub fn public_key(key: &SigningKey) -> String {
+    hex::encode(key.verifying_key().as_bytes())
+}
+
+/// The current unix timestamp in milliseconds
+pub fn now_millis() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|elapsed| elapsed.as_millis() as u64)
+        .unwrap_or_default()
+}
+
+/// How long a signature made by [`sign`] is valid for
+pub const SIGNATURE_VALIDITY_MILLIS: u64 = 60 * 1000;
+
+/// Signs the canonical payload of `order` for `tenant` with a random nonce, valid for [`SIGNATURE_VALIDITY_MILLIS`]
+/// from `now`, and attaches the signature
+pub fn sign(order: &mut Order, key: &SigningKey, tenant: &str, now: u64) {
+    order.signature = Some(signature(key, now, |nonce, expires_at| {
+        order.signing_payload(tenant, nonce, expires_at)
+    }));
+}
+
+/// Signs amending the order `id` like [`sign`]
+pub fn sign_amend(
+    id: OrderId,
+    request: &mut AmendRequest,
+    key: &SigningKey,
+    tenant: &str,
+    now: u64,
+) {
+    request.signature = Some(signature(key, now, |nonce, expires_at| {
+        request.signing_payload(id, tenant, nonce, expires_at)
+    }));
+}
+
+/// Signs the `payload` of a random nonce and the expiry
+fn signature(
+    key: &SigningKey,
+    now: u64,
+    payload: impl FnOnce(u64, u64) -> Vec<u8>,
+) -> OrderSignature {
+    let nonce = rand::random();
+    let expires_at = now + SIGNATURE_VALIDITY_MILLIS;
+    let signature = key.sign(&payload(nonce, expires_at));
+    OrderSignature {
+        nonce,
+        expires_at,
+        value: hex::encode(signature.to_bytes()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    // reduce the warnings for naming tests
+    #![allow(non_snake_case)]
+
+    use super::*;
+    use ed25519_dalek::{Signature, Verifier};
+    use octopus_common::types::{OrderType, Side, TimeInForce, DEFAULT_MARKET};
+
+    fn store(name: &str) -> KeyStore {
+        let dir =
+            std::env::temp_dir().join(format!("octopus-keys-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        KeyStore::new(dir)
+    }
+
+    #[test]
+    fn test_KeyStore_generate_stores_a_private_key_once() {
+        let store = store("generate");
+        assert!(store.load("ALICE").unwrap().is_none());
+        let key = store.generate("ALICE", false).unwrap();
+        assert_eq!(store.load("ALICE").unwrap(), Some(key.clone()));
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let path = store.path_of("ALICE").unwrap();
+            assert_eq!(
+                fs::metadata(path).unwrap().permissions().mode() & 0o777,
+                0o600
+            );
+        }
+
+        let e = store.generate("ALICE", false).unwrap_err();
+        assert_eq!(e.kind(), io::ErrorKind::AlreadyExists);
+        let replaced = store.generate("ALICE", true).unwrap();
+        assert_ne!(public_key(&replaced), public_key(&key));
+        assert!(store.path_of("../ALICE").is_err());
+        fs::remove_dir_all(&store.dir).unwrap();
+    }
+
+    #[test]
+    fn test_sign_covers_the_signing_payload() {
+        let key = SigningKey::from_bytes(&[5; 32]);
+        let mut order = Order {
+            price: 10,
+            amount: 3,
+            side: Side::Sell,
+            signer: "ALICE".to_string(),
+            client_order_id: None,
+            signature: None,
+            order_type: OrderType::Limit,
+            time_in_force: TimeInForce::Gtc,
+            trigger_price: None,
+            market: DEFAULT_MARKET.to_string(),
+        };
+        sign(&mut order, &key, "acme", 1_000);
+        let signed = order.signature.clone().unwrap();
+        assert_eq!(signed.expires_at, 1_000 + SIGNATURE_VALIDITY_MILLIS);
+        let bytes: [u8; 64] = hex::decode(&signed.value).unwrap().try_into().unwrap();
+        let signature = Signature::from_bytes(&bytes);
+        let verifying_key = key.verifying_key();
+        assert!(verifying_key
+            .verify(
+                &order.signing_payload("acme", signed.nonce, signed.expires_at),
+                &signature
+            )
+            .is_ok());
+        assert!(verifying_key
+            .verify(
+                &order.signing_payload("default", signed.nonce, signed.expires_at),
+                &signature
+            )
+            .is_err());
+    }
+}
@@ -1,18 +1,371 @@
-use std::{io, num::ParseIntError};
+mod depth;
+mod keys;
+mod recipients;
+mod replay;
 
-use reqwest;
-use serde::{Deserialize, Serialize};
-use tokio;
+use std::{io, num::ParseIntError, path::PathBuf};
 
-use clap::Parser;
-use octopus_common::tx::Tx;
+use clap::{Parser, Subcommand};
+use octopus_common::tx::{Memo, Tx};
 use octopus_common::types::{
-    AccountBalanceRequest, AccountUpdateRequest, Order, PartialOrder, SendRequest, Side,
+    AccountBalanceRequest, AccountUpdateRequest, AmendRequest, CancelQuery, Order, OrderType,
+    PartialOrder, PublicKeyRequest, Receipt, RegisteredPublicKey, SavedRecipient, SendRequest,
+    Side, TimeInForce, TradesPage, TradesQuery, DEFAULT_MARKET, DEFAULT_TRADES_PAGE,
 };
 
+use keys::KeyStore;
+use recipients::Completion;
+use replay::{ReplayEvent, RestingOrder, StateSnapshot};
+
 #[derive(Parser, Debug)]
 struct Args {
     url: String,
+
+    /// API key sent with every request (`x-api-key` header)
+    #[arg(long)]
+    api_key: Option<String>,
+
+    /// Tenant to send the requests to (`x-tenant` header)
+    #[arg(long)]
+    tenant: Option<String>,
+
+    /// Directory of the order signing keys, `~/.octopus/keys` by default
+    #[arg(long)]
+    keys_dir: Option<PathBuf>,
+
+    /// Run a single command instead of the interactive prompt
+    #[command(subcommand)]
+    command: Option<Command>,
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Render the cumulative bid/ask depth as a bar chart
+    Depth {
+        /// Price levels per side
+        #[arg(long, default_value_t = 10)]
+        levels: usize,
+
+        /// Width of the longest bar in characters
+        #[arg(long, default_value_t = 50)]
+        width: usize,
+    },
+    /// Claim play funds for a sandbox account, if the server runs a faucet
+    Faucet { account: String, amount: u64 },
+    /// Manage the keypairs that sign an account's orders
+    Keys {
+        #[command(subcommand)]
+        command: KeysCommand,
+    },
+    /// Print a page of the executed trades, oldest first
+    Trades {
+        /// Only trades of taker orders after this ordinal
+        #[arg(long)]
+        since_ordinal: Option<u64>,
+
+        /// Continue after the trade with this id
+        #[arg(long)]
+        after: Option<u64>,
+
+        /// Trades per page
+        #[arg(long, default_value_t = DEFAULT_TRADES_PAGE)]
+        limit: usize,
+    },
+    /// Replay a recorded event log against a fresh server and compare the resulting state with a snapshot, exits with
+    /// status 1 if they differ
+    Replay {
+        /// JSON array of the recorded events
+        #[arg(long)]
+        events: PathBuf,
+
+        /// JSON snapshot of the expected balances and order book
+        #[arg(long)]
+        assert_state: PathBuf,
+    },
+}
+
+#[derive(Subcommand, Debug)]
+enum KeysCommand {
+    /// Create a new keypair for an account
+    Generate {
+        account: String,
+
+        /// Replace an existing key, it can't be recovered afterwards
+        #[arg(long)]
+        force: bool,
+    },
+    /// Print the public key of an account
+    Show { account: String },
+    /// Register the public key of an account with the server, its orders have to be signed from then on
+    Register { account: String },
+}
+
+/// Runs a `keys` command
+async fn manage_keys(
+    client: &reqwest::Client,
+    url: &str,
+    store: &KeyStore,
+    command: KeysCommand,
+) -> Result<(), reqwest::Error> {
+    let register = matches!(command, KeysCommand::Register { .. });
+    let (account, key) = match command {
+        KeysCommand::Generate { account, force } => match store.generate(&account, force) {
+            Ok(key) => {
+                println!("Generated a key for account '{}'", account);
+                (account, key)
+            }
+            Err(e) => {
+                eprintln!("Can't generate a key: {}", e);
+                return Ok(());
+            }
+        },
+        KeysCommand::Show { account } | KeysCommand::Register { account } => {
+            match store.load(&account) {
+                Ok(Some(key)) => (account, key),
+                Ok(None) => {
+                    eprintln!("Account '{}' has no key, generate one first", account);
+                    return Ok(());
+                }
+                Err(e) => {
+                    eprintln!("Can't read the key: {}", e);
+                    return Ok(());
+                }
+            }
+        }
+    };
+    let public_key = keys::public_key(&key);
+    println!("Public key of '{}': {}", account, public_key);
+    if let Ok(path) = store.path_of(&account) {
+        println!("Stored in {}", path.display());
+    }
+    if register {
+        let pubkeys_url = format!("{}/account/{}/pubkeys", url, account);
+        let response = client
+            .post(pubkeys_url)
+            .json(&PublicKeyRequest { public_key })
+            .send()
+            .await?;
+
+        if !response.status().is_success() {
+            eprintln!("Something went wrong: {:?}", response);
+            return Ok(());
+        }
+        let registered = response.json::<RegisteredPublicKey>().await?;
+        println!("Registered: {:#?}", registered);
+    }
+    Ok(())
+}
+
+/// Prints the depth chart of the current order book
+async fn print_depth(
+    client: &reqwest::Client,
+    url: &str,
+    levels: usize,
+    width: usize,
+) -> Result<(), reqwest::Error> {
+    let orderbook_url = format!("{}/orderbook", url);
+    let response = client.get(orderbook_url).send().await?;
+
+    if !response.status().is_success() {
+        eprintln!("Something went wrong: {:?}", response);
+    }
+    let orderbook = response.json::<Vec<PartialOrder>>().await?;
+    let (bids, asks) = depth::levels(&orderbook, levels);
+    print!("{}", depth::render(&bids, &asks, width));
+    Ok(())
+}
+
+/// Sends the recorded `events` to the server in order, then compares its balances and book with `expected`. Rejected
+/// events are reported and the replay continues, the recording may contain rejections. Returns whether the state
+/// matched.
+async fn replay_events(
+    client: &reqwest::Client,
+    url: &str,
+    events: &[ReplayEvent],
+    expected: &StateSnapshot,
+) -> Result<bool, reqwest::Error> {
+    for (i, event) in events.iter().enumerate() {
+        let request = match event {
+            ReplayEvent::Deposit { signer, amount } => client
+                .post(format!("{}/account/deposit", url))
+                .json(&AccountUpdateRequest {
+                    signer: signer.clone(),
+                    amount: *amount,
+                }),
+            ReplayEvent::Withdraw { signer, amount } => client
+                .post(format!("{}/account/withdraw", url))
+                .json(&AccountUpdateRequest {
+                    signer: signer.clone(),
+                    amount: *amount,
+                }),
+            ReplayEvent::Order(order) => client.post(format!("{}/order", url)).json(order),
+            ReplayEvent::Cancel { signer, order_id } => client
+                .delete(format!("{}/order/{}", url, order_id))
+                .query(&CancelQuery {
+                    signer: signer.clone(),
+                }),
+            ReplayEvent::Amend {
+                signer,
+                order_id,
+                price,
+                amount,
+            } => client
+                .put(format!("{}/order/{}", url, order_id))
+                .json(&AmendRequest {
+                    signer: signer.clone(),
+                    price: *price,
+                    amount: *amount,
+                    signature: None,
+                }),
+        };
+        let response = request.send().await?;
+        if !response.status().is_success() {
+            eprintln!(
+                "Event {} was rejected ({}): {}",
+                i,
+                response.status(),
+                response.text().await?
+            );
+        }
+    }
+
+    let mut actual = StateSnapshot::default();
+    for account in replay::accounts(events, expected) {
+        let response = client
+            .post(format!("{}/account", url))
+            .json(&AccountBalanceRequest {
+                signer: account.clone(),
+            })
+            .send()
+            .await?;
+        // Accounts that don't exist have no balance
+        if response.status().is_success() {
+            actual.balances.insert(account, response.json().await?);
+        }
+    }
+    let response = client.get(format!("{}/orderbook", url)).send().await?;
+    actual.orderbook = response
+        .json::<Vec<PartialOrder>>()
+        .await?
+        .iter()
+        .map(RestingOrder::from)
+        .collect();
+
+    let differences = replay::diff(expected, &actual);
+    for difference in differences.iter() {
+        println!("{}", difference);
+    }
+    println!(
+        "Replayed {} events, {} differences",
+        events.len(),
+        differences.len()
+    );
+    Ok(differences.is_empty())
+}
+
+/// Prints a page of the trade tape
+async fn print_trades(
+    client: &reqwest::Client,
+    url: &str,
+    query: &TradesQuery,
+) -> Result<(), reqwest::Error> {
+    let trades_url = format!("{}/trades", url);
+    let response = client.get(trades_url).query(query).send().await?;
+
+    if !response.status().is_success() {
+        eprintln!("Something went wrong: {:?}", response.text().await?);
+        return Ok(());
+    }
+    let page = response.json::<TradesPage>().await?;
+    println!(
+        "{:>10} {:>10} {:>10} {:>10} {:>10} {:>12} {:>12}",
+        "id", "ordinal", "market", "price", "amount", "buyer", "seller"
+    );
+    for trade in page.trades.iter() {
+        println!(
+            "{:>10} {:>10} {:>10} {:>10} {:>10} {:>12} {:>12}{}",
+            trade.id,
+            trade.ordinal,
+            trade.market,
+            trade.price,
+            trade.amount,
+            trade.buyer,
+            trade.seller,
+            if trade.busted.is_some() {
+                " busted"
+            } else {
+                ""
+            }
+        );
+    }
+    if let Some(next) = page.next {
+        println!("More trades with --after {}", next);
+    }
+    Ok(())
+}
+
+/// Claims play funds for `account` from the faucet
+async fn claim_faucet(
+    client: &reqwest::Client,
+    url: &str,
+    account: String,
+    amount: u64,
+) -> Result<(), reqwest::Error> {
+    let faucet_url = format!("{}/faucet", url);
+    let request = AccountUpdateRequest {
+        signer: account.clone(),
+        amount,
+    };
+    let response = client.post(faucet_url).json(&request).send().await?;
+
+    match response.status() {
+        status if status.is_success() => {
+            println!("Credited {} play funds to account '{}'", amount, account)
+        }
+        reqwest::StatusCode::NOT_FOUND => eprintln!("The server doesn't run a faucet"),
+        _ => eprintln!("Something went wrong: {:?}", response.text().await?),
+    }
+    Ok(())
+}
+
+/// The saved recipients of `account`, empty if they can't be fetched
+async fn fetch_recipients(
+    client: &reqwest::Client,
+    url: &str,
+    account: &str,
+) -> Vec<SavedRecipient> {
+    let recipients_url = format!("{}/account/{}/recipients", url, account);
+    match client.get(recipients_url).send().await {
+        Ok(response) if response.status().is_success() => response.json().await.unwrap_or_default(),
+        _ => vec![],
+    }
+}
+
+/// Reads the recipient of a transfer, completing saved aliases to their signer. Returns `None` if the user didn't
+/// confirm a completed alias.
+fn read_recipient(saved: &[SavedRecipient]) -> Option<String> {
+    if !saved.is_empty() {
+        let aliases: Vec<_> = saved
+            .iter()
+            .map(|r| format!("{} ({})", r.alias, r.signer))
+            .collect();
+        println!("Saved recipients: {}", aliases.join(", "));
+    }
+    let input = read_from_stdin("Recipient Account or alias:");
+    match recipients::complete(&input, saved) {
+        Completion::Alias(recipient) => Some(recipient.signer.clone()),
+        Completion::Prefix(recipient) => {
+            let question = format!(
+                "Send to alias '{}' ({})? [y/N]",
+                recipient.alias, recipient.signer
+            );
+            match read_from_stdin(&question).to_lowercase().as_str() {
+                "y" | "yes" => Some(recipient.signer.clone()),
+                _ => None,
+            }
+        }
+        Completion::Signer => Some(input),
+    }
 }
 
 fn read_order_parameters() -> Result<Order, String> {
@@ -29,14 +382,53 @@ fn read_order_parameters() -> Result<Order, String> {
     let price = read_from_stdin("Price:")
         .parse()
         .map_err(|e: ParseIntError| e.to_string())?;
+    // A stop-limit order waits for a trade at its trigger price
+    let trigger_price = match read_from_stdin("Trigger price (empty for none):").as_str() {
+        "" => None,
+        trigger => Some(trigger.parse().map_err(|e: ParseIntError| e.to_string())?),
+    };
+    let market = match read_from_stdin(&format!("Market (empty for {}):", DEFAULT_MARKET)).as_str()
+    {
+        "" => DEFAULT_MARKET.to_string(),
+        market => market.to_string(),
+    };
     Ok(Order {
         price,
         amount,
         side,
         signer: account,
+        client_order_id: None,
+        signature: None,
+        order_type: OrderType::Limit,
+        time_in_force: TimeInForce::Gtc,
+        trigger_price,
+        market,
     })
 }
 
+/// Reads the id of the order to amend and its new price and amount
+fn read_amend_parameters() -> Result<(u64, AmendRequest), String> {
+    let signer = read_from_stdin("Account:");
+    let id = read_from_stdin("Order id:")
+        .parse()
+        .map_err(|e: ParseIntError| e.to_string())?;
+    let price = read_from_stdin("New price:")
+        .parse()
+        .map_err(|e: ParseIntError| e.to_string())?;
+    let amount = read_from_stdin("New amount:")
+        .parse()
+        .map_err(|e: ParseIntError| e.to_string())?;
+    Ok((
+        id,
+        AmendRequest {
+            signer,
+            price,
+            amount,
+            signature: None,
+        },
+    ))
+}
+
 fn read_from_stdin(label: &str) -> String {
     let mut buffer = String::new();
     println!("{}", label);
@@ -57,11 +449,82 @@ async fn main() -> Result<(), reqwest::Error> {
         url
     );
 
-    let client = reqwest::Client::new();
+    let mut headers = reqwest::header::HeaderMap::new();
+    if let Some(api_key) = args.api_key {
+        match reqwest::header::HeaderValue::from_str(&api_key) {
+            Ok(value) => {
+                headers.insert("x-api-key", value);
+            }
+            Err(e) => eprintln!("Ignoring invalid API key: {:?}", e),
+        }
+    }
+    // Signatures are only valid for the tenant they're sent to, the server falls back to this one without a header
+    let tenant_header = args.tenant.is_some();
+    let tenant = args.tenant.unwrap_or_else(|| "default".to_string());
+    if tenant_header {
+        match reqwest::header::HeaderValue::from_str(&tenant) {
+            Ok(value) => {
+                headers.insert("x-tenant", value);
+            }
+            Err(e) => eprintln!("Ignoring invalid tenant: {:?}", e),
+        }
+    }
+    let client = reqwest::Client::builder()
+        .default_headers(headers)
+        .build()?;
+
+    let store = KeyStore::new(
+        args.keys_dir
+            .or_else(keys::default_dir)
+            .unwrap_or_else(|| PathBuf::from(keys::DEFAULT_KEYS_DIR)),
+    );
+
+    match args.command {
+        Some(Command::Depth { levels, width }) => {
+            return print_depth(&client, &url, levels, width).await;
+        }
+        Some(Command::Faucet { account, amount }) => {
+            return claim_faucet(&client, &url, account, amount).await;
+        }
+        Some(Command::Keys { command }) => {
+            return manage_keys(&client, &url, &store, command).await
+        }
+        Some(Command::Trades {
+            since_ordinal,
+            after,
+            limit,
+        }) => {
+            let query = TradesQuery {
+                since_ordinal,
+                after,
+                limit: Some(limit),
+            };
+            return print_trades(&client, &url, &query).await;
+        }
+        Some(Command::Replay {
+            events,
+            assert_state,
+        }) => {
+            let loaded = replay::load::<Vec<ReplayEvent>>(&events)
+                .and_then(|events| Ok((events, replay::load::<StateSnapshot>(&assert_state)?)));
+            let matched = match loaded {
+                Ok((events, expected)) => replay_events(&client, &url, &events, &expected).await?,
+                Err(e) => {
+                    eprintln!("Can't read the replay: {}", e);
+                    false
+                }
+            };
+            if !matched {
+                std::process::exit(1);
+            }
+            return Ok(());
+        }
+        None => {}
+    }
 
     loop {
         let input = read_from_stdin(
-            "Choose operation [deposit, withdraw, send, print, txlog, order, orderbook, quit], confirm with return:",
+            "Choose operation [deposit, withdraw, send, print, txlog, order, amend, orderbook, depth, trades, quit], confirm with return:",
         );
         match input.as_str() {
             "deposit" => {
@@ -106,13 +569,24 @@ async fn main() -> Result<(), reqwest::Error> {
             }
             "send" => {
                 let sender = read_from_stdin("Sender Account:");
-                let recipient = read_from_stdin("Recipient Account:");
+                let saved = fetch_recipients(&client, &url, &sender).await;
+                // Send to the signer that was shown, not the alias, in case the alias changes in the meantime
+                let Some(recipient) = read_recipient(&saved) else {
+                    println!("Nothing sent");
+                    continue;
+                };
                 let raw_amount = read_from_stdin("Amount:").parse();
                 if let Ok(amount) = raw_amount {
+                    let memo = read_from_stdin("Memo (optional):");
                     let request = SendRequest {
                         from: sender.clone(),
                         to: recipient.clone(),
                         amount,
+                        to_alias: None,
+                        memo: (!memo.is_empty()).then(|| Memo {
+                            text: Some(memo),
+                            ..Memo::default()
+                        }),
                     };
                     let send_url = format!("{}/account/send", url);
                     let response = client.post(send_url).json(&request).send().await?;
@@ -129,7 +603,13 @@ async fn main() -> Result<(), reqwest::Error> {
                 }
             }
             "order" => match read_order_parameters() {
-                Ok(order) => {
+                Ok(mut order) => {
+                    // Sign whenever there's a key, the server only insists once it's registered
+                    match store.load(&order.signer) {
+                        Ok(Some(key)) => keys::sign(&mut order, &key, &tenant, keys::now_millis()),
+                        Ok(None) => {}
+                        Err(e) => eprintln!("Sending the order unsigned: {}", e),
+                    }
                     let order_url = format!("{}/order", url);
                     let response = client.post(order_url).json(&order).send().await?;
 
@@ -142,6 +622,29 @@ async fn main() -> Result<(), reqwest::Error> {
                     eprintln!("Invalid Order parameters: '{:?}'", msg);
                 }
             },
+            "amend" => match read_amend_parameters() {
+                Ok((id, mut request)) => {
+                    match store.load(&request.signer) {
+                        Ok(Some(key)) => {
+                            keys::sign_amend(id, &mut request, &key, &tenant, keys::now_millis())
+                        }
+                        Ok(None) => {}
+                        Err(e) => eprintln!("Sending the amendment unsigned: {}", e),
+                    }
+                    let amend_url = format!("{}/order/{}", url, id);
+                    let response = client.put(amend_url).json(&request).send().await?;
+
+                    if !response.status().is_success() {
+                        eprintln!("Something went wrong: {:?}", response);
+                    } else {
+                        let receipt = response.json::<Receipt>().await?;
+                        println!("Amended: {:#?}", receipt);
+                    }
+                }
+                Err(msg) => {
+                    eprintln!("Invalid amendment parameters: '{:?}'", msg);
+                }
+            },
             "orderbook" => {
                 let orderbook_url = format!("{}/orderbook", url);
                 let response = client.get(orderbook_url).send().await?;
@@ -152,6 +655,8 @@ async fn main() -> Result<(), reqwest::Error> {
                 let orderbook = response.json::<Vec<PartialOrder>>().await?;
                 println!("The orderbook: {:#?}", orderbook);
             }
+            "depth" => print_depth(&client, &url, 10, 50).await?,
+            "trades" => print_trades(&client, &url, &TradesQuery::default()).await?,
             "txlog" => {
                 let txlog_url = format!("{}/txlog", url);
                 let response = client.get(txlog_url).send().await?;
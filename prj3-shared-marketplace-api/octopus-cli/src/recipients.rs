@@ -0,0 +1,57 @@
+use octopus_common::types::SavedRecipient;
+
+/// What a recipient typed at the prompt refers to
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum Completion<'a> {
+    /// Exactly a saved alias
+    Alias(&'a SavedRecipient),
+    /// The start of exactly one saved alias, to be confirmed before sending
+    Prefix(&'a SavedRecipient),
+    /// No alias, the input is taken as a signer
+    Signer,
+}
+
+/// Completes `input` to a saved alias. Ambiguous prefixes aren't completed.
+pub fn complete<'a>(input: &str, saved: &'a [SavedRecipient]) -> Completion<'a> {
+    if input.is_empty() {
+        return Completion::Signer;
+    }
+    if let Some(recipient) = saved.iter().find(|r| r.alias == input) {
+        return Completion::Alias(recipient);
+    }
+    let mut candidates = saved.iter().filter(|r| r.alias.starts_with(input));
+    match (candidates.next(), candidates.next()) {
+        (Some(recipient), None) => Completion::Prefix(recipient),
+        _ => Completion::Signer,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    // reduce the warnings for naming tests
+    #![allow(non_snake_case)]
+
+    use super::*;
+
+    fn saved(alias: &str, signer: &str) -> SavedRecipient {
+        SavedRecipient {
+            alias: alias.to_string(),
+            signer: signer.to_string(),
+        }
+    }
+
+    #[test]
+    fn test_complete_prefers_exact_aliases_and_skips_ambiguous_prefixes() {
+        let book = [
+            saved("bob", "BOB"),
+            saved("bobby", "ROBERT"),
+            saved("carol", "C"),
+        ];
+        assert_eq!(complete("bob", &book), Completion::Alias(&book[0]));
+        assert_eq!(complete("bobb", &book), Completion::Prefix(&book[1]));
+        assert_eq!(complete("ca", &book), Completion::Prefix(&book[2]));
+        assert_eq!(complete("bo", &book), Completion::Signer);
+        assert_eq!(complete("DAVE", &book), Completion::Signer);
+        assert_eq!(complete("", &book), Completion::Signer);
+    }
+}
@@ -0,0 +1,200 @@
+//! Deterministic replays for release verification: a recorded event log is sent to a fresh server and the resulting
+//! balances and order book are compared with the state snapshot the previous release ended up in.
+//!
+//! Both files are JSON. The event log is an array of events tagged with `event`, e.g.
+//! `{"event": "deposit", "signer": "ALICE", "amount": 100}`. Cancels and amendments refer to order ids, so the server
+//! has to issue them deterministically, like a fresh server with the default `ids` configuration does.
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt;
+use std::path::Path;
+
+use octopus_common::types::{Order, PartialOrder, Side};
+use serde::{Deserialize, Serialize};
+
+/// A recorded request, replayed in the order of the log
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum ReplayEvent {
+    Deposit {
+        signer: String,
+        amount: u64,
+    },
+    Withdraw {
+        signer: String,
+        amount: u64,
+    },
+    Order(Order),
+    Cancel {
+        signer: String,
+        order_id: u64,
+    },
+    Amend {
+        signer: String,
+        order_id: u64,
+        price: u64,
+        amount: u64,
+    },
+}
+
+impl ReplayEvent {
+    /// The account the event acts for
+    pub fn signer(&self) -> &str {
+        match self {
+            ReplayEvent::Deposit { signer, .. }
+            | ReplayEvent::Withdraw { signer, .. }
+            | ReplayEvent::Cancel { signer, .. }
+            | ReplayEvent::Amend { signer, .. } => signer,
+            ReplayEvent::Order(order) => &order.signer,
+        }
+    }
+}
+
+/// An order resting in the book, without the identifiers that depend on the server's configuration
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RestingOrder {
+    pub side: Side,
+    pub price: u64,
+    pub remaining: u64,
+    pub signer: String,
+}
+
+impl From<&PartialOrder> for RestingOrder {
+    fn from(order: &PartialOrder) -> Self {
+        RestingOrder {
+            side: order.side.clone(),
+            price: order.price,
+            remaining: order.remaining,
+            signer: order.signer.clone(),
+        }
+    }
+}
+
+impl fmt::Display for RestingOrder {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{:?} {} @ {} of {}",
+            self.side, self.remaining, self.price, self.signer
+        )
+    }
+}
+
+/// The balances of the accounts and the public order book, in the order the server lists it
+#[derive(Debug, Default, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct StateSnapshot {
+    pub balances: BTreeMap<String, u64>,
+    pub orderbook: Vec<RestingOrder>,
+}
+
+/// Reads a JSON file of the replay
+/// # Errors
+/// The file can't be read or parsed
+pub fn load<T: for<'de> Deserialize<'de>>(path: &Path) -> Result<T, String> {
+    let contents =
+        std::fs::read_to_string(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+    serde_json::from_str(&contents).map_err(|e| format!("{}: {}", path.display(), e))
+}
+
+/// The accounts whose balances are compared: the expected ones and every one the events act for
+pub fn accounts(events: &[ReplayEvent], expected: &StateSnapshot) -> BTreeSet<String> {
+    events
+        .iter()
+        .map(|event| event.signer().to_string())
+        .chain(expected.balances.keys().cloned())
+        .collect()
+}
+
+/// Every difference between the `expected` and the `actual` state, one per line. Empty if they match.
+pub fn diff(expected: &StateSnapshot, actual: &StateSnapshot) -> Vec<String> {
+    let mut differences = vec![];
+    let accounts: BTreeSet<&String> = expected
+        .balances
+        .keys()
+        .chain(actual.balances.keys())
+        .collect();
+    for account in accounts {
+        match (expected.balances.get(account), actual.balances.get(account)) {
+            (Some(expected), Some(actual)) if expected != actual => differences.push(format!(
+                "balance of {}: expected {}, got {}",
+                account, expected, actual
+            )),
+            (Some(expected), None) => differences.push(format!(
+                "balance of {}: expected {}, the account doesn't exist",
+                account, expected
+            )),
+            (None, Some(actual)) => {
+                differences.push(format!("balance of {}: unexpected {}", account, actual))
+            }
+            _ => {}
+        }
+    }
+    let positions = expected.orderbook.len().max(actual.orderbook.len());
+    for i in 0..positions {
+        match (expected.orderbook.get(i), actual.orderbook.get(i)) {
+            (Some(expected), Some(actual)) if expected != actual => differences.push(format!(
+                "orderbook[{}]: expected {}, got {}",
+                i, expected, actual
+            )),
+            (Some(expected), None) => {
+                differences.push(format!("orderbook[{}]: expected {}, missing", i, expected))
+            }
+            (None, Some(actual)) => {
+                differences.push(format!("orderbook[{}]: unexpected {}", i, actual))
+            }
+            _ => {}
+        }
+    }
+    differences
+}
+
+#[cfg(test)]
+mod tests {
+    // reduce the warnings for naming tests
+    #![allow(non_snake_case)]
+
+    use super::*;
+
+    #[test]
+    fn test_diff_reports_balances_and_book_positions() {
+        let events: Vec<ReplayEvent> = serde_json::from_str(
+            r#"[
+                {"event": "deposit", "signer": "ALICE", "amount": 100},
+                {"event": "order", "price": 10, "amount": 2, "side": "Sell", "signer": "ALICE"},
+                {"event": "cancel", "signer": "BOB", "order_id": 1}
+            ]"#,
+        )
+        .unwrap();
+        assert_eq!(events[1].signer(), "ALICE");
+        let resting = |price, signer: &str| RestingOrder {
+            side: Side::Sell,
+            price,
+            remaining: 2,
+            signer: signer.to_string(),
+        };
+        let expected = StateSnapshot {
+            balances: BTreeMap::from([("ALICE".to_string(), 100), ("CAROL".to_string(), 5)]),
+            orderbook: vec![resting(10, "ALICE")],
+        };
+        assert_eq!(
+            accounts(&events, &expected).into_iter().collect::<Vec<_>>(),
+            ["ALICE", "BOB", "CAROL"]
+        );
+        assert!(diff(&expected, &expected).is_empty());
+
+        let actual = StateSnapshot {
+            balances: BTreeMap::from([("ALICE".to_string(), 90), ("BOB".to_string(), 0)]),
+            orderbook: vec![resting(11, "ALICE"), resting(12, "BOB")],
+        };
+        assert_eq!(
+            diff(&expected, &actual),
+            [
+                "balance of ALICE: expected 100, got 90",
+                "balance of BOB: unexpected 0",
+                "balance of CAROL: expected 5, the account doesn't exist",
+                "orderbook[0]: expected Sell 2 @ 10 of ALICE, got Sell 2 @ 11 of ALICE",
+                "orderbook[1]: unexpected Sell 2 @ 12 of BOB",
+            ]
+        );
+    }
+}
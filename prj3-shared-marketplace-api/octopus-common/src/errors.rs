@@ -1,5 +1,7 @@
 use warp::reject::Reject;
 
+use crate::types::{OrderConstraint, Receipt};
+
 /// An application-specific error type
 #[derive(Debug, PartialEq, Eq)]
 pub enum ApplicationError {
@@ -11,6 +13,243 @@ pub enum ApplicationError {
 
     /// Too much currency in the account (overflow)
     AccountOverFunded(String, u64),
+
+    /// API key wasn't found for the account
+    ApiKeyNotFound(String),
+
+    /// An API key needs at least one scope
+    ApiKeyWithoutScopes(String),
+
+    /// Missing or invalid credentials
+    Unauthorized(String),
+
+    /// The credentials don't permit the operation
+    Forbidden(String),
+
+    /// The operation needs the account's PIN
+    PinRequired(String),
+
+    /// The PIN is wrong (account, attempts left before the PIN is locked)
+    WrongPin(String, u32),
+
+    /// Too many wrong PINs, retry after the given number of seconds (account, seconds)
+    PinLocked(String, u64),
+
+    /// A PIN that's too short or too long
+    InvalidPin(String),
+
+    /// Public keys are 32 bytes, hex-encoded, and a valid ed25519 point
+    InvalidPublicKey(String),
+
+    /// The signer registered a public key, so its orders have to be signed
+    SignatureRequired(String),
+
+    /// The signature is malformed or doesn't match the order and any of the signer's public keys
+    InvalidSignature(String),
+
+    /// A signed order expired, or is valid for longer than [`crate::types::MAX_SIGNATURE_VALIDITY_MILLIS`] (expiry)
+    InvalidSignatureExpiry(u64),
+
+    /// The signer already signed an order with this nonce (signer, nonce)
+    NonceReused(String, u64),
+
+    /// Tenant wasn't found
+    TenantNotFound(String),
+
+    /// A tenant with that name exists already
+    TenantAlreadyExists(String),
+
+    /// Tenant names may only contain ASCII letters, digits, `-`, and `_`
+    InvalidTenantName(String),
+
+    /// A gateway deposit failed validation
+    InvalidDeposit(String),
+
+    /// A gateway notification reused the id of a different notification
+    DuplicateNotification(String),
+
+    /// Hold wasn't found or was completed already
+    HoldNotFound(u64),
+
+    /// Recurring buy wasn't found for the account
+    RecurringBuyNotFound(u64),
+
+    /// No market with that symbol
+    MarketNotFound(String),
+
+    /// A recurring buy needs a positive amount and interval in a known market
+    InvalidRecurringBuy(String),
+
+    /// The sides of a quote don't fit together
+    InvalidQuote(String),
+
+    /// The orders of a bracket don't fit together
+    InvalidBracket(String),
+
+    /// There are no orders on the other side of the book to match a market order
+    NoLiquidity(String),
+
+    /// The account has no stop-loss
+    StopLossNotSet(String),
+
+    /// The account breached its stop-loss and can only reduce its position until the next session
+    StopLossBreached(String),
+
+    /// Months are formatted as `YYYY-MM`
+    InvalidMonth(String),
+
+    /// No invoice for the account in that month
+    InvoiceNotFound(String),
+
+    /// Days are formatted as `YYYY-MM-DD`
+    InvalidDay(String),
+
+    /// No market report for that day
+    ReportNotFound(String),
+
+    /// The account archive doesn't exist or expired
+    ArchiveNotFound(u64),
+
+    /// The account still has open holds or pending withdrawals and can't be deleted
+    AccountInUse(String),
+
+    /// Withdrawal wasn't found
+    WithdrawalNotFound(u64),
+
+    /// The withdrawal was approved or rejected already
+    WithdrawalAlreadyResolved(u64),
+
+    /// The payout has no recipients, too many, or an invalid leg
+    InvalidPayout(String),
+
+    /// The withdrawal of a cold account can't be confirmed yet (id, seconds left)
+    WithdrawalDelayed(u64, u64),
+
+    /// Withdrawals of cold accounts are approved by the account's confirmation, not by an admin (id)
+    WithdrawalNeedsConfirmation(u64),
+
+    /// Trade wasn't found
+    TradeNotFound(u64),
+
+    /// No open order with that ordinal
+    OrderNotFound(u64),
+
+    /// No transaction of the account with that id
+    TransactionNotFound(u64),
+
+    /// The open order belongs to another account
+    OrderNotOwned(u64),
+
+    /// The trade was busted already
+    TradeAlreadyBusted(u64),
+
+    /// The limit price is further from the reference price than the price collar allows (price, reference price)
+    OutsidePriceCollar(u64, u64),
+
+    /// A market order would fill further from the best price than the slippage limit allows (worst price, best price)
+    SlippageExceeded(u64, u64),
+
+    /// An order would execute further from the last traded price than the circuit breaker allows (worst price, last
+    /// price)
+    PriceBandExceeded(u64, u64),
+
+    /// The circuit breaker halted the market, retry after the given number of seconds (market, seconds)
+    TradingHalted(String, u64),
+
+    /// The market is in a call auction, which only takes orders that can rest until it's run (market)
+    AuctionInProgress(String),
+
+    /// The signer submitted an order with this client order id moments ago (client order id, original receipt)
+    DuplicateOrder(String, Box<Receipt>),
+
+    /// A point in time is either an ordinal or an RFC 3339 timestamp
+    InvalidPointInTime(String),
+
+    /// The book deltas after this sequence number aren't retained anymore, start over from a snapshot
+    DeltasUnavailable(u64),
+
+    /// Writing an export file failed
+    ExportFailed(String),
+
+    /// Reading or writing durable server state failed
+    StorageFailed(String),
+
+    /// The seed data couldn't be read or applied
+    InvalidSeed(String),
+
+    /// Chaos settings out of range
+    InvalidChaosSettings(String),
+
+    /// The server configuration couldn't be read or is out of range
+    InvalidConfig(String),
+
+    /// A message in a trading session couldn't be parsed
+    InvalidSessionMessage(String),
+
+    /// An alias is malformed, or a transfer names both a recipient and an alias
+    InvalidAlias(String),
+
+    /// The account saved no recipient under the alias
+    AliasNotFound(String),
+
+    /// The account whitelists its destinations and the destination isn't active on it (account, destination)
+    DestinationNotWhitelisted(String, String),
+
+    /// The account didn't whitelist the destination
+    WhitelistEntryNotFound(String),
+
+    /// The account keeps no whitelist
+    WhitelistNotFound(String),
+
+    /// A transfer memo exceeds its size limits
+    InvalidMemo(String),
+
+    /// A transaction tag is empty, too long, has other characters than letters, digits, and `-_:/.`, or there are too
+    /// many of them (reason)
+    InvalidTag(String),
+
+    /// A faucet claim asks for more than the faucet hands out at once (requested, maximum)
+    FaucetLimitExceeded(u64, u64),
+
+    /// The account claimed from the faucet moments ago, retry after the given number of seconds (account, seconds)
+    FaucetCoolingDown(String, u64),
+
+    /// A dead-man's switch timeout above the maximum (seconds)
+    InvalidDeadmanTimeout(u64),
+
+    /// The account submits orders faster or cancels more of them than its limits allow, retry after the given number
+    /// of seconds (account, seconds)
+    OrderThrottled(String, u64),
+
+    /// The order queue is full, retry after the given number of seconds
+    Overloaded(u64),
+
+    /// The route group already works on as many requests as it may at once (group)
+    ConcurrencyLimited(String),
+
+    /// The request timeout header isn't a number of milliseconds
+    InvalidDeadline(String),
+
+    /// A good 'til time order expired before it was placed, the expiry
+    InvalidExpiry(u64),
+
+    /// An order violates a constraint of its market
+    InvalidOrder(OrderConstraint),
+
+    /// An order can't be amended that way (reason)
+    InvalidAmendment(String),
+
+    /// The request ran past its deadline and was abandoned (timeout in milliseconds, what was done by then)
+    DeadlineExceeded(u64, String),
+
+    /// The operation would mix sandbox and real funds or orders (account)
+    SandboxViolation(String),
+
+    /// The server is still restoring its state and doesn't accept orders yet (startup phase)
+    StartingUp(String),
+
+    /// The server is shutting down for maintenance and doesn't accept changes anymore
+    Maintenance,
 }
 
 #[derive(Debug)]
@@ -1,12 +1,249 @@
 use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+use crate::{errors::ApplicationError, types::anonymize};
+
+/// The longest memo text in characters
+pub const MAX_MEMO_LENGTH: usize = 140;
+
+/// The most metadata entries of one memo
+pub const MAX_MEMO_ENTRIES: usize = 16;
+
+/// The longest metadata key or value in characters
+pub const MAX_MEMO_FIELD_LENGTH: usize = 64;
+
+/// Reference text and metadata of a transfer, e.g. an invoice number to reconcile it with an external system
+#[derive(Debug, Default, PartialEq, Eq, Clone, Serialize, Deserialize)]
+pub struct Memo {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub text: Option<String>,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub metadata: BTreeMap<String, String>,
+}
+
+impl Memo {
+    /// Checks the size limits, memos are stored with every transfer
+    /// # Errors
+    /// The text, a key or value, or the number of entries exceeds its limit, or a key is empty
+    pub fn validate(&self) -> Result<(), ApplicationError> {
+        let too_long = |value: &str, max: usize| value.chars().count() > max;
+        if self
+            .text
+            .as_deref()
+            .is_some_and(|text| too_long(text, MAX_MEMO_LENGTH))
+        {
+            return Err(ApplicationError::InvalidMemo(format!(
+                "the text has more than {} characters",
+                MAX_MEMO_LENGTH
+            )));
+        }
+        if self.metadata.len() > MAX_MEMO_ENTRIES {
+            return Err(ApplicationError::InvalidMemo(format!(
+                "more than {} metadata entries",
+                MAX_MEMO_ENTRIES
+            )));
+        }
+        for (key, value) in self.metadata.iter() {
+            if key.is_empty()
+                || too_long(key, MAX_MEMO_FIELD_LENGTH)
+                || too_long(value, MAX_MEMO_FIELD_LENGTH)
+            {
+                return Err(ApplicationError::InvalidMemo(format!(
+                    "metadata keys have 1 to {0} characters, values up to {0}",
+                    MAX_MEMO_FIELD_LENGTH
+                )));
+            }
+        }
+        Ok(())
+    }
+}
 
 /// A transaction type. Transactions should be able to rebuild a ledger's state
 /// when they are applied in the same sequence to an empty state.
 #[derive(Debug, PartialEq, Eq, Clone, Serialize, Deserialize)]
 pub enum Tx {
     /// Currency was added to the account
-    Deposit { account: String, amount: u64 },
+    Deposit {
+        account: String,
+        amount: u64,
+        /// Set on the receiving leg of a transfer with a memo
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        memo: Option<Memo>,
+    },
 
     /// Currency was withdrawn from the account
-    Withdraw { account: String, amount: u64 },
+    Withdraw {
+        account: String,
+        amount: u64,
+        /// Set on the sending leg of a transfer with a memo
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        memo: Option<Memo>,
+    },
+
+    /// Currency was charged as a fee and moved to the fee account
+    Fee { account: String, amount: u64 },
+
+    /// Currency was reserved in a hold and isn't available to the account anymore
+    Hold {
+        id: u64,
+        account: String,
+        amount: u64,
+    },
+
+    /// The held currency was transferred to the recipient
+    Capture {
+        id: u64,
+        from: String,
+        to: String,
+        amount: u64,
+    },
+
+    /// The held currency was returned to the account
+    Release {
+        id: u64,
+        account: String,
+        amount: u64,
+    },
+
+    /// A withdrawal above the approval threshold or of a cold account was requested, the currency is held until it's
+    /// resolved
+    WithdrawalRequested {
+        id: u64,
+        account: String,
+        amount: u64,
+    },
+
+    /// The held currency of a pending withdrawal left the platform
+    WithdrawalApproved {
+        id: u64,
+        account: String,
+        amount: u64,
+    },
+
+    /// The held currency of a pending withdrawal was returned to the account
+    WithdrawalRejected {
+        id: u64,
+        account: String,
+        amount: u64,
+    },
+
+    /// Play currency was issued to a sandbox account
+    Faucet { account: String, amount: u64 },
+
+    /// The play currency left in a closed sandbox account was removed
+    SandboxClosed { account: String, amount: u64 },
+
+    /// The buyer paid the seller for the trade with the id `trade_id`
+    Settlement {
+        trade_id: u64,
+        from: String,
+        to: String,
+        amount: u64,
+    },
+
+    /// The seller returned the payment for the busted trade with the id `trade_id` to the buyer
+    SettlementReversed {
+        trade_id: u64,
+        from: String,
+        to: String,
+        amount: u64,
+    },
+
+    /// Currency was paid to one recipient of the payout `id`. The legs of a payout are recorded together or not at all.
+    Payout {
+        id: u64,
+        from: String,
+        to: String,
+        amount: u64,
+    },
+
+    /// The dust balance of an account was moved to the dust account
+    DustSwept {
+        from: String,
+        to: String,
+        amount: u64,
+    },
+}
+
+impl Tx {
+    /// The amount of currency the transaction moved
+    pub fn amount(&self) -> u64 {
+        match self {
+            Tx::Deposit { amount, .. }
+            | Tx::Withdraw { amount, .. }
+            | Tx::Fee { amount, .. }
+            | Tx::Hold { amount, .. }
+            | Tx::Capture { amount, .. }
+            | Tx::Release { amount, .. }
+            | Tx::WithdrawalRequested { amount, .. }
+            | Tx::WithdrawalApproved { amount, .. }
+            | Tx::WithdrawalRejected { amount, .. }
+            | Tx::Faucet { amount, .. }
+            | Tx::SandboxClosed { amount, .. }
+            | Tx::Settlement { amount, .. }
+            | Tx::SettlementReversed { amount, .. }
+            | Tx::Payout { amount, .. }
+            | Tx::DustSwept { amount, .. } => *amount,
+        }
+    }
+
+    /// The accounts the transaction debits or credits
+    pub fn parties(&self) -> Vec<&str> {
+        match self {
+            Tx::Capture { from, to, .. }
+            | Tx::Settlement { from, to, .. }
+            | Tx::SettlementReversed { from, to, .. }
+            | Tx::Payout { from, to, .. }
+            | Tx::DustSwept { from, to, .. } => vec![from, to],
+            Tx::Deposit { account, .. }
+            | Tx::Faucet { account, .. }
+            | Tx::SandboxClosed { account, .. }
+            | Tx::Withdraw { account, .. }
+            | Tx::Fee { account, .. }
+            | Tx::Hold { account, .. }
+            | Tx::Release { account, .. }
+            | Tx::WithdrawalRequested { account, .. }
+            | Tx::WithdrawalApproved { account, .. }
+            | Tx::WithdrawalRejected { account, .. } => vec![account],
+        }
+    }
+
+    /// The memo of a transfer leg
+    pub fn memo(&self) -> Option<&Memo> {
+        match self {
+            Tx::Deposit { memo, .. } | Tx::Withdraw { memo, .. } => memo.as_ref(),
+            _ => None,
+        }
+    }
+
+    /// Sets the memo of a transfer leg, other transactions don't carry one
+    pub fn set_memo(&mut self, memo: Option<Memo>) {
+        if let Tx::Deposit { memo: current, .. } | Tx::Withdraw { memo: current, .. } = self {
+            *current = memo;
+        }
+    }
+
+    /// Replaces the account name `signer` with `token`, the amounts stay as they are
+    pub fn anonymize(&mut self, signer: &str, token: &str) {
+        match self {
+            Tx::Capture { from, to, .. }
+            | Tx::Settlement { from, to, .. }
+            | Tx::SettlementReversed { from, to, .. }
+            | Tx::Payout { from, to, .. }
+            | Tx::DustSwept { from, to, .. } => {
+                anonymize(from, signer, token);
+                anonymize(to, signer, token);
+            }
+            Tx::Deposit { account, .. }
+            | Tx::Faucet { account, .. }
+            | Tx::SandboxClosed { account, .. }
+            | Tx::Withdraw { account, .. }
+            | Tx::Fee { account, .. }
+            | Tx::Hold { account, .. }
+            | Tx::Release { account, .. }
+            | Tx::WithdrawalRequested { account, .. }
+            | Tx::WithdrawalApproved { account, .. }
+            | Tx::WithdrawalRejected { account, .. } => anonymize(account, signer, token),
+        }
+    }
 }
@@ -1,5 +1,7 @@
 use serde::{Deserialize, Serialize};
-use std::cmp::Reverse;
+use std::collections::{BTreeMap, VecDeque};
+
+use crate::tx::Memo;
 
 /// Simplified side of a position as well as order.
 #[derive(Clone, PartialOrd, PartialEq, Eq, Debug, Ord, Serialize, Deserialize)]
@@ -10,6 +12,30 @@ pub enum Side {
     Sell,
 }
 
+/// How an order is priced
+#[derive(Clone, Copy, Default, PartialEq, Eq, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum OrderType {
+    /// Trades at the price or better, the rest waits in the book
+    #[default]
+    Limit,
+    /// Takes what the other side of the book offers at any price, the rest is dropped. The price is ignored.
+    Market,
+}
+
+/// How long what's left of a limit order stays in the book
+#[derive(Clone, Copy, Default, PartialEq, Eq, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "UPPERCASE")]
+pub enum TimeInForce {
+    /// Good 'til cancelled: the rest waits in the book
+    #[default]
+    Gtc,
+    /// Immediate or cancel: trades what it can right away, the rest is cancelled
+    Ioc,
+    /// Good 'til time: the rest waits in the book until the expiry, a unix timestamp (ms)
+    Gtt(u64),
+}
+
 /// An order for a specified symbol to buy or sell an amount at a given price.
 #[derive(Clone, PartialEq, Eq, Debug, Serialize, Deserialize)]
 pub struct Order {
@@ -21,17 +47,103 @@ pub struct Order {
     pub side: Side,
     /// The account signer
     pub signer: String,
+    /// Chosen by the client to identify the order. Resubmitting the same id shortly after is rejected as a duplicate.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub client_order_id: Option<String>,
+    /// Required once the signer registered a public key
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub signature: Option<OrderSignature>,
+    /// Limit orders unless set
+    #[serde(default)]
+    pub order_type: OrderType,
+    /// Good 'til cancelled unless set
+    #[serde(default)]
+    pub time_in_force: TimeInForce,
+    /// Makes a stop order: it waits outside the book until a trade at or beyond this price, a stop-loss for a market
+    /// order and a stop-limit for a limit order. Buy stops trigger at or above it, sell stops at or below it.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub trigger_price: Option<u64>,
+    /// The symbol of the market to trade in, [`DEFAULT_MARKET`] unless set
+    #[serde(default = "default_market", alias = "symbol")]
+    pub market: String,
+}
+
+/// The longest a signed order may be valid for, the nonces of valid orders are remembered until they expire
+pub const MAX_SIGNATURE_VALIDITY_MILLIS: u64 = 5 * 60 * 1000;
+
+/// The signature of an order and what makes it single use
+#[derive(Clone, PartialEq, Eq, Debug, Serialize, Deserialize)]
+pub struct OrderSignature {
+    /// Chosen by the signer, each nonce is accepted once while the order is valid
+    pub nonce: u64,
+    /// The signed order is rejected from this unix timestamp (ms) on
+    pub expires_at: u64,
+    /// Hex-encoded ed25519 signature of [`Order::signing_payload`]
+    pub value: String,
+}
+
+/// The bytes a signature of the `domain` covers: the domain, then every one of the `fields` on a line of its own,
+/// prefixed with its length in bytes. A field can't end early or spill into the next one, whatever text it holds.
+fn signing_payload(domain: &str, fields: &[String]) -> Vec<u8> {
+    let mut payload = domain.to_string();
+    for field in fields {
+        payload.push_str(&format!("\n{}:{}", field.len(), field));
+    }
+    payload.into_bytes()
 }
 
 impl Order {
-    /// Convert an [`Order`] into a [`PartialOrder`] with the added parameters
+    /// The canonical bytes an order's signature covers: the `tenant` it's sent to, the `nonce` and `expires_at` of its
+    /// [`OrderSignature`], and every field of the order but the signature, in a fixed format. Market orders, a time in
+    /// force other than good 'til cancelled, and a trigger price add a field of their own each. So does a market
+    /// other than the default one.
+    pub fn signing_payload(&self, tenant: &str, nonce: u64, expires_at: u64) -> Vec<u8> {
+        let side = match self.side {
+            Side::Buy => "buy",
+            Side::Sell => "sell",
+        };
+        let mut fields = vec![
+            tenant.to_string(),
+            nonce.to_string(),
+            expires_at.to_string(),
+            self.signer.clone(),
+            side.to_string(),
+            self.price.to_string(),
+            self.amount.to_string(),
+            self.client_order_id.clone().unwrap_or_default(),
+        ];
+        if self.order_type == OrderType::Market {
+            fields.push("market".to_string());
+        }
+        match self.time_in_force {
+            TimeInForce::Gtc => {}
+            TimeInForce::Ioc => fields.push("ioc".to_string()),
+            TimeInForce::Gtt(expiry) => fields.push(format!("gtt {}", expiry)),
+        }
+        if let Some(trigger_price) = self.trigger_price {
+            fields.push(format!("stop {}", trigger_price));
+        }
+        if self.market != DEFAULT_MARKET {
+            fields.push(format!("symbol {}", self.market));
+        }
+        signing_payload("octopus-order/v3", &fields)
+    }
+
+    /// Convert an [`Order`] into a [`PartialOrder`] with the added parameters. A new order's id is its ordinal.
     pub fn into_partial_order(self, ordinal: u64, remaining: u64) -> PartialOrder {
         let Order {
             price,
             amount,
             side,
             signer,
+            time_in_force,
+            market,
+            ..
         } = self;
+        let expires_at = match time_in_force {
+            TimeInForce::Gtt(expiry) => Some(expiry),
+            TimeInForce::Gtc | TimeInForce::Ioc => None,
+        };
         PartialOrder {
             price,
             amount,
@@ -39,12 +151,18 @@ impl Order {
             side,
             signer,
             ordinal,
+            order_id: ordinal,
+            expires_at,
+            market,
         }
     }
 }
 
+/// Identifies an order for as long as it rests in the book
+pub type OrderId = u64;
+
 /// A position represents an unfilled order that is kept in the system for later filling.
-#[derive(Clone, PartialEq, Debug, Eq, Ord, Serialize, Deserialize)]
+#[derive(Clone, PartialEq, Debug, Eq, Serialize, Deserialize)]
 pub struct PartialOrder {
     /// Price per unit
     pub price: u64,
@@ -58,23 +176,219 @@ pub struct PartialOrder {
     pub signer: String,
     /// Sequence number
     pub ordinal: u64,
-}
-
-impl PartialOrd for PartialOrder {
-    fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
-        // this reverses the comparison to create a min heap
-        Reverse(self.ordinal).partial_cmp(&Reverse(other.ordinal))
-    }
+    /// Stays the same while the order rests, to look it up, cancel, or amend it later
+    #[serde(default)]
+    pub order_id: OrderId,
+    /// When a good 'til time order is taken off the book, unix timestamp (ms)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub expires_at: Option<u64>,
+    /// The symbol of the market the order is in
+    #[serde(default = "default_market")]
+    pub market: String,
 }
 
 /// A receipt issued to the caller for accepting an [`Order`]
-#[derive(Clone, PartialOrd, PartialEq, Eq, Debug, Serialize, Deserialize)]
+#[derive(Clone, PartialEq, Eq, Debug, Serialize, Deserialize)]
 pub struct Receipt {
     /// Sequence number
     pub ordinal: u64,
 
-    /// Matches that happened immediately
+    /// The id of what's left of the order in the book
+    #[serde(default)]
+    pub order_id: OrderId,
+
+    /// Matches that happened immediately, each at the price of its resting order
     pub matches: Vec<PartialOrder>,
+
+    /// Units traded immediately
+    #[serde(default)]
+    pub filled: u64,
+
+    /// Units dropped instead of resting in the book, what's left of market and immediate-or-cancel orders
+    #[serde(default)]
+    pub cancelled: u64,
+
+    /// The limit price of the order, `None` for market orders. The matches are at this price or better, the
+    /// difference is the price improvement.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub limit_price: Option<u64>,
+
+    /// The symbol of the market the order is in
+    #[serde(default = "default_market")]
+    pub market: String,
+
+    /// The resting orders of the same account the order met, and how each was resolved
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub self_matches: Vec<SelfMatch>,
+
+    /// What the matches amounted to and the fee charged on them, once they're settled
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub settlement: Option<Settlement>,
+
+    /// Units of the order left resting in the book, or waiting for the trigger of a stop order
+    #[serde(default)]
+    pub remaining: u64,
+
+    /// The sum of amount times price over the matches
+    #[serde(default)]
+    pub total_notional: u64,
+
+    /// The total notional over the units filled, rounded down, `None` if nothing was filled
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub average_price: Option<u64>,
+
+    /// How much of the order was filled
+    #[serde(default)]
+    pub status: ReceiptStatus,
+}
+
+/// Where an order stands after it was processed
+#[derive(Clone, Copy, Default, PartialEq, Eq, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ReceiptStatus {
+    /// Every unit traded
+    Filled,
+    /// Some units traded, the rest rests in the book or was cancelled
+    PartiallyFilled,
+    /// Nothing traded, the order rests in the book or waits for its trigger
+    #[default]
+    Resting,
+    /// Nothing traded and nothing rests, like a market order meeting an empty book
+    Cancelled,
+}
+
+/// The funds moved by the immediate matches of an order
+#[derive(Clone, Copy, Default, PartialEq, Eq, Debug, Serialize, Deserialize)]
+pub struct Settlement {
+    /// The notional of the matches
+    pub gross: u64,
+    /// The taker fee charged on top
+    pub fee: u64,
+    /// What the account paid for a buy, the gross plus the fee, or received for a sell, the gross minus the fee
+    pub net: u64,
+}
+
+/// What happens when an order meets a resting order of the same account. Orders never trade with their own account.
+#[derive(Clone, Copy, Default, PartialEq, Eq, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SelfMatchPolicy {
+    /// The resting order keeps its place, the order matches the ones behind it
+    #[default]
+    SkipResting,
+    /// The resting order is cancelled, the order matches the ones behind it
+    CancelResting,
+    /// What's left of the order is cancelled, the resting order stays
+    CancelIncoming,
+    /// Both are reduced by the smaller of their remaining amounts, the one left with nothing is cancelled
+    DecrementBoth,
+}
+
+/// How an order's meeting with a resting order of the same account was resolved
+#[derive(Clone, PartialEq, Eq, Debug, Serialize, Deserialize)]
+pub struct SelfMatch {
+    /// The policy of the book at the time
+    pub policy: SelfMatchPolicy,
+    /// The resting order the order met
+    pub resting_order_id: OrderId,
+    /// The price level of the resting order
+    pub price: u64,
+    /// Units taken off the resting order
+    pub resting_cancelled: u64,
+    /// Units taken off the order, part of the receipt's `cancelled`
+    pub incoming_cancelled: u64,
+}
+
+/// A bid and an ask of the same account, accepted or rejected together. A new quote replaces what's left of the
+/// account's previous one.
+#[derive(Clone, PartialEq, Eq, Debug, Serialize, Deserialize)]
+pub struct QuoteRequest {
+    pub bid: Order,
+    pub ask: Order,
+}
+
+/// A receipt issued for accepting both sides of a quote
+#[derive(Clone, PartialEq, Eq, Debug, Serialize, Deserialize)]
+pub struct QuoteReceipt {
+    /// What was left of the replaced quote
+    pub cancelled: Vec<PartialOrder>,
+    pub bid: Receipt,
+    pub ask: Receipt,
+}
+
+/// A take-profit and a stop-loss closing the same position, one cancels the other: once either order fills or the
+/// stop triggers, the engine cancels the other one.
+#[derive(Clone, PartialEq, Eq, Debug, Serialize, Deserialize)]
+pub struct BracketRequest {
+    /// A limit order resting at the target price
+    pub take_profit: Order,
+    /// A stop order on the same side, triggered on the way against the position
+    pub stop_loss: Order,
+}
+
+/// A receipt issued for accepting both orders of a bracket
+#[derive(Clone, PartialEq, Eq, Debug, Serialize, Deserialize)]
+pub struct BracketReceipt {
+    pub take_profit: Receipt,
+    pub stop_loss: Receipt,
+    /// The order of the bracket that was cancelled right away because the other one filled or triggered on placement
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub cancelled: Vec<PartialOrder>,
+}
+
+/// The outcome of a call auction: the orders that crossed at the clearing price
+#[derive(Clone, PartialEq, Eq, Debug, Default, Serialize, Deserialize)]
+pub struct AuctionReceipt {
+    /// The single price every match of the auction executed at, `None` if no bid met an ask
+    pub price: Option<u64>,
+    /// Units crossed at the clearing price
+    pub volume: u64,
+    /// Each crossed bid with the asks it met, in price-time priority
+    pub fills: Vec<AuctionFill>,
+}
+
+/// A bid crossed in a call auction: the receipt of the bid's part that filled, with the asks it met as matches
+#[derive(Clone, PartialEq, Eq, Debug, Serialize, Deserialize)]
+pub struct AuctionFill {
+    /// Signer of the bid
+    pub signer: String,
+    #[serde(flatten)]
+    pub receipt: Receipt,
+}
+
+/// Replaces the account name `signer` with `token`, leaves other accounts as they are
+pub fn anonymize(account: &mut String, signer: &str, token: &str) {
+    if account == signer {
+        *account = token.to_string();
+    }
+}
+
+impl Receipt {
+    /// Replaces `signer` with `token` in the matched orders
+    pub fn anonymize(&mut self, signer: &str, token: &str) {
+        for order in self.matches.iter_mut() {
+            anonymize(&mut order.signer, signer, token);
+        }
+    }
+
+    /// Units of the order cancelled by its self-matches
+    pub fn incoming_self_matched(&self) -> u64 {
+        self.self_matches.iter().map(|m| m.incoming_cancelled).sum()
+    }
+
+    /// Sets the totals of the matches and the status from `filled`, `cancelled`, and the `remaining` units
+    pub fn total(&mut self, remaining: u64) {
+        self.remaining = remaining;
+        self.total_notional = self.matches.iter().fold(0u64, |sum, m| {
+            sum.saturating_add(m.amount.saturating_mul(m.price))
+        });
+        self.average_price = (self.filled > 0).then(|| self.total_notional / self.filled);
+        self.status = match (self.filled, remaining, self.cancelled) {
+            (0, 0, _) => ReceiptStatus::Cancelled,
+            (0, _, _) => ReceiptStatus::Resting,
+            (_, 0, 0) => ReceiptStatus::Filled,
+            _ => ReceiptStatus::PartiallyFilled,
+        };
+    }
 }
 
 impl PartialOrder {
@@ -99,9 +413,1219 @@ pub struct AccountBalanceRequest {
     pub signer: String,
 }
 
+/// Registers a public key for verifying the account's order signatures
+#[derive(Debug, PartialEq, Eq, Clone, Serialize, Deserialize)]
+pub struct PublicKeyRequest {
+    /// Hex-encoded ed25519 public key
+    pub public_key: String,
+}
+
+/// A public key registered for an account
+#[derive(Debug, PartialEq, Eq, Clone, Serialize, Deserialize)]
+pub struct RegisteredPublicKey {
+    /// Hex-encoded ed25519 public key
+    pub public_key: String,
+    /// Unix timestamp (ms)
+    pub registered_at: u64,
+}
+
+/// Sets the PIN of an account, changing an existing PIN requires the current one
+#[derive(Debug, PartialEq, Eq, Clone, Serialize, Deserialize)]
+pub struct PinRequest {
+    pub pin: String,
+    pub current_pin: Option<String>,
+}
+
+/// One recipient of a payout
+#[derive(Debug, PartialEq, Eq, Clone, Serialize, Deserialize)]
+pub struct PayoutLeg {
+    pub to: String,
+    pub amount: u64,
+}
+
+/// Pays many recipients from one account at once, e.g. salaries or rebates
+#[derive(Debug, PartialEq, Eq, Clone, Serialize, Deserialize)]
+pub struct PayoutRequest {
+    pub from: String,
+    pub recipients: Vec<PayoutLeg>,
+}
+
+/// Marks an account cold or, once the cold withdrawal delay passed, hot again
+#[derive(Debug, PartialEq, Eq, Clone, Serialize, Deserialize)]
+pub struct ColdRequest {
+    pub cold: bool,
+}
+
+/// A transfer to `to`, or to the recipient the sender saved as `to_alias`
 #[derive(Debug, PartialEq, Eq, Clone, Serialize, Deserialize)]
 pub struct SendRequest {
     pub from: String,
+    #[serde(default)]
+    pub to: String,
+    pub amount: u64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub to_alias: Option<String>,
+    /// Recorded on both legs of the transfer
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub memo: Option<Memo>,
+}
+
+/// A recipient an account saved under an alias
+#[derive(Debug, PartialEq, Eq, Clone, Serialize, Deserialize)]
+pub struct SavedRecipient {
+    pub alias: String,
+    pub signer: String,
+}
+
+/// A destination an account approved for its withdrawals and transfers
+#[derive(Debug, PartialEq, Eq, Clone, Serialize, Deserialize)]
+pub struct WhitelistEntry {
+    /// An account for transfers, or the external identifier withdrawals name
+    pub destination: String,
+    /// Unix timestamp (ms) the entry becomes active, funds can't move to it above the threshold before
+    pub active_at: u64,
+    /// Unix timestamp (ms) the entry stops being active once it was removed
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub removed_at: Option<u64>,
+}
+
+/// Withdraws funds, to an external destination if named
+#[derive(Debug, PartialEq, Eq, Clone, Serialize, Deserialize)]
+pub struct WithdrawalRequest {
+    pub signer: String,
+    pub amount: u64,
+    /// Checked against the account's whitelist
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub destination: Option<String>,
+}
+
+/// Saves the recipient of an alias
+#[derive(Debug, PartialEq, Eq, Clone, Serialize, Deserialize)]
+pub struct SavedRecipientRequest {
+    pub signer: String,
+}
+
+/// A permission that can be granted to an API key
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug, Serialize, Deserialize)]
+pub enum ApiKeyScope {
+    /// Query balances, orders, and transactions
+    Read,
+    /// Place orders
+    Trade,
+    /// Move funds out of the account
+    Withdraw,
+}
+
+/// The role of a credential. Roles are ordered by their privileges, i.e. `Admin` can do everything `Operator` can.
+#[derive(
+    Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Debug, Default, Serialize, Deserialize,
+)]
+pub enum Role {
+    /// Can only read the data of its own account
+    ReadOnly,
+    /// Can trade and move funds of its own account
+    #[default]
+    Trader,
+    /// Can act on behalf of every account, e.g. to fund it
+    Operator,
+    /// Can do everything, including issuing operator credentials
+    Admin,
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Serialize, Deserialize)]
+pub struct ApiKeyRequest {
+    pub scopes: Vec<ApiKeyScope>,
+    #[serde(default)]
+    pub role: Role,
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Serialize, Deserialize)]
+pub struct AdminApiKeyRequest {
+    pub signer: String,
+    pub role: Role,
+    pub scopes: Vec<ApiKeyScope>,
+}
+
+/// The public part of an API key. The secret itself is only revealed once, see [`NewApiKey`].
+#[derive(Debug, PartialEq, Eq, Clone, Serialize, Deserialize)]
+pub struct ApiKey {
+    /// Identifier of the key, also the prefix of its secret
+    pub id: String,
+    /// The account the key acts for
+    pub signer: String,
+    /// The role of the key's bearer
+    pub role: Role,
+    /// What the key is allowed to do
+    pub scopes: Vec<ApiKeyScope>,
+    /// Revoked keys can't be used anymore
+    pub revoked: bool,
+}
+
+/// A freshly created API key including its secret
+#[derive(Debug, PartialEq, Eq, Clone, Serialize, Deserialize)]
+pub struct NewApiKey {
+    pub key: ApiKey,
+    pub secret: String,
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Serialize, Deserialize)]
+pub struct TenantRequest {
+    pub name: String,
+}
+
+/// The body of every error response
+#[derive(Debug, PartialEq, Eq, Clone, Serialize, Deserialize)]
+pub struct ErrorResponse {
+    pub code: u16,
+    pub message: String,
+    /// The original receipt when a duplicate order was rejected
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub receipt: Option<Receipt>,
+}
+
+/// A notification from the payment gateway that funds arrived for an account
+#[derive(Debug, PartialEq, Eq, Clone, Serialize, Deserialize)]
+pub struct DepositNotification {
+    /// The gateway's unique identifier, resending a notification doesn't credit the account again
+    pub id: String,
+    pub account: String,
+    pub amount: u64,
+    /// ISO 4217 currency code
+    pub currency: String,
+}
+
+/// The lifecycle of a withdrawal that needs approval
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Serialize, Deserialize)]
+pub enum WithdrawalStatus {
+    Pending,
+    Approved,
+    Rejected,
+}
+
+/// A withdrawal above the approval threshold or of a cold account
+#[derive(Debug, PartialEq, Eq, Clone, Serialize, Deserialize)]
+pub struct PendingWithdrawal {
+    pub id: u64,
+    pub account: String,
+    pub amount: u64,
+    pub status: WithdrawalStatus,
+    /// Where the funds go, if the withdrawal named a destination
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub destination: Option<String>,
+    /// Withdrawals of cold accounts wait for the account's confirmation, which isn't accepted before this unix
+    /// timestamp (ms)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub release_at: Option<u64>,
+}
+
+/// Funds reserved for a later transfer
+#[derive(Debug, PartialEq, Eq, Clone, Serialize, Deserialize)]
+pub struct Hold {
+    pub id: u64,
+    pub account: String,
+    pub amount: u64,
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Serialize, Deserialize)]
+pub struct HoldRequest {
+    pub from: String,
+    pub amount: u64,
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Serialize, Deserialize)]
+pub struct CaptureRequest {
     pub to: String,
+}
+
+/// The market every order trades in
+pub const DEFAULT_MARKET: &str = "OCTO-USD";
+
+fn default_market() -> String {
+    DEFAULT_MARKET.to_string()
+}
+
+#[derive(Debug, Default, PartialEq, Eq, Clone, Serialize, Deserialize)]
+pub struct OrderQuery {
+    /// Skip the price collar check. Only admins may set it.
+    #[serde(default)]
+    pub override_collar: bool,
+}
+
+/// The account cancelling one of its orders
+#[derive(Debug, PartialEq, Eq, Clone, Serialize, Deserialize)]
+pub struct CancelQuery {
+    pub signer: String,
+}
+
+/// The new price and open amount of one of the account's resting orders
+#[derive(Debug, PartialEq, Eq, Clone, Serialize, Deserialize)]
+pub struct AmendRequest {
+    pub signer: String,
+    pub price: u64,
+    pub amount: u64,
+    /// Required once the signer registered a public key, like for orders
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub signature: Option<OrderSignature>,
+}
+
+impl AmendRequest {
+    /// The canonical bytes the signature of amending the order `id` covers, like [`Order::signing_payload`]
+    pub fn signing_payload(
+        &self,
+        id: OrderId,
+        tenant: &str,
+        nonce: u64,
+        expires_at: u64,
+    ) -> Vec<u8> {
+        let fields = [
+            tenant.to_string(),
+            nonce.to_string(),
+            expires_at.to_string(),
+            self.signer.clone(),
+            id.to_string(),
+            self.price.to_string(),
+            self.amount.to_string(),
+        ];
+        signing_payload("octopus-amend/v2", &fields)
+    }
+}
+
+/// The account looking up where one of its orders stands in its price level
+#[derive(Debug, PartialEq, Eq, Clone, Serialize, Deserialize)]
+pub struct QueuePositionQuery {
+    pub signer: String,
+}
+
+/// Where a resting order stands in the time priority of its price level. Orders ahead of it fill first at this price,
+/// unless they're of the taker's own account.
+#[derive(Debug, PartialEq, Eq, Clone, Serialize, Deserialize)]
+pub struct QueuePosition {
+    pub order_id: OrderId,
+    pub side: Side,
+    pub price: u64,
+    pub remaining: u64,
+    /// Open quantity of the orders placed before it at this price
+    pub ahead_quantity: u64,
+    pub ahead_orders: usize,
+    /// Open quantity of the whole price level, the order included
+    pub level_quantity: u64,
+    pub level_orders: usize,
+}
+
+/// Which open orders of an account to cancel in one step. Every criterion that is set has to match.
+#[derive(Debug, PartialEq, Eq, Clone, Serialize, Deserialize)]
+pub struct CancelFilter {
+    pub signer: String,
+    /// The symbol of the market the orders are in
+    pub market: Option<String>,
+    pub side: Option<Side>,
+    pub min_price: Option<u64>,
+    pub max_price: Option<u64>,
+    /// Only orders placed more than this many milliseconds ago
+    pub older_than_ms: Option<u64>,
+}
+
+impl CancelFilter {
+    /// Whether `order` is of the signer and matches the side and the price range. The market and the age are up to
+    /// the book holding the order.
+    pub fn matches(&self, order: &PartialOrder) -> bool {
+        order.signer == self.signer
+            && self.side.as_ref().is_none_or(|side| *side == order.side)
+            && self.min_price.is_none_or(|min| order.price >= min)
+            && self.max_price.is_none_or(|max| order.price <= max)
+    }
+}
+
+/// The account looking up what happened to one of its orders
+#[derive(Debug, PartialEq, Eq, Clone, Serialize, Deserialize)]
+pub struct OrderEventsQuery {
+    pub signer: String,
+}
+
+/// Whether a market accepts orders
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum MarketStatus {
+    Open,
+    Halted,
+    /// Orders rest without matching until the call auction is run
+    Auction,
+}
+
+/// The daily window in which a market accepts orders. `00:00` to `24:00` means continuous trading.
+#[derive(Debug, PartialEq, Eq, Clone, Serialize, Deserialize)]
+pub struct TradingHours {
+    pub timezone: String,
+    /// `HH:MM`
+    pub open: String,
+    /// `HH:MM`
+    pub close: String,
+}
+
+/// The fees of accounts that traded at least `min_volume` units
+#[derive(Debug, PartialEq, Eq, Clone, Serialize, Deserialize)]
+pub struct FeeTier {
+    pub min_volume: u64,
+    pub maker_fee_bps: u64,
+    pub taker_fee_bps: u64,
+}
+
+/// The reference data of a market for validating orders before submitting them
+#[derive(Debug, PartialEq, Eq, Clone, Serialize, Deserialize)]
+pub struct MarketInfo {
+    pub symbol: String,
+    /// Prices must be a multiple of the tick size
+    pub tick_size: u64,
+    /// Amounts must be a multiple of the lot size
+    pub lot_size: u64,
+    /// The smallest amount of an order, 0 if any lot will do
+    #[serde(default)]
+    pub min_amount: u64,
+    /// The largest amount of an order, if limited
+    #[serde(default)]
+    pub max_amount: Option<u64>,
+    /// Ascending by `min_volume`
+    pub fee_tiers: Vec<FeeTier>,
+    pub trading_hours: TradingHours,
+    pub status: MarketStatus,
+    /// Sandbox accounts may trade here, against each other only
+    #[serde(default)]
+    pub sandbox: bool,
+}
+
+/// The constraint of a market an order violates
+#[derive(Debug, PartialEq, Eq, Clone, Serialize, Deserialize)]
+#[serde(tag = "constraint", rename_all = "snake_case")]
+pub enum OrderConstraint {
+    /// The limit or trigger price isn't a multiple of the tick size
+    TickSize {
+        price: u64,
+        tick_size: u64,
+    },
+    /// The amount isn't a multiple of the lot size
+    LotSize {
+        amount: u64,
+        lot_size: u64,
+    },
+    MinAmount {
+        amount: u64,
+        min_amount: u64,
+    },
+    MaxAmount {
+        amount: u64,
+        max_amount: u64,
+    },
+    /// The amount times the limit or trigger price is more currency than can be held
+    Notional {
+        amount: u64,
+        price: u64,
+    },
+}
+
+/// Where a reference price was taken from
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ReferenceSource {
+    /// The median of the trades within the window
+    Trades,
+    /// The oracle price an operator set, without recent trades
+    Oracle,
+    /// The last trade, older than the window, without an oracle price
+    LastTrade,
+}
+
+/// The price a market's collar, stop-losses, and profit and loss are measured against
+#[derive(Debug, PartialEq, Eq, Clone, Serialize, Deserialize)]
+pub struct ReferencePrice {
+    pub price: u64,
+    pub source: ReferenceSource,
+}
+
+/// An external price of a market, the reference price while it doesn't trade
+#[derive(Debug, PartialEq, Eq, Clone, Serialize, Deserialize)]
+pub struct OraclePrice {
+    pub price: u64,
+}
+
+/// The latest prices of a market
+#[derive(Debug, PartialEq, Eq, Clone, Serialize, Deserialize)]
+pub struct Ticker {
+    pub symbol: String,
+    pub last_price: Option<u64>,
+    pub reference: Option<ReferencePrice>,
+    pub best_bid: Option<u64>,
+    pub best_ask: Option<u64>,
+    /// The best ask less the best bid, `None` unless both sides have orders
+    pub spread: Option<u64>,
+}
+
+/// The market of `GET /ticker`
+#[derive(Debug, PartialEq, Eq, Clone, Serialize, Deserialize)]
+pub struct TickerQuery {
+    #[serde(default = "default_market", alias = "symbol")]
+    pub market: String,
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Serialize, Deserialize)]
+pub struct RecurringBuyRequest {
+    /// Currency to spend on each execution
+    pub amount: u64,
+    /// Seconds between executions
+    pub interval_secs: u64,
+    #[serde(default = "default_market")]
+    pub market: String,
+}
+
+/// A scheduled market buy repeating at a fixed interval (dollar-cost averaging)
+#[derive(Debug, PartialEq, Eq, Clone, Serialize, Deserialize)]
+pub struct RecurringBuy {
+    pub id: u64,
+    pub account: String,
+    pub market: String,
+    /// Currency to spend on each execution
+    pub amount: u64,
+    /// Seconds between executions
+    pub interval_secs: u64,
+    /// Paused plans aren't executed
+    pub paused: bool,
+    /// Unix timestamp (ms) of the next execution
+    pub next_run: u64,
+}
+
+/// The outcome of a [`RecurringBuy`] execution
+#[derive(Debug, PartialEq, Eq, Clone, Serialize, Deserialize)]
+pub struct RecurringBuyExecution {
+    pub plan_id: u64,
+    /// Unix timestamp (ms) of the execution
+    pub timestamp: u64,
+    /// The receipt of the buy order, if it was accepted
+    pub receipt: Option<Receipt>,
+    /// Why the buy failed
+    pub error: Option<String>,
+}
+
+/// An account's net holding in the market, valued with the average cost method
+#[derive(Debug, Default, PartialEq, Eq, Clone, Serialize, Deserialize)]
+pub struct Position {
+    /// Units held, negative for a short position
+    pub units: i64,
+    /// Currency paid for the units held, negative for a short position
+    pub cost: i64,
+    /// Profit or loss of closed units
+    pub realized: i64,
+}
+
+impl Position {
+    /// Profit or loss of the units held if they were closed at `mark`
+    pub fn unrealized(&self, mark: u64) -> i64 {
+        self.units * mark as i64 - self.cost
+    }
+
+    /// Realized and unrealized profit or loss
+    pub fn pnl(&self, mark: u64) -> i64 {
+        self.realized + self.unrealized(mark)
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Serialize, Deserialize)]
+pub struct StopLossRequest {
+    /// The loss at which the account stops trading for the rest of the session
+    pub max_daily_loss: u64,
+}
+
+/// The open orders and the position of an account in one market
+#[derive(Debug, PartialEq, Eq, Clone, Serialize, Deserialize)]
+pub struct MarketExposure {
+    pub market: String,
+    /// Price of the open buy orders
+    pub open_buy_notional: u64,
+    /// Price of the open sell orders
+    pub open_sell_notional: u64,
+    pub position: Position,
+}
+
+/// The funds an account has committed, for pre-trade checks
+#[derive(Debug, PartialEq, Eq, Clone, Serialize, Deserialize)]
+pub struct Exposure {
+    pub signer: String,
+    /// The available balance
+    pub balance: u64,
+    /// Funds in open holds
+    pub held: u64,
+    /// Funds of withdrawals waiting for approval
+    pub reserved: u64,
+    pub markets: Vec<MarketExposure>,
+    /// What's left of the balance once the open buy orders and their taker fees are paid
+    pub trading_power: u64,
+}
+
+/// An account's stop-loss for the current session
+#[derive(Debug, PartialEq, Eq, Clone, Serialize, Deserialize)]
+pub struct StopLossStatus {
+    pub max_daily_loss: u64,
+    /// Realized and unrealized profit or loss since the session started
+    pub daily_pnl: i64,
+    /// Whether risk-increasing orders are blocked until the next session
+    pub breached: bool,
+}
+
+/// Arms, refreshes, or with a zero timeout disarms a dead-man's switch
+#[derive(Debug, Default, PartialEq, Eq, Clone, Serialize, Deserialize)]
+pub struct DeadmanQuery {
+    /// Seconds until the account's open orders are cancelled unless the switch is refreshed
+    pub timeout: u64,
+}
+
+/// An account's dead-man's switch
+#[derive(Debug, PartialEq, Eq, Clone, Serialize, Deserialize)]
+pub struct DeadmanStatus {
+    pub signer: String,
+    pub timeout_secs: u64,
+    /// When the open orders are cancelled unless the switch is refreshed, Unix timestamp (ms). `None` if disarmed.
+    pub expires_at: Option<u64>,
+}
+
+/// Trading statistics of an account within a time window
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
+pub struct AccountStats {
+    pub account: String,
+    /// Traded currency (price times amount)
+    pub volume: u64,
+    pub trade_count: u64,
+    /// Share of position-reducing trades that realized a profit
+    pub win_rate: f64,
+    /// Realized profit or loss
+    pub pnl: i64,
+}
+
+impl AccountStats {
+    /// Statistics of an account without trades
+    pub fn new(account: &str) -> Self {
+        AccountStats {
+            account: account.to_string(),
+            volume: 0,
+            trade_count: 0,
+            win_rate: 0.0,
+            pnl: 0,
+        }
+    }
+}
+
+#[derive(Debug, Default, PartialEq, Eq, Clone, Serialize, Deserialize)]
+pub struct StatsQuery {
+    /// Only count trades of the last seconds, all trades if missing
+    pub window_secs: Option<u64>,
+}
+
+/// What the leaderboard is ranked by
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum LeaderboardSort {
+    #[default]
+    Pnl,
+    Volume,
+    WinRate,
+}
+
+#[derive(Debug, Default, PartialEq, Eq, Clone, Serialize, Deserialize)]
+pub struct LeaderboardQuery {
+    /// Only count trades of the last seconds, all trades if missing
+    pub window_secs: Option<u64>,
+    #[serde(default)]
+    pub sort_by: LeaderboardSort,
+    pub limit: Option<usize>,
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Serialize, Deserialize)]
+pub struct LeaderboardRequest {
+    /// Whether the account is listed on the leaderboard
+    pub opt_in: bool,
+}
+
+/// Why a fee was charged
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum FeeKind {
+    /// Charged to the order that took liquidity from the book
+    Taker,
+    /// Charged to the resting order that provided the liquidity
+    Maker,
+}
+
+/// A fee charged to an account
+#[derive(Debug, PartialEq, Eq, Clone, Serialize, Deserialize)]
+pub struct FeeCharge {
+    /// Unix timestamp (ms)
+    pub timestamp: u64,
+    pub account: String,
+    pub amount: u64,
+    pub kind: FeeKind,
+}
+
+/// The fees of an account in one calendar month (UTC)
+#[derive(Debug, PartialEq, Eq, Clone, Serialize, Deserialize)]
+pub struct Invoice {
+    /// Stable across regenerations
+    pub id: String,
+    pub account: String,
+    /// `YYYY-MM`
+    pub month: String,
+    pub total: u64,
+    pub charges: Vec<FeeCharge>,
+}
+
+/// What one market traded in one day (UTC), busted trades excluded
+#[derive(Debug, PartialEq, Eq, Clone, Serialize, Deserialize)]
+pub struct MarketSummary {
+    pub market: String,
+    pub open: u64,
+    pub high: u64,
+    pub low: u64,
+    pub close: u64,
+    /// Units traded
+    pub volume: u64,
+    /// Price of the units traded
+    pub notional: u64,
+    pub trades: u64,
+    /// Change from the open to the close in basis points of the open
+    pub change_bps: i64,
+}
+
+/// The end-of-day report of all markets
+#[derive(Debug, PartialEq, Eq, Clone, Serialize, Deserialize)]
+pub struct DailyReport {
+    /// `YYYY-MM-DD`
+    pub day: String,
+    /// Markets that traded that day by symbol
+    pub markets: Vec<MarketSummary>,
+    /// Symbols of the markets with the largest price changes, largest first
+    pub top_movers: Vec<String>,
+    /// Fees charged that day
+    pub fees: u64,
+}
+
+/// What one account contributes to a market: its share of the book and of the turnover
+#[derive(Debug, Default, PartialEq, Eq, Clone, Serialize, Deserialize)]
+pub struct AccountConcentration {
+    pub signer: String,
+    /// Units of its orders resting on both sides of the book
+    pub resting: u64,
+    /// Its share of the units resting in the book, in basis points
+    pub book_share_bps: u64,
+    /// Units it bought or sold in the window, as taker or maker
+    pub volume: u64,
+    /// Price of the units it traded in the window
+    pub notional: u64,
+}
+
+/// Open interest, concentration, and turnover of one market
+#[derive(Debug, Default, PartialEq, Eq, Clone, Serialize, Deserialize)]
+pub struct MarketAnalytics {
+    pub market: String,
+    /// Units held in long positions, which equals the units owed by short positions, over all trades
+    pub open_interest: u64,
+    /// Units resting on both sides of the book
+    pub resting: u64,
+    /// The largest share of the book one account holds, in basis points
+    pub top_book_share_bps: u64,
+    /// Units traded in the window
+    pub turnover: u64,
+    /// Price of the units traded in the window
+    pub notional: u64,
+    pub trades: u64,
+    /// Every account with resting orders or trades in the window, the largest share of the book first
+    pub accounts: Vec<AccountConcentration>,
+}
+
+/// The representation of a document
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DocumentFormat {
+    #[default]
+    Json,
+    Csv,
+}
+
+/// Replaces the tags an account put on one of its transactions, no tags remove them
+#[derive(Debug, Default, PartialEq, Eq, Clone, Serialize, Deserialize)]
+pub struct TxTagsRequest {
+    pub tags: Vec<String>,
+}
+
+/// Narrows the transactions of an account's statement or export
+#[derive(Debug, Default, PartialEq, Eq, Clone, Serialize, Deserialize)]
+pub struct StatementQuery {
+    /// Only the transactions the account tagged with it
+    pub tag: Option<String>,
+}
+
+#[derive(Debug, Default, PartialEq, Eq, Clone, Serialize, Deserialize)]
+pub struct DocumentQuery {
+    #[serde(default)]
+    pub format: DocumentFormat,
+}
+
+/// A side of the order book as it's named in queries
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum BookSide {
+    Bid,
+    Ask,
+}
+
+impl From<BookSide> for Side {
+    fn from(side: BookSide) -> Self {
+        match side {
+            BookSide::Bid => Side::Buy,
+            BookSide::Ask => Side::Sell,
+        }
+    }
+}
+
+/// Narrows the order book down to one side, a price range, and the best price levels of each side
+#[derive(Debug, Default, PartialEq, Eq, Clone, Serialize, Deserialize)]
+pub struct BookQuery {
+    pub side: Option<BookSide>,
+    /// Price levels per side, best first
+    pub limit: Option<usize>,
+    pub min_price: Option<u64>,
+    pub max_price: Option<u64>,
+    /// The symbol of the market's book, [`DEFAULT_MARKET`] unless set
+    #[serde(alias = "symbol")]
+    pub market: Option<String>,
+}
+
+impl BookQuery {
+    /// Whether orders on `side` are included
+    pub fn includes(&self, side: &Side) -> bool {
+        self.side
+            .is_none_or(|included| Side::from(included) == *side)
+    }
+}
+
+/// Price levels per side of `GET /depth` unless asked for otherwise
+pub const DEFAULT_DEPTH_LEVELS: usize = 10;
+
+/// How many of the best price levels of each side `GET /depth` aggregates
+#[derive(Debug, Default, PartialEq, Eq, Clone, Serialize, Deserialize)]
+pub struct DepthQuery {
+    /// [`DEFAULT_DEPTH_LEVELS`] unless set
+    pub levels: Option<usize>,
+}
+
+#[derive(Debug, Default, PartialEq, Eq, Clone, Serialize, Deserialize)]
+pub struct PointInTimeQuery {
+    /// An ordinal or an RFC 3339 timestamp to look back to
+    pub at: Option<String>,
+}
+
+/// A match between an incoming (taker) order and a resting (maker) order
+#[derive(Debug, PartialEq, Eq, Clone, Serialize, Deserialize)]
+pub struct Trade {
+    /// Sequence number of the trade, referenced by its settlement transactions
+    pub id: u64,
+    /// The ordinal of the taker order
+    pub ordinal: u64,
+    pub maker_ordinal: u64,
+    /// Unix timestamp (ms)
+    pub timestamp: u64,
+    pub market: String,
+    pub price: u64,
+    pub amount: u64,
+    pub taker: String,
+    pub maker: String,
+    pub taker_side: Side,
+    /// The limit price of the taker order, `None` for market orders. Trades execute at the price of the maker order,
+    /// at or better than it.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub limit_price: Option<u64>,
+    /// The fee charged to the taker for the trade
+    #[serde(default)]
+    pub taker_fee: u64,
+    /// The fee charged to the maker for the trade
+    #[serde(default)]
+    pub maker_fee: u64,
+    /// When an operator busted the trade, Unix timestamp (ms). Busted trades stay on the tape, their settlement is
+    /// reversed.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub busted: Option<u64>,
+}
+
+/// Trades per page of `GET /trades` unless asked for otherwise
+pub const DEFAULT_TRADES_PAGE: usize = 100;
+/// The most trades in one page of `GET /trades`
+pub const MAX_TRADES_PAGE: usize = 1000;
+
+/// A page of the trade tape, oldest first
+#[derive(Debug, Default, PartialEq, Eq, Clone, Serialize, Deserialize)]
+pub struct TradesQuery {
+    /// Only trades of taker orders with a higher ordinal
+    pub since_ordinal: Option<u64>,
+    /// Continues after the trade with this id, the `next` of the previous page
+    pub after: Option<u64>,
+    /// [`DEFAULT_TRADES_PAGE`] unless set, [`MAX_TRADES_PAGE`] at most
+    pub limit: Option<usize>,
+}
+
+impl TradesQuery {
+    /// The number of trades to return
+    pub fn limit(&self) -> usize {
+        self.limit
+            .unwrap_or(DEFAULT_TRADES_PAGE)
+            .min(MAX_TRADES_PAGE)
+    }
+}
+
+/// A [`Trade`] on the tape, with its buyer and seller instead of the taker and maker
+#[derive(Debug, PartialEq, Eq, Clone, Serialize, Deserialize)]
+pub struct TapeTrade {
+    pub id: u64,
+    /// The ordinal of the taker order
+    pub ordinal: u64,
+    /// Unix timestamp (ms)
+    pub timestamp: u64,
+    pub market: String,
+    pub price: u64,
+    pub amount: u64,
+    pub buyer: String,
+    pub seller: String,
+    /// When an operator busted the trade, Unix timestamp (ms)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub busted: Option<u64>,
+}
+
+impl From<&Trade> for TapeTrade {
+    fn from(trade: &Trade) -> Self {
+        let (buyer, seller) = match trade.taker_side {
+            Side::Buy => (&trade.taker, &trade.maker),
+            Side::Sell => (&trade.maker, &trade.taker),
+        };
+        TapeTrade {
+            id: trade.id,
+            ordinal: trade.ordinal,
+            timestamp: trade.timestamp,
+            market: trade.market.clone(),
+            price: trade.price,
+            amount: trade.amount,
+            buyer: buyer.clone(),
+            seller: seller.clone(),
+            busted: trade.busted,
+        }
+    }
+}
+
+/// One page of the trade tape
+#[derive(Debug, PartialEq, Eq, Clone, Serialize, Deserialize)]
+pub struct TradesPage {
+    pub trades: Vec<TapeTrade>,
+    /// The `after` of the next page, `None` on the last page
+    pub next: Option<u64>,
+}
+
+/// Where an order is in its life after an [`OrderEvent`]
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum OrderState {
+    Accepted,
+    /// A trade reached the trigger price of a stop order, it went into the book
+    Triggered,
+    /// Its signer changed the price or the open amount, the amount is the new open amount
+    Amended,
+    PartiallyFilled,
+    Filled,
+    /// By its signer, a dead-man's switch, a stop-loss, or a new quote, or the rest of a market or
+    /// immediate-or-cancel order
+    Cancelled,
+    /// The expiry of a good 'til time order passed
+    Expired,
+}
+
+/// A match of an order
+#[derive(Debug, PartialEq, Eq, Clone, Serialize, Deserialize)]
+pub struct OrderFill {
+    pub trade_id: u64,
+    pub price: u64,
+    /// Whether the order took liquidity from the book, instead of resting in it
+    pub taker: bool,
+    /// Charged to the signer for the fill
+    pub fee: u64,
+    /// When an operator busted the trade, Unix timestamp (ms)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub busted: Option<u64>,
+}
+
+/// A step in the life of an order
+#[derive(Debug, PartialEq, Eq, Clone, Serialize, Deserialize)]
+pub struct OrderEvent {
+    /// Unix timestamp (ms)
+    pub timestamp: u64,
+    pub state: OrderState,
+    /// The units accepted, filled, or taken off the book
+    pub amount: u64,
+    /// The units still open after the event
+    pub remaining: u64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub fill: Option<OrderFill>,
+}
+
+/// What happened to an order from its acceptance on, see `GET /order/{id}/events`
+#[derive(Debug, PartialEq, Eq, Clone, Serialize, Deserialize)]
+pub struct OrderLifecycle {
+    pub order_id: OrderId,
+    /// The order as it was placed
+    pub order: Order,
+    pub events: Vec<OrderEvent>,
+}
+
+impl Trade {
+    /// Replaces `signer` with `token` as taker and maker
+    pub fn anonymize(&mut self, signer: &str, token: &str) {
+        anonymize(&mut self.taker, signer, token);
+        anonymize(&mut self.maker, signer, token);
+    }
+}
+
+/// The outcome of deleting an account
+#[derive(Debug, PartialEq, Eq, Clone, Serialize, Deserialize)]
+pub struct DeletedAccount {
+    /// Replaces the account's name throughout the stored history
+    pub token: String,
+    /// The balance that was withdrawn before the deletion
+    pub swept: u64,
+    /// The balance that was too small to withdraw and moved to the dust account instead
+    #[serde(default)]
+    pub dust: u64,
+}
+
+/// Opts an account in or out of having its dust swept
+#[derive(Debug, PartialEq, Eq, Clone, Serialize, Deserialize)]
+pub struct DustConsentRequest {
+    pub consent: bool,
+}
+
+/// Why a dust balance was moved to the dust account
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DustSource {
+    /// The scheduled sweep, the account consented
+    Sweep,
+    /// The account was deleted
+    Closure,
+}
+
+/// A dust balance moved to the dust account
+#[derive(Debug, PartialEq, Eq, Clone, Serialize, Deserialize)]
+pub struct DustSweep {
+    /// Unix timestamp (ms)
+    pub timestamp: u64,
+    pub account: String,
     pub amount: u64,
+    pub source: DustSource,
+}
+
+/// The dust collected so far
+#[derive(Debug, Default, PartialEq, Eq, Clone, Serialize, Deserialize)]
+pub struct DustReport {
+    /// Balances above 0 and below this are dust, 0 if nothing is
+    pub threshold: u64,
+    /// The accounts that consented to sweeps
+    pub consenting: u64,
+    /// The sum of all sweeps
+    pub total: u64,
+    /// Oldest first
+    pub sweeps: Vec<DustSweep>,
+}
+
+/// How a price level changed
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DeltaAction {
+    /// A new price level
+    Add,
+    /// The quantity of an existing price level changed
+    Amend,
+    /// The price level is gone
+    Remove,
+}
+
+/// A change to one price level of the order book
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BookDelta {
+    /// Increases by one with every delta
+    pub seq: u64,
+    pub side: Side,
+    pub price: u64,
+    /// The quantity at the price level after the change
+    pub quantity: u64,
+    pub action: DeltaAction,
+}
+
+/// The deltas after a sequence number
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BookUpdate {
+    /// The sequence number of the latest delta, to ask for the next update with
+    pub seq: u64,
+    pub deltas: Vec<BookDelta>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BookUpdatesQuery {
+    pub since_seq: u64,
+    /// How long to wait for new deltas
+    pub timeout_secs: Option<u64>,
+}
+
+/// The open quantity at a price
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PriceLevel {
+    pub price: u64,
+    pub quantity: u64,
+}
+
+/// The price levels of the order book as of a delta sequence number
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BookSnapshot {
+    pub seq: u64,
+    /// Highest price first
+    pub bids: Vec<PriceLevel>,
+    /// Lowest price first
+    pub asks: Vec<PriceLevel>,
+}
+
+impl BookSnapshot {
+    /// Applies a delta to keep a local copy of the book up to date
+    pub fn apply(&mut self, delta: &BookDelta) {
+        let levels = match delta.side {
+            Side::Buy => &mut self.bids,
+            Side::Sell => &mut self.asks,
+        };
+        levels.retain(|level| level.price != delta.price);
+        if delta.quantity > 0 {
+            levels.push(PriceLevel {
+                price: delta.price,
+                quantity: delta.quantity,
+            });
+        }
+        match delta.side {
+            Side::Buy => levels.sort_by_key(|level| std::cmp::Reverse(level.price)),
+            Side::Sell => levels.sort_by_key(|level| level.price),
+        }
+        self.seq = delta.seq;
+    }
+}
+
+/// Everything of a matching engine that outlives a restart: both sides of the book, the stops waiting for their
+/// trigger, the brackets, and the receipts issued so far
+#[derive(Debug, Default, PartialEq, Eq, Clone, Serialize, Deserialize)]
+pub struct OrderBookSnapshot {
+    /// The last ordinal the engine issued
+    pub ordinal: u64,
+    /// The resting orders by price, oldest first
+    pub bids: BTreeMap<u64, VecDeque<PartialOrder>>,
+    pub asks: BTreeMap<u64, VecDeque<PartialOrder>>,
+    /// Stop orders by id
+    #[serde(default)]
+    pub stops: BTreeMap<OrderId, Order>,
+    /// The other order of each bracket by order id, in both directions
+    #[serde(default)]
+    pub links: BTreeMap<OrderId, OrderId>,
+    /// The price of the latest match, stops trigger on it
+    #[serde(default)]
+    pub last_price: Option<u64>,
+    /// Whether a call auction was open
+    #[serde(default)]
+    pub auction: bool,
+    #[serde(default)]
+    pub history: Vec<Receipt>,
+}
+
+/// A message on the order book websocket
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum BookMessage {
+    /// Sent first, and again whenever the connection fell too far behind to catch up with deltas
+    Snapshot(BookSnapshot),
+    Delta(BookDelta),
+    /// Sent first if the market has one, and again whenever it changes
+    Reference(ReferencePrice),
+}
+
+/// Options of a trading session over the websocket
+#[derive(Debug, Default, PartialEq, Eq, Clone, Serialize, Deserialize)]
+pub struct TradingSessionQuery {
+    /// Cancel the open orders of the session's accounts when the connection drops, so no stale quotes stay behind
+    #[serde(default)]
+    pub cancel_on_disconnect: bool,
+}
+
+/// A request of the client in a trading session
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum SessionRequest {
+    Order(Order),
+}
+
+/// A reply to a [`SessionRequest`], in the order of the requests
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum SessionMessage {
+    Receipt(Receipt),
+    Rejected(ErrorResponse),
+}
+
+/// The account whose private channel to subscribe to
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AccountChannelQuery {
+    pub signer: String,
+}
+
+/// A message on the private channel of an account, only about the account's own orders and funds
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum AccountUpdate {
+    /// An order of the account was accepted, or taken off the book before it filled
+    Order {
+        signer: String,
+        state: OrderState,
+        order: PartialOrder,
+    },
+    /// An order of the account matched `amount` units at `price`
+    Fill {
+        signer: String,
+        order_id: OrderId,
+        market: String,
+        side: Side,
+        price: u64,
+        amount: u64,
+        /// Whether the order took liquidity from the book, instead of resting in it
+        taker: bool,
+    },
+    /// The balance of the account changed
+    Balance { signer: String, balance: u64 },
+}
+
+impl AccountUpdate {
+    /// The account the update is about
+    pub fn signer(&self) -> &str {
+        match self {
+            AccountUpdate::Order { signer, .. }
+            | AccountUpdate::Fill { signer, .. }
+            | AccountUpdate::Balance { signer, .. } => signer,
+        }
+    }
+
+    /// The scopes that each permit receiving the update. Trading keys hear about their orders, only reading keys
+    /// about the funds.
+    pub fn scopes(&self) -> &'static [ApiKeyScope] {
+        match self {
+            AccountUpdate::Order { .. } | AccountUpdate::Fill { .. } => {
+                &[ApiKeyScope::Read, ApiKeyScope::Trade]
+            }
+            AccountUpdate::Balance { .. } => &[ApiKeyScope::Read],
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BookDeltasQuery {
+    pub since_seq: u64,
 }
@@ -0,0 +1,78 @@
+//! Depth queries on the level aggregates against walking every resting order, as the engine did before it kept
+//! aggregates. Run with `cargo bench -p octopus-engine`.
+use std::collections::{BTreeMap, VecDeque};
+
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+use octopus_common::types::{
+    Order, OrderType, PartialOrder, PriceLevel, Side, TimeInForce, DEFAULT_MARKET,
+};
+use octopus_engine::MatchingEngine;
+
+const LEVELS: u64 = 1_000;
+const ORDERS_PER_LEVEL: u64 = 20;
+
+/// A book of [`LEVELS`] bid and ask levels with [`ORDERS_PER_LEVEL`] orders each
+fn deep_book() -> MatchingEngine {
+    let mut matching_engine = MatchingEngine::new();
+    for level in 0..LEVELS {
+        for i in 0..ORDERS_PER_LEVEL {
+            for (side, price) in [(Side::Buy, 10_000 - level), (Side::Sell, 10_001 + level)] {
+                let order = Order {
+                    price,
+                    amount: 1 + i % 5,
+                    side,
+                    signer: format!("TRADER{}", i),
+                    client_order_id: None,
+                    signature: None,
+                    order_type: OrderType::Limit,
+                    time_in_force: TimeInForce::Gtc,
+                    trigger_price: None,
+                    market: DEFAULT_MARKET.to_string(),
+                };
+                matching_engine.process(order).unwrap();
+            }
+        }
+    }
+    matching_engine
+}
+
+/// The levels of one side by summing the orders of every level
+fn walked_levels(book: &BTreeMap<u64, VecDeque<PartialOrder>>) -> Vec<PriceLevel> {
+    book.iter()
+        .map(|(price, orders)| PriceLevel {
+            price: *price,
+            quantity: orders.iter().map(|o| o.remaining).sum(),
+        })
+        .collect()
+}
+
+fn depth(c: &mut Criterion) {
+    let matching_engine = deep_book();
+    let mut group = c.benchmark_group("depth");
+    group.bench_function("aggregates", |b| {
+        b.iter(|| black_box(matching_engine.levels(&Side::Buy)))
+    });
+    group.bench_function("walk orders", |b| {
+        b.iter(|| black_box(walked_levels(&matching_engine.bids)))
+    });
+    group.finish();
+}
+
+fn best_bid_offer(c: &mut Criterion) {
+    let matching_engine = deep_book();
+    let mut group = c.benchmark_group("best bid offer");
+    group.bench_function("aggregates", |b| {
+        b.iter(|| black_box(matching_engine.best_bid_offer()))
+    });
+    group.bench_function("walk orders", |b| {
+        b.iter(|| {
+            let bid = walked_levels(&matching_engine.bids).pop();
+            let ask = walked_levels(&matching_engine.asks).into_iter().next();
+            black_box((bid, ask))
+        })
+    });
+    group.finish();
+}
+
+criterion_group!(benches, depth, best_bid_offer);
+criterion_main!(benches);
@@ -0,0 +1,158 @@
+//! Orders per second through the matching engine: small orders against a deep book, a random flow of orders from an
+//! empty book, and the worst cases of a single order sweeping a whole side of the book, past the orders of its own
+//! account too. Run with `cargo bench -p octopus-engine --bench matching`.
+use criterion::{black_box, criterion_group, criterion_main, BatchSize, Criterion, Throughput};
+use octopus_common::types::{Order, OrderType, Side, TimeInForce, DEFAULT_MARKET};
+use octopus_engine::MatchingEngine;
+
+const LEVELS: u64 = 1_000;
+const ORDERS_PER_LEVEL: u64 = 20;
+const FLOW: u64 = 10_000;
+const MID: u64 = 10_000;
+
+fn order(signer: &str, side: Side, price: u64, amount: u64, order_type: OrderType) -> Order {
+    Order {
+        price,
+        amount,
+        side,
+        signer: signer.to_string(),
+        client_order_id: None,
+        signature: None,
+        order_type,
+        time_in_force: TimeInForce::Gtc,
+        trigger_price: None,
+        market: DEFAULT_MARKET.to_string(),
+    }
+}
+
+/// A book of [`LEVELS`] bid and ask levels around [`MID`] with [`ORDERS_PER_LEVEL`] orders each, every fourth order
+/// of a level from `own`
+fn deep_book(own: &str) -> MatchingEngine {
+    let mut matching_engine = MatchingEngine::new();
+    for level in 0..LEVELS {
+        for i in 0..ORDERS_PER_LEVEL {
+            let signer = match i % 4 {
+                0 => own.to_string(),
+                _ => format!("TRADER{}", i),
+            };
+            for (side, price) in [(Side::Buy, MID - level), (Side::Sell, MID + 1 + level)] {
+                let order = order(&signer, side, price, 1 + i % 5, OrderType::Limit);
+                matching_engine.process(order).unwrap();
+            }
+        }
+    }
+    matching_engine
+}
+
+/// A xorshift generator, so every run sees the same flow without pulling in a random number crate
+struct Flow(u64);
+
+impl Flow {
+    fn next(&mut self, bound: u64) -> u64 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        self.0 % bound
+    }
+}
+
+/// [`FLOW`] orders of 20 accounts, mostly limit orders within 50 of [`MID`], some crossing, some at market
+fn random_flow() -> Vec<Order> {
+    let mut flow = Flow(0x2545_f491_4f6c_dd1d);
+    (0..FLOW)
+        .map(|_| {
+            let side = match flow.next(2) {
+                0 => Side::Buy,
+                _ => Side::Sell,
+            };
+            let order_type = match flow.next(20) {
+                0 => OrderType::Market,
+                _ => OrderType::Limit,
+            };
+            let signer = format!("TRADER{}", flow.next(20));
+            let price = MID - 50 + flow.next(100);
+            order(&signer, side, price, 1 + flow.next(10), order_type)
+        })
+        .collect()
+}
+
+fn deep_book_flow(c: &mut Criterion) {
+    // Small orders crossing the spread, alternating sides
+    let orders: Vec<Order> = (0..1_000)
+        .map(|i| match i % 2 {
+            0 => order("TAKER", Side::Buy, MID + 5, 1, OrderType::Limit),
+            _ => order("TAKER", Side::Sell, MID - 5, 1, OrderType::Limit),
+        })
+        .collect();
+    let mut group = c.benchmark_group("deep book");
+    group.sample_size(10);
+    group.throughput(Throughput::Elements(orders.len() as u64));
+    group.bench_function("small crossing orders", |b| {
+        b.iter_batched(
+            || (deep_book("OWNER"), orders.clone()),
+            |(mut matching_engine, orders)| {
+                for order in orders {
+                    black_box(matching_engine.process(order).unwrap());
+                }
+                // Dropped outside of the measurement
+                matching_engine
+            },
+            BatchSize::LargeInput,
+        )
+    });
+    group.finish();
+}
+
+fn random(c: &mut Criterion) {
+    let orders = random_flow();
+    let mut group = c.benchmark_group("random flow");
+    group.throughput(Throughput::Elements(orders.len() as u64));
+    group.bench_function("from an empty book", |b| {
+        b.iter_batched(
+            || orders.clone(),
+            |orders| {
+                let mut matching_engine = MatchingEngine::new();
+                for order in orders {
+                    black_box(matching_engine.process(order).unwrap());
+                }
+                matching_engine
+            },
+            BatchSize::LargeInput,
+        )
+    });
+    group.finish();
+}
+
+fn sweep(c: &mut Criterion) {
+    let mut group = c.benchmark_group("sweep");
+    group.sample_size(10);
+    group.throughput(Throughput::Elements(1));
+    // Every resting ask is filled by one market order
+    group.bench_function("whole side", |b| {
+        b.iter_batched(
+            || deep_book("OWNER"),
+            |mut matching_engine| {
+                let sweep = order("TAKER", Side::Buy, 0, u64::MAX / 2, OrderType::Market);
+                black_box(matching_engine.process(sweep).unwrap());
+                matching_engine
+            },
+            BatchSize::LargeInput,
+        )
+    });
+    // A quarter of the asks are of the same account and are passed over in place
+    group.bench_function("whole side past own orders", |b| {
+        b.iter_batched(
+            || deep_book("TAKER"),
+            |mut matching_engine| {
+                let sweep = order("TAKER", Side::Buy, 0, u64::MAX / 2, OrderType::Market);
+                black_box(matching_engine.process(sweep).unwrap());
+                matching_engine
+            },
+            BatchSize::LargeInput,
+        )
+    });
+    group.finish();
+}
+
+criterion_group!(benches, deep_book_flow, random, sweep);
+criterion_main!(benches);
@@ -0,0 +1,558 @@
+use std::{
+    collections::{BTreeMap, HashMap, VecDeque},
+    sync::Arc,
+};
+
+use chrono::DateTime;
+use octopus_common::{
+    errors::ApplicationError,
+    types::{anonymize, Order, OrderId, PartialOrder, SelfMatchPolicy},
+};
+
+use super::{
+    guard::RefusedStops,
+    ids::{IdGenerator, MonotonicIds},
+    matching::anonymize_book,
+    MatchingEngine,
+};
+
+/// The number of events between two snapshots
+pub const SNAPSHOT_INTERVAL: usize = 1000;
+
+/// A past state of the order book
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PointInTime {
+    /// Right after the order with this ordinal was processed
+    Ordinal(u64),
+    /// After everything that happened up to these unix milliseconds
+    Timestamp(u64),
+}
+
+impl PointInTime {
+    /// Parses an ordinal like `42` or an RFC 3339 timestamp like `2026-10-01T12:00:00Z`
+    /// # Errors
+    /// The value is neither
+    pub fn parse(value: &str) -> Result<Self, ApplicationError> {
+        if let Ok(ordinal) = value.parse() {
+            return Ok(PointInTime::Ordinal(ordinal));
+        }
+        DateTime::parse_from_rfc3339(value)
+            .ok()
+            .and_then(|time| u64::try_from(time.timestamp_millis()).ok())
+            .map(PointInTime::Timestamp)
+            .ok_or(ApplicationError::InvalidPointInTime(value.to_string()))
+    }
+}
+
+/// An input that changed the order book. Replaying all events in order on an empty [`MatchingEngine`] rebuilds the book.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum BookEvent {
+    /// An order was processed
+    Order { timestamp: u64, order: Order },
+    /// An open order was removed by its signer
+    Cancel {
+        timestamp: u64,
+        order_id: OrderId,
+        signer: String,
+    },
+    /// All open orders of an account were removed
+    CancelAll { timestamp: u64, signer: String },
+    /// The open orders of an account with these ids were removed in one step
+    CancelMany {
+        timestamp: u64,
+        signer: String,
+        order_ids: Vec<OrderId>,
+    },
+    /// The price and open amount of a resting order were changed
+    Amend {
+        timestamp: u64,
+        order_id: OrderId,
+        price: u64,
+        amount: u64,
+    },
+    /// The good 'til time orders that expired at `timestamp` were removed
+    Expire { timestamp: u64 },
+    /// A two-sided quote was processed, replacing the account's orders with the `replace` ids
+    Quote {
+        timestamp: u64,
+        bid: Order,
+        ask: Order,
+        replace: Vec<OrderId>,
+    },
+    /// A take-profit and a stop-loss were processed as a bracket, one cancels the other
+    Bracket {
+        timestamp: u64,
+        take_profit: Order,
+        stop_loss: Order,
+    },
+    /// A call auction was opened, orders rest without matching until it's run
+    AuctionStart { timestamp: u64 },
+    /// The call auction was run, crossing the book at one price
+    Auction { timestamp: u64 },
+}
+
+impl BookEvent {
+    pub fn timestamp(&self) -> u64 {
+        match self {
+            BookEvent::Order { timestamp, .. }
+            | BookEvent::Cancel { timestamp, .. }
+            | BookEvent::CancelAll { timestamp, .. }
+            | BookEvent::CancelMany { timestamp, .. }
+            | BookEvent::Amend { timestamp, .. }
+            | BookEvent::Expire { timestamp }
+            | BookEvent::Quote { timestamp, .. }
+            | BookEvent::Bracket { timestamp, .. }
+            | BookEvent::AuctionStart { timestamp }
+            | BookEvent::Auction { timestamp } => *timestamp,
+        }
+    }
+
+    fn apply(&self, matching_engine: &mut MatchingEngine) {
+        match self {
+            BookEvent::Order { order, .. } => {
+                // Processing never fails for orders that were processed before
+                let _ = matching_engine.process(order.clone());
+            }
+            BookEvent::Cancel {
+                order_id, signer, ..
+            } => {
+                // Cancelling never fails for orders that were cancelled before
+                let _ = matching_engine.cancel(*order_id, signer);
+            }
+            BookEvent::CancelAll { signer, .. } => {
+                matching_engine.cancel_all(signer);
+            }
+            BookEvent::CancelMany {
+                signer, order_ids, ..
+            } => {
+                matching_engine
+                    .cancel_where(|o| o.signer == *signer && order_ids.contains(&o.order_id));
+            }
+            BookEvent::Amend {
+                order_id,
+                price,
+                amount,
+                ..
+            } => {
+                let _ = matching_engine.amend(*order_id, *price, *amount);
+            }
+            BookEvent::Expire { timestamp } => {
+                matching_engine.expire(*timestamp);
+            }
+            BookEvent::Quote {
+                bid, ask, replace, ..
+            } => {
+                let _ = matching_engine.process_quote(bid.clone(), ask.clone(), replace);
+            }
+            BookEvent::Bracket {
+                take_profit,
+                stop_loss,
+                ..
+            } => {
+                let _ = matching_engine.process_bracket(take_profit.clone(), stop_loss.clone());
+            }
+            BookEvent::AuctionStart { .. } => matching_engine.start_auction(),
+            BookEvent::Auction { .. } => {
+                let _ = matching_engine.run_auction();
+            }
+        }
+    }
+}
+
+/// The book after a number of events
+#[derive(Debug, Clone)]
+struct Snapshot {
+    events: usize,
+    ordinal: u64,
+    bids: BTreeMap<u64, VecDeque<PartialOrder>>,
+    asks: BTreeMap<u64, VecDeque<PartialOrder>>,
+    stops: BTreeMap<OrderId, Order>,
+    last_price: Option<u64>,
+    links: HashMap<OrderId, OrderId>,
+    auction: bool,
+}
+
+/// Every [`BookEvent`] with periodic snapshots, so past books are rebuilt by replaying the events after the
+/// closest snapshot.
+#[derive(Debug)]
+pub struct EventLog {
+    /// The events and the engine's ordinal after each of them
+    events: Vec<(u64, BookEvent)>,
+    /// The index of the event that triggered each stop order
+    triggers: HashMap<OrderId, usize>,
+    /// The index of the event that cancelled each order because the other order of its bracket filled or triggered
+    unlinks: HashMap<OrderId, usize>,
+    /// The index of the event that cancelled each stop because the stop guard refused it, replays refuse it again
+    refusals: HashMap<OrderId, usize>,
+    /// The index of the auction that crossed each bid with an ask, by their ordinals
+    crossings: HashMap<(u64, u64), usize>,
+    snapshots: Vec<Snapshot>,
+    snapshot_interval: usize,
+    /// Issued the ordinals of the recorded engine, replays issue them again
+    ids: Arc<dyn IdGenerator>,
+    /// Resolved the self-matches of the recorded engine, replays resolve them the same way
+    self_match_policy: SelfMatchPolicy,
+}
+
+impl Default for EventLog {
+    fn default() -> Self {
+        EventLog::new(SNAPSHOT_INTERVAL)
+    }
+}
+
+impl EventLog {
+    pub fn new(snapshot_interval: usize) -> Self {
+        EventLog {
+            events: vec![],
+            triggers: HashMap::new(),
+            unlinks: HashMap::new(),
+            refusals: HashMap::new(),
+            crossings: HashMap::new(),
+            snapshots: vec![],
+            snapshot_interval: snapshot_interval.max(1),
+            ids: Arc::new(MonotonicIds),
+            self_match_policy: SelfMatchPolicy::default(),
+        }
+    }
+
+    /// Appends an event that was just applied to `matching_engine`
+    pub fn record(&mut self, event: BookEvent, matching_engine: &MatchingEngine) {
+        self.ids = matching_engine.ids.clone();
+        self.self_match_policy = matching_engine.self_match_policy;
+        if matches!(
+            event,
+            BookEvent::Order { .. }
+                | BookEvent::Quote { .. }
+                | BookEvent::Amend { .. }
+                | BookEvent::Bracket { .. }
+                | BookEvent::Auction { .. }
+        ) {
+            for stop in matching_engine.triggered() {
+                self.triggers
+                    .insert(stop.receipt.order_id, self.events.len());
+            }
+            for order in matching_engine.unlinked() {
+                self.unlinks.insert(order.order_id, self.events.len());
+            }
+            for stop in matching_engine.refused() {
+                self.refusals.insert(stop.order_id, self.events.len());
+            }
+        }
+        if let BookEvent::Auction { .. } = event {
+            for fill in matching_engine.crossed() {
+                for ask in fill.receipt.matches.iter() {
+                    self.crossings
+                        .insert((fill.receipt.ordinal, ask.ordinal), self.events.len());
+                }
+            }
+        }
+        self.events.push((matching_engine.ordinal, event));
+        if self.events.len().is_multiple_of(self.snapshot_interval) {
+            self.checkpoint(matching_engine);
+        }
+    }
+
+    /// Takes a snapshot of the book of `matching_engine` after the events recorded so far, e.g. when the book was
+    /// restored instead of built by the events. Past books are rebuilt from it onwards.
+    pub fn checkpoint(&mut self, matching_engine: &MatchingEngine) {
+        self.ids = matching_engine.ids.clone();
+        self.self_match_policy = matching_engine.self_match_policy;
+        self.snapshots.push(Snapshot {
+            events: self.events.len(),
+            ordinal: matching_engine.ordinal,
+            bids: matching_engine.bids.clone(),
+            asks: matching_engine.asks.clone(),
+            stops: matching_engine.stops.clone(),
+            last_price: matching_engine.last_price,
+            links: matching_engine.links.clone(),
+            auction: matching_engine.auction,
+        });
+    }
+
+    /// The book right after the order with `ordinal` was processed
+    pub fn book_at_ordinal(&self, ordinal: u64) -> MatchingEngine {
+        self.replay(self.events.partition_point(|(o, _)| *o <= ordinal))
+    }
+
+    /// The events in the order they happened, each with the engine's ordinal after it
+    pub fn events(&self) -> &[(u64, BookEvent)] {
+        &self.events
+    }
+
+    /// Where the order with `order_id` was placed: the index of its event and the order as it was placed. `None` for
+    /// orders that weren't placed in this book.
+    pub fn placement(&self, order_id: OrderId) -> Option<(usize, &Order)> {
+        let index = self
+            .events
+            .partition_point(|(ordinal, _)| *ordinal < order_id);
+        let (ordinal, event) = self.events.get(index)?;
+        let order = match event {
+            BookEvent::Order { order, .. } if *ordinal == order_id => order,
+            BookEvent::Quote { ask, .. } if *ordinal == order_id => ask,
+            // The bid of a quote is processed right before its ask
+            BookEvent::Quote { bid, .. } if self.ids.next_id(order_id) == *ordinal => bid,
+            BookEvent::Bracket { stop_loss, .. } if *ordinal == order_id => stop_loss,
+            // The take-profit of a bracket is processed right before its stop-loss
+            BookEvent::Bracket { take_profit, .. } if self.ids.next_id(order_id) == *ordinal => {
+                take_profit
+            }
+            _ => return None,
+        };
+        Some((index, order))
+    }
+
+    /// The index of the event that triggered the stop order with `order_id`, `None` for stops that are still waiting
+    /// and for other orders
+    pub fn triggered_at(&self, order_id: OrderId) -> Option<usize> {
+        self.triggers.get(&order_id).copied()
+    }
+
+    /// The index of the event that cancelled the order with `order_id` because the other order of its bracket filled or
+    /// triggered, `None` for other orders
+    pub fn unlinked_at(&self, order_id: OrderId) -> Option<usize> {
+        self.unlinks.get(&order_id).copied()
+    }
+
+    /// The index of the event that cancelled the stop with `order_id` because the stop guard refused it when it reached
+    /// its trigger price, `None` for other orders
+    pub fn refused_at(&self, order_id: OrderId) -> Option<usize> {
+        self.refusals.get(&order_id).copied()
+    }
+
+    /// The index of the auction that crossed the bid with `ordinal` with the ask with `maker_ordinal`, `None` if they
+    /// met outside of an auction
+    pub fn crossed_at(&self, ordinal: u64, maker_ordinal: u64) -> Option<usize> {
+        self.crossings.get(&(ordinal, maker_ordinal)).copied()
+    }
+
+    /// The book after all events up to and including `timestamp`
+    pub fn book_at_time(&self, timestamp: u64) -> MatchingEngine {
+        self.replay(
+            self.events
+                .partition_point(|(_, event)| event.timestamp() <= timestamp),
+        )
+    }
+
+    /// Replaces `signer` with `token` in every event and snapshot, so past books name the token instead
+    pub fn anonymize(&mut self, signer: &str, token: &str) {
+        for (_, event) in self.events.iter_mut() {
+            match event {
+                BookEvent::Order { order, .. } => anonymize(&mut order.signer, signer, token),
+                BookEvent::Cancel {
+                    signer: account, ..
+                }
+                | BookEvent::CancelAll {
+                    signer: account, ..
+                }
+                | BookEvent::CancelMany {
+                    signer: account, ..
+                } => anonymize(account, signer, token),
+                BookEvent::Amend { .. }
+                | BookEvent::Expire { .. }
+                | BookEvent::AuctionStart { .. }
+                | BookEvent::Auction { .. } => {}
+                BookEvent::Quote { bid, ask, .. } => {
+                    anonymize(&mut bid.signer, signer, token);
+                    anonymize(&mut ask.signer, signer, token);
+                }
+                BookEvent::Bracket {
+                    take_profit,
+                    stop_loss,
+                    ..
+                } => {
+                    anonymize(&mut take_profit.signer, signer, token);
+                    anonymize(&mut stop_loss.signer, signer, token);
+                }
+            }
+        }
+        for snapshot in self.snapshots.iter_mut() {
+            anonymize_book(&mut snapshot.bids, signer, token);
+            anonymize_book(&mut snapshot.asks, signer, token);
+            for stop in snapshot.stops.values_mut() {
+                anonymize(&mut stop.signer, signer, token);
+            }
+        }
+    }
+
+    /// Rebuilds the book after the first `count` events
+    fn replay(&self, count: usize) -> MatchingEngine {
+        let snapshot = self
+            .snapshots
+            .partition_point(|snapshot| snapshot.events <= count)
+            .checked_sub(1)
+            .map(|i| &self.snapshots[i]);
+        let (mut matching_engine, start) = match snapshot {
+            Some(snapshot) => {
+                let mut matching_engine = MatchingEngine::from_book(
+                    snapshot.ordinal,
+                    snapshot.bids.clone(),
+                    snapshot.asks.clone(),
+                );
+                matching_engine.stops = snapshot.stops.clone();
+                matching_engine.last_price = snapshot.last_price;
+                matching_engine.links = snapshot.links.clone();
+                matching_engine.auction = snapshot.auction;
+                (matching_engine, snapshot.events)
+            }
+            None => (MatchingEngine::new(), 0),
+        };
+        matching_engine.ids = self.ids.clone();
+        matching_engine.self_match_policy = self.self_match_policy;
+        if !self.refusals.is_empty() {
+            let refused = self.refusals.keys().copied().collect();
+            matching_engine.set_stop_guard(Some(Box::new(RefusedStops(refused))));
+        }
+        for (_, event) in &self.events[start..count] {
+            event.apply(&mut matching_engine);
+        }
+        matching_engine
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    // reduce the warnings for naming tests
+    #![allow(non_snake_case)]
+
+    use super::*;
+    use crate::StopGuard;
+    use octopus_common::types::{OrderType, PriceLevel, Side, TimeInForce, DEFAULT_MARKET};
+
+    fn order(price: u64, side: Side, signer: &str) -> Order {
+        Order {
+            price,
+            amount: 1,
+            side,
+            signer: signer.to_string(),
+            client_order_id: None,
+            signature: None,
+            order_type: OrderType::Limit,
+            time_in_force: TimeInForce::Gtc,
+            trigger_price: None,
+            market: DEFAULT_MARKET.to_string(),
+        }
+    }
+
+    /// Applies and logs an event
+    fn run(event: BookEvent, matching_engine: &mut MatchingEngine, event_log: &mut EventLog) {
+        event.apply(matching_engine);
+        event_log.record(event, matching_engine);
+    }
+
+    #[test]
+    fn test_EventLog_book_at_ordinal_and_time_match_live_book() {
+        let mut matching_engine = MatchingEngine::new();
+        let mut event_log = EventLog::new(3);
+        let mut books = vec![];
+        for i in 0..10u64 {
+            let event = if i == 6 {
+                BookEvent::CancelAll {
+                    timestamp: i * 10,
+                    signer: "ALICE".to_string(),
+                }
+            } else {
+                let side = if i % 2 == 0 { Side::Sell } else { Side::Buy };
+                let signer = if i % 3 == 0 { "ALICE" } else { "BOB" };
+                BookEvent::Order {
+                    timestamp: i * 10,
+                    order: order(10 + i % 4, side, signer),
+                }
+            };
+            run(event, &mut matching_engine, &mut event_log);
+            books.push((matching_engine.ordinal, matching_engine.orders()));
+        }
+        assert_eq!(event_log.events.len(), 10);
+        assert_eq!(event_log.snapshots.len(), 3);
+
+        for (i, (ordinal, book)) in books.iter().enumerate() {
+            assert_eq!(&event_log.book_at_time(i as u64 * 10).orders(), book);
+            if i != 6 {
+                assert_eq!(&event_log.book_at_ordinal(*ordinal).orders(), book);
+            }
+        }
+        assert!(event_log.book_at_ordinal(0).orders().is_empty());
+        assert_eq!(
+            event_log.book_at_time(1_000).orders(),
+            matching_engine.orders()
+        );
+    }
+
+    /// Refuses every stop
+    #[derive(Debug)]
+    struct RefuseAll;
+
+    impl StopGuard for RefuseAll {
+        fn admit(&mut self, _order_id: OrderId, _order: &Order, _fills: &[PriceLevel]) -> bool {
+            false
+        }
+    }
+
+    #[test]
+    fn test_EventLog_replay_refuses_the_refused_stops() {
+        let mut matching_engine = MatchingEngine::new();
+        let mut event_log = EventLog::new(100);
+        run(
+            BookEvent::Order {
+                timestamp: 1,
+                order: order(10, Side::Sell, "ALICE"),
+            },
+            &mut matching_engine,
+            &mut event_log,
+        );
+        run(
+            BookEvent::Order {
+                timestamp: 2,
+                order: order(10, Side::Sell, "ALICE"),
+            },
+            &mut matching_engine,
+            &mut event_log,
+        );
+        let stop = Order {
+            order_type: OrderType::Market,
+            trigger_price: Some(10),
+            ..order(0, Side::Buy, "CAROL")
+        };
+        run(
+            BookEvent::Order {
+                timestamp: 3,
+                order: stop,
+            },
+            &mut matching_engine,
+            &mut event_log,
+        );
+        let stop_id = matching_engine.ordinal;
+
+        matching_engine.set_stop_guard(Some(Box::new(RefuseAll)));
+        run(
+            BookEvent::Order {
+                timestamp: 4,
+                order: order(10, Side::Buy, "BOB"),
+            },
+            &mut matching_engine,
+            &mut event_log,
+        );
+        assert_eq!(event_log.refused_at(stop_id), Some(3));
+        assert_eq!(event_log.triggered_at(stop_id), None);
+
+        // Without the refusal the stop would have taken the second ask
+        let replayed = event_log.book_at_time(4);
+        assert_eq!(replayed.orders(), matching_engine.orders());
+        assert_eq!(replayed.level_quantity(&Side::Sell, 10), 1);
+        assert!(replayed.stops().is_empty());
+    }
+
+    #[test]
+    fn test_PointInTime_parse_ordinal_or_timestamp() {
+        assert_eq!(PointInTime::parse("42"), Ok(PointInTime::Ordinal(42)));
+        assert_eq!(
+            PointInTime::parse("2026-10-01T00:00:00.5Z"),
+            Ok(PointInTime::Timestamp(1_790_812_800_500))
+        );
+        assert_eq!(
+            PointInTime::parse("yesterday"),
+            Err(ApplicationError::InvalidPointInTime(
+                "yesterday".to_string()
+            ))
+        );
+    }
+}
@@ -0,0 +1,20 @@
+use std::{collections::HashSet, fmt};
+
+use octopus_common::types::{Order, OrderId, PriceLevel};
+
+/// Decides whether a stop order that reached its trigger price may execute, e.g. whether its signer can still pay for
+/// what it would fill at. Refused stops are cancelled instead, see [`crate::MatchingEngine::refused`].
+pub trait StopGuard: fmt::Debug + Send + Sync {
+    /// Whether the stop `order` with `order_id` may execute now, filling at the price levels of `fills`
+    fn admit(&mut self, order_id: OrderId, order: &Order, fills: &[PriceLevel]) -> bool;
+}
+
+/// Refuses the stops a recorded engine refused, so replays cancel them at the same event
+#[derive(Debug, Default)]
+pub(crate) struct RefusedStops(pub(crate) HashSet<OrderId>);
+
+impl StopGuard for RefusedStops {
+    fn admit(&mut self, order_id: OrderId, _order: &Order, _fills: &[PriceLevel]) -> bool {
+        !self.0.contains(&order_id)
+    }
+}
@@ -0,0 +1,139 @@
+//! How orders, trades, holds, and withdrawals are numbered. Every [`IdGenerator`] issues growing identifiers, since
+//! the ordinals of the book are its time priority.
+//!
+//! Replaying the book event log issues the ordinals again, so an identifier may only depend on the one before it.
+use std::fmt::Debug;
+
+/// Issues the identifier after the last one
+pub trait IdGenerator: Debug + Send + Sync {
+    /// The identifier after `last`, always greater than it
+    fn next_id(&self, last: u64) -> u64;
+
+    /// The identifier `n` identifiers after `last`, `last` itself for `n = 0`
+    fn nth_after(&self, last: u64, n: u64) -> u64 {
+        (0..n).fold(last, |id, _| self.next_id(id))
+    }
+}
+
+/// Counts up by one, the identifiers of a single server
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct MonotonicIds;
+
+impl IdGenerator for MonotonicIds {
+    fn next_id(&self, last: u64) -> u64 {
+        last + 1
+    }
+
+    fn nth_after(&self, last: u64, n: u64) -> u64 {
+        last + n
+    }
+}
+
+/// The number of bits of a [`SnowflakeIds`] node
+pub const NODE_BITS: u32 = 10;
+
+/// The number of bits of a [`SnowflakeIds`] sequence. Node and sequence stay below 2^53, so JavaScript clients read
+/// the identifiers exactly.
+pub const SEQUENCE_BITS: u32 = 42;
+
+const SEQUENCE_MASK: u64 = (1 << SEQUENCE_BITS) - 1;
+
+/// Node-prefixed identifiers like Snowflake's: the node above a sequence, so servers sharing a marketplace never issue
+/// the same identifier. Unlike Snowflake's they carry no timestamp, replays have to issue them again.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SnowflakeIds {
+    node: u64,
+}
+
+impl SnowflakeIds {
+    /// The identifiers of `node`, `None` if it doesn't fit in [`NODE_BITS`]
+    pub fn new(node: u16) -> Option<Self> {
+        (u64::from(node) < 1 << NODE_BITS).then_some(SnowflakeIds {
+            node: u64::from(node),
+        })
+    }
+
+    /// The node that issued `id`
+    pub fn node_of(id: u64) -> u16 {
+        (id >> SEQUENCE_BITS) as u16
+    }
+}
+
+impl IdGenerator for SnowflakeIds {
+    fn next_id(&self, last: u64) -> u64 {
+        // Identifiers issued before the node was set, e.g. by counters, continue within the node
+        let next = (self.node << SEQUENCE_BITS) | ((last & SEQUENCE_MASK) + 1);
+        next.max(last + 1)
+    }
+}
+
+/// The largest step between two [`DeterministicIds`]
+pub const MAX_STEP: u64 = 16;
+
+/// Sparse identifiers for simulations: each is 1 to [`MAX_STEP`] after the last one, the step drawn from a hash of
+/// the seed and the last identifier. A seed issues the same identifiers in every run, and code that counts on
+/// consecutive identifiers stands out.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DeterministicIds {
+    pub seed: u64,
+}
+
+impl IdGenerator for DeterministicIds {
+    fn next_id(&self, last: u64) -> u64 {
+        last + 1 + mix(self.seed ^ last) % MAX_STEP
+    }
+}
+
+/// The SplitMix64 finalizer, spreads every bit of `value` over the result
+fn mix(value: u64) -> u64 {
+    let mut z = value.wrapping_add(0x9e37_79b9_7f4a_7c15);
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    z ^ (z >> 31)
+}
+
+#[cfg(test)]
+mod tests {
+    // reduce the warnings for naming tests
+    #![allow(non_snake_case)]
+
+    use super::*;
+
+    #[test]
+    fn test_IdGenerator_next_id_grows_for_every_strategy() {
+        let snowflake = SnowflakeIds::new(3).unwrap();
+        assert_eq!(SnowflakeIds::new(1 << NODE_BITS), None);
+        let first = snowflake.next_id(0);
+        assert_eq!(
+            (SnowflakeIds::node_of(first), first & SEQUENCE_MASK),
+            (3, 1)
+        );
+        // Counter identifiers from before continue within the node
+        assert_eq!(snowflake.next_id(41), (3 << SEQUENCE_BITS) | 42);
+        assert!(snowflake.nth_after(0, 1_000) < 1 << 53);
+
+        let deterministic = DeterministicIds { seed: 7 };
+        let run = |generator: &DeterministicIds| {
+            (0..100)
+                .scan(0, |id, _| {
+                    *id = generator.next_id(*id);
+                    Some(*id)
+                })
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(run(&deterministic), run(&DeterministicIds { seed: 7 }));
+        assert_ne!(run(&deterministic), run(&DeterministicIds { seed: 8 }));
+        assert_eq!(deterministic.nth_after(0, 100), run(&deterministic)[99]);
+
+        let generators: [&dyn IdGenerator; 3] = [&MonotonicIds, &snowflake, &deterministic];
+        for generator in generators {
+            let mut last = 0;
+            for _ in 0..1_000 {
+                let next = generator.next_id(last);
+                assert!(next > last);
+                last = next;
+            }
+        }
+        assert_eq!(MonotonicIds.nth_after(5, 3), 8);
+    }
+}
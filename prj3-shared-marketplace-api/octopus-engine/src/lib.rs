@@ -0,0 +1,16 @@
+//! The matching engine of the marketplace: price-time priority matching and the book event log.
+mod events;
+mod guard;
+mod ids;
+mod matching;
+mod observer;
+mod pool;
+mod sink;
+
+pub use events::{BookEvent, EventLog, PointInTime};
+pub use guard::StopGuard;
+pub use ids::{DeterministicIds, IdGenerator, MonotonicIds, SnowflakeIds, NODE_BITS};
+pub use matching::{LevelAggregate, MatchingEngine, TriggeredStop};
+pub use observer::EngineObserver;
+pub use pool::{BookCapacity, PoolStats};
+pub use sink::{EngineEvent, EventSink};
@@ -0,0 +1,3432 @@
+use std::{
+    collections::{btree_map, BTreeMap, HashMap, VecDeque},
+    sync::Arc,
+};
+
+use octopus_common::{
+    errors::ApplicationError,
+    types::{
+        anonymize, AuctionFill, AuctionReceipt, BookQuery, BracketReceipt, Order,
+        OrderBookSnapshot, OrderId, OrderType, PartialOrder, PriceLevel, QueuePosition,
+        QuoteReceipt, Receipt, ReceiptStatus, SelfMatch, SelfMatchPolicy, Side, TimeInForce,
+    },
+};
+
+use crate::guard::StopGuard;
+use crate::ids::{IdGenerator, MonotonicIds};
+use crate::observer::{EngineObserver, Observers};
+use crate::pool::{BookCapacity, LevelPool, PoolStats};
+use crate::sink::{EventSink, SinkObserver};
+
+/// The price levels of one side of a book within the price range of `query`, in ascending price order
+fn price_range<'a>(
+    book: &'a BTreeMap<u64, VecDeque<PartialOrder>>,
+    query: &BookQuery,
+) -> btree_map::Range<'a, u64, VecDeque<PartialOrder>> {
+    let min = query.min_price.unwrap_or(u64::MIN);
+    let max = query.max_price.unwrap_or(u64::MAX);
+    if min > max {
+        // `BTreeMap::range` panics on inverted ranges
+        return book.range(0..0);
+    }
+    book.range(min..=max)
+}
+
+/// The open quantity and number of orders of a price level, kept up to date as orders rest, match, and cancel so
+/// depth queries don't walk the orders
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct LevelAggregate {
+    pub quantity: u64,
+    pub orders: usize,
+}
+
+/// The aggregates of one side of a book by price
+type Aggregates = BTreeMap<u64, LevelAggregate>;
+
+/// The aggregates of one side of a book within the price range of `query`, in ascending price order
+fn aggregate_range<'a>(
+    levels: &'a Aggregates,
+    query: &BookQuery,
+) -> btree_map::Range<'a, u64, LevelAggregate> {
+    let min = query.min_price.unwrap_or(u64::MIN);
+    let max = query.max_price.unwrap_or(u64::MAX);
+    if min > max {
+        return levels.range(0..0);
+    }
+    levels.range(min..=max)
+}
+
+fn level((price, aggregate): (&u64, &LevelAggregate)) -> PriceLevel {
+    PriceLevel {
+        price: *price,
+        quantity: aggregate.quantity,
+    }
+}
+
+/// Adds a resting order to the aggregate of its level
+fn add_resting(levels: &mut Aggregates, order: &PartialOrder) {
+    let aggregate = levels.entry(order.price).or_default();
+    aggregate.quantity += order.remaining;
+    aggregate.orders += 1;
+}
+
+/// Takes `quantity` off the aggregate at `price`, and the order with it if it left the book
+fn take_resting(levels: &mut Aggregates, price: u64, quantity: u64, removed: bool) {
+    if let btree_map::Entry::Occupied(mut entry) = levels.entry(price) {
+        let aggregate = entry.get_mut();
+        aggregate.quantity -= quantity;
+        if removed {
+            aggregate.orders -= 1;
+        }
+        if aggregate.orders == 0 {
+            entry.remove();
+        }
+    }
+}
+
+/// The aggregates of every level of one side of a book
+fn aggregate(book: &BTreeMap<u64, VecDeque<PartialOrder>>) -> Aggregates {
+    let mut levels = Aggregates::new();
+    for order in book.values().flatten() {
+        add_resting(&mut levels, order);
+    }
+    levels
+}
+
+/// The side and price level of every resting order of a book by id
+fn index(
+    bids: &BTreeMap<u64, VecDeque<PartialOrder>>,
+    asks: &BTreeMap<u64, VecDeque<PartialOrder>>,
+) -> HashMap<OrderId, (Side, u64)> {
+    bids.values()
+        .chain(asks.values())
+        .flatten()
+        .map(|o| (o.order_id, (o.side.clone(), o.price)))
+        .collect()
+}
+
+/// Replaces `signer` with `token` in one side of a book
+pub(crate) fn anonymize_book(
+    book: &mut BTreeMap<u64, VecDeque<PartialOrder>>,
+    signer: &str,
+    token: &str,
+) {
+    for order in book.values_mut().flatten() {
+        anonymize(&mut order.signer, signer, token);
+    }
+}
+
+/// Removes the emptied levels of one side of a book, keeping them in `pool`
+fn prune(book: &mut BTreeMap<u64, VecDeque<PartialOrder>>, pool: &mut LevelPool) {
+    book.retain(|_, orders| match orders.is_empty() {
+        true => {
+            pool.give(std::mem::take(orders));
+            false
+        }
+        false => true,
+    });
+}
+
+/// A stop order that reached its trigger price and was processed like a regular order of the same type
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TriggeredStop {
+    /// The stop order as it was placed
+    pub order: Order,
+    /// What processing it did, under the ordinal it was placed with
+    pub receipt: Receipt,
+}
+
+#[derive(Debug)]
+pub struct MatchingEngine {
+    /// The last sequence number
+    pub ordinal: u64,
+    /// Issues the ordinals
+    pub(crate) ids: Arc<dyn IdGenerator>,
+
+    /// The "Bid" or "Buy" side of the order book. Ordered by ordinal number. Only change it through the engine, the
+    /// level aggregates are kept alongside.
+    pub bids: BTreeMap<u64, VecDeque<PartialOrder>>,
+    /// The "Ask" or "Sell" side of the order book. Ordered by ordinal number. Only change it through the engine.
+    pub asks: BTreeMap<u64, VecDeque<PartialOrder>>,
+    /// Previous matches for record keeping
+    pub history: Vec<Receipt>,
+    bid_levels: Aggregates,
+    ask_levels: Aggregates,
+    /// Where each resting order is, kept alongside the books
+    index: HashMap<OrderId, (Side, u64)>,
+    /// Stop orders waiting for their trigger price by id, outside the book
+    pub(crate) stops: BTreeMap<OrderId, Order>,
+    /// The price of the latest match, stops trigger on it
+    pub(crate) last_price: Option<u64>,
+    /// The stops the latest order, quote, or amendment triggered
+    triggered: Vec<TriggeredStop>,
+    observers: Observers,
+    /// Emptied price levels, reused for the next ones
+    pool: LevelPool,
+    /// How orders meeting resting orders of the same account are resolved
+    pub(crate) self_match_policy: SelfMatchPolicy,
+    /// The other order of each bracket by order id, in both directions
+    pub(crate) links: HashMap<OrderId, OrderId>,
+    /// The orders the latest operation cancelled because the other order of their bracket filled or triggered
+    unlinked: Vec<PartialOrder>,
+    /// Decides whether the stops that reach their trigger price may execute, all of them may without one
+    stop_guard: Option<Box<dyn StopGuard>>,
+    /// The stops the latest operation cancelled because the guard refused them
+    refused: Vec<PartialOrder>,
+    /// Whether a call auction is open, orders rest without matching until it's run
+    pub(crate) auction: bool,
+    /// The bids the latest auction crossed with the asks they met
+    crossed: Vec<AuctionFill>,
+}
+
+impl Default for MatchingEngine {
+    fn default() -> Self {
+        MatchingEngine::new()
+    }
+}
+
+impl MatchingEngine {
+    /// Creates a new [`MatchingEngine`] with an ordinal of 0 and empty books
+    pub fn new() -> Self {
+        MatchingEngine::with_ids(Arc::new(MonotonicIds))
+    }
+
+    /// Like [`MatchingEngine::new`], but the ordinals are issued by `ids`
+    pub fn with_ids(ids: Arc<dyn IdGenerator>) -> Self {
+        MatchingEngine {
+            ordinal: 0,
+            ids,
+            bids: BTreeMap::new(),
+            asks: BTreeMap::new(),
+            history: Vec::new(),
+            bid_levels: Aggregates::new(),
+            ask_levels: Aggregates::new(),
+            index: HashMap::new(),
+            stops: BTreeMap::new(),
+            last_price: None,
+            triggered: vec![],
+            observers: Observers::default(),
+            pool: LevelPool::default(),
+            self_match_policy: SelfMatchPolicy::default(),
+            links: HashMap::new(),
+            unlinked: vec![],
+            stop_guard: None,
+            refused: vec![],
+            auction: false,
+            crossed: vec![],
+        }
+    }
+
+    /// An engine continuing after `ordinal` with the given books, e.g. from a snapshot
+    pub fn from_book(
+        ordinal: u64,
+        bids: BTreeMap<u64, VecDeque<PartialOrder>>,
+        asks: BTreeMap<u64, VecDeque<PartialOrder>>,
+    ) -> Self {
+        MatchingEngine {
+            ordinal,
+            bid_levels: aggregate(&bids),
+            ask_levels: aggregate(&asks),
+            index: index(&bids, &asks),
+            bids,
+            asks,
+            ..MatchingEngine::new()
+        }
+    }
+
+    /// Captures the book, the stops, the brackets, and the history, see [`MatchingEngine::restore`]
+    pub fn snapshot(&self) -> OrderBookSnapshot {
+        OrderBookSnapshot {
+            ordinal: self.ordinal,
+            bids: self.bids.clone(),
+            asks: self.asks.clone(),
+            stops: self.stops.clone(),
+            links: self.links.iter().map(|(a, b)| (*a, *b)).collect(),
+            last_price: self.last_price,
+            auction: self.auction,
+            history: self.history.clone(),
+        }
+    }
+
+    /// Replaces the book, the stops, the brackets, and the history with the ones of `snapshot`. The ids, the
+    /// observers, and the self-match policy stay, the ordinals continue after the later of the engine's and the
+    /// snapshot's. Observers aren't told about the restored orders.
+    pub fn restore(&mut self, snapshot: OrderBookSnapshot) {
+        self.ordinal = self.ordinal.max(snapshot.ordinal);
+        self.bid_levels = aggregate(&snapshot.bids);
+        self.ask_levels = aggregate(&snapshot.asks);
+        self.index = index(&snapshot.bids, &snapshot.asks);
+        self.bids = snapshot.bids;
+        self.asks = snapshot.asks;
+        self.stops = snapshot.stops;
+        self.links = snapshot.links.into_iter().collect();
+        self.last_price = snapshot.last_price;
+        self.auction = snapshot.auction;
+        self.history = snapshot.history;
+        self.triggered.clear();
+        self.unlinked.clear();
+        self.refused.clear();
+        self.crossed.clear();
+    }
+
+    /// Allocates the price levels and the order index of a book of `capacity` up front. Levels emptied later are kept
+    /// for reuse, up to the preallocated number.
+    pub fn preallocate(&mut self, capacity: BookCapacity) {
+        self.pool.preallocate(capacity);
+        self.index
+            .reserve(2 * capacity.levels * capacity.orders_per_level);
+    }
+
+    /// Resolves the self-matches of the orders processed from now on with `policy`
+    pub fn set_self_match_policy(&mut self, policy: SelfMatchPolicy) {
+        self.self_match_policy = policy;
+    }
+
+    pub fn self_match_policy(&self) -> SelfMatchPolicy {
+        self.self_match_policy
+    }
+
+    /// How the price levels of the book were allocated
+    pub fn pool_stats(&self) -> PoolStats {
+        self.pool.stats()
+    }
+
+    /// Notifies `observer` of everything this engine does from now on. Books rebuilt from an [`crate::EventLog`]
+    /// start without observers.
+    pub fn register_observer(&mut self, observer: Box<dyn EngineObserver>) {
+        self.observers.register(observer);
+    }
+
+    /// Lets the stops that reach their trigger price from now on execute only if `guard` admits them, `None` admits all
+    pub fn set_stop_guard(&mut self, guard: Option<Box<dyn StopGuard>>) {
+        self.stop_guard = guard;
+    }
+
+    /// Sends the [`crate::EngineEvent`]s of the book to `sink`, see [`MatchingEngine::register_observer`]
+    pub fn register_sink(&mut self, sink: Box<dyn EventSink>) {
+        self.observers.register(Box::new(SinkObserver(sink)));
+    }
+
+    /// Processes an [`Order`] and returns a [`Receipt`]
+    /// This includes matching the order to whatever is in the current books and adding the remainder (if any) to the book for future matching.
+    /// The remainder of a market or immediate-or-cancel order is dropped instead and reported as cancelled.
+    ///
+    /// A stop order is set aside with an empty receipt until a match reaches its trigger price. The stops triggered
+    /// by the order's matches are processed right after it, see [`MatchingEngine::triggered`].
+    pub fn process(&mut self, order: Order) -> Result<Receipt, ApplicationError> {
+        self.triggered.clear();
+        self.unlinked.clear();
+        self.refused.clear();
+        let receipt = self.place(order)?;
+        self.trigger_stops()?;
+        Ok(receipt)
+    }
+
+    /// Issues the next ordinal for `order` and matches it, or sets it aside if it's a stop order
+    /// # Errors
+    /// A call auction is open and the order is a market or immediate-or-cancel order, which can't rest until it's run
+    fn place(&mut self, order: Order) -> Result<Receipt, ApplicationError> {
+        let rests = order.order_type == OrderType::Limit && order.time_in_force != TimeInForce::Ioc;
+        if self.auction && order.trigger_price.is_none() && !rests {
+            return Err(ApplicationError::AuctionInProgress(order.market));
+        }
+        // Issue the next ordinal number for this order
+        self.ordinal = self.ids.next_id(self.ordinal);
+        let ordinal = self.ordinal;
+        if order.trigger_price.is_some() {
+            let (market, remaining) = (order.market.clone(), order.amount);
+            self.stops.insert(ordinal, order);
+            return Ok(Receipt {
+                ordinal,
+                order_id: ordinal,
+                matches: vec![],
+                filled: 0,
+                cancelled: 0,
+                limit_price: None,
+                market,
+                self_matches: vec![],
+                settlement: None,
+                remaining,
+                total_notional: 0,
+                average_price: None,
+                status: ReceiptStatus::Resting,
+            });
+        }
+        self.execute(order, ordinal, ordinal)
+    }
+
+    /// Processes the stops the last price reached in the order they were placed, each under its own ordinal. Their
+    /// matches move the price and may trigger more stops. Stops the guard refuses are cancelled without touching their
+    /// bracket's other order.
+    fn trigger_stops(&mut self) -> Result<(), ApplicationError> {
+        while let Some(order_id) = self
+            .stops
+            .iter()
+            .find(|(_, stop)| self.reached(stop))
+            .map(|(order_id, _)| *order_id)
+        {
+            let stop = self.stops.remove(&order_id).expect("found above");
+            let order = Order {
+                trigger_price: None,
+                ..stop.clone()
+            };
+            let fills = self.fills_of(&order);
+            if let Some(guard) = self.stop_guard.as_mut() {
+                if !guard.admit(order_id, &stop, &fills) {
+                    self.unlink(order_id);
+                    let amount = stop.amount;
+                    self.refused.push(stop.into_partial_order(order_id, amount));
+                    continue;
+                }
+            }
+            self.cancel_linked(order_id);
+            let receipt = self.execute(order, order_id, order_id)?;
+            self.triggered.push(TriggeredStop {
+                order: stop,
+                receipt,
+            });
+        }
+        Ok(())
+    }
+
+    /// Whether the last price reached the trigger price of `stop`
+    fn reached(&self, stop: &Order) -> bool {
+        match (self.last_price, stop.trigger_price, &stop.side) {
+            (Some(price), Some(trigger), Side::Buy) => price >= trigger,
+            (Some(price), Some(trigger), Side::Sell) => price <= trigger,
+            _ => false,
+        }
+    }
+
+    /// The stops the latest order, quote, or amendment triggered, in the order they were processed. Their matches aren't in the
+    /// receipt of the order that triggered them.
+    pub fn triggered(&self) -> &[TriggeredStop] {
+        &self.triggered
+    }
+
+    /// The stops the latest order, quote, bracket, amendment, or auction cancelled because the stop guard refused them
+    /// when they reached their trigger price, in the order they were refused
+    pub fn refused(&self) -> &[PartialOrder] {
+        &self.refused
+    }
+
+    /// The orders the latest order, quote, bracket, or amendment cancelled because the other order of their bracket
+    /// filled or triggered, in the order they were cancelled
+    pub fn unlinked(&self) -> &[PartialOrder] {
+        &self.unlinked
+    }
+
+    /// The other order of the bracket the order with `order_id` belongs to, `None` once either of them filled,
+    /// triggered, or was cancelled
+    pub fn linked(&self, order_id: OrderId) -> Option<OrderId> {
+        self.links.get(&order_id).copied()
+    }
+
+    /// Forgets the bracket of the order with `order_id`, the other order of it stays on its own
+    fn unlink(&mut self, order_id: OrderId) {
+        if let Some(other) = self.links.remove(&order_id) {
+            self.links.remove(&other);
+        }
+    }
+
+    /// Forgets the bracket of the order with `order_id` and cancels the other order of it, if it's still open
+    fn cancel_linked(&mut self, order_id: OrderId) {
+        let Some(other) = self.links.remove(&order_id) else {
+            return;
+        };
+        self.links.remove(&other);
+        let signer = match (self.stops.get(&other), self.order(other)) {
+            (Some(stop), _) => stop.signer.clone(),
+            (None, Some(order)) => order.signer.clone(),
+            (None, None) => return,
+        };
+        if let Ok(cancelled) = self.cancel(other, &signer) {
+            self.unlinked.push(cancelled);
+        }
+    }
+
+    /// Opens a call auction: orders rest without matching until [`MatchingEngine::run_auction`] crosses them. Market and
+    /// immediate-or-cancel orders are rejected meanwhile.
+    pub fn start_auction(&mut self) {
+        self.auction = true;
+    }
+
+    /// The bids the latest auction crossed with the asks they met, see [`MatchingEngine::run_auction`]
+    pub fn crossed(&self) -> &[AuctionFill] {
+        &self.crossed
+    }
+
+    /// Whether a call auction is open
+    pub fn in_auction(&self) -> bool {
+        self.auction
+    }
+
+    /// Closes the call auction and crosses the book at the single price that executes the most volume. Of equal
+    /// volumes the price leaving the smallest surplus wins, then the one closest to the last price, then the lower one.
+    ///
+    /// Bids and asks are filled in price-time priority, every match at the clearing price, so one side of the
+    /// crossing orders fills completely and the book is left uncrossed. The auction has no incoming order, the bids
+    /// are reported as takers and self-matches aren't prevented. The stops the clearing price reached are triggered
+    /// afterwards.
+    pub fn run_auction(&mut self) -> Result<AuctionReceipt, ApplicationError> {
+        self.auction = false;
+        self.triggered.clear();
+        self.unlinked.clear();
+        self.refused.clear();
+        self.crossed.clear();
+        let Some((price, volume)) = self.clearing_price() else {
+            return Ok(AuctionReceipt::default());
+        };
+
+        // The parts of the asks that cross, best first
+        let mut asks: VecDeque<PartialOrder> = VecDeque::new();
+        let mut left = volume;
+        for (level, orders) in self.asks.range_mut(..=price) {
+            for order in orders.iter_mut() {
+                if left == 0 {
+                    break;
+                }
+                let take = order.remaining.min(left);
+                left -= take;
+                take_resting(&mut self.ask_levels, *level, take, order.remaining == take);
+                asks.push_back(PartialOrder::take_from(order, take, price));
+            }
+        }
+        let mut crossed: Vec<(PartialOrder, Receipt)> = vec![];
+        let mut left = volume;
+        for (level, orders) in self.bids.range_mut(price..).rev() {
+            for order in orders.iter_mut() {
+                if left == 0 {
+                    break;
+                }
+                let take = order.remaining.min(left);
+                left -= take;
+                take_resting(&mut self.bid_levels, *level, take, order.remaining == take);
+                let bid = PartialOrder::take_from(order, take, price);
+                let mut matches = vec![];
+                let mut needed = take;
+                while needed > 0 {
+                    let ask = asks.front_mut().expect("both sides cross the same volume");
+                    let amount = ask.amount.min(needed);
+                    matches.push(PartialOrder {
+                        amount,
+                        ..ask.clone()
+                    });
+                    needed -= amount;
+                    ask.amount -= amount;
+                    if ask.amount == 0 {
+                        asks.pop_front();
+                    }
+                }
+                let mut receipt = Receipt {
+                    ordinal: bid.ordinal,
+                    order_id: bid.order_id,
+                    matches,
+                    filled: take,
+                    cancelled: 0,
+                    limit_price: None,
+                    market: bid.market.clone(),
+                    self_matches: vec![],
+                    settlement: None,
+                    remaining: 0,
+                    total_notional: 0,
+                    average_price: None,
+                    status: ReceiptStatus::Resting,
+                };
+                receipt.total(order.remaining);
+                crossed.push((bid, receipt));
+            }
+        }
+
+        let mut changed: Vec<(Side, u64)> = vec![];
+        for (side, book) in [(Side::Buy, &mut self.bids), (Side::Sell, &mut self.asks)] {
+            for (level, orders) in book.iter_mut() {
+                if orders.iter().any(|o| o.remaining == 0) {
+                    changed.push((side.clone(), *level));
+                }
+                for filled in orders.iter().filter(|o| o.remaining == 0) {
+                    self.index.remove(&filled.order_id);
+                }
+                orders.retain(|o| o.remaining > 0);
+            }
+            prune(book, &mut self.pool);
+        }
+        // Partially filled orders keep their level
+        for (bid, receipt) in crossed.iter() {
+            for (side, order_id) in receipt
+                .matches
+                .iter()
+                .map(|m| (Side::Sell, m.order_id))
+                .chain([(Side::Buy, bid.order_id)])
+            {
+                if let Some((_, level)) = self.index.get(&order_id) {
+                    if !changed.contains(&(side.clone(), *level)) {
+                        changed.push((side, *level));
+                    }
+                }
+            }
+        }
+        self.last_price = Some(price);
+        if !self.observers.is_empty() {
+            for (bid, receipt) in crossed.iter() {
+                for ask in receipt.matches.iter() {
+                    self.observers.notify(|o| o.on_trade(bid, ask));
+                }
+            }
+            self.notify_levels(changed);
+        }
+        self.crossed = crossed
+            .into_iter()
+            .map(|(bid, receipt)| AuctionFill {
+                signer: bid.signer,
+                receipt,
+            })
+            .collect();
+        let fills = self.crossed.clone();
+        for AuctionFill { receipt, .. } in fills.iter() {
+            self.history.push(receipt.clone());
+            // Filling a part of an order of a bracket cancels the other one
+            for filled in receipt.matches.iter().map(|m| m.order_id) {
+                self.cancel_linked(filled);
+            }
+            self.cancel_linked(receipt.order_id);
+        }
+        self.trigger_stops()?;
+        Ok(AuctionReceipt {
+            price: Some(price),
+            volume,
+            fills,
+        })
+    }
+
+    /// The price crossing the most volume in a call auction with that volume, `None` if no bid meets an ask
+    fn clearing_price(&self) -> Option<(u64, u64)> {
+        self.bid_levels
+            .keys()
+            .chain(self.ask_levels.keys())
+            .filter_map(|price| {
+                let demand: u64 = self
+                    .bid_levels
+                    .range(price..)
+                    .map(|(_, l)| l.quantity)
+                    .sum();
+                let supply: u64 = self
+                    .ask_levels
+                    .range(..=price)
+                    .map(|(_, l)| l.quantity)
+                    .sum();
+                let volume = demand.min(supply);
+                let distance = self.last_price.map_or(0, |last| last.abs_diff(*price));
+                (volume > 0).then_some((*price, volume, demand.abs_diff(supply), distance))
+            })
+            .min_by_key(|(price, volume, surplus, distance)| {
+                (std::cmp::Reverse(*volume), *surplus, *distance, *price)
+            })
+            .map(|(price, volume, _, _)| (price, volume))
+    }
+
+    /// The price of the latest match in this book
+    pub fn last_price(&self) -> Option<u64> {
+        self.last_price
+    }
+
+    /// The stop orders waiting for their trigger price, by id
+    pub fn stops(&self) -> &BTreeMap<OrderId, Order> {
+        &self.stops
+    }
+
+    /// Matches `order` with `order_id` under `ordinal` and rests what's left of it. Every match executes at the price
+    /// of the resting order: a buy at 12 meeting an ask at 10 pays 10, a sell at 10 meeting a bid at 12 gets 12.
+    fn execute(
+        &mut self,
+        order: Order,
+        ordinal: u64,
+        order_id: OrderId,
+    ) -> Result<Receipt, ApplicationError> {
+        let original_amount = order.amount;
+        let market = order.order_type == OrderType::Market;
+        let rests = !market && order.time_in_force != TimeInForce::Ioc;
+        let mut partial = order.into_partial_order(ordinal, original_amount);
+        partial.order_id = order_id;
+        let limit_price = (!market).then_some(partial.price);
+        // Observers are told about the order as it was accepted, before any of it is matched
+        let observed = (!self.observers.is_empty()).then(|| {
+            let own_level = self.level_quantity(&partial.side, partial.price);
+            (partial.clone(), own_level)
+        });
+        if let Some((accepted, _)) = &observed {
+            self.observers.notify(|o| o.on_order_accepted(accepted));
+        }
+
+        // Orders are matched to the opposite side
+        let (mut receipt, removed) = match &partial.side {
+            Side::Buy => {
+                // Fetch all orders in the expected price range from this side of the orderbook
+                let limit = if market { u64::MAX } else { partial.price };
+                // In a call auction orders rest without matching
+                let auction = self.auction;
+                let orderbook_entry = self
+                    .asks
+                    .range_mut(u64::MIN..=limit)
+                    .take_while(|_| !auction);
+
+                let (receipt, removed) = MatchingEngine::match_order(
+                    &partial,
+                    orderbook_entry,
+                    &mut self.ask_levels,
+                    ordinal,
+                    self.self_match_policy,
+                )?;
+                let matched_amount: u64 = receipt.matches.iter().map(|m| m.amount).sum();
+                let dropped = receipt.incoming_self_matched();
+
+                // The order wasn't fully matched
+                if matched_amount + dropped < original_amount && rests {
+                    partial.remaining = original_amount - matched_amount - dropped;
+                    let price = partial.price;
+                    add_resting(&mut self.bid_levels, &partial);
+                    self.index.insert(partial.order_id, (Side::Buy, price));
+                    // Behind the orders already resting at the price
+                    self.bids
+                        .entry(price)
+                        .or_insert_with(|| self.pool.take())
+                        .push_back(partial);
+                }
+                (receipt, removed)
+            }
+            Side::Sell => {
+                // Fetch all orders in the expected price range from this side of the orderbook
+                let limit = if market { u64::MIN } else { partial.price };
+                // Best price first, the highest bid
+                let auction = self.auction;
+                let orderbook_entry = self
+                    .bids
+                    .range_mut(limit..=u64::MAX)
+                    .rev()
+                    .take_while(|_| !auction);
+
+                let (receipt, removed) = MatchingEngine::match_order(
+                    &partial,
+                    orderbook_entry,
+                    &mut self.bid_levels,
+                    ordinal,
+                    self.self_match_policy,
+                )?;
+                let matched_amount: u64 = receipt.matches.iter().map(|m| m.amount).sum();
+                let dropped = receipt.incoming_self_matched();
+
+                // The order wasn't fully matched
+                if matched_amount + dropped < original_amount && rests {
+                    partial.remaining = original_amount - matched_amount - dropped;
+                    let price = partial.price;
+                    add_resting(&mut self.ask_levels, &partial);
+                    self.index.insert(partial.order_id, (Side::Sell, price));
+                    // Behind the orders already resting at the price
+                    self.asks
+                        .entry(price)
+                        .or_insert_with(|| self.pool.take())
+                        .push_back(partial);
+                }
+                (receipt, removed)
+            }
+        };
+
+        receipt.filled = receipt.matches.iter().map(|m| m.amount).sum();
+        receipt.limit_price = limit_price;
+        if let Some(last) = receipt.matches.last() {
+            self.last_price = Some(last.price);
+        }
+        receipt.cancelled = match rests {
+            true => receipt.incoming_self_matched(),
+            false => original_amount - receipt.filled,
+        };
+        receipt.total(original_amount - receipt.filled - receipt.cancelled);
+
+        // Cleanup: Remove price entries without orders from the orderbook
+        prune(&mut self.asks, &mut self.pool);
+        prune(&mut self.bids, &mut self.pool);
+        for filled in receipt.matches.iter().filter(|m| m.remaining == 0) {
+            self.index.remove(&filled.order_id);
+        }
+        for order in removed.iter() {
+            self.index.remove(&order.order_id);
+        }
+
+        if let Some((taker, own_level)) = observed {
+            for order in removed.iter() {
+                self.observers.notify(|o| o.on_cancel(order));
+            }
+            self.notify_matched(&taker, &receipt, own_level);
+        }
+
+        // Keep a log of matches
+        self.history.push(receipt.clone());
+        // Filling a part of an order of a bracket cancels the other one
+        for filled in receipt.matches.iter().map(|m| m.order_id) {
+            self.cancel_linked(filled);
+        }
+        if receipt.filled > 0 {
+            self.cancel_linked(order_id);
+        }
+        Ok(receipt)
+    }
+
+    /// Notifies the observers of the matches of `taker` and the levels they changed
+    fn notify_matched(&mut self, taker: &PartialOrder, receipt: &Receipt, own_level: u64) {
+        for maker in receipt.matches.iter() {
+            self.observers.notify(|o| o.on_trade(taker, maker));
+        }
+        let mut changed: Vec<(Side, u64)> = vec![];
+        for maker in receipt.matches.iter() {
+            if !changed.contains(&(maker.side.clone(), maker.price)) {
+                changed.push((maker.side.clone(), maker.price));
+            }
+        }
+        let resting_side = match taker.side {
+            Side::Buy => Side::Sell,
+            Side::Sell => Side::Buy,
+        };
+        for self_match in receipt
+            .self_matches
+            .iter()
+            .filter(|m| m.resting_cancelled > 0)
+        {
+            if !changed.contains(&(resting_side.clone(), self_match.price)) {
+                changed.push((resting_side.clone(), self_match.price));
+            }
+        }
+        if self.level_quantity(&taker.side, taker.price) != own_level {
+            changed.push((taker.side.clone(), taker.price));
+        }
+        self.notify_levels(changed);
+    }
+
+    /// Notifies the observers of the current quantity of `levels`
+    fn notify_levels(&mut self, levels: Vec<(Side, u64)>) {
+        for (side, price) in levels {
+            let level = PriceLevel {
+                price,
+                quantity: self.level_quantity(&side, price),
+            };
+            self.observers.notify(|o| o.on_book_change(&side, &level));
+        }
+    }
+
+    /// All open orders, asks first, each side in ascending price order
+    pub fn orders(&self) -> Vec<PartialOrder> {
+        self.asks
+            .values()
+            .cloned()
+            .chain(self.bids.values().cloned())
+            .flatten()
+            .collect()
+    }
+
+    /// The open orders matching `query`, ordered like [`MatchingEngine::orders`]. Only the requested price levels
+    /// are visited and cloned.
+    pub fn orders_matching(&self, query: &BookQuery) -> Vec<PartialOrder> {
+        let limit = query.limit.unwrap_or(usize::MAX);
+        let mut orders = vec![];
+        if query.includes(&Side::Sell) {
+            for (_, level) in price_range(&self.asks, query).take(limit) {
+                orders.extend(level.iter().cloned());
+            }
+        }
+        if query.includes(&Side::Buy) {
+            let best: Vec<_> = price_range(&self.bids, query).rev().take(limit).collect();
+            for (_, level) in best.into_iter().rev() {
+                orders.extend(level.iter().cloned());
+            }
+        }
+        orders
+    }
+
+    /// The open quantity per price level, best price first
+    pub fn levels(&self, side: &Side) -> Vec<PriceLevel> {
+        self.levels_matching(side, &BookQuery::default())
+    }
+
+    /// Like [`MatchingEngine::levels`], restricted to the side, price range, and number of levels of `query`
+    pub fn levels_matching(&self, side: &Side, query: &BookQuery) -> Vec<PriceLevel> {
+        if !query.includes(side) {
+            return vec![];
+        }
+        let limit = query.limit.unwrap_or(usize::MAX);
+        match side {
+            Side::Buy => aggregate_range(&self.bid_levels, query)
+                .rev()
+                .take(limit)
+                .map(level)
+                .collect(),
+            Side::Sell => aggregate_range(&self.ask_levels, query)
+                .take(limit)
+                .map(level)
+                .collect(),
+        }
+    }
+
+    fn aggregates(&self, side: &Side) -> &Aggregates {
+        match side {
+            Side::Buy => &self.bid_levels,
+            Side::Sell => &self.ask_levels,
+        }
+    }
+
+    /// The open quantity and number of orders at a price level, `None` if nothing rests there
+    pub fn level_aggregate(&self, side: &Side, price: u64) -> Option<LevelAggregate> {
+        self.aggregates(side).get(&price).copied()
+    }
+
+    /// The highest bid level, `None` if nobody bids
+    pub fn best_bid(&self) -> Option<PriceLevel> {
+        self.bid_levels.iter().next_back().map(level)
+    }
+
+    /// The lowest ask level, `None` if nobody asks
+    pub fn best_ask(&self) -> Option<PriceLevel> {
+        self.ask_levels.iter().next().map(level)
+    }
+
+    /// The best bid and ask levels, `None` for an empty side
+    pub fn best_bid_offer(&self) -> (Option<PriceLevel>, Option<PriceLevel>) {
+        (self.best_bid(), self.best_ask())
+    }
+
+    /// The open quantity at a price level
+    pub fn level_quantity(&self, side: &Side, price: u64) -> u64 {
+        self.level_aggregate(side, price)
+            .map(|aggregate| aggregate.quantity)
+            .unwrap_or(0)
+    }
+
+    /// The number of open orders on one side of the book
+    pub fn order_count(&self, side: &Side) -> usize {
+        let book = match side {
+            Side::Buy => &self.bids,
+            Side::Sell => &self.asks,
+        };
+        book.values().map(|orders| orders.len()).sum()
+    }
+
+    /// The number of units `signer` has open on one side of the book
+    pub fn open_amount(&self, signer: &str, side: &Side) -> u64 {
+        let book = match side {
+            Side::Buy => &self.bids,
+            Side::Sell => &self.asks,
+        };
+        book.values()
+            .flatten()
+            .filter(|o| o.signer == signer)
+            .map(|o| o.remaining)
+            .sum()
+    }
+
+    /// Processes both sides of a quote as one operation: the open orders of the quoting account with the `replace`
+    /// ids are cancelled, then the bid and the ask are processed like two [`Order`]s.
+    /// # Errors
+    /// The bid isn't a buy or the ask isn't a sell of the same account, or the bid isn't below the ask. Nothing is
+    /// changed then.
+    pub fn process_quote(
+        &mut self,
+        bid: Order,
+        ask: Order,
+        replace: &[OrderId],
+    ) -> Result<QuoteReceipt, ApplicationError> {
+        MatchingEngine::validate_quote(&bid, &ask)?;
+        self.triggered.clear();
+        self.unlinked.clear();
+        self.refused.clear();
+        let signer = bid.signer.clone();
+        let cancelled = self.cancel_where(|o| o.signer == signer && replace.contains(&o.order_id));
+        let receipt = QuoteReceipt {
+            cancelled,
+            bid: self.place(bid)?,
+            ask: self.place(ask)?,
+        };
+        self.trigger_stops()?;
+        Ok(receipt)
+    }
+
+    /// Checks that `bid` and `ask` form a quote, see [`MatchingEngine::process_quote`]
+    /// # Errors
+    /// The bid isn't a buy or the ask isn't a sell of the same account, or the bid isn't below the ask
+    pub fn validate_quote(bid: &Order, ask: &Order) -> Result<(), ApplicationError> {
+        if bid.side != Side::Buy || ask.side != Side::Sell {
+            return Err(ApplicationError::InvalidQuote(
+                "the bid has to buy and the ask to sell".to_string(),
+            ));
+        }
+        if bid.order_type != OrderType::Limit || ask.order_type != OrderType::Limit {
+            return Err(ApplicationError::InvalidQuote(
+                "both sides have to be limit orders".to_string(),
+            ));
+        }
+        if bid.time_in_force != TimeInForce::Gtc
+            || ask.time_in_force != TimeInForce::Gtc
+            || bid.trigger_price.is_some()
+            || ask.trigger_price.is_some()
+        {
+            return Err(ApplicationError::InvalidQuote(
+                "both sides have to rest in the book".to_string(),
+            ));
+        }
+        if bid.signer != ask.signer {
+            return Err(ApplicationError::InvalidQuote(
+                "both sides need the same signer".to_string(),
+            ));
+        }
+        if bid.market != ask.market {
+            return Err(ApplicationError::InvalidQuote(
+                "both sides have to be in the same market".to_string(),
+            ));
+        }
+        if bid.price >= ask.price {
+            return Err(ApplicationError::InvalidQuote(format!(
+                "the bid price {} isn't below the ask price {}",
+                bid.price, ask.price
+            )));
+        }
+        Ok(())
+    }
+
+    /// Processes the orders of a bracket as one operation: the take-profit like an [`Order`], then the stop-loss is set
+    /// aside and linked to it. Once either of them fills in part or the stop triggers, the other one is cancelled, see
+    /// [`MatchingEngine::unlinked`]. Cancelling one of them leaves the other one on its own.
+    /// # Errors
+    /// The take-profit isn't a resting limit order, the stop-loss isn't a stop order, or they don't close the same
+    /// position of one account. Nothing is changed then.
+    pub fn process_bracket(
+        &mut self,
+        take_profit: Order,
+        stop_loss: Order,
+    ) -> Result<BracketReceipt, ApplicationError> {
+        MatchingEngine::validate_bracket(&take_profit, &stop_loss)?;
+        self.triggered.clear();
+        self.unlinked.clear();
+        self.refused.clear();
+        let take_profit = self.place(take_profit)?;
+        let stop_loss = self.place(stop_loss)?;
+        self.links.insert(take_profit.order_id, stop_loss.order_id);
+        self.links.insert(stop_loss.order_id, take_profit.order_id);
+        // The take-profit filled on placement, the stop-loss goes before it could trigger
+        if take_profit.filled > 0 {
+            self.cancel_linked(take_profit.order_id);
+        }
+        self.trigger_stops()?;
+        let ids = [take_profit.order_id, stop_loss.order_id];
+        Ok(BracketReceipt {
+            cancelled: self
+                .unlinked
+                .iter()
+                .filter(|o| ids.contains(&o.order_id))
+                .cloned()
+                .collect(),
+            take_profit,
+            stop_loss,
+        })
+    }
+
+    /// Checks that `take_profit` and `stop_loss` form a bracket, see [`MatchingEngine::process_bracket`]
+    /// # Errors
+    /// The take-profit isn't a resting limit order, the stop-loss isn't a stop order, or they don't close the same
+    /// position of one account
+    pub fn validate_bracket(
+        take_profit: &Order,
+        stop_loss: &Order,
+    ) -> Result<(), ApplicationError> {
+        if take_profit.order_type != OrderType::Limit
+            || take_profit.time_in_force == TimeInForce::Ioc
+            || take_profit.trigger_price.is_some()
+        {
+            return Err(ApplicationError::InvalidBracket(
+                "the take-profit has to be a limit order resting in the book".to_string(),
+            ));
+        }
+        let Some(trigger_price) = stop_loss.trigger_price else {
+            return Err(ApplicationError::InvalidBracket(
+                "the stop-loss needs a trigger price".to_string(),
+            ));
+        };
+        if take_profit.signer != stop_loss.signer {
+            return Err(ApplicationError::InvalidBracket(
+                "both orders need the same signer".to_string(),
+            ));
+        }
+        if take_profit.market != stop_loss.market {
+            return Err(ApplicationError::InvalidBracket(
+                "both orders have to be in the same market".to_string(),
+            ));
+        }
+        if take_profit.side != stop_loss.side {
+            return Err(ApplicationError::InvalidBracket(
+                "both orders have to close the position on the same side".to_string(),
+            ));
+        }
+        // A sell closes a long position: profit above, loss below. A buy closes a short one the other way around.
+        let apart = match take_profit.side {
+            Side::Sell => take_profit.price > trigger_price,
+            Side::Buy => take_profit.price < trigger_price,
+        };
+        if !apart {
+            return Err(ApplicationError::InvalidBracket(format!(
+                "the take-profit price {} and the stop-loss trigger {} are on the wrong sides",
+                take_profit.price, trigger_price
+            )));
+        }
+        Ok(())
+    }
+
+    /// The price levels `order` would fill at if it were processed now, best price first, with the quantity at each.
+    /// Orders of the same signer are skipped like in matching.
+    pub fn fills_of(&self, order: &Order) -> Vec<PriceLevel> {
+        let market = order.order_type == OrderType::Market;
+        let levels: Box<dyn Iterator<Item = (&u64, &VecDeque<PartialOrder>)>> = match order.side {
+            Side::Buy => Box::new(
+                self.asks
+                    .range(..)
+                    .take_while(|(price, _)| market || **price <= order.price),
+            ),
+            Side::Sell => Box::new(
+                self.bids
+                    .range(..)
+                    .rev()
+                    .take_while(|(price, _)| market || **price >= order.price),
+            ),
+        };
+        let mut remaining = order.amount;
+        let mut fills = vec![];
+        for (price, orders) in levels {
+            if remaining == 0 {
+                break;
+            }
+            let available: u64 = orders
+                .iter()
+                .filter(|o| o.signer != order.signer)
+                .map(|o| o.remaining)
+                .sum();
+            let quantity = available.min(remaining);
+            if quantity > 0 {
+                remaining -= quantity;
+                fills.push(PriceLevel {
+                    price: *price,
+                    quantity,
+                });
+            }
+        }
+        fills
+    }
+
+    /// The resting orders `order` would match if it were processed now, oldest first at each price, each with the
+    /// amount and price of its match like in a [`Receipt`]. Orders of the same signer are resolved with the book's
+    /// [`SelfMatchPolicy`] like in matching, stops and orders placed during an auction match nothing.
+    pub fn matches_of(&self, order: &Order) -> Vec<PartialOrder> {
+        if self.auction || order.trigger_price.is_some() {
+            return vec![];
+        }
+        let market = order.order_type == OrderType::Market;
+        let levels: Box<dyn Iterator<Item = (&u64, &VecDeque<PartialOrder>)>> = match order.side {
+            Side::Buy => Box::new(
+                self.asks
+                    .range(..)
+                    .take_while(|(price, _)| market || **price <= order.price),
+            ),
+            Side::Sell => Box::new(
+                self.bids
+                    .range(..)
+                    .rev()
+                    .take_while(|(price, _)| market || **price >= order.price),
+            ),
+        };
+        let mut remaining = order.amount;
+        let mut matches = vec![];
+        for (price, orders) in levels {
+            for resting in orders.iter() {
+                if remaining == 0 {
+                    return matches;
+                }
+                if resting.signer == order.signer {
+                    match self.self_match_policy {
+                        SelfMatchPolicy::SkipResting | SelfMatchPolicy::CancelResting => {}
+                        SelfMatchPolicy::CancelIncoming => return matches,
+                        SelfMatchPolicy::DecrementBoth => {
+                            remaining -= resting.remaining.min(remaining)
+                        }
+                    }
+                    continue;
+                }
+                let amount = resting.remaining.min(remaining);
+                remaining -= amount;
+                matches.push(PartialOrder {
+                    price: *price,
+                    amount,
+                    remaining: resting.remaining - amount,
+                    ..resting.clone()
+                });
+            }
+        }
+        matches
+    }
+
+    /// The price of the units `signer` has open on one side of the book
+    pub fn open_notional(&self, signer: &str, side: &Side) -> u64 {
+        let book = match side {
+            Side::Buy => &self.bids,
+            Side::Sell => &self.asks,
+        };
+        book.values()
+            .flatten()
+            .filter(|o| o.signer == signer)
+            .map(|o| o.remaining * o.price)
+            .sum()
+    }
+
+    /// The resting order with `order_id`
+    pub fn order(&self, order_id: OrderId) -> Option<&PartialOrder> {
+        let (side, price) = self.index.get(&order_id)?;
+        let book = match side {
+            Side::Buy => &self.bids,
+            Side::Sell => &self.asks,
+        };
+        book.get(price)?.iter().find(|o| o.order_id == order_id)
+    }
+
+    /// Where the resting order with `order_id` stands in its price level, `None` if no order with that id rests in the
+    /// book. The level totals come from the level aggregates, only the orders ahead are counted.
+    pub fn queue_position(&self, order_id: OrderId) -> Option<QueuePosition> {
+        let order = self.order(order_id)?;
+        let level = self.level_aggregate(&order.side, order.price)?;
+        let book = match order.side {
+            Side::Buy => &self.bids,
+            Side::Sell => &self.asks,
+        };
+        let ahead: Vec<&PartialOrder> = book
+            .get(&order.price)?
+            .iter()
+            .take_while(|o| o.order_id != order_id)
+            .collect();
+        Some(QueuePosition {
+            order_id,
+            side: order.side.clone(),
+            price: order.price,
+            remaining: order.remaining,
+            ahead_quantity: ahead.iter().map(|o| o.remaining).sum(),
+            ahead_orders: ahead.len(),
+            level_quantity: level.quantity,
+            level_orders: level.orders,
+        })
+    }
+
+    /// Changes the price and the open amount of the resting order with `order_id`. A smaller amount at the same price
+    /// keeps the order's time priority. A new price or a larger amount takes the order off the book and processes it
+    /// again under a new ordinal, behind the orders at its price, and it may match at the new price. The receipt keeps
+    /// the order's id and goes into the history.
+    /// # Errors
+    /// - No resting order has that id
+    /// - The new amount is zero
+    pub fn amend(
+        &mut self,
+        order_id: OrderId,
+        new_price: u64,
+        new_amount: u64,
+    ) -> Result<Receipt, ApplicationError> {
+        let resting = self
+            .order(order_id)
+            .ok_or(ApplicationError::OrderNotFound(order_id))?
+            .clone();
+        if new_amount == 0 {
+            return Err(ApplicationError::InvalidAmendment(
+                "the amount has to be above zero, cancel the order instead".to_string(),
+            ));
+        }
+        self.triggered.clear();
+        self.unlinked.clear();
+        self.refused.clear();
+        if new_price == resting.price && new_amount <= resting.remaining {
+            let (book, levels) = match resting.side {
+                Side::Buy => (&mut self.bids, &mut self.bid_levels),
+                Side::Sell => (&mut self.asks, &mut self.ask_levels),
+            };
+            let orders = book
+                .get_mut(&resting.price)
+                .expect("indexed orders rest at their level");
+            for order in orders.iter_mut().filter(|o| o.order_id == order_id) {
+                order.remaining = new_amount;
+            }
+            take_resting(levels, resting.price, resting.remaining - new_amount, false);
+            if !self.observers.is_empty() && new_amount < resting.remaining {
+                self.notify_levels(vec![(resting.side.clone(), resting.price)]);
+            }
+            let receipt = Receipt {
+                ordinal: resting.ordinal,
+                order_id,
+                matches: vec![],
+                filled: 0,
+                cancelled: 0,
+                limit_price: None,
+                market: resting.market.clone(),
+                self_matches: vec![],
+                settlement: None,
+                remaining: new_amount,
+                total_notional: 0,
+                average_price: None,
+                status: ReceiptStatus::Resting,
+            };
+            self.history.push(receipt.clone());
+            return Ok(receipt);
+        }
+
+        // The order stays in its bracket under the new ordinal
+        let linked = self.linked(order_id);
+        self.cancel_where(|o| o.order_id == order_id);
+        if let Some(other) = linked {
+            self.links.insert(order_id, other);
+            self.links.insert(other, order_id);
+        }
+        self.ordinal = self.ids.next_id(self.ordinal);
+        let order = Order {
+            price: new_price,
+            amount: new_amount,
+            side: resting.side,
+            signer: resting.signer,
+            client_order_id: None,
+            signature: None,
+            order_type: OrderType::Limit,
+            time_in_force: resting
+                .expires_at
+                .map_or(TimeInForce::Gtc, TimeInForce::Gtt),
+            trigger_price: None,
+            market: resting.market,
+        };
+        let receipt = self.execute(order, self.ordinal, order_id)?;
+        self.trigger_stops()?;
+        Ok(receipt)
+    }
+
+    /// Removes the resting order or the waiting stop with `order_id` and returns it
+    /// # Errors
+    /// - No resting order or stop has that id
+    /// - The order wasn't placed by `signer`
+    pub fn cancel(
+        &mut self,
+        order_id: OrderId,
+        signer: &str,
+    ) -> Result<PartialOrder, ApplicationError> {
+        if let Some(stop) = self.stops.get(&order_id) {
+            if stop.signer != signer {
+                return Err(ApplicationError::OrderNotOwned(order_id));
+            }
+            let stop = self.stops.remove(&order_id).expect("found above");
+            self.unlink(order_id);
+            let amount = stop.amount;
+            return Ok(stop.into_partial_order(order_id, amount));
+        }
+        match self.order(order_id) {
+            None => return Err(ApplicationError::OrderNotFound(order_id)),
+            Some(order) if order.signer != signer => {
+                return Err(ApplicationError::OrderNotOwned(order_id))
+            }
+            Some(_) => {}
+        }
+        let (side, price) = self.index[&order_id].clone();
+        let (book, levels) = match side {
+            Side::Buy => (&mut self.bids, &mut self.bid_levels),
+            Side::Sell => (&mut self.asks, &mut self.ask_levels),
+        };
+        let orders = book
+            .get_mut(&price)
+            .expect("indexed orders rest at their level");
+        let (cancelled, kept): (Vec<_>, Vec<_>) = std::mem::take(orders)
+            .into_iter()
+            .partition(|o| o.order_id == order_id);
+        *orders = kept.into();
+        if orders.is_empty() {
+            let emptied = book.remove(&price).expect("found above");
+            self.pool.give(emptied);
+        }
+        for order in cancelled.iter() {
+            take_resting(levels, price, order.remaining, true);
+        }
+        Ok(self.cancelled(cancelled, false).remove(0))
+    }
+
+    /// Removes all open orders and waiting stops of `signer` from both sides of the book and returns them
+    pub fn cancel_all(&mut self, signer: &str) -> Vec<PartialOrder> {
+        self.cancel_where(|o| o.signer == signer)
+    }
+
+    /// Removes the good 'til time orders and stops that expired at `now` from both sides of the book and returns them
+    pub fn expire(&mut self, now: u64) -> Vec<PartialOrder> {
+        self.remove_where(|o| o.expires_at.is_some_and(|expiry| expiry <= now), true)
+    }
+
+    /// Removes the open orders and stops matching `cancel` from both sides of the book and returns them in ordinal order
+    pub fn cancel_where(&mut self, cancel: impl Fn(&PartialOrder) -> bool) -> Vec<PartialOrder> {
+        self.remove_where(cancel, false)
+    }
+
+    /// Like [`MatchingEngine::cancel_where`], the observers hear of `expired` orders as such
+    fn remove_where(
+        &mut self,
+        cancel: impl Fn(&PartialOrder) -> bool,
+        expired: bool,
+    ) -> Vec<PartialOrder> {
+        let mut stops = vec![];
+        for (order_id, stop) in std::mem::take(&mut self.stops) {
+            let partial = stop.clone().into_partial_order(order_id, stop.amount);
+            if cancel(&partial) {
+                stops.push(partial);
+            } else {
+                self.stops.insert(order_id, stop);
+            }
+        }
+        let mut cancelled = vec![];
+        for (book, levels) in [
+            (&mut self.bids, &mut self.bid_levels),
+            (&mut self.asks, &mut self.ask_levels),
+        ] {
+            for orders in book.values_mut() {
+                let (removed, kept): (Vec<_>, Vec<_>) =
+                    std::mem::take(orders).into_iter().partition(|o| cancel(o));
+                *orders = kept.into();
+                for order in removed.iter() {
+                    take_resting(levels, order.price, order.remaining, true);
+                }
+                cancelled.extend(removed);
+            }
+            prune(book, &mut self.pool);
+        }
+        let mut cancelled = self.cancelled(cancelled, expired);
+        // Stops were never in the book, the observers don't hear of them
+        for stop in stops.iter() {
+            self.unlink(stop.order_id);
+        }
+        cancelled.extend(stops);
+        cancelled.sort_by_key(|o| o.ordinal);
+        cancelled
+    }
+
+    /// Forgets the orders that were taken off the book, `expired` or cancelled, and tells the observers. Returns them in
+    /// ordinal order.
+    fn cancelled(&mut self, mut cancelled: Vec<PartialOrder>, expired: bool) -> Vec<PartialOrder> {
+        cancelled.sort_by_key(|o| o.ordinal);
+        for order in cancelled.iter() {
+            self.index.remove(&order.order_id);
+            self.unlink(order.order_id);
+        }
+        if !self.observers.is_empty() {
+            let mut changed: Vec<(Side, u64)> = vec![];
+            for order in cancelled.iter() {
+                match expired {
+                    true => self.observers.notify(|o| o.on_expire(order)),
+                    false => self.observers.notify(|o| o.on_cancel(order)),
+                }
+                if !changed.contains(&(order.side.clone(), order.price)) {
+                    changed.push((order.side.clone(), order.price));
+                }
+            }
+            self.notify_levels(changed);
+        }
+        cancelled
+    }
+
+    /// Replaces `signer` with `token` in the resting orders, the stops, and the history
+    pub fn anonymize(&mut self, signer: &str, token: &str) {
+        anonymize_book(&mut self.bids, signer, token);
+        anonymize_book(&mut self.asks, signer, token);
+        for stop in self.stops.values_mut() {
+            anonymize(&mut stop.signer, signer, token);
+        }
+        for stop in self.triggered.iter_mut() {
+            anonymize(&mut stop.order.signer, signer, token);
+            stop.receipt.anonymize(signer, token);
+        }
+        for receipt in self.history.iter_mut() {
+            receipt.anonymize(signer, token);
+        }
+    }
+
+    /// Matches an order to the provided order book side. Resting orders of the same account are resolved with
+    /// `policy`, the ones it takes off the book are returned with what was cancelled of them.
+    /// # Parameters
+    /// - `orderbook_entry`: a pre-filtered iterator for order book_entry in the correct price range
+    /// - `levels`: the aggregates of the same side, the matched quantity is taken off them
+    /// - `ordinal` the next ordinal number to use if a position is opened
+    /// - `policy`: how self-matches are resolved
+    fn match_order<'a, T>(
+        order: &PartialOrder,
+        mut orderbook_entry: T,
+        levels: &mut Aggregates,
+        ordinal: u64,
+        policy: SelfMatchPolicy,
+    ) -> Result<(Receipt, Vec<PartialOrder>), ApplicationError>
+    where
+        T: Iterator<Item = (&'a u64, &'a mut VecDeque<PartialOrder>)>,
+    {
+        let mut remaining_amount = order.amount;
+        let mut matches = vec![];
+        let mut self_matches = vec![];
+        let mut removed = vec![];
+
+        // Each matching position's amount is subtraced
+        'outer: while remaining_amount > 0 {
+            // The iterator contains all orderbook_entry of a price point
+            let Some((price, orderbook_entry)) = orderbook_entry.next() else {
+                // Nothing left to match with
+                break 'outer;
+            };
+            // Walk the level oldest first in place. Orders passed over by a self-match keep their place ahead of the
+            // rest, the ones filled or cancelled in full are moved out of it.
+            let mut i = 0;
+            while remaining_amount > 0 && i < orderbook_entry.len() {
+                let pos = &mut orderbook_entry[i];
+                // A self-match is illegal so the policy resolves it instead of matching
+                if pos.signer == order.signer {
+                    let (resting_cancelled, incoming_cancelled) = match policy {
+                        SelfMatchPolicy::SkipResting => (0, 0),
+                        SelfMatchPolicy::CancelResting => (pos.remaining, 0),
+                        SelfMatchPolicy::CancelIncoming => (0, remaining_amount),
+                        SelfMatchPolicy::DecrementBoth => {
+                            let decrement = pos.remaining.min(remaining_amount);
+                            (decrement, decrement)
+                        }
+                    };
+                    self_matches.push(SelfMatch {
+                        policy,
+                        resting_order_id: pos.order_id,
+                        price: *price,
+                        resting_cancelled,
+                        incoming_cancelled,
+                    });
+                    remaining_amount -= incoming_cancelled;
+                    if resting_cancelled > 0 {
+                        pos.remaining -= resting_cancelled;
+                        let left = pos.remaining;
+                        take_resting(levels, *price, resting_cancelled, left == 0);
+                        if left == 0 {
+                            removed.extend(orderbook_entry.remove(i));
+                            continue;
+                        }
+                    }
+                    i += 1;
+                    continue;
+                }
+
+                if pos.remaining > remaining_amount {
+                    // The last match takes a part of the position, which keeps its place
+                    matches.push(PartialOrder::take_from(pos, remaining_amount, *price));
+                    take_resting(levels, *price, remaining_amount, false);
+                    remaining_amount = 0;
+                } else {
+                    // The match is what was left of the position, not its initial amount. The position leaves the
+                    // book so it's moved into the match instead of copied.
+                    let rest = pos.remaining;
+                    take_resting(levels, *price, rest, true);
+                    remaining_amount -= rest;
+                    let mut filled = orderbook_entry
+                        .remove(i)
+                        .expect("the index is within the level");
+                    filled.remaining = 0;
+                    filled.amount = rest;
+                    filled.price = *price;
+                    matches.push(filled);
+                }
+            }
+        }
+
+        let receipt = Receipt {
+            ordinal,
+            order_id: order.order_id,
+            matches,
+            filled: 0,
+            cancelled: 0,
+            limit_price: None,
+            market: order.market.clone(),
+            self_matches,
+            settlement: None,
+            remaining: 0,
+            total_notional: 0,
+            average_price: None,
+            status: ReceiptStatus::Resting,
+        };
+        Ok((receipt, removed))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    // reduce the warnings for naming tests
+    #![allow(non_snake_case)]
+
+    use super::*;
+    use crate::EngineEvent;
+    use octopus_common::types::{BookSide, DEFAULT_MARKET};
+
+    #[test]
+    fn test_MatchingEngine_process_partially_match_order() {
+        let mut matching_engine = MatchingEngine::new();
+
+        let alice_receipt = matching_engine
+            .process(Order {
+                price: 10,
+                amount: 1,
+                side: Side::Sell,
+                signer: "ALICE".to_string(),
+                client_order_id: None,
+                signature: None,
+                order_type: OrderType::Limit,
+                time_in_force: TimeInForce::Gtc,
+                trigger_price: None,
+                market: DEFAULT_MARKET.to_string(),
+            })
+            .unwrap();
+        assert_eq!(alice_receipt.matches, vec![]);
+        assert_eq!(alice_receipt.ordinal, 1);
+
+        let bob_receipt = matching_engine
+            .process(Order {
+                price: 10,
+                amount: 2,
+                side: Side::Buy,
+                signer: "BOB".to_string(),
+                client_order_id: None,
+                signature: None,
+                order_type: OrderType::Limit,
+                time_in_force: TimeInForce::Gtc,
+                trigger_price: None,
+                market: DEFAULT_MARKET.to_string(),
+            })
+            .unwrap();
+
+        assert_eq!(
+            bob_receipt.matches,
+            vec![PartialOrder {
+                price: 10,
+                amount: 1,
+                remaining: 0,
+                side: Side::Sell,
+                signer: "ALICE".to_string(),
+                ordinal: 1,
+                order_id: 1,
+                expires_at: None,
+                market: DEFAULT_MARKET.to_string(),
+            }]
+        );
+        assert!(matching_engine.asks.is_empty());
+        assert_eq!(matching_engine.bids.len(), 1);
+    }
+
+    #[test]
+    fn test_MatchingEngine_process_fully_match_order() {
+        let mut matching_engine = MatchingEngine::new();
+
+        let alice_receipt = matching_engine
+            .process(Order {
+                price: 10,
+                amount: 2,
+                side: Side::Sell,
+                signer: "ALICE".to_string(),
+                client_order_id: None,
+                signature: None,
+                order_type: OrderType::Limit,
+                time_in_force: TimeInForce::Gtc,
+                trigger_price: None,
+                market: DEFAULT_MARKET.to_string(),
+            })
+            .unwrap();
+        assert_eq!(alice_receipt.matches, vec![]);
+        assert_eq!(alice_receipt.ordinal, 1);
+
+        let bob_receipt = matching_engine
+            .process(Order {
+                price: 10,
+                amount: 2,
+                side: Side::Buy,
+                signer: "BOB".to_string(),
+                client_order_id: None,
+                signature: None,
+                order_type: OrderType::Limit,
+                time_in_force: TimeInForce::Gtc,
+                trigger_price: None,
+                market: DEFAULT_MARKET.to_string(),
+            })
+            .unwrap();
+
+        assert_eq!(
+            bob_receipt.matches,
+            vec![PartialOrder {
+                price: 10,
+                amount: 2,
+                remaining: 0,
+                side: Side::Sell,
+                signer: "ALICE".to_string(),
+                ordinal: 1,
+                order_id: 1,
+                expires_at: None,
+                market: DEFAULT_MARKET.to_string(),
+            }]
+        );
+
+        // A fully matched order doesn't remain in the book
+        assert!(matching_engine.asks.is_empty());
+        assert!(matching_engine.bids.is_empty());
+    }
+
+    #[test]
+    fn test_MatchingEngine_process_fully_match_order_multi_match() {
+        let mut matching_engine = MatchingEngine::new();
+
+        let alice_receipt = matching_engine
+            .process(Order {
+                price: 10,
+                amount: 1,
+                side: Side::Sell,
+                signer: "ALICE".to_string(),
+                client_order_id: None,
+                signature: None,
+                order_type: OrderType::Limit,
+                time_in_force: TimeInForce::Gtc,
+                trigger_price: None,
+                market: DEFAULT_MARKET.to_string(),
+            })
+            .unwrap();
+        assert_eq!(alice_receipt.matches, vec![]);
+        assert_eq!(alice_receipt.ordinal, 1);
+
+        let charlie_receipt = matching_engine
+            .process(Order {
+                price: 10,
+                amount: 1,
+                side: Side::Sell,
+                signer: "CHARLIE".to_string(),
+                client_order_id: None,
+                signature: None,
+                order_type: OrderType::Limit,
+                time_in_force: TimeInForce::Gtc,
+                trigger_price: None,
+                market: DEFAULT_MARKET.to_string(),
+            })
+            .unwrap();
+        assert_eq!(charlie_receipt.matches, vec![]);
+        assert_eq!(charlie_receipt.ordinal, 2);
+
+        let bob_receipt = matching_engine
+            .process(Order {
+                price: 10,
+                amount: 2,
+                side: Side::Buy,
+                signer: "BOB".to_string(),
+                client_order_id: None,
+                signature: None,
+                order_type: OrderType::Limit,
+                time_in_force: TimeInForce::Gtc,
+                trigger_price: None,
+                market: DEFAULT_MARKET.to_string(),
+            })
+            .unwrap();
+
+        assert_eq!(
+            bob_receipt.matches,
+            vec![
+                PartialOrder {
+                    price: 10,
+                    amount: 1,
+                    remaining: 0,
+                    side: Side::Sell,
+                    signer: "ALICE".to_string(),
+                    ordinal: 1,
+                    order_id: 1,
+                    expires_at: None,
+                    market: DEFAULT_MARKET.to_string(),
+                },
+                PartialOrder {
+                    price: 10,
+                    amount: 1,
+                    remaining: 0,
+                    side: Side::Sell,
+                    signer: "CHARLIE".to_string(),
+                    ordinal: 2,
+                    order_id: 2,
+                    expires_at: None,
+                    market: DEFAULT_MARKET.to_string(),
+                }
+            ]
+        );
+        // A fully matched order doesn't remain in the book
+        assert!(matching_engine.asks.is_empty());
+        assert!(matching_engine.bids.is_empty());
+    }
+
+    #[test]
+    fn test_MatchingEngine_process_fully_match_order_multi_match_price_ranges_sell() {
+        let mut matching_engine = MatchingEngine::new();
+
+        let alice_receipt = matching_engine
+            .process(Order {
+                price: 11,
+                amount: 1,
+                side: Side::Sell,
+                signer: "ALICE".to_string(),
+                client_order_id: None,
+                signature: None,
+                order_type: OrderType::Limit,
+                time_in_force: TimeInForce::Gtc,
+                trigger_price: None,
+                market: DEFAULT_MARKET.to_string(),
+            })
+            .unwrap();
+        assert_eq!(alice_receipt.matches, vec![]);
+        assert_eq!(alice_receipt.ordinal, 1);
+
+        let charlie_receipt = matching_engine
+            .process(Order {
+                price: 9,
+                amount: 1,
+                side: Side::Sell,
+                signer: "CHARLIE".to_string(),
+                client_order_id: None,
+                signature: None,
+                order_type: OrderType::Limit,
+                time_in_force: TimeInForce::Gtc,
+                trigger_price: None,
+                market: DEFAULT_MARKET.to_string(),
+            })
+            .unwrap();
+        assert_eq!(charlie_receipt.matches, vec![]);
+        assert_eq!(charlie_receipt.ordinal, 2);
+
+        let bob_receipt = matching_engine
+            .process(Order {
+                price: 15,
+                amount: 2,
+                side: Side::Buy,
+                signer: "BOB".to_string(),
+                client_order_id: None,
+                signature: None,
+                order_type: OrderType::Limit,
+                time_in_force: TimeInForce::Gtc,
+                trigger_price: None,
+                market: DEFAULT_MARKET.to_string(),
+            })
+            .unwrap();
+
+        assert_eq!(
+            bob_receipt.matches,
+            vec![
+                PartialOrder {
+                    price: 9,
+                    amount: 1,
+                    remaining: 0,
+                    side: Side::Sell,
+                    signer: "CHARLIE".to_string(),
+                    ordinal: 2,
+                    order_id: 2,
+                    expires_at: None,
+                    market: DEFAULT_MARKET.to_string(),
+                },
+                PartialOrder {
+                    price: 11,
+                    amount: 1,
+                    remaining: 0,
+                    side: Side::Sell,
+                    signer: "ALICE".to_string(),
+                    ordinal: 1,
+                    order_id: 1,
+                    expires_at: None,
+                    market: DEFAULT_MARKET.to_string(),
+                }
+            ]
+        );
+        // A fully matched order doesn't remain in the book
+        assert!(matching_engine.asks.is_empty());
+        assert!(matching_engine.bids.is_empty());
+    }
+
+    #[test]
+    fn test_MatchingEngine_process_fully_match_order_multi_match_price_ranges_buy() {
+        let mut matching_engine = MatchingEngine::new();
+
+        let alice_receipt = matching_engine
+            .process(Order {
+                price: 10,
+                amount: 1,
+                side: Side::Buy,
+                signer: "ALICE".to_string(),
+                client_order_id: None,
+                signature: None,
+                order_type: OrderType::Limit,
+                time_in_force: TimeInForce::Gtc,
+                trigger_price: None,
+                market: DEFAULT_MARKET.to_string(),
+            })
+            .unwrap();
+        assert_eq!(alice_receipt.matches, vec![]);
+        assert_eq!(alice_receipt.ordinal, 1);
+
+        let charlie_receipt = matching_engine
+            .process(Order {
+                price: 5,
+                amount: 1,
+                side: Side::Buy,
+                signer: "CHARLIE".to_string(),
+                client_order_id: None,
+                signature: None,
+                order_type: OrderType::Limit,
+                time_in_force: TimeInForce::Gtc,
+                trigger_price: None,
+                market: DEFAULT_MARKET.to_string(),
+            })
+            .unwrap();
+        assert_eq!(charlie_receipt.matches, vec![]);
+        assert_eq!(charlie_receipt.ordinal, 2);
+
+        let bob_receipt = matching_engine
+            .process(Order {
+                price: 4,
+                amount: 2,
+                side: Side::Sell,
+                signer: "BOB".to_string(),
+                client_order_id: None,
+                signature: None,
+                order_type: OrderType::Limit,
+                time_in_force: TimeInForce::Gtc,
+                trigger_price: None,
+                market: DEFAULT_MARKET.to_string(),
+            })
+            .unwrap();
+
+        assert_eq!(
+            bob_receipt.matches,
+            // The highest bid first
+            vec![
+                PartialOrder {
+                    price: 10,
+                    amount: 1,
+                    remaining: 0,
+                    side: Side::Buy,
+                    signer: "ALICE".to_string(),
+                    ordinal: 1,
+                    order_id: 1,
+                    expires_at: None,
+                    market: DEFAULT_MARKET.to_string(),
+                },
+                PartialOrder {
+                    price: 5,
+                    amount: 1,
+                    remaining: 0,
+                    side: Side::Buy,
+                    signer: "CHARLIE".to_string(),
+                    ordinal: 2,
+                    order_id: 2,
+                    expires_at: None,
+                    market: DEFAULT_MARKET.to_string(),
+                },
+            ]
+        );
+        // A fully matched order doesn't remain in the book
+        assert!(matching_engine.asks.is_empty());
+        assert!(matching_engine.bids.is_empty());
+    }
+
+    #[test]
+    fn test_MatchingEngine_process_fully_match_order_no_self_match() {
+        let mut matching_engine = MatchingEngine::new();
+
+        let alice_receipt = matching_engine
+            .process(Order {
+                price: 10,
+                amount: 1,
+                side: Side::Sell,
+                signer: "ALICE".to_string(),
+                client_order_id: None,
+                signature: None,
+                order_type: OrderType::Limit,
+                time_in_force: TimeInForce::Gtc,
+                trigger_price: None,
+                market: DEFAULT_MARKET.to_string(),
+            })
+            .unwrap();
+        assert_eq!(alice_receipt.matches, vec![]);
+        assert_eq!(alice_receipt.ordinal, 1);
+
+        let charlie_receipt = matching_engine
+            .process(Order {
+                price: 10,
+                amount: 1,
+                side: Side::Sell,
+                signer: "CHARLIE".to_string(),
+                client_order_id: None,
+                signature: None,
+                order_type: OrderType::Limit,
+                time_in_force: TimeInForce::Gtc,
+                trigger_price: None,
+                market: DEFAULT_MARKET.to_string(),
+            })
+            .unwrap();
+        assert_eq!(charlie_receipt.matches, vec![]);
+        assert_eq!(charlie_receipt.ordinal, 2);
+
+        let alice_receipt = matching_engine
+            .process(Order {
+                price: 10,
+                amount: 2,
+                side: Side::Buy,
+                signer: "ALICE".to_string(),
+                client_order_id: None,
+                signature: None,
+                order_type: OrderType::Limit,
+                time_in_force: TimeInForce::Gtc,
+                trigger_price: None,
+                market: DEFAULT_MARKET.to_string(),
+            })
+            .unwrap();
+
+        assert_eq!(
+            alice_receipt.matches,
+            vec![PartialOrder {
+                price: 10,
+                amount: 1,
+                remaining: 0,
+                side: Side::Sell,
+                signer: "CHARLIE".to_string(),
+                ordinal: 2,
+                order_id: 2,
+                expires_at: None,
+                market: DEFAULT_MARKET.to_string(),
+            }]
+        );
+        // A fully matched order doesn't remain in the book
+        assert_eq!(matching_engine.asks.len(), 1);
+        assert_eq!(matching_engine.bids.len(), 1);
+    }
+
+    #[test]
+    fn test_MatchingEngine_process_resolves_self_matches_with_the_policy() {
+        let order = |amount, side, signer: &str| Order {
+            price: 10,
+            amount,
+            side,
+            signer: signer.to_string(),
+            client_order_id: None,
+            signature: None,
+            order_type: OrderType::Limit,
+            time_in_force: TimeInForce::Gtc,
+            trigger_price: None,
+            market: DEFAULT_MARKET.to_string(),
+        };
+        // (policy, resting cancelled, incoming cancelled, matched, bid left, asks left)
+        let cases = [
+            (SelfMatchPolicy::SkipResting, 0, 0, 1, 1, 3),
+            (SelfMatchPolicy::CancelResting, 3, 0, 1, 1, 0),
+            (SelfMatchPolicy::CancelIncoming, 0, 2, 0, 0, 4),
+            (SelfMatchPolicy::DecrementBoth, 2, 2, 0, 0, 2),
+        ];
+        for (policy, resting_cancelled, incoming_cancelled, filled, bid, asks) in cases {
+            let mut matching_engine = MatchingEngine::new();
+            matching_engine.set_self_match_policy(policy);
+            matching_engine
+                .process(order(3, Side::Sell, "ALICE"))
+                .unwrap();
+            matching_engine
+                .process(order(1, Side::Sell, "CHARLIE"))
+                .unwrap();
+
+            let incoming = order(2, Side::Buy, "ALICE");
+            // The preview resolves the self-match the same way
+            let previewed: u64 = matching_engine
+                .matches_of(&incoming)
+                .iter()
+                .map(|m| m.amount)
+                .sum();
+            let receipt = matching_engine.process(incoming).unwrap();
+            assert_eq!(previewed, filled, "{:?}", policy);
+            assert_eq!(
+                receipt.self_matches,
+                vec![SelfMatch {
+                    policy,
+                    resting_order_id: 1,
+                    price: 10,
+                    resting_cancelled,
+                    incoming_cancelled,
+                }],
+                "{:?}",
+                policy
+            );
+            assert_eq!(receipt.filled, filled, "{:?}", policy);
+            assert_eq!(receipt.cancelled, incoming_cancelled, "{:?}", policy);
+            assert_eq!(
+                matching_engine.level_quantity(&Side::Buy, 10),
+                bid,
+                "{:?}",
+                policy
+            );
+            assert_eq!(
+                matching_engine.level_quantity(&Side::Sell, 10),
+                asks,
+                "{:?}",
+                policy
+            );
+            assert_eq!(
+                matching_engine.order(1).is_some(),
+                resting_cancelled < 3,
+                "{:?}",
+                policy
+            );
+            assert_eq!(
+                walked_levels(&matching_engine.asks),
+                aggregated_levels(&matching_engine, &Side::Sell)
+            );
+        }
+    }
+
+    #[test]
+    fn test_MatchingEngine_process_matches_same_price_orders_first_in_first_out() {
+        let mut matching_engine = MatchingEngine::new();
+        let order = |signer: &str, side, amount| Order {
+            price: 10,
+            amount,
+            side,
+            signer: signer.to_string(),
+            client_order_id: None,
+            signature: None,
+            order_type: OrderType::Limit,
+            time_in_force: TimeInForce::Gtc,
+            trigger_price: None,
+            market: DEFAULT_MARKET.to_string(),
+        };
+        for (signer, amount) in [("A", 2), ("B", 1), ("C", 1), ("D", 1), ("E", 1)] {
+            matching_engine
+                .process(order(signer, Side::Sell, amount))
+                .unwrap();
+        }
+        let makers = |receipt: Receipt| {
+            receipt
+                .matches
+                .into_iter()
+                .map(|m| (m.signer, m.amount))
+                .collect::<Vec<_>>()
+        };
+        let queue = |matching_engine: &MatchingEngine| {
+            matching_engine.asks[&10]
+                .iter()
+                .map(|o| o.signer.clone())
+                .collect::<Vec<_>>()
+        };
+
+        // A partially filled order keeps its place at the front
+        let receipt = matching_engine.process(order("X", Side::Buy, 1)).unwrap();
+        assert_eq!(makers(receipt), vec![("A".to_string(), 1)]);
+        assert_eq!(queue(&matching_engine), vec!["A", "B", "C", "D", "E"]);
+
+        // The taker's own order is skipped but stays ahead of the ones after it
+        let receipt = matching_engine.process(order("C", Side::Buy, 3)).unwrap();
+        assert_eq!(
+            makers(receipt),
+            vec![
+                ("A".to_string(), 1),
+                ("B".to_string(), 1),
+                ("D".to_string(), 1)
+            ]
+        );
+        assert_eq!(queue(&matching_engine), vec!["C", "E"]);
+
+        let receipt = matching_engine.process(order("X", Side::Buy, 2)).unwrap();
+        assert_eq!(
+            makers(receipt),
+            vec![("C".to_string(), 1), ("E".to_string(), 1)]
+        );
+        assert!(matching_engine.asks.is_empty());
+    }
+
+    #[test]
+    fn test_MatchingEngine_cancel_all_removes_orders_of_signer() {
+        let mut matching_engine = MatchingEngine::new();
+        for (price, side, signer) in [
+            (10, Side::Sell, "ALICE"),
+            (10, Side::Sell, "BOB"),
+            (8, Side::Buy, "ALICE"),
+        ] {
+            matching_engine
+                .process(Order {
+                    price,
+                    amount: 1,
+                    side,
+                    signer: signer.to_string(),
+                    client_order_id: None,
+                    signature: None,
+                    order_type: OrderType::Limit,
+                    time_in_force: TimeInForce::Gtc,
+                    trigger_price: None,
+                    market: DEFAULT_MARKET.to_string(),
+                })
+                .unwrap();
+        }
+
+        let cancelled = matching_engine.cancel_all("ALICE");
+        assert_eq!(
+            cancelled.iter().map(|o| o.ordinal).collect::<Vec<_>>(),
+            vec![1, 3]
+        );
+        assert!(matching_engine.bids.is_empty());
+        assert_eq!(matching_engine.asks[&10].len(), 1);
+        assert!(matching_engine.cancel_all("ALICE").is_empty());
+    }
+
+    #[test]
+    fn test_MatchingEngine_restore_continues_from_the_snapshot() {
+        let mut matching_engine = MatchingEngine::new();
+        let order = |price, side, signer: &str, trigger_price| Order {
+            price,
+            amount: 2,
+            side,
+            signer: signer.to_string(),
+            client_order_id: None,
+            signature: None,
+            order_type: OrderType::Limit,
+            time_in_force: TimeInForce::Gtc,
+            trigger_price,
+            market: DEFAULT_MARKET.to_string(),
+        };
+        for (price, side, trigger_price) in [
+            (10, Side::Sell, None),
+            (11, Side::Sell, None),
+            (8, Side::Buy, None),
+            (12, Side::Buy, Some(11)),
+        ] {
+            matching_engine
+                .process(order(price, side, "ALICE", trigger_price))
+                .unwrap();
+        }
+        matching_engine
+            .process(order(10, Side::Buy, "BOB", None))
+            .unwrap();
+        let snapshot = matching_engine.snapshot();
+
+        let mut restored = MatchingEngine::new();
+        restored.restore(snapshot.clone());
+        assert_eq!(restored.snapshot(), snapshot);
+        assert_eq!(restored.ordinal, 5);
+        assert_eq!(
+            restored.levels(&Side::Sell),
+            matching_engine.levels(&Side::Sell)
+        );
+        assert_eq!(restored.order(2).map(|o| o.remaining), Some(2));
+        assert_eq!(restored.history.len(), 4);
+
+        // The next order matches the restored ask and triggers the restored stop
+        let receipt = restored.process(order(11, Side::Buy, "BOB", None)).unwrap();
+        assert_eq!((receipt.ordinal, receipt.filled), (6, 2));
+        assert_eq!(restored.triggered().len(), 1);
+        assert!(restored.stops().is_empty());
+    }
+
+    #[test]
+    fn test_MatchingEngine_preallocate_reuses_emptied_levels() {
+        let mut matching_engine = MatchingEngine::new();
+        matching_engine.preallocate(BookCapacity {
+            levels: 1,
+            orders_per_level: 4,
+        });
+        assert_eq!(matching_engine.pool_stats().idle, 2);
+
+        for (price, amount, side) in [
+            (10, 1, Side::Sell),
+            (8, 1, Side::Buy),
+            (12, 1, Side::Sell),
+            (12, 2, Side::Buy),
+        ] {
+            matching_engine
+                .process(Order {
+                    price,
+                    amount,
+                    side,
+                    signer: format!("SIGNER{price}{amount}"),
+                    client_order_id: None,
+                    signature: None,
+                    order_type: OrderType::Limit,
+                    time_in_force: TimeInForce::Gtc,
+                    trigger_price: None,
+                    market: DEFAULT_MARKET.to_string(),
+                })
+                .unwrap();
+        }
+        assert!(matching_engine.asks.is_empty());
+        assert!(matching_engine.bids[&8].capacity() >= 4);
+        assert_eq!(
+            matching_engine.pool_stats(),
+            PoolStats {
+                preallocated: 2,
+                reused: 2,
+                allocated: 1,
+                idle: 2,
+            }
+        );
+
+        // The pool doesn't grow past the preallocated levels
+        matching_engine.cancel_all("SIGNER81");
+        assert_eq!(matching_engine.pool_stats().idle, 2);
+    }
+
+    #[test]
+    fn test_MatchingEngine_cancel_removes_only_own_orders() {
+        let mut matching_engine = MatchingEngine::new();
+        for (price, side) in [(10, Side::Sell), (8, Side::Buy)] {
+            matching_engine
+                .process(Order {
+                    price,
+                    amount: 2,
+                    side,
+                    signer: "ALICE".to_string(),
+                    client_order_id: None,
+                    signature: None,
+                    order_type: OrderType::Limit,
+                    time_in_force: TimeInForce::Gtc,
+                    trigger_price: None,
+                    market: DEFAULT_MARKET.to_string(),
+                })
+                .unwrap();
+        }
+
+        assert_eq!(
+            matching_engine.cancel(1, "BOB"),
+            Err(ApplicationError::OrderNotOwned(1))
+        );
+        let cancelled = matching_engine.cancel(1, "ALICE").unwrap();
+        assert_eq!((cancelled.price, cancelled.remaining), (10, 2));
+        assert!(matching_engine.asks.is_empty());
+        assert_eq!(matching_engine.level_quantity(&Side::Sell, 10), 0);
+        assert_eq!(matching_engine.bids[&8].len(), 1);
+        assert_eq!(
+            matching_engine.cancel(1, "ALICE"),
+            Err(ApplicationError::OrderNotFound(1))
+        );
+    }
+
+    #[test]
+    fn test_MatchingEngine_order_finds_resting_orders_by_id() {
+        let mut matching_engine = MatchingEngine::new();
+        let order = |price, side, signer: &str| Order {
+            price,
+            amount: 1,
+            side,
+            signer: signer.to_string(),
+            client_order_id: None,
+            signature: None,
+            order_type: OrderType::Limit,
+            time_in_force: TimeInForce::Gtc,
+            trigger_price: None,
+            market: DEFAULT_MARKET.to_string(),
+        };
+        let ask = matching_engine
+            .process(Order {
+                amount: 2,
+                ..order(10, Side::Sell, "ALICE")
+            })
+            .unwrap();
+        assert_eq!(matching_engine.order(ask.order_id).unwrap().remaining, 2);
+
+        let bid = matching_engine
+            .process(order(10, Side::Buy, "BOB"))
+            .unwrap();
+        assert_eq!(bid.matches[0].order_id, ask.order_id);
+        assert_eq!(matching_engine.order(bid.order_id), None);
+        assert_eq!(matching_engine.order(ask.order_id).unwrap().remaining, 1);
+
+        matching_engine
+            .process(order(10, Side::Buy, "BOB"))
+            .unwrap();
+        assert_eq!(matching_engine.order(ask.order_id), None);
+        let resting = matching_engine.process(order(9, Side::Buy, "BOB")).unwrap();
+        assert_eq!(matching_engine.order(resting.order_id).unwrap().price, 9);
+        assert_eq!(matching_engine.index.len(), 1);
+    }
+
+    #[test]
+    fn test_MatchingEngine_amend_keeps_priority_only_for_smaller_amounts() {
+        let mut matching_engine = MatchingEngine::new();
+        let order = |price, amount, side, signer: &str| Order {
+            price,
+            amount,
+            side,
+            signer: signer.to_string(),
+            client_order_id: None,
+            signature: None,
+            order_type: OrderType::Limit,
+            time_in_force: TimeInForce::Gtc,
+            trigger_price: None,
+            market: DEFAULT_MARKET.to_string(),
+        };
+        let first = matching_engine
+            .process(order(10, 3, Side::Sell, "ALICE"))
+            .unwrap();
+        let second = matching_engine
+            .process(order(10, 3, Side::Sell, "BOB"))
+            .unwrap();
+        assert_eq!(
+            matching_engine.amend(first.order_id, 10, 0),
+            Err(ApplicationError::InvalidAmendment(
+                "the amount has to be above zero, cancel the order instead".to_string()
+            ))
+        );
+        assert_eq!(
+            matching_engine.amend(42, 10, 1),
+            Err(ApplicationError::OrderNotFound(42))
+        );
+
+        // A smaller amount stays first in line
+        let receipt = matching_engine.amend(first.order_id, 10, 2).unwrap();
+        assert_eq!(receipt.ordinal, first.ordinal);
+        assert_eq!(matching_engine.history.last(), Some(&receipt));
+        assert_eq!(matching_engine.level_quantity(&Side::Sell, 10), 5);
+        assert_eq!(
+            matching_engine
+                .queue_position(first.order_id)
+                .unwrap()
+                .ahead_orders,
+            0
+        );
+
+        // A larger amount goes behind the other orders at the price
+        let receipt = matching_engine.amend(first.order_id, 10, 4).unwrap();
+        assert_eq!(receipt.order_id, first.order_id);
+        assert!(receipt.ordinal > second.ordinal);
+        let position = matching_engine.queue_position(first.order_id).unwrap();
+        assert_eq!((position.ahead_orders, position.remaining), (1, 4));
+        assert_eq!(matching_engine.level_quantity(&Side::Sell, 10), 7);
+
+        // A new price matches like a new order
+        matching_engine
+            .process(order(8, 1, Side::Buy, "CAROL"))
+            .unwrap();
+        let receipt = matching_engine.amend(first.order_id, 8, 4).unwrap();
+        assert_eq!(receipt.filled, 1);
+        assert_eq!(receipt.matches[0].signer, "CAROL");
+        assert_eq!(matching_engine.order(first.order_id).unwrap().remaining, 3);
+        assert_eq!(matching_engine.level_quantity(&Side::Sell, 10), 3);
+    }
+
+    #[test]
+    fn test_MatchingEngine_queue_position_counts_the_orders_ahead() {
+        let mut matching_engine = MatchingEngine::new();
+        let order = |amount, signer: &str| Order {
+            price: 10,
+            amount,
+            side: Side::Sell,
+            signer: signer.to_string(),
+            client_order_id: None,
+            signature: None,
+            order_type: OrderType::Limit,
+            time_in_force: TimeInForce::Gtc,
+            trigger_price: None,
+            market: DEFAULT_MARKET.to_string(),
+        };
+        let first = matching_engine.process(order(2, "ALICE")).unwrap();
+        let second = matching_engine.process(order(3, "BOB")).unwrap();
+        let third = matching_engine.process(order(4, "ALICE")).unwrap();
+
+        let position = matching_engine.queue_position(third.order_id).unwrap();
+        assert_eq!((position.ahead_quantity, position.ahead_orders), (5, 2));
+        assert_eq!((position.level_quantity, position.level_orders), (9, 3));
+        assert_eq!(position.remaining, 4);
+
+        // Fills and cancellations move the order up
+        matching_engine
+            .process(Order {
+                side: Side::Buy,
+                ..order(1, "CAROL")
+            })
+            .unwrap();
+        matching_engine.cancel(second.order_id, "BOB").unwrap();
+        let position = matching_engine.queue_position(third.order_id).unwrap();
+        assert_eq!((position.ahead_quantity, position.ahead_orders), (1, 1));
+        assert_eq!((position.level_quantity, position.level_orders), (5, 2));
+        assert_eq!(
+            matching_engine
+                .queue_position(first.order_id)
+                .unwrap()
+                .ahead_orders,
+            0
+        );
+        assert_eq!(matching_engine.queue_position(second.order_id), None);
+    }
+
+    #[test]
+    fn test_MatchingEngine_process_quote_replaces_the_previous_quote() {
+        let mut matching_engine = MatchingEngine::new();
+        let order = |price, side, signer: &str| Order {
+            price,
+            amount: 2,
+            side,
+            signer: signer.to_string(),
+            client_order_id: None,
+            signature: None,
+            order_type: OrderType::Limit,
+            time_in_force: TimeInForce::Gtc,
+            trigger_price: None,
+            market: DEFAULT_MARKET.to_string(),
+        };
+        matching_engine
+            .process(order(12, Side::Sell, "ALICE"))
+            .unwrap();
+        let first = matching_engine
+            .process_quote(
+                order(9, Side::Buy, "ALICE"),
+                order(11, Side::Sell, "ALICE"),
+                &[],
+            )
+            .unwrap();
+        assert_eq!((first.bid.ordinal, first.ask.ordinal), (2, 3));
+
+        // Only the replaced orders of the quoting account are cancelled
+        let second = matching_engine
+            .process_quote(
+                order(10, Side::Buy, "ALICE"),
+                order(13, Side::Sell, "ALICE"),
+                &[1, 2, 3],
+            )
+            .unwrap();
+        assert_eq!(
+            second
+                .cancelled
+                .iter()
+                .map(|o| o.ordinal)
+                .collect::<Vec<_>>(),
+            vec![1, 2, 3]
+        );
+        assert_eq!(
+            matching_engine
+                .orders()
+                .iter()
+                .map(|o| (o.ordinal, o.price))
+                .collect::<Vec<_>>(),
+            vec![(5, 13), (4, 10)]
+        );
+
+        assert_eq!(
+            matching_engine.process_quote(
+                order(13, Side::Buy, "ALICE"),
+                order(13, Side::Sell, "ALICE"),
+                &[4, 5],
+            ),
+            Err(ApplicationError::InvalidQuote(
+                "the bid price 13 isn't below the ask price 13".to_string()
+            ))
+        );
+        assert_eq!(matching_engine.orders().len(), 2);
+        assert_eq!(matching_engine.ordinal, 5);
+    }
+
+    #[test]
+    fn test_MatchingEngine_orders_matching_limits_side_and_price_range() {
+        let mut matching_engine = MatchingEngine::new();
+        for (price, side) in [
+            (8, Side::Buy),
+            (9, Side::Buy),
+            (9, Side::Buy),
+            (11, Side::Sell),
+            (12, Side::Sell),
+            (14, Side::Sell),
+        ] {
+            matching_engine
+                .process(Order {
+                    price,
+                    amount: 1,
+                    side,
+                    signer: "ALICE".to_string(),
+                    client_order_id: None,
+                    signature: None,
+                    order_type: OrderType::Limit,
+                    time_in_force: TimeInForce::Gtc,
+                    trigger_price: None,
+                    market: DEFAULT_MARKET.to_string(),
+                })
+                .unwrap();
+        }
+        let prices = |query: BookQuery| {
+            matching_engine
+                .orders_matching(&query)
+                .iter()
+                .map(|o| o.price)
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(prices(BookQuery::default()), [11, 12, 14, 8, 9, 9]);
+        let best = BookQuery {
+            limit: Some(1),
+            ..BookQuery::default()
+        };
+        assert_eq!(prices(best.clone()), [11, 9, 9]);
+        assert_eq!(
+            prices(BookQuery {
+                side: Some(BookSide::Ask),
+                min_price: Some(12),
+                ..BookQuery::default()
+            }),
+            [12, 14]
+        );
+        assert_eq!(
+            prices(BookQuery {
+                min_price: Some(10),
+                max_price: Some(9),
+                ..BookQuery::default()
+            }),
+            Vec::<u64>::new()
+        );
+        assert_eq!(
+            matching_engine.levels_matching(&Side::Buy, &best),
+            vec![PriceLevel {
+                price: 9,
+                quantity: 2
+            }]
+        );
+        assert!(matching_engine
+            .levels_matching(
+                &Side::Sell,
+                &BookQuery {
+                    side: Some(BookSide::Bid),
+                    ..BookQuery::default()
+                }
+            )
+            .is_empty());
+    }
+
+    #[test]
+    fn test_MatchingEngine_process_no_match() {
+        let mut matching_engine = MatchingEngine::new();
+
+        let alice_receipt = matching_engine
+            .process(Order {
+                price: 10,
+                amount: 2,
+                side: Side::Sell,
+                signer: "ALICE".to_string(),
+                client_order_id: None,
+                signature: None,
+                order_type: OrderType::Limit,
+                time_in_force: TimeInForce::Gtc,
+                trigger_price: None,
+                market: DEFAULT_MARKET.to_string(),
+            })
+            .unwrap();
+        assert_eq!(alice_receipt.matches, vec![]);
+        assert_eq!(alice_receipt.ordinal, 1);
+
+        let bob_receipt = matching_engine
+            .process(Order {
+                price: 11,
+                amount: 2,
+                side: Side::Sell,
+                signer: "BOB".to_string(),
+                client_order_id: None,
+                signature: None,
+                order_type: OrderType::Limit,
+                time_in_force: TimeInForce::Gtc,
+                trigger_price: None,
+                market: DEFAULT_MARKET.to_string(),
+            })
+            .unwrap();
+
+        assert_eq!(bob_receipt.matches, vec![]);
+        assert_eq!(matching_engine.asks.len(), 2);
+    }
+
+    #[test]
+    fn test_MatchingEngine_process_increment_ordinal_matching_engine() {
+        let mut matching_engine = MatchingEngine::new();
+        assert_eq!(matching_engine.ordinal, 0);
+        let receipt = matching_engine
+            .process(Order {
+                price: 10,
+                amount: 1,
+                side: Side::Buy,
+                signer: "ALICE".to_string(),
+                client_order_id: None,
+                signature: None,
+                order_type: OrderType::Limit,
+                time_in_force: TimeInForce::Gtc,
+                trigger_price: None,
+                market: DEFAULT_MARKET.to_string(),
+            })
+            .unwrap();
+        assert_eq!(receipt.ordinal, matching_engine.ordinal);
+
+        let receipt = matching_engine
+            .process(Order {
+                price: 10,
+                amount: 1,
+                side: Side::Buy,
+                signer: "BOB".to_string(),
+                client_order_id: None,
+                signature: None,
+                order_type: OrderType::Limit,
+                time_in_force: TimeInForce::Gtc,
+                trigger_price: None,
+                market: DEFAULT_MARKET.to_string(),
+            })
+            .unwrap();
+        assert_eq!(receipt.ordinal, matching_engine.ordinal);
+
+        let receipt = matching_engine
+            .process(Order {
+                price: 10,
+                amount: 1,
+                side: Side::Buy,
+                signer: "CHARLIE".to_string(),
+                client_order_id: None,
+                signature: None,
+                order_type: OrderType::Limit,
+                time_in_force: TimeInForce::Gtc,
+                trigger_price: None,
+                market: DEFAULT_MARKET.to_string(),
+            })
+            .unwrap();
+        assert_eq!(receipt.ordinal, matching_engine.ordinal);
+        assert_eq!(matching_engine.ordinal, 3);
+    }
+
+    /// The levels of one side computed from the orders, like the engine did before it kept aggregates
+    fn walked_levels(book: &BTreeMap<u64, VecDeque<PartialOrder>>) -> Vec<(u64, u64, usize)> {
+        book.iter()
+            .map(|(price, orders)| {
+                let quantity = orders.iter().map(|o| o.remaining).sum();
+                (*price, quantity, orders.len())
+            })
+            .collect()
+    }
+
+    fn aggregated_levels(matching_engine: &MatchingEngine, side: &Side) -> Vec<(u64, u64, usize)> {
+        let mut levels: Vec<_> = matching_engine
+            .levels(side)
+            .into_iter()
+            .map(|level| {
+                let aggregate = matching_engine.level_aggregate(side, level.price).unwrap();
+                (level.price, aggregate.quantity, aggregate.orders)
+            })
+            .collect();
+        levels.sort();
+        levels
+    }
+
+    #[test]
+    fn test_MatchingEngine_level_aggregates_follow_the_orders() {
+        let order = |signer: &str, side: Side, price: u64, amount: u64| Order {
+            price,
+            amount,
+            side,
+            signer: signer.to_string(),
+            client_order_id: None,
+            signature: None,
+            order_type: OrderType::Limit,
+            time_in_force: TimeInForce::Gtc,
+            trigger_price: None,
+            market: DEFAULT_MARKET.to_string(),
+        };
+        let mut matching_engine = MatchingEngine::new();
+        let flow = [
+            order("ALICE", Side::Sell, 12, 3),
+            order("BOB", Side::Sell, 12, 2),
+            order("ALICE", Side::Sell, 14, 5),
+            order("CAROL", Side::Buy, 9, 4),
+            order("CAROL", Side::Buy, 10, 1),
+            // Partially fills ALICE at 12
+            order("DAVE", Side::Buy, 12, 1),
+            // Takes the rest of 12
+            order("CAROL", Side::Buy, 13, 4),
+            // Skips its own bid at 10 and rests
+            order("CAROL", Side::Sell, 11, 1),
+            order("ALICE", Side::Sell, 10, 1),
+        ];
+        for o in flow {
+            matching_engine.process(o).unwrap();
+            for (side, book) in [
+                (Side::Buy, &matching_engine.bids),
+                (Side::Sell, &matching_engine.asks),
+            ] {
+                assert_eq!(
+                    aggregated_levels(&matching_engine, &side),
+                    walked_levels(book)
+                );
+            }
+        }
+        assert_eq!(
+            matching_engine.best_bid_offer(),
+            (
+                Some(PriceLevel {
+                    price: 9,
+                    quantity: 4
+                }),
+                Some(PriceLevel {
+                    price: 11,
+                    quantity: 1
+                })
+            )
+        );
+
+        matching_engine.cancel_all("CAROL");
+        assert_eq!(aggregated_levels(&matching_engine, &Side::Buy), vec![]);
+        assert_eq!(matching_engine.best_bid(), None);
+        assert_eq!(
+            matching_engine.best_ask(),
+            Some(PriceLevel {
+                price: 14,
+                quantity: 5
+            })
+        );
+        assert_eq!(matching_engine.order_count(&Side::Buy), 0);
+        assert_eq!(matching_engine.level_aggregate(&Side::Buy, 9), None);
+        let restored = MatchingEngine::from_book(
+            matching_engine.ordinal,
+            matching_engine.bids.clone(),
+            matching_engine.asks.clone(),
+        );
+        assert_eq!(
+            restored.levels(&Side::Sell),
+            matching_engine.levels(&Side::Sell)
+        );
+        assert_eq!(restored.order_count(&Side::Sell), 1);
+    }
+
+    /// Records every notification as a line of text
+    struct Recorder(std::sync::Arc<std::sync::Mutex<Vec<String>>>);
+
+    impl EngineObserver for Recorder {
+        fn on_order_accepted(&mut self, order: &PartialOrder) {
+            let line = format!("accepted {} {}", order.ordinal, order.amount);
+            self.0.lock().unwrap().push(line);
+        }
+
+        fn on_trade(&mut self, taker: &PartialOrder, maker: &PartialOrder) {
+            let line = format!(
+                "trade {}<-{} {}@{}",
+                taker.signer, maker.signer, maker.amount, maker.price
+            );
+            self.0.lock().unwrap().push(line);
+        }
+
+        fn on_cancel(&mut self, order: &PartialOrder) {
+            let line = format!("cancel {}", order.ordinal);
+            self.0.lock().unwrap().push(line);
+        }
+
+        fn on_book_change(&mut self, side: &Side, level: &PriceLevel) {
+            let line = format!("level {:?} {}={}", side, level.price, level.quantity);
+            self.0.lock().unwrap().push(line);
+        }
+    }
+
+    #[test]
+    fn test_MatchingEngine_register_observer_notifies_every_change() {
+        let order = |signer: &str, side: Side, price: u64, amount: u64| Order {
+            price,
+            amount,
+            side,
+            signer: signer.to_string(),
+            client_order_id: None,
+            signature: None,
+            order_type: OrderType::Limit,
+            time_in_force: TimeInForce::Gtc,
+            trigger_price: None,
+            market: DEFAULT_MARKET.to_string(),
+        };
+        let lines = std::sync::Arc::default();
+        let mut matching_engine = MatchingEngine::new();
+        matching_engine.register_observer(Box::new(Recorder(std::sync::Arc::clone(&lines))));
+
+        matching_engine
+            .process(order("ALICE", Side::Sell, 10, 2))
+            .unwrap();
+        matching_engine
+            .process(order("ALICE", Side::Sell, 11, 2))
+            .unwrap();
+        matching_engine
+            .process(order("BOB", Side::Buy, 11, 3))
+            .unwrap();
+        matching_engine
+            .process(order("CAROL", Side::Buy, 9, 1))
+            .unwrap();
+        matching_engine.cancel_all("ALICE");
+        assert_eq!(
+            *lines.lock().unwrap(),
+            vec![
+                "accepted 1 2",
+                "level Sell 10=2",
+                "accepted 2 2",
+                "level Sell 11=2",
+                "accepted 3 3",
+                "trade BOB<-ALICE 2@10",
+                "trade BOB<-ALICE 1@11",
+                "level Sell 10=0",
+                "level Sell 11=1",
+                "accepted 4 1",
+                "level Buy 9=1",
+                "cancel 2",
+                "level Sell 11=0",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_MatchingEngine_register_sink_sends_the_events() {
+        let order = |signer: &str, side: Side, price: u64, time_in_force| Order {
+            price,
+            amount: 2,
+            side,
+            signer: signer.to_string(),
+            client_order_id: None,
+            signature: None,
+            order_type: OrderType::Limit,
+            time_in_force,
+            trigger_price: None,
+            market: DEFAULT_MARKET.to_string(),
+        };
+        let (sender, events) = std::sync::mpsc::channel();
+        let mut matching_engine = MatchingEngine::new();
+        matching_engine.register_sink(Box::new(sender));
+
+        matching_engine
+            .process(order("ALICE", Side::Sell, 10, TimeInForce::Gtc))
+            .unwrap();
+        matching_engine
+            .process(order("BOB", Side::Buy, 10, TimeInForce::Gtc))
+            .unwrap();
+        matching_engine
+            .process(order("ALICE", Side::Sell, 11, TimeInForce::Gtt(1_000)))
+            .unwrap();
+        matching_engine
+            .process(order("ALICE", Side::Sell, 12, TimeInForce::Gtc))
+            .unwrap();
+        matching_engine.expire(1_000);
+        matching_engine.cancel_all("ALICE");
+
+        let events: Vec<String> = events
+            .try_iter()
+            .map(|event| match event {
+                EngineEvent::OrderAccepted { order } => format!("accepted {}", order.ordinal),
+                EngineEvent::Trade { taker, maker } => {
+                    format!("trade {}<-{}", taker.ordinal, maker.ordinal)
+                }
+                EngineEvent::OrderCancelled { order } => format!("cancelled {}", order.ordinal),
+                EngineEvent::OrderExpired { order } => format!("expired {}", order.ordinal),
+            })
+            .collect();
+        assert_eq!(
+            events,
+            vec![
+                "accepted 1",
+                "accepted 2",
+                "trade 2<-1",
+                "accepted 3",
+                "accepted 4",
+                "expired 3",
+                "cancelled 4",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_MatchingEngine_process_market_order_sweeps_without_resting() {
+        let mut matching_engine = MatchingEngine::new();
+        let order = |price, amount, side, signer: &str, order_type| Order {
+            price,
+            amount,
+            side,
+            signer: signer.to_string(),
+            client_order_id: None,
+            signature: None,
+            order_type,
+            time_in_force: TimeInForce::Gtc,
+            trigger_price: None,
+            market: DEFAULT_MARKET.to_string(),
+        };
+        for (price, amount) in [(10, 2), (12, 3), (15, 1)] {
+            matching_engine
+                .process(order(price, amount, Side::Sell, "ALICE", OrderType::Limit))
+                .unwrap();
+        }
+        let market_buy = order(0, 7, Side::Buy, "BOB", OrderType::Market);
+        assert_eq!(
+            matching_engine.fills_of(&market_buy),
+            vec![
+                PriceLevel {
+                    price: 10,
+                    quantity: 2
+                },
+                PriceLevel {
+                    price: 12,
+                    quantity: 3
+                },
+                PriceLevel {
+                    price: 15,
+                    quantity: 1
+                },
+            ]
+        );
+
+        let receipt = matching_engine.process(market_buy).unwrap();
+        assert_eq!(receipt.matches.iter().map(|m| m.amount).sum::<u64>(), 6);
+        // The unfilled unit is dropped instead of resting at price 0
+        assert_eq!((receipt.filled, receipt.cancelled), (6, 1));
+        assert!(matching_engine.asks.is_empty());
+        assert!(matching_engine.bids.is_empty());
+    }
+
+    #[test]
+    fn test_MatchingEngine_process_executes_at_the_resting_price() {
+        let mut matching_engine = MatchingEngine::new();
+        let order = |signer: &str, side, price, order_type| Order {
+            price,
+            amount: 1,
+            side,
+            signer: signer.to_string(),
+            client_order_id: None,
+            signature: None,
+            order_type,
+            time_in_force: TimeInForce::Gtc,
+            trigger_price: None,
+            market: DEFAULT_MARKET.to_string(),
+        };
+        // A buy at 12 meets an ask at 10 and pays 10
+        matching_engine
+            .process(order("ALICE", Side::Sell, 10, OrderType::Limit))
+            .unwrap();
+        let buy = matching_engine
+            .process(order("BOB", Side::Buy, 12, OrderType::Limit))
+            .unwrap();
+        assert_eq!(buy.limit_price, Some(12));
+        assert_eq!(
+            buy.matches.iter().map(|m| m.price).collect::<Vec<_>>(),
+            vec![10]
+        );
+
+        // A sell at 10 meets a bid at 12 and gets 12
+        matching_engine
+            .process(order("ALICE", Side::Buy, 12, OrderType::Limit))
+            .unwrap();
+        let sell = matching_engine
+            .process(order("BOB", Side::Sell, 10, OrderType::Limit))
+            .unwrap();
+        assert_eq!(sell.limit_price, Some(10));
+        assert_eq!(
+            sell.matches.iter().map(|m| m.price).collect::<Vec<_>>(),
+            vec![12]
+        );
+        assert_eq!(matching_engine.last_price(), Some(12));
+
+        // Market orders have no limit
+        matching_engine
+            .process(order("ALICE", Side::Sell, 11, OrderType::Limit))
+            .unwrap();
+        let market = matching_engine
+            .process(order("BOB", Side::Buy, 0, OrderType::Market))
+            .unwrap();
+        assert_eq!((market.limit_price, market.matches[0].price), (None, 11));
+    }
+
+    #[test]
+    fn test_MatchingEngine_process_totals_the_matches_of_the_receipt() {
+        let mut matching_engine = MatchingEngine::new();
+        let order = |price, amount, side, signer: &str, time_in_force| Order {
+            price,
+            amount,
+            side,
+            signer: signer.to_string(),
+            client_order_id: None,
+            signature: None,
+            order_type: OrderType::Limit,
+            time_in_force,
+            trigger_price: None,
+            market: DEFAULT_MARKET.to_string(),
+        };
+        let resting = matching_engine
+            .process(order(10, 2, Side::Sell, "ALICE", TimeInForce::Gtc))
+            .unwrap();
+        assert_eq!(
+            (resting.remaining, resting.average_price, resting.status),
+            (2, None, ReceiptStatus::Resting)
+        );
+        matching_engine
+            .process(order(11, 1, Side::Sell, "ALICE", TimeInForce::Gtc))
+            .unwrap();
+
+        // 2 at 10 and 1 at 11 average to 10 after rounding down, 2 units rest
+        let buy = matching_engine
+            .process(order(11, 5, Side::Buy, "BOB", TimeInForce::Gtc))
+            .unwrap();
+        assert_eq!(
+            (
+                buy.filled,
+                buy.remaining,
+                buy.total_notional,
+                buy.average_price
+            ),
+            (3, 2, 31, Some(10))
+        );
+        assert_eq!(buy.status, ReceiptStatus::PartiallyFilled);
+
+        let sell = matching_engine
+            .process(order(11, 2, Side::Sell, "ALICE", TimeInForce::Gtc))
+            .unwrap();
+        assert_eq!(
+            (sell.remaining, sell.average_price, sell.status),
+            (0, Some(11), ReceiptStatus::Filled)
+        );
+
+        let ioc = matching_engine
+            .process(order(9, 1, Side::Buy, "BOB", TimeInForce::Ioc))
+            .unwrap();
+        assert_eq!(
+            (ioc.cancelled, ioc.remaining, ioc.status),
+            (1, 0, ReceiptStatus::Cancelled)
+        );
+    }
+
+    #[test]
+    fn test_MatchingEngine_process_ioc_order_cancels_the_remainder() {
+        let mut matching_engine = MatchingEngine::new();
+        let order = |price, amount, side, signer: &str, time_in_force| Order {
+            price,
+            amount,
+            side,
+            signer: signer.to_string(),
+            client_order_id: None,
+            signature: None,
+            order_type: OrderType::Limit,
+            time_in_force,
+            trigger_price: None,
+            market: DEFAULT_MARKET.to_string(),
+        };
+        for price in [10, 11, 13] {
+            matching_engine
+                .process(order(price, 1, Side::Sell, "ALICE", TimeInForce::Gtc))
+                .unwrap();
+        }
+
+        let receipt = matching_engine
+            .process(order(12, 5, Side::Buy, "BOB", TimeInForce::Ioc))
+            .unwrap();
+        assert_eq!((receipt.filled, receipt.cancelled), (2, 3));
+        assert!(matching_engine.bids.is_empty());
+        assert_eq!(matching_engine.order(receipt.order_id), None);
+
+        // Nothing to cancel once the order is filled
+        let receipt = matching_engine
+            .process(order(13, 1, Side::Buy, "BOB", TimeInForce::Ioc))
+            .unwrap();
+        assert_eq!((receipt.filled, receipt.cancelled), (1, 0));
+
+        let receipt = matching_engine
+            .process(order(9, 2, Side::Buy, "BOB", TimeInForce::Gtc))
+            .unwrap();
+        assert_eq!((receipt.filled, receipt.cancelled), (0, 0));
+        assert_eq!(matching_engine.bids[&9].len(), 1);
+    }
+
+    #[test]
+    fn test_MatchingEngine_expire_removes_gtt_orders_past_their_expiry() {
+        let mut matching_engine = MatchingEngine::new();
+        let order = |price, time_in_force| Order {
+            price,
+            amount: 2,
+            side: Side::Sell,
+            signer: "ALICE".to_string(),
+            client_order_id: None,
+            signature: None,
+            order_type: OrderType::Limit,
+            time_in_force,
+            trigger_price: None,
+            market: DEFAULT_MARKET.to_string(),
+        };
+        let expiring = matching_engine
+            .process(order(10, TimeInForce::Gtt(1_000)))
+            .unwrap();
+        matching_engine
+            .process(order(11, TimeInForce::Gtt(2_000)))
+            .unwrap();
+        matching_engine
+            .process(order(12, TimeInForce::Gtc))
+            .unwrap();
+        assert_eq!(
+            matching_engine.order(expiring.order_id).unwrap().expires_at,
+            Some(1_000)
+        );
+
+        assert!(matching_engine.expire(999).is_empty());
+        let expired = matching_engine.expire(1_000);
+        assert_eq!(expired.len(), 1);
+        assert_eq!(expired[0].order_id, expiring.order_id);
+        assert_eq!(matching_engine.order(expiring.order_id), None);
+        assert_eq!(matching_engine.level_quantity(&Side::Sell, 10), 0);
+
+        assert_eq!(matching_engine.expire(u64::MAX).len(), 1);
+        assert_eq!(matching_engine.asks.keys().collect::<Vec<_>>(), vec![&12]);
+    }
+
+    #[test]
+    fn test_MatchingEngine_process_triggers_stop_orders_after_a_trade() {
+        let mut matching_engine = MatchingEngine::new();
+        let order = |signer: &str, side, price, order_type, trigger_price| Order {
+            price,
+            amount: 1,
+            side,
+            signer: signer.to_string(),
+            client_order_id: None,
+            signature: None,
+            order_type,
+            time_in_force: TimeInForce::Gtc,
+            trigger_price,
+            market: DEFAULT_MARKET.to_string(),
+        };
+        for price in [10, 10, 12] {
+            matching_engine
+                .process(order("ALICE", Side::Sell, price, OrderType::Limit, None))
+                .unwrap();
+        }
+        let stop_loss = matching_engine
+            .process(order("CAROL", Side::Buy, 0, OrderType::Market, Some(10)))
+            .unwrap();
+        let stop_limit = matching_engine
+            .process(order("DAVE", Side::Sell, 9, OrderType::Limit, Some(9)))
+            .unwrap();
+        let cancelled = matching_engine
+            .process(order("EVE", Side::Buy, 0, OrderType::Market, Some(10)))
+            .unwrap();
+        assert!(stop_loss.matches.is_empty());
+        assert_eq!(matching_engine.stops().len(), 3);
+        assert_eq!(
+            matching_engine
+                .cancel(cancelled.order_id, "EVE")
+                .unwrap()
+                .order_id,
+            cancelled.order_id
+        );
+
+        let receipt = matching_engine
+            .process(order("BOB", Side::Buy, 10, OrderType::Limit, None))
+            .unwrap();
+        assert_eq!(receipt.filled, 1);
+        let triggered = matching_engine.triggered();
+        assert_eq!(triggered.len(), 1);
+        assert_eq!(triggered[0].order.signer, "CAROL");
+        assert_eq!(triggered[0].receipt.ordinal, stop_loss.ordinal);
+        assert_eq!(triggered[0].receipt.matches[0].price, 10);
+        assert_eq!(matching_engine.level_quantity(&Side::Sell, 10), 0);
+        // The sell stop waits for the price to fall to 9
+        assert_eq!(
+            matching_engine.stops().keys().collect::<Vec<_>>(),
+            vec![&stop_limit.order_id]
+        );
+
+        let cancelled = matching_engine.cancel_all("DAVE");
+        assert_eq!(cancelled.len(), 1);
+        assert_eq!(cancelled[0].order_id, stop_limit.order_id);
+        assert!(matching_engine.stops().is_empty());
+    }
+
+    /// Admits the buy stops whose fills cost at most the budget of their signer
+    #[derive(Debug)]
+    struct Budgets(HashMap<String, u64>);
+
+    impl StopGuard for Budgets {
+        fn admit(&mut self, _order_id: OrderId, order: &Order, fills: &[PriceLevel]) -> bool {
+            let cost: u64 = fills.iter().map(|fill| fill.price * fill.quantity).sum();
+            order.side == Side::Sell || self.0.get(&order.signer).is_some_and(|b| *b >= cost)
+        }
+    }
+
+    #[test]
+    fn test_MatchingEngine_process_cancels_stops_the_guard_refuses() {
+        let mut matching_engine = MatchingEngine::new();
+        matching_engine.set_stop_guard(Some(Box::new(Budgets(HashMap::from([
+            ("ALICE".to_string(), 100),
+            ("CAROL".to_string(), 1_000),
+        ])))));
+        let order = |signer: &str, amount, price, order_type, trigger_price| Order {
+            price,
+            amount,
+            side: Side::Buy,
+            signer: signer.to_string(),
+            client_order_id: None,
+            signature: None,
+            order_type,
+            time_in_force: TimeInForce::Gtc,
+            trigger_price,
+            market: DEFAULT_MARKET.to_string(),
+        };
+        for (amount, price) in [(1, 10), (20, 50)] {
+            matching_engine
+                .process(Order {
+                    side: Side::Sell,
+                    ..order("MAKER", amount, price, OrderType::Limit, None)
+                })
+                .unwrap();
+        }
+        let refused = matching_engine
+            .process(order("ALICE", 10, 0, OrderType::Market, Some(10)))
+            .unwrap();
+        let admitted = matching_engine
+            .process(order("CAROL", 10, 0, OrderType::Market, Some(10)))
+            .unwrap();
+
+        matching_engine
+            .process(order("BOB", 1, 10, OrderType::Limit, None))
+            .unwrap();
+        // ALICE can't pay 500 for 10 units at 50, CAROL can
+        assert_eq!(matching_engine.refused().len(), 1);
+        assert_eq!(matching_engine.refused()[0].order_id, refused.order_id);
+        let triggered = matching_engine.triggered();
+        assert_eq!(triggered.len(), 1);
+        assert_eq!(triggered[0].receipt.order_id, admitted.order_id);
+        assert_eq!(triggered[0].receipt.filled, 10);
+        assert_eq!(matching_engine.level_quantity(&Side::Sell, 50), 10);
+        assert!(matching_engine.stops().is_empty());
+    }
+
+    #[test]
+    fn test_MatchingEngine_process_bracket_cancels_the_other_order() {
+        let mut matching_engine = MatchingEngine::new();
+        let order = |signer: &str, side, price, order_type, trigger_price| Order {
+            price,
+            amount: 1,
+            side,
+            signer: signer.to_string(),
+            client_order_id: None,
+            signature: None,
+            order_type,
+            time_in_force: TimeInForce::Gtc,
+            trigger_price,
+            market: DEFAULT_MARKET.to_string(),
+        };
+        let take_profit = |price| order("ALICE", Side::Sell, price, OrderType::Limit, None);
+        let stop_loss = || order("ALICE", Side::Sell, 8, OrderType::Limit, Some(9));
+        assert!(matches!(
+            matching_engine.process_bracket(take_profit(9), stop_loss()),
+            Err(ApplicationError::InvalidBracket(_))
+        ));
+        assert!(matches!(
+            matching_engine.process_bracket(take_profit(12), take_profit(13)),
+            Err(ApplicationError::InvalidBracket(_))
+        ));
+
+        // The take-profit fills, the stop-loss goes
+        let first = matching_engine
+            .process_bracket(take_profit(12), stop_loss())
+            .unwrap();
+        assert_eq!(
+            matching_engine.linked(first.take_profit.order_id),
+            Some(first.stop_loss.order_id)
+        );
+        matching_engine
+            .process(order("BOB", Side::Buy, 12, OrderType::Limit, None))
+            .unwrap();
+        assert_eq!(matching_engine.unlinked().len(), 1);
+        assert_eq!(
+            matching_engine.unlinked()[0].order_id,
+            first.stop_loss.order_id
+        );
+        assert!(matching_engine.stops().is_empty());
+        assert_eq!(matching_engine.linked(first.take_profit.order_id), None);
+
+        // The stop-loss triggers, the take-profit goes
+        let second = matching_engine
+            .process_bracket(take_profit(15), stop_loss())
+            .unwrap();
+        matching_engine
+            .process(order("BOB", Side::Buy, 9, OrderType::Limit, None))
+            .unwrap();
+        matching_engine
+            .process(order("BOB", Side::Buy, 9, OrderType::Limit, None))
+            .unwrap();
+        matching_engine
+            .process(order("CAROL", Side::Sell, 0, OrderType::Market, None))
+            .unwrap();
+        assert_eq!(matching_engine.triggered().len(), 1);
+        assert_eq!(
+            matching_engine.triggered()[0].receipt.order_id,
+            second.stop_loss.order_id
+        );
+        assert_eq!(
+            matching_engine.unlinked()[0].order_id,
+            second.take_profit.order_id
+        );
+        assert_eq!(matching_engine.level_quantity(&Side::Sell, 15), 0);
+
+        // A take-profit filling on placement takes its stop-loss along right away
+        matching_engine
+            .process(order("BOB", Side::Buy, 11, OrderType::Limit, None))
+            .unwrap();
+        let third = matching_engine
+            .process_bracket(take_profit(10), stop_loss())
+            .unwrap();
+        assert_eq!(third.take_profit.filled, 1);
+        assert_eq!(third.cancelled.len(), 1);
+        assert_eq!(third.cancelled[0].order_id, third.stop_loss.order_id);
+        assert!(matching_engine.stops().is_empty());
+    }
+
+    #[test]
+    fn test_MatchingEngine_run_auction_crosses_at_the_price_of_the_most_volume() {
+        let mut matching_engine = MatchingEngine::new();
+        let order = |signer: &str, side, price, amount, order_type| Order {
+            price,
+            amount,
+            side,
+            signer: signer.to_string(),
+            client_order_id: None,
+            signature: None,
+            order_type,
+            time_in_force: TimeInForce::Gtc,
+            trigger_price: None,
+            market: DEFAULT_MARKET.to_string(),
+        };
+        matching_engine.start_auction();
+        for (signer, side, price) in [
+            ("ALICE", Side::Sell, 10),
+            ("BOB", Side::Sell, 12),
+            ("CAROL", Side::Buy, 13),
+            ("DAVE", Side::Buy, 11),
+        ] {
+            let amount = if side == Side::Sell { 5 } else { 4 };
+            let receipt = matching_engine
+                .process(order(signer, side, price, amount, OrderType::Limit))
+                .unwrap();
+            assert!(receipt.matches.is_empty());
+        }
+        assert_eq!(
+            matching_engine.process(order("EVE", Side::Buy, 0, 1, OrderType::Market)),
+            Err(ApplicationError::AuctionInProgress(
+                DEFAULT_MARKET.to_string()
+            ))
+        );
+
+        let auction = matching_engine.run_auction().unwrap();
+        // 10 and 11 both cross 5 units with 3 left over, the lower price wins
+        assert_eq!((auction.price, auction.volume), (Some(10), 5));
+        assert_eq!(
+            auction
+                .fills
+                .iter()
+                .map(|f| (
+                    f.signer.as_str(),
+                    f.receipt.matches[0].signer.as_str(),
+                    f.receipt.matches[0].amount
+                ))
+                .collect::<Vec<_>>(),
+            vec![("CAROL", "ALICE", 4), ("DAVE", "ALICE", 1)]
+        );
+        assert!(auction
+            .fills
+            .iter()
+            .flat_map(|f| f.receipt.matches.iter())
+            .all(|m| m.price == 10));
+        assert!(!matching_engine.in_auction());
+        assert_eq!(matching_engine.last_price(), Some(10));
+        assert_eq!(matching_engine.level_quantity(&Side::Buy, 11), 3);
+        assert_eq!(matching_engine.level_quantity(&Side::Sell, 10), 0);
+        assert_eq!(matching_engine.level_quantity(&Side::Sell, 12), 5);
+        // Continuous trading resumes
+        let receipt = matching_engine
+            .process(order("EVE", Side::Sell, 0, 1, OrderType::Market))
+            .unwrap();
+        assert_eq!(receipt.matches[0].signer, "DAVE");
+        assert_eq!(matching_engine.run_auction().unwrap().price, None);
+    }
+}
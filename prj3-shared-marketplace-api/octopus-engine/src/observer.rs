@@ -0,0 +1,54 @@
+use std::fmt;
+
+use octopus_common::types::{PartialOrder, PriceLevel, Side};
+
+/// Hooks into everything a [`crate::MatchingEngine`] does, for broadcasters, metrics, and publishers that shouldn't
+/// depend on the engine's internals. Every method does nothing by default, so observers only implement what they need.
+///
+/// Observers are called synchronously while the engine processes an order, keep them cheap. Engines are shared between
+/// threads, so are their observers.
+pub trait EngineObserver: Send + Sync {
+    /// An order was accepted with its ordinal, before it's matched
+    fn on_order_accepted(&mut self, _order: &PartialOrder) {}
+
+    /// `taker` matched the resting order `maker`. The maker's `amount` is the matched amount, its `price` the price of
+    /// the match.
+    fn on_trade(&mut self, _taker: &PartialOrder, _maker: &PartialOrder) {}
+
+    /// A resting order was removed from the book before it was filled
+    fn on_cancel(&mut self, _order: &PartialOrder) {}
+
+    /// A good 'til time order was taken off the book when it expired, a cancel unless the observer tells them apart
+    fn on_expire(&mut self, order: &PartialOrder) {
+        self.on_cancel(order);
+    }
+
+    /// The open quantity of a price level changed, a quantity of 0 removed the level
+    fn on_book_change(&mut self, _side: &Side, _level: &PriceLevel) {}
+}
+
+/// The observers registered with an engine, notified in registration order
+#[derive(Default)]
+pub(crate) struct Observers(Vec<Box<dyn EngineObserver>>);
+
+impl Observers {
+    pub(crate) fn register(&mut self, observer: Box<dyn EngineObserver>) {
+        self.0.push(observer);
+    }
+
+    pub(crate) fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    pub(crate) fn notify(&mut self, mut event: impl FnMut(&mut dyn EngineObserver)) {
+        for observer in self.0.iter_mut() {
+            event(observer.as_mut());
+        }
+    }
+}
+
+impl fmt::Debug for Observers {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} observers", self.0.len())
+    }
+}
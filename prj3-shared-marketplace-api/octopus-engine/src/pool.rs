@@ -0,0 +1,85 @@
+use std::collections::VecDeque;
+
+use octopus_common::types::PartialOrder;
+
+/// How much of a book to allocate before the first order, so a burst after startup doesn't allocate on every level
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct BookCapacity {
+    /// The price levels expected on each side of the book
+    pub levels: usize,
+    /// The resting orders expected at each price level
+    pub orders_per_level: usize,
+}
+
+/// What the price levels of a book cost in allocations
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct PoolStats {
+    /// Levels allocated up front by [`crate::MatchingEngine::preallocate`]
+    pub preallocated: u64,
+    /// Levels opened with an emptied level from the pool
+    pub reused: u64,
+    /// Levels opened with a new allocation because the pool was empty
+    pub allocated: u64,
+    /// Emptied levels waiting in the pool
+    pub idle: usize,
+}
+
+/// Emptied price levels kept with their capacity to open the next ones. Only as many are kept as both sides of the
+/// preallocated book hold, an engine that wasn't preallocated keeps none.
+#[derive(Debug, Default)]
+pub(crate) struct LevelPool {
+    levels: Vec<VecDeque<PartialOrder>>,
+    capacity: BookCapacity,
+    preallocated: u64,
+    reused: u64,
+    allocated: u64,
+}
+
+impl LevelPool {
+    /// Allocates the levels of both sides of a book of `capacity`
+    pub(crate) fn preallocate(&mut self, capacity: BookCapacity) {
+        self.capacity = capacity;
+        let missing = self.max_idle().saturating_sub(self.levels.len());
+        self.levels.reserve(missing);
+        for _ in 0..missing {
+            self.levels
+                .push(VecDeque::with_capacity(capacity.orders_per_level));
+        }
+        self.preallocated += missing as u64;
+    }
+
+    /// An empty level, from the pool if there is one
+    pub(crate) fn take(&mut self) -> VecDeque<PartialOrder> {
+        match self.levels.pop() {
+            Some(level) => {
+                self.reused += 1;
+                level
+            }
+            None => {
+                self.allocated += 1;
+                VecDeque::with_capacity(self.capacity.orders_per_level)
+            }
+        }
+    }
+
+    /// Keeps the emptied `level` for later, unless the pool is full
+    pub(crate) fn give(&mut self, mut level: VecDeque<PartialOrder>) {
+        if self.levels.len() < self.max_idle() {
+            level.clear();
+            self.levels.push(level);
+        }
+    }
+
+    pub(crate) fn stats(&self) -> PoolStats {
+        PoolStats {
+            preallocated: self.preallocated,
+            reused: self.reused,
+            allocated: self.allocated,
+            idle: self.levels.len(),
+        }
+    }
+
+    fn max_idle(&self) -> usize {
+        2 * self.capacity.levels
+    }
+}
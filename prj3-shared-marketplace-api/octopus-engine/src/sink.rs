@@ -0,0 +1,67 @@
+use std::sync::mpsc;
+
+use octopus_common::types::PartialOrder;
+use serde::Serialize;
+
+use crate::observer::EngineObserver;
+
+/// What happened to an order, as a value that can be sent on, see [`EventSink`]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum EngineEvent {
+    /// An order was accepted with its ordinal, before it's matched
+    OrderAccepted { order: PartialOrder },
+    /// `taker` matched the resting order `maker`, whose `amount` and `price` are the ones of the match
+    Trade {
+        taker: PartialOrder,
+        maker: PartialOrder,
+    },
+    /// A resting order was removed from the book before it was filled
+    OrderCancelled { order: PartialOrder },
+    /// A good 'til time order was taken off the book when it expired
+    OrderExpired { order: PartialOrder },
+}
+
+/// Receives the [`EngineEvent`]s of a [`crate::MatchingEngine`], e.g. to fan them out to websockets, logs, or a message
+/// queue. Simpler than an [`EngineObserver`] where the events are passed on anyway, but every event is a copy. Sinks are
+/// called synchronously like observers, hand the events off rather than working on them.
+pub trait EventSink: Send + Sync {
+    fn send(&mut self, event: EngineEvent);
+}
+
+/// Events are queued for a consumer on another thread, a consumer that went away is ignored
+impl EventSink for mpsc::Sender<EngineEvent> {
+    fn send(&mut self, event: EngineEvent) {
+        let _ = mpsc::Sender::send(self, event);
+    }
+}
+
+/// Turns the observer callbacks into events for a sink
+pub(crate) struct SinkObserver(pub(crate) Box<dyn EventSink>);
+
+impl EngineObserver for SinkObserver {
+    fn on_order_accepted(&mut self, order: &PartialOrder) {
+        self.0.send(EngineEvent::OrderAccepted {
+            order: order.clone(),
+        });
+    }
+
+    fn on_trade(&mut self, taker: &PartialOrder, maker: &PartialOrder) {
+        self.0.send(EngineEvent::Trade {
+            taker: taker.clone(),
+            maker: maker.clone(),
+        });
+    }
+
+    fn on_cancel(&mut self, order: &PartialOrder) {
+        self.0.send(EngineEvent::OrderCancelled {
+            order: order.clone(),
+        });
+    }
+
+    fn on_expire(&mut self, order: &PartialOrder) {
+        self.0.send(EngineEvent::OrderExpired {
+            order: order.clone(),
+        });
+    }
+}
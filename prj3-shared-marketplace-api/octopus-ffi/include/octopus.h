@@ -0,0 +1,108 @@
+#ifndef OCTOPUS_H
+#define OCTOPUS_H
+
+/* Generated by cbindgen from octopus-ffi/src/lib.rs. Do not edit. */
+
+#include <stdarg.h>
+#include <stdbool.h>
+#include <stddef.h>
+#include <stdint.h>
+#include <stdlib.h>
+
+typedef enum OctopusStatus {
+  OCTOPUS_STATUS_OK = 0,
+  /**
+   * The engine or an out parameter was null
+   */
+  OCTOPUS_STATUS_NULL_POINTER,
+  /**
+   * The signer was null or not valid UTF-8
+   */
+  OCTOPUS_STATUS_INVALID_SIGNER,
+  /**
+   * The engine rejected the order
+   */
+  OCTOPUS_STATUS_REJECTED,
+} OctopusStatus;
+
+typedef enum OctopusSide {
+  OCTOPUS_SIDE_BUY,
+  OCTOPUS_SIDE_SELL,
+} OctopusSide;
+
+typedef enum OctopusEventKind {
+  /**
+   * The order was assigned `ordinal`
+   */
+  OCTOPUS_EVENT_KIND_ACCEPTED,
+  /**
+   * The order `ordinal` matched `amount` at `price` against the resting order `maker_ordinal`
+   */
+  OCTOPUS_EVENT_KIND_TRADE,
+  /**
+   * The unmatched `amount` of the order `ordinal` was added to the book at `price`
+   */
+  OCTOPUS_EVENT_KIND_RESTED,
+} OctopusEventKind;
+
+/**
+ * An opaque matching engine with the events that haven't been polled yet
+ */
+typedef struct OctopusEngine OctopusEngine;
+
+/**
+ * Something that happened while processing an order. Fields that don't apply to the kind are 0.
+ */
+typedef struct OctopusEvent {
+  enum OctopusEventKind kind;
+  uint64_t ordinal;
+  uint64_t maker_ordinal;
+  uint64_t price;
+  uint64_t amount;
+} OctopusEvent;
+
+#ifdef __cplusplus
+extern "C" {
+#endif // __cplusplus
+
+/**
+ * Creates an engine with an empty book. Free it with [`octopus_engine_free`].
+ */
+struct OctopusEngine *octopus_engine_new(void);
+
+/**
+ * Destroys an engine and its unpolled events. Null is ignored.
+ *
+ * # Safety
+ * `engine` must come from [`octopus_engine_new`] and must not be used afterwards.
+ */
+void octopus_engine_free(struct OctopusEngine *engine);
+
+/**
+ * Matches an order against the book and rests the remainder. The resulting events are queued for
+ * [`octopus_engine_poll`], and the order's ordinal is written to `ordinal` if it isn't null.
+ *
+ * # Safety
+ * `engine` must be a live engine, `signer` a NUL-terminated string, and `ordinal` null or writable.
+ */
+enum OctopusStatus octopus_engine_submit(struct OctopusEngine *engine,
+                                         uint64_t price,
+                                         uint64_t amount,
+                                         enum OctopusSide side,
+                                         const char *signer,
+                                         uint64_t *ordinal);
+
+/**
+ * Takes the oldest unpolled event into `event`. Returns false, leaving `event` untouched, if there is none.
+ *
+ * # Safety
+ * `engine` must be a live engine and `event` writable.
+ */
+bool octopus_engine_poll(struct OctopusEngine *engine,
+                         struct OctopusEvent *event);
+
+#ifdef __cplusplus
+}  // extern "C"
+#endif  // __cplusplus
+
+#endif  /* OCTOPUS_H */
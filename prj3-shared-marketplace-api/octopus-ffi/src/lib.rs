@@ -0,0 +1,280 @@
+//! A C API for embedding the matching engine as a shared or static library.
+//!
+//! The header is generated into `include/octopus.h` on every build. An engine is created with
+//! [`octopus_engine_new`], fed with [`octopus_engine_submit`], drained with [`octopus_engine_poll`], and freed with
+//! [`octopus_engine_free`]. An engine must not be used from two threads at once.
+use std::{
+    collections::VecDeque,
+    ffi::{c_char, CStr},
+};
+
+use octopus_common::types::{Order, OrderType, Side, TimeInForce, DEFAULT_MARKET};
+use octopus_engine::MatchingEngine;
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OctopusSide {
+    Buy,
+    Sell,
+}
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OctopusStatus {
+    Ok = 0,
+    /// The engine or an out parameter was null
+    NullPointer,
+    /// The signer was null or not valid UTF-8
+    InvalidSigner,
+    /// The engine rejected the order
+    Rejected,
+}
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OctopusEventKind {
+    /// The order was assigned `ordinal`
+    Accepted,
+    /// The order `ordinal` matched `amount` at `price` against the resting order `maker_ordinal`
+    Trade,
+    /// The unmatched `amount` of the order `ordinal` was added to the book at `price`
+    Rested,
+}
+
+/// Something that happened while processing an order. Fields that don't apply to the kind are 0.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct OctopusEvent {
+    pub kind: OctopusEventKind,
+    pub ordinal: u64,
+    pub maker_ordinal: u64,
+    pub price: u64,
+    pub amount: u64,
+}
+
+/// An opaque matching engine with the events that haven't been polled yet
+#[derive(Debug, Default)]
+pub struct OctopusEngine {
+    engine: MatchingEngine,
+    events: VecDeque<OctopusEvent>,
+}
+
+impl OctopusEngine {
+    fn submit(&mut self, order: Order) -> Result<u64, OctopusStatus> {
+        let (price, amount) = (order.price, order.amount);
+        let receipt = self
+            .engine
+            .process(order)
+            .map_err(|_| OctopusStatus::Rejected)?;
+        let event = |kind, maker_ordinal, price, amount| OctopusEvent {
+            kind,
+            ordinal: receipt.ordinal,
+            maker_ordinal,
+            price,
+            amount,
+        };
+        self.events
+            .push_back(event(OctopusEventKind::Accepted, 0, 0, 0));
+        let mut matched = 0;
+        for maker in &receipt.matches {
+            matched += maker.amount;
+            self.events.push_back(event(
+                OctopusEventKind::Trade,
+                maker.ordinal,
+                maker.price,
+                maker.amount,
+            ));
+        }
+        if matched < amount {
+            self.events
+                .push_back(event(OctopusEventKind::Rested, 0, price, amount - matched));
+        }
+        Ok(receipt.ordinal)
+    }
+}
+
+/// Creates an engine with an empty book. Free it with [`octopus_engine_free`].
+#[no_mangle]
+pub extern "C" fn octopus_engine_new() -> *mut OctopusEngine {
+    Box::into_raw(Box::default())
+}
+
+/// Destroys an engine and its unpolled events. Null is ignored.
+///
+/// # Safety
+/// `engine` must come from [`octopus_engine_new`] and must not be used afterwards.
+#[no_mangle]
+pub unsafe extern "C" fn octopus_engine_free(engine: *mut OctopusEngine) {
+    if !engine.is_null() {
+        drop(Box::from_raw(engine));
+    }
+}
+
+/// Matches an order against the book and rests the remainder. The resulting events are queued for
+/// [`octopus_engine_poll`], and the order's ordinal is written to `ordinal` if it isn't null.
+///
+/// # Safety
+/// `engine` must be a live engine, `signer` a NUL-terminated string, and `ordinal` null or writable.
+#[no_mangle]
+pub unsafe extern "C" fn octopus_engine_submit(
+    engine: *mut OctopusEngine,
+    price: u64,
+    amount: u64,
+    side: OctopusSide,
+    signer: *const c_char,
+    ordinal: *mut u64,
+) -> OctopusStatus {
+    let Some(engine) = engine.as_mut() else {
+        return OctopusStatus::NullPointer;
+    };
+    if signer.is_null() {
+        return OctopusStatus::InvalidSigner;
+    }
+    let Ok(signer) = CStr::from_ptr(signer).to_str() else {
+        return OctopusStatus::InvalidSigner;
+    };
+    let order = Order {
+        price,
+        amount,
+        side: match side {
+            OctopusSide::Buy => Side::Buy,
+            OctopusSide::Sell => Side::Sell,
+        },
+        signer: signer.to_string(),
+        client_order_id: None,
+        signature: None,
+        order_type: OrderType::Limit,
+        time_in_force: TimeInForce::Gtc,
+        trigger_price: None,
+        market: DEFAULT_MARKET.to_string(),
+    };
+    match engine.submit(order) {
+        Ok(assigned) => {
+            if let Some(ordinal) = ordinal.as_mut() {
+                *ordinal = assigned;
+            }
+            OctopusStatus::Ok
+        }
+        Err(status) => status,
+    }
+}
+
+/// Takes the oldest unpolled event into `event`. Returns false, leaving `event` untouched, if there is none.
+///
+/// # Safety
+/// `engine` must be a live engine and `event` writable.
+#[no_mangle]
+pub unsafe extern "C" fn octopus_engine_poll(
+    engine: *mut OctopusEngine,
+    event: *mut OctopusEvent,
+) -> bool {
+    match (engine.as_mut(), event.as_mut()) {
+        (Some(engine), Some(event)) => match engine.events.pop_front() {
+            Some(next) => {
+                *event = next;
+                true
+            }
+            None => false,
+        },
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    // reduce the warnings for naming tests
+    #![allow(non_snake_case)]
+
+    use super::*;
+
+    fn poll_all(engine: *mut OctopusEngine) -> Vec<OctopusEvent> {
+        let mut events = vec![];
+        let mut event = OctopusEvent {
+            kind: OctopusEventKind::Accepted,
+            ordinal: 0,
+            maker_ordinal: 0,
+            price: 0,
+            amount: 0,
+        };
+        while unsafe { octopus_engine_poll(engine, &mut event) } {
+            events.push(event);
+        }
+        events
+    }
+
+    #[test]
+    fn test_octopus_engine_submit_queues_events() {
+        let engine = octopus_engine_new();
+        let mut ordinal = 0;
+        unsafe {
+            assert_eq!(
+                octopus_engine_submit(
+                    engine,
+                    10,
+                    2,
+                    OctopusSide::Sell,
+                    c"ALICE".as_ptr(),
+                    &mut ordinal
+                ),
+                OctopusStatus::Ok
+            );
+            assert_eq!(ordinal, 1);
+            assert_eq!(poll_all(engine).len(), 2);
+            assert_eq!(
+                octopus_engine_submit(
+                    engine,
+                    11,
+                    5,
+                    OctopusSide::Buy,
+                    c"BOB".as_ptr(),
+                    &mut ordinal
+                ),
+                OctopusStatus::Ok
+            );
+        }
+        assert_eq!(
+            poll_all(engine)
+                .iter()
+                .map(|e| (e.kind, e.ordinal, e.maker_ordinal, e.price, e.amount))
+                .collect::<Vec<_>>(),
+            vec![
+                (OctopusEventKind::Accepted, 2, 0, 0, 0),
+                (OctopusEventKind::Trade, 2, 1, 10, 2),
+                (OctopusEventKind::Rested, 2, 0, 11, 3),
+            ]
+        );
+        unsafe { octopus_engine_free(engine) };
+    }
+
+    #[test]
+    fn test_octopus_engine_submit_rejects_null_pointers() {
+        unsafe {
+            assert_eq!(
+                octopus_engine_submit(
+                    std::ptr::null_mut(),
+                    10,
+                    1,
+                    OctopusSide::Buy,
+                    c"ALICE".as_ptr(),
+                    std::ptr::null_mut()
+                ),
+                OctopusStatus::NullPointer
+            );
+            let engine = octopus_engine_new();
+            assert_eq!(
+                octopus_engine_submit(
+                    engine,
+                    10,
+                    1,
+                    OctopusSide::Buy,
+                    std::ptr::null(),
+                    std::ptr::null_mut()
+                ),
+                OctopusStatus::InvalidSigner
+            );
+            assert!(poll_all(engine).is_empty());
+            octopus_engine_free(engine);
+            octopus_engine_free(std::ptr::null_mut());
+        }
+    }
+}
@@ -0,0 +1,244 @@
+//! Python bindings for the production [`octopus_engine::MatchingEngine`], so backtests run the exact matching logic.
+//!
+//! Build and install the `octopus` module into the current virtualenv with `maturin develop -m octopus-py/Cargo.toml`:
+//!
+//! ```python
+//! import octopus
+//!
+//! engine = octopus.MatchingEngine()
+//! engine.process(octopus.Order(10, 2, octopus.Side.Sell, "ALICE"))
+//! receipt = engine.process(octopus.Order(10, 1, octopus.Side.Buy, "BOB"))
+//! assert receipt.matches[0].signer == "ALICE"
+//! ```
+use octopus_common::types;
+use pyo3::{exceptions::PyValueError, prelude::*};
+
+#[pyclass(eq, eq_int, from_py_object)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Side {
+    Buy,
+    Sell,
+}
+
+impl From<Side> for types::Side {
+    fn from(side: Side) -> Self {
+        match side {
+            Side::Buy => types::Side::Buy,
+            Side::Sell => types::Side::Sell,
+        }
+    }
+}
+
+impl From<types::Side> for Side {
+    fn from(side: types::Side) -> Self {
+        match side {
+            types::Side::Buy => Side::Buy,
+            types::Side::Sell => Side::Sell,
+        }
+    }
+}
+
+/// An order to submit to the [`MatchingEngine`]
+#[pyclass(get_all, set_all, from_py_object)]
+#[derive(Debug, Clone)]
+pub struct Order {
+    pub price: u64,
+    pub amount: u64,
+    pub side: Side,
+    pub signer: String,
+}
+
+#[pymethods]
+impl Order {
+    #[new]
+    fn new(price: u64, amount: u64, side: Side, signer: String) -> Self {
+        Order {
+            price,
+            amount,
+            side,
+            signer,
+        }
+    }
+
+    fn __repr__(&self) -> String {
+        format!(
+            "Order(price={}, amount={}, side={:?}, signer={:?})",
+            self.price, self.amount, self.side, self.signer
+        )
+    }
+}
+
+/// An order in the book, or the maker side of a match
+#[pyclass(get_all, skip_from_py_object)]
+#[derive(Debug, Clone)]
+pub struct PartialOrder {
+    pub price: u64,
+    pub amount: u64,
+    pub remaining: u64,
+    pub side: Side,
+    pub signer: String,
+    pub ordinal: u64,
+    pub order_id: u64,
+}
+
+#[pymethods]
+impl PartialOrder {
+    fn __repr__(&self) -> String {
+        format!(
+            "PartialOrder(price={}, amount={}, remaining={}, side={:?}, signer={:?}, ordinal={}, order_id={})",
+            self.price, self.amount, self.remaining, self.side, self.signer, self.ordinal, self.order_id
+        )
+    }
+}
+
+impl From<types::PartialOrder> for PartialOrder {
+    fn from(order: types::PartialOrder) -> Self {
+        PartialOrder {
+            price: order.price,
+            amount: order.amount,
+            remaining: order.remaining,
+            side: order.side.into(),
+            signer: order.signer,
+            ordinal: order.ordinal,
+            order_id: order.order_id,
+        }
+    }
+}
+
+#[pyclass(get_all, skip_from_py_object)]
+#[derive(Debug, Clone)]
+pub struct Receipt {
+    pub ordinal: u64,
+    pub order_id: u64,
+    pub filled: u64,
+    pub cancelled: u64,
+    pub matches: Vec<PartialOrder>,
+}
+
+impl From<types::Receipt> for Receipt {
+    fn from(receipt: types::Receipt) -> Self {
+        Receipt {
+            ordinal: receipt.ordinal,
+            order_id: receipt.order_id,
+            filled: receipt.filled,
+            cancelled: receipt.cancelled,
+            matches: receipt
+                .matches
+                .into_iter()
+                .map(PartialOrder::from)
+                .collect(),
+        }
+    }
+}
+
+/// The matching engine the marketplace runs. Accounts and balances aren't part of it.
+#[pyclass]
+#[derive(Debug, Default)]
+pub struct MatchingEngine {
+    inner: octopus_engine::MatchingEngine,
+}
+
+#[pymethods]
+impl MatchingEngine {
+    #[new]
+    fn new() -> Self {
+        MatchingEngine::default()
+    }
+
+    /// The ordinal of the last processed order
+    #[getter]
+    fn ordinal(&self) -> u64 {
+        self.inner.ordinal
+    }
+
+    /// Matches an order against the book and rests the remainder
+    fn process(&mut self, order: Order) -> PyResult<Receipt> {
+        self.inner
+            .process(types::Order {
+                price: order.price,
+                amount: order.amount,
+                side: order.side.into(),
+                signer: order.signer,
+                client_order_id: None,
+                signature: None,
+                order_type: types::OrderType::Limit,
+                time_in_force: types::TimeInForce::Gtc,
+                trigger_price: None,
+                market: types::DEFAULT_MARKET.to_string(),
+            })
+            .map(Receipt::from)
+            .map_err(|e| PyValueError::new_err(format!("{:?}", e)))
+    }
+
+    /// All open orders, asks first, each side in ascending price order
+    fn orders(&self) -> Vec<PartialOrder> {
+        self.inner
+            .orders()
+            .into_iter()
+            .map(PartialOrder::from)
+            .collect()
+    }
+
+    /// `(price, quantity)` per price level of one side, best price first
+    fn levels(&self, side: Side) -> Vec<(u64, u64)> {
+        self.inner
+            .levels(&side.into())
+            .into_iter()
+            .map(|level| (level.price, level.quantity))
+            .collect()
+    }
+
+    /// Removes all open orders of `signer` and returns them
+    fn cancel_all(&mut self, signer: &str) -> Vec<PartialOrder> {
+        self.inner
+            .cancel_all(signer)
+            .into_iter()
+            .map(PartialOrder::from)
+            .collect()
+    }
+}
+
+#[pymodule]
+fn octopus(m: &Bound<'_, PyModule>) -> PyResult<()> {
+    m.add_class::<Side>()?;
+    m.add_class::<Order>()?;
+    m.add_class::<PartialOrder>()?;
+    m.add_class::<Receipt>()?;
+    m.add_class::<MatchingEngine>()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    // reduce the warnings for naming tests
+    #![allow(non_snake_case)]
+
+    use super::*;
+    use pyo3::types::PyDict;
+
+    #[test]
+    fn test_MatchingEngine_from_python() {
+        Python::initialize();
+        Python::attach(|py| {
+            let module = PyModule::new(py, "octopus").unwrap();
+            octopus(&module).unwrap();
+            let locals = PyDict::new(py);
+            locals.set_item("octopus", module).unwrap();
+            py.run(
+                cr#"
+engine = octopus.MatchingEngine()
+engine.process(octopus.Order(10, 2, octopus.Side.Sell, "ALICE"))
+receipt = engine.process(octopus.Order(10, 1, octopus.Side.Buy, "BOB"))
+assert receipt.ordinal == 2
+assert [(m.signer, m.amount) for m in receipt.matches] == [("ALICE", 1)]
+assert engine.levels(octopus.Side.Sell) == [(10, 1)]
+assert [o.ordinal for o in engine.cancel_all("ALICE")] == [1]
+assert engine.orders() == []
+"#,
+                None,
+                Some(&locals),
+            )
+            .unwrap();
+        });
+    }
+}
@@ -1,20 +1,60 @@
-use octopus_common::{errors::ApplicationError, tx::Tx};
-use std::collections::HashMap;
+use octopus_common::{
+    errors::ApplicationError,
+    tx::Tx,
+    types::{Hold, PayoutLeg},
+};
+use std::{
+    collections::{BTreeMap, HashMap, HashSet},
+    sync::Arc,
+};
+
+use crate::core::{IdGenerator, MonotonicIds};
 
 /// A type for managing accounts and their current currency balance
-#[derive(Debug, Default)]
+#[derive(Debug)]
 pub struct Accounts {
     accounts: HashMap<String, u64>,
+    holds: BTreeMap<u64, Hold>,
+    last_hold_id: u64,
+    /// Issues the hold ids
+    ids: Arc<dyn IdGenerator>,
+    /// Accounts holding play funds issued by [`Accounts::faucet`]
+    sandbox: HashSet<String>,
+}
+
+impl Default for Accounts {
+    fn default() -> Self {
+        Accounts::new()
+    }
 }
 
 impl Accounts {
     /// Returns an empty instance of the [`Accounts`] type
     pub fn new() -> Self {
+        Accounts::with_ids(Arc::new(MonotonicIds))
+    }
+
+    /// Like [`Accounts::new`], but the hold ids are issued by `ids`
+    pub fn with_ids(ids: Arc<dyn IdGenerator>) -> Self {
         Accounts {
             accounts: HashMap::new(),
+            holds: BTreeMap::new(),
+            last_hold_id: 0,
+            ids,
+            sandbox: HashSet::new(),
         }
     }
 
+    /// The id of the latest hold
+    pub fn last_hold_id(&self) -> u64 {
+        self.last_hold_id
+    }
+
+    /// Continues the hold ids after `last`
+    pub fn resume_hold_ids(&mut self, last: u64) {
+        self.last_hold_id = self.last_hold_id.max(last);
+    }
+
     /// Retrieves the balance of an account
     pub fn balance_of(&self, signer: &str) -> Result<&u64, ApplicationError> {
         self.accounts
@@ -29,9 +69,8 @@ impl Accounts {
         if let Some(account) = self.accounts.get_mut(signer) {
             (*account)
                 .checked_add(amount)
-                .and_then(|r| {
+                .inspect(|&r| {
                     *account = r;
-                    Some(r)
                 })
                 .ok_or(ApplicationError::AccountOverFunded(
                     signer.to_string(),
@@ -41,16 +80,53 @@ impl Accounts {
                 .map(|_| Tx::Deposit {
                     account: signer.to_string(),
                     amount,
+                    memo: None,
                 })
         } else {
             self.accounts.insert(signer.to_string(), amount);
             Ok(Tx::Deposit {
                 account: signer.to_string(),
                 amount,
+                memo: None,
             })
         }
     }
 
+    /// Issues play funds to the `signer` sandbox account, opening it if necessary
+    /// # Errors
+    /// The account holds real funds, or would overflow
+    pub fn faucet(&mut self, signer: &str, amount: u64) -> Result<Tx, ApplicationError> {
+        if self.accounts.contains_key(signer) && !self.is_sandbox(signer) {
+            return Err(ApplicationError::SandboxViolation(signer.to_string()));
+        }
+        self.deposit(signer, amount)?;
+        self.sandbox.insert(signer.to_string());
+        Ok(Tx::Faucet {
+            account: signer.to_string(),
+            amount,
+        })
+    }
+
+    /// Whether the account holds play funds
+    pub fn is_sandbox(&self, signer: &str) -> bool {
+        self.sandbox.contains(signer)
+    }
+
+    /// Makes sure funds only move between two sandbox or two real accounts
+    /// # Errors
+    /// One of the accounts is a sandbox account, the other one isn't
+    pub fn ensure_same_funds(&self, sender: &str, recipient: &str) -> Result<(), ApplicationError> {
+        if self.is_sandbox(sender) != self.is_sandbox(recipient) {
+            let sandbox = if self.is_sandbox(sender) {
+                sender
+            } else {
+                recipient
+            };
+            return Err(ApplicationError::SandboxViolation(sandbox.to_string()));
+        }
+        Ok(())
+    }
+
     /// Withdraws the `amount` from the `signer` account.
     /// # Errors
     /// Attempted overflow
@@ -58,9 +134,8 @@ impl Accounts {
         if let Some(account) = self.accounts.get_mut(signer) {
             (*account)
                 .checked_sub(amount)
-                .and_then(|r| {
+                .inspect(|&r| {
                     *account = r;
-                    Some(r)
                 })
                 .ok_or(ApplicationError::AccountUnderFunded(
                     signer.to_string(),
@@ -70,16 +145,40 @@ impl Accounts {
                 .map(|_| Tx::Withdraw {
                     account: signer.to_string(),
                     amount,
+                    memo: None,
                 })
         } else {
             Err(ApplicationError::AccountNotFound(signer.to_string()))
         }
     }
 
+    /// Withdraws the remaining balance of `signer` and removes the account. The play currency
+    /// of a sandbox account is removed instead of withdrawn.
+    /// # Errors
+    /// The account doesn't exist or has open holds
+    pub fn close(&mut self, signer: &str) -> Result<Tx, ApplicationError> {
+        let balance = *self.balance_of(signer)?;
+        if self.holds.values().any(|hold| hold.account == signer) {
+            return Err(ApplicationError::AccountInUse(signer.to_string()));
+        }
+        self.accounts.remove(signer);
+        if self.sandbox.remove(signer) {
+            return Ok(Tx::SandboxClosed {
+                account: signer.to_string(),
+                amount: balance,
+            });
+        }
+        Ok(Tx::Withdraw {
+            account: signer.to_string(),
+            amount: balance,
+            memo: None,
+        })
+    }
+
     /// Withdraws the amount from the sender account and deposits it in the recipient account.
     ///
     /// # Errors
-    /// The account doesn't exist
+    /// An account doesn't exist or the sender has insufficient funds
     pub fn send(
         &mut self,
         sender: &str,
@@ -98,19 +197,250 @@ impl Accounts {
             // if let Err(e) = my_func_call() { return Err(e); }
             let tx_withdraw = self.withdraw(sender, amount)?;
             self.deposit(recipient, amount)
-                .map_err(|e| {
+                .inspect_err(|_| {
                     // return the funds to the sender on error
                     self.deposit(sender, amount).unwrap();
-                    e
                 })
                 .map(|tx_deposit| (tx_withdraw, tx_deposit))
+        } else if !self.accounts.contains_key(sender) {
+            Err(ApplicationError::AccountNotFound(sender.to_string()))
+        } else if !self.accounts.contains_key(recipient) {
+            Err(ApplicationError::AccountNotFound(recipient.to_string()))
         } else {
-            if !self.accounts.contains_key(sender) {
-                Err(ApplicationError::AccountNotFound(sender.to_string()))
-            } else {
-                Err(ApplicationError::AccountNotFound(recipient.to_string()))
+            Err(ApplicationError::AccountUnderFunded(
+                sender.to_string(),
+                amount,
+            ))
+        }
+    }
+
+    /// Pays every leg from the `payer` account as part of the payout `id`. Everything is checked before the first
+    /// balance changes, so either all recipients are credited or none is.
+    /// # Errors
+    /// An account doesn't exist, the payer has insufficient funds, or a recipient would overflow
+    pub fn payout(
+        &mut self,
+        id: u64,
+        payer: &str,
+        legs: &[PayoutLeg],
+    ) -> Result<Vec<Tx>, ApplicationError> {
+        let balance = *self.balance_of(payer)?;
+        let total = legs
+            .iter()
+            .try_fold(0u64, |total, leg| total.checked_add(leg.amount))
+            .filter(|total| *total <= balance)
+            .ok_or_else(|| {
+                let total = legs
+                    .iter()
+                    .fold(0u64, |t, leg| t.saturating_add(leg.amount));
+                ApplicationError::AccountUnderFunded(payer.to_string(), total)
+            })?;
+        // A recipient may be paid in several legs
+        let mut credits: HashMap<&str, u64> = HashMap::new();
+        for leg in legs {
+            *credits.entry(leg.to.as_str()).or_default() += leg.amount;
+        }
+        for (recipient, credit) in credits.iter() {
+            if self.balance_of(recipient)?.checked_add(*credit).is_none() {
+                return Err(ApplicationError::AccountOverFunded(
+                    recipient.to_string(),
+                    *credit,
+                ));
+            }
+        }
+
+        self.accounts.insert(payer.to_string(), balance - total);
+        for (recipient, credit) in credits {
+            if let Some(account) = self.accounts.get_mut(recipient) {
+                *account += credit;
             }
         }
+        Ok(legs
+            .iter()
+            .map(|leg| Tx::Payout {
+                id,
+                from: payer.to_string(),
+                to: leg.to.clone(),
+                amount: leg.amount,
+            })
+            .collect())
+    }
+
+    /// Checks that the `transfers` of an amount from one account to another can be applied one after the other: both
+    /// accounts exist, every sender holds the amount when its turn comes, and no recipient overflows. The `collector`
+    /// is opened by the first transfer to it if necessary, like [`Accounts::charge_fee`] does. Nothing changes.
+    /// # Errors
+    /// The first transfer that would fail
+    pub fn check_transfers(
+        &self,
+        transfers: &[(&str, &str, u64)],
+        collector: &str,
+    ) -> Result<(), ApplicationError> {
+        let mut balances: HashMap<&str, u64> = HashMap::new();
+        let balance = |balances: &HashMap<&str, u64>, signer: &str| match balances.get(signer) {
+            Some(balance) => Ok(*balance),
+            None if signer == collector && !self.accounts.contains_key(signer) => Ok(0),
+            None => self.balance_of(signer).copied(),
+        };
+        for (from, to, amount) in transfers.iter().copied() {
+            let remaining = balance(&balances, from)?.checked_sub(amount).ok_or(
+                ApplicationError::AccountUnderFunded(from.to_string(), amount),
+            )?;
+            balances.insert(from, remaining);
+            let credited = balance(&balances, to)?
+                .checked_add(amount)
+                .ok_or(ApplicationError::AccountOverFunded(to.to_string(), amount))?;
+            balances.insert(to, credited);
+        }
+        Ok(())
+    }
+
+    /// Moves the price of the trade `trade_id` from the `buyer` to the `seller` account.
+    /// # Errors
+    /// Either account doesn't exist, or the buyer has insufficient funds
+    pub fn settle(
+        &mut self,
+        trade_id: u64,
+        buyer: &str,
+        seller: &str,
+        amount: u64,
+    ) -> Result<Tx, ApplicationError> {
+        self.send(buyer, seller, amount).map(|_| Tx::Settlement {
+            trade_id,
+            from: buyer.to_string(),
+            to: seller.to_string(),
+            amount,
+        })
+    }
+
+    /// Moves the price of the busted trade `trade_id` back from the `seller` to the `buyer` account.
+    /// # Errors
+    /// Either account doesn't exist, or the seller has insufficient funds
+    pub fn reverse_settlement(
+        &mut self,
+        trade_id: u64,
+        seller: &str,
+        buyer: &str,
+        amount: u64,
+    ) -> Result<Tx, ApplicationError> {
+        self.send(seller, buyer, amount)
+            .map(|_| Tx::SettlementReversed {
+                trade_id,
+                from: seller.to_string(),
+                to: buyer.to_string(),
+                amount,
+            })
+    }
+
+    /// Moves a fee from the `signer` account to the `collector` account, creating the latter if necessary.
+    /// # Errors
+    /// The account doesn't exist or has insufficient funds, or the collector would overflow
+    pub fn charge_fee(
+        &mut self,
+        signer: &str,
+        collector: &str,
+        amount: u64,
+    ) -> Result<Tx, ApplicationError> {
+        self.withdraw(signer, amount)?;
+        self.deposit(collector, amount)
+            .inspect_err(|_| {
+                // return the funds to the signer on error
+                self.deposit(signer, amount).unwrap();
+            })
+            .map(|_| Tx::Fee {
+                account: signer.to_string(),
+                amount,
+            })
+    }
+
+    /// Moves the whole balance of the `signer` account to the `collector` account, creating the latter if necessary
+    /// # Errors
+    /// The account doesn't exist, or the collector would overflow
+    pub fn sweep(&mut self, signer: &str, collector: &str) -> Result<Tx, ApplicationError> {
+        let amount = *self.balance_of(signer)?;
+        self.withdraw(signer, amount)?;
+        self.deposit(collector, amount)
+            .inspect_err(|_| {
+                // return the funds to the signer on error
+                self.deposit(signer, amount).unwrap();
+            })
+            .map(|_| Tx::DustSwept {
+                from: signer.to_string(),
+                to: collector.to_string(),
+                amount,
+            })
+    }
+
+    /// Reserves the `amount` of the `signer` account. Held funds don't count towards the balance until they're released.
+    /// # Errors
+    /// The account doesn't exist or has insufficient funds
+    pub fn hold(&mut self, signer: &str, amount: u64) -> Result<Tx, ApplicationError> {
+        self.withdraw(signer, amount)?;
+        self.last_hold_id = self.ids.next_id(self.last_hold_id);
+        let id = self.last_hold_id;
+        self.holds.insert(
+            id,
+            Hold {
+                id,
+                account: signer.to_string(),
+                amount,
+            },
+        );
+        Ok(Tx::Hold {
+            id,
+            account: signer.to_string(),
+            amount,
+        })
+    }
+
+    /// Every account's balance by signer
+    pub fn balances(&self) -> BTreeMap<String, u64> {
+        self.accounts
+            .iter()
+            .map(|(signer, balance)| (signer.clone(), *balance))
+            .collect()
+    }
+
+    /// The open holds, oldest first
+    pub fn holds(&self) -> Vec<Hold> {
+        self.holds.values().cloned().collect()
+    }
+
+    /// Retrieves an open hold
+    pub fn hold_of(&self, id: u64) -> Result<&Hold, ApplicationError> {
+        self.holds
+            .get(&id)
+            .ok_or(ApplicationError::HoldNotFound(id))
+    }
+
+    /// Transfers the held funds to the `recipient` account and closes the hold.
+    /// # Errors
+    /// The hold or the recipient doesn't exist, or the recipient would overflow
+    pub fn capture(&mut self, id: u64, recipient: &str) -> Result<Tx, ApplicationError> {
+        let hold = self.hold_of(id)?.clone();
+        self.balance_of(recipient)?;
+        self.deposit(recipient, hold.amount)?;
+        self.holds.remove(&id);
+        Ok(Tx::Capture {
+            id,
+            from: hold.account,
+            to: recipient.to_string(),
+            amount: hold.amount,
+        })
+    }
+
+    /// Returns the held funds to their account and closes the hold.
+    /// # Errors
+    /// The hold doesn't exist or the account would overflow
+    pub fn release(&mut self, id: u64) -> Result<Tx, ApplicationError> {
+        let hold = self.hold_of(id)?.clone();
+        self.deposit(&hold.account, hold.amount)?;
+        self.holds.remove(&id);
+        Ok(Tx::Release {
+            id,
+            account: hold.account,
+            amount: hold.amount,
+        })
     }
 }
 
@@ -157,7 +487,8 @@ mod tests {
             actual,
             Ok(Tx::Deposit {
                 account: "a-key".to_string(),
-                amount: amt
+                amount: amt,
+                memo: None,
             })
         );
     }
@@ -172,7 +503,8 @@ mod tests {
             actual,
             Ok(Tx::Withdraw {
                 account: "a-key".to_string(),
-                amount: amt
+                amount: amt,
+                memo: None,
             })
         );
     }
@@ -191,14 +523,16 @@ mod tests {
             tx1,
             Tx::Withdraw {
                 account: "a-key".to_string(),
-                amount: amt
+                amount: amt,
+                memo: None,
             }
         );
         assert_eq!(
             tx2,
             Tx::Deposit {
                 account: "b-key".to_string(),
-                amount: amt
+                amount: amt,
+                memo: None,
             }
         );
 
@@ -207,7 +541,8 @@ mod tests {
             actual,
             Ok(Tx::Withdraw {
                 account: "b-key".to_string(),
-                amount: amt
+                amount: amt,
+                memo: None,
             })
         );
     }
@@ -222,7 +557,13 @@ mod tests {
         accounts.deposit("b-key", 0).expect("Couldn't deposit");
 
         let actual = accounts.send("a-key", "b-key", amt + 1);
-        assert!(actual.is_err());
+        assert_eq!(
+            actual,
+            Err(ApplicationError::AccountUnderFunded(
+                "a-key".to_string(),
+                amt + 1
+            ))
+        );
         let expected: HashMap<String, u64> =
             vec![("a-key".to_string(), amt), ("b-key".to_string(), 0)]
                 .into_iter()
@@ -230,6 +571,19 @@ mod tests {
         assert_eq!(accounts.accounts, expected);
     }
 
+    #[test]
+    fn test_accounts_send_to_a_missing_recipient_fails() {
+        let mut accounts = Accounts::new();
+        accounts.deposit("a-key", 100).expect("Couldn't deposit");
+
+        let actual = accounts.send("a-key", "b-key", 1);
+        assert_eq!(
+            actual,
+            Err(ApplicationError::AccountNotFound("b-key".to_string()))
+        );
+        assert_eq!(accounts.balance_of("a-key"), Ok(&100));
+    }
+
     #[test]
     fn test_accounts_send_overfunded_fails_and_rolls_back() {
         let mut accounts = Accounts::new();
@@ -249,4 +603,71 @@ mod tests {
                 .collect();
         assert_eq!(accounts.accounts, expected);
     }
+
+    #[test]
+    fn test_accounts_check_transfers_applies_them_in_order() {
+        let mut accounts = Accounts::new();
+        accounts.deposit("a-key", 10).unwrap();
+        accounts.deposit("b-key", u64::MAX - 5).unwrap();
+
+        // b-key's funds from the first transfer pay for the second, the collector is opened
+        assert_eq!(
+            accounts.check_transfers(&[("a-key", "b-key", 5), ("b-key", "fees", 10)], "fees"),
+            Ok(())
+        );
+        assert_eq!(
+            accounts.check_transfers(&[("a-key", "b-key", 6)], "fees"),
+            Err(ApplicationError::AccountOverFunded("b-key".to_string(), 6))
+        );
+        assert_eq!(
+            accounts.check_transfers(&[("a-key", "fees", 4), ("a-key", "fees", 7)], "fees"),
+            Err(ApplicationError::AccountUnderFunded("a-key".to_string(), 7))
+        );
+        assert_eq!(
+            accounts.check_transfers(&[("a-key", "c-key", 1)], "fees"),
+            Err(ApplicationError::AccountNotFound("c-key".to_string()))
+        );
+        // Nothing changed
+        assert_eq!(accounts.balance_of("a-key"), Ok(&10));
+        assert_eq!(
+            accounts.balance_of("fees"),
+            Err(ApplicationError::AccountNotFound("fees".to_string()))
+        );
+    }
+
+    #[test]
+    fn test_accounts_hold_capture_and_release() {
+        let mut accounts = Accounts::new();
+        accounts.deposit("a-key", 100).expect("Couldn't deposit");
+        accounts.deposit("b-key", 0).expect("Couldn't deposit");
+
+        let first = accounts.hold("a-key", 60).expect("Couldn't hold");
+        assert_eq!(
+            first,
+            Tx::Hold {
+                id: 1,
+                account: "a-key".to_string(),
+                amount: 60
+            }
+        );
+        assert_eq!(accounts.balance_of("a-key"), Ok(&40));
+        assert!(accounts.hold("a-key", 60).is_err());
+        accounts.hold("a-key", 40).expect("Couldn't hold");
+
+        assert!(accounts.capture(1, "c-key").is_err());
+        assert_eq!(
+            accounts.capture(1, "b-key"),
+            Ok(Tx::Capture {
+                id: 1,
+                from: "a-key".to_string(),
+                to: "b-key".to_string(),
+                amount: 60
+            })
+        );
+        assert_eq!(accounts.release(1), Err(ApplicationError::HoldNotFound(1)));
+        accounts.release(2).expect("Couldn't release");
+
+        assert_eq!(accounts.balance_of("a-key"), Ok(&40));
+        assert_eq!(accounts.balance_of("b-key"), Ok(&60));
+    }
 }
@@ -0,0 +1,103 @@
+//! Counters of the matching engine's activity for the metrics endpoint, kept by an [`EngineObserver`] so the metrics
+//! don't reach into the order processing.
+use octopus_common::types::PartialOrder;
+use std::sync::{
+    atomic::{AtomicU64, Ordering},
+    Arc,
+};
+
+use crate::core::EngineObserver;
+
+#[derive(Debug, Default)]
+struct Counters {
+    orders: AtomicU64,
+    trades: AtomicU64,
+    traded_quantity: AtomicU64,
+    cancels: AtomicU64,
+}
+
+/// The number of orders, trades, and cancellations of a book since the start. Clones share their counters, so one
+/// clone is registered with the engine while another one is read.
+#[derive(Debug, Default, Clone)]
+pub struct EngineActivity(Arc<Counters>);
+
+impl EngineActivity {
+    /// Orders accepted by the engine
+    pub fn orders(&self) -> u64 {
+        self.0.orders.load(Ordering::Relaxed)
+    }
+
+    /// Matches between two orders
+    pub fn trades(&self) -> u64 {
+        self.0.trades.load(Ordering::Relaxed)
+    }
+
+    /// Units exchanged in all matches
+    pub fn traded_quantity(&self) -> u64 {
+        self.0.traded_quantity.load(Ordering::Relaxed)
+    }
+
+    /// Resting orders removed before they were filled
+    pub fn cancels(&self) -> u64 {
+        self.0.cancels.load(Ordering::Relaxed)
+    }
+}
+
+impl EngineObserver for EngineActivity {
+    fn on_order_accepted(&mut self, _order: &PartialOrder) {
+        self.0.orders.fetch_add(1, Ordering::Relaxed);
+    }
+
+    fn on_trade(&mut self, _taker: &PartialOrder, maker: &PartialOrder) {
+        self.0.trades.fetch_add(1, Ordering::Relaxed);
+        self.0
+            .traded_quantity
+            .fetch_add(maker.amount, Ordering::Relaxed);
+    }
+
+    fn on_cancel(&mut self, _order: &PartialOrder) {
+        self.0.cancels.fetch_add(1, Ordering::Relaxed);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    // reduce the warnings for naming tests
+    #![allow(non_snake_case)]
+
+    use crate::trading_platform::TradingPlatform;
+    use octopus_common::types::{Order, OrderType, Side, TimeInForce, DEFAULT_MARKET};
+
+    fn order(signer: &str, side: Side, amount: u64) -> Order {
+        Order {
+            price: 10,
+            amount,
+            side,
+            signer: signer.to_string(),
+            client_order_id: None,
+            signature: None,
+            order_type: OrderType::Limit,
+            time_in_force: TimeInForce::Gtc,
+            trigger_price: None,
+            market: DEFAULT_MARKET.to_string(),
+        }
+    }
+
+    #[test]
+    fn test_EngineActivity_counts_the_public_book() {
+        let mut trading_platform = TradingPlatform::new();
+        trading_platform.deposit("ALICE", 1000).unwrap();
+        trading_platform.deposit("BOB", 1000).unwrap();
+        trading_platform
+            .order(order("ALICE", Side::Sell, 5))
+            .unwrap();
+        trading_platform.order(order("BOB", Side::Buy, 3)).unwrap();
+        trading_platform.cancel_all("ALICE", 0);
+
+        let activity = &trading_platform.activity;
+        assert_eq!(activity.orders(), 2);
+        assert_eq!(activity.trades(), 1);
+        assert_eq!(activity.traded_quantity(), 3);
+        assert_eq!(activity.cancels(), 1);
+    }
+}
@@ -0,0 +1,148 @@
+//! Saved recipients per account, so transfers can name a short alias instead of retyping the recipient's signer.
+use octopus_common::{errors::ApplicationError, types::SavedRecipient};
+use std::collections::{BTreeMap, HashMap};
+
+/// The longest alias in characters
+pub const MAX_ALIAS_LENGTH: usize = 32;
+
+/// Aliases are short, case-sensitive names of letters, digits, `-`, and `_`
+fn validate_alias(alias: &str) -> Result<(), ApplicationError> {
+    let valid = !alias.is_empty()
+        && alias.chars().count() <= MAX_ALIAS_LENGTH
+        && alias
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_');
+    if valid {
+        Ok(())
+    } else {
+        Err(ApplicationError::InvalidAlias(format!(
+            "an alias has 1 to {} letters, digits, '-', or '_'",
+            MAX_ALIAS_LENGTH
+        )))
+    }
+}
+
+/// The saved recipients of every account, by alias
+#[derive(Debug, Default)]
+pub struct AddressBook {
+    entries: HashMap<String, BTreeMap<String, String>>,
+}
+
+impl AddressBook {
+    pub fn new() -> Self {
+        AddressBook::default()
+    }
+
+    /// Saves `signer` as `alias` of `owner`, replacing an earlier recipient of the alias
+    /// # Errors
+    /// The alias is empty, too long, or contains other characters
+    pub fn save(
+        &mut self,
+        owner: &str,
+        alias: &str,
+        signer: &str,
+    ) -> Result<SavedRecipient, ApplicationError> {
+        validate_alias(alias)?;
+        self.entries
+            .entry(owner.to_string())
+            .or_default()
+            .insert(alias.to_string(), signer.to_string());
+        Ok(SavedRecipient {
+            alias: alias.to_string(),
+            signer: signer.to_string(),
+        })
+    }
+
+    /// Removes `alias` from the recipients of `owner`
+    /// # Errors
+    /// The owner has no such alias
+    pub fn delete(&mut self, owner: &str, alias: &str) -> Result<SavedRecipient, ApplicationError> {
+        self.entries
+            .get_mut(owner)
+            .and_then(|recipients| recipients.remove(alias))
+            .map(|signer| SavedRecipient {
+                alias: alias.to_string(),
+                signer,
+            })
+            .ok_or(ApplicationError::AliasNotFound(alias.to_string()))
+    }
+
+    /// The saved recipients of `owner`, by alias
+    pub fn list(&self, owner: &str) -> Vec<SavedRecipient> {
+        self.entries
+            .get(owner)
+            .into_iter()
+            .flatten()
+            .map(|(alias, signer)| SavedRecipient {
+                alias: alias.clone(),
+                signer: signer.clone(),
+            })
+            .collect()
+    }
+
+    /// The signer `owner` saved as `alias`
+    /// # Errors
+    /// The owner has no such alias
+    pub fn resolve(&self, owner: &str, alias: &str) -> Result<&str, ApplicationError> {
+        self.entries
+            .get(owner)
+            .and_then(|recipients| recipients.get(alias))
+            .map(String::as_str)
+            .ok_or(ApplicationError::AliasNotFound(alias.to_string()))
+    }
+
+    /// Forgets the recipients of `signer` and every alias of other accounts pointing to it
+    pub fn remove(&mut self, signer: &str) {
+        self.entries.remove(signer);
+        for recipients in self.entries.values_mut() {
+            recipients.retain(|_, recipient| recipient != signer);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    // reduce the warnings for naming tests
+    #![allow(non_snake_case)]
+
+    use super::*;
+
+    #[test]
+    fn test_AddressBook_resolve_is_scoped_to_the_owner() {
+        let mut book = AddressBook::new();
+        book.save("ALICE", "bob", "BOB").unwrap();
+        book.save("ALICE", "carol", "CAROL").unwrap();
+        assert_eq!(book.resolve("ALICE", "bob"), Ok("BOB"));
+        assert_eq!(
+            book.resolve("CAROL", "bob"),
+            Err(ApplicationError::AliasNotFound("bob".to_string()))
+        );
+        assert!(matches!(
+            book.save("ALICE", "bob smith", "BOB"),
+            Err(ApplicationError::InvalidAlias(_))
+        ));
+
+        book.save("ALICE", "bob", "BOBBY").unwrap();
+        assert_eq!(book.resolve("ALICE", "bob"), Ok("BOBBY"));
+        assert_eq!(book.delete("ALICE", "bob").unwrap().signer, "BOBBY");
+        assert!(book.resolve("ALICE", "bob").is_err());
+    }
+
+    #[test]
+    fn test_AddressBook_remove_drops_aliases_of_the_account() {
+        let mut book = AddressBook::new();
+        book.save("ALICE", "bob", "BOB").unwrap();
+        book.save("ALICE", "carol", "CAROL").unwrap();
+        book.save("BOB", "alice", "ALICE").unwrap();
+
+        book.remove("BOB");
+        assert!(book.list("BOB").is_empty());
+        assert_eq!(
+            book.list("ALICE"),
+            vec![SavedRecipient {
+                alias: "carol".to_string(),
+                signer: "CAROL".to_string()
+            }]
+        );
+    }
+}
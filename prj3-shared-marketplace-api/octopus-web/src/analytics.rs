@@ -0,0 +1,178 @@
+//! Market analytics for surveillance and capacity planning: the open interest of every market, how much of its book
+//! single accounts hold, and its turnover by account.
+//!
+//! Everything is computed from the books and the tape on request, nothing is kept in between. Open interest covers the
+//! whole tape, turnover only the trades of the window. Busted trades don't count, the sandbox book isn't included.
+use octopus_common::types::{AccountConcentration, MarketAnalytics, Side, Trade};
+use std::{
+    cmp::Reverse,
+    collections::{BTreeMap, HashMap},
+};
+
+use crate::core::MatchingEngine;
+
+/// The share of `part` in `total` in basis points, 0 of nothing
+fn share_bps(part: u64, total: u64) -> u64 {
+    match total {
+        0 => 0,
+        total => (part as u128 * 10_000 / total as u128) as u64,
+    }
+}
+
+/// What is known of one market while the books and the tape are walked
+#[derive(Default)]
+struct Tally {
+    analytics: MarketAnalytics,
+    /// Net units bought by account, over all trades
+    positions: HashMap<String, i64>,
+    accounts: HashMap<String, AccountConcentration>,
+}
+
+impl Tally {
+    fn account(&mut self, signer: &str) -> &mut AccountConcentration {
+        self.accounts
+            .entry(signer.to_string())
+            .or_insert_with(|| AccountConcentration {
+                signer: signer.to_string(),
+                ..AccountConcentration::default()
+            })
+    }
+}
+
+/// Analyzes the market of each book with the trades of the tape, counting turnover from `since` (unix ms) on. Markets
+/// without a book but with trades are included, the markets are ordered by symbol.
+pub fn analyze<'a>(
+    books: impl IntoIterator<Item = (&'a str, &'a MatchingEngine)>,
+    trades: &[Trade],
+    since: u64,
+) -> Vec<MarketAnalytics> {
+    let mut tallies: BTreeMap<String, Tally> = BTreeMap::new();
+    for (market, book) in books {
+        let tally = tallies.entry(market.to_string()).or_default();
+        for order in book.orders() {
+            tally.analytics.resting += order.remaining;
+            tally.account(&order.signer).resting += order.remaining;
+        }
+    }
+    for trade in trades.iter().filter(|t| t.busted.is_none()) {
+        let tally = tallies.entry(trade.market.clone()).or_default();
+        let units = trade.amount as i64;
+        let (buyer, seller) = match trade.taker_side {
+            Side::Buy => (&trade.taker, &trade.maker),
+            Side::Sell => (&trade.maker, &trade.taker),
+        };
+        *tally.positions.entry(buyer.clone()).or_default() += units;
+        *tally.positions.entry(seller.clone()).or_default() -= units;
+        if trade.timestamp < since {
+            continue;
+        }
+        let notional = trade.amount * trade.price;
+        tally.analytics.turnover += trade.amount;
+        tally.analytics.notional += notional;
+        tally.analytics.trades += 1;
+        for signer in [buyer, seller] {
+            let account = tally.account(signer);
+            account.volume += trade.amount;
+            account.notional += notional;
+        }
+    }
+
+    tallies
+        .into_iter()
+        .map(|(market, tally)| {
+            let mut analytics = tally.analytics;
+            analytics.market = market;
+            analytics.open_interest = tally
+                .positions
+                .values()
+                .filter(|units| **units > 0)
+                .map(|units| *units as u64)
+                .sum();
+            let mut accounts: Vec<_> = tally.accounts.into_values().collect();
+            for account in accounts.iter_mut() {
+                account.book_share_bps = share_bps(account.resting, analytics.resting);
+            }
+            accounts.sort_by(|a, b| {
+                (Reverse(a.resting), Reverse(a.volume), &a.signer).cmp(&(
+                    Reverse(b.resting),
+                    Reverse(b.volume),
+                    &b.signer,
+                ))
+            });
+            analytics.top_book_share_bps = accounts.first().map_or(0, |a| a.book_share_bps);
+            analytics.accounts = accounts;
+            analytics
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    // reduce the warnings for naming tests
+    #![allow(non_snake_case)]
+
+    use super::*;
+    use octopus_common::types::{Order, OrderType, TimeInForce, DEFAULT_MARKET};
+
+    #[test]
+    fn test_analyze_reports_open_interest_concentration_and_turnover() {
+        let mut book = MatchingEngine::new();
+        for (signer, amount) in [("ALICE", 3), ("BOB", 1)] {
+            book.process(Order {
+                price: 10,
+                amount,
+                side: Side::Sell,
+                signer: signer.to_string(),
+                client_order_id: None,
+                signature: None,
+                order_type: OrderType::Limit,
+                time_in_force: TimeInForce::Gtc,
+                trigger_price: None,
+                market: DEFAULT_MARKET.to_string(),
+            })
+            .unwrap();
+        }
+        let trade = |timestamp, taker: &str, maker: &str, amount, busted| Trade {
+            id: timestamp,
+            ordinal: timestamp,
+            maker_ordinal: 0,
+            timestamp,
+            market: DEFAULT_MARKET.to_string(),
+            price: 10,
+            amount,
+            taker: taker.to_string(),
+            maker: maker.to_string(),
+            taker_side: Side::Buy,
+            limit_price: None,
+            taker_fee: 0,
+            maker_fee: 0,
+            busted,
+        };
+        let trades = [
+            trade(1, "CAROL", "BOB", 5, None),
+            trade(2, "BOB", "CAROL", 2, None),
+            trade(3, "CAROL", "BOB", 7, Some(4)),
+        ];
+
+        let analytics = analyze([(DEFAULT_MARKET, &book)], &trades, 2);
+        assert_eq!(analytics.len(), 1);
+        let market = &analytics[0];
+        // CAROL is long 3, BOB short 3
+        assert_eq!(market.open_interest, 3);
+        assert_eq!(market.resting, 4);
+        assert_eq!(market.top_book_share_bps, 7_500);
+        // Only the second trade is in the window, the third was busted
+        assert_eq!(
+            (market.turnover, market.notional, market.trades),
+            (2, 20, 1)
+        );
+        assert_eq!(
+            market
+                .accounts
+                .iter()
+                .map(|a| (a.signer.as_str(), a.book_share_bps, a.volume))
+                .collect::<Vec<_>>(),
+            vec![("ALICE", 7_500, 0), ("BOB", 2_500, 2), ("CAROL", 0, 2)]
+        );
+    }
+}
@@ -0,0 +1,181 @@
+use octopus_common::{
+    errors::ApplicationError,
+    types::{ApiKey, ApiKeyScope, NewApiKey, Role},
+};
+use rand::RngCore;
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+
+/// Random bytes in a key id
+const KEY_ID_BYTES: usize = 8;
+/// Random bytes in a key secret
+const KEY_SECRET_BYTES: usize = 32;
+
+/// An API key together with the hash of its secret
+#[derive(Debug)]
+struct StoredApiKey {
+    key: ApiKey,
+    secret_hash: String,
+}
+
+/// A type for managing the API keys of all accounts. Only hashes of the secrets are kept.
+#[derive(Debug, Default)]
+pub struct ApiKeys {
+    keys: HashMap<String, StoredApiKey>,
+}
+
+pub(crate) fn random_hex(len: usize) -> String {
+    let mut bytes = vec![0; len];
+    rand::thread_rng().fill_bytes(&mut bytes);
+    hex::encode(bytes)
+}
+
+fn hash_secret(secret: &str) -> String {
+    hex::encode(Sha256::digest(secret.as_bytes()))
+}
+
+impl ApiKeys {
+    /// Returns an empty instance of the [`ApiKeys`] type
+    pub fn new() -> Self {
+        ApiKeys {
+            keys: HashMap::new(),
+        }
+    }
+
+    /// Creates a new key for `signer` with the provided `role` and `scopes`. The secret has the form `<id>.<random>`.
+    /// # Errors
+    /// No scopes were provided
+    pub fn create(
+        &mut self,
+        signer: &str,
+        role: Role,
+        scopes: &[ApiKeyScope],
+    ) -> Result<NewApiKey, ApplicationError> {
+        if scopes.is_empty() {
+            return Err(ApplicationError::ApiKeyWithoutScopes(signer.to_string()));
+        }
+        let mut unique_scopes = vec![];
+        for scope in scopes {
+            if !unique_scopes.contains(scope) {
+                unique_scopes.push(*scope);
+            }
+        }
+
+        let id = format!("ak_{}", random_hex(KEY_ID_BYTES));
+        let secret = format!("{}.{}", id, random_hex(KEY_SECRET_BYTES));
+        let key = ApiKey {
+            id: id.clone(),
+            signer: signer.to_string(),
+            role,
+            scopes: unique_scopes,
+            revoked: false,
+        };
+        self.keys.insert(
+            id,
+            StoredApiKey {
+                key: key.clone(),
+                secret_hash: hash_secret(&secret),
+            },
+        );
+        Ok(NewApiKey { key, secret })
+    }
+
+    /// Lists all keys (including revoked ones) of an account
+    pub fn list(&self, signer: &str) -> Vec<ApiKey> {
+        let mut keys: Vec<ApiKey> = self
+            .keys
+            .values()
+            .filter(|stored| stored.key.signer == signer)
+            .map(|stored| stored.key.clone())
+            .collect();
+        keys.sort_by(|a, b| a.id.cmp(&b.id));
+        keys
+    }
+
+    /// Revokes the key `id` of `signer`. Revoking a key twice is fine.
+    /// # Errors
+    /// The key doesn't exist or belongs to someone else
+    pub fn revoke(&mut self, signer: &str, id: &str) -> Result<ApiKey, ApplicationError> {
+        match self.keys.get_mut(id) {
+            Some(stored) if stored.key.signer == signer => {
+                stored.key.revoked = true;
+                Ok(stored.key.clone())
+            }
+            _ => Err(ApplicationError::ApiKeyNotFound(id.to_string())),
+        }
+    }
+
+    /// Revokes every key of `signer` and moves them to `token`
+    pub fn anonymize(&mut self, signer: &str, token: &str) {
+        for stored in self.keys.values_mut() {
+            if stored.key.signer == signer {
+                stored.key.signer = token.to_string();
+                stored.key.revoked = true;
+            }
+        }
+    }
+
+    /// Looks up the active key that belongs to the `secret`
+    pub fn authenticate(&self, secret: &str) -> Option<&ApiKey> {
+        let (id, _) = secret.split_once('.')?;
+        self.keys
+            .get(id)
+            .filter(|stored| !stored.key.revoked && stored.secret_hash == hash_secret(secret))
+            .map(|stored| &stored.key)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_api_keys_create_and_authenticate() {
+        let mut api_keys = ApiKeys::new();
+        let new_key = api_keys
+            .create(
+                "a-key",
+                Role::Trader,
+                &[ApiKeyScope::Read, ApiKeyScope::Read],
+            )
+            .unwrap();
+        assert_eq!(new_key.key.scopes, vec![ApiKeyScope::Read]);
+        assert!(new_key.secret.starts_with(&new_key.key.id));
+
+        assert_eq!(api_keys.authenticate(&new_key.secret), Some(&new_key.key));
+        assert_eq!(api_keys.authenticate(&new_key.key.id), None);
+        assert_eq!(
+            api_keys.authenticate(&format!("{}.wrong", new_key.key.id)),
+            None
+        );
+    }
+
+    #[test]
+    fn test_api_keys_create_requires_scopes() {
+        let mut api_keys = ApiKeys::new();
+        assert_eq!(
+            api_keys.create("a-key", Role::Trader, &[]),
+            Err(ApplicationError::ApiKeyWithoutScopes("a-key".to_string()))
+        );
+    }
+
+    #[test]
+    fn test_api_keys_revoke_works() {
+        let mut api_keys = ApiKeys::new();
+        let new_key = api_keys
+            .create("a-key", Role::Trader, &[ApiKeyScope::Trade])
+            .unwrap();
+
+        // Only the owner can revoke a key
+        assert_eq!(
+            api_keys.revoke("b-key", &new_key.key.id),
+            Err(ApplicationError::ApiKeyNotFound(new_key.key.id.clone()))
+        );
+
+        let revoked = api_keys.revoke("a-key", &new_key.key.id).unwrap();
+        assert!(revoked.revoked);
+        assert_eq!(api_keys.authenticate(&new_key.secret), None);
+        assert_eq!(api_keys.list("a-key"), vec![revoked]);
+        assert!(api_keys.list("b-key").is_empty());
+    }
+}
@@ -0,0 +1,253 @@
+//! Exports of everything the platform stores about one account, e.g. for data subject access requests.
+//!
+//! Small archives are returned right away. Larger ones are serialized in the background and fetched from a download
+//! link once they're ready.
+use octopus_common::{
+    errors::ApplicationError,
+    tx::Tx,
+    types::{
+        ApiKey, Hold, Invoice, PartialOrder, PendingWithdrawal, RecurringBuy, StopLossStatus, Trade,
+    },
+};
+use serde::Serialize;
+use std::collections::BTreeMap;
+
+use crate::trading_platform::TradingPlatform;
+
+/// Archives with more transactions and trades than this are generated in the background
+pub const INLINE_ARCHIVE_LIMIT: usize = 1000;
+
+/// How long a generated archive can be downloaded
+pub const ARCHIVE_RETENTION_MILLIS: u64 = 60 * 60 * 1000;
+
+/// The settings of an account
+#[derive(Debug, Clone, Serialize)]
+pub struct AccountProfile {
+    pub sandbox: bool,
+    pub leaderboard_opt_in: bool,
+    pub api_keys: Vec<ApiKey>,
+    pub recurring_buys: Vec<RecurringBuy>,
+    pub stop_loss: Option<StopLossStatus>,
+}
+
+/// A transaction with when it happened
+#[derive(Debug, Clone, Serialize)]
+pub struct ArchivedTx {
+    /// The position of the transaction in the log, from 1
+    pub id: u64,
+    pub ordinal: u64,
+    pub timestamp: u64,
+    pub tx: Tx,
+    /// The tags the account put on it, in statements and account exports
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub tags: Vec<String>,
+}
+
+/// Everything stored about one account
+#[derive(Debug, Clone, Serialize)]
+pub struct AccountArchive {
+    pub signer: String,
+    /// Unix timestamp (ms)
+    pub generated_at: u64,
+    pub profile: AccountProfile,
+    pub balance: u64,
+    pub holds: Vec<Hold>,
+    pub withdrawals: Vec<PendingWithdrawal>,
+    /// Every transaction the account was a party of, oldest first
+    pub transactions: Vec<ArchivedTx>,
+    /// The open orders, in the book of every market and the sandbox book
+    pub orders: Vec<PartialOrder>,
+    /// Every trade the account took part in as taker or maker, oldest first
+    pub trades: Vec<Trade>,
+    pub invoices: Vec<Invoice>,
+}
+
+impl AccountArchive {
+    /// Collects the data of `signer` as of `now`, only the transactions it tagged with `tag` if set
+    /// # Errors
+    /// The account doesn't exist
+    pub fn collect(
+        trading_platform: &TradingPlatform,
+        signer: &str,
+        tag: Option<&str>,
+        now: u64,
+    ) -> Result<Self, ApplicationError> {
+        let balance = *trading_platform.accounts.balance_of(signer)?;
+        let profile = AccountProfile {
+            sandbox: trading_platform.accounts.is_sandbox(signer),
+            leaderboard_opt_in: trading_platform.trade_stats.is_opted_in(signer),
+            api_keys: trading_platform.api_keys_of(signer)?,
+            recurring_buys: trading_platform.recurring_buys.list(signer),
+            stop_loss: trading_platform.stop_loss_of(signer, now).ok(),
+        };
+        let own = |account: &str| account == signer;
+        Ok(AccountArchive {
+            signer: signer.to_string(),
+            generated_at: now,
+            profile,
+            balance,
+            holds: trading_platform
+                .accounts
+                .holds()
+                .into_iter()
+                .filter(|hold| own(&hold.account))
+                .collect(),
+            withdrawals: trading_platform
+                .withdrawals
+                .values()
+                .filter(|withdrawal| own(&withdrawal.account))
+                .cloned()
+                .collect(),
+            transactions: trading_platform.statement(signer, tag)?,
+            orders: trading_platform
+                .orderbook()
+                .into_iter()
+                .chain(
+                    trading_platform
+                        .books
+                        .values()
+                        .flat_map(|book| book.orders()),
+                )
+                .chain(trading_platform.sandbox_book.orders())
+                .filter(|order| own(&order.signer))
+                .collect(),
+            trades: trading_platform
+                .trades
+                .iter()
+                .chain(trading_platform.sandbox_trades.iter())
+                .filter(|trade| own(&trade.taker) || own(&trade.maker))
+                .cloned()
+                .collect(),
+            invoices: trading_platform.invoices.of(signer),
+        })
+    }
+
+    /// The number of transactions and trades, which make up most of an archive
+    pub fn entries(&self) -> usize {
+        self.transactions.len() + self.trades.len()
+    }
+}
+
+/// A requested archive, `None` while it's being generated
+#[derive(Debug)]
+struct StoredArchive {
+    signer: String,
+    requested_at: u64,
+    json: Option<Vec<u8>>,
+}
+
+/// Archives generated in the background by id, kept for [`ARCHIVE_RETENTION_MILLIS`]
+#[derive(Debug, Default)]
+pub struct Archives {
+    last_id: u64,
+    archives: BTreeMap<u64, StoredArchive>,
+}
+
+impl Archives {
+    /// Registers an archive of `signer` that's about to be generated and returns its id
+    pub fn start(&mut self, signer: &str, now: u64) -> u64 {
+        self.archives
+            .retain(|_, archive| archive.requested_at + ARCHIVE_RETENTION_MILLIS > now);
+        self.last_id += 1;
+        self.archives.insert(
+            self.last_id,
+            StoredArchive {
+                signer: signer.to_string(),
+                requested_at: now,
+                json: None,
+            },
+        );
+        self.last_id
+    }
+
+    /// Stores the generated archive
+    pub fn complete(&mut self, id: u64, json: Vec<u8>) {
+        if let Some(archive) = self.archives.get_mut(&id) {
+            archive.json = Some(json);
+        }
+    }
+
+    /// Drops every archive of `signer`
+    pub fn discard(&mut self, signer: &str) {
+        self.archives.retain(|_, archive| archive.signer != signer);
+    }
+
+    /// The archive `id` of `signer`, `None` while it's being generated
+    /// # Errors
+    /// There's no such archive of `signer`, or it expired
+    pub fn get(&self, signer: &str, id: u64) -> Result<Option<&[u8]>, ApplicationError> {
+        self.archives
+            .get(&id)
+            .filter(|archive| archive.signer == signer)
+            .map(|archive| archive.json.as_deref())
+            .ok_or(ApplicationError::ArchiveNotFound(id))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    // reduce the warnings for naming tests
+    #![allow(non_snake_case)]
+
+    use super::*;
+    use octopus_common::types::{Order, OrderType, Side, TimeInForce, DEFAULT_MARKET};
+
+    #[test]
+    fn test_AccountArchive_collect_only_includes_the_account() {
+        let mut trading_platform = TradingPlatform::new();
+        trading_platform.deposit("ALICE", 100).unwrap();
+        trading_platform.deposit("BOB", 100).unwrap();
+        trading_platform.hold("BOB", 10).unwrap();
+        for (signer, side) in [
+            ("ALICE", Side::Sell),
+            ("BOB", Side::Buy),
+            ("BOB", Side::Buy),
+        ] {
+            trading_platform
+                .order(Order {
+                    price: 10,
+                    amount: 1,
+                    side,
+                    signer: signer.to_string(),
+                    client_order_id: None,
+                    signature: None,
+                    order_type: OrderType::Limit,
+                    time_in_force: TimeInForce::Gtc,
+                    trigger_price: None,
+                    market: DEFAULT_MARKET.to_string(),
+                })
+                .unwrap();
+        }
+
+        let archive = AccountArchive::collect(&trading_platform, "ALICE", None, 5).unwrap();
+        assert_eq!(archive.balance, 110);
+        assert_eq!(archive.transactions.len(), 2);
+        assert_eq!(archive.trades.len(), 1);
+        assert!(archive.orders.is_empty() && archive.holds.is_empty());
+        let archive = AccountArchive::collect(&trading_platform, "BOB", None, 5).unwrap();
+        assert_eq!((archive.orders.len(), archive.holds.len()), (1, 1));
+        assert!(matches!(
+            AccountArchive::collect(&trading_platform, "CAROL", None, 5),
+            Err(ApplicationError::AccountNotFound(_))
+        ));
+    }
+
+    #[test]
+    fn test_Archives_get_is_scoped_to_the_signer_and_expires() {
+        let mut archives = Archives::default();
+        let id = archives.start("ALICE", 0);
+        assert_eq!(archives.get("ALICE", id), Ok(None));
+        archives.complete(id, b"{}".to_vec());
+        assert_eq!(archives.get("ALICE", id), Ok(Some(&b"{}"[..])));
+        assert_eq!(
+            archives.get("BOB", id),
+            Err(ApplicationError::ArchiveNotFound(id))
+        );
+
+        archives.start("BOB", ARCHIVE_RETENTION_MILLIS);
+        assert_eq!(
+            archives.get("ALICE", id),
+            Err(ApplicationError::ArchiveNotFound(id))
+        );
+    }
+}
@@ -0,0 +1,258 @@
+//! The audit trail of administrative and security-relevant actions: who changed what, why, and the state before and
+//! after.
+//!
+//! Entries are kept in memory for the admin query endpoint and also written to the `audit` log target, so they can
+//! be shipped to durable storage with the regular log pipeline.
+use serde::{Deserialize, Serialize};
+use std::sync::{Arc, Mutex};
+use warp::Filter;
+
+use crate::{
+    scheduler::now_millis,
+    search::SearchQuery,
+    tenants::{DEFAULT_TENANT, TENANT_HEADER},
+};
+
+/// The header carrying the reason for an administrative action
+pub const JUSTIFICATION_HEADER: &str = "x-justification";
+
+/// The log target audit entries are written to
+pub const AUDIT_TARGET: &str = "audit";
+
+/// The actor recorded for changes that weren't requested through the API, e.g. a reload on SIGHUP
+pub const SYSTEM_ACTOR: &str = "system";
+
+/// The operations that are audited, mostly administrative ones
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AuditAction {
+    /// Funds were credited to an account by an operator
+    ManualDeposit,
+    /// Play funds were issued to a sandbox account by an operator or claimed from the faucet
+    SandboxFunding,
+    ApiKeyIssued,
+    WithdrawalResolved,
+    /// An erroneous trade was busted and its settlement reversed
+    TradeBusted,
+    /// The oracle price of a market was set, its reference price while it doesn't trade
+    OraclePriceSet,
+    /// A market was listed or its configuration replaced by an operator
+    MarketListed,
+    /// A call auction was opened, orders rest without matching until it's run
+    AuctionStarted,
+    /// The call auction was run and the book crossed at its clearing price
+    AuctionRun,
+    InvoicesGenerated,
+    ReportGenerated,
+    TenantCreated,
+    ConfigReloaded,
+    ChaosConfigured,
+    /// An account registered a public key for signing its orders
+    PublicKeyRegistered,
+    /// A signed order was accepted, the entry keeps the signature for non-repudiation
+    SignedOrder,
+    /// A signed amendment of a resting order was accepted, kept like a signed order
+    SignedAmend,
+}
+
+/// One audited operation
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AuditEntry {
+    pub id: u64,
+    /// Unix timestamp (ms)
+    pub timestamp: u64,
+    /// The account of the credential or signed order, or the admin key
+    pub actor: String,
+    pub tenant: String,
+    pub action: AuditAction,
+    pub justification: Option<String>,
+    /// The affected state before the operation, `null` if there was none
+    pub before: serde_json::Value,
+    pub after: serde_json::Value,
+}
+
+/// Filters for querying the audit trail, all of them optional
+#[derive(Debug, Default, Clone, Deserialize)]
+pub struct AuditQuery {
+    pub actor: Option<String>,
+    pub action: Option<AuditAction>,
+}
+
+/// Every audited operation since the start, oldest first
+#[derive(Debug, Default)]
+pub struct AuditLog {
+    entries: Mutex<Vec<AuditEntry>>,
+}
+
+impl AuditLog {
+    /// Appends an entry and writes it to the [`AUDIT_TARGET`]
+    pub fn record(
+        &self,
+        actor: &str,
+        tenant: &str,
+        justification: Option<String>,
+        action: AuditAction,
+        before: impl Serialize,
+        after: impl Serialize,
+    ) -> AuditEntry {
+        let mut entries = self.entries.lock().unwrap();
+        let entry = AuditEntry {
+            id: entries.len() as u64 + 1,
+            timestamp: now_millis(),
+            actor: actor.to_string(),
+            tenant: tenant.to_string(),
+            action,
+            justification,
+            before: serde_json::to_value(before).unwrap_or_default(),
+            after: serde_json::to_value(after).unwrap_or_default(),
+        };
+        match serde_json::to_string(&entry) {
+            Ok(json) => log::info!(target: AUDIT_TARGET, "{}", json),
+            Err(e) => log::error!(target: AUDIT_TARGET, "Can't serialize {:?}: {}", entry, e),
+        }
+        entries.push(entry.clone());
+        entry
+    }
+
+    /// The entries matching every filter of `query`, oldest first
+    pub fn query(&self, query: &AuditQuery) -> Vec<AuditEntry> {
+        self.entries
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|entry| {
+                query
+                    .actor
+                    .as_ref()
+                    .is_none_or(|actor| &entry.actor == actor)
+            })
+            .filter(|entry| query.action.is_none_or(|action| entry.action == action))
+            .cloned()
+            .collect()
+    }
+
+    /// The entries of `tenant` matching the search `query`, newest first
+    pub fn search(&self, query: &SearchQuery, tenant: &str) -> Vec<AuditEntry> {
+        self.entries
+            .lock()
+            .unwrap()
+            .iter()
+            .rev()
+            .filter(|entry| entry.tenant == tenant && query.matches_audit(entry))
+            .take(query.limit())
+            .cloned()
+            .collect()
+    }
+}
+
+/// The audit trail together with the tenant and justification of the request
+#[derive(Debug, Clone)]
+pub struct Auditor {
+    pub log: Arc<AuditLog>,
+    pub tenant: String,
+    pub justification: Option<String>,
+}
+
+impl Default for Auditor {
+    fn default() -> Self {
+        Auditor {
+            log: Arc::default(),
+            tenant: DEFAULT_TENANT.to_string(),
+            justification: None,
+        }
+    }
+}
+
+impl Auditor {
+    /// Records an operation of `actor` on the request's tenant
+    pub fn record(
+        &self,
+        actor: &str,
+        action: AuditAction,
+        before: impl Serialize,
+        after: impl Serialize,
+    ) -> AuditEntry {
+        self.log.record(
+            actor,
+            &self.tenant,
+            self.justification.clone(),
+            action,
+            before,
+            after,
+        )
+    }
+}
+
+/// A filter that provides an [`Auditor`] for the tenant and justification in the request headers
+pub fn with_auditor(
+    log: Arc<AuditLog>,
+) -> impl Filter<Extract = (Auditor,), Error = warp::Rejection> + Clone {
+    warp::header::optional::<String>(TENANT_HEADER)
+        .and(warp::header::optional::<String>(JUSTIFICATION_HEADER))
+        .map(
+            move |tenant: Option<String>, justification: Option<String>| Auditor {
+                log: log.clone(),
+                tenant: tenant.unwrap_or_else(|| DEFAULT_TENANT.to_string()),
+                justification,
+            },
+        )
+}
+
+#[cfg(test)]
+mod tests {
+    // reduce the warnings for naming tests
+    #![allow(non_snake_case)]
+
+    use super::*;
+
+    #[test]
+    fn test_AuditLog_query_filters_by_actor_and_action() {
+        let log = AuditLog::default();
+        log.record(
+            "admin",
+            DEFAULT_TENANT,
+            Some("customer complaint".to_string()),
+            AuditAction::ManualDeposit,
+            Some(10),
+            Some(20),
+        );
+        log.record(
+            "OPS",
+            DEFAULT_TENANT,
+            None,
+            AuditAction::ManualDeposit,
+            0,
+            5,
+        );
+        log.record("admin", "acme", None, AuditAction::TenantCreated, (), ());
+
+        let all = log.query(&AuditQuery::default());
+        assert_eq!(all.iter().map(|e| e.id).collect::<Vec<_>>(), [1, 2, 3]);
+        let deposits = log.query(&AuditQuery {
+            actor: Some("admin".to_string()),
+            action: Some(AuditAction::ManualDeposit),
+        });
+        assert_eq!(deposits.len(), 1);
+        assert_eq!(deposits[0].before, serde_json::json!(10));
+        assert_eq!(deposits[0].after, serde_json::json!(20));
+        assert_eq!(
+            deposits[0].justification.as_deref(),
+            Some("customer complaint")
+        );
+    }
+
+    #[tokio::test]
+    async fn test_with_auditor_reads_tenant_and_justification() {
+        let filter = with_auditor(Arc::new(AuditLog::default()));
+        let auditor = warp::test::request()
+            .header(TENANT_HEADER, "acme")
+            .header(JUSTIFICATION_HEADER, "month end")
+            .filter(&filter)
+            .await
+            .unwrap();
+        assert_eq!(auditor.tenant, "acme");
+        assert_eq!(auditor.justification.as_deref(), Some("month end"));
+        let auditor = warp::test::request().filter(&filter).await.unwrap();
+        assert_eq!(auditor.tenant, DEFAULT_TENANT);
+    }
+}
@@ -0,0 +1,355 @@
+use octopus_common::{
+    errors::{ApplicationError, OctopusError},
+    types::{ApiKeyScope, Role},
+};
+use rand::RngCore;
+use sha2::{Digest, Sha256};
+use std::sync::{Arc, Mutex};
+use warp::Filter;
+
+use crate::trading_platform::TradingPlatform;
+
+/// The header carrying the API key secret
+pub const API_KEY_HEADER: &str = "x-api-key";
+
+/// The actor of operations authenticated with the admin key, which isn't bound to an account
+pub const ADMIN_ACTOR: &str = "admin";
+
+/// The platform-wide admin secret. Only its hash is kept in memory.
+#[derive(Debug)]
+pub struct AdminKey {
+    secret_hash: Vec<u8>,
+}
+
+impl AdminKey {
+    /// Creates an [`AdminKey`] from a known secret
+    pub fn new(secret: &str) -> Self {
+        AdminKey {
+            secret_hash: Sha256::digest(secret.as_bytes()).to_vec(),
+        }
+    }
+
+    /// Uses the configured secret or generates a random one. The generated secret is returned for printing.
+    pub fn from_config(secret: Option<&str>) -> (Self, Option<String>) {
+        match secret {
+            Some(secret) if !secret.is_empty() => (AdminKey::new(secret), None),
+            _ => {
+                let mut bytes = [0; 32];
+                rand::thread_rng().fill_bytes(&mut bytes);
+                let secret = hex::encode(bytes);
+                (AdminKey::new(&secret), Some(secret))
+            }
+        }
+    }
+
+    fn matches(&self, secret: &str) -> bool {
+        Sha256::digest(secret.as_bytes()).as_slice() == self.secret_hash.as_slice()
+    }
+}
+
+/// The authenticated identity behind a request
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Credential {
+    /// What the credential may do
+    pub role: Role,
+    /// The account the credential is bound to. Admin credentials aren't bound to any account.
+    pub signer: Option<String>,
+    /// Permissions of the underlying API key
+    pub scopes: Vec<ApiKeyScope>,
+}
+
+impl Credential {
+    /// A credential with all privileges
+    pub fn admin() -> Self {
+        Credential {
+            role: Role::Admin,
+            signer: None,
+            scopes: vec![ApiKeyScope::Read, ApiKeyScope::Trade, ApiKeyScope::Withdraw],
+        }
+    }
+
+    /// Who acts with this credential, e.g. in the audit trail
+    pub fn actor(&self) -> &str {
+        self.signer.as_deref().unwrap_or(ADMIN_ACTOR)
+    }
+
+    fn forbidden(signer: &str) -> ApplicationError {
+        ApplicationError::Forbidden(signer.to_string())
+    }
+
+    /// Checks that the credential may act on behalf of `signer` with `scope`, which its key needs to have. Admins may
+    /// act for every account. Operators may too, but only admins move the funds of other accounts.
+    pub fn authorize(&self, signer: &str, scope: ApiKeyScope) -> Result<(), ApplicationError> {
+        if self.role == Role::Admin {
+            return Ok(());
+        }
+        let own_account = self.signer.as_deref() == Some(signer);
+        let role_permits = match self.role {
+            Role::Admin => true,
+            Role::Operator => own_account || scope != ApiKeyScope::Withdraw,
+            Role::Trader => own_account,
+            Role::ReadOnly => own_account && scope == ApiKeyScope::Read,
+        };
+        if role_permits && self.scopes.contains(&scope) {
+            Ok(())
+        } else {
+            Err(Credential::forbidden(signer))
+        }
+    }
+
+    /// Checks that the credential may revoke the keys of `signer`. Revoking can lock the account's trading and
+    /// withdrawal clients out, so it takes the highest scope, like withdrawing.
+    pub fn authorize_revoke(&self, signer: &str) -> Result<(), ApplicationError> {
+        self.authorize(signer, ApiKeyScope::Withdraw)
+    }
+
+    /// Checks that the credential may tag the transactions of `signer`. Tagging changes the account's records, so it
+    /// takes the trade or the withdraw scope.
+    pub fn authorize_tagging(&self, signer: &str) -> Result<(), ApplicationError> {
+        self.authorize(signer, ApiKeyScope::Trade)
+            .or_else(|_| self.authorize(signer, ApiKeyScope::Withdraw))
+    }
+
+    /// Checks that the credential may issue a key for `signer` with `role` and `scopes`. Keys can't have more privileges than their issuer,
+    /// and operators can't issue keys moving funds.
+    pub fn authorize_grant(
+        &self,
+        signer: &str,
+        role: Role,
+        scopes: &[ApiKeyScope],
+    ) -> Result<(), ApplicationError> {
+        let permitted = match self.role {
+            Role::Admin => true,
+            Role::Operator => {
+                role <= Role::Trader
+                    && !scopes.contains(&ApiKeyScope::Withdraw)
+                    && scopes.iter().all(|s| self.scopes.contains(s))
+            }
+            Role::Trader | Role::ReadOnly => {
+                self.signer.as_deref() == Some(signer)
+                    && role <= self.role
+                    && scopes.iter().all(|s| self.scopes.contains(s))
+            }
+        };
+        if permitted {
+            Ok(())
+        } else {
+            Err(Credential::forbidden(signer))
+        }
+    }
+}
+
+/// Resolves an API key secret to a [`Credential`]
+pub fn authenticate(
+    secret: Option<&str>,
+    admin_key: &AdminKey,
+    trading_platform: &TradingPlatform,
+) -> Result<Credential, ApplicationError> {
+    let secret = secret.ok_or(ApplicationError::Unauthorized(
+        "missing API key".to_string(),
+    ))?;
+    if admin_key.matches(secret) {
+        return Ok(Credential::admin());
+    }
+    trading_platform
+        .api_keys
+        .authenticate(secret)
+        .map(|key| Credential {
+            role: key.role,
+            signer: Some(key.signer.clone()),
+            scopes: key.scopes.clone(),
+        })
+        .ok_or(ApplicationError::Unauthorized(
+            "invalid API key".to_string(),
+        ))
+}
+
+/// A filter that authenticates the request's API key and requires a credential with at least `role`
+pub fn with_role<S>(
+    role: Role,
+    admin_key: Arc<AdminKey>,
+    trading_platform_state: S,
+) -> impl Filter<Extract = (Credential,), Error = warp::Rejection> + Clone
+where
+    S: Filter<Extract = (Arc<Mutex<TradingPlatform>>,), Error = warp::Rejection>
+        + Clone
+        + Send
+        + Sync
+        + 'static,
+{
+    warp::header::optional::<String>(API_KEY_HEADER)
+        .and(trading_platform_state)
+        .and_then(
+            move |secret: Option<String>, trading_platform: Arc<Mutex<TradingPlatform>>| {
+                let admin_key = admin_key.clone();
+                async move {
+                    let ledger_lock = trading_platform.lock().unwrap();
+                    let credential = authenticate(secret.as_deref(), &admin_key, &ledger_lock)
+                        .map_err(|e| warp::reject::custom(OctopusError(e)))?;
+                    if credential.role < role {
+                        return Err(warp::reject::custom(OctopusError(
+                            ApplicationError::Forbidden(format!("{:?} role required", role)),
+                        )));
+                    }
+                    Ok::<_, warp::Rejection>(credential)
+                }
+            },
+        )
+}
+
+#[cfg(test)]
+mod tests {
+    // reduce the warnings for naming tests
+    #![allow(non_snake_case)]
+
+    use super::*;
+
+    fn trader(signer: &str, scopes: &[ApiKeyScope]) -> Credential {
+        Credential {
+            role: Role::Trader,
+            signer: Some(signer.to_string()),
+            scopes: scopes.to_vec(),
+        }
+    }
+
+    #[test]
+    fn test_authenticate_resolves_admin_and_api_keys() {
+        let mut trading_platform = TradingPlatform::new();
+        trading_platform.deposit("ALICE", 100).unwrap();
+        let new_key = trading_platform
+            .create_api_key("ALICE", Role::ReadOnly, &[ApiKeyScope::Read])
+            .unwrap();
+        let admin_key = AdminKey::new("admin-secret");
+
+        assert_eq!(
+            authenticate(Some("admin-secret"), &admin_key, &trading_platform),
+            Ok(Credential::admin())
+        );
+        assert_eq!(
+            authenticate(Some(&new_key.secret), &admin_key, &trading_platform),
+            Ok(Credential {
+                role: Role::ReadOnly,
+                signer: Some("ALICE".to_string()),
+                scopes: vec![ApiKeyScope::Read],
+            })
+        );
+        assert!(authenticate(Some("wrong"), &admin_key, &trading_platform).is_err());
+        assert!(authenticate(None, &admin_key, &trading_platform).is_err());
+    }
+
+    #[test]
+    fn test_Credential_authorize_checks_account_and_scope() {
+        let alice = trader("ALICE", &[ApiKeyScope::Read, ApiKeyScope::Trade]);
+        assert_eq!(alice.authorize("ALICE", ApiKeyScope::Trade), Ok(()));
+        assert_eq!(
+            alice.authorize("ALICE", ApiKeyScope::Withdraw),
+            Err(ApplicationError::Forbidden("ALICE".to_string()))
+        );
+        assert_eq!(
+            alice.authorize("BOB", ApiKeyScope::Read),
+            Err(ApplicationError::Forbidden("BOB".to_string()))
+        );
+
+        // Read-only credentials can't trade even with the scope
+        let read_only = Credential {
+            role: Role::ReadOnly,
+            ..alice.clone()
+        };
+        assert_eq!(read_only.authorize("ALICE", ApiKeyScope::Read), Ok(()));
+        assert!(read_only.authorize("ALICE", ApiKeyScope::Trade).is_err());
+
+        // Operators need the scope too, and can't move the funds of other accounts
+        let operator = Credential {
+            role: Role::Operator,
+            signer: Some("ops".to_string()),
+            scopes: vec![ApiKeyScope::Read, ApiKeyScope::Trade, ApiKeyScope::Withdraw],
+        };
+        assert_eq!(operator.authorize("BOB", ApiKeyScope::Trade), Ok(()));
+        assert_eq!(
+            operator.authorize("BOB", ApiKeyScope::Withdraw),
+            Err(ApplicationError::Forbidden("BOB".to_string()))
+        );
+        assert_eq!(operator.authorize("ops", ApiKeyScope::Withdraw), Ok(()));
+        let unscoped = Credential {
+            scopes: vec![],
+            ..operator
+        };
+        assert!(unscoped.authorize("BOB", ApiKeyScope::Read).is_err());
+        assert_eq!(
+            Credential::admin().authorize("BOB", ApiKeyScope::Withdraw),
+            Ok(())
+        );
+    }
+
+    #[test]
+    fn test_Credential_authorize_revoke_needs_the_withdraw_scope() {
+        let read = Credential {
+            role: Role::ReadOnly,
+            ..trader("ALICE", &[ApiKeyScope::Read])
+        };
+        assert_eq!(
+            read.authorize_revoke("ALICE"),
+            Err(ApplicationError::Forbidden("ALICE".to_string()))
+        );
+        let trading = trader("ALICE", &[ApiKeyScope::Read, ApiKeyScope::Trade]);
+        assert!(trading.authorize_revoke("ALICE").is_err());
+        let owner = trader("ALICE", &[ApiKeyScope::Read, ApiKeyScope::Withdraw]);
+        assert_eq!(owner.authorize_revoke("ALICE"), Ok(()));
+        assert!(owner.authorize_revoke("BOB").is_err());
+        assert_eq!(Credential::admin().authorize_revoke("BOB"), Ok(()));
+    }
+
+    #[test]
+    fn test_Credential_authorize_tagging_needs_the_trade_or_withdraw_scope() {
+        let read = trader("ALICE", &[ApiKeyScope::Read]);
+        assert_eq!(
+            read.authorize_tagging("ALICE"),
+            Err(ApplicationError::Forbidden("ALICE".to_string()))
+        );
+        let trading = trader("ALICE", &[ApiKeyScope::Read, ApiKeyScope::Trade]);
+        assert_eq!(trading.authorize_tagging("ALICE"), Ok(()));
+        assert!(trading.authorize_tagging("BOB").is_err());
+        let owner = trader("ALICE", &[ApiKeyScope::Read, ApiKeyScope::Withdraw]);
+        assert_eq!(owner.authorize_tagging("ALICE"), Ok(()));
+    }
+
+    #[test]
+    fn test_Credential_authorize_grant_prevents_escalation() {
+        let alice = trader("ALICE", &[ApiKeyScope::Read, ApiKeyScope::Trade]);
+        assert_eq!(
+            alice.authorize_grant("ALICE", Role::ReadOnly, &[ApiKeyScope::Read]),
+            Ok(())
+        );
+        assert!(alice
+            .authorize_grant("ALICE", Role::Trader, &[ApiKeyScope::Withdraw])
+            .is_err());
+        assert!(alice
+            .authorize_grant("ALICE", Role::Operator, &[ApiKeyScope::Read])
+            .is_err());
+        assert!(alice
+            .authorize_grant("BOB", Role::Trader, &[ApiKeyScope::Read])
+            .is_err());
+
+        let operator = Credential {
+            role: Role::Operator,
+            signer: Some("ops".to_string()),
+            scopes: vec![ApiKeyScope::Read, ApiKeyScope::Trade, ApiKeyScope::Withdraw],
+        };
+        assert!(operator
+            .authorize_grant(
+                "BOB",
+                Role::Trader,
+                &[ApiKeyScope::Read, ApiKeyScope::Trade]
+            )
+            .is_ok());
+        assert!(operator
+            .authorize_grant("BOB", Role::Trader, &[ApiKeyScope::Withdraw])
+            .is_err());
+        assert!(operator
+            .authorize_grant("BOB", Role::Operator, &[ApiKeyScope::Read])
+            .is_err());
+        assert!(Credential::admin()
+            .authorize_grant("ops", Role::Operator, &[ApiKeyScope::Read])
+            .is_ok());
+    }
+}
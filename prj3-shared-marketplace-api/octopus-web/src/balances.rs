@@ -0,0 +1,327 @@
+use std::collections::HashMap;
+
+use octopus_common::{errors::ApplicationError, tx::Tx};
+
+use crate::{
+    archives::ArchivedTx,
+    core::PointInTime,
+    fees::FEE_ACCOUNT,
+    search::{SearchQuery, TxIndex},
+};
+
+/// The number of transactions between two balance snapshots
+pub const SNAPSHOT_INTERVAL: usize = 1000;
+
+/// A transaction and when it happened
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Entry {
+    /// The matching engine's ordinal when the transaction happened
+    ordinal: u64,
+    timestamp: u64,
+    tx: Tx,
+}
+
+/// All balances after a number of transactions
+#[derive(Debug, Clone)]
+struct Snapshot {
+    entries: usize,
+    balances: HashMap<String, u64>,
+}
+
+/// Every [`Tx`] with periodic snapshots of all balances, so past balances are rebuilt by replaying
+/// the transactions after the closest snapshot.
+#[derive(Debug)]
+pub struct BalanceLog {
+    entries: Vec<Entry>,
+    snapshots: Vec<Snapshot>,
+    snapshot_interval: usize,
+    /// The balances after all entries, the base of the next snapshot
+    balances: HashMap<String, u64>,
+    index: TxIndex,
+}
+
+impl Default for BalanceLog {
+    fn default() -> Self {
+        BalanceLog::new(SNAPSHOT_INTERVAL)
+    }
+}
+
+impl BalanceLog {
+    pub fn new(snapshot_interval: usize) -> Self {
+        BalanceLog {
+            entries: vec![],
+            snapshots: vec![],
+            snapshot_interval: snapshot_interval.max(1),
+            balances: HashMap::new(),
+            index: TxIndex::default(),
+        }
+    }
+
+    /// Appends a transaction that was applied to the accounts
+    pub fn record(&mut self, ordinal: u64, timestamp: u64, tx: Tx) {
+        apply(&mut self.balances, &tx);
+        self.index.insert(self.entries.len(), &tx);
+        self.entries.push(Entry {
+            ordinal,
+            timestamp,
+            tx,
+        });
+        if self.entries.len().is_multiple_of(self.snapshot_interval) {
+            self.snapshots.push(Snapshot {
+                entries: self.entries.len(),
+                balances: self.balances.clone(),
+            });
+        }
+    }
+
+    /// The logged transactions, oldest first, with the ordinal and timestamp they happened at
+    pub fn entries(&self) -> impl Iterator<Item = (u64, u64, &Tx)> {
+        self.entries
+            .iter()
+            .map(|entry| (entry.ordinal, entry.timestamp, &entry.tx))
+    }
+
+    /// The transaction with `id`, its position in the log from 1
+    pub fn get(&self, id: u64) -> Option<&Tx> {
+        let position = usize::try_from(id).ok()?.checked_sub(1)?;
+        self.entries.get(position).map(|entry| &entry.tx)
+    }
+
+    /// Replaces `signer` with `token` in every entry and snapshot. Past balances are then found under the token.
+    pub fn anonymize(&mut self, signer: &str, token: &str) {
+        for entry in self.entries.iter_mut() {
+            entry.tx.anonymize(signer, token);
+        }
+        self.index.rename(signer, token);
+        let balance_maps = self
+            .snapshots
+            .iter_mut()
+            .map(|snapshot| &mut snapshot.balances)
+            .chain([&mut self.balances]);
+        for balances in balance_maps {
+            if let Some(balance) = balances.remove(signer) {
+                balances.insert(token.to_string(), balance);
+            }
+        }
+    }
+
+    /// The logged transactions matching `query`, newest first
+    pub fn search(&self, query: &SearchQuery) -> Vec<ArchivedTx> {
+        // Entries are logged in time order
+        let start = query.from.map_or(0, |from| {
+            self.entries.partition_point(|entry| entry.timestamp < from)
+        });
+        let end = query.until.map_or(self.entries.len(), |until| {
+            self.entries
+                .partition_point(|entry| entry.timestamp < until)
+        });
+        let positions: Vec<usize> = match self.index.candidates(query) {
+            Some(candidates) => candidates
+                .into_iter()
+                .filter(|position| (start..end).contains(position))
+                .collect(),
+            None => (start..end).collect(),
+        };
+        positions
+            .into_iter()
+            .rev()
+            .map(|position| (position, &self.entries[position]))
+            .filter(|(_, entry)| query.matches_tx(&entry.tx))
+            .take(query.limit())
+            .map(|(position, entry)| ArchivedTx {
+                id: position as u64 + 1,
+                ordinal: entry.ordinal,
+                timestamp: entry.timestamp,
+                tx: entry.tx.clone(),
+                tags: vec![],
+            })
+            .collect()
+    }
+
+    /// The balance of `signer` at a past point in time
+    /// # Errors
+    /// The account didn't exist at that point
+    pub fn balance_of_at(&self, signer: &str, at: PointInTime) -> Result<u64, ApplicationError> {
+        let count = match at {
+            PointInTime::Ordinal(ordinal) => self
+                .entries
+                .partition_point(|entry| entry.ordinal <= ordinal),
+            PointInTime::Timestamp(timestamp) => self
+                .entries
+                .partition_point(|entry| entry.timestamp <= timestamp),
+        };
+        let snapshot = self
+            .snapshots
+            .partition_point(|snapshot| snapshot.entries <= count)
+            .checked_sub(1)
+            .map(|i| &self.snapshots[i]);
+        let (start, mut balance) = match snapshot {
+            Some(snapshot) => (snapshot.entries, snapshot.balances.get(signer).copied()),
+            None => (0, None),
+        };
+        for entry in &self.entries[start..count] {
+            balance = replay(balance, signer, &entry.tx);
+        }
+        balance.ok_or(ApplicationError::AccountNotFound(signer.to_string()))
+    }
+}
+
+/// Applies the effect of a transaction on all balances
+fn apply(balances: &mut HashMap<String, u64>, tx: &Tx) {
+    for account in accounts_of(tx) {
+        let balance = replay(balances.get(account).copied(), account, tx);
+        if let Some(balance) = balance {
+            balances.insert(account.to_string(), balance);
+        }
+    }
+}
+
+/// The accounts whose balance a transaction changes
+fn accounts_of(tx: &Tx) -> Vec<&str> {
+    match tx {
+        Tx::Fee { account, .. } => vec![account, FEE_ACCOUNT],
+        Tx::Capture { to, .. } => vec![to],
+        Tx::Settlement { from, to, .. }
+        | Tx::SettlementReversed { from, to, .. }
+        | Tx::Payout { from, to, .. }
+        | Tx::DustSwept { from, to, .. } => vec![from, to],
+        Tx::Deposit { account, .. }
+        | Tx::Faucet { account, .. }
+        | Tx::SandboxClosed { account, .. }
+        | Tx::Withdraw { account, .. }
+        | Tx::Hold { account, .. }
+        | Tx::Release { account, .. }
+        | Tx::WithdrawalRequested { account, .. }
+        | Tx::WithdrawalApproved { account, .. }
+        | Tx::WithdrawalRejected { account, .. } => vec![account],
+    }
+}
+
+/// The balance of `signer` after a transaction. `None` means the account doesn't exist (yet).
+fn replay(balance: Option<u64>, signer: &str, tx: &Tx) -> Option<u64> {
+    let credit = |amount: u64| Some(balance.unwrap_or(0).saturating_add(amount));
+    let debit = |amount: u64| balance.map(|balance| balance.saturating_sub(amount));
+    match tx {
+        Tx::Deposit {
+            account, amount, ..
+        }
+        | Tx::Faucet { account, amount }
+            if account == signer =>
+        {
+            credit(*amount)
+        }
+        Tx::Fee { account, amount } if account == signer => debit(*amount),
+        Tx::Fee { amount, .. } if signer == FEE_ACCOUNT => credit(*amount),
+        Tx::Capture { to, amount, .. } if to == signer => credit(*amount),
+        // Self-trades don't change the balance
+        Tx::Settlement { from, to, .. }
+        | Tx::SettlementReversed { from, to, .. }
+        | Tx::Payout { from, to, .. }
+        | Tx::DustSwept { from, to, .. }
+            if from == to =>
+        {
+            balance
+        }
+        Tx::Settlement { to, amount, .. }
+        | Tx::SettlementReversed { to, amount, .. }
+        | Tx::Payout { to, amount, .. }
+        | Tx::DustSwept { to, amount, .. }
+            if to == signer =>
+        {
+            credit(*amount)
+        }
+        Tx::Settlement { from, amount, .. }
+        | Tx::SettlementReversed { from, amount, .. }
+        | Tx::Payout { from, amount, .. }
+        | Tx::DustSwept { from, amount, .. }
+            if from == signer =>
+        {
+            debit(*amount)
+        }
+        Tx::Release {
+            account, amount, ..
+        }
+        | Tx::WithdrawalRejected {
+            account, amount, ..
+        } if account == signer => credit(*amount),
+        Tx::Withdraw {
+            account, amount, ..
+        }
+        | Tx::SandboxClosed { account, amount }
+        | Tx::Hold {
+            account, amount, ..
+        }
+        | Tx::WithdrawalRequested {
+            account, amount, ..
+        } if account == signer => debit(*amount),
+        _ => balance,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    // reduce the warnings for naming tests
+    #![allow(non_snake_case)]
+
+    use super::*;
+
+    #[test]
+    fn test_BalanceLog_balance_of_at_replays_from_snapshots() {
+        let mut balance_log = BalanceLog::new(2);
+        let txs = vec![
+            Tx::Deposit {
+                account: "ALICE".to_string(),
+                amount: 100,
+                memo: None,
+            },
+            Tx::Hold {
+                id: 1,
+                account: "ALICE".to_string(),
+                amount: 30,
+            },
+            Tx::Fee {
+                account: "ALICE".to_string(),
+                amount: 5,
+            },
+            Tx::Capture {
+                id: 1,
+                from: "ALICE".to_string(),
+                to: FEE_ACCOUNT.to_string(),
+                amount: 30,
+            },
+            Tx::Withdraw {
+                account: "ALICE".to_string(),
+                amount: 15,
+                memo: None,
+            },
+        ];
+        for (i, tx) in txs.into_iter().enumerate() {
+            balance_log.record(i as u64 / 2, i as u64 * 10, tx);
+        }
+
+        assert_eq!(
+            balance_log.balance_of_at("ALICE", PointInTime::Timestamp(0)),
+            Ok(100)
+        );
+        assert_eq!(
+            balance_log.balance_of_at("ALICE", PointInTime::Ordinal(0)),
+            Ok(70)
+        );
+        assert_eq!(
+            balance_log.balance_of_at("ALICE", PointInTime::Timestamp(35)),
+            Ok(65)
+        );
+        assert_eq!(
+            balance_log.balance_of_at("ALICE", PointInTime::Ordinal(2)),
+            Ok(50)
+        );
+        assert_eq!(
+            balance_log.balance_of_at(FEE_ACCOUNT, PointInTime::Ordinal(1)),
+            Ok(35)
+        );
+        assert_eq!(
+            balance_log.balance_of_at(FEE_ACCOUNT, PointInTime::Timestamp(15)),
+            Err(ApplicationError::AccountNotFound(FEE_ACCOUNT.to_string()))
+        );
+    }
+}
@@ -0,0 +1,290 @@
+use std::collections::{BTreeMap, VecDeque};
+
+use octopus_common::{
+    errors::ApplicationError,
+    types::{BookDelta, BookUpdate, DeltaAction, Side},
+};
+use tokio::sync::watch;
+
+use crate::core::MatchingEngine;
+
+/// The number of deltas kept for clients catching up
+pub const DEFAULT_RETAINED_DELTAS: usize = 10_000;
+
+/// How long a long-polling request waits for new deltas by default
+pub const DEFAULT_LONG_POLL_SECS: u64 = 25;
+
+/// The longest a long-polling request may wait
+pub const MAX_LONG_POLL_SECS: u64 = 60;
+
+/// A price level touched by a book mutation, with its quantity before the mutation if it's known
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TouchedLevel {
+    pub side: Side,
+    pub price: u64,
+    /// `None` if the level existed but its previous quantity wasn't recorded
+    pub before: Option<u64>,
+}
+
+/// The resting quantity and number of price levels of one side of the book
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct SideDepth {
+    pub quantity: u64,
+    pub levels: u64,
+}
+
+/// Depth and order-flow gauges, updated from every delta so reading them doesn't walk the book
+#[derive(Debug, Default)]
+pub struct BookDepth {
+    quantities: BTreeMap<(Side, u64), u64>,
+    pub bids: SideDepth,
+    pub asks: SideDepth,
+    /// Quantity added to the bids or removed from the asks, minus quantity removed from the bids or added to the
+    /// asks, since the start. Rising values mean buying pressure.
+    pub order_flow_imbalance: i64,
+}
+
+impl BookDepth {
+    /// Applies the new quantity of a level
+    pub fn apply(&mut self, delta: &BookDelta) {
+        let key = (delta.side.clone(), delta.price);
+        let before = match delta.quantity {
+            0 => self.quantities.remove(&key),
+            quantity => self.quantities.insert(key, quantity),
+        }
+        .unwrap_or(0);
+        let side = match delta.side {
+            Side::Buy => &mut self.bids,
+            Side::Sell => &mut self.asks,
+        };
+        side.quantity = side.quantity - before + delta.quantity;
+        match (before, delta.quantity) {
+            (0, 0) => {}
+            (0, _) => side.levels += 1,
+            (_, 0) => side.levels -= 1,
+            _ => {}
+        }
+        let change = delta.quantity as i64 - before as i64;
+        self.order_flow_imbalance += match delta.side {
+            Side::Buy => change,
+            Side::Sell => -change,
+        };
+    }
+
+    /// `(bids - asks) / (bids + asks)` of the resting quantity, from -1 (only asks) to 1 (only bids). 0 when the book
+    /// is empty.
+    pub fn imbalance(&self) -> f64 {
+        let total = self.bids.quantity as f64 + self.asks.quantity as f64;
+        if total == 0.0 {
+            return 0.0;
+        }
+        (self.bids.quantity as f64 - self.asks.quantity as f64) / total
+    }
+}
+
+/// The sequence-numbered stream of price level changes. Recent deltas are retained so clients can catch up
+/// from a sequence number, and waiting clients are woken up on every change.
+#[derive(Debug)]
+pub struct BookUpdates {
+    seq: u64,
+    deltas: VecDeque<BookDelta>,
+    retained: usize,
+    latest: watch::Sender<u64>,
+    depth: BookDepth,
+}
+
+impl Default for BookUpdates {
+    fn default() -> Self {
+        BookUpdates::new(DEFAULT_RETAINED_DELTAS)
+    }
+}
+
+impl BookUpdates {
+    pub fn new(retained: usize) -> Self {
+        BookUpdates {
+            seq: 0,
+            deltas: VecDeque::new(),
+            retained: retained.max(1),
+            latest: watch::channel(0).0,
+            depth: BookDepth::default(),
+        }
+    }
+
+    /// The sequence number of the latest delta
+    pub fn seq(&self) -> u64 {
+        self.seq
+    }
+
+    /// The gauges of the book as of the latest delta
+    pub fn depth(&self) -> &BookDepth {
+        &self.depth
+    }
+
+    /// Publishes a delta for every touched level whose quantity changed
+    pub fn publish(&mut self, matching_engine: &MatchingEngine, touched: Vec<TouchedLevel>) {
+        let published = self.seq;
+        let mut seen: Vec<(&Side, u64)> = vec![];
+        for level in touched.iter() {
+            if seen.contains(&(&level.side, level.price)) {
+                continue;
+            }
+            seen.push((&level.side, level.price));
+            let quantity = matching_engine.level_quantity(&level.side, level.price);
+            let action = match (level.before, quantity) {
+                (Some(before), _) if before == quantity => continue,
+                (Some(0), _) => DeltaAction::Add,
+                (_, 0) => DeltaAction::Remove,
+                _ => DeltaAction::Amend,
+            };
+            self.seq += 1;
+            let delta = BookDelta {
+                seq: self.seq,
+                side: level.side.clone(),
+                price: level.price,
+                quantity,
+                action,
+            };
+            self.depth.apply(&delta);
+            self.deltas.push_back(delta);
+        }
+        while self.deltas.len() > self.retained {
+            self.deltas.pop_front();
+        }
+        if self.seq != published {
+            self.latest.send_replace(self.seq);
+        }
+    }
+
+    /// The deltas after `since_seq`
+    /// # Errors
+    /// Some of the deltas aren't retained anymore, the client has to start over from a snapshot
+    pub fn since(&self, since_seq: u64) -> Result<BookUpdate, ApplicationError> {
+        let oldest = self
+            .deltas
+            .front()
+            .map(|delta| delta.seq)
+            .unwrap_or(self.seq + 1);
+        if since_seq.saturating_add(1) < oldest && since_seq < self.seq {
+            return Err(ApplicationError::DeltasUnavailable(since_seq));
+        }
+        Ok(BookUpdate {
+            seq: self.seq,
+            deltas: self
+                .deltas
+                .iter()
+                .filter(|delta| delta.seq > since_seq)
+                .cloned()
+                .collect(),
+        })
+    }
+
+    /// Follows the sequence number of the latest delta
+    pub fn subscribe(&self) -> watch::Receiver<u64> {
+        self.latest.subscribe()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    // reduce the warnings for naming tests
+    #![allow(non_snake_case)]
+
+    use super::*;
+    use octopus_common::types::{Order, OrderType, TimeInForce, DEFAULT_MARKET};
+
+    fn sell(price: u64, amount: u64, signer: &str) -> Order {
+        Order {
+            price,
+            amount,
+            side: Side::Sell,
+            signer: signer.to_string(),
+            client_order_id: None,
+            signature: None,
+            order_type: OrderType::Limit,
+            time_in_force: TimeInForce::Gtc,
+            trigger_price: None,
+            market: DEFAULT_MARKET.to_string(),
+        }
+    }
+
+    fn touched(side: Side, price: u64, before: Option<u64>) -> TouchedLevel {
+        TouchedLevel {
+            side,
+            price,
+            before,
+        }
+    }
+
+    #[test]
+    fn test_BookUpdates_publish_and_since() {
+        let mut matching_engine = MatchingEngine::new();
+        let mut book_updates = BookUpdates::new(2);
+        let mut receiver = book_updates.subscribe();
+
+        matching_engine.process(sell(10, 2, "ALICE")).unwrap();
+        book_updates.publish(&matching_engine, vec![touched(Side::Sell, 10, Some(0))]);
+        matching_engine.process(sell(10, 1, "ALICE")).unwrap();
+        book_updates.publish(&matching_engine, vec![touched(Side::Sell, 10, Some(2))]);
+        matching_engine.cancel_all("ALICE");
+        book_updates.publish(
+            &matching_engine,
+            vec![touched(Side::Sell, 10, None), touched(Side::Sell, 10, None)],
+        );
+        // Nothing changed
+        book_updates.publish(&matching_engine, vec![touched(Side::Buy, 9, Some(0))]);
+
+        assert!(receiver.has_changed().unwrap());
+        assert_eq!(*receiver.borrow_and_update(), 3);
+        let update = book_updates.since(1).unwrap();
+        assert_eq!(update.seq, 3);
+        assert_eq!(
+            update
+                .deltas
+                .iter()
+                .map(|delta| (delta.seq, delta.quantity, delta.action))
+                .collect::<Vec<_>>(),
+            vec![(2, 3, DeltaAction::Amend), (3, 0, DeltaAction::Remove)]
+        );
+        assert_eq!(book_updates.since(3).unwrap().deltas, vec![]);
+        assert_eq!(
+            book_updates.since(0),
+            Err(ApplicationError::DeltasUnavailable(0))
+        );
+    }
+
+    #[test]
+    fn test_BookDepth_apply_tracks_sides_and_flow() {
+        let mut depth = BookDepth::default();
+        let delta = |side, price, quantity| BookDelta {
+            seq: 0,
+            side,
+            price,
+            quantity,
+            action: DeltaAction::Amend,
+        };
+        depth.apply(&delta(Side::Buy, 9, 4));
+        depth.apply(&delta(Side::Sell, 11, 2));
+        depth.apply(&delta(Side::Sell, 12, 2));
+        assert_eq!(
+            depth.asks,
+            SideDepth {
+                quantity: 4,
+                levels: 2
+            }
+        );
+        assert_eq!(depth.imbalance(), 0.0);
+        assert_eq!(depth.order_flow_imbalance, 0);
+
+        // A buy takes the level at 11
+        depth.apply(&delta(Side::Sell, 11, 0));
+        assert_eq!(
+            depth.asks,
+            SideDepth {
+                quantity: 2,
+                levels: 1
+            }
+        );
+        assert_eq!(depth.order_flow_imbalance, 2);
+        assert_eq!(depth.imbalance(), 2.0 / 6.0);
+    }
+}
@@ -0,0 +1,104 @@
+//! Fault injection for validating retries, idempotency, and recovery. Only compiled with the `chaos` feature, and
+//! inactive until an admin configures it through `PUT /admin/chaos`.
+use std::{convert::Infallible, sync::RwLock, time::Duration};
+
+use octopus_common::errors::ApplicationError;
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+use warp::Filter;
+
+/// The faults to inject. The defaults inject nothing.
+#[derive(Debug, Default, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ChaosSettings {
+    /// Delay added to every request (ms)
+    pub latency_ms: u64,
+    /// Up to this much random delay on top of `latency_ms` (ms)
+    pub jitter_ms: u64,
+    /// The probability from 0 to 1 that a transaction is applied without being written to the balance history
+    pub drop_write_probability: f64,
+    /// How long the order worker keeps the platform locked after each order (ms)
+    pub lock_hold_ms: u64,
+}
+
+static SETTINGS: RwLock<ChaosSettings> = RwLock::new(ChaosSettings {
+    latency_ms: 0,
+    jitter_ms: 0,
+    drop_write_probability: 0.0,
+    lock_hold_ms: 0,
+});
+
+/// The faults currently injected
+pub fn settings() -> ChaosSettings {
+    SETTINGS.read().unwrap().clone()
+}
+
+/// Replaces the injected faults
+/// # Errors
+/// The drop probability isn't between 0 and 1
+pub fn configure(settings: ChaosSettings) -> Result<ChaosSettings, ApplicationError> {
+    if !(0.0..=1.0).contains(&settings.drop_write_probability) {
+        return Err(ApplicationError::InvalidChaosSettings(format!(
+            "drop_write_probability {} is not between 0 and 1",
+            settings.drop_write_probability
+        )));
+    }
+    log::warn!("Chaos settings changed to {:?}", settings);
+    *SETTINGS.write().unwrap() = settings.clone();
+    Ok(settings)
+}
+
+/// Whether the next write should be lost
+pub fn drop_write() -> bool {
+    let probability = SETTINGS.read().unwrap().drop_write_probability;
+    probability > 0.0 && rand::thread_rng().gen_bool(probability)
+}
+
+/// Blocks the calling thread for `lock_hold_ms`. Called while holding a lock, it makes other requests wait for it.
+pub fn hold_lock() {
+    let lock_hold_ms = SETTINGS.read().unwrap().lock_hold_ms;
+    if lock_hold_ms > 0 {
+        std::thread::sleep(Duration::from_millis(lock_hold_ms));
+    }
+}
+
+fn latency() -> Duration {
+    let settings = SETTINGS.read().unwrap();
+    let jitter = match settings.jitter_ms {
+        0 => 0,
+        jitter_ms => rand::thread_rng().gen_range(0..=jitter_ms),
+    };
+    Duration::from_millis(settings.latency_ms + jitter)
+}
+
+/// A filter delaying every request by the configured latency
+pub fn delay() -> impl Filter<Extract = (), Error = Infallible> + Clone {
+    warp::any()
+        .then(|| async {
+            let latency = latency();
+            if !latency.is_zero() {
+                tokio::time::sleep(latency).await;
+            }
+        })
+        .untuple_one()
+}
+
+#[cfg(test)]
+mod tests {
+    // reduce the warnings for naming tests
+    #![allow(non_snake_case)]
+
+    use super::*;
+
+    #[test]
+    fn test_configure_validates_probability() {
+        assert!(configure(ChaosSettings {
+            drop_write_probability: 1.5,
+            ..ChaosSettings::default()
+        })
+        .is_err());
+        assert_eq!(settings(), ChaosSettings::default());
+        assert!(!drop_write());
+        assert!(latency().is_zero());
+    }
+}
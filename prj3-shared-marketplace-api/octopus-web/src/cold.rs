@@ -0,0 +1,94 @@
+//! Cold accounts, the custody practice of keeping funds behind a delay: every withdrawal of a cold account waits for
+//! [`ColdAccounts::delay_millis`] and the account's own confirmation before the funds leave.
+//!
+//! Marking an account cold takes effect at once, making it hot again only after the delay, so a leaked withdrawal
+//! key can't lift the delay for a withdrawal right away.
+use std::collections::HashMap;
+
+/// How long cold withdrawals wait unless configured otherwise
+pub const DEFAULT_COLD_WITHDRAWAL_DELAY_SECS: u64 = 24 * 60 * 60;
+
+/// The accounts marked cold
+#[derive(Debug)]
+pub struct ColdAccounts {
+    /// How long withdrawals of cold accounts wait before they can be confirmed
+    pub delay_millis: u64,
+    /// Cold accounts, with the unix timestamp (ms) they turn hot again if that was asked for
+    accounts: HashMap<String, Option<u64>>,
+}
+
+impl Default for ColdAccounts {
+    fn default() -> Self {
+        ColdAccounts {
+            delay_millis: DEFAULT_COLD_WITHDRAWAL_DELAY_SECS * 1000,
+            accounts: HashMap::new(),
+        }
+    }
+}
+
+impl ColdAccounts {
+    pub fn new() -> Self {
+        ColdAccounts::default()
+    }
+
+    /// Whether `signer` is cold at `now`
+    pub fn is_cold(&self, signer: &str, now: u64) -> bool {
+        match self.accounts.get(signer) {
+            Some(Some(hot_at)) => *hot_at > now,
+            Some(None) => true,
+            None => false,
+        }
+    }
+
+    /// Marks `signer` cold right away, or hot once the delay passed. Returns when the account turns hot, `None` for
+    /// cold accounts.
+    pub fn set(&mut self, signer: &str, cold: bool, now: u64) -> Option<u64> {
+        if cold {
+            self.accounts.insert(signer.to_string(), None);
+            return None;
+        }
+        if !self.is_cold(signer, now) {
+            self.accounts.remove(signer);
+            return Some(now);
+        }
+        let delay = self.delay_millis;
+        self.accounts
+            .get_mut(signer)
+            .map(|hot_at| *hot_at.get_or_insert(now + delay))
+    }
+
+    /// Forgets `signer`
+    pub fn remove(&mut self, signer: &str) {
+        self.accounts.remove(signer);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    // reduce the warnings for naming tests
+    #![allow(non_snake_case)]
+
+    use super::*;
+
+    #[test]
+    fn test_ColdAccounts_set_turns_hot_only_after_the_delay() {
+        let mut cold = ColdAccounts::new();
+        cold.delay_millis = 1_000;
+        assert!(!cold.is_cold("ALICE", 0));
+        assert_eq!(cold.set("ALICE", true, 0), None);
+        assert!(cold.is_cold("ALICE", 0));
+
+        assert_eq!(cold.set("ALICE", false, 500), Some(1_500));
+        // Asking again doesn't push the delay out
+        assert_eq!(cold.set("ALICE", false, 900), Some(1_500));
+        assert!(cold.is_cold("ALICE", 1_499));
+        assert!(!cold.is_cold("ALICE", 1_500));
+        assert_eq!(cold.set("ALICE", false, 2_000), Some(2_000));
+
+        // Marking it cold again cancels the pending change
+        cold.set("ALICE", true, 2_000);
+        cold.set("ALICE", false, 2_100);
+        cold.set("ALICE", true, 2_200);
+        assert!(cold.is_cold("ALICE", 10_000));
+    }
+}
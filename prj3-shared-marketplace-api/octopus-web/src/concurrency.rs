@@ -0,0 +1,156 @@
+//! Concurrency caps per route group. A request of a group that's at its cap is shed right away with
+//! `503 Service Unavailable` instead of waiting for a turn, so expensive exports and queries can't pile up and starve
+//! order submission of threads and the platform lock.
+use octopus_common::errors::{ApplicationError, OctopusError};
+use serde::{Deserialize, Serialize};
+use std::sync::{
+    atomic::{AtomicU64, Ordering},
+    Arc,
+};
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+use warp::Filter;
+
+/// The most requests of each route group worked on at once, unlimited if not set
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ConcurrencyConfig {
+    /// Order, quote, and bracket submissions
+    pub orders: Option<usize>,
+    /// Parquet exports of the ledger, the tape, and accounts
+    pub exports: Option<usize>,
+    /// Transaction searches, order lifecycles, and market analytics
+    pub queries: Option<usize>,
+}
+
+impl Default for ConcurrencyConfig {
+    fn default() -> Self {
+        ConcurrencyConfig {
+            orders: None,
+            exports: Some(2),
+            queries: Some(8),
+        }
+    }
+}
+
+/// The requests of a route group in flight, and how many were shed
+#[derive(Debug)]
+pub struct RouteGroup {
+    pub name: &'static str,
+    pub limit: Option<usize>,
+    permits: Option<Arc<Semaphore>>,
+    shed: AtomicU64,
+}
+
+/// A place in a route group, given back when dropped
+#[derive(Debug)]
+pub struct Permit {
+    _held: Option<OwnedSemaphorePermit>,
+}
+
+impl RouteGroup {
+    pub fn new(name: &'static str, limit: Option<usize>) -> Self {
+        RouteGroup {
+            name,
+            limit,
+            permits: limit.map(|limit| Arc::new(Semaphore::new(limit))),
+            shed: AtomicU64::new(0),
+        }
+    }
+
+    /// Takes a place in the group
+    /// # Errors
+    /// The group is at its limit, the request is counted as shed
+    pub fn enter(&self) -> Result<Permit, ApplicationError> {
+        match &self.permits {
+            None => Ok(Permit { _held: None }),
+            Some(permits) => permits
+                .clone()
+                .try_acquire_owned()
+                .map(|permit| Permit {
+                    _held: Some(permit),
+                })
+                .map_err(|_| {
+                    self.shed.fetch_add(1, Ordering::Relaxed);
+                    ApplicationError::ConcurrencyLimited(self.name.to_string())
+                }),
+        }
+    }
+
+    /// Requests of the group being worked on now, 0 for groups without a limit
+    pub fn in_flight(&self) -> usize {
+        match (&self.permits, self.limit) {
+            (Some(permits), Some(limit)) => limit - permits.available_permits(),
+            _ => 0,
+        }
+    }
+
+    /// Requests turned away since startup
+    pub fn shed(&self) -> u64 {
+        self.shed.load(Ordering::Relaxed)
+    }
+}
+
+/// Every route group with a concurrency cap
+#[derive(Debug)]
+pub struct RouteGroups {
+    pub orders: Arc<RouteGroup>,
+    pub exports: Arc<RouteGroup>,
+    pub queries: Arc<RouteGroup>,
+}
+
+impl RouteGroups {
+    pub fn new(config: &ConcurrencyConfig) -> Self {
+        RouteGroups {
+            orders: Arc::new(RouteGroup::new("orders", config.orders)),
+            exports: Arc::new(RouteGroup::new("exports", config.exports)),
+            queries: Arc::new(RouteGroup::new("queries", config.queries)),
+        }
+    }
+
+    pub fn all(&self) -> [&RouteGroup; 3] {
+        [&self.orders, &self.exports, &self.queries]
+    }
+}
+
+/// A filter that takes a place in `group` for the rest of the request, or rejects it if the group is full
+pub fn with_permit(
+    group: &Arc<RouteGroup>,
+) -> impl Filter<Extract = (Permit,), Error = warp::Rejection> + Clone {
+    let group = group.clone();
+    warp::any().and_then(move || {
+        let group = group.clone();
+        async move {
+            group
+                .enter()
+                .map_err(|e| warp::reject::custom(OctopusError(e)))
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    // reduce the warnings for naming tests
+    #![allow(non_snake_case)]
+
+    use super::*;
+
+    #[test]
+    fn test_RouteGroup_enter_sheds_requests_over_the_limit() {
+        let group = RouteGroup::new("exports", Some(1));
+        let first = group.enter().unwrap();
+        assert_eq!(group.in_flight(), 1);
+        assert!(matches!(
+            group.enter(),
+            Err(ApplicationError::ConcurrencyLimited(name)) if name == "exports"
+        ));
+        assert_eq!(group.shed(), 1);
+
+        drop(first);
+        assert_eq!(group.in_flight(), 0);
+        assert!(group.enter().is_ok());
+
+        let unlimited = RouteGroup::new("orders", None);
+        let permits: Vec<_> = (0..100).map(|_| unlimited.enter().unwrap()).collect();
+        assert_eq!((permits.len(), unlimited.shed()), (100, 0));
+    }
+}
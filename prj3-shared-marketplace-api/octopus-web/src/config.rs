@@ -0,0 +1,631 @@
+//! The server configuration, layered from the defaults, a TOML file, `OCTOPUS_*` environment variables, and command
+//! line flags, each overriding the one before.
+//!
+//! Nested keys are set from the environment with `__`, e.g. `OCTOPUS_SERVER__PORT=8080`. The flat variables the server
+//! read before the configuration file existed, like `OCTOPUS_TAKER_FEE_BPS`, keep working.
+//!
+//! The fees and risk limits are reloaded from the same sources on SIGHUP or `POST /admin/config/reload`, everything
+//! else needs a restart.
+use std::{
+    net::{IpAddr, Ipv4Addr},
+    path::{Path, PathBuf},
+    sync::{Arc, RwLock},
+};
+
+use clap::Parser;
+use figment::{
+    providers::{Env, Format, Serialized, Toml},
+    Figment,
+};
+use octopus_common::{errors::ApplicationError, types::SelfMatchPolicy};
+use serde::{Deserialize, Deserializer, Serialize};
+
+use crate::{
+    cold::DEFAULT_COLD_WITHDRAWAL_DELAY_SECS,
+    concurrency::ConcurrencyConfig,
+    core::{DeterministicIds, IdGenerator, MonotonicIds, SnowflakeIds, NODE_BITS},
+    deadline::DEFAULT_MAX_REQUEST_TIMEOUT_SECS,
+    duplicates::DEFAULT_DUPLICATE_WINDOW_SECS,
+    ingest::DEFAULT_ORDER_QUEUE_CAPACITY,
+    markets::MarketDefinition,
+    reference::DEFAULT_REFERENCE_WINDOW_SECS,
+    settlement::SettlementPolicy,
+    tenants::{TenantSettings, Tenants},
+    whitelist::DEFAULT_WHITELIST_DELAY_SECS,
+};
+
+/// The configuration file read from the working directory if no `--config` is given
+pub const DEFAULT_CONFIG_FILE: &str = "octopus.toml";
+
+/// The prefix of the environment variables overriding the configuration file
+pub const ENV_PREFIX: &str = "OCTOPUS_";
+
+/// Flat environment variables (without [`ENV_PREFIX`]) and the keys they set
+const ENV_ALIASES: &[(&str, &str)] = &[
+    ("tenants", "tenants"),
+    ("taker_fee_bps", "fees.taker_fee_bps"),
+    ("maker_fee_bps", "fees.maker_fee_bps"),
+    ("order_queue_capacity", "limits.order_queue_capacity"),
+    (
+        "withdrawal_approval_threshold",
+        "limits.withdrawal_approval_threshold",
+    ),
+    ("price_collar_bps", "limits.price_collar_bps"),
+];
+
+/// Secrets are taken verbatim from these variables, they would be parsed as numbers otherwise
+const SECRET_ENV: &[(&str, &str)] = &[
+    ("OCTOPUS_ADMIN_KEY", "auth.admin_key"),
+    ("OCTOPUS_GATEWAY_SECRET", "auth.gateway_secret"),
+];
+
+/// Replaces configured secrets in [`Config::redacted`]
+const REDACTED: &str = "<redacted>";
+
+/// The most play funds a faucet claim credits unless configured otherwise
+pub const DEFAULT_FAUCET_MAX_AMOUNT: u64 = 10_000;
+
+/// The wait between two faucet claims of an account unless configured otherwise
+pub const DEFAULT_FAUCET_COOLDOWN_SECS: u64 = 60 * 60;
+
+/// Command line flags, overriding the configuration file and the environment
+#[derive(Parser, Debug, Clone, Default)]
+#[command(about = "The octopus marketplace server")]
+pub struct Args {
+    /// The TOML configuration file, `octopus.toml` is read if it exists
+    #[arg(long)]
+    pub config: Option<PathBuf>,
+
+    /// The port to listen on
+    #[arg(long)]
+    pub port: Option<u16>,
+
+    /// A JSON or YAML file with accounts, markets, and resting orders loaded into the default tenant at startup
+    #[arg(long)]
+    pub seed: Option<PathBuf>,
+
+    /// Start simulated traders placing orders around the last price
+    #[arg(long)]
+    pub demo: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ServerConfig {
+    pub address: IpAddr,
+    pub port: u16,
+    /// Listen on `address` and `port`, may be turned off to serve on the unix socket only
+    pub tcp: bool,
+    /// Serve on a Unix domain socket at this path as well
+    pub unix_socket: Option<PathBuf>,
+    /// The longest a request is worked on, clients may ask for less, see [`crate::deadline`]
+    pub max_request_timeout_secs: u64,
+    /// The most requests of each route group worked on at once, see [`crate::concurrency`]
+    pub concurrency: ConcurrencyConfig,
+}
+
+impl Default for ServerConfig {
+    fn default() -> Self {
+        ServerConfig {
+            address: IpAddr::V4(Ipv4Addr::UNSPECIFIED),
+            port: 3000,
+            tcp: true,
+            unix_socket: None,
+            max_request_timeout_secs: DEFAULT_MAX_REQUEST_TIMEOUT_SECS,
+            concurrency: ConcurrencyConfig::default(),
+        }
+    }
+}
+
+/// Secrets that are generated at startup if missing
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct AuthConfig {
+    pub admin_key: Option<String>,
+    /// Shared with the payment gateway to sign its notifications
+    pub gateway_secret: Option<String>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct FeeConfig {
+    pub taker_fee_bps: u64,
+    pub maker_fee_bps: u64,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct LimitConfig {
+    /// Orders waiting for or in processing before new submissions are rejected
+    pub order_queue_capacity: usize,
+    /// Withdrawals above this amount wait for an admin's approval
+    pub withdrawal_approval_threshold: Option<u64>,
+    /// Withdrawals and sends above this amount need the PIN of accounts that set one
+    pub pin_threshold: u64,
+    /// Withdrawals of cold accounts can be confirmed this many seconds after they were requested
+    pub cold_withdrawal_delay_secs: u64,
+    /// Withdrawals and sends above this amount may only go to whitelisted destinations of accounts that keep a
+    /// whitelist
+    pub whitelist_threshold: u64,
+    /// New whitelist entries become active this many seconds after they were added
+    pub whitelist_delay_secs: u64,
+    /// Balances above 0 and below this are dust, swept for the accounts that consent. 0 turns sweeping off.
+    pub dust_threshold: u64,
+    /// Orders further than this many basis points from the reference price are rejected
+    pub price_collar_bps: Option<u64>,
+    /// Trades within this many seconds make up the reference price
+    pub reference_window_secs: u64,
+    /// Market orders filling further than this many basis points from the best price are rejected
+    pub max_slippage_bps: Option<u64>,
+    /// Orders executing further than this many basis points from the last traded price trip the circuit breaker
+    pub circuit_breaker_bps: Option<u64>,
+    /// How long a tripped market halts, 0 only rejects the order
+    pub circuit_breaker_halt_secs: u64,
+    /// Orders resubmitted with the same client order id within this many seconds are rejected
+    pub duplicate_order_window_secs: u64,
+    /// Orders an account may submit per second
+    pub max_orders_per_sec: Option<u64>,
+    /// Cancellations as a percentage of its orders an account may have within a minute
+    pub max_cancel_ratio_percent: Option<u64>,
+}
+
+impl Default for LimitConfig {
+    fn default() -> Self {
+        LimitConfig {
+            order_queue_capacity: DEFAULT_ORDER_QUEUE_CAPACITY,
+            withdrawal_approval_threshold: None,
+            pin_threshold: 0,
+            cold_withdrawal_delay_secs: DEFAULT_COLD_WITHDRAWAL_DELAY_SECS,
+            whitelist_threshold: 0,
+            whitelist_delay_secs: DEFAULT_WHITELIST_DELAY_SECS,
+            dust_threshold: 0,
+            price_collar_bps: None,
+            reference_window_secs: DEFAULT_REFERENCE_WINDOW_SECS,
+            max_slippage_bps: None,
+            circuit_breaker_bps: None,
+            circuit_breaker_halt_secs: 0,
+            duplicate_order_window_secs: DEFAULT_DUPLICATE_WINDOW_SECS,
+            max_orders_per_sec: None,
+            max_cancel_ratio_percent: None,
+        }
+    }
+}
+
+/// Self-service play funds, only served with the `faucet` feature
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct FaucetConfig {
+    /// The most play funds a single claim credits
+    pub max_amount: u64,
+    /// How long an account waits between two claims
+    pub cooldown_secs: u64,
+}
+
+impl Default for FaucetConfig {
+    fn default() -> Self {
+        FaucetConfig {
+            max_amount: DEFAULT_FAUCET_MAX_AMOUNT,
+            cooldown_secs: DEFAULT_FAUCET_COOLDOWN_SECS,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct StorageConfig {
+    /// Loaded into the default tenant at startup, see [`crate::seed::SeedData`]
+    pub seed: Option<PathBuf>,
+    /// Durable state like the identifier counters and the books parked on shutdown, kept in memory only if missing
+    pub data_dir: Option<PathBuf>,
+}
+
+/// How a platform issues ordinals, trade ids, hold ids, and withdrawal ids, see [`IdGenerator`]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "strategy", rename_all = "lowercase")]
+pub enum IdStrategy {
+    /// Counting up by one, see [`MonotonicIds`]
+    #[default]
+    Monotonic,
+    /// Prefixed with the node, for servers sharing a marketplace, see [`SnowflakeIds`]
+    Snowflake { node: u16 },
+    /// Sparse and the same in every run with the seed, for simulations, see [`DeterministicIds`]
+    Deterministic { seed: u64 },
+}
+
+impl IdStrategy {
+    /// The generator of the strategy
+    /// # Errors
+    /// The node doesn't fit in a Snowflake id
+    pub fn generator(&self) -> Result<Arc<dyn IdGenerator>, ApplicationError> {
+        Ok(match *self {
+            IdStrategy::Monotonic => Arc::new(MonotonicIds),
+            IdStrategy::Snowflake { node } => {
+                Arc::new(SnowflakeIds::new(node).ok_or_else(|| {
+                    ApplicationError::InvalidConfig(format!(
+                        "ids.node must be below {}, got {}",
+                        1 << NODE_BITS,
+                        node
+                    ))
+                })?)
+            }
+            IdStrategy::Deterministic { seed } => Arc::new(DeterministicIds { seed }),
+        })
+    }
+}
+
+/// The effective configuration of the server
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Config {
+    pub server: ServerConfig,
+    pub auth: AuthConfig,
+    /// Tenants hosted besides the default one, a list or a comma-separated string
+    #[serde(deserialize_with = "names")]
+    pub tenants: Vec<String>,
+    pub fees: FeeConfig,
+    pub limits: LimitConfig,
+    /// Markets added to or replacing the default ones of every tenant
+    pub markets: Vec<MarketDefinition>,
+    pub storage: StorageConfig,
+    pub ids: IdStrategy,
+    /// How the books of every tenant resolve self-matches
+    pub self_match_policy: SelfMatchPolicy,
+    /// How the fees of every tenant are rounded
+    pub settlement: SettlementPolicy,
+    pub faucet: FaucetConfig,
+    pub demo: bool,
+}
+
+/// Accepts `["a", "b"]` as well as `"a, b"`
+fn names<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<String>, D::Error> {
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum Names {
+        List(Vec<String>),
+        Text(String),
+    }
+    Ok(match Names::deserialize(deserializer)? {
+        Names::List(names) => names,
+        Names::Text(names) => names
+            .split(',')
+            .map(str::trim)
+            .filter(|name| !name.is_empty())
+            .map(str::to_string)
+            .collect(),
+    })
+}
+
+impl Config {
+    /// Layers the configuration file, the environment, and `args` over the defaults and validates the result
+    /// # Errors
+    /// An explicitly given file is missing, a value has the wrong type, or [`Config::validate`] fails
+    pub fn load(args: &Args) -> Result<Self, ApplicationError> {
+        let mut figment = Figment::from(Serialized::defaults(Config::default()));
+        match &args.config {
+            Some(path) => figment = figment.merge(Toml::file_exact(path)),
+            None if Path::new(DEFAULT_CONFIG_FILE).is_file() => {
+                figment = figment.merge(Toml::file_exact(DEFAULT_CONFIG_FILE))
+            }
+            None => {}
+        }
+        let secrets: Vec<&str> = SECRET_ENV
+            .iter()
+            .map(|(name, _)| &name[ENV_PREFIX.len()..])
+            .collect();
+        figment = figment.merge(Env::prefixed(ENV_PREFIX).ignore(&secrets).map(|key| {
+            ENV_ALIASES
+                .iter()
+                .find(|(alias, _)| key == *alias)
+                .map(|(_, path)| path.to_string())
+                .unwrap_or_else(|| key.as_str().replace("__", "."))
+                .into()
+        }));
+        for (name, path) in SECRET_ENV {
+            if let Some(secret) = Env::var(name) {
+                figment = figment.merge(Serialized::default(path, secret));
+            }
+        }
+
+        if let Some(port) = args.port {
+            figment = figment.merge(Serialized::default("server.port", port));
+        }
+        if let Some(seed) = &args.seed {
+            figment = figment.merge(Serialized::default("storage.seed", seed));
+        }
+        if args.demo {
+            figment = figment.merge(Serialized::default("demo", true));
+        }
+
+        let config: Config = figment
+            .extract()
+            .map_err(|e| ApplicationError::InvalidConfig(e.to_string()))?;
+        config.validate()?;
+        Ok(config)
+    }
+
+    /// Checks the values that would only fail once the server runs
+    pub fn validate(&self) -> Result<(), ApplicationError> {
+        let invalid = |reason: String| Err(ApplicationError::InvalidConfig(reason));
+        if !self.server.tcp && self.server.unix_socket.is_none() {
+            return invalid("server.tcp is off and there's no server.unix_socket".to_string());
+        }
+        if !cfg!(unix) && self.server.unix_socket.is_some() {
+            return invalid("server.unix_socket is only supported on Unix".to_string());
+        }
+        let concurrency = &self.server.concurrency;
+        for (group, limit) in [
+            ("orders", concurrency.orders),
+            ("exports", concurrency.exports),
+            ("queries", concurrency.queries),
+        ] {
+            if limit == Some(0) {
+                return invalid(format!("server.concurrency.{} must be positive", group));
+            }
+        }
+        if self.limits.order_queue_capacity == 0 {
+            return invalid("limits.order_queue_capacity must be positive".to_string());
+        }
+        for (key, bps) in [
+            ("taker_fee_bps", self.fees.taker_fee_bps),
+            ("maker_fee_bps", self.fees.maker_fee_bps),
+        ] {
+            if bps > 10_000 {
+                return invalid(format!("fees.{} must be at most 10000, got {}", key, bps));
+            }
+        }
+        if let Some((market, _)) = self.settlement.units.iter().find(|(_, unit)| **unit == 0) {
+            return invalid(format!(
+                "settlement.units of market {} must be positive",
+                market
+            ));
+        }
+        self.ids.generator()?;
+        for (i, market) in self.markets.iter().enumerate() {
+            if market.symbol.is_empty() {
+                return invalid(format!("markets[{}] has no symbol", i));
+            }
+            market.config.validate(&market.symbol)?;
+            if self.markets[..i]
+                .iter()
+                .any(|other| other.symbol == market.symbol)
+            {
+                return invalid(format!("market {} is configured twice", market.symbol));
+            }
+        }
+        Ok(())
+    }
+
+    /// The configuration with its secrets masked, for inspection
+    pub fn redacted(&self) -> Self {
+        let mask = |secret: &Option<String>| secret.as_ref().map(|_| REDACTED.to_string());
+        let mut config = self.clone();
+        config.auth.admin_key = mask(&self.auth.admin_key);
+        config.auth.gateway_secret = mask(&self.auth.gateway_secret);
+        config
+    }
+
+    /// The settings every tenant's platform is created with
+    pub fn tenant_settings(&self) -> TenantSettings {
+        TenantSettings {
+            order_queue_capacity: self.limits.order_queue_capacity,
+            withdrawal_approval_threshold: self.limits.withdrawal_approval_threshold,
+            pin_threshold: self.limits.pin_threshold,
+            cold_withdrawal_delay_secs: self.limits.cold_withdrawal_delay_secs,
+            whitelist_threshold: self.limits.whitelist_threshold,
+            whitelist_delay_secs: self.limits.whitelist_delay_secs,
+            dust_threshold: self.limits.dust_threshold,
+            taker_fee_bps: self.fees.taker_fee_bps,
+            maker_fee_bps: self.fees.maker_fee_bps,
+            price_collar_bps: self.limits.price_collar_bps,
+            reference_window_secs: self.limits.reference_window_secs,
+            max_slippage_bps: self.limits.max_slippage_bps,
+            circuit_breaker_bps: self.limits.circuit_breaker_bps,
+            circuit_breaker_halt_secs: self.limits.circuit_breaker_halt_secs,
+            duplicate_window_secs: self.limits.duplicate_order_window_secs,
+            max_orders_per_sec: self.limits.max_orders_per_sec,
+            max_cancel_ratio_percent: self.limits.max_cancel_ratio_percent,
+            markets: self.markets.clone(),
+            data_dir: self.storage.data_dir.clone(),
+            ids: self.ids,
+            self_match_policy: self.self_match_policy,
+            settlement: self.settlement.clone(),
+        }
+    }
+
+    /// Takes the fees and risk limits from `reloaded` and keeps everything else
+    pub fn reload_from(&mut self, reloaded: Config) {
+        self.fees = reloaded.fees;
+        self.limits.withdrawal_approval_threshold = reloaded.limits.withdrawal_approval_threshold;
+        self.limits.pin_threshold = reloaded.limits.pin_threshold;
+        self.limits.cold_withdrawal_delay_secs = reloaded.limits.cold_withdrawal_delay_secs;
+        self.limits.whitelist_threshold = reloaded.limits.whitelist_threshold;
+        self.limits.whitelist_delay_secs = reloaded.limits.whitelist_delay_secs;
+        self.limits.dust_threshold = reloaded.limits.dust_threshold;
+        self.limits.price_collar_bps = reloaded.limits.price_collar_bps;
+        self.limits.max_slippage_bps = reloaded.limits.max_slippage_bps;
+        self.limits.circuit_breaker_bps = reloaded.limits.circuit_breaker_bps;
+        self.limits.circuit_breaker_halt_secs = reloaded.limits.circuit_breaker_halt_secs;
+        self.limits.max_orders_per_sec = reloaded.limits.max_orders_per_sec;
+        self.limits.max_cancel_ratio_percent = reloaded.limits.max_cancel_ratio_percent;
+    }
+}
+
+/// Loads the configuration again and applies its fees and risk limits to every tenant. Open orders aren't touched.
+/// # Errors
+/// The reloaded configuration is invalid, the previous settings stay in effect
+pub fn reload(
+    args: &Args,
+    config: &RwLock<Config>,
+    tenants: &Tenants,
+) -> Result<Config, ApplicationError> {
+    let reloaded = Config::load(args)?;
+    let mut config = config.write().unwrap();
+    config.reload_from(reloaded);
+    tenants.update_limits(&config.tenant_settings());
+    Ok(config.clone())
+}
+
+/// Reloads the configuration on every SIGHUP
+#[cfg(unix)]
+pub fn reload_on_hangup(
+    args: Arc<Args>,
+    config: Arc<RwLock<Config>>,
+    tenants: Arc<Tenants>,
+    audit_log: Arc<crate::audit::AuditLog>,
+) {
+    use crate::{
+        audit::{AuditAction, SYSTEM_ACTOR},
+        tenants::DEFAULT_TENANT,
+    };
+    use tokio::signal::unix::{signal, SignalKind};
+
+    let mut hangups = match signal(SignalKind::hangup()) {
+        Ok(hangups) => hangups,
+        Err(e) => {
+            log::error!(
+                "Can't listen for SIGHUP, reload with the admin endpoint: {}",
+                e
+            );
+            return;
+        }
+    };
+    tokio::spawn(async move {
+        while hangups.recv().await.is_some() {
+            let before = config.read().unwrap().redacted();
+            match reload(&args, &config, &tenants) {
+                Ok(config) => {
+                    log::info!(
+                        "Reloaded the fees {:?} and limits {:?}",
+                        config.fees,
+                        config.limits
+                    );
+                    audit_log.record(
+                        SYSTEM_ACTOR,
+                        DEFAULT_TENANT,
+                        Some("SIGHUP".to_string()),
+                        AuditAction::ConfigReloaded,
+                        before,
+                        config.redacted(),
+                    );
+                }
+                Err(e) => log::error!("Reloading the configuration failed: {:?}", e),
+            }
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    // reduce the warnings for naming tests
+    #![allow(non_snake_case)]
+    // Jail closures have to return figment's error
+    #![allow(clippy::result_large_err)]
+
+    use super::*;
+    use figment::Jail;
+
+    #[test]
+    fn test_Config_load_layers_file_env_and_args() {
+        Jail::expect_with(|jail| {
+            jail.create_file(
+                DEFAULT_CONFIG_FILE,
+                r#"
+                tenants = ["acme"]
+
+                [server]
+                port = 8080
+
+                [fees]
+                taker_fee_bps = 10
+
+                [ids]
+                strategy = "snowflake"
+                node = 3
+
+                [[markets]]
+                symbol = "ETH-USD"
+                tick_size = 5
+                "#,
+            )?;
+            jail.set_env("OCTOPUS_TAKER_FEE_BPS", 25);
+            jail.set_env("OCTOPUS_LIMITS__PRICE_COLLAR_BPS", 500);
+            jail.set_env("OCTOPUS_ADMIN_KEY", "0123");
+            let config = Config::load(&Args {
+                port: Some(9090),
+                demo: true,
+                ..Args::default()
+            })
+            .unwrap();
+            assert_eq!(config.server.port, 9090);
+            assert_eq!(config.tenants, vec!["acme"]);
+            assert_eq!(config.fees.taker_fee_bps, 25);
+            assert_eq!(config.limits.price_collar_bps, Some(500));
+            assert_eq!(config.markets[0].config.tick_size, 5);
+            assert_eq!(config.ids, IdStrategy::Snowflake { node: 3 });
+            assert_eq!(config.auth.admin_key.as_deref(), Some("0123"));
+            assert!(config.demo);
+
+            jail.set_env("OCTOPUS_TENANTS", "acme, globex");
+            let config = Config::load(&Args::default()).unwrap();
+            assert_eq!(config.tenants, vec!["acme", "globex"]);
+            assert_eq!(config.server.port, 8080);
+            assert_eq!(config.redacted().auth.admin_key.as_deref(), Some(REDACTED));
+            Ok(())
+        });
+    }
+
+    #[test]
+    fn test_reload_only_changes_fees_and_limits() {
+        Jail::expect_with(|jail| {
+            jail.set_env("OCTOPUS_TAKER_FEE_BPS", 10);
+            let args = Args::default();
+            let config = RwLock::new(Config::load(&args).unwrap());
+            let tenants = Tenants::new(&[], config.read().unwrap().tenant_settings()).unwrap();
+
+            jail.set_env("OCTOPUS_TAKER_FEE_BPS", 20);
+            jail.set_env("OCTOPUS_SERVER__PORT", 8080);
+            let reloaded = reload(&args, &config, &tenants).unwrap();
+            assert_eq!(reloaded.fees.taker_fee_bps, 20);
+            assert_eq!(reloaded.server.port, 3000);
+            let platform = tenants.get(crate::tenants::DEFAULT_TENANT).unwrap();
+            assert_eq!(platform.lock().unwrap().taker_fee_bps, 20);
+
+            jail.set_env("OCTOPUS_TAKER_FEE_BPS", 10_001);
+            assert!(reload(&args, &config, &tenants).is_err());
+            assert_eq!(config.read().unwrap().fees.taker_fee_bps, 20);
+            Ok(())
+        });
+    }
+
+    #[test]
+    fn test_Config_load_rejects_invalid_values() {
+        Jail::expect_with(|jail| {
+            jail.set_env("OCTOPUS_TAKER_FEE_BPS", 10_001);
+            assert!(matches!(
+                Config::load(&Args::default()),
+                Err(ApplicationError::InvalidConfig(_))
+            ));
+            jail.set_env("OCTOPUS_TAKER_FEE_BPS", "cheap");
+            assert!(matches!(
+                Config::load(&Args::default()),
+                Err(ApplicationError::InvalidConfig(_))
+            ));
+            jail.set_env("OCTOPUS_TAKER_FEE_BPS", 0);
+            jail.set_env("OCTOPUS_IDS__STRATEGY", "snowflake");
+            jail.set_env("OCTOPUS_IDS__NODE", 1024);
+            assert!(matches!(
+                Config::load(&Args::default()),
+                Err(ApplicationError::InvalidConfig(_))
+            ));
+            jail.set_env("OCTOPUS_IDS__STRATEGY", "monotonic");
+            assert!(matches!(
+                Config::load(&Args {
+                    config: Some(PathBuf::from("missing.toml")),
+                    ..Args::default()
+                }),
+                Err(ApplicationError::InvalidConfig(_))
+            ));
+            Ok(())
+        });
+    }
+}
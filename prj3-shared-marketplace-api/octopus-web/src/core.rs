@@ -1,3 +0,0 @@
-mod matching;
-
-pub use matching::MatchingEngine;
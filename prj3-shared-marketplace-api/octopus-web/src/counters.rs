@@ -0,0 +1,166 @@
+use octopus_common::errors::ApplicationError;
+use serde::{Deserialize, Serialize};
+use std::{
+    fs,
+    io::Write,
+    path::{Path, PathBuf},
+};
+
+/// The file in a tenant's data directory holding its [`CounterStore`]
+pub const COUNTERS_FILE: &str = "counters.json";
+
+/// Identifiers leased per write, so not every order costs a write. A restart skips at most this many identifiers.
+pub const ID_LEASE: u64 = 1000;
+
+/// The last identifier issued of each kind
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct IdCounters {
+    /// Of the matching engine
+    pub ordinal: u64,
+    pub hold_id: u64,
+    pub withdrawal_id: u64,
+    pub recurring_buy_id: u64,
+    pub trade_id: u64,
+    pub payout_id: u64,
+}
+
+impl IdCounters {
+    fn fields(&self) -> [u64; 6] {
+        self.named().map(|(_, value)| value)
+    }
+
+    /// Every counter with its field name
+    pub fn named(&self) -> [(&'static str, u64); 6] {
+        [
+            ("ordinal", self.ordinal),
+            ("hold_id", self.hold_id),
+            ("withdrawal_id", self.withdrawal_id),
+            ("recurring_buy_id", self.recurring_buy_id),
+            ("trade_id", self.trade_id),
+            ("payout_id", self.payout_id),
+        ]
+    }
+
+    /// Whether any counter is ahead of the one in `other`
+    pub fn exceeds(&self, other: &IdCounters) -> bool {
+        self.fields()
+            .iter()
+            .zip(other.fields())
+            .any(|(this, other)| *this > other)
+    }
+
+    /// Every counter advanced by `n`
+    pub fn plus(&self, n: u64) -> Self {
+        IdCounters {
+            ordinal: self.ordinal + n,
+            hold_id: self.hold_id + n,
+            withdrawal_id: self.withdrawal_id + n,
+            recurring_buy_id: self.recurring_buy_id + n,
+            trade_id: self.trade_id + n,
+            payout_id: self.payout_id + n,
+        }
+    }
+}
+
+/// Keeps the counters on disk ahead of the issued identifiers, so a restarted server never reissues one
+#[derive(Debug)]
+pub struct CounterStore {
+    path: PathBuf,
+    leased: IdCounters,
+}
+
+impl CounterStore {
+    /// Opens the store at `path`, starting from zero if the file doesn't exist yet. Returns the counters to resume
+    /// from, which are past everything issued before the restart.
+    /// # Errors
+    /// The file can't be read or parsed
+    pub fn open(path: &Path) -> Result<(Self, IdCounters), ApplicationError> {
+        let failed =
+            |e: String| ApplicationError::StorageFailed(format!("{}: {}", path.display(), e));
+        let leased = match fs::read(path) {
+            Ok(bytes) => serde_json::from_slice(&bytes).map_err(|e| failed(e.to_string()))?,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => IdCounters::default(),
+            Err(e) => return Err(failed(e.to_string())),
+        };
+        let store = CounterStore {
+            path: path.to_path_buf(),
+            leased,
+        };
+        Ok((store, leased))
+    }
+
+    /// Makes sure `next` is covered by the file before any of its identifiers is issued
+    /// # Errors
+    /// Writing the file failed, the identifiers must not be issued
+    pub fn reserve(&mut self, next: &IdCounters) -> Result<(), ApplicationError> {
+        if !next.exceeds(&self.leased) {
+            return Ok(());
+        }
+        let leased = next.plus(ID_LEASE);
+        serde_json::to_vec(&leased)
+            .map_err(std::io::Error::from)
+            .and_then(|contents| write_atomically(&self.path, &contents))
+            .map_err(|e| {
+                ApplicationError::StorageFailed(format!("{}: {}", self.path.display(), e))
+            })?;
+        self.leased = leased;
+        Ok(())
+    }
+}
+
+/// Replaces the file at `path` atomically, so a crash leaves either the old or the new contents
+pub fn write_atomically(path: &Path, contents: &[u8]) -> std::io::Result<()> {
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    let staged = path.with_extension("tmp");
+    let mut file = fs::File::create(&staged)?;
+    file.write_all(contents)?;
+    file.sync_all()?;
+    fs::rename(&staged, path)
+}
+
+#[cfg(test)]
+mod tests {
+    // reduce the warnings for naming tests
+    #![allow(non_snake_case)]
+
+    use super::*;
+
+    #[test]
+    fn test_CounterStore_resumes_past_issued_ids() {
+        let dir = std::env::temp_dir().join(format!("octopus-counters-{}", std::process::id()));
+        let path = dir.join(COUNTERS_FILE);
+        let _ = fs::remove_dir_all(&dir);
+
+        let (mut store, resumed) = CounterStore::open(&path).unwrap();
+        assert_eq!(resumed, IdCounters::default());
+        let issued = IdCounters {
+            ordinal: 1,
+            ..IdCounters::default()
+        };
+        store.reserve(&issued).unwrap();
+        // Within the lease nothing is written
+        fs::remove_file(&path).unwrap();
+        store
+            .reserve(&IdCounters {
+                ordinal: ID_LEASE,
+                ..IdCounters::default()
+            })
+            .unwrap();
+        assert!(!path.exists());
+        store
+            .reserve(&IdCounters {
+                ordinal: ID_LEASE + 2,
+                hold_id: 1,
+                ..IdCounters::default()
+            })
+            .unwrap();
+
+        let (_, resumed) = CounterStore::open(&path).unwrap();
+        assert_eq!(resumed.ordinal, 2 * ID_LEASE + 2);
+        assert_eq!(resumed.hold_id, ID_LEASE + 1);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
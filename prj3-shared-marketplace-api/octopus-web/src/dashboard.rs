@@ -0,0 +1,42 @@
+//! A small dashboard at `GET /ui`: the order book of the default market, the trade tape, and a balance lookup. The page
+//! is compiled into the binary and only calls the JSON and websocket endpoints any client can, the tape and balances
+//! with the API key entered on the page.
+use warp::Filter;
+
+const PAGE: &str = include_str!("dashboard.html");
+
+/// Serves the dashboard page
+pub fn route() -> impl Filter<Extract = (impl warp::Reply,), Error = warp::Rejection> + Clone {
+    warp::path!("ui")
+        .and(warp::get())
+        .map(|| warp::reply::html(PAGE))
+}
+
+#[cfg(test)]
+mod tests {
+    // reduce the warnings for naming tests
+    #![allow(non_snake_case)]
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_route_serves_the_dashboard_page() {
+        let response = warp::test::request().path("/ui").reply(&route()).await;
+        assert_eq!(response.status(), 200);
+        assert_eq!(
+            response.headers()["content-type"],
+            "text/html; charset=utf-8"
+        );
+        let page = std::str::from_utf8(response.body()).unwrap();
+        for endpoint in ["/depth", "/orderbook/ws", "/trades", "/account"] {
+            assert!(page.contains(endpoint), "{} isn't used", endpoint);
+        }
+
+        let post = warp::test::request()
+            .method("POST")
+            .path("/ui")
+            .reply(&route())
+            .await;
+        assert_eq!(post.status(), 405);
+    }
+}
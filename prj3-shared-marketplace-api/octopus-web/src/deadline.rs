@@ -0,0 +1,94 @@
+//! Request deadlines. A client may shorten how long the server works on its request with the [`DEADLINE_HEADER`], the
+//! server's `max_request_timeout_secs` bounds it. Operations that run past the deadline are abandoned and answered
+//! with `408 Request Timeout`, saying how far they got.
+use octopus_common::errors::{ApplicationError, OctopusError};
+use std::time::{Duration, Instant};
+use warp::Filter;
+
+/// The header with the number of milliseconds a client waits for its request
+pub const DEADLINE_HEADER: &str = "x-request-timeout-ms";
+
+/// The longest a request is worked on unless configured otherwise
+pub const DEFAULT_MAX_REQUEST_TIMEOUT_SECS: u64 = 30;
+
+/// The instant by which a request has to be answered
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Deadline {
+    at: Instant,
+    timeout: Duration,
+}
+
+impl Deadline {
+    /// The deadline `timeout` from now
+    pub fn after(timeout: Duration) -> Self {
+        Deadline {
+            at: Instant::now() + timeout,
+            timeout,
+        }
+    }
+
+    /// The time left until the deadline, zero once it passed
+    pub fn remaining(&self) -> Duration {
+        self.at.saturating_duration_since(Instant::now())
+    }
+
+    /// The error for a request that missed this deadline, `progress` describes what was done by then
+    pub fn exceeded(&self, progress: &str) -> ApplicationError {
+        ApplicationError::DeadlineExceeded(self.timeout.as_millis() as u64, progress.to_string())
+    }
+}
+
+/// Parses the value of the [`DEADLINE_HEADER`] into a timeout of at most `max`, `max` without a header
+/// # Errors
+/// The value isn't a number of milliseconds
+pub fn timeout_of(header: Option<&str>, max: Duration) -> Result<Duration, ApplicationError> {
+    match header {
+        Some(value) => value
+            .trim()
+            .parse::<u64>()
+            .map(|millis| Duration::from_millis(millis).min(max))
+            .map_err(|_| ApplicationError::InvalidDeadline(value.to_string())),
+        None => Ok(max),
+    }
+}
+
+/// A filter that starts the [`Deadline`] of a request, bounded by `max`
+pub fn with_deadline(
+    max: Duration,
+) -> impl Filter<Extract = (Deadline,), Error = warp::Rejection> + Clone {
+    warp::header::optional::<String>(DEADLINE_HEADER).and_then(
+        move |header: Option<String>| async move {
+            timeout_of(header.as_deref(), max)
+                .map(Deadline::after)
+                .map_err(|e| warp::reject::custom(OctopusError(e)))
+        },
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    // reduce the warnings for naming tests
+    #![allow(non_snake_case)]
+
+    use super::*;
+
+    #[test]
+    fn test_timeout_of_is_bounded_by_the_maximum() {
+        let max = Duration::from_secs(5);
+        assert_eq!(timeout_of(None, max), Ok(max));
+        assert_eq!(timeout_of(Some("250"), max), Ok(Duration::from_millis(250)));
+        assert_eq!(timeout_of(Some("60000"), max), Ok(max));
+        assert_eq!(
+            timeout_of(Some("soon"), max),
+            Err(ApplicationError::InvalidDeadline("soon".to_string()))
+        );
+
+        let deadline = Deadline::after(Duration::ZERO);
+        assert_eq!(deadline.remaining(), Duration::ZERO);
+        assert_eq!(
+            deadline.exceeded("nothing was done"),
+            ApplicationError::DeadlineExceeded(0, "nothing was done".to_string())
+        );
+        assert!(Deadline::after(max).remaining() > Duration::ZERO);
+    }
+}
@@ -0,0 +1,196 @@
+//! Dead-man's switches for automated traders.
+//!
+//! An account arms its switch with a timeout and keeps refreshing it while it's healthy. If the refreshes stop, e.g.
+//! because the trader crashed or lost connectivity, every open order of the account is cancelled once the timeout
+//! passes. Every armed switch is a timer task of its own.
+use octopus_common::{errors::ApplicationError, types::DeadmanStatus};
+use std::{
+    collections::HashMap,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Mutex,
+    },
+    time::Duration,
+};
+use tokio::task::JoinHandle;
+
+use crate::{scheduler::now_millis, trading_platform::TradingPlatform};
+
+/// The longest timeout a switch can be armed with
+pub const MAX_DEADMAN_TIMEOUT_SECS: u64 = 24 * 60 * 60;
+
+/// The pending cancellation of one account
+#[derive(Debug)]
+struct Timer {
+    /// Tells a timer that fired from the one that replaced it
+    generation: u64,
+    task: JoinHandle<()>,
+}
+
+/// The armed switches of every account of every tenant
+#[derive(Debug, Default)]
+pub struct DeadmanSwitches {
+    timers: Mutex<HashMap<(String, String), Timer>>,
+    last_generation: AtomicU64,
+}
+
+impl DeadmanSwitches {
+    pub fn new() -> Self {
+        DeadmanSwitches::default()
+    }
+
+    /// Arms or refreshes the switch of the `signer` account of `tenant`, a zero `timeout` disarms it. Once the timeout
+    /// passes without another call, the account's open orders are cancelled.
+    /// # Errors
+    /// - The account doesn't exist
+    /// - The timeout is longer than [`MAX_DEADMAN_TIMEOUT_SECS`]
+    pub fn arm(
+        self: &Arc<Self>,
+        trading_platform: Arc<Mutex<TradingPlatform>>,
+        tenant: &str,
+        signer: &str,
+        timeout: Duration,
+    ) -> Result<DeadmanStatus, ApplicationError> {
+        if timeout.as_secs() > MAX_DEADMAN_TIMEOUT_SECS {
+            return Err(ApplicationError::InvalidDeadmanTimeout(timeout.as_secs()));
+        }
+        trading_platform
+            .lock()
+            .unwrap()
+            .accounts
+            .balance_of(signer)?;
+        let key = (tenant.to_string(), signer.to_string());
+        let mut timers = self.timers.lock().unwrap();
+        if let Some(previous) = timers.remove(&key) {
+            previous.task.abort();
+        }
+        if timeout.is_zero() {
+            return Ok(DeadmanStatus {
+                signer: signer.to_string(),
+                timeout_secs: 0,
+                expires_at: None,
+            });
+        }
+
+        let generation = self.last_generation.fetch_add(1, Ordering::Relaxed) + 1;
+        let switches = Arc::clone(self);
+        let timer_key = key.clone();
+        let task = tokio::spawn(async move {
+            tokio::time::sleep(timeout).await;
+            switches.fire(&timer_key, generation, &trading_platform);
+        });
+        timers.insert(key, Timer { generation, task });
+        Ok(DeadmanStatus {
+            signer: signer.to_string(),
+            timeout_secs: timeout.as_secs(),
+            expires_at: Some(now_millis() + timeout.as_millis() as u64),
+        })
+    }
+
+    /// Cancels the open orders of the account, unless the timer was refreshed since it was started
+    fn fire(
+        &self,
+        key: &(String, String),
+        generation: u64,
+        trading_platform: &Mutex<TradingPlatform>,
+    ) {
+        {
+            let mut timers = self.timers.lock().unwrap();
+            // A refresh right as the timer fired wins
+            if timers.get(key).map(|timer| timer.generation) != Some(generation) {
+                return;
+            }
+            timers.remove(key);
+        }
+        let (tenant, signer) = key;
+        let cancelled = trading_platform
+            .lock()
+            .unwrap()
+            .cancel_all(signer, now_millis());
+        log::info!(
+            "Dead-man's switch of {} in tenant {} expired, cancelled {} open orders",
+            signer,
+            tenant,
+            cancelled.len()
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    // reduce the warnings for naming tests
+    #![allow(non_snake_case)]
+
+    use super::*;
+    use octopus_common::types::{Order, OrderType, Side, TimeInForce, DEFAULT_MARKET};
+
+    fn platform() -> Arc<Mutex<TradingPlatform>> {
+        let mut trading_platform = TradingPlatform::new();
+        trading_platform.deposit("ALICE", 1_000).unwrap();
+        trading_platform
+            .order(Order {
+                price: 10,
+                amount: 1,
+                side: Side::Sell,
+                signer: "ALICE".to_string(),
+                client_order_id: None,
+                signature: None,
+                order_type: OrderType::Limit,
+                time_in_force: TimeInForce::Gtc,
+                trigger_price: None,
+                market: DEFAULT_MARKET.to_string(),
+            })
+            .unwrap();
+        Arc::new(Mutex::new(trading_platform))
+    }
+
+    #[tokio::test]
+    async fn test_DeadmanSwitches_arm_cancels_orders_unless_refreshed() {
+        let switches = Arc::new(DeadmanSwitches::new());
+        let trading_platform = platform();
+        let timeout = Duration::from_millis(200);
+
+        switches
+            .arm(trading_platform.clone(), "default", "ALICE", timeout)
+            .unwrap();
+        tokio::time::sleep(Duration::from_millis(120)).await;
+        switches
+            .arm(trading_platform.clone(), "default", "ALICE", timeout)
+            .unwrap();
+        tokio::time::sleep(Duration::from_millis(120)).await;
+        // The refresh pushed the deadline back
+        assert_eq!(trading_platform.lock().unwrap().orderbook().len(), 1);
+
+        tokio::time::sleep(Duration::from_millis(200)).await;
+        assert!(trading_platform.lock().unwrap().orderbook().is_empty());
+        assert!(switches.timers.lock().unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_DeadmanSwitches_arm_with_zero_timeout_disarms() {
+        let switches = Arc::new(DeadmanSwitches::new());
+        let trading_platform = platform();
+
+        switches
+            .arm(
+                trading_platform.clone(),
+                "default",
+                "ALICE",
+                Duration::from_millis(20),
+            )
+            .unwrap();
+        let status = switches
+            .arm(trading_platform.clone(), "default", "ALICE", Duration::ZERO)
+            .unwrap();
+        assert_eq!(status.expires_at, None);
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        assert_eq!(trading_platform.lock().unwrap().orderbook().len(), 1);
+
+        assert_eq!(
+            switches
+                .arm(trading_platform, "default", "BOB", Duration::from_secs(1))
+                .unwrap_err(),
+            ApplicationError::AccountNotFound("BOB".to_string())
+        );
+    }
+}
@@ -0,0 +1,110 @@
+//! Simulated traders that give a fresh server live data: each bot places randomized orders around the last price
+//! and now and then sends money to another bot.
+use std::{sync::Arc, time::Duration};
+
+use octopus_common::types::{Order, OrderType, Side, TimeInForce, DEFAULT_MARKET};
+use rand::{rngs::StdRng, Rng, SeedableRng};
+
+use crate::tenants::{Tenants, DEFAULT_TENANT};
+
+/// The number of bots started in demo mode
+pub const DEMO_BOTS: usize = 5;
+
+/// The balance each bot starts with
+pub const BOT_DEPOSIT: u64 = 1_000_000;
+
+/// The price the bots trade around until the first match
+const START_PRICE: u64 = 100;
+
+/// The name of a bot's account
+pub fn bot_account(bot: usize) -> String {
+    format!("demo-bot-{}", bot)
+}
+
+/// A random order up to 5% away from `reference`
+fn random_order(rng: &mut impl Rng, signer: &str, reference: u64) -> Order {
+    let spread = (reference / 20).max(1);
+    Order {
+        price: rng
+            .gen_range(reference.saturating_sub(spread)..=reference + spread)
+            .max(1),
+        amount: rng.gen_range(1..=10),
+        side: if rng.gen_bool(0.5) {
+            Side::Buy
+        } else {
+            Side::Sell
+        },
+        signer: signer.to_string(),
+        client_order_id: None,
+        signature: None,
+        order_type: OrderType::Limit,
+        time_in_force: TimeInForce::Gtc,
+        trigger_price: None,
+        market: DEFAULT_MARKET.to_string(),
+    }
+}
+
+/// Funds `bots` accounts in the [`DEFAULT_TENANT`] and lets each of them trade in its own task
+pub fn start(tenants: &Tenants, bots: usize) -> Vec<tokio::task::JoinHandle<()>> {
+    let (Ok(trading_platform), Ok(order_queue)) =
+        (tenants.get(DEFAULT_TENANT), tenants.orders(DEFAULT_TENANT))
+    else {
+        return vec![];
+    };
+    {
+        let mut ledger_lock = trading_platform.lock().unwrap();
+        for bot in 0..bots {
+            if let Err(e) = ledger_lock.deposit(&bot_account(bot), BOT_DEPOSIT) {
+                log::warn!("Funding {} failed: {:?}", bot_account(bot), e);
+            }
+        }
+    }
+    (0..bots)
+        .map(|bot| {
+            let trading_platform = trading_platform.clone();
+            let order_queue = Arc::clone(&order_queue);
+            tokio::spawn(async move {
+                let signer = bot_account(bot);
+                let mut rng = StdRng::from_entropy();
+                loop {
+                    tokio::time::sleep(Duration::from_millis(rng.gen_range(200..=1_500))).await;
+                    if rng.gen_ratio(1, 10) {
+                        let to = bot_account(rng.gen_range(0..bots));
+                        let amount = rng.gen_range(1..=1_000);
+                        // Transfers between bots may fail like any other, e.g. for lack of funds
+                        let _ = trading_platform.lock().unwrap().send(&signer, &to, amount);
+                        continue;
+                    }
+                    let reference = trading_platform
+                        .lock()
+                        .unwrap()
+                        .last_price
+                        .unwrap_or(START_PRICE);
+                    let order = random_order(&mut rng, &signer, reference);
+                    if let Err(e) = order_queue.submit(order).await {
+                        log::debug!("{} had an order rejected: {:?}", signer, e);
+                    }
+                }
+            })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    // reduce the warnings for naming tests
+    #![allow(non_snake_case)]
+
+    use super::*;
+
+    #[test]
+    fn test_random_order_stays_near_reference() {
+        let mut rng = StdRng::seed_from_u64(7);
+        for _ in 0..100 {
+            let order = random_order(&mut rng, "demo-bot-0", 100);
+            assert!((95..=105).contains(&order.price));
+            assert!((1..=10).contains(&order.amount));
+        }
+        assert!(random_order(&mut rng, "demo-bot-0", 0).price >= 1);
+    }
+}
@@ -0,0 +1,110 @@
+use octopus_common::types::Receipt;
+use std::collections::{HashMap, VecDeque};
+
+/// How long a client order id is remembered unless configured otherwise
+pub const DEFAULT_DUPLICATE_WINDOW_SECS: u64 = 60;
+
+/// Receipts of the recent orders by signer and client order id, so resubmissions of an order are rejected with the
+/// original receipt instead of trading twice
+#[derive(Debug)]
+pub struct RecentOrders {
+    window_millis: u64,
+    receipts: HashMap<(String, String), Receipt>,
+    /// When each key was added, oldest first
+    added: VecDeque<(u64, (String, String))>,
+}
+
+impl Default for RecentOrders {
+    fn default() -> Self {
+        RecentOrders::new(DEFAULT_DUPLICATE_WINDOW_SECS * 1000)
+    }
+}
+
+impl RecentOrders {
+    pub fn new(window_millis: u64) -> Self {
+        RecentOrders {
+            window_millis,
+            receipts: HashMap::new(),
+            added: VecDeque::new(),
+        }
+    }
+
+    /// Forgets the orders that are older than the window at `now`
+    fn expire(&mut self, now: u64) {
+        while let Some((added, _)) = self.added.front() {
+            if added + self.window_millis > now {
+                break;
+            }
+            if let Some((_, key)) = self.added.pop_front() {
+                self.receipts.remove(&key);
+            }
+        }
+    }
+
+    /// The receipt of the order `signer` submitted as `client_order_id` within the window
+    pub fn get(&mut self, signer: &str, client_order_id: &str, now: u64) -> Option<&Receipt> {
+        self.expire(now);
+        self.receipts
+            .get(&(signer.to_string(), client_order_id.to_string()))
+    }
+
+    /// Replaces `signer` with `token` in the keys and receipts
+    pub fn anonymize(&mut self, signer: &str, token: &str) {
+        let rename = |(account, client_order_id): (String, String)| match account == signer {
+            true => (token.to_string(), client_order_id),
+            false => (account, client_order_id),
+        };
+        self.receipts = std::mem::take(&mut self.receipts)
+            .into_iter()
+            .map(|(key, mut receipt)| {
+                receipt.anonymize(signer, token);
+                (rename(key), receipt)
+            })
+            .collect();
+        for (_, key) in self.added.iter_mut() {
+            *key = rename(std::mem::take(key));
+        }
+    }
+
+    /// Remembers the receipt of an accepted order
+    pub fn insert(&mut self, signer: &str, client_order_id: &str, receipt: Receipt, now: u64) {
+        self.expire(now);
+        let key = (signer.to_string(), client_order_id.to_string());
+        self.added.push_back((now, key.clone()));
+        self.receipts.insert(key, receipt);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    // reduce the warnings for naming tests
+    #![allow(non_snake_case)]
+
+    use super::*;
+    use octopus_common::types::{ReceiptStatus, DEFAULT_MARKET};
+
+    #[test]
+    fn test_RecentOrders_forgets_orders_after_the_window() {
+        let mut recent = RecentOrders::new(1_000);
+        let receipt = Receipt {
+            ordinal: 1,
+            order_id: 1,
+            matches: vec![],
+            filled: 0,
+            cancelled: 0,
+            limit_price: None,
+            market: DEFAULT_MARKET.to_string(),
+            self_matches: vec![],
+            settlement: None,
+            remaining: 0,
+            total_notional: 0,
+            average_price: None,
+            status: ReceiptStatus::Resting,
+        };
+        recent.insert("ALICE", "a-1", receipt.clone(), 10);
+        assert_eq!(recent.get("ALICE", "a-1", 1_009), Some(&receipt));
+        assert_eq!(recent.get("BOB", "a-1", 1_009), None);
+        assert_eq!(recent.get("ALICE", "a-1", 1_010), None);
+        assert!(recent.receipts.is_empty());
+    }
+}
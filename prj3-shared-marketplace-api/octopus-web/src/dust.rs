@@ -0,0 +1,61 @@
+//! Dust, balances too small to be worth trading or withdrawing. The scheduler sweeps the dust of the accounts that
+//! consented into the [`DUST_ACCOUNT`], and a deleted account leaves its dust there instead of withdrawing it.
+use octopus_common::types::{anonymize, DustReport, DustSweep};
+use std::collections::BTreeSet;
+
+/// The account collecting all dust
+pub const DUST_ACCOUNT: &str = "octopus-dust";
+
+/// The accounts that consented to sweeps, and every sweep
+#[derive(Debug, Default)]
+pub struct Dust {
+    /// Balances above 0 and below this are dust, 0 turns sweeping off
+    pub threshold: u64,
+    consenting: BTreeSet<String>,
+    sweeps: Vec<DustSweep>,
+}
+
+impl Dust {
+    pub fn new() -> Self {
+        Dust::default()
+    }
+
+    /// Whether `balance` is dust
+    pub fn is_dust(&self, balance: u64) -> bool {
+        balance > 0 && balance < self.threshold
+    }
+
+    /// Opts `signer` in or out of sweeps
+    pub fn set_consent(&mut self, signer: &str, consent: bool) {
+        match consent {
+            true => self.consenting.insert(signer.to_string()),
+            false => self.consenting.remove(signer),
+        };
+    }
+
+    /// The accounts that consented to sweeps
+    pub fn consenting(&self) -> Vec<String> {
+        self.consenting.iter().cloned().collect()
+    }
+
+    pub fn record(&mut self, sweep: DustSweep) {
+        self.sweeps.push(sweep);
+    }
+
+    /// Forgets the consent of `signer` and replaces it with `token` in the sweeps
+    pub fn anonymize(&mut self, signer: &str, token: &str) {
+        self.consenting.remove(signer);
+        for sweep in self.sweeps.iter_mut() {
+            anonymize(&mut sweep.account, signer, token);
+        }
+    }
+
+    pub fn report(&self) -> DustReport {
+        DustReport {
+            threshold: self.threshold,
+            consenting: self.consenting.len() as u64,
+            total: self.sweeps.iter().map(|sweep| sweep.amount).sum(),
+            sweeps: self.sweeps.clone(),
+        }
+    }
+}
@@ -0,0 +1,106 @@
+//! The order events of every public book for operators: accepted orders, trades, cancellations, and expiries as they
+//! happen, logged and streamed over `GET /admin/events/ws` without polling. Sandbox orders aren't included.
+use futures_util::{SinkExt, StreamExt};
+use tokio::sync::broadcast;
+use warp::ws::{Message, WebSocket};
+
+use crate::core::{EngineEvent, EventSink};
+
+/// The number of events a slow stream may fall behind before it's closed
+pub const ENGINE_EVENTS_CAPACITY: usize = 4096;
+
+/// Publishes the events of the books it's registered with. Clones publish to the same streams, so a clone is
+/// registered with each book.
+#[derive(Debug, Clone)]
+pub struct EngineEvents(broadcast::Sender<EngineEvent>);
+
+impl Default for EngineEvents {
+    fn default() -> Self {
+        EngineEvents(broadcast::channel(ENGINE_EVENTS_CAPACITY).0)
+    }
+}
+
+impl EngineEvents {
+    pub fn subscribe(&self) -> broadcast::Receiver<EngineEvent> {
+        self.0.subscribe()
+    }
+}
+
+impl EventSink for EngineEvents {
+    fn send(&mut self, event: EngineEvent) {
+        log::debug!("{:?}", event);
+        // Nobody listening is fine
+        let _ = self.0.send(event);
+    }
+}
+
+/// Forwards the events until the connection closes. A stream that falls behind by more than
+/// [`ENGINE_EVENTS_CAPACITY`] events is closed.
+pub async fn serve(socket: WebSocket, mut events: broadcast::Receiver<EngineEvent>) {
+    let (mut outgoing, mut incoming) = socket.split();
+    loop {
+        tokio::select! {
+            event = events.recv() => match event {
+                Ok(event) => {
+                    let text = serde_json::to_string(&event).expect("engine events serialize");
+                    if outgoing.send(Message::text(text)).await.is_err() {
+                        return;
+                    }
+                }
+                Err(e) => {
+                    log::info!("Closing an engine event stream: {}", e);
+                    let _ = outgoing.send(Message::close()).await;
+                    return;
+                }
+            },
+            // Anything the client sends is ignored
+            message = incoming.next() => match message {
+                Some(Ok(message)) if !message.is_close() => {}
+                _ => return,
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    // reduce the warnings for naming tests
+    #![allow(non_snake_case)]
+
+    use super::*;
+    use crate::{markets::MarketConfig, trading_platform::TradingPlatform};
+    use octopus_common::types::{Order, OrderType, Side, TimeInForce};
+
+    #[test]
+    fn test_EngineEvents_subscribe_hears_of_every_public_book() {
+        let mut trading_platform = TradingPlatform::new();
+        trading_platform.deposit("ALICE", 1_000).unwrap();
+        trading_platform
+            .list_market("SQUID-USD", MarketConfig::default())
+            .unwrap();
+        let mut events = trading_platform.engine_events.subscribe();
+        for market in ["OCTO-USD", "SQUID-USD"] {
+            trading_platform
+                .order(Order {
+                    price: 10,
+                    amount: 1,
+                    side: Side::Buy,
+                    signer: "ALICE".to_string(),
+                    client_order_id: None,
+                    signature: None,
+                    order_type: OrderType::Limit,
+                    time_in_force: TimeInForce::Gtc,
+                    trigger_price: None,
+                    market: market.to_string(),
+                })
+                .unwrap();
+        }
+        for market in ["OCTO-USD", "SQUID-USD"] {
+            match events.try_recv().unwrap() {
+                EngineEvent::OrderAccepted { order } => assert_eq!(order.market, market),
+                event => panic!("unexpected {:?}", event),
+            }
+        }
+        assert!(events.try_recv().is_err());
+    }
+}
@@ -0,0 +1,44 @@
+use warp::http::{header, StatusCode};
+use warp::Reply;
+
+/// The ETag of market data as of a book sequence number
+pub fn of(seq: u64) -> String {
+    format!("\"{}\"", seq)
+}
+
+/// Whether an `If-None-Match` header lists `etag`. Weak comparison, as for conditional GETs.
+pub fn matches(if_none_match: Option<&str>, etag: &str) -> bool {
+    if_none_match.is_some_and(|tags| {
+        tags.split(',')
+            .map(|tag| tag.trim())
+            .any(|tag| tag == "*" || tag.trim_start_matches("W/") == etag)
+    })
+}
+
+/// `304 Not Modified` if the client has the data as of `seq` already, otherwise the reply tagged with `seq`
+pub fn reply(if_none_match: Option<&str>, seq: u64, reply: impl Reply) -> warp::reply::Response {
+    let etag = of(seq);
+    if matches(if_none_match, &etag) {
+        let not_modified = warp::reply::with_status(warp::reply(), StatusCode::NOT_MODIFIED);
+        return warp::reply::with_header(not_modified, header::ETAG, etag).into_response();
+    }
+    warp::reply::with_header(reply, header::ETAG, etag).into_response()
+}
+
+#[cfg(test)]
+mod tests {
+    // reduce the warnings for naming tests
+    #![allow(non_snake_case)]
+
+    use super::*;
+
+    #[test]
+    fn test_matches_compares_weakly() {
+        let etag = of(7);
+        assert!(matches(Some("\"7\""), &etag));
+        assert!(matches(Some("\"3\", W/\"7\""), &etag));
+        assert!(matches(Some("*"), &etag));
+        assert!(!matches(Some("\"70\""), &etag));
+        assert!(!matches(None, &etag));
+    }
+}
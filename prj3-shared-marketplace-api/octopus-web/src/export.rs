@@ -0,0 +1,379 @@
+//! Parquet exports of the exchange history for analytical tools.
+//!
+//! Both files have a single row group, are uncompressed, and store amounts and prices as unsigned 64-bit
+//! integers. The schemas are [`TRANSACTIONS_SCHEMA`] and [`TRADES_SCHEMA`].
+use std::sync::Arc;
+
+use octopus_common::{
+    errors::ApplicationError,
+    tx::{Memo, Tx},
+    types::{Side, Trade},
+};
+use parquet::{
+    data_type::{ByteArray, ByteArrayType, Int64Type},
+    errors::ParquetError,
+    file::{properties::WriterProperties, writer::SerializedFileWriter},
+    schema::parser::parse_message_type,
+};
+
+use crate::fees::FEE_ACCOUNT;
+
+/// The media type of the exported files
+pub const PARQUET_CONTENT_TYPE: &str = "application/vnd.apache.parquet";
+
+/// One row per transaction in the order they happened.
+/// - `sequence`: the position in the transaction log, starting at 0
+/// - `ordinal`: the matching engine's ordinal when the transaction happened
+/// - `kind`: the [`Tx`] variant, e.g. `Deposit` or `Capture`
+/// - `id`: the hold, withdrawal or trade id, if any
+/// - `account`: the account that was debited or credited (the sender for captures and settlements, the seller for
+///   reversed settlements)
+/// - `counterparty`: the recipient of a capture or (reversed) settlement, or the fee account for fees
+/// - `memo`/`metadata`: the memo text and metadata (a JSON object) of a transfer, if any
+pub const TRANSACTIONS_SCHEMA: &str = "
+message transaction {
+    REQUIRED INT64 sequence;
+    REQUIRED INT64 ordinal;
+    REQUIRED INT64 timestamp (TIMESTAMP(MILLIS,true));
+    REQUIRED BYTE_ARRAY kind (UTF8);
+    OPTIONAL INT64 id;
+    REQUIRED BYTE_ARRAY account (UTF8);
+    OPTIONAL BYTE_ARRAY counterparty (UTF8);
+    REQUIRED INT64 amount (INTEGER(64,false));
+    OPTIONAL BYTE_ARRAY memo (UTF8);
+    OPTIONAL BYTE_ARRAY metadata (UTF8);
+}";
+
+/// One row per match in the order they happened.
+/// - `id`: the trade id, referenced by the settlement transactions
+/// - `ordinal`/`maker_ordinal`: the ordinals of the taker and the maker order
+/// - `taker_side`: `buy` or `sell`
+/// - `busted`: when the trade was busted, if it was
+pub const TRADES_SCHEMA: &str = "
+message trade {
+    REQUIRED INT64 id;
+    REQUIRED INT64 ordinal;
+    REQUIRED INT64 maker_ordinal;
+    REQUIRED INT64 timestamp (TIMESTAMP(MILLIS,true));
+    REQUIRED BYTE_ARRAY market (UTF8);
+    REQUIRED INT64 price (INTEGER(64,false));
+    REQUIRED INT64 amount (INTEGER(64,false));
+    REQUIRED BYTE_ARRAY taker (UTF8);
+    REQUIRED BYTE_ARRAY maker (UTF8);
+    REQUIRED BYTE_ARRAY taker_side (UTF8);
+    OPTIONAL INT64 busted (TIMESTAMP(MILLIS,true));
+}";
+
+/// The values of a column. Optional columns carry a definition level per row, and values only for the rows that have one.
+enum Column {
+    Int64(Vec<i64>, Option<Vec<i16>>),
+    Utf8(Vec<ByteArray>, Option<Vec<i16>>),
+}
+
+impl Column {
+    fn int64(values: impl Iterator<Item = u64>) -> Self {
+        // Unsigned columns are annotated in the schema, so the bits are stored as they are
+        Column::Int64(values.map(|value| value as i64).collect(), None)
+    }
+
+    fn utf8<S: AsRef<str>>(values: impl Iterator<Item = S>) -> Self {
+        Column::Utf8(
+            values
+                .map(|value| ByteArray::from(value.as_ref()))
+                .collect(),
+            None,
+        )
+    }
+
+    fn optional_int64(values: impl Iterator<Item = Option<u64>>) -> Self {
+        let (values, levels) = optional(values);
+        Column::Int64(
+            values.into_iter().map(|value| value as i64).collect(),
+            Some(levels),
+        )
+    }
+
+    fn optional_utf8<S: AsRef<str>>(values: impl Iterator<Item = Option<S>>) -> Self {
+        let (values, levels) = optional(values);
+        Column::Utf8(
+            values
+                .into_iter()
+                .map(|value| ByteArray::from(value.as_ref()))
+                .collect(),
+            Some(levels),
+        )
+    }
+}
+
+fn optional<T>(values: impl Iterator<Item = Option<T>>) -> (Vec<T>, Vec<i16>) {
+    let mut present = vec![];
+    let mut levels = vec![];
+    for value in values {
+        levels.push(value.is_some() as i16);
+        present.extend(value);
+    }
+    (present, levels)
+}
+
+/// Writes the columns in schema order into a Parquet file
+fn write(schema: &str, columns: Vec<Column>) -> Result<Vec<u8>, ApplicationError> {
+    write_parquet(schema, columns).map_err(|e| ApplicationError::ExportFailed(e.to_string()))
+}
+
+fn write_parquet(schema: &str, columns: Vec<Column>) -> Result<Vec<u8>, ParquetError> {
+    let schema = Arc::new(parse_message_type(schema)?);
+    let properties = Arc::new(WriterProperties::builder().build());
+    let mut writer = SerializedFileWriter::new(vec![], schema, properties)?;
+    let mut row_group = writer.next_row_group()?;
+    for column in columns {
+        let mut column_writer = row_group
+            .next_column()?
+            .ok_or_else(|| ParquetError::General("more columns than in the schema".to_string()))?;
+        match column {
+            Column::Int64(values, levels) => {
+                column_writer
+                    .typed::<Int64Type>()
+                    .write_batch(&values, levels.as_deref(), None)?;
+            }
+            Column::Utf8(values, levels) => {
+                column_writer.typed::<ByteArrayType>().write_batch(
+                    &values,
+                    levels.as_deref(),
+                    None,
+                )?;
+            }
+        }
+        column_writer.close()?;
+    }
+    row_group.close()?;
+    writer.into_inner()
+}
+
+/// The entries of the transaction log, see [`crate::balances::BalanceLog::entries`], as a Parquet file following
+/// [`TRANSACTIONS_SCHEMA`]
+pub fn transactions<'a>(
+    entries: impl Iterator<Item = (u64, u64, &'a Tx)>,
+) -> Result<Vec<u8>, ApplicationError> {
+    let rows: Vec<_> = entries.map(row_of).collect();
+    write(
+        TRANSACTIONS_SCHEMA,
+        vec![
+            Column::int64(0..rows.len() as u64),
+            Column::int64(rows.iter().map(|row| row.ordinal)),
+            Column::int64(rows.iter().map(|row| row.timestamp)),
+            Column::utf8(rows.iter().map(|row| row.kind)),
+            Column::optional_int64(rows.iter().map(|row| row.id)),
+            Column::utf8(rows.iter().map(|row| row.account)),
+            Column::optional_utf8(rows.iter().map(|row| row.counterparty)),
+            Column::int64(rows.iter().map(|row| row.amount)),
+            Column::optional_utf8(
+                rows.iter()
+                    .map(|row| row.memo.and_then(|memo| memo.text.as_ref())),
+            ),
+            Column::optional_utf8(rows.iter().map(|row| {
+                row.memo
+                    .filter(|memo| !memo.metadata.is_empty())
+                    .map(|memo| serde_json::to_string(&memo.metadata).unwrap_or_default())
+            })),
+        ],
+    )
+}
+
+/// The trade tape as a Parquet file following [`TRADES_SCHEMA`]
+pub fn trades(trades: &[Trade]) -> Result<Vec<u8>, ApplicationError> {
+    write(
+        TRADES_SCHEMA,
+        vec![
+            Column::int64(trades.iter().map(|trade| trade.id)),
+            Column::int64(trades.iter().map(|trade| trade.ordinal)),
+            Column::int64(trades.iter().map(|trade| trade.maker_ordinal)),
+            Column::int64(trades.iter().map(|trade| trade.timestamp)),
+            Column::utf8(trades.iter().map(|trade| &trade.market)),
+            Column::int64(trades.iter().map(|trade| trade.price)),
+            Column::int64(trades.iter().map(|trade| trade.amount)),
+            Column::utf8(trades.iter().map(|trade| &trade.taker)),
+            Column::utf8(trades.iter().map(|trade| &trade.maker)),
+            Column::utf8(trades.iter().map(|trade| match trade.taker_side {
+                Side::Buy => "buy",
+                Side::Sell => "sell",
+            })),
+            Column::optional_int64(trades.iter().map(|trade| trade.busted)),
+        ],
+    )
+}
+
+/// A transaction flattened into the columns of [`TRANSACTIONS_SCHEMA`]
+struct TransactionRow<'a> {
+    ordinal: u64,
+    timestamp: u64,
+    kind: &'static str,
+    id: Option<u64>,
+    account: &'a str,
+    counterparty: Option<&'a str>,
+    amount: u64,
+    memo: Option<&'a Memo>,
+}
+
+fn row_of((ordinal, timestamp, tx): (u64, u64, &Tx)) -> TransactionRow<'_> {
+    let (kind, id, account, counterparty, amount) = match tx {
+        Tx::Deposit {
+            account, amount, ..
+        } => ("Deposit", None, account, None, amount),
+        Tx::Faucet { account, amount } => ("Faucet", None, account, None, amount),
+        Tx::SandboxClosed { account, amount } => ("SandboxClosed", None, account, None, amount),
+        Tx::Withdraw {
+            account, amount, ..
+        } => ("Withdraw", None, account, None, amount),
+        Tx::Fee { account, amount } => ("Fee", None, account, Some(FEE_ACCOUNT), amount),
+        Tx::Hold {
+            id,
+            account,
+            amount,
+        } => ("Hold", Some(*id), account, None, amount),
+        Tx::Capture {
+            id,
+            from,
+            to,
+            amount,
+        } => ("Capture", Some(*id), from, Some(to.as_str()), amount),
+        Tx::Release {
+            id,
+            account,
+            amount,
+        } => ("Release", Some(*id), account, None, amount),
+        Tx::Payout {
+            id,
+            from,
+            to,
+            amount,
+        } => ("Payout", Some(*id), from, Some(to.as_str()), amount),
+        Tx::DustSwept { from, to, amount } => ("DustSwept", None, from, Some(to.as_str()), amount),
+        Tx::WithdrawalRequested {
+            id,
+            account,
+            amount,
+        } => ("WithdrawalRequested", Some(*id), account, None, amount),
+        Tx::WithdrawalApproved {
+            id,
+            account,
+            amount,
+        } => ("WithdrawalApproved", Some(*id), account, None, amount),
+        Tx::WithdrawalRejected {
+            id,
+            account,
+            amount,
+        } => ("WithdrawalRejected", Some(*id), account, None, amount),
+        Tx::Settlement {
+            trade_id,
+            from,
+            to,
+            amount,
+        } => (
+            "Settlement",
+            Some(*trade_id),
+            from,
+            Some(to.as_str()),
+            amount,
+        ),
+        Tx::SettlementReversed {
+            trade_id,
+            from,
+            to,
+            amount,
+        } => (
+            "SettlementReversed",
+            Some(*trade_id),
+            from,
+            Some(to.as_str()),
+            amount,
+        ),
+    };
+    TransactionRow {
+        ordinal,
+        timestamp,
+        kind,
+        id,
+        account,
+        counterparty,
+        amount: *amount,
+        memo: tx.memo(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    // reduce the warnings for naming tests
+    #![allow(non_snake_case)]
+
+    use super::*;
+    use crate::balances::BalanceLog;
+    use bytes::Bytes;
+    use parquet::file::reader::{FileReader, SerializedFileReader};
+
+    fn read(file: Vec<u8>) -> SerializedFileReader<Bytes> {
+        SerializedFileReader::new(Bytes::from(file)).unwrap()
+    }
+
+    #[test]
+    fn test_transactions_writes_one_row_per_tx() {
+        let mut balance_log = BalanceLog::default();
+        balance_log.record(
+            0,
+            1,
+            Tx::Deposit {
+                account: "ALICE".to_string(),
+                amount: u64::MAX,
+                memo: None,
+            },
+        );
+        balance_log.record(
+            1,
+            2,
+            Tx::Capture {
+                id: 1,
+                from: "ALICE".to_string(),
+                to: "BOB".to_string(),
+                amount: 10,
+            },
+        );
+        let reader = read(transactions(balance_log.entries()).unwrap());
+        let metadata = reader.metadata();
+        assert_eq!(metadata.file_metadata().num_rows(), 2);
+        let columns: Vec<_> = metadata
+            .file_metadata()
+            .schema_descr()
+            .columns()
+            .iter()
+            .map(|column| column.name().to_string())
+            .collect();
+        assert_eq!(
+            columns,
+            vec![
+                "sequence",
+                "ordinal",
+                "timestamp",
+                "kind",
+                "id",
+                "account",
+                "counterparty",
+                "amount",
+                "memo",
+                "metadata"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_trades_writes_empty_tape() {
+        let reader = read(trades(&[]).unwrap());
+        assert_eq!(reader.metadata().file_metadata().num_rows(), 0);
+        assert_eq!(
+            reader
+                .metadata()
+                .file_metadata()
+                .schema_descr()
+                .num_columns(),
+            11
+        );
+    }
+}
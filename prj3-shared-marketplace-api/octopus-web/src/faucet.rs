@@ -0,0 +1,122 @@
+//! Self-service play funds for test deployments. Only compiled with the `faucet` feature.
+//!
+//! Integration environments claim funds for their sandbox accounts through `POST /faucet` instead of asking an operator
+//! for a deposit. Claims are capped, and every account has to wait for a cooldown between two of them.
+use octopus_common::{errors::ApplicationError, tx::Tx};
+use std::{collections::HashMap, sync::Mutex};
+
+use crate::{config::FaucetConfig, trading_platform::TradingPlatform};
+
+/// Hands out capped amounts of play funds to sandbox accounts
+#[derive(Debug)]
+pub struct Faucet {
+    max_amount: u64,
+    cooldown_millis: u64,
+    /// When each account of each tenant last claimed funds (ms)
+    last_claims: Mutex<HashMap<(String, String), u64>>,
+}
+
+impl Faucet {
+    pub fn new(config: &FaucetConfig) -> Self {
+        Faucet {
+            max_amount: config.max_amount,
+            cooldown_millis: config.cooldown_secs * 1000,
+            last_claims: Mutex::default(),
+        }
+    }
+
+    /// Credits `amount` of play funds to the `signer` sandbox account of `tenant`, opening it if necessary
+    /// # Errors
+    /// - The amount exceeds the maximum claim
+    /// - The account claimed funds less than a cooldown ago
+    /// - The account holds real funds, see [`TradingPlatform::faucet`]
+    pub fn claim(
+        &self,
+        trading_platform: &mut TradingPlatform,
+        tenant: &str,
+        signer: &str,
+        amount: u64,
+        now: u64,
+    ) -> Result<Tx, ApplicationError> {
+        if amount > self.max_amount {
+            return Err(ApplicationError::FaucetLimitExceeded(
+                amount,
+                self.max_amount,
+            ));
+        }
+        let key = (tenant.to_string(), signer.to_string());
+        let mut last_claims = self.last_claims.lock().unwrap();
+        if let Some(last_claim) = last_claims.get(&key) {
+            let available_at = last_claim + self.cooldown_millis;
+            if available_at > now {
+                let secs = (available_at - now).div_ceil(1000);
+                return Err(ApplicationError::FaucetCoolingDown(
+                    signer.to_string(),
+                    secs,
+                ));
+            }
+        }
+        let tx = trading_platform.faucet(signer, amount)?;
+        last_claims.insert(key, now);
+        Ok(tx)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    // reduce the warnings for naming tests
+    #![allow(non_snake_case)]
+
+    use super::*;
+
+    fn faucet() -> Faucet {
+        Faucet::new(&FaucetConfig {
+            max_amount: 100,
+            cooldown_secs: 60,
+        })
+    }
+
+    #[test]
+    fn test_Faucet_claim_waits_for_the_cooldown() {
+        let faucet = faucet();
+        let mut trading_platform = TradingPlatform::new();
+        faucet
+            .claim(&mut trading_platform, "default", "ALICE", 100, 1_000)
+            .unwrap();
+        assert_eq!(
+            faucet.claim(&mut trading_platform, "default", "ALICE", 10, 30_500),
+            Err(ApplicationError::FaucetCoolingDown("ALICE".to_string(), 31))
+        );
+        // Cooldowns are per account and tenant
+        faucet
+            .claim(&mut trading_platform, "default", "BOB", 10, 30_500)
+            .unwrap();
+        faucet
+            .claim(&mut trading_platform, "acme", "ALICE", 10, 30_500)
+            .unwrap();
+
+        faucet
+            .claim(&mut trading_platform, "default", "ALICE", 10, 61_000)
+            .unwrap();
+        assert_eq!(trading_platform.accounts.balance_of("ALICE"), Ok(&120));
+    }
+
+    #[test]
+    fn test_Faucet_claim_rejects_large_amounts_and_real_accounts() {
+        let faucet = faucet();
+        let mut trading_platform = TradingPlatform::new();
+        assert_eq!(
+            faucet.claim(&mut trading_platform, "default", "ALICE", 101, 0),
+            Err(ApplicationError::FaucetLimitExceeded(101, 100))
+        );
+        trading_platform.deposit("BOB", 10).unwrap();
+        assert_eq!(
+            faucet.claim(&mut trading_platform, "default", "BOB", 10, 0),
+            Err(ApplicationError::SandboxViolation("BOB".to_string()))
+        );
+        // Failed claims don't start a cooldown
+        faucet
+            .claim(&mut trading_platform, "default", "ALICE", 100, 0)
+            .unwrap();
+    }
+}
@@ -0,0 +1,42 @@
+use octopus_common::types::{anonymize, FeeCharge};
+use std::collections::BTreeSet;
+
+/// The account collecting all fees
+pub const FEE_ACCOUNT: &str = "octopus-fees";
+
+/// Every fee charged, in order
+#[derive(Debug, Default)]
+pub struct FeeLedger {
+    charges: Vec<FeeCharge>,
+}
+
+impl FeeLedger {
+    pub fn new() -> Self {
+        FeeLedger::default()
+    }
+
+    pub fn record(&mut self, charge: FeeCharge) {
+        self.charges.push(charge);
+    }
+
+    /// Replaces `signer` with `token` in the charges
+    pub fn anonymize(&mut self, signer: &str, token: &str) {
+        for charge in self.charges.iter_mut() {
+            anonymize(&mut charge.account, signer, token);
+        }
+    }
+
+    /// Charges with a timestamp in `from..to`
+    pub fn charges_between(&self, from: u64, to: u64) -> impl Iterator<Item = &FeeCharge> {
+        self.charges
+            .iter()
+            .filter(move |c| (from..to).contains(&c.timestamp))
+    }
+
+    /// Accounts charged in `from..to`
+    pub fn accounts_between(&self, from: u64, to: u64) -> BTreeSet<String> {
+        self.charges_between(from, to)
+            .map(|c| c.account.clone())
+            .collect()
+    }
+}
@@ -0,0 +1,133 @@
+use hmac::{Hmac, Mac};
+use octopus_common::{errors::ApplicationError, types::DepositNotification};
+use rand::RngCore;
+use sha2::Sha256;
+
+/// The header carrying the hex-encoded HMAC-SHA256 of the request body
+pub const GATEWAY_SIGNATURE_HEADER: &str = "x-gateway-signature";
+
+/// The only currency accounts are held in
+pub const SETTLEMENT_CURRENCY: &str = "USD";
+
+/// The secret shared with the (simulated) payment gateway to sign its notifications
+#[derive(Debug)]
+pub struct GatewayKey {
+    secret: Vec<u8>,
+}
+
+impl GatewayKey {
+    pub fn new(secret: &str) -> Self {
+        GatewayKey {
+            secret: secret.as_bytes().to_vec(),
+        }
+    }
+
+    /// Uses the configured secret or generates a random one. The generated secret is returned for printing.
+    pub fn from_config(secret: Option<&str>) -> (Self, Option<String>) {
+        match secret {
+            Some(secret) if !secret.is_empty() => (GatewayKey::new(secret), None),
+            _ => {
+                let mut bytes = [0; 32];
+                rand::thread_rng().fill_bytes(&mut bytes);
+                let secret = hex::encode(bytes);
+                (GatewayKey::new(&secret), Some(secret))
+            }
+        }
+    }
+
+    fn mac(&self) -> Hmac<Sha256> {
+        Hmac::<Sha256>::new_from_slice(&self.secret).expect("HMAC accepts keys of any length")
+    }
+
+    /// Checks the signature of a notification body in constant time
+    pub fn verify(&self, body: &[u8], signature: Option<&str>) -> Result<(), ApplicationError> {
+        let invalid = || ApplicationError::Unauthorized("invalid gateway signature".to_string());
+        let signature = signature
+            .and_then(|signature| hex::decode(signature).ok())
+            .ok_or_else(invalid)?;
+        let mut mac = self.mac();
+        mac.update(body);
+        mac.verify_slice(&signature).map_err(|_| invalid())
+    }
+}
+
+/// Parses and validates a signed notification body
+/// # Errors
+/// The body isn't a valid notification, the amount is zero, or the currency isn't the [`SETTLEMENT_CURRENCY`]
+pub fn parse_notification(body: &[u8]) -> Result<DepositNotification, ApplicationError> {
+    let notification: DepositNotification = serde_json::from_slice(body)
+        .map_err(|e| ApplicationError::InvalidDeposit(e.to_string()))?;
+    if notification.id.is_empty() {
+        return Err(ApplicationError::InvalidDeposit(
+            "missing notification id".to_string(),
+        ));
+    }
+    if notification.amount == 0 {
+        return Err(ApplicationError::InvalidDeposit(format!(
+            "{}: amount must be positive",
+            notification.id
+        )));
+    }
+    if notification.currency != SETTLEMENT_CURRENCY {
+        return Err(ApplicationError::InvalidDeposit(format!(
+            "{}: unsupported currency {}",
+            notification.id, notification.currency
+        )));
+    }
+    Ok(notification)
+}
+
+#[cfg(test)]
+mod tests {
+    // reduce the warnings for naming tests
+    #![allow(non_snake_case)]
+
+    use super::*;
+
+    /// Signs a body the way the gateway does
+    fn sign(key: &GatewayKey, body: &[u8]) -> String {
+        let mut mac = key.mac();
+        mac.update(body);
+        hex::encode(mac.finalize().into_bytes())
+    }
+
+    #[test]
+    fn test_GatewayKey_verify_checks_signature() {
+        let key = GatewayKey::new("shared");
+        let body = br#"{"id":"n1","account":"ALICE","amount":100,"currency":"USD"}"#;
+        let signature = sign(&key, body);
+
+        assert_eq!(key.verify(body, Some(&signature)), Ok(()));
+        assert!(key.verify(b"{}", Some(&signature)).is_err());
+        assert!(GatewayKey::new("other")
+            .verify(body, Some(&signature))
+            .is_err());
+        assert!(key.verify(body, Some("not hex")).is_err());
+        assert!(key.verify(body, None).is_err());
+    }
+
+    #[test]
+    fn test_parse_notification_validates_amount_and_currency() {
+        assert_eq!(
+            parse_notification(br#"{"id":"n1","account":"ALICE","amount":100,"currency":"USD"}"#),
+            Ok(DepositNotification {
+                id: "n1".to_string(),
+                account: "ALICE".to_string(),
+                amount: 100,
+                currency: "USD".to_string(),
+            })
+        );
+        assert!(parse_notification(
+            br#"{"id":"n1","account":"ALICE","amount":0,"currency":"USD"}"#
+        )
+        .is_err());
+        assert!(parse_notification(
+            br#"{"id":"n1","account":"ALICE","amount":1,"currency":"EUR"}"#
+        )
+        .is_err());
+        assert!(parse_notification(
+            br#"{"id":"n1","account":"ALICE","amount":-1,"currency":"USD"}"#
+        )
+        .is_err());
+    }
+}
@@ -0,0 +1,509 @@
+use std::sync::{Arc, Mutex};
+
+use async_graphql::{
+    Context, Enum, ErrorExtensions, InputObject, Object, Schema, SimpleObject, Subscription,
+};
+use futures_util::Stream;
+use octopus_common::{
+    errors::ApplicationError,
+    types::{self, ApiKeyScope, BookQuery, OrderType, Role, TimeInForce},
+};
+use tokio::sync::broadcast::error::RecvError;
+
+use crate::{
+    auth::Credential, core::PointInTime, ingest::OrderQueue, rejection::status_of,
+    startup::Startup, trading_platform::TradingPlatform,
+};
+
+/// The number of trades returned when no limit is given
+pub const DEFAULT_TRADES_LIMIT: usize = 100;
+
+/// The GraphQL schema. Requests need the tenant's `Arc<Mutex<TradingPlatform>>` as data, queries on accounts and
+/// trades a [`Credential`], and mutations the tenant's `Arc<OrderQueue>`.
+pub type OctopusSchema = Schema<Query, Mutation, Subscription>;
+
+pub fn schema() -> OctopusSchema {
+    Schema::new(Query, Mutation, Subscription)
+}
+
+/// Turns an [`ApplicationError`] into a GraphQL error with the REST API's status code in the `code` extension
+fn error(e: ApplicationError) -> async_graphql::Error {
+    async_graphql::Error::new(format!("{:?}", e))
+        .extend_with(|_, extensions| extensions.set("code", status_of(&e).as_u16()))
+}
+
+fn authorize(ctx: &Context<'_>, signer: &str, scope: ApiKeyScope) -> async_graphql::Result<()> {
+    ctx.data_opt::<Credential>()
+        .ok_or(ApplicationError::Unauthorized(
+            "missing API key".to_string(),
+        ))
+        .and_then(|credential| credential.authorize(signer, scope))
+        .map_err(error)
+}
+
+/// Whether the credential of the request may see who traded, which takes an operator like `GET /trades`
+fn sees_counterparties(ctx: &Context<'_>) -> bool {
+    ctx.data_opt::<Credential>()
+        .is_some_and(|credential| credential.role >= Role::Operator)
+}
+
+fn trading_platform<'a>(
+    ctx: &Context<'a>,
+) -> async_graphql::Result<&'a Arc<Mutex<TradingPlatform>>> {
+    ctx.data::<Arc<Mutex<TradingPlatform>>>()
+}
+
+#[derive(Enum, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OrderSide {
+    Buy,
+    Sell,
+}
+
+impl From<types::Side> for OrderSide {
+    fn from(side: types::Side) -> Self {
+        match side {
+            types::Side::Buy => OrderSide::Buy,
+            types::Side::Sell => OrderSide::Sell,
+        }
+    }
+}
+
+impl From<OrderSide> for types::Side {
+    fn from(side: OrderSide) -> Self {
+        match side {
+            OrderSide::Buy => types::Side::Buy,
+            OrderSide::Sell => types::Side::Sell,
+        }
+    }
+}
+
+/// An order resting in the book, or the maker side of a match
+#[derive(SimpleObject, Debug, Clone)]
+pub struct BookOrder {
+    pub ordinal: u64,
+    pub order_id: u64,
+    pub price: u64,
+    pub amount: u64,
+    pub remaining: u64,
+    pub side: OrderSide,
+    pub signer: String,
+}
+
+impl From<types::PartialOrder> for BookOrder {
+    fn from(order: types::PartialOrder) -> Self {
+        BookOrder {
+            ordinal: order.ordinal,
+            order_id: order.order_id,
+            price: order.price,
+            amount: order.amount,
+            remaining: order.remaining,
+            side: order.side.into(),
+            signer: order.signer,
+        }
+    }
+}
+
+#[derive(Enum, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OrderStatus {
+    Filled,
+    PartiallyFilled,
+    Resting,
+    Cancelled,
+}
+
+impl From<types::ReceiptStatus> for OrderStatus {
+    fn from(status: types::ReceiptStatus) -> Self {
+        match status {
+            types::ReceiptStatus::Filled => OrderStatus::Filled,
+            types::ReceiptStatus::PartiallyFilled => OrderStatus::PartiallyFilled,
+            types::ReceiptStatus::Resting => OrderStatus::Resting,
+            types::ReceiptStatus::Cancelled => OrderStatus::Cancelled,
+        }
+    }
+}
+
+#[derive(SimpleObject, Debug, Clone)]
+pub struct OrderReceipt {
+    pub ordinal: u64,
+    pub order_id: u64,
+    pub filled: u64,
+    pub cancelled: u64,
+    pub remaining: u64,
+    pub total_notional: u64,
+    /// Unset if nothing was filled
+    pub average_price: Option<u64>,
+    pub status: OrderStatus,
+    pub matches: Vec<BookOrder>,
+}
+
+impl From<types::Receipt> for OrderReceipt {
+    fn from(receipt: types::Receipt) -> Self {
+        OrderReceipt {
+            ordinal: receipt.ordinal,
+            order_id: receipt.order_id,
+            filled: receipt.filled,
+            cancelled: receipt.cancelled,
+            remaining: receipt.remaining,
+            total_notional: receipt.total_notional,
+            average_price: receipt.average_price,
+            status: receipt.status.into(),
+            matches: receipt.matches.into_iter().map(BookOrder::from).collect(),
+        }
+    }
+}
+
+#[derive(SimpleObject, Debug, Clone)]
+pub struct Trade {
+    pub id: u64,
+    pub ordinal: u64,
+    pub maker_ordinal: u64,
+    /// Unix timestamp (ms)
+    pub timestamp: u64,
+    pub market: String,
+    pub price: u64,
+    pub amount: u64,
+    /// Only shown to operators
+    pub taker: Option<String>,
+    /// Only shown to operators
+    pub maker: Option<String>,
+    pub taker_side: OrderSide,
+    /// The limit price of the taker order, unset for market orders
+    pub limit_price: Option<u64>,
+    pub taker_fee: u64,
+    pub maker_fee: u64,
+    /// When the trade was busted, Unix timestamp (ms)
+    pub busted: Option<u64>,
+}
+
+impl Trade {
+    /// The `trade`, without who traded unless the caller may see the `counterparties`
+    fn seen(trade: types::Trade, counterparties: bool) -> Self {
+        Trade {
+            id: trade.id,
+            ordinal: trade.ordinal,
+            maker_ordinal: trade.maker_ordinal,
+            timestamp: trade.timestamp,
+            market: trade.market,
+            price: trade.price,
+            amount: trade.amount,
+            taker: counterparties.then_some(trade.taker),
+            maker: counterparties.then_some(trade.maker),
+            taker_side: trade.taker_side.into(),
+            limit_price: trade.limit_price,
+            taker_fee: trade.taker_fee,
+            maker_fee: trade.maker_fee,
+            busted: trade.busted,
+        }
+    }
+}
+
+#[derive(SimpleObject, Debug, Clone)]
+pub struct Account {
+    pub signer: String,
+    pub balance: u64,
+    pub open_orders: Vec<BookOrder>,
+}
+
+#[derive(InputObject, Debug, Clone)]
+pub struct OrderInput {
+    pub price: u64,
+    pub amount: u64,
+    pub side: OrderSide,
+    pub signer: String,
+    /// Required once the signer registered a public key
+    pub signature: Option<SignatureInput>,
+    /// Makes it a stop-limit order that waits for a trade at this price
+    pub trigger_price: Option<u64>,
+}
+
+/// See [`types::OrderSignature`]
+#[derive(InputObject, Debug, Clone)]
+pub struct SignatureInput {
+    pub nonce: u64,
+    /// Unix timestamp in milliseconds
+    pub expires_at: u64,
+    /// Hex-encoded ed25519 signature
+    pub value: String,
+}
+
+impl From<SignatureInput> for types::OrderSignature {
+    fn from(signature: SignatureInput) -> Self {
+        types::OrderSignature {
+            nonce: signature.nonce,
+            expires_at: signature.expires_at,
+            value: signature.value,
+        }
+    }
+}
+
+fn open_orders(trading_platform: &TradingPlatform, signer: &str) -> Vec<BookOrder> {
+    trading_platform
+        .orderbook()
+        .into_iter()
+        .filter(|order| order.signer == signer)
+        .map(BookOrder::from)
+        .collect()
+}
+
+pub struct Query;
+
+#[Object]
+impl Query {
+    /// The balance and open orders of an account
+    async fn account(&self, ctx: &Context<'_>, signer: String) -> async_graphql::Result<Account> {
+        authorize(ctx, &signer, ApiKeyScope::Read)?;
+        let mut ledger_lock = trading_platform(ctx)?.lock().unwrap();
+        let balance = *ledger_lock.balance_of(&signer).map_err(error)?;
+        let open_orders = open_orders(&ledger_lock, &signer);
+        Ok(Account {
+            signer,
+            balance,
+            open_orders,
+        })
+    }
+
+    /// The open orders of an account
+    async fn orders(
+        &self,
+        ctx: &Context<'_>,
+        signer: String,
+    ) -> async_graphql::Result<Vec<BookOrder>> {
+        authorize(ctx, &signer, ApiKeyScope::Read)?;
+        let ledger_lock = trading_platform(ctx)?.lock().unwrap();
+        Ok(open_orders(&ledger_lock, &signer))
+    }
+
+    /// The most recent trades, newest first. Only operators see who traded.
+    async fn trades(
+        &self,
+        ctx: &Context<'_>,
+        limit: Option<usize>,
+    ) -> async_graphql::Result<Vec<Trade>> {
+        let counterparties = sees_counterparties(ctx);
+        let ledger_lock = trading_platform(ctx)?.lock().unwrap();
+        Ok(ledger_lock
+            .trades
+            .iter()
+            .rev()
+            .take(limit.unwrap_or(DEFAULT_TRADES_LIMIT))
+            .map(|trade| Trade::seen(trade.clone(), counterparties))
+            .collect())
+    }
+
+    /// The order book, or the book at a past ordinal or RFC 3339 timestamp
+    async fn orderbook(
+        &self,
+        ctx: &Context<'_>,
+        at: Option<String>,
+    ) -> async_graphql::Result<Vec<BookOrder>> {
+        let at = at
+            .as_deref()
+            .map(PointInTime::parse)
+            .transpose()
+            .map_err(error)?;
+        let ledger_lock = trading_platform(ctx)?.lock().unwrap();
+        let orders = match at {
+            Some(at) => ledger_lock.orderbook_at(at, &BookQuery::default()),
+            None => ledger_lock.orderbook(),
+        };
+        Ok(orders.into_iter().map(BookOrder::from).collect())
+    }
+}
+
+pub struct Mutation;
+
+#[Object]
+impl Mutation {
+    /// Places an order through the order queue, like `POST /order`
+    async fn place_order(
+        &self,
+        ctx: &Context<'_>,
+        order: OrderInput,
+    ) -> async_graphql::Result<OrderReceipt> {
+        authorize(ctx, &order.signer, ApiKeyScope::Trade)?;
+        if let Some(startup) = ctx.data_opt::<Arc<Startup>>() {
+            startup.ensure_serving().map_err(error)?;
+        }
+        let order = types::Order {
+            price: order.price,
+            amount: order.amount,
+            side: order.side.into(),
+            signer: order.signer,
+            client_order_id: None,
+            signature: order.signature.map(Into::into),
+            order_type: OrderType::Limit,
+            time_in_force: TimeInForce::Gtc,
+            trigger_price: order.trigger_price,
+            market: types::DEFAULT_MARKET.to_string(),
+        };
+        ctx.data::<Arc<OrderQueue>>()?
+            .submit(order)
+            .await
+            .map(OrderReceipt::from)
+            .map_err(error)
+    }
+}
+
+pub struct Subscription;
+
+#[Subscription]
+impl Subscription {
+    /// Every trade as it happens. Only operators see who traded.
+    async fn trades(&self, ctx: &Context<'_>) -> async_graphql::Result<impl Stream<Item = Trade>> {
+        let counterparties = sees_counterparties(ctx);
+        let receiver = trading_platform(ctx)?
+            .lock()
+            .unwrap()
+            .trade_feed
+            .subscribe();
+        Ok(futures_util::stream::unfold(
+            receiver,
+            move |mut receiver| async move {
+                loop {
+                    match receiver.recv().await {
+                        Ok(trade) => return Some((Trade::seen(trade, counterparties), receiver)),
+                        Err(RecvError::Lagged(missed)) => {
+                            log::warn!(
+                                "A trade subscriber fell behind and missed {} trades",
+                                missed
+                            )
+                        }
+                        Err(RecvError::Closed) => return None,
+                    }
+                }
+            },
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    // reduce the warnings for naming tests
+    #![allow(non_snake_case)]
+
+    use super::*;
+    use async_graphql::Request;
+    use octopus_common::types::Role;
+    use serde_json::json;
+
+    fn credential(signer: &str) -> Credential {
+        Credential {
+            role: Role::Trader,
+            signer: Some(signer.to_string()),
+            scopes: vec![ApiKeyScope::Read, ApiKeyScope::Trade],
+        }
+    }
+
+    fn platform() -> Arc<Mutex<TradingPlatform>> {
+        let trading_platform = Arc::new(Mutex::new(TradingPlatform::new()));
+        trading_platform
+            .lock()
+            .unwrap()
+            .deposit("ALICE", 100)
+            .unwrap();
+        trading_platform
+    }
+
+    #[tokio::test]
+    async fn test_schema_place_order_and_query_account() {
+        let trading_platform = platform();
+        let order_queue = Arc::new(OrderQueue::start(trading_platform.clone(), 8));
+        let schema = schema();
+
+        let response = schema
+            .execute(
+                Request::new(
+                    r#"mutation { placeOrder(order: {price: 10, amount: 2, side: SELL, signer: "ALICE"}) { ordinal } }"#,
+                )
+                .data(credential("ALICE"))
+                .data(trading_platform.clone())
+                .data(order_queue),
+            )
+            .await;
+        assert!(response.errors.is_empty(), "{:?}", response.errors);
+
+        let response = schema
+            .execute(
+                Request::new(
+                    r#"{ account(signer: "ALICE") { balance openOrders { price side } } orderbook { ordinal } }"#,
+                )
+                .data(credential("ALICE"))
+                .data(trading_platform),
+            )
+            .await;
+        assert_eq!(
+            response.data.into_json().unwrap(),
+            json!({
+                "account": {"balance": 100, "openOrders": [{"price": 10, "side": "SELL"}]},
+                "orderbook": [{"ordinal": 1}]
+            })
+        );
+    }
+
+    #[tokio::test]
+    async fn test_schema_trades_show_who_traded_to_operators_only() {
+        let trading_platform = platform();
+        {
+            let mut ledger_lock = trading_platform.lock().unwrap();
+            ledger_lock.deposit("BOB", 100).unwrap();
+            let order = |side, signer: &str| types::Order {
+                price: 10,
+                amount: 1,
+                side,
+                signer: signer.to_string(),
+                client_order_id: None,
+                signature: None,
+                order_type: OrderType::Limit,
+                time_in_force: TimeInForce::Gtc,
+                trigger_price: None,
+                market: types::DEFAULT_MARKET.to_string(),
+            };
+            ledger_lock
+                .order(order(types::Side::Sell, "ALICE"))
+                .unwrap();
+            ledger_lock.order(order(types::Side::Buy, "BOB")).unwrap();
+        }
+        let schema = schema();
+        let trades = |credential| {
+            schema.execute(
+                Request::new(r#"{ trades { price taker maker } }"#)
+                    .data(credential)
+                    .data(trading_platform.clone()),
+            )
+        };
+
+        assert_eq!(
+            trades(credential("ALICE")).await.data.into_json().unwrap(),
+            json!({"trades": [{"price": 10, "taker": null, "maker": null}]})
+        );
+        let operator = Credential {
+            role: Role::Operator,
+            signer: None,
+            scopes: vec![ApiKeyScope::Read],
+        };
+        assert_eq!(
+            trades(operator).await.data.into_json().unwrap(),
+            json!({"trades": [{"price": 10, "taker": "BOB", "maker": "ALICE"}]})
+        );
+    }
+
+    #[tokio::test]
+    async fn test_schema_account_requires_own_credential() {
+        let response = schema()
+            .execute(
+                Request::new(r#"{ account(signer: "ALICE") { balance } }"#)
+                    .data(credential("BOB"))
+                    .data(platform()),
+            )
+            .await;
+        assert_eq!(response.errors.len(), 1);
+        assert_eq!(
+            response.errors[0]
+                .extensions
+                .as_ref()
+                .and_then(|extensions| extensions.get("code"))
+                .cloned(),
+            Some(async_graphql::Value::from(403))
+        );
+    }
+}
@@ -0,0 +1,261 @@
+use octopus_common::{
+    errors::ApplicationError,
+    types::{Order, Receipt},
+};
+use std::{
+    sync::{
+        atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering},
+        mpsc, Arc, Mutex,
+    },
+    thread,
+};
+use tokio::sync::oneshot;
+
+use crate::deadline::Deadline;
+use crate::trading_platform::TradingPlatform;
+
+/// Orders waiting for or in processing before new submissions are rejected
+pub const DEFAULT_ORDER_QUEUE_CAPACITY: usize = 1024;
+
+/// Seconds a client should wait before resubmitting a rejected order
+pub const RETRY_AFTER_SECS: u64 = 1;
+
+/// The receipt of a queued order, or why it wasn't placed
+type Reply = oneshot::Receiver<Result<Receipt, ApplicationError>>;
+
+/// An order waiting in the queue
+struct Job {
+    order: Order,
+    /// Skips the price collar check
+    override_collar: bool,
+    /// Set by the worker when it starts processing the order, or by the client when it abandons the order first
+    claimed: Arc<AtomicBool>,
+    reply: oneshot::Sender<Result<Receipt, ApplicationError>>,
+}
+
+/// A bounded queue in front of [`TradingPlatform::order`]. A single worker thread processes the orders in sequence,
+/// so requests wait in the queue instead of piling up on the platform's mutex.
+#[derive(Debug)]
+pub struct OrderQueue {
+    sender: Mutex<mpsc::Sender<Job>>,
+    depth: Arc<AtomicUsize>,
+    capacity: usize,
+    rejected: AtomicU64,
+}
+
+impl OrderQueue {
+    /// Creates the queue and starts its worker. The worker stops when the queue is dropped.
+    pub fn start(trading_platform: Arc<Mutex<TradingPlatform>>, capacity: usize) -> Self {
+        let (sender, receiver) = mpsc::channel::<Job>();
+        let depth = Arc::new(AtomicUsize::new(0));
+        let worker_depth = depth.clone();
+        thread::spawn(move || {
+            for job in receiver {
+                if job.claimed.swap(true, Ordering::SeqCst) {
+                    // The client gave up on the order before its turn
+                    worker_depth.fetch_sub(1, Ordering::SeqCst);
+                    continue;
+                }
+                let mut ledger_lock = trading_platform.lock().unwrap();
+                let result = ledger_lock.place_order(job.order, job.override_collar);
+                #[cfg(feature = "chaos")]
+                crate::chaos::hold_lock();
+                drop(ledger_lock);
+                worker_depth.fetch_sub(1, Ordering::SeqCst);
+                // The client may have gone away already
+                let _ = job.reply.send(result);
+            }
+        });
+        OrderQueue {
+            sender: Mutex::new(sender),
+            depth,
+            capacity,
+            rejected: AtomicU64::new(0),
+        }
+    }
+
+    /// Adds an order to the queue without waiting for it to be processed. `override_collar` skips the price collar
+    /// check. Returns where its receipt goes and the flag that claims it.
+    /// # Errors
+    /// The queue is full
+    fn enqueue(
+        &self,
+        order: Order,
+        override_collar: bool,
+    ) -> Result<(Reply, Arc<AtomicBool>), ApplicationError> {
+        let reserved = self
+            .depth
+            .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |depth| {
+                (depth < self.capacity).then_some(depth + 1)
+            });
+        if reserved.is_err() {
+            self.rejected.fetch_add(1, Ordering::Relaxed);
+            return Err(ApplicationError::Overloaded(RETRY_AFTER_SECS));
+        }
+
+        let (reply, receiver) = oneshot::channel();
+        let claimed = Arc::new(AtomicBool::new(false));
+        let job = Job {
+            order,
+            override_collar,
+            claimed: claimed.clone(),
+            reply,
+        };
+        if self.sender.lock().unwrap().send(job).is_err() {
+            self.depth.fetch_sub(1, Ordering::SeqCst);
+            return Err(ApplicationError::Overloaded(RETRY_AFTER_SECS));
+        }
+        Ok((receiver, claimed))
+    }
+
+    /// Queues an order and waits for its [`Receipt`]
+    pub async fn submit(&self, order: Order) -> Result<Receipt, ApplicationError> {
+        self.submit_with(order, false, None).await
+    }
+
+    /// Like [`OrderQueue::submit`], but `override_collar` skips the price collar check. An order still waiting in the
+    /// queue at the `deadline` is dropped without being placed, one the worker already started on is waited for.
+    pub async fn submit_with(
+        &self,
+        order: Order,
+        override_collar: bool,
+        deadline: Option<Deadline>,
+    ) -> Result<Receipt, ApplicationError> {
+        let (mut receiver, claimed) = self.enqueue(order, override_collar)?;
+        let reply = match deadline {
+            Some(deadline) => match tokio::time::timeout(deadline.remaining(), &mut receiver).await
+            {
+                Ok(reply) => reply,
+                Err(_) if !claimed.swap(true, Ordering::SeqCst) => {
+                    return Err(deadline.exceeded("the order was still queued and wasn't placed"))
+                }
+                Err(_) => receiver.await,
+            },
+            None => receiver.await,
+        };
+        reply.unwrap_or(Err(ApplicationError::Overloaded(RETRY_AFTER_SECS)))
+    }
+
+    /// Orders currently waiting or in processing
+    pub fn depth(&self) -> usize {
+        self.depth.load(Ordering::SeqCst)
+    }
+
+    /// The maximum depth
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    /// Orders rejected because the queue was full
+    pub fn rejected(&self) -> u64 {
+        self.rejected.load(Ordering::Relaxed)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    // reduce the warnings for naming tests
+    #![allow(non_snake_case)]
+
+    use super::*;
+    use octopus_common::types::{OrderType, Side, TimeInForce, DEFAULT_MARKET};
+
+    fn order(signer: &str) -> Order {
+        Order {
+            price: 10,
+            amount: 1,
+            side: Side::Sell,
+            signer: signer.to_string(),
+            client_order_id: None,
+            signature: None,
+            order_type: OrderType::Limit,
+            time_in_force: TimeInForce::Gtc,
+            trigger_price: None,
+            market: DEFAULT_MARKET.to_string(),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_OrderQueue_submit_processes_orders() {
+        let trading_platform = Arc::new(Mutex::new(TradingPlatform::new()));
+        trading_platform
+            .lock()
+            .unwrap()
+            .deposit("ALICE", 100)
+            .unwrap();
+        let queue = OrderQueue::start(trading_platform.clone(), 2);
+
+        let receipt = queue.submit(order("ALICE")).await.unwrap();
+        assert_eq!(receipt.ordinal, 1);
+        assert_eq!(
+            queue.submit(order("BOB")).await,
+            Err(ApplicationError::AccountNotFound("BOB".to_string()))
+        );
+        assert_eq!(queue.depth(), 0);
+        assert_eq!(trading_platform.lock().unwrap().orderbook().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_OrderQueue_enqueue_rejects_when_full() {
+        let trading_platform = Arc::new(Mutex::new(TradingPlatform::new()));
+        trading_platform
+            .lock()
+            .unwrap()
+            .deposit("ALICE", 100)
+            .unwrap();
+        let queue = OrderQueue::start(trading_platform.clone(), 2);
+
+        // Block the worker so the orders stay in the queue
+        let ledger_lock = trading_platform.lock().unwrap();
+        let (first, _) = queue.enqueue(order("ALICE"), false).unwrap();
+        let (second, _) = queue.enqueue(order("ALICE"), false).unwrap();
+        assert_eq!(
+            queue.enqueue(order("ALICE"), false).err(),
+            Some(ApplicationError::Overloaded(RETRY_AFTER_SECS))
+        );
+        assert_eq!(queue.depth(), 2);
+        assert_eq!(queue.rejected(), 1);
+        drop(ledger_lock);
+
+        assert!(first.await.unwrap().is_ok());
+        assert!(second.await.unwrap().is_ok());
+        assert_eq!(queue.depth(), 0);
+    }
+
+    #[tokio::test]
+    async fn test_OrderQueue_submit_with_drops_orders_still_queued_at_the_deadline() {
+        let trading_platform = Arc::new(Mutex::new(TradingPlatform::new()));
+        trading_platform
+            .lock()
+            .unwrap()
+            .deposit("ALICE", 100)
+            .unwrap();
+        let queue = OrderQueue::start(trading_platform.clone(), 2);
+
+        // Hold the lock on another thread, the worker blocks on the first order
+        let (locked, wait_locked) = mpsc::channel();
+        let blocker = trading_platform.clone();
+        let holder = thread::spawn(move || {
+            let _ledger_lock = blocker.lock().unwrap();
+            locked.send(()).unwrap();
+            thread::sleep(std::time::Duration::from_millis(200));
+        });
+        wait_locked.recv().unwrap();
+        let (first, _) = queue.enqueue(order("ALICE"), false).unwrap();
+
+        let deadline = Deadline::after(std::time::Duration::from_millis(20));
+        assert_eq!(
+            queue
+                .submit_with(order("ALICE"), false, Some(deadline))
+                .await,
+            Err(ApplicationError::DeadlineExceeded(
+                20,
+                "the order was still queued and wasn't placed".to_string()
+            ))
+        );
+
+        holder.join().unwrap();
+        assert!(first.await.unwrap().is_ok());
+        assert_eq!(trading_platform.lock().unwrap().orderbook().len(), 1);
+    }
+}
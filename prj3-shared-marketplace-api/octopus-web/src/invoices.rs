@@ -0,0 +1,229 @@
+use chrono::{DateTime, Datelike, NaiveDate};
+use octopus_common::{errors::ApplicationError, types::Invoice};
+use std::collections::BTreeMap;
+
+use crate::fees::FeeLedger;
+
+/// The calendar month (UTC) of a unix timestamp (ms) as `YYYY-MM`
+pub fn month_of(timestamp: u64) -> String {
+    let date = DateTime::from_timestamp_millis(timestamp as i64).unwrap_or_default();
+    format!("{:04}-{:02}", date.year(), date.month())
+}
+
+/// The month before the month of `timestamp`
+pub fn previous_month_of(timestamp: u64) -> String {
+    let (start, _) = month_bounds(&month_of(timestamp)).unwrap_or_default();
+    month_of(start.saturating_sub(1))
+}
+
+/// The first millisecond of `month` and of the month after
+pub fn month_bounds(month: &str) -> Result<(u64, u64), ApplicationError> {
+    let invalid = || ApplicationError::InvalidMonth(month.to_string());
+    let start =
+        NaiveDate::parse_from_str(&format!("{}-01", month), "%Y-%m-%d").map_err(|_| invalid())?;
+    let end = start
+        .checked_add_months(chrono::Months::new(1))
+        .ok_or_else(invalid)?;
+    let millis = |date: NaiveDate| {
+        date.and_hms_opt(0, 0, 0)
+            .map(|d| d.and_utc().timestamp_millis())
+            .filter(|ms| *ms >= 0)
+            .map(|ms| ms as u64)
+            .ok_or_else(invalid)
+    };
+    Ok((millis(start)?, millis(end)?))
+}
+
+/// Renders an invoice as CSV with one line per charge
+pub fn to_csv(invoice: &Invoice) -> String {
+    let mut csv = String::from("invoice,account,month,timestamp,kind,amount\n");
+    for charge in invoice.charges.iter() {
+        csv.push_str(&format!(
+            "{},{},{},{},{:?},{}\n",
+            invoice.id,
+            invoice.account,
+            invoice.month,
+            charge.timestamp,
+            charge.kind,
+            charge.amount
+        ));
+    }
+    csv.push_str(&format!(
+        "{},{},{},,Total,{}\n",
+        invoice.id, invoice.account, invoice.month, invoice.total
+    ));
+    csv
+}
+
+/// Monthly fee invoices of all accounts
+#[derive(Debug, Default)]
+pub struct Invoices {
+    invoices: BTreeMap<(String, String), Invoice>,
+    /// The latest month the scheduled job generated invoices for
+    last_generated: Option<String>,
+}
+
+impl Invoices {
+    pub fn new() -> Self {
+        Invoices::default()
+    }
+
+    /// Creates or replaces the invoices of `month` for every account charged that month. Regenerating a month
+    /// yields the same invoices as long as no fees were added.
+    pub fn generate(
+        &mut self,
+        month: &str,
+        ledger: &FeeLedger,
+    ) -> Result<Vec<Invoice>, ApplicationError> {
+        let (from, to) = month_bounds(month)?;
+        let invoices: Vec<Invoice> = ledger
+            .accounts_between(from, to)
+            .into_iter()
+            .map(|account| {
+                let charges: Vec<_> = ledger
+                    .charges_between(from, to)
+                    .filter(|c| c.account == account)
+                    .cloned()
+                    .collect();
+                Invoice {
+                    id: format!("INV-{}-{}", month, account),
+                    total: charges.iter().map(|c| c.amount).sum(),
+                    month: month.to_string(),
+                    account,
+                    charges,
+                }
+            })
+            .collect();
+        for invoice in invoices.iter() {
+            self.invoices.insert(
+                (invoice.account.clone(), invoice.month.clone()),
+                invoice.clone(),
+            );
+        }
+        Ok(invoices)
+    }
+
+    /// Generates the invoices of the previous month once it has ended. Returns the month when it generated invoices.
+    pub fn generate_due(&mut self, now: u64, ledger: &FeeLedger) -> Option<String> {
+        let month = previous_month_of(now);
+        if self.last_generated.as_ref() == Some(&month) {
+            return None;
+        }
+        self.generate(&month, ledger).ok()?;
+        self.last_generated = Some(month.clone());
+        Some(month)
+    }
+
+    /// Moves the invoices of `signer` to `token`, their ids name the token from then on
+    pub fn anonymize(&mut self, signer: &str, token: &str) {
+        let keys: Vec<_> = self
+            .invoices
+            .keys()
+            .filter(|(account, _)| account == signer)
+            .cloned()
+            .collect();
+        for key in keys {
+            if let Some(mut invoice) = self.invoices.remove(&key) {
+                invoice.id = format!("INV-{}-{}", invoice.month, token);
+                invoice.account = token.to_string();
+                for charge in invoice.charges.iter_mut() {
+                    charge.account = token.to_string();
+                }
+                self.invoices
+                    .insert((token.to_string(), invoice.month.clone()), invoice);
+            }
+        }
+    }
+
+    /// The invoices of `signer`, oldest first
+    pub fn of(&self, signer: &str) -> Vec<Invoice> {
+        self.invoices
+            .values()
+            .filter(|invoice| invoice.account == signer)
+            .cloned()
+            .collect()
+    }
+
+    /// The invoices of every account for `month`
+    pub fn of_month(&self, month: &str) -> Vec<Invoice> {
+        self.invoices
+            .values()
+            .filter(|invoice| invoice.month == month)
+            .cloned()
+            .collect()
+    }
+
+    /// The invoice of `signer` for `month`
+    pub fn get(&self, signer: &str, month: &str) -> Result<Invoice, ApplicationError> {
+        self.invoices
+            .get(&(signer.to_string(), month.to_string()))
+            .cloned()
+            .ok_or(ApplicationError::InvoiceNotFound(format!(
+                "{} {}",
+                signer, month
+            )))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    // reduce the warnings for naming tests
+    #![allow(non_snake_case)]
+
+    use super::*;
+    use octopus_common::types::{FeeCharge, FeeKind};
+
+    // 2026-10-16T00:00:00Z
+    const OCTOBER: u64 = 1_792_108_800_000;
+
+    fn charge(timestamp: u64, account: &str, amount: u64) -> FeeCharge {
+        FeeCharge {
+            timestamp,
+            account: account.to_string(),
+            amount,
+            kind: FeeKind::Taker,
+        }
+    }
+
+    #[test]
+    fn test_month_bounds_and_month_of() {
+        assert_eq!(month_of(OCTOBER), "2026-10");
+        assert_eq!(previous_month_of(OCTOBER), "2026-09");
+        let (from, to) = month_bounds("2026-10").unwrap();
+        assert_eq!(month_of(from), "2026-10");
+        assert_eq!(month_of(to), "2026-11");
+        assert_eq!(month_of(to - 1), "2026-10");
+        assert_eq!(
+            month_bounds("2026-13"),
+            Err(ApplicationError::InvalidMonth("2026-13".to_string()))
+        );
+    }
+
+    #[test]
+    fn test_Invoices_generate_is_idempotent() {
+        let mut ledger = FeeLedger::new();
+        let (from, to) = month_bounds("2026-10").unwrap();
+        ledger.record(charge(from, "ALICE", 3));
+        ledger.record(charge(to - 1, "ALICE", 4));
+        ledger.record(charge(to, "ALICE", 5));
+        ledger.record(charge(OCTOBER, "BOB", 1));
+
+        let mut invoices = Invoices::new();
+        let first = invoices.generate("2026-10", &ledger).unwrap();
+        let second = invoices.generate("2026-10", &ledger).unwrap();
+        assert_eq!(first, second);
+        assert_eq!(first.len(), 2);
+
+        let alice = invoices.get("ALICE", "2026-10").unwrap();
+        assert_eq!(alice.id, "INV-2026-10-ALICE");
+        assert_eq!(alice.total, 7);
+        assert_eq!(invoices.of("ALICE").len(), 1);
+        assert!(to_csv(&alice).ends_with("INV-2026-10-ALICE,ALICE,2026-10,,Total,7\n"));
+
+        assert_eq!(
+            invoices.generate_due(to, &ledger),
+            Some("2026-10".to_string())
+        );
+        assert_eq!(invoices.generate_due(to + 1, &ledger), None);
+    }
+}
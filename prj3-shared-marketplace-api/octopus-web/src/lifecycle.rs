@@ -0,0 +1,275 @@
+//! The life of an order, assembled from the book event log and the tape: accepted, triggered if it's a stop order,
+//! amended or filled in parts, and then filled, cancelled, or expired. Orders of the sandbox book aren't in the event
+//! log.
+use octopus_common::{
+    errors::ApplicationError,
+    types::{
+        OrderEvent, OrderFill, OrderId, OrderLifecycle, OrderState, OrderType, TimeInForce, Trade,
+    },
+};
+use std::collections::BTreeMap;
+
+use crate::core::{BookEvent, EventLog};
+
+/// Every step of the order with `order_id` up to now
+/// # Errors
+/// The order wasn't placed in the book of `book_log`
+pub fn lifecycle(
+    book_log: &EventLog,
+    trades: &[Trade],
+    order_id: OrderId,
+) -> Result<OrderLifecycle, ApplicationError> {
+    let (placed, order) = book_log
+        .placement(order_id)
+        .ok_or(ApplicationError::OrderNotFound(order_id))?;
+    let events = book_log.events();
+    // An amendment that cost the order its time priority gave it the ordinal of the amendment
+    let mut ordinals = vec![order_id];
+    for index in placed + 1..events.len() {
+        if let BookEvent::Amend { order_id: id, .. } = &events[index].1 {
+            if *id == order_id && events[index].0 != events[index - 1].0 {
+                ordinals.push(events[index].0);
+            }
+        }
+    }
+    // The fills of the order by the event that executed them, the taker's ordinal leads to its event unless the taker
+    // was a stop triggered later or the fill was crossed in an auction
+    let mut fills: BTreeMap<usize, Vec<&Trade>> = BTreeMap::new();
+    for trade in trades
+        .iter()
+        .filter(|t| ordinals.contains(&t.ordinal) || ordinals.contains(&t.maker_ordinal))
+    {
+        let index = book_log
+            .crossed_at(trade.ordinal, trade.maker_ordinal)
+            .or_else(|| book_log.triggered_at(trade.ordinal))
+            .unwrap_or_else(|| events.partition_point(|(ordinal, _)| *ordinal < trade.ordinal));
+        fills.entry(index).or_default().push(trade);
+    }
+    // Where the order met the book, a stop order only once it triggered
+    let stop = order.trigger_price.is_some();
+    let executed = match stop {
+        true => book_log.triggered_at(order_id),
+        false => Some(placed),
+    };
+    let rests = order.order_type == OrderType::Limit && order.time_in_force != TimeInForce::Ioc;
+
+    let mut remaining = order.amount;
+    let mut steps = vec![OrderEvent {
+        timestamp: events[placed].1.timestamp(),
+        state: OrderState::Accepted,
+        amount: order.amount,
+        remaining,
+        fill: None,
+    }];
+    for (index, (_, event)) in events.iter().enumerate().skip(placed) {
+        if stop && executed == Some(index) {
+            steps.push(OrderEvent {
+                timestamp: event.timestamp(),
+                state: OrderState::Triggered,
+                amount: remaining,
+                remaining,
+                fill: None,
+            });
+        }
+        if let BookEvent::Amend {
+            order_id: id,
+            amount,
+            ..
+        } = event
+        {
+            if *id == order_id {
+                remaining = *amount;
+                steps.push(OrderEvent {
+                    timestamp: event.timestamp(),
+                    state: OrderState::Amended,
+                    amount: *amount,
+                    remaining,
+                    fill: None,
+                });
+            }
+        }
+        for trade in fills.remove(&index).unwrap_or_default() {
+            remaining = remaining.saturating_sub(trade.amount);
+            let taker = ordinals.contains(&trade.ordinal);
+            steps.push(OrderEvent {
+                timestamp: trade.timestamp,
+                state: match remaining {
+                    0 => OrderState::Filled,
+                    _ => OrderState::PartiallyFilled,
+                },
+                amount: trade.amount,
+                remaining,
+                fill: Some(OrderFill {
+                    trade_id: trade.id,
+                    price: trade.price,
+                    taker,
+                    fee: if taker {
+                        trade.taker_fee
+                    } else {
+                        trade.maker_fee
+                    },
+                    busted: trade.busted,
+                }),
+            });
+        }
+        if remaining == 0 {
+            break;
+        }
+        let taken_off = match event {
+            // Market and immediate-or-cancel orders don't rest
+            _ if executed == Some(index) => (!rests).then_some(OrderState::Cancelled),
+            // The other order of its bracket filled or triggered
+            _ if book_log.unlinked_at(order_id) == Some(index) => Some(OrderState::Cancelled),
+            // The stop triggered, but its signer couldn't pay for it
+            _ if book_log.refused_at(order_id) == Some(index) => Some(OrderState::Cancelled),
+            BookEvent::Cancel { order_id: id, .. } => {
+                (*id == order_id).then_some(OrderState::Cancelled)
+            }
+            BookEvent::CancelAll { signer, .. } => {
+                (*signer == order.signer).then_some(OrderState::Cancelled)
+            }
+            BookEvent::CancelMany { order_ids, .. } => order_ids
+                .contains(&order_id)
+                .then_some(OrderState::Cancelled),
+            BookEvent::Quote { replace, .. } => {
+                replace.contains(&order_id).then_some(OrderState::Cancelled)
+            }
+            BookEvent::Expire { timestamp } => {
+                matches!(order.time_in_force, TimeInForce::Gtt(expiry) if expiry <= *timestamp)
+                    .then_some(OrderState::Expired)
+            }
+            BookEvent::Order { .. }
+            | BookEvent::Amend { .. }
+            | BookEvent::Bracket { .. }
+            | BookEvent::AuctionStart { .. }
+            | BookEvent::Auction { .. } => None,
+        };
+        if let Some(state) = taken_off {
+            steps.push(OrderEvent {
+                timestamp: event.timestamp(),
+                state,
+                amount: remaining,
+                remaining: 0,
+                fill: None,
+            });
+            break;
+        }
+    }
+    Ok(OrderLifecycle {
+        order_id,
+        order: order.clone(),
+        events: steps,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    // reduce the warnings for naming tests
+    #![allow(non_snake_case)]
+
+    use super::*;
+    use crate::trading_platform::TradingPlatform;
+    use octopus_common::types::{Order, Side, DEFAULT_MARKET};
+
+    fn order(signer: &str, side: Side, amount: u64, time_in_force: TimeInForce) -> Order {
+        Order {
+            price: 10,
+            amount,
+            side,
+            signer: signer.to_string(),
+            client_order_id: None,
+            signature: None,
+            order_type: OrderType::Limit,
+            time_in_force,
+            trigger_price: None,
+            market: DEFAULT_MARKET.to_string(),
+        }
+    }
+
+    #[test]
+    fn test_lifecycle_follows_an_order_from_acceptance_to_cancellation() {
+        let mut trading_platform = TradingPlatform::new();
+        trading_platform.taker_fee_bps = 1_000;
+        trading_platform.deposit("ALICE", 100).unwrap();
+        trading_platform.deposit("BOB", 100).unwrap();
+        let resting = trading_platform
+            .order(order("ALICE", Side::Sell, 4, TimeInForce::Gtc))
+            .unwrap();
+        let taker = trading_platform
+            .order(order("BOB", Side::Buy, 1, TimeInForce::Gtc))
+            .unwrap();
+        let ioc = trading_platform
+            .order(order("BOB", Side::Buy, 5, TimeInForce::Ioc))
+            .unwrap();
+
+        let state = |lifecycle: &OrderLifecycle| {
+            lifecycle
+                .events
+                .iter()
+                .map(|e| (e.state, e.amount, e.remaining))
+                .collect::<Vec<_>>()
+        };
+        let taker = trading_platform
+            .order_lifecycle(taker.order_id, "BOB")
+            .unwrap();
+        assert_eq!(
+            state(&taker),
+            vec![(OrderState::Accepted, 1, 1), (OrderState::Filled, 1, 0)]
+        );
+        let fill = taker.events[1].fill.clone().unwrap();
+        assert_eq!((fill.price, fill.taker, fill.fee), (10, true, 1));
+
+        let ioc = trading_platform
+            .order_lifecycle(ioc.order_id, "BOB")
+            .unwrap();
+        assert_eq!(
+            state(&ioc),
+            vec![
+                (OrderState::Accepted, 5, 5),
+                (OrderState::PartiallyFilled, 3, 2),
+                (OrderState::Cancelled, 2, 0)
+            ]
+        );
+
+        let resting_id = resting.order_id;
+        let maker = trading_platform
+            .order_lifecycle(resting_id, "ALICE")
+            .unwrap();
+        assert_eq!(
+            state(&maker),
+            vec![
+                (OrderState::Accepted, 4, 4),
+                (OrderState::PartiallyFilled, 1, 3),
+                (OrderState::Filled, 3, 0)
+            ]
+        );
+        assert_eq!(maker.events[1].fill.as_ref().unwrap().fee, 0);
+
+        let cancelled = trading_platform
+            .order(order("ALICE", Side::Sell, 2, TimeInForce::Gtc))
+            .unwrap();
+        trading_platform.cancel_all("ALICE", 42);
+        let lifecycle = trading_platform
+            .order_lifecycle(cancelled.order_id, "ALICE")
+            .unwrap();
+        assert_eq!(
+            lifecycle.events.last(),
+            Some(&OrderEvent {
+                timestamp: 42,
+                state: OrderState::Cancelled,
+                amount: 2,
+                remaining: 0,
+                fill: None,
+            })
+        );
+
+        assert_eq!(
+            trading_platform.order_lifecycle(resting_id, "BOB"),
+            Err(ApplicationError::OrderNotOwned(resting_id))
+        );
+        assert_eq!(
+            trading_platform.order_lifecycle(99, "ALICE"),
+            Err(ApplicationError::OrderNotFound(99))
+        );
+    }
+}
@@ -1,142 +1,3041 @@
 mod accounting;
-mod core;
+mod activity;
+mod address_book;
+mod analytics;
+mod api_keys;
+mod archives;
+mod audit;
+mod auth;
+mod balances;
+mod book_updates;
+#[cfg(feature = "chaos")]
+mod chaos;
+mod cold;
+mod concurrency;
+mod config;
+mod counters;
+mod dashboard;
+mod deadline;
+mod deadman;
+mod demo;
+mod duplicates;
+mod dust;
+mod engine_events;
+mod etag;
+mod export;
+#[cfg(feature = "faucet")]
+mod faucet;
+mod fees;
+mod gateway;
+mod graphql;
+mod ingest;
+mod invoices;
+mod lifecycle;
+mod markets;
+mod metrics;
+mod pins;
+mod positions;
+mod private_channel;
+mod recurring;
+mod reference;
+mod rejection;
+mod reports;
+mod risk;
+mod scheduler;
+mod search;
+mod seed;
+mod sessions;
+mod settlement;
+mod shutdown;
+mod signing;
+mod startup;
+mod stats;
+mod tags;
+mod tenants;
+mod throttle;
 
 mod trading_platform;
-use warp::Filter;
+#[cfg(unix)]
+mod unix_socket;
+mod verification;
+mod whitelist;
 
-use std::sync::{Arc, Mutex};
+// The matching engine lives in its own crate so it can be embedded elsewhere
+use futures_util::{SinkExt, StreamExt};
+use octopus_engine as core;
+use warp::ws::{Message, WebSocket};
+use warp::{Filter, Reply};
 
+use std::path::Path;
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::Duration;
+
+use crate::archives::AccountArchive;
+use crate::audit::{AuditAction, AuditLog, AuditQuery, Auditor};
+use crate::auth::{AdminKey, Credential};
+use crate::concurrency::{Permit, RouteGroups};
+use crate::config::{Args, Config};
+use crate::core::PointInTime;
+use crate::deadline::Deadline;
+use crate::gateway::GatewayKey;
+use crate::graphql::OctopusSchema;
+use crate::ingest::OrderQueue;
+use crate::markets::MarketConfig;
+use crate::private_channel::PrivateChannel;
+use crate::search::{SearchQuery, SearchResults};
+use crate::sessions::TradingSession;
+use crate::startup::{Startup, StartupPhase};
+use crate::tenants::Tenants;
 use crate::trading_platform::TradingPlatform;
-use octopus_common::errors::OctopusError;
-use octopus_common::types::{AccountBalanceRequest, AccountUpdateRequest, Order, SendRequest};
+use crate::verification::StateDigest;
+use async_graphql::http::WebSocketProtocols;
+use async_graphql_warp::{GraphQLResponse, GraphQLWebSocket};
+use clap::Parser;
+use octopus_common::errors::{ApplicationError, OctopusError};
+use octopus_common::types::{
+    AccountBalanceRequest, AccountChannelQuery, AccountUpdateRequest, AdminApiKeyRequest,
+    AmendRequest, ApiKeyRequest, ApiKeyScope, BookDeltasQuery, BookMessage, BookQuery,
+    BookUpdatesQuery, BracketRequest, CancelFilter, CancelQuery, CaptureRequest, ColdRequest,
+    DeadmanQuery, DepthQuery, DocumentFormat, DocumentQuery, DustConsentRequest, HoldRequest,
+    LeaderboardQuery, LeaderboardRequest, OraclePrice, Order, OrderEventsQuery, OrderQuery,
+    PayoutRequest, PinRequest, PointInTimeQuery, PublicKeyRequest, QueuePositionQuery,
+    QuoteRequest, RecurringBuyRequest, Role, SavedRecipientRequest, SendRequest, StatementQuery,
+    StatsQuery, StopLossRequest, TenantRequest, TickerQuery, TradesQuery, TradingSessionQuery,
+    TxTagsRequest, WithdrawalRequest, DEFAULT_DEPTH_LEVELS, DEFAULT_MARKET,
+};
 
 async fn balance_request(
+    credential: Credential,
     account: AccountBalanceRequest,
+    query: PointInTimeQuery,
     trading_platform: Arc<Mutex<TradingPlatform>>,
 ) -> Result<impl warp::Reply, warp::Rejection> {
     let account = account.signer;
+    credential
+        .authorize(&account, ApiKeyScope::Read)
+        .map_err(|e| warp::reject::custom(OctopusError(e)))?;
     let mut ledger_lock = trading_platform.lock().unwrap();
-    match ledger_lock.balance_of(&account) {
+    let balance = match query.at {
+        Some(at) => PointInTime::parse(&at).and_then(|at| ledger_lock.balance_of_at(&account, at)),
+        None => ledger_lock.balance_of(&account).copied(),
+    };
+    match balance {
         Ok(balance) => Ok(warp::reply::json(&balance)),
         Err(e) => Err(warp::reject::custom(OctopusError(e))),
     }
 }
 
 async fn deposit(
+    credential: Credential,
     account: AccountUpdateRequest,
+    auditor: Auditor,
     trading_platform: Arc<Mutex<TradingPlatform>>,
 ) -> Result<impl warp::Reply, warp::Rejection> {
     let mut ledger_lock = trading_platform.lock().unwrap();
+    let before = ledger_lock
+        .accounts
+        .balance_of(&account.signer)
+        .ok()
+        .copied();
     match ledger_lock.deposit(&account.signer, account.amount) {
+        Ok(tx) => {
+            let after = ledger_lock
+                .accounts
+                .balance_of(&account.signer)
+                .ok()
+                .copied();
+            auditor.record(
+                credential.actor(),
+                AuditAction::ManualDeposit,
+                before,
+                after,
+            );
+            Ok(warp::reply::json(&tx))
+        }
+        Err(e) => Err(warp::reject::custom(OctopusError(e))),
+    }
+}
+
+async fn fund_sandbox(
+    credential: Credential,
+    account: AccountUpdateRequest,
+    auditor: Auditor,
+    trading_platform: Arc<Mutex<TradingPlatform>>,
+) -> Result<impl warp::Reply, warp::Rejection> {
+    let mut ledger_lock = trading_platform.lock().unwrap();
+    let before = ledger_lock
+        .accounts
+        .balance_of(&account.signer)
+        .ok()
+        .copied();
+    match ledger_lock.faucet(&account.signer, account.amount) {
+        Ok(tx) => {
+            let after = ledger_lock
+                .accounts
+                .balance_of(&account.signer)
+                .ok()
+                .copied();
+            auditor.record(
+                credential.actor(),
+                AuditAction::SandboxFunding,
+                before,
+                after,
+            );
+            Ok(warp::reply::json(&tx))
+        }
+        Err(e) => Err(warp::reject::custom(OctopusError(e))),
+    }
+}
+
+#[cfg(feature = "faucet")]
+async fn claim_faucet(
+    credential: Credential,
+    account: AccountUpdateRequest,
+    auditor: Auditor,
+    faucet: Arc<faucet::Faucet>,
+    trading_platform: Arc<Mutex<TradingPlatform>>,
+) -> Result<impl warp::Reply, warp::Rejection> {
+    credential
+        .authorize(&account.signer, ApiKeyScope::Trade)
+        .map_err(|e| warp::reject::custom(OctopusError(e)))?;
+    let mut ledger_lock = trading_platform.lock().unwrap();
+    let before = ledger_lock
+        .accounts
+        .balance_of(&account.signer)
+        .ok()
+        .copied();
+    match faucet.claim(
+        &mut ledger_lock,
+        &auditor.tenant,
+        &account.signer,
+        account.amount,
+        scheduler::now_millis(),
+    ) {
+        Ok(tx) => {
+            let after = ledger_lock
+                .accounts
+                .balance_of(&account.signer)
+                .ok()
+                .copied();
+            auditor.record(
+                credential.actor(),
+                AuditAction::SandboxFunding,
+                before,
+                after,
+            );
+            Ok(warp::reply::json(&tx))
+        }
+        Err(e) => Err(warp::reject::custom(OctopusError(e))),
+    }
+}
+
+async fn sandbox_orderbook(
+    trading_platform: Arc<Mutex<TradingPlatform>>,
+) -> Result<impl warp::Reply, warp::Rejection> {
+    let ledger_lock = trading_platform.lock().unwrap();
+    Ok(warp::reply::json(&ledger_lock.sandbox_book.orders()))
+}
+
+async fn gateway_deposit(
+    signature: Option<String>,
+    body: warp::hyper::body::Bytes,
+    gateway_key: Arc<GatewayKey>,
+    trading_platform: Arc<Mutex<TradingPlatform>>,
+) -> Result<impl warp::Reply, warp::Rejection> {
+    gateway_key
+        .verify(&body, signature.as_deref())
+        .map_err(|e| warp::reject::custom(OctopusError(e)))?;
+    let notification =
+        gateway::parse_notification(&body).map_err(|e| warp::reject::custom(OctopusError(e)))?;
+    let mut ledger_lock = trading_platform.lock().unwrap();
+    match ledger_lock.gateway_deposit(notification) {
         Ok(tx) => Ok(warp::reply::json(&tx)),
         Err(e) => Err(warp::reject::custom(OctopusError(e))),
     }
 }
 
 async fn withdraw(
-    account: AccountUpdateRequest,
+    credential: Credential,
+    request: WithdrawalRequest,
+    pin: Option<String>,
     trading_platform: Arc<Mutex<TradingPlatform>>,
 ) -> Result<impl warp::Reply, warp::Rejection> {
+    credential
+        .authorize(&request.signer, ApiKeyScope::Withdraw)
+        .map_err(|e| warp::reject::custom(OctopusError(e)))?;
     let mut ledger_lock = trading_platform.lock().unwrap();
-    match ledger_lock.withdraw(&account.signer, account.amount) {
+    let now = scheduler::now_millis();
+    match ledger_lock
+        .verify_pin(&request.signer, request.amount, pin.as_deref(), now)
+        .and_then(|()| {
+            ledger_lock.withdraw(
+                &request.signer,
+                request.amount,
+                request.destination.as_deref(),
+            )
+        }) {
         Ok(tx) => Ok(warp::reply::json(&tx)),
         Err(e) => Err(warp::reject::custom(OctopusError(e))),
     }
 }
 
 async fn send(
+    credential: Credential,
     send_request: SendRequest,
+    pin: Option<String>,
     trading_platform: Arc<Mutex<TradingPlatform>>,
 ) -> Result<impl warp::Reply, warp::Rejection> {
+    credential
+        .authorize(&send_request.from, ApiKeyScope::Withdraw)
+        .map_err(|e| warp::reject::custom(OctopusError(e)))?;
     let mut ledger_lock = trading_platform.lock().unwrap();
-    match ledger_lock.send(&send_request.from, &send_request.to, send_request.amount) {
+    let now = scheduler::now_millis();
+    match ledger_lock
+        .recipient_of(&send_request)
+        .and_then(|to| {
+            ledger_lock
+                .verify_pin(&send_request.from, send_request.amount, pin.as_deref(), now)
+                .map(|()| to)
+        })
+        .and_then(|to| {
+            ledger_lock.send_with(
+                &send_request.from,
+                &to,
+                send_request.amount,
+                send_request.memo.clone(),
+            )
+        }) {
         Ok(receipt) => Ok(warp::reply::json(&receipt)),
         Err(e) => Err(warp::reject::custom(OctopusError(e))),
     }
 }
 
-async fn order(
-    order: Order,
+async fn payout(
+    credential: Credential,
+    request: PayoutRequest,
+    pin: Option<String>,
     trading_platform: Arc<Mutex<TradingPlatform>>,
 ) -> Result<impl warp::Reply, warp::Rejection> {
+    credential
+        .authorize(&request.from, ApiKeyScope::Withdraw)
+        .map_err(|e| warp::reject::custom(OctopusError(e)))?;
+    let total = request
+        .recipients
+        .iter()
+        .fold(0u64, |total, leg| total.saturating_add(leg.amount));
     let mut ledger_lock = trading_platform.lock().unwrap();
-    match ledger_lock.order(order) {
-        Ok(receipt) => Ok(warp::reply::json(&receipt)),
+    let now = scheduler::now_millis();
+    match ledger_lock
+        .verify_pin(&request.from, total, pin.as_deref(), now)
+        .and_then(|()| ledger_lock.payout(&request))
+    {
+        Ok(legs) => Ok(warp::reply::json(&legs)),
         Err(e) => Err(warp::reject::custom(OctopusError(e))),
     }
 }
 
-async fn orderbook(
+async fn save_recipient(
+    signer: String,
+    alias: String,
+    credential: Credential,
+    request: SavedRecipientRequest,
+    trading_platform: Arc<Mutex<TradingPlatform>>,
+) -> Result<impl warp::Reply, warp::Rejection> {
+    credential
+        .authorize(&signer, ApiKeyScope::Withdraw)
+        .map_err(|e| warp::reject::custom(OctopusError(e)))?;
+    let mut ledger_lock = trading_platform.lock().unwrap();
+    match ledger_lock.save_recipient(&signer, &alias, &request.signer) {
+        Ok(recipient) => Ok(warp::reply::json(&recipient)),
+        Err(e) => Err(warp::reject::custom(OctopusError(e))),
+    }
+}
+
+async fn forget_recipient(
+    signer: String,
+    alias: String,
+    credential: Credential,
+    trading_platform: Arc<Mutex<TradingPlatform>>,
+) -> Result<impl warp::Reply, warp::Rejection> {
+    credential
+        .authorize(&signer, ApiKeyScope::Withdraw)
+        .map_err(|e| warp::reject::custom(OctopusError(e)))?;
+    let mut ledger_lock = trading_platform.lock().unwrap();
+    match ledger_lock.address_book.delete(&signer, &alias) {
+        Ok(recipient) => Ok(warp::reply::json(&recipient)),
+        Err(e) => Err(warp::reject::custom(OctopusError(e))),
+    }
+}
+
+async fn recipients(
+    signer: String,
+    credential: Credential,
     trading_platform: Arc<Mutex<TradingPlatform>>,
 ) -> Result<impl warp::Reply, warp::Rejection> {
+    credential
+        .authorize(&signer, ApiKeyScope::Read)
+        .map_err(|e| warp::reject::custom(OctopusError(e)))?;
     let ledger_lock = trading_platform.lock().unwrap();
-    Ok(warp::reply::json(&ledger_lock.orderbook()))
+    Ok(warp::reply::json(&ledger_lock.address_book.list(&signer)))
 }
 
-async fn transactions(
+async fn add_whitelist_entry(
+    signer: String,
+    destination: String,
+    credential: Credential,
+    trading_platform: Arc<Mutex<TradingPlatform>>,
+) -> Result<impl warp::Reply, warp::Rejection> {
+    credential
+        .authorize(&signer, ApiKeyScope::Withdraw)
+        .map_err(|e| warp::reject::custom(OctopusError(e)))?;
+    let mut ledger_lock = trading_platform.lock().unwrap();
+    match ledger_lock.whitelist(&signer, &destination, scheduler::now_millis()) {
+        Ok(entry) => Ok(warp::reply::json(&entry)),
+        Err(e) => Err(warp::reject::custom(OctopusError(e))),
+    }
+}
+
+async fn forget_whitelist_entry(
+    signer: String,
+    destination: String,
+    credential: Credential,
+    trading_platform: Arc<Mutex<TradingPlatform>>,
+) -> Result<impl warp::Reply, warp::Rejection> {
+    credential
+        .authorize(&signer, ApiKeyScope::Withdraw)
+        .map_err(|e| warp::reject::custom(OctopusError(e)))?;
+    let mut ledger_lock = trading_platform.lock().unwrap();
+    match ledger_lock
+        .whitelists
+        .delete(&signer, &destination, scheduler::now_millis())
+    {
+        Ok(entry) => Ok(warp::reply::json(&entry)),
+        Err(e) => Err(warp::reject::custom(OctopusError(e))),
+    }
+}
+
+async fn disable_whitelist(
+    signer: String,
+    credential: Credential,
+    trading_platform: Arc<Mutex<TradingPlatform>>,
+) -> Result<impl warp::Reply, warp::Rejection> {
+    credential
+        .authorize(&signer, ApiKeyScope::Withdraw)
+        .map_err(|e| warp::reject::custom(OctopusError(e)))?;
+    let mut ledger_lock = trading_platform.lock().unwrap();
+    match ledger_lock
+        .whitelists
+        .disable(&signer, scheduler::now_millis())
+    {
+        Ok(disabled_at) => Ok(warp::reply::json(&serde_json::json!({
+            "signer": signer,
+            "disabled_at": disabled_at,
+        }))),
+        Err(e) => Err(warp::reject::custom(OctopusError(e))),
+    }
+}
+
+async fn whitelist(
+    signer: String,
+    credential: Credential,
     trading_platform: Arc<Mutex<TradingPlatform>>,
 ) -> Result<impl warp::Reply, warp::Rejection> {
+    credential
+        .authorize(&signer, ApiKeyScope::Read)
+        .map_err(|e| warp::reject::custom(OctopusError(e)))?;
     let ledger_lock = trading_platform.lock().unwrap();
-    Ok(warp::reply::json(&ledger_lock.transactions))
+    Ok(warp::reply::json(
+        &ledger_lock
+            .whitelists
+            .list(&signer, scheduler::now_millis()),
+    ))
 }
 
-#[tokio::main]
-async fn main() {
-    pretty_env_logger::init();
+async fn register_public_key(
+    signer: String,
+    credential: Credential,
+    request: PublicKeyRequest,
+    trading_platform: Arc<Mutex<TradingPlatform>>,
+) -> Result<impl warp::Reply, warp::Rejection> {
+    credential
+        .authorize(&signer, ApiKeyScope::Trade)
+        .map_err(|e| warp::reject::custom(OctopusError(e)))?;
+    let mut ledger_lock = trading_platform.lock().unwrap();
+    match ledger_lock.register_public_key(&signer, &request.public_key, scheduler::now_millis()) {
+        Ok(registered) => Ok(warp::reply::json(&registered)),
+        Err(e) => Err(warp::reject::custom(OctopusError(e))),
+    }
+}
 
-    let trading_platform = Arc::new(Mutex::new(TradingPlatform::new()));
-    let trading_platform_state = warp::any().map(move || trading_platform.clone());
+async fn public_keys(
+    signer: String,
+    credential: Credential,
+    trading_platform: Arc<Mutex<TradingPlatform>>,
+) -> Result<impl warp::Reply, warp::Rejection> {
+    credential
+        .authorize(&signer, ApiKeyScope::Read)
+        .map_err(|e| warp::reject::custom(OctopusError(e)))?;
+    let ledger_lock = trading_platform.lock().unwrap();
+    Ok(warp::reply::json(&ledger_lock.signing_keys.list(&signer)))
+}
 
-    let post_account = warp::path!("account")
-        .and(warp::post())
-        .and(warp::body::json())
-        .and(trading_platform_state.clone())
-        .and_then(balance_request);
+async fn set_pin(
+    signer: String,
+    credential: Credential,
+    request: PinRequest,
+    trading_platform: Arc<Mutex<TradingPlatform>>,
+) -> Result<impl warp::Reply, warp::Rejection> {
+    credential
+        .authorize(&signer, ApiKeyScope::Withdraw)
+        .map_err(|e| warp::reject::custom(OctopusError(e)))?;
+    let mut ledger_lock = trading_platform.lock().unwrap();
+    match ledger_lock.set_pin(
+        &signer,
+        &request.pin,
+        request.current_pin.as_deref(),
+        scheduler::now_millis(),
+    ) {
+        Ok(()) => Ok(warp::reply::json(
+            &serde_json::json!({ "signer": signer, "pin_set": true }),
+        )),
+        Err(e) => Err(warp::reject::custom(OctopusError(e))),
+    }
+}
 
-    let post_deposit = warp::path!("account" / "deposit")
-        .and(warp::post())
-        .and(warp::body::json())
-        .and(trading_platform_state.clone())
-        .and_then(deposit);
+async fn set_cold(
+    signer: String,
+    credential: Credential,
+    request: ColdRequest,
+    trading_platform: Arc<Mutex<TradingPlatform>>,
+) -> Result<impl warp::Reply, warp::Rejection> {
+    credential
+        .authorize(&signer, ApiKeyScope::Withdraw)
+        .map_err(|e| warp::reject::custom(OctopusError(e)))?;
+    let mut ledger_lock = trading_platform.lock().unwrap();
+    match ledger_lock.set_cold(&signer, request.cold, scheduler::now_millis()) {
+        Ok(hot_at) => Ok(warp::reply::json(&serde_json::json!({
+            "signer": signer,
+            "cold": hot_at.is_none(),
+            "hot_at": hot_at,
+        }))),
+        Err(e) => Err(warp::reject::custom(OctopusError(e))),
+    }
+}
 
-    let post_withdraw = warp::path!("account" / "withdraw")
-        .and(warp::post())
-        .and(warp::body::json())
-        .and(trading_platform_state.clone())
-        .and_then(withdraw);
+async fn set_dust_consent(
+    signer: String,
+    credential: Credential,
+    request: DustConsentRequest,
+    trading_platform: Arc<Mutex<TradingPlatform>>,
+) -> Result<impl warp::Reply, warp::Rejection> {
+    credential
+        .authorize(&signer, ApiKeyScope::Withdraw)
+        .map_err(|e| warp::reject::custom(OctopusError(e)))?;
+    let mut ledger_lock = trading_platform.lock().unwrap();
+    match ledger_lock.set_dust_consent(&signer, request.consent) {
+        Ok(()) => Ok(warp::reply::json(&serde_json::json!({
+            "signer": signer,
+            "consent": request.consent,
+        }))),
+        Err(e) => Err(warp::reject::custom(OctopusError(e))),
+    }
+}
 
-    let post_send = warp::path!("account" / "send")
-        .and(warp::post())
-        .and(warp::body::json())
-        .and(trading_platform_state.clone())
-        .and_then(send);
+async fn tag_transaction(
+    signer: String,
+    id: u64,
+    credential: Credential,
+    request: TxTagsRequest,
+    trading_platform: Arc<Mutex<TradingPlatform>>,
+) -> Result<impl warp::Reply, warp::Rejection> {
+    credential
+        .authorize_tagging(&signer)
+        .map_err(|e| warp::reject::custom(OctopusError(e)))?;
+    let mut ledger_lock = trading_platform.lock().unwrap();
+    match ledger_lock.tag_transaction(&signer, id, &request.tags) {
+        Ok(tags) => Ok(warp::reply::json(&serde_json::json!({
+            "id": id,
+            "tags": tags,
+        }))),
+        Err(e) => Err(warp::reject::custom(OctopusError(e))),
+    }
+}
 
-    let post_ordet = warp::path!("order")
-        .and(warp::post())
-        .and(warp::body::json())
-        .and(trading_platform_state.clone())
-        .and_then(order);
+async fn account_statement(
+    signer: String,
+    credential: Credential,
+    query: StatementQuery,
+    trading_platform: Arc<Mutex<TradingPlatform>>,
+) -> Result<impl warp::Reply, warp::Rejection> {
+    credential
+        .authorize(&signer, ApiKeyScope::Read)
+        .map_err(|e| warp::reject::custom(OctopusError(e)))?;
+    let ledger_lock = trading_platform.lock().unwrap();
+    match ledger_lock.statement(&signer, query.tag.as_deref()) {
+        Ok(transactions) => Ok(warp::reply::json(&transactions)),
+        Err(e) => Err(warp::reject::custom(OctopusError(e))),
+    }
+}
 
-    let get_orderbook = warp::path!("orderbook")
-        .and(warp::get())
-        .and(trading_platform_state.clone())
-        .and_then(orderbook);
+async fn account_withdrawals(
+    signer: String,
+    credential: Credential,
+    trading_platform: Arc<Mutex<TradingPlatform>>,
+) -> Result<impl warp::Reply, warp::Rejection> {
+    credential
+        .authorize(&signer, ApiKeyScope::Read)
+        .map_err(|e| warp::reject::custom(OctopusError(e)))?;
+    let ledger_lock = trading_platform.lock().unwrap();
+    Ok(warp::reply::json(&ledger_lock.account_withdrawals(&signer)))
+}
 
-    let get_transactions = warp::path!("txlog")
+async fn confirm_withdrawal(
+    signer: String,
+    id: u64,
+    credential: Credential,
+    trading_platform: Arc<Mutex<TradingPlatform>>,
+) -> Result<impl warp::Reply, warp::Rejection> {
+    credential
+        .authorize(&signer, ApiKeyScope::Withdraw)
+        .map_err(|e| warp::reject::custom(OctopusError(e)))?;
+    let mut ledger_lock = trading_platform.lock().unwrap();
+    match ledger_lock.confirm_withdrawal(id, &signer, scheduler::now_millis()) {
+        Ok(tx) => Ok(warp::reply::json(&tx)),
+        Err(e) => Err(warp::reject::custom(OctopusError(e))),
+    }
+}
+
+async fn hold(
+    credential: Credential,
+    request: HoldRequest,
+    trading_platform: Arc<Mutex<TradingPlatform>>,
+) -> Result<impl warp::Reply, warp::Rejection> {
+    credential
+        .authorize(&request.from, ApiKeyScope::Withdraw)
+        .map_err(|e| warp::reject::custom(OctopusError(e)))?;
+    let mut ledger_lock = trading_platform.lock().unwrap();
+    match ledger_lock.hold(&request.from, request.amount) {
+        Ok(tx) => Ok(warp::reply::json(&tx)),
+        Err(e) => Err(warp::reject::custom(OctopusError(e))),
+    }
+}
+
+async fn capture(
+    id: u64,
+    credential: Credential,
+    request: CaptureRequest,
+    pin: Option<String>,
+    trading_platform: Arc<Mutex<TradingPlatform>>,
+) -> Result<impl warp::Reply, warp::Rejection> {
+    let mut ledger_lock = trading_platform.lock().unwrap();
+    let (owner, amount) = ledger_lock
+        .accounts
+        .hold_of(id)
+        .map(|hold| (hold.account.clone(), hold.amount))
+        .map_err(|e| warp::reject::custom(OctopusError(e)))?;
+    credential
+        .authorize(&owner, ApiKeyScope::Withdraw)
+        .map_err(|e| warp::reject::custom(OctopusError(e)))?;
+    let now = scheduler::now_millis();
+    match ledger_lock
+        .verify_pin(&owner, amount, pin.as_deref(), now)
+        .and_then(|()| ledger_lock.capture(id, &request.to))
+    {
+        Ok(tx) => Ok(warp::reply::json(&tx)),
+        Err(e) => Err(warp::reject::custom(OctopusError(e))),
+    }
+}
+
+async fn release(
+    id: u64,
+    credential: Credential,
+    trading_platform: Arc<Mutex<TradingPlatform>>,
+) -> Result<impl warp::Reply, warp::Rejection> {
+    let mut ledger_lock = trading_platform.lock().unwrap();
+    let owner = ledger_lock
+        .accounts
+        .hold_of(id)
+        .map(|hold| hold.account.clone())
+        .map_err(|e| warp::reject::custom(OctopusError(e)))?;
+    credential
+        .authorize(&owner, ApiKeyScope::Withdraw)
+        .map_err(|e| warp::reject::custom(OctopusError(e)))?;
+    match ledger_lock.release(id) {
+        Ok(tx) => Ok(warp::reply::json(&tx)),
+        Err(e) => Err(warp::reject::custom(OctopusError(e))),
+    }
+}
+
+async fn create_recurring_buy(
+    signer: String,
+    credential: Credential,
+    request: RecurringBuyRequest,
+    trading_platform: Arc<Mutex<TradingPlatform>>,
+) -> Result<impl warp::Reply, warp::Rejection> {
+    credential
+        .authorize(&signer, ApiKeyScope::Trade)
+        .map_err(|e| warp::reject::custom(OctopusError(e)))?;
+    let mut ledger_lock = trading_platform.lock().unwrap();
+    match ledger_lock.create_recurring_buy(&signer, request, scheduler::now_millis()) {
+        Ok(plan) => Ok(warp::reply::json(&plan)),
+        Err(e) => Err(warp::reject::custom(OctopusError(e))),
+    }
+}
+
+async fn recurring_buys(
+    signer: String,
+    credential: Credential,
+    trading_platform: Arc<Mutex<TradingPlatform>>,
+) -> Result<impl warp::Reply, warp::Rejection> {
+    credential
+        .authorize(&signer, ApiKeyScope::Read)
+        .map_err(|e| warp::reject::custom(OctopusError(e)))?;
+    let ledger_lock = trading_platform.lock().unwrap();
+    Ok(warp::reply::json(&ledger_lock.recurring_buys.list(&signer)))
+}
+
+async fn update_recurring_buy(
+    signer: String,
+    id: u64,
+    action: String,
+    credential: Credential,
+    trading_platform: Arc<Mutex<TradingPlatform>>,
+) -> Result<impl warp::Reply, warp::Rejection> {
+    credential
+        .authorize(&signer, ApiKeyScope::Trade)
+        .map_err(|e| warp::reject::custom(OctopusError(e)))?;
+    let mut ledger_lock = trading_platform.lock().unwrap();
+    let result = match action.as_str() {
+        "pause" => ledger_lock.recurring_buys.pause(&signer, id),
+        "resume" => ledger_lock
+            .recurring_buys
+            .resume(&signer, id, scheduler::now_millis()),
+        _ => return Err(warp::reject::not_found()),
+    };
+    match result {
+        Ok(plan) => Ok(warp::reply::json(&plan)),
+        Err(e) => Err(warp::reject::custom(OctopusError(e))),
+    }
+}
+
+async fn recurring_buy_executions(
+    signer: String,
+    id: u64,
+    credential: Credential,
+    trading_platform: Arc<Mutex<TradingPlatform>>,
+) -> Result<impl warp::Reply, warp::Rejection> {
+    credential
+        .authorize(&signer, ApiKeyScope::Read)
+        .map_err(|e| warp::reject::custom(OctopusError(e)))?;
+    let ledger_lock = trading_platform.lock().unwrap();
+    match ledger_lock.recurring_buys.executions(&signer, id) {
+        Ok(executions) => Ok(warp::reply::json(&executions)),
+        Err(e) => Err(warp::reject::custom(OctopusError(e))),
+    }
+}
+
+async fn set_stop_loss(
+    signer: String,
+    credential: Credential,
+    request: StopLossRequest,
+    trading_platform: Arc<Mutex<TradingPlatform>>,
+) -> Result<impl warp::Reply, warp::Rejection> {
+    credential
+        .authorize(&signer, ApiKeyScope::Trade)
+        .map_err(|e| warp::reject::custom(OctopusError(e)))?;
+    let mut ledger_lock = trading_platform.lock().unwrap();
+    match ledger_lock.set_stop_loss(&signer, request.max_daily_loss, scheduler::now_millis()) {
+        Ok(status) => Ok(warp::reply::json(&status)),
+        Err(e) => Err(warp::reject::custom(OctopusError(e))),
+    }
+}
+
+async fn arm_deadman(
+    signer: String,
+    credential: Credential,
+    query: DeadmanQuery,
+    auditor: Auditor,
+    switches: Arc<deadman::DeadmanSwitches>,
+    trading_platform: Arc<Mutex<TradingPlatform>>,
+) -> Result<impl warp::Reply, warp::Rejection> {
+    credential
+        .authorize(&signer, ApiKeyScope::Trade)
+        .map_err(|e| warp::reject::custom(OctopusError(e)))?;
+    match switches.arm(
+        trading_platform,
+        &auditor.tenant,
+        &signer,
+        Duration::from_secs(query.timeout),
+    ) {
+        Ok(status) => Ok(warp::reply::json(&status)),
+        Err(e) => Err(warp::reject::custom(OctopusError(e))),
+    }
+}
+
+async fn stop_loss(
+    signer: String,
+    credential: Credential,
+    trading_platform: Arc<Mutex<TradingPlatform>>,
+) -> Result<impl warp::Reply, warp::Rejection> {
+    credential
+        .authorize(&signer, ApiKeyScope::Read)
+        .map_err(|e| warp::reject::custom(OctopusError(e)))?;
+    let ledger_lock = trading_platform.lock().unwrap();
+    match ledger_lock.stop_loss_of(&signer, scheduler::now_millis()) {
+        Ok(status) => Ok(warp::reply::json(&status)),
+        Err(e) => Err(warp::reject::custom(OctopusError(e))),
+    }
+}
+
+async fn close_account(
+    signer: String,
+    credential: Credential,
+    pin: Option<String>,
+    trading_platform: Arc<Mutex<TradingPlatform>>,
+) -> Result<impl warp::Reply, warp::Rejection> {
+    credential
+        .authorize(&signer, ApiKeyScope::Withdraw)
+        .map_err(|e| warp::reject::custom(OctopusError(e)))?;
+    let mut ledger_lock = trading_platform.lock().unwrap();
+    let now = scheduler::now_millis();
+    // Closing sweeps the whole balance out, like withdrawing it
+    match ledger_lock
+        .balance_of(&signer)
+        .copied()
+        .and_then(|balance| ledger_lock.verify_pin(&signer, balance, pin.as_deref(), now))
+        .and_then(|()| ledger_lock.delete_account(&signer))
+    {
+        Ok(deleted) => Ok(warp::reply::json(&deleted)),
+        Err(e) => Err(warp::reject::custom(OctopusError(e))),
+    }
+}
+
+async fn export_account(
+    signer: String,
+    credential: Credential,
+    query: StatementQuery,
+    _permit: Permit,
+    trading_platform: Arc<Mutex<TradingPlatform>>,
+) -> Result<warp::reply::Response, warp::Rejection> {
+    credential
+        .authorize(&signer, ApiKeyScope::Read)
+        .map_err(|e| warp::reject::custom(OctopusError(e)))?;
+    let now = scheduler::now_millis();
+    let (archive, id) = {
+        let mut ledger_lock = trading_platform.lock().unwrap();
+        let archive = AccountArchive::collect(&ledger_lock, &signer, query.tag.as_deref(), now)
+            .map_err(|e| warp::reject::custom(OctopusError(e)))?;
+        if archive.entries() <= archives::INLINE_ARCHIVE_LIMIT {
+            return Ok(warp::reply::json(&archive).into_response());
+        }
+        (archive, ledger_lock.archives.start(&signer, now))
+    };
+    // Serialize without holding the lock, the client polls the download link meanwhile
+    let generating = trading_platform.clone();
+    tokio::task::spawn_blocking(move || {
+        let json = serde_json::to_vec(&archive).expect("Archives are always serializable");
+        generating.lock().unwrap().archives.complete(id, json);
+    });
+    let download = format!("/account/{}/export/{}", signer, id);
+    let reply = warp::reply::json(&serde_json::json!({ "id": id, "download": download }));
+    let reply = warp::reply::with_header(reply, "location", download);
+    Ok(warp::reply::with_status(reply, warp::http::StatusCode::ACCEPTED).into_response())
+}
+
+async fn account_archive(
+    signer: String,
+    id: u64,
+    credential: Credential,
+    trading_platform: Arc<Mutex<TradingPlatform>>,
+) -> Result<warp::reply::Response, warp::Rejection> {
+    credential
+        .authorize(&signer, ApiKeyScope::Read)
+        .map_err(|e| warp::reject::custom(OctopusError(e)))?;
+    let ledger_lock = trading_platform.lock().unwrap();
+    match ledger_lock.archives.get(&signer, id) {
+        Ok(Some(json)) => {
+            Ok(
+                warp::reply::with_header(json.to_vec(), "content-type", "application/json")
+                    .into_response(),
+            )
+        }
+        Ok(None) => Ok(warp::reply::with_status(
+            warp::reply::json(&serde_json::json!({ "id": id, "status": "pending" })),
+            warp::http::StatusCode::ACCEPTED,
+        )
+        .into_response()),
+        Err(e) => Err(warp::reject::custom(OctopusError(e))),
+    }
+}
+
+async fn position(
+    signer: String,
+    credential: Credential,
+    trading_platform: Arc<Mutex<TradingPlatform>>,
+) -> Result<impl warp::Reply, warp::Rejection> {
+    credential
+        .authorize(&signer, ApiKeyScope::Read)
+        .map_err(|e| warp::reject::custom(OctopusError(e)))?;
+    let ledger_lock = trading_platform.lock().unwrap();
+    match ledger_lock.position_of(&signer) {
+        Ok(position) => Ok(warp::reply::json(&position)),
+        Err(e) => Err(warp::reject::custom(OctopusError(e))),
+    }
+}
+
+async fn exposure(
+    signer: String,
+    credential: Credential,
+    trading_platform: Arc<Mutex<TradingPlatform>>,
+) -> Result<impl warp::Reply, warp::Rejection> {
+    credential
+        .authorize(&signer, ApiKeyScope::Read)
+        .map_err(|e| warp::reject::custom(OctopusError(e)))?;
+    let ledger_lock = trading_platform.lock().unwrap();
+    match ledger_lock.exposure_of(&signer) {
+        Ok(exposure) => Ok(warp::reply::json(&exposure)),
+        Err(e) => Err(warp::reject::custom(OctopusError(e))),
+    }
+}
+
+/// The start of a statistics window ending now, all time without a window
+fn window_start(window_secs: Option<u64>) -> u64 {
+    window_secs
+        .map(|secs| scheduler::now_millis().saturating_sub(secs * 1000))
+        .unwrap_or_default()
+}
+
+async fn account_stats(
+    signer: String,
+    credential: Credential,
+    query: StatsQuery,
+    trading_platform: Arc<Mutex<TradingPlatform>>,
+) -> Result<impl warp::Reply, warp::Rejection> {
+    credential
+        .authorize(&signer, ApiKeyScope::Read)
+        .map_err(|e| warp::reject::custom(OctopusError(e)))?;
+    let ledger_lock = trading_platform.lock().unwrap();
+    match ledger_lock.stats_of(&signer, window_start(query.window_secs)) {
+        Ok(stats) => Ok(warp::reply::json(&stats)),
+        Err(e) => Err(warp::reject::custom(OctopusError(e))),
+    }
+}
+
+async fn set_leaderboard_opt_in(
+    signer: String,
+    credential: Credential,
+    request: LeaderboardRequest,
+    trading_platform: Arc<Mutex<TradingPlatform>>,
+) -> Result<impl warp::Reply, warp::Rejection> {
+    credential
+        .authorize(&signer, ApiKeyScope::Trade)
+        .map_err(|e| warp::reject::custom(OctopusError(e)))?;
+    let mut ledger_lock = trading_platform.lock().unwrap();
+    match ledger_lock.set_leaderboard_opt_in(&signer, request.opt_in) {
+        Ok(()) => Ok(warp::reply::json(&request)),
+        Err(e) => Err(warp::reject::custom(OctopusError(e))),
+    }
+}
+
+async fn leaderboard(
+    query: LeaderboardQuery,
+    trading_platform: Arc<Mutex<TradingPlatform>>,
+) -> Result<impl warp::Reply, warp::Rejection> {
+    let ledger_lock = trading_platform.lock().unwrap();
+    let mut ranking = ledger_lock
+        .trade_stats
+        .leaderboard(window_start(query.window_secs), query.sort_by);
+    if let Some(limit) = query.limit {
+        ranking.truncate(limit);
+    }
+    Ok(warp::reply::json(&ranking))
+}
+
+async fn invoices(
+    signer: String,
+    credential: Credential,
+    trading_platform: Arc<Mutex<TradingPlatform>>,
+) -> Result<impl warp::Reply, warp::Rejection> {
+    credential
+        .authorize(&signer, ApiKeyScope::Read)
+        .map_err(|e| warp::reject::custom(OctopusError(e)))?;
+    let ledger_lock = trading_platform.lock().unwrap();
+    Ok(warp::reply::json(&ledger_lock.invoices.of(&signer)))
+}
+
+async fn invoice(
+    signer: String,
+    month: String,
+    credential: Credential,
+    query: DocumentQuery,
+    trading_platform: Arc<Mutex<TradingPlatform>>,
+) -> Result<warp::reply::Response, warp::Rejection> {
+    credential
+        .authorize(&signer, ApiKeyScope::Read)
+        .map_err(|e| warp::reject::custom(OctopusError(e)))?;
+    let ledger_lock = trading_platform.lock().unwrap();
+    let invoice = ledger_lock
+        .invoices
+        .get(&signer, &month)
+        .map_err(|e| warp::reject::custom(OctopusError(e)))?;
+    Ok(match query.format {
+        DocumentFormat::Json => warp::reply::json(&invoice).into_response(),
+        DocumentFormat::Csv => {
+            warp::reply::with_header(invoices::to_csv(&invoice), "content-type", "text/csv")
+                .into_response()
+        }
+    })
+}
+
+async fn generate_invoices(
+    month: String,
+    credential: Credential,
+    auditor: Auditor,
+    trading_platform: Arc<Mutex<TradingPlatform>>,
+) -> Result<impl warp::Reply, warp::Rejection> {
+    let mut ledger_lock = trading_platform.lock().unwrap();
+    let before = ledger_lock.invoices.of_month(&month);
+    match ledger_lock.generate_invoices(&month) {
+        Ok(invoices) => {
+            auditor.record(
+                credential.actor(),
+                AuditAction::InvoicesGenerated,
+                before,
+                &invoices,
+            );
+            Ok(warp::reply::json(&invoices))
+        }
+        Err(e) => Err(warp::reject::custom(OctopusError(e))),
+    }
+}
+
+async fn report(
+    day: String,
+    _credential: Credential,
+    query: DocumentQuery,
+    trading_platform: Arc<Mutex<TradingPlatform>>,
+) -> Result<warp::reply::Response, warp::Rejection> {
+    let ledger_lock = trading_platform.lock().unwrap();
+    let report = ledger_lock
+        .reports
+        .get(&day)
+        .map_err(|e| warp::reject::custom(OctopusError(e)))?;
+    Ok(match query.format {
+        DocumentFormat::Json => warp::reply::json(&report).into_response(),
+        DocumentFormat::Csv => {
+            warp::reply::with_header(reports::to_csv(&report), "content-type", "text/csv")
+                .into_response()
+        }
+    })
+}
+
+async fn analytics(
+    _credential: Credential,
+    _permit: Permit,
+    query: StatsQuery,
+    trading_platform: Arc<Mutex<TradingPlatform>>,
+) -> Result<impl warp::Reply, warp::Rejection> {
+    let ledger_lock = trading_platform.lock().unwrap();
+    Ok(warp::reply::json(
+        &ledger_lock.analytics(window_start(query.window_secs)),
+    ))
+}
+
+async fn dust_report(
+    _credential: Credential,
+    trading_platform: Arc<Mutex<TradingPlatform>>,
+) -> Result<impl warp::Reply, warp::Rejection> {
+    let ledger_lock = trading_platform.lock().unwrap();
+    Ok(warp::reply::json(&ledger_lock.dust_report()))
+}
+
+async fn state_digest(
+    _credential: Credential,
+    trading_platform: Arc<Mutex<TradingPlatform>>,
+) -> Result<impl warp::Reply, warp::Rejection> {
+    let ledger_lock = trading_platform.lock().unwrap();
+    Ok(warp::reply::json(
+        &ledger_lock.digest(scheduler::now_millis()),
+    ))
+}
+
+async fn compare_state(
+    _credential: Credential,
+    other: StateDigest,
+    trading_platform: Arc<Mutex<TradingPlatform>>,
+) -> Result<impl warp::Reply, warp::Rejection> {
+    let digest = trading_platform
+        .lock()
+        .unwrap()
+        .digest(scheduler::now_millis());
+    Ok(warp::reply::json(&digest.compare(&other)))
+}
+
+async fn generate_report(
+    day: String,
+    credential: Credential,
+    auditor: Auditor,
+    trading_platform: Arc<Mutex<TradingPlatform>>,
+) -> Result<impl warp::Reply, warp::Rejection> {
+    let mut ledger_lock = trading_platform.lock().unwrap();
+    let before = ledger_lock.reports.get(&day).ok();
+    match ledger_lock.generate_report(&day) {
+        Ok(report) => {
+            auditor.record(
+                credential.actor(),
+                AuditAction::ReportGenerated,
+                before,
+                &report,
+            );
+            Ok(warp::reply::json(&report))
+        }
+        Err(e) => Err(warp::reject::custom(OctopusError(e))),
+    }
+}
+
+async fn export_transactions(
+    _credential: Credential,
+    _permit: Permit,
+    deadline: Deadline,
+    trading_platform: Arc<Mutex<TradingPlatform>>,
+) -> Result<impl warp::Reply, warp::Rejection> {
+    // Copy the log and write the file without holding the lock
+    let entries: Vec<_> = trading_platform
+        .lock()
+        .unwrap()
+        .balance_log
+        .entries()
+        .map(|(ordinal, timestamp, tx)| (ordinal, timestamp, tx.clone()))
+        .collect();
+    let file = export_before(deadline, move || {
+        export::transactions(
+            entries
+                .iter()
+                .map(|(ordinal, timestamp, tx)| (*ordinal, *timestamp, tx)),
+        )
+    })
+    .await?;
+    Ok(parquet_file(file, "transactions.parquet"))
+}
+
+async fn export_trades(
+    _credential: Credential,
+    _permit: Permit,
+    deadline: Deadline,
+    trading_platform: Arc<Mutex<TradingPlatform>>,
+) -> Result<impl warp::Reply, warp::Rejection> {
+    let trades = trading_platform.lock().unwrap().trades.clone();
+    let file = export_before(deadline, move || export::trades(&trades)).await?;
+    Ok(parquet_file(file, "trades.parquet"))
+}
+
+/// Writes an export on a blocking thread and waits for the file until the `deadline`. An abandoned export finishes
+/// in the background, without any lock, and is discarded.
+async fn export_before(
+    deadline: Deadline,
+    write: impl FnOnce() -> Result<Vec<u8>, ApplicationError> + Send + 'static,
+) -> Result<Vec<u8>, warp::Rejection> {
+    let export = tokio::task::spawn_blocking(write);
+    match tokio::time::timeout(deadline.remaining(), export).await {
+        Ok(Ok(written)) => written,
+        Ok(Err(e)) => Err(ApplicationError::ExportFailed(e.to_string())),
+        Err(_) => Err(deadline.exceeded("the export was abandoned before the file was written")),
+    }
+    .map_err(|e| warp::reject::custom(OctopusError(e)))
+}
+
+/// A download of an exported Parquet file
+fn parquet_file(file: Vec<u8>, name: &str) -> impl warp::Reply {
+    warp::reply::with_header(
+        warp::reply::with_header(file, "content-type", export::PARQUET_CONTENT_TYPE),
+        "content-disposition",
+        format!("attachment; filename=\"{}\"", name),
+    )
+}
+
+async fn create_api_key(
+    signer: String,
+    credential: Credential,
+    request: ApiKeyRequest,
+    trading_platform: Arc<Mutex<TradingPlatform>>,
+) -> Result<impl warp::Reply, warp::Rejection> {
+    credential
+        .authorize_grant(&signer, request.role, &request.scopes)
+        .map_err(|e| warp::reject::custom(OctopusError(e)))?;
+    let mut ledger_lock = trading_platform.lock().unwrap();
+    match ledger_lock.create_api_key(&signer, request.role, &request.scopes) {
+        Ok(new_key) => Ok(warp::reply::json(&new_key)),
+        Err(e) => Err(warp::reject::custom(OctopusError(e))),
+    }
+}
+
+async fn api_keys(
+    signer: String,
+    credential: Credential,
+    trading_platform: Arc<Mutex<TradingPlatform>>,
+) -> Result<impl warp::Reply, warp::Rejection> {
+    credential
+        .authorize(&signer, ApiKeyScope::Read)
+        .map_err(|e| warp::reject::custom(OctopusError(e)))?;
+    let ledger_lock = trading_platform.lock().unwrap();
+    match ledger_lock.api_keys_of(&signer) {
+        Ok(keys) => Ok(warp::reply::json(&keys)),
+        Err(e) => Err(warp::reject::custom(OctopusError(e))),
+    }
+}
+
+async fn revoke_api_key(
+    signer: String,
+    id: String,
+    credential: Credential,
+    trading_platform: Arc<Mutex<TradingPlatform>>,
+) -> Result<impl warp::Reply, warp::Rejection> {
+    credential
+        .authorize_revoke(&signer)
+        .map_err(|e| warp::reject::custom(OctopusError(e)))?;
+    let mut ledger_lock = trading_platform.lock().unwrap();
+    match ledger_lock.revoke_api_key(&signer, &id) {
+        Ok(key) => Ok(warp::reply::json(&key)),
+        Err(e) => Err(warp::reject::custom(OctopusError(e))),
+    }
+}
+
+async fn issue_api_key(
+    credential: Credential,
+    request: AdminApiKeyRequest,
+    auditor: Auditor,
+    trading_platform: Arc<Mutex<TradingPlatform>>,
+) -> Result<impl warp::Reply, warp::Rejection> {
+    let mut ledger_lock = trading_platform.lock().unwrap();
+    match ledger_lock.issue_api_key(&request.signer, request.role, &request.scopes) {
+        Ok(new_key) => {
+            // The secret stays out of the audit trail
+            auditor.record(
+                credential.actor(),
+                AuditAction::ApiKeyIssued,
+                (),
+                &new_key.key,
+            );
+            Ok(warp::reply::json(&new_key))
+        }
+        Err(e) => Err(warp::reject::custom(OctopusError(e))),
+    }
+}
+
+async fn pending_withdrawals(
+    _credential: Credential,
+    trading_platform: Arc<Mutex<TradingPlatform>>,
+) -> Result<impl warp::Reply, warp::Rejection> {
+    let ledger_lock = trading_platform.lock().unwrap();
+    Ok(warp::reply::json(&ledger_lock.pending_withdrawals()))
+}
+
+async fn resolve_withdrawal(
+    id: u64,
+    decision: String,
+    credential: Credential,
+    auditor: Auditor,
+    trading_platform: Arc<Mutex<TradingPlatform>>,
+) -> Result<impl warp::Reply, warp::Rejection> {
+    let approve = match decision.as_str() {
+        "approve" => true,
+        "reject" => false,
+        _ => return Err(warp::reject::not_found()),
+    };
+    let mut ledger_lock = trading_platform.lock().unwrap();
+    let before = ledger_lock.withdrawals.get(&id).cloned();
+    match ledger_lock.resolve_withdrawal(id, approve) {
+        Ok(tx) => {
+            let after = ledger_lock.withdrawals.get(&id);
+            auditor.record(
+                credential.actor(),
+                AuditAction::WithdrawalResolved,
+                before,
+                after,
+            );
+            Ok(warp::reply::json(&tx))
+        }
+        Err(e) => Err(warp::reject::custom(OctopusError(e))),
+    }
+}
+
+async fn bust_trade(
+    id: u64,
+    credential: Credential,
+    auditor: Auditor,
+    trading_platform: Arc<Mutex<TradingPlatform>>,
+) -> Result<impl warp::Reply, warp::Rejection> {
+    let mut ledger_lock = trading_platform.lock().unwrap();
+    let before = ledger_lock
+        .trades
+        .iter()
+        .chain(ledger_lock.sandbox_trades.iter())
+        .find(|trade| trade.id == id)
+        .cloned();
+    match ledger_lock.bust_trade(id, scheduler::now_millis()) {
+        Ok(trade) => {
+            auditor.record(credential.actor(), AuditAction::TradeBusted, before, &trade);
+            Ok(warp::reply::json(&trade))
+        }
+        Err(e) => Err(warp::reject::custom(OctopusError(e))),
+    }
+}
+
+async fn start_auction(
+    credential: Credential,
+    auditor: Auditor,
+    trading_platform: Arc<Mutex<TradingPlatform>>,
+) -> Result<impl warp::Reply, warp::Rejection> {
+    let mut ledger_lock = trading_platform.lock().unwrap();
+    let before = ledger_lock.market_info(DEFAULT_MARKET).ok();
+    ledger_lock.start_auction();
+    match ledger_lock.market_info(DEFAULT_MARKET) {
+        Ok(info) => {
+            auditor.record(
+                credential.actor(),
+                AuditAction::AuctionStarted,
+                before,
+                &info,
+            );
+            Ok(warp::reply::json(&info))
+        }
+        Err(e) => Err(warp::reject::custom(OctopusError(e))),
+    }
+}
+
+async fn run_auction(
+    credential: Credential,
+    auditor: Auditor,
+    trading_platform: Arc<Mutex<TradingPlatform>>,
+) -> Result<impl warp::Reply, warp::Rejection> {
+    let mut ledger_lock = trading_platform.lock().unwrap();
+    match ledger_lock.run_auction() {
+        Ok(receipt) => {
+            auditor.record(credential.actor(), AuditAction::AuctionRun, (), &receipt);
+            Ok(warp::reply::json(&receipt))
+        }
+        Err(e) => Err(warp::reject::custom(OctopusError(e))),
+    }
+}
+
+async fn list_market(
+    symbol: String,
+    credential: Credential,
+    config: MarketConfig,
+    auditor: Auditor,
+    trading_platform: Arc<Mutex<TradingPlatform>>,
+) -> Result<impl warp::Reply, warp::Rejection> {
+    let mut ledger_lock = trading_platform.lock().unwrap();
+    let before = ledger_lock.market_info(&symbol).ok();
+    match ledger_lock.list_market(&symbol, config) {
+        Ok(info) => {
+            auditor.record(credential.actor(), AuditAction::MarketListed, before, &info);
+            Ok(warp::reply::json(&info))
+        }
+        Err(e) => Err(warp::reject::custom(OctopusError(e))),
+    }
+}
+
+async fn set_oracle_price(
+    symbol: String,
+    credential: Credential,
+    oracle: OraclePrice,
+    auditor: Auditor,
+    trading_platform: Arc<Mutex<TradingPlatform>>,
+) -> Result<impl warp::Reply, warp::Rejection> {
+    let now = scheduler::now_millis();
+    let mut ledger_lock = trading_platform.lock().unwrap();
+    let before = ledger_lock.ticker(&symbol, now).ok();
+    match ledger_lock
+        .set_oracle_price(&symbol, oracle.price)
+        .and_then(|_| ledger_lock.ticker(&symbol, now))
+    {
+        Ok(ticker) => {
+            auditor.record(
+                credential.actor(),
+                AuditAction::OraclePriceSet,
+                before,
+                &ticker,
+            );
+            Ok(warp::reply::json(&ticker))
+        }
+        Err(e) => Err(warp::reject::custom(OctopusError(e))),
+    }
+}
+
+async fn list_tenants(
+    _credential: Credential,
+    tenants: Arc<Tenants>,
+) -> Result<impl warp::Reply, warp::Rejection> {
+    Ok(warp::reply::json(&tenants.names()))
+}
+
+async fn create_tenant(
+    credential: Credential,
+    request: TenantRequest,
+    auditor: Auditor,
+    tenants: Arc<Tenants>,
+) -> Result<impl warp::Reply, warp::Rejection> {
+    let before = tenants.names();
+    match tenants.create(&request.name) {
+        Ok(()) => {
+            let after = tenants.names();
+            auditor.record(
+                credential.actor(),
+                AuditAction::TenantCreated,
+                before,
+                &after,
+            );
+            Ok(warp::reply::json(&after))
+        }
+        Err(e) => Err(warp::reject::custom(OctopusError(e))),
+    }
+}
+
+async fn order(
+    credential: Credential,
+    _permit: Permit,
+    query: OrderQuery,
+    order: Order,
+    deadline: Deadline,
+    order_queue: Arc<OrderQueue>,
+) -> Result<impl warp::Reply, warp::Rejection> {
+    credential
+        .authorize(&order.signer, ApiKeyScope::Trade)
+        .map_err(|e| warp::reject::custom(OctopusError(e)))?;
+    if query.override_collar && credential.role != Role::Admin {
+        return Err(warp::reject::custom(OctopusError(
+            ApplicationError::Forbidden(order.signer),
+        )));
+    }
+    match order_queue
+        .submit_with(order, query.override_collar, Some(deadline))
+        .await
+    {
+        Ok(receipt) => Ok(warp::reply::json(&receipt)),
+        Err(e) => Err(warp::reject::custom(OctopusError(e))),
+    }
+}
+
+async fn cancel_order(
+    id: u64,
+    credential: Credential,
+    query: CancelQuery,
+    trading_platform: Arc<Mutex<TradingPlatform>>,
+) -> Result<impl warp::Reply, warp::Rejection> {
+    credential
+        .authorize(&query.signer, ApiKeyScope::Trade)
+        .map_err(|e| warp::reject::custom(OctopusError(e)))?;
+    let mut ledger_lock = trading_platform.lock().unwrap();
+    match ledger_lock.cancel_order(id, &query.signer, scheduler::now_millis()) {
+        Ok(order) => Ok(warp::reply::json(&order)),
+        Err(e) => Err(warp::reject::custom(OctopusError(e))),
+    }
+}
+
+async fn amend_order(
+    id: u64,
+    credential: Credential,
+    request: AmendRequest,
+    trading_platform: Arc<Mutex<TradingPlatform>>,
+) -> Result<impl warp::Reply, warp::Rejection> {
+    credential
+        .authorize(&request.signer, ApiKeyScope::Trade)
+        .map_err(|e| warp::reject::custom(OctopusError(e)))?;
+    let mut ledger_lock = trading_platform.lock().unwrap();
+    match ledger_lock.amend_order(id, request) {
+        Ok(receipt) => Ok(warp::reply::json(&receipt)),
+        Err(e) => Err(warp::reject::custom(OctopusError(e))),
+    }
+}
+
+async fn cancel_orders(
+    credential: Credential,
+    filter: CancelFilter,
+    trading_platform: Arc<Mutex<TradingPlatform>>,
+) -> Result<impl warp::Reply, warp::Rejection> {
+    credential
+        .authorize(&filter.signer, ApiKeyScope::Trade)
+        .map_err(|e| warp::reject::custom(OctopusError(e)))?;
+    let mut ledger_lock = trading_platform.lock().unwrap();
+    match ledger_lock.cancel_matching(&filter, scheduler::now_millis()) {
+        Ok(order_ids) => Ok(warp::reply::json(&order_ids)),
+        Err(e) => Err(warp::reject::custom(OctopusError(e))),
+    }
+}
+
+async fn order_events(
+    id: u64,
+    credential: Credential,
+    _permit: Permit,
+    query: OrderEventsQuery,
+    trading_platform: Arc<Mutex<TradingPlatform>>,
+) -> Result<impl warp::Reply, warp::Rejection> {
+    credential
+        .authorize(&query.signer, ApiKeyScope::Read)
+        .map_err(|e| warp::reject::custom(OctopusError(e)))?;
+    let ledger_lock = trading_platform.lock().unwrap();
+    match ledger_lock.order_lifecycle(id, &query.signer) {
+        Ok(lifecycle) => Ok(warp::reply::json(&lifecycle)),
+        Err(e) => Err(warp::reject::custom(OctopusError(e))),
+    }
+}
+
+async fn queue_position(
+    id: u64,
+    credential: Credential,
+    query: QueuePositionQuery,
+    trading_platform: Arc<Mutex<TradingPlatform>>,
+) -> Result<impl warp::Reply, warp::Rejection> {
+    credential
+        .authorize(&query.signer, ApiKeyScope::Read)
+        .map_err(|e| warp::reject::custom(OctopusError(e)))?;
+    let ledger_lock = trading_platform.lock().unwrap();
+    match ledger_lock.queue_position(id, &query.signer) {
+        Ok(position) => Ok(warp::reply::json(&position)),
+        Err(e) => Err(warp::reject::custom(OctopusError(e))),
+    }
+}
+
+async fn quote(
+    credential: Credential,
+    _permit: Permit,
+    request: QuoteRequest,
+    trading_platform: Arc<Mutex<TradingPlatform>>,
+) -> Result<impl warp::Reply, warp::Rejection> {
+    for order in [&request.bid, &request.ask] {
+        credential
+            .authorize(&order.signer, ApiKeyScope::Trade)
+            .map_err(|e| warp::reject::custom(OctopusError(e)))?;
+    }
+    let mut ledger_lock = trading_platform.lock().unwrap();
+    match ledger_lock.quote(request) {
+        Ok(receipt) => Ok(warp::reply::json(&receipt)),
+        Err(e) => Err(warp::reject::custom(OctopusError(e))),
+    }
+}
+
+async fn bracket(
+    credential: Credential,
+    _permit: Permit,
+    request: BracketRequest,
+    trading_platform: Arc<Mutex<TradingPlatform>>,
+) -> Result<impl warp::Reply, warp::Rejection> {
+    for order in [&request.take_profit, &request.stop_loss] {
+        credential
+            .authorize(&order.signer, ApiKeyScope::Trade)
+            .map_err(|e| warp::reject::custom(OctopusError(e)))?;
+    }
+    let mut ledger_lock = trading_platform.lock().unwrap();
+    match ledger_lock.bracket(request) {
+        Ok(receipt) => Ok(warp::reply::json(&receipt)),
+        Err(e) => Err(warp::reject::custom(OctopusError(e))),
+    }
+}
+
+async fn orderbook_updates(
+    query: BookUpdatesQuery,
+    trading_platform: Arc<Mutex<TradingPlatform>>,
+) -> Result<impl warp::Reply, warp::Rejection> {
+    let timeout = query
+        .timeout_secs
+        .unwrap_or(book_updates::DEFAULT_LONG_POLL_SECS)
+        .min(book_updates::MAX_LONG_POLL_SECS);
+    let mut latest = trading_platform.lock().unwrap().book_updates.subscribe();
+    // Wait without holding the lock, an empty update is returned on timeout
+    let _ = tokio::time::timeout(
+        Duration::from_secs(timeout),
+        latest.wait_for(|seq| *seq > query.since_seq),
+    )
+    .await;
+    let ledger_lock = trading_platform.lock().unwrap();
+    match ledger_lock.book_updates.since(query.since_seq) {
+        Ok(update) => Ok(warp::reply::json(&update)),
+        Err(e) => Err(warp::reject::custom(OctopusError(e))),
+    }
+}
+
+async fn orderbook_deltas(
+    query: BookDeltasQuery,
+    trading_platform: Arc<Mutex<TradingPlatform>>,
+) -> Result<impl warp::Reply, warp::Rejection> {
+    let ledger_lock = trading_platform.lock().unwrap();
+    match ledger_lock.book_updates.since(query.since_seq) {
+        Ok(update) => Ok(warp::reply::json(&update)),
+        Err(e) => Err(warp::reject::custom(OctopusError(e))),
+    }
+}
+
+async fn market_info(
+    symbol: String,
+    trading_platform: Arc<Mutex<TradingPlatform>>,
+) -> Result<impl warp::Reply, warp::Rejection> {
+    let ledger_lock = trading_platform.lock().unwrap();
+    match ledger_lock.market_info(&symbol) {
+        Ok(info) => Ok(warp::reply::json(&info)),
+        Err(e) => Err(warp::reject::custom(OctopusError(e))),
+    }
+}
+
+async fn ticker(
+    symbol: String,
+    trading_platform: Arc<Mutex<TradingPlatform>>,
+) -> Result<impl warp::Reply, warp::Rejection> {
+    let ledger_lock = trading_platform.lock().unwrap();
+    match ledger_lock.ticker(&symbol, scheduler::now_millis()) {
+        Ok(ticker) => Ok(warp::reply::json(&ticker)),
+        Err(e) => Err(warp::reject::custom(OctopusError(e))),
+    }
+}
+
+/// Like [`ticker`], for the market in the query
+async fn top_of_book(
+    query: TickerQuery,
+    trading_platform: Arc<Mutex<TradingPlatform>>,
+) -> Result<impl warp::Reply, warp::Rejection> {
+    ticker(query.market, trading_platform).await
+}
+
+async fn orderbook_snapshot(
+    query: BookQuery,
+    if_none_match: Option<String>,
+    trading_platform: Arc<Mutex<TradingPlatform>>,
+) -> Result<impl warp::Reply, warp::Rejection> {
+    let ledger_lock = trading_platform.lock().unwrap();
+    let snapshot = ledger_lock.book_snapshot_matching(&query);
+    Ok(etag::reply(
+        if_none_match.as_deref(),
+        snapshot.seq,
+        warp::reply::json(&snapshot),
+    ))
+}
+
+/// The aggregated best price levels, tagged with the sequence number like the snapshot
+async fn depth(
+    query: DepthQuery,
+    if_none_match: Option<String>,
+    trading_platform: Arc<Mutex<TradingPlatform>>,
+) -> Result<impl warp::Reply, warp::Rejection> {
+    let ledger_lock = trading_platform.lock().unwrap();
+    let depth = ledger_lock.depth(query.levels.unwrap_or(DEFAULT_DEPTH_LEVELS));
+    Ok(etag::reply(
+        if_none_match.as_deref(),
+        depth.seq,
+        warp::reply::json(&depth),
+    ))
+}
+
+/// Sends a snapshot of the price levels followed by every delta, and the reference price whenever it changed along
+/// with the book. Connections that fall behind further than the retained deltas get a new snapshot.
+async fn stream_orderbook(socket: WebSocket, trading_platform: Arc<Mutex<TradingPlatform>>) {
+    let (mut outgoing, mut incoming) = socket.split();
+    let (mut latest, snapshot, mut reference) = {
+        let ledger_lock = trading_platform.lock().unwrap();
+        (
+            ledger_lock.book_updates.subscribe(),
+            ledger_lock.book_snapshot(),
+            ledger_lock.reference_price(scheduler::now_millis()),
+        )
+    };
+    let mut seq = snapshot.seq;
+    let mut messages = vec![BookMessage::Snapshot(snapshot)];
+    messages.extend(reference.clone().map(BookMessage::Reference));
+    loop {
+        for message in messages.drain(..) {
+            let text = serde_json::to_string(&message).expect("book messages serialize");
+            if outgoing.send(Message::text(text)).await.is_err() {
+                return;
+            }
+        }
+        tokio::select! {
+            changed = latest.changed() => {
+                if changed.is_err() {
+                    return;
+                }
+                let ledger_lock = trading_platform.lock().unwrap();
+                match ledger_lock.book_updates.since(seq) {
+                    Ok(update) => {
+                        seq = update.seq;
+                        messages.extend(update.deltas.into_iter().map(BookMessage::Delta));
+                    }
+                    Err(_) => {
+                        let snapshot = ledger_lock.book_snapshot();
+                        seq = snapshot.seq;
+                        messages.push(BookMessage::Snapshot(snapshot));
+                    }
+                }
+                let current = ledger_lock.reference_price(scheduler::now_millis());
+                if current != reference {
+                    reference = current;
+                    messages.extend(reference.clone().map(BookMessage::Reference));
+                }
+            }
+            // Anything the client sends is ignored, the stream ends when it closes the connection
+            message = incoming.next() => match message {
+                Some(Ok(message)) if !message.is_close() => {}
+                _ => return,
+            },
+        }
+    }
+}
+
+/// Opens the private channel of an account, if the credential may read or trade for it
+async fn account_channel(
+    ws: warp::ws::Ws,
+    credential: Credential,
+    query: AccountChannelQuery,
+    trading_platform: Arc<Mutex<TradingPlatform>>,
+) -> Result<impl warp::Reply, warp::Rejection> {
+    let channel = PrivateChannel::open(credential, query.signer)
+        .map_err(|e| warp::reject::custom(OctopusError(e)))?;
+    let updates = trading_platform.lock().unwrap().account_feed.subscribe();
+    Ok(ws.on_upgrade(move |socket| private_channel::serve(socket, channel, updates)))
+}
+
+async fn graphql_request(
+    credential: Credential,
+    (schema, request): (OctopusSchema, async_graphql::Request),
+    trading_platform: Arc<Mutex<TradingPlatform>>,
+    order_queue: Arc<OrderQueue>,
+    startup: Arc<Startup>,
+) -> Result<impl warp::Reply, warp::Rejection> {
+    let request = request
+        .data(credential)
+        .data(trading_platform)
+        .data(order_queue)
+        .data(startup);
+    Ok(GraphQLResponse::from(schema.execute(request).await))
+}
+
+/// Serves GraphQL subscriptions of the selected tenant over a websocket, bound to the credential of the upgrade
+/// request like queries
+fn graphql_subscription(
+    ws: warp::ws::Ws,
+    credential: Credential,
+    protocol: WebSocketProtocols,
+    schema: OctopusSchema,
+    trading_platform: Arc<Mutex<TradingPlatform>>,
+) -> impl warp::Reply {
+    let reply = ws.on_upgrade(move |socket| {
+        let mut data = async_graphql::Data::default();
+        data.insert(credential);
+        data.insert(trading_platform);
+        GraphQLWebSocket::new(socket, schema, protocol)
+            .with_data(data)
+            .serve()
+    });
+    warp::reply::with_header(
+        reply,
+        "sec-websocket-protocol",
+        protocol.sec_websocket_protocol(),
+    )
+}
+
+/// The current book of the default market is tagged with its sequence number, past books and other markets' books
+/// aren't
+async fn orderbook(
+    query: PointInTimeQuery,
+    book_query: BookQuery,
+    if_none_match: Option<String>,
+    trading_platform: Arc<Mutex<TradingPlatform>>,
+) -> Result<warp::reply::Response, warp::Rejection> {
+    let ledger_lock = trading_platform.lock().unwrap();
+    match query.at {
+        Some(at) => PointInTime::parse(&at)
+            .map(|at| warp::reply::json(&ledger_lock.orderbook_at(at, &book_query)).into_response())
+            .map_err(|e| warp::reject::custom(OctopusError(e))),
+        None => {
+            let orders = ledger_lock
+                .orderbook_matching(&book_query)
+                .map_err(|e| warp::reject::custom(OctopusError(e)))?;
+            // Only the default market's book has a sequence number
+            match book_query.market.as_deref().unwrap_or(DEFAULT_MARKET) {
+                DEFAULT_MARKET => Ok(etag::reply(
+                    if_none_match.as_deref(),
+                    ledger_lock.book_updates.seq(),
+                    warp::reply::json(&orders),
+                )),
+                _ => Ok(warp::reply::json(&orders).into_response()),
+            }
+        }
+    }
+}
+
+async fn effective_config(
+    _credential: Credential,
+    config: Arc<RwLock<Config>>,
+) -> Result<impl warp::Reply, warp::Rejection> {
+    Ok(warp::reply::json(&config.read().unwrap().redacted()))
+}
+
+async fn reload_config(
+    credential: Credential,
+    auditor: Auditor,
+    args: Arc<Args>,
+    config: Arc<RwLock<Config>>,
+    tenants: Arc<Tenants>,
+) -> Result<impl warp::Reply, warp::Rejection> {
+    let before = config.read().unwrap().redacted();
+    match config::reload(&args, &config, &tenants) {
+        Ok(config) => {
+            let after = config.redacted();
+            auditor.record(
+                credential.actor(),
+                AuditAction::ConfigReloaded,
+                before,
+                &after,
+            );
+            Ok(warp::reply::json(&after))
+        }
+        Err(e) => Err(warp::reject::custom(OctopusError(e))),
+    }
+}
+
+async fn audit_trail(
+    _credential: Credential,
+    query: AuditQuery,
+    audit_log: Arc<AuditLog>,
+) -> Result<impl warp::Reply, warp::Rejection> {
+    Ok(warp::reply::json(&audit_log.query(&query)))
+}
+
+async fn status(startup: Arc<Startup>) -> Result<impl warp::Reply, warp::Rejection> {
+    Ok(warp::reply::json(&startup.phase()))
+}
+
+async fn metrics(
+    _credential: Credential,
+    tenants: Arc<Tenants>,
+    route_groups: Arc<RouteGroups>,
+) -> Result<impl warp::Reply, warp::Rejection> {
+    Ok(warp::reply::with_header(
+        metrics::render(&tenants, &route_groups),
+        "content-type",
+        "text/plain; version=0.0.4",
+    ))
+}
+
+#[cfg(feature = "chaos")]
+async fn chaos_settings(_credential: Credential) -> Result<impl warp::Reply, warp::Rejection> {
+    Ok(warp::reply::json(&chaos::settings()))
+}
+
+#[cfg(feature = "chaos")]
+async fn configure_chaos(
+    credential: Credential,
+    settings: chaos::ChaosSettings,
+    auditor: Auditor,
+) -> Result<impl warp::Reply, warp::Rejection> {
+    let before = chaos::settings();
+    match chaos::configure(settings) {
+        Ok(settings) => {
+            auditor.record(
+                credential.actor(),
+                AuditAction::ChaosConfigured,
+                before,
+                &settings,
+            );
+            Ok(warp::reply::json(&settings))
+        }
+        Err(e) => Err(warp::reject::custom(OctopusError(e))),
+    }
+}
+
+async fn transactions(
+    _credential: Credential,
+    trading_platform: Arc<Mutex<TradingPlatform>>,
+) -> Result<impl warp::Reply, warp::Rejection> {
+    let ledger_lock = trading_platform.lock().unwrap();
+    Ok(warp::reply::json(&ledger_lock.transactions))
+}
+
+/// The transactions and audit entries of the tenant matching the query
+async fn search_transactions(
+    _credential: Credential,
+    _permit: Permit,
+    query: SearchQuery,
+    auditor: Auditor,
+    trading_platform: Arc<Mutex<TradingPlatform>>,
+) -> Result<impl warp::Reply, warp::Rejection> {
+    let transactions = trading_platform.lock().unwrap().balance_log.search(&query);
+    Ok(warp::reply::json(&SearchResults {
+        transactions,
+        audit_entries: auditor.log.search(&query, &auditor.tenant),
+    }))
+}
+
+/// A page of the trade tape with the buyer and seller of each trade
+async fn trade_tape(
+    _credential: Credential,
+    query: TradesQuery,
+    trading_platform: Arc<Mutex<TradingPlatform>>,
+) -> Result<impl warp::Reply, warp::Rejection> {
+    let ledger_lock = trading_platform.lock().unwrap();
+    Ok(warp::reply::json(&ledger_lock.trade_tape(&query)))
+}
+
+/// Applies the seed file to the default tenant, reporting the progress as the startup phase
+fn replay_seed(path: &Path, tenants: &Tenants, startup: &Startup) -> Result<(), ApplicationError> {
+    let seed = seed::SeedData::load(path)?;
+    let total = seed.events();
+    startup.advance(StartupPhase::Replaying { applied: 0, total });
+    let trading_platform = tenants.get(tenants::DEFAULT_TENANT)?;
+    let mut ledger_lock = trading_platform.lock().unwrap();
+    seed.apply(&mut ledger_lock, |applied| {
+        startup.advance(StartupPhase::Replaying { applied, total })
+    })?;
+    println!(
+        "Seeded tenant {} from {}",
+        tenants::DEFAULT_TENANT,
+        path.display()
+    );
+    Ok(())
+}
+
+#[tokio::main]
+async fn main() {
+    pretty_env_logger::init();
+
+    let args = Arc::new(Args::parse());
+    let config = match Config::load(&args) {
+        Ok(config) => config,
+        Err(e) => panic!("Invalid configuration: {:?}", e),
+    };
+
+    let (admin_key, generated_secret) = AdminKey::from_config(config.auth.admin_key.as_deref());
+    if let Some(secret) = generated_secret {
+        println!(
+            "No auth.admin_key configured, using the generated admin key: {}",
+            secret
+        );
+    }
+    let admin_key = Arc::new(admin_key);
+
+    let (gateway_key, generated_secret) =
+        GatewayKey::from_config(config.auth.gateway_secret.as_deref());
+    if let Some(secret) = generated_secret {
+        println!(
+            "No auth.gateway_secret configured, using the generated gateway secret: {}",
+            secret
+        );
+    }
+    let gateway_key = Arc::new(gateway_key);
+    let gateway_key_state = warp::any().map(move || gateway_key.clone());
+
+    let names: Vec<&str> = config.tenants.iter().map(String::as_str).collect();
+    let tenants = match Tenants::new(&names, config.tenant_settings()) {
+        Ok(tenants) => Arc::new(tenants),
+        Err(e) => panic!("Invalid tenants: {:?}", e),
+    };
+    println!("Hosting tenants: {:?}", tenants.names());
+    let startup = Arc::new(Startup::default());
+    let audit_log = tenants.audit_log();
+    let seed_path = config.storage.seed.clone();
+    let demo = config.demo;
+    let (stop_servers, servers_stopped) = tokio::sync::watch::channel(false);
+    // Restores the state while the API is already up, starts everything that places orders on its own, and parks the
+    // open state on shutdown before the servers stop
+    let lifecycle = {
+        let startup = startup.clone();
+        let tenants = tenants.clone();
+        async move {
+            if let Some(path) = seed_path {
+                let seeded = tenants.clone();
+                let progress = startup.clone();
+                let result =
+                    tokio::task::spawn_blocking(move || replay_seed(&path, &seeded, &progress))
+                        .await
+                        .expect("Replaying the seed panicked");
+                if let Err(e) = result {
+                    panic!("Invalid seed: {:?}", e);
+                }
+            }
+            let restoring = tenants.clone();
+            let restored = tokio::task::spawn_blocking(move || restoring.restore_books())
+                .await
+                .expect("Restoring the books panicked");
+            match restored {
+                Ok(restored) => {
+                    for (name, orders, dropped) in restored {
+                        println!(
+                            "Restored {} parked orders of tenant {}, dropped {}",
+                            orders, name, dropped
+                        );
+                    }
+                }
+                Err(e) => panic!("Can't restore the parked books: {:?}", e),
+            }
+            startup.advance(StartupPhase::Serving);
+            let mut background = vec![scheduler::start(tenants.clone(), scheduler::TICK)];
+            if demo {
+                background.extend(demo::start(&tenants, demo::DEMO_BOTS));
+                println!(
+                    "Demo mode: {} simulated traders are placing orders",
+                    demo::DEMO_BOTS
+                );
+            }
+
+            shutdown::signalled().await;
+            println!("Shutting down, open orders are parked");
+            startup.advance(StartupPhase::ShuttingDown);
+            for task in background {
+                task.abort();
+            }
+            let parked = tokio::task::spawn_blocking(move || tenants.park(scheduler::now_millis()))
+                .await
+                .expect("Parking the tenants panicked");
+            match parked {
+                Ok(markers) => {
+                    for (name, marker) in markers {
+                        println!("Parked tenant {}: {:?}", name, marker);
+                    }
+                }
+                Err(e) => log::error!("Parking failed: {:?}", e),
+            }
+            let _ = stop_servers.send(true);
+        }
+    };
+    let trading_platform_state = tenants::with_tenant(tenants.clone());
+    let order_queue_state = tenants::with_order_queue(tenants.clone());
+    let deadman_switches = Arc::new(deadman::DeadmanSwitches::new());
+    #[cfg(feature = "faucet")]
+    let faucet = Arc::new(faucet::Faucet::new(&config.faucet));
+    let address = (config.server.address, config.server.port);
+    let tcp = config.server.tcp;
+    let deadline =
+        deadline::with_deadline(Duration::from_secs(config.server.max_request_timeout_secs));
+    let route_groups = Arc::new(RouteGroups::new(&config.server.concurrency));
+    let order_permit = concurrency::with_permit(&route_groups.orders);
+    let export_permit = concurrency::with_permit(&route_groups.exports);
+    let query_permit = concurrency::with_permit(&route_groups.queries);
+    let route_groups_state = warp::any().map(move || route_groups.clone());
+    #[cfg(unix)]
+    let unix_socket = config.server.unix_socket.clone();
+    let config = Arc::new(RwLock::new(config));
+    #[cfg(unix)]
+    config::reload_on_hangup(
+        args.clone(),
+        config.clone(),
+        tenants.clone(),
+        audit_log.clone(),
+    );
+    let tenants_state = warp::any().map(move || tenants.clone());
+    let serving = startup::when_serving(startup.clone());
+    let maintenance = startup::unless_shutting_down(startup.clone());
+    let startup_state = warp::any().map(move || startup.clone());
+    let config_state = warp::any().map(move || config.clone());
+    let args_state = warp::any().map(move || args.clone());
+    let auditor = audit::with_auditor(audit_log.clone());
+    let audit_log_state = warp::any().map(move || audit_log.clone());
+    let schema = graphql::schema();
+    let graphql_state = async_graphql_warp::graphql(schema.clone());
+    let schema_state = warp::any().map(move || schema.clone());
+
+    // Every route group requires a different minimum role
+    let account_auth = auth::with_role(
+        Role::ReadOnly,
+        admin_key.clone(),
+        trading_platform_state.clone(),
+    );
+    let operator_auth = auth::with_role(
+        Role::Operator,
+        admin_key.clone(),
+        trading_platform_state.clone(),
+    );
+    let admin_auth = auth::with_role(
+        Role::Admin,
+        admin_key.clone(),
+        trading_platform_state.clone(),
+    );
+
+    // Every route is boxed, otherwise the nested filter types make compile times explode
+
+    // Account surface: a credential acting for the account
+    let post_account = warp::path!("account")
+        .and(warp::post())
+        .and(account_auth.clone())
+        .and(warp::body::json())
+        .and(warp::query::<PointInTimeQuery>())
+        .and(trading_platform_state.clone())
+        .and_then(balance_request)
+        .boxed();
+
+    let post_withdraw = warp::path!("account" / "withdraw")
+        .and(warp::post())
+        .and(account_auth.clone())
+        .and(warp::body::json())
+        .and(warp::header::optional::<String>(pins::PIN_HEADER))
+        .and(trading_platform_state.clone())
+        .and_then(withdraw)
+        .boxed();
+
+    let post_send = warp::path!("account" / "send")
+        .and(warp::post())
+        .and(account_auth.clone())
+        .and(warp::body::json())
+        .and(warp::header::optional::<String>(pins::PIN_HEADER))
+        .and(trading_platform_state.clone())
+        .and_then(send)
+        .boxed();
+
+    let post_payout = warp::path!("account" / "payout")
+        .and(warp::post())
+        .and(account_auth.clone())
+        .and(warp::body::json())
+        .and(warp::header::optional::<String>(pins::PIN_HEADER))
+        .and(trading_platform_state.clone())
+        .and_then(payout)
+        .boxed();
+
+    let put_recipient = warp::path!("account" / String / "recipients" / String)
+        .and(warp::put())
+        .and(account_auth.clone())
+        .and(warp::body::json())
+        .and(trading_platform_state.clone())
+        .and_then(save_recipient)
+        .boxed();
+
+    let delete_recipient = warp::path!("account" / String / "recipients" / String)
+        .and(warp::delete())
+        .and(account_auth.clone())
+        .and(trading_platform_state.clone())
+        .and_then(forget_recipient)
+        .boxed();
+
+    let get_recipients = warp::path!("account" / String / "recipients")
+        .and(warp::get())
+        .and(account_auth.clone())
+        .and(trading_platform_state.clone())
+        .and_then(recipients)
+        .boxed();
+
+    // Loosening a whitelist waits for the whitelist delay: new destinations become active, removed ones and a disabled
+    // whitelist stop applying only after it
+    let put_whitelist_entry = warp::path!("account" / String / "whitelist" / String)
+        .and(warp::put())
+        .and(account_auth.clone())
+        .and(trading_platform_state.clone())
+        .and_then(add_whitelist_entry)
+        .boxed();
+
+    let delete_whitelist_entry = warp::path!("account" / String / "whitelist" / String)
+        .and(warp::delete())
+        .and(account_auth.clone())
+        .and(trading_platform_state.clone())
+        .and_then(forget_whitelist_entry)
+        .boxed();
+
+    let delete_whitelist = warp::path!("account" / String / "whitelist")
+        .and(warp::delete())
+        .and(account_auth.clone())
+        .and(trading_platform_state.clone())
+        .and_then(disable_whitelist)
+        .boxed();
+
+    let get_whitelist = warp::path!("account" / String / "whitelist")
+        .and(warp::get())
+        .and(account_auth.clone())
+        .and(trading_platform_state.clone())
+        .and_then(whitelist)
+        .boxed();
+
+    let post_public_key = warp::path!("account" / String / "pubkeys")
+        .and(warp::post())
+        .and(account_auth.clone())
+        .and(warp::body::json())
+        .and(trading_platform_state.clone())
+        .and_then(register_public_key)
+        .boxed();
+
+    let get_public_keys = warp::path!("account" / String / "pubkeys")
+        .and(warp::get())
+        .and(account_auth.clone())
+        .and(trading_platform_state.clone())
+        .and_then(public_keys)
+        .boxed();
+
+    let put_pin = warp::path!("account" / String / "pin")
+        .and(warp::put())
+        .and(account_auth.clone())
+        .and(warp::body::json())
+        .and(trading_platform_state.clone())
+        .and_then(set_pin)
+        .boxed();
+
+    let put_cold = warp::path!("account" / String / "cold")
+        .and(warp::put())
+        .and(account_auth.clone())
+        .and(warp::body::json())
+        .and(trading_platform_state.clone())
+        .and_then(set_cold)
+        .boxed();
+
+    let put_dust_consent = warp::path!("account" / String / "dust")
+        .and(warp::put())
+        .and(account_auth.clone())
+        .and(warp::body::json())
+        .and(trading_platform_state.clone())
+        .and_then(set_dust_consent)
+        .boxed();
+
+    let get_account_withdrawals = warp::path!("account" / String / "withdrawals")
+        .and(warp::get())
+        .and(account_auth.clone())
+        .and(trading_platform_state.clone())
+        .and_then(account_withdrawals)
+        .boxed();
+
+    let post_withdrawal_confirmation =
+        warp::path!("account" / String / "withdrawals" / u64 / "confirm")
+            .and(warp::post())
+            .and(account_auth.clone())
+            .and(trading_platform_state.clone())
+            .and_then(confirm_withdrawal)
+            .boxed();
+
+    let post_hold = warp::path!("account" / "hold")
+        .and(warp::post())
+        .and(account_auth.clone())
+        .and(warp::body::json())
+        .and(trading_platform_state.clone())
+        .and_then(hold)
+        .boxed();
+
+    let post_capture = warp::path!("account" / "hold" / u64 / "capture")
+        .and(warp::post())
+        .and(account_auth.clone())
+        .and(warp::body::json())
+        .and(warp::header::optional::<String>(pins::PIN_HEADER))
+        .and(trading_platform_state.clone())
+        .and_then(capture)
+        .boxed();
+
+    let post_release = warp::path!("account" / "hold" / u64 / "release")
+        .and(warp::post())
+        .and(account_auth.clone())
+        .and(trading_platform_state.clone())
+        .and_then(release)
+        .boxed();
+
+    let post_api_key = warp::path!("account" / String / "apikeys")
+        .and(warp::post())
+        .and(account_auth.clone())
+        .and(warp::body::json())
+        .and(trading_platform_state.clone())
+        .and_then(create_api_key)
+        .boxed();
+
+    let get_api_keys = warp::path!("account" / String / "apikeys")
+        .and(warp::get())
+        .and(account_auth.clone())
+        .and(trading_platform_state.clone())
+        .and_then(api_keys)
+        .boxed();
+
+    let delete_api_key = warp::path!("account" / String / "apikeys" / String)
+        .and(warp::delete())
+        .and(account_auth.clone())
+        .and(trading_platform_state.clone())
+        .and_then(revoke_api_key)
+        .boxed();
+
+    let post_recurring_buy = warp::path!("account" / String / "recurring")
+        .and(warp::post())
+        .and(account_auth.clone())
+        .and(warp::body::json())
+        .and(trading_platform_state.clone())
+        .and_then(create_recurring_buy)
+        .boxed();
+
+    let get_recurring_buys = warp::path!("account" / String / "recurring")
+        .and(warp::get())
+        .and(account_auth.clone())
+        .and(trading_platform_state.clone())
+        .and_then(recurring_buys)
+        .boxed();
+
+    let post_recurring_buy_action = warp::path!("account" / String / "recurring" / u64 / String)
+        .and(warp::post())
+        .and(account_auth.clone())
+        .and(trading_platform_state.clone())
+        .and_then(update_recurring_buy)
+        .boxed();
+
+    let get_recurring_buy_executions =
+        warp::path!("account" / String / "recurring" / u64 / "executions")
+            .and(warp::get())
+            .and(account_auth.clone())
+            .and(trading_platform_state.clone())
+            .and_then(recurring_buy_executions)
+            .boxed();
+
+    let put_stop_loss = warp::path!("account" / String / "stoploss")
+        .and(warp::put())
+        .and(account_auth.clone())
+        .and(warp::body::json())
+        .and(trading_platform_state.clone())
+        .and_then(set_stop_loss)
+        .boxed();
+
+    // Cancels the account's open orders unless refreshed within the timeout
+    let post_deadman = warp::path!("account" / String / "deadman")
+        .and(warp::post())
+        .and(account_auth.clone())
+        .and(warp::query::<DeadmanQuery>())
+        .and(auditor.clone())
+        .and(warp::any().map(move || deadman_switches.clone()))
+        .and(trading_platform_state.clone())
+        .and_then(arm_deadman)
+        .boxed();
+
+    let get_stop_loss = warp::path!("account" / String / "stoploss")
+        .and(warp::get())
+        .and(account_auth.clone())
+        .and(trading_platform_state.clone())
+        .and_then(stop_loss)
+        .boxed();
+
+    let get_position = warp::path!("account" / String / "position")
+        .and(warp::get())
+        .and(account_auth.clone())
+        .and(trading_platform_state.clone())
+        .and_then(position)
+        .boxed();
+
+    let get_exposure = warp::path!("account" / String / "exposure")
+        .and(warp::get())
+        .and(account_auth.clone())
+        .and(trading_platform_state.clone())
+        .and_then(exposure)
+        .boxed();
+
+    let delete_account = warp::path!("account" / String)
+        .and(warp::delete())
+        .and(account_auth.clone())
+        .and(warp::header::optional::<String>(pins::PIN_HEADER))
+        .and(trading_platform_state.clone())
+        .and_then(close_account)
+        .boxed();
+
+    let put_transaction_tags = warp::path!("account" / String / "transactions" / u64 / "tags")
+        .and(warp::put())
+        .and(account_auth.clone())
+        .and(warp::body::json())
+        .and(trading_platform_state.clone())
+        .and_then(tag_transaction)
+        .boxed();
+
+    let get_account_statement = warp::path!("account" / String / "transactions")
+        .and(warp::get())
+        .and(account_auth.clone())
+        .and(warp::query::<StatementQuery>())
+        .and(trading_platform_state.clone())
+        .and_then(account_statement)
+        .boxed();
+
+    let get_account_export = warp::path!("account" / String / "export")
+        .and(warp::get())
+        .and(account_auth.clone())
+        .and(warp::query::<StatementQuery>())
+        .and(export_permit.clone())
+        .and(trading_platform_state.clone())
+        .and_then(export_account)
+        .boxed();
+
+    let get_account_archive = warp::path!("account" / String / "export" / u64)
+        .and(warp::get())
+        .and(account_auth.clone())
+        .and(trading_platform_state.clone())
+        .and_then(account_archive)
+        .boxed();
+
+    let get_account_stats = warp::path!("account" / String / "stats")
+        .and(warp::get())
+        .and(account_auth.clone())
+        .and(warp::query::<StatsQuery>())
+        .and(trading_platform_state.clone())
+        .and_then(account_stats)
+        .boxed();
+
+    let put_leaderboard_opt_in = warp::path!("account" / String / "leaderboard")
+        .and(warp::put())
+        .and(account_auth.clone())
+        .and(warp::body::json())
+        .and(trading_platform_state.clone())
+        .and_then(set_leaderboard_opt_in)
+        .boxed();
+
+    let get_invoices = warp::path!("account" / String / "invoices")
+        .and(warp::get())
+        .and(account_auth.clone())
+        .and(trading_platform_state.clone())
+        .and_then(invoices)
+        .boxed();
+
+    let get_invoice = warp::path!("account" / String / "invoices" / String)
+        .and(warp::get())
+        .and(account_auth.clone())
+        .and(warp::query::<DocumentQuery>())
+        .and(trading_platform_state.clone())
+        .and_then(invoice)
+        .boxed();
+
+    let post_ordet = warp::path!("order")
+        .and(warp::post())
+        .and(account_auth.clone())
+        .and(order_permit.clone())
+        .and(serving.clone())
+        .and(warp::query::<OrderQuery>())
+        .and(warp::body::json())
+        .and(deadline.clone())
+        .and(order_queue_state.clone())
+        .and_then(order)
+        .boxed();
+
+    let delete_order = warp::path!("order" / u64)
+        .and(warp::delete())
+        .and(account_auth.clone())
+        .and(warp::query::<CancelQuery>())
+        .and(trading_platform_state.clone())
+        .and_then(cancel_order)
+        .boxed();
+
+    // A new price or amount for a resting order, it keeps its id
+    let put_order = warp::path!("order" / u64)
+        .and(warp::put())
+        .and(account_auth.clone())
+        .and(serving.clone())
+        .and(warp::body::json())
+        .and(trading_platform_state.clone())
+        .and_then(amend_order)
+        .boxed();
+
+    // Every open order of the account matching a filter, in one pass over the book
+    let post_orders_cancel = warp::path!("orders" / "cancel")
+        .and(warp::post())
+        .and(account_auth.clone())
+        .and(warp::body::json())
+        .and(trading_platform_state.clone())
+        .and_then(cancel_orders)
+        .boxed();
+
+    // What happened to an order, for support staff as well as the account
+    let get_order_events = warp::path!("order" / u64 / "events")
+        .and(warp::get())
+        .and(account_auth.clone())
+        .and(query_permit.clone())
+        .and(warp::query::<OrderEventsQuery>())
+        .and(trading_platform_state.clone())
+        .and_then(order_events)
+        .boxed();
+
+    // How much rests ahead of an order at its price, for market makers deciding whether to reprice
+    let get_queue_position = warp::path!("order" / u64 / "queue")
+        .and(warp::get())
+        .and(account_auth.clone())
+        .and(warp::query::<QueuePositionQuery>())
+        .and(trading_platform_state.clone())
+        .and_then(queue_position)
+        .boxed();
+
+    // Both sides of a quote in one step, replacing the account's previous quote
+    let post_quote = warp::path!("quote")
+        .and(warp::post())
+        .and(account_auth.clone())
+        .and(order_permit.clone())
+        .and(serving.clone())
+        .and(warp::body::json())
+        .and(trading_platform_state.clone())
+        .and_then(quote)
+        .boxed();
+
+    // A take-profit and a stop-loss in one step, one cancels the other
+    let post_bracket = warp::path!("order" / "bracket")
+        .and(warp::post())
+        .and(account_auth.clone())
+        .and(order_permit.clone())
+        .and(serving.clone())
+        .and(warp::body::json())
+        .and(trading_platform_state.clone())
+        .and_then(bracket)
+        .boxed();
+
+    // Trading sessions are bound to the credential of the upgrade request
+    let get_trade_ws = warp::path!("trade" / "ws")
+        .and(warp::ws())
+        .and(account_auth.clone())
+        .and(serving.clone())
+        .and(warp::query::<TradingSessionQuery>())
+        .and(order_queue_state.clone())
+        .and(trading_platform_state.clone())
+        .map(
+            |ws: warp::ws::Ws,
+             credential,
+             query: TradingSessionQuery,
+             order_queue,
+             trading_platform| {
+                let session = TradingSession::new(
+                    credential,
+                    query.cancel_on_disconnect,
+                    order_queue,
+                    trading_platform,
+                );
+                ws.on_upgrade(move |socket| sessions::serve(socket, session))
+            },
+        )
+        .boxed();
+
+    // Private channels are bound to the credential of the upgrade request
+    let get_account_ws = warp::path!("account" / "ws")
+        .and(warp::ws())
+        .and(account_auth.clone())
+        .and(warp::query::<AccountChannelQuery>())
+        .and(trading_platform_state.clone())
+        .and_then(account_channel)
+        .boxed();
+
+    // Operator surface: funding accounts and inspecting the ledger
+    let post_deposit = warp::path!("account" / "deposit")
+        .and(warp::post())
+        .and(operator_auth.clone())
+        .and(warp::body::json())
+        .and(auditor.clone())
+        .and(trading_platform_state.clone())
+        .and_then(deposit)
+        .boxed();
+
+    let post_sandbox_funds = warp::path!("account" / "sandbox")
+        .and(warp::post())
+        .and(operator_auth.clone())
+        .and(warp::body::json())
+        .and(auditor.clone())
+        .and(trading_platform_state.clone())
+        .and_then(fund_sandbox)
+        .boxed();
+
+    let get_transactions = warp::path!("txlog")
+        .and(warp::get())
+        .and(operator_auth.clone())
+        .and(trading_platform_state.clone())
+        .and_then(transactions)
+        .boxed();
+
+    let get_search_transactions = warp::path!("search" / "transactions")
+        .and(warp::get())
+        .and(operator_auth.clone())
+        .and(query_permit.clone())
+        .and(warp::query::<SearchQuery>())
+        .and(auditor.clone())
+        .and(trading_platform_state.clone())
+        .and_then(search_transactions)
+        .boxed();
+
+    // Names the counterparties, unlike the public market data
+    let get_trades = warp::path!("trades")
+        .and(warp::get())
+        .and(operator_auth.clone())
+        .and(warp::query::<TradesQuery>())
+        .and(trading_platform_state.clone())
+        .and_then(trade_tape)
+        .boxed();
+
+    let get_metrics = warp::path!("metrics")
+        .and(warp::get())
+        .and(operator_auth.clone())
+        .and(tenants_state.clone())
+        .and(route_groups_state.clone())
+        .and_then(metrics)
+        .boxed();
+
+    // Admin surface
+    let post_admin_api_key = warp::path!("admin" / "apikeys")
+        .and(warp::post())
+        .and(admin_auth.clone())
+        .and(warp::body::json())
+        .and(auditor.clone())
+        .and(trading_platform_state.clone())
+        .and_then(issue_api_key)
+        .boxed();
+
+    let get_withdrawals = warp::path!("admin" / "withdrawals")
+        .and(warp::get())
+        .and(admin_auth.clone())
+        .and(trading_platform_state.clone())
+        .and_then(pending_withdrawals)
+        .boxed();
+
+    let post_withdrawal_decision = warp::path!("admin" / "withdrawals" / u64 / String)
+        .and(warp::post())
+        .and(admin_auth.clone())
+        .and(auditor.clone())
+        .and(trading_platform_state.clone())
+        .and_then(resolve_withdrawal)
+        .boxed();
+
+    // Reverses the settlement of an erroneous trade, the trade stays on the tape marked as busted
+    let post_trade_bust = warp::path!("admin" / "trades" / u64 / "bust")
+        .and(warp::post())
+        .and(admin_auth.clone())
+        .and(auditor.clone())
+        .and(trading_platform_state.clone())
+        .and_then(bust_trade)
+        .boxed();
+
+    // Call auctions for the open and close: orders accumulate, then cross at a single price
+    let post_auction = warp::path!("admin" / "auction")
+        .and(warp::post())
+        .and(admin_auth.clone())
+        .and(auditor.clone())
+        .and(trading_platform_state.clone())
+        .and_then(start_auction)
+        .boxed();
+
+    let post_auction_run = warp::path!("admin" / "auction" / "run")
+        .and(warp::post())
+        .and(admin_auth.clone())
+        .and(auditor.clone())
+        .and(trading_platform_state.clone())
+        .and_then(run_auction)
+        .boxed();
+
+    let put_market = warp::path!("admin" / "markets" / String)
+        .and(warp::put())
+        .and(admin_auth.clone())
+        .and(warp::body::json())
+        .and(auditor.clone())
+        .and(trading_platform_state.clone())
+        .and_then(list_market)
+        .boxed();
+
+    // The reference price of a market while it doesn't trade
+    let put_oracle_price = warp::path!("admin" / "markets" / String / "oracle")
+        .and(warp::put())
+        .and(admin_auth.clone())
+        .and(warp::body::json())
+        .and(auditor.clone())
+        .and(trading_platform_state.clone())
+        .and_then(set_oracle_price)
+        .boxed();
+
+    let post_invoices = warp::path!("admin" / "invoices" / String)
+        .and(warp::post())
+        .and(admin_auth.clone())
+        .and(auditor.clone())
+        .and(trading_platform_state.clone())
+        .and_then(generate_invoices)
+        .boxed();
+
+    let get_report = warp::path!("admin" / "reports" / String)
+        .and(warp::get())
+        .and(admin_auth.clone())
+        .and(warp::query::<DocumentQuery>())
+        .and(trading_platform_state.clone())
+        .and_then(report)
+        .boxed();
+
+    let post_report = warp::path!("admin" / "reports" / String)
+        .and(warp::post())
+        .and(admin_auth.clone())
+        .and(auditor.clone())
+        .and(trading_platform_state.clone())
+        .and_then(generate_report)
+        .boxed();
+
+    // Open interest, book concentration, and turnover by market for surveillance
+    let get_analytics = warp::path!("admin" / "analytics")
+        .and(warp::get())
+        .and(admin_auth.clone())
+        .and(query_permit.clone())
+        .and(warp::query::<StatsQuery>())
+        .and(trading_platform_state.clone())
+        .and_then(analytics)
+        .boxed();
+
+    let get_dust = warp::path!("admin" / "dust")
+        .and(warp::get())
+        .and(admin_auth.clone())
+        .and(trading_platform_state.clone())
+        .and_then(dust_report)
+        .boxed();
+
+    // Operators follow the order events of every public book
+    let get_engine_events_ws = warp::path!("admin" / "events" / "ws")
+        .and(warp::ws())
+        .and(admin_auth.clone())
+        .and(trading_platform_state.clone())
+        .map(
+            |ws: warp::ws::Ws,
+             _credential: Credential,
+             trading_platform: Arc<Mutex<TradingPlatform>>| {
+                let events = trading_platform.lock().unwrap().engine_events.subscribe();
+                ws.on_upgrade(move |socket| engine_events::serve(socket, events))
+            },
+        )
+        .boxed();
+
+    let get_state = warp::path!("admin" / "state")
+        .and(warp::get())
+        .and(admin_auth.clone())
+        .and(trading_platform_state.clone())
+        .and_then(state_digest)
+        .boxed();
+
+    let post_state_compare = warp::path!("admin" / "state" / "compare")
+        .and(warp::post())
+        .and(admin_auth.clone())
+        .and(warp::body::json())
+        .and(trading_platform_state.clone())
+        .and_then(compare_state)
+        .boxed();
+
+    let get_export_transactions = warp::path!("admin" / "export" / "transactions")
+        .and(warp::get())
+        .and(admin_auth.clone())
+        .and(export_permit.clone())
+        .and(deadline.clone())
+        .and(trading_platform_state.clone())
+        .and_then(export_transactions)
+        .boxed();
+
+    let get_export_trades = warp::path!("admin" / "export" / "trades")
+        .and(warp::get())
+        .and(admin_auth.clone())
+        .and(export_permit.clone())
+        .and(deadline.clone())
+        .and(trading_platform_state.clone())
+        .and_then(export_trades)
+        .boxed();
+
+    let get_tenants = warp::path!("admin" / "tenants")
+        .and(warp::get())
+        .and(admin_auth.clone())
+        .and(tenants_state.clone())
+        .and_then(list_tenants)
+        .boxed();
+
+    let post_tenant = warp::path!("admin" / "tenants")
+        .and(warp::post())
+        .and(admin_auth.clone())
+        .and(warp::body::json())
+        .and(auditor.clone())
+        .and(tenants_state.clone())
+        .and_then(create_tenant)
+        .boxed();
+
+    let get_config = warp::path!("admin" / "config")
+        .and(warp::get())
+        .and(admin_auth.clone())
+        .and(config_state.clone())
+        .and_then(effective_config)
+        .boxed();
+
+    let post_config_reload = warp::path!("admin" / "config" / "reload")
+        .and(warp::post())
+        .and(admin_auth.clone())
+        .and(auditor.clone())
+        .and(args_state.clone())
+        .and(config_state.clone())
+        .and(tenants_state.clone())
+        .and_then(reload_config)
+        .boxed();
+
+    let get_audit = warp::path!("admin" / "audit")
+        .and(warp::get())
+        .and(admin_auth.clone())
+        .and(warp::query::<AuditQuery>())
+        .and(audit_log_state.clone())
+        .and_then(audit_trail)
+        .boxed();
+
+    // Fault injection, only with the `chaos` feature
+    #[cfg(feature = "chaos")]
+    let get_chaos = warp::path!("admin" / "chaos")
+        .and(warp::get())
+        .and(admin_auth.clone())
+        .and_then(chaos_settings)
+        .boxed();
+
+    #[cfg(feature = "chaos")]
+    let put_chaos = warp::path!("admin" / "chaos")
+        .and(warp::put())
+        .and(admin_auth.clone())
+        .and(warp::body::json())
+        .and(auditor.clone())
+        .and_then(configure_chaos)
+        .boxed();
+
+    // Self-service play funds, only with the `faucet` feature
+    #[cfg(feature = "faucet")]
+    let post_faucet = warp::path!("faucet")
+        .and(warp::post())
+        .and(account_auth.clone())
+        .and(warp::body::json())
+        .and(auditor.clone())
+        .and(warp::any().map(move || faucet.clone()))
+        .and(trading_platform_state.clone())
+        .and_then(claim_faucet)
+        .boxed();
+
+    // Payment gateway callbacks, authenticated by their signature
+    let post_gateway_deposit = warp::path!("gateway" / "deposit")
+        .and(warp::post())
+        .and(warp::header::optional::<String>(
+            gateway::GATEWAY_SIGNATURE_HEADER,
+        ))
+        .and(warp::body::content_length_limit(16 * 1024))
+        .and(warp::body::bytes())
+        .and(gateway_key_state.clone())
+        .and(trading_platform_state.clone())
+        .and_then(gateway_deposit)
+        .boxed();
+
+    // Public market data
+    let get_market_info = warp::path!("markets" / String / "info")
+        .and(warp::get())
+        .and(trading_platform_state.clone())
+        .and_then(market_info)
+        .boxed();
+
+    let get_ticker = warp::path!("markets" / String / "ticker")
+        .and(warp::get())
+        .and(trading_platform_state.clone())
+        .and_then(ticker)
+        .boxed();
+
+    let get_top_of_book = warp::path!("ticker")
+        .and(warp::get())
+        .and(warp::query::<TickerQuery>())
+        .and(trading_platform_state.clone())
+        .and_then(top_of_book)
+        .boxed();
+
+    // GraphQL: queries, mutations, and subscriptions need a credential
+    let post_graphql = warp::path!("graphql")
+        .and(warp::post())
+        .and(account_auth.clone())
+        .and(graphql_state)
+        .and(trading_platform_state.clone())
+        .and(order_queue_state.clone())
+        .and(startup_state.clone())
+        .and_then(graphql_request)
+        .boxed();
+
+    let get_graphql_ws = warp::path!("graphql" / "ws")
+        .and(warp::ws())
+        .and(account_auth.clone())
+        .and(async_graphql_warp::graphql_protocol())
+        .and(schema_state.clone())
+        .and(trading_platform_state.clone())
+        .map(graphql_subscription)
+        .boxed();
+
+    let get_orderbook_updates = warp::path!("orderbook" / "updates")
+        .and(warp::get())
+        .and(warp::query::<BookUpdatesQuery>())
+        .and(trading_platform_state.clone())
+        .and_then(orderbook_updates)
+        .boxed();
+
+    let get_orderbook_deltas = warp::path!("orderbook" / "deltas")
+        .and(warp::get())
+        .and(warp::query::<BookDeltasQuery>())
+        .and(trading_platform_state.clone())
+        .and_then(orderbook_deltas)
+        .boxed();
+
+    let get_orderbook_snapshot = warp::path!("orderbook" / "snapshot")
+        .and(warp::get())
+        .and(warp::query::<BookQuery>())
+        .and(warp::header::optional::<String>("if-none-match"))
+        .and(trading_platform_state.clone())
+        .and_then(orderbook_snapshot)
+        .boxed();
+
+    let get_depth = warp::path!("depth")
+        .and(warp::get())
+        .and(warp::query::<DepthQuery>())
+        .and(warp::header::optional::<String>("if-none-match"))
+        .and(trading_platform_state.clone())
+        .and_then(depth)
+        .boxed();
+
+    let get_orderbook_ws = warp::path!("orderbook" / "ws")
+        .and(warp::ws())
+        .and(trading_platform_state.clone())
+        .map(|ws: warp::ws::Ws, trading_platform| {
+            ws.on_upgrade(move |socket| stream_orderbook(socket, trading_platform))
+        })
+        .boxed();
+
+    let get_orderbook = warp::path!("orderbook")
+        .and(warp::get())
+        .and(warp::query::<PointInTimeQuery>())
+        .and(warp::query::<BookQuery>())
+        .and(warp::header::optional::<String>("if-none-match"))
+        .and(trading_platform_state.clone())
+        .and_then(orderbook)
+        .boxed();
+
+    let get_status = warp::path!("status")
+        .and(warp::get())
+        .and(startup_state.clone())
+        .and_then(status)
+        .boxed();
+
+    let get_sandbox_orderbook = warp::path!("sandbox" / "orderbook")
+        .and(warp::get())
+        .and(trading_platform_state.clone())
+        .and_then(sandbox_orderbook)
+        .boxed();
+
+    let get_dashboard = dashboard::route().boxed();
+
+    let get_leaderboard = warp::path!("leaderboard")
         .and(warp::get())
+        .and(warp::query::<LeaderboardQuery>())
         .and(trading_platform_state.clone())
-        .and_then(transactions);
+        .and_then(leaderboard)
+        .boxed();
 
-    // Combine routes
-    let routes = post_account
+    // Combine routes, boxed per group so the combined type stays within the compiler's limits
+    let account_routes = post_account
         .or(post_deposit)
+        .or(post_sandbox_funds)
+        .or(post_gateway_deposit)
         .or(post_withdraw)
+        .or(put_pin)
+        .or(put_cold)
+        .or(put_dust_consent)
+        .or(put_transaction_tags)
+        .or(get_account_statement)
+        .or(get_account_withdrawals)
+        .or(post_withdrawal_confirmation)
+        .or(put_recipient)
+        .or(delete_recipient)
+        .or(get_recipients)
+        .or(put_whitelist_entry)
+        .or(delete_whitelist_entry)
+        .or(delete_whitelist)
+        .or(get_whitelist)
+        .or(post_public_key)
+        .or(get_public_keys)
+        .boxed();
+    // Boxed in two parts, one chain this long overflows the compiler's depth limit
+    let account_routes = account_routes
         .or(post_send)
-        .or(post_ordet)
+        .or(post_payout)
+        .or(post_hold)
+        .or(post_capture)
+        .or(post_release)
+        .or(post_recurring_buy)
+        .or(get_recurring_buys)
+        .or(post_recurring_buy_action)
+        .or(get_recurring_buy_executions)
+        .or(put_stop_loss)
+        .or(post_deadman)
+        .or(get_stop_loss)
+        .or(get_position)
+        .or(get_exposure)
+        .or(get_account_stats)
+        .or(delete_account)
+        .or(get_account_export)
+        .or(get_account_archive)
+        .or(put_leaderboard_opt_in)
+        .or(get_invoices)
+        .or(get_invoice)
+        .or(post_api_key)
+        .or(get_api_keys)
+        .or(delete_api_key)
+        .boxed();
+    let admin_routes = post_admin_api_key
+        .or(get_withdrawals)
+        .or(post_withdrawal_decision)
+        .or(post_trade_bust)
+        .or(post_auction)
+        .or(post_auction_run)
+        .or(put_market)
+        .or(put_oracle_price)
+        .or(post_invoices)
+        .or(get_report)
+        .or(post_report)
+        .or(get_analytics)
+        .or(get_dust)
+        .or(get_state)
+        .or(get_engine_events_ws)
+        .or(post_state_compare)
+        .or(get_export_transactions)
+        .or(get_export_trades)
+        .or(get_tenants)
+        .or(post_tenant)
+        .or(get_config)
+        .or(post_config_reload)
+        .or(get_audit)
+        .boxed();
+    let market_routes = post_ordet
+        .or(delete_order)
+        .or(put_order)
+        .or(post_orders_cancel)
+        .or(get_order_events)
+        .or(get_queue_position)
+        .or(post_quote)
+        .or(post_bracket)
+        .or(get_trade_ws)
+        .or(get_account_ws)
         .or(get_orderbook)
+        .or(get_orderbook_updates)
+        .or(get_orderbook_deltas)
+        .or(get_orderbook_snapshot)
+        .or(get_depth)
+        .or(get_orderbook_ws)
+        .or(get_market_info)
+        .or(get_ticker)
+        .or(get_top_of_book)
+        .or(get_sandbox_orderbook)
+        .or(post_graphql)
+        .or(get_graphql_ws)
+        .or(get_leaderboard)
         .or(get_transactions)
+        .or(get_search_transactions)
+        .or(get_trades)
+        .or(get_metrics)
+        .or(get_status)
+        .or(get_dashboard)
+        .boxed();
+    let routes = account_routes.or(admin_routes).or(market_routes).boxed();
+    #[cfg(feature = "chaos")]
+    let routes = chaos::delay()
+        .and(routes.or(get_chaos).or(put_chaos))
+        .boxed();
+    #[cfg(feature = "faucet")]
+    let routes = routes.or(post_faucet).boxed();
+    let routes = maintenance
+        .and(routes)
+        .recover(rejection::handle_rejection)
         .with(warp::cors().allow_any_origin());
 
-    println!("Server running on http://localhost:3000");
-    warp::serve(routes).run(([0, 0, 0, 0], 3000)).await;
+    let stopped = |mut servers_stopped: tokio::sync::watch::Receiver<bool>| async move {
+        let _ = servers_stopped.wait_for(|stopped| *stopped).await;
+    };
+    let tcp_server = async {
+        if tcp {
+            let (_, server) = warp::serve(routes.clone())
+                .bind_with_graceful_shutdown(address, stopped(servers_stopped.clone()));
+            println!("Server running on http://localhost:{}", address.1);
+            server.await;
+        }
+    };
+    let unix_server = async {
+        #[cfg(unix)]
+        if let Some(path) = &unix_socket {
+            let incoming = match unix_socket::bind(path) {
+                Ok(incoming) => incoming,
+                Err(e) => panic!("Can't listen on {}: {}", path.display(), e),
+            };
+            println!("Server running on unix:{}", path.display());
+            warp::serve(routes.clone())
+                .serve_incoming_with_graceful_shutdown(incoming, stopped(servers_stopped.clone()))
+                .await;
+        }
+    };
+    tokio::join!(lifecycle, tcp_server, unix_server);
 }
@@ -0,0 +1,216 @@
+use octopus_common::{
+    errors::ApplicationError,
+    types::{
+        FeeTier, MarketInfo, MarketStatus, Order, OrderConstraint, OrderType, TradingHours,
+        DEFAULT_MARKET,
+    },
+};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+use crate::core::BookCapacity;
+
+/// The parameters of a market. Missing fields take the default.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct MarketConfig {
+    pub tick_size: u64,
+    pub lot_size: u64,
+    /// The smallest amount of an order, 0 if any lot will do
+    pub min_amount: u64,
+    /// The largest amount of an order, unlimited if not set
+    pub max_amount: Option<u64>,
+    pub trading_hours: TradingHours,
+    pub status: MarketStatus,
+    /// Sandbox accounts may trade here, in a book of their own
+    pub sandbox: bool,
+    /// Price levels allocated up front on each side of the book, 0 allocates each level as it opens
+    pub preallocated_levels: usize,
+    /// Resting orders each preallocated level has room for
+    pub orders_per_level: usize,
+}
+
+impl Default for MarketConfig {
+    /// Any price and amount, traded around the clock
+    fn default() -> Self {
+        MarketConfig {
+            tick_size: 1,
+            lot_size: 1,
+            min_amount: 0,
+            max_amount: None,
+            trading_hours: TradingHours {
+                timezone: "UTC".to_string(),
+                open: "00:00".to_string(),
+                close: "24:00".to_string(),
+            },
+            status: MarketStatus::Open,
+            sandbox: false,
+            preallocated_levels: 0,
+            orders_per_level: 0,
+        }
+    }
+}
+
+impl MarketConfig {
+    /// Checks that the market of `symbol` can be traded with this configuration
+    /// # Errors
+    /// The tick or lot size is 0, or the amount limits exclude every order
+    pub fn validate(&self, symbol: &str) -> Result<(), ApplicationError> {
+        let invalid = |reason: String| Err(ApplicationError::InvalidConfig(reason));
+        if self.tick_size == 0 || self.lot_size == 0 {
+            return invalid(format!(
+                "the tick and lot size of market {} must be positive",
+                symbol
+            ));
+        }
+        if self
+            .max_amount
+            .is_some_and(|max| max < self.min_amount.max(self.lot_size))
+        {
+            return invalid(format!(
+                "the max_amount of market {} is below its smallest order",
+                symbol
+            ));
+        }
+        Ok(())
+    }
+
+    /// Checks the price and amount of `order` against the market's increments and limits. Market orders take any
+    /// price, their trigger price still has to be on a tick.
+    /// # Errors
+    /// The order violates a constraint, [`ApplicationError::InvalidOrder`] names it
+    pub fn check(&self, order: &Order) -> Result<(), ApplicationError> {
+        let limit_price = (order.order_type == OrderType::Limit).then_some(order.price);
+        let violated = if let Some(price) = limit_price
+            .into_iter()
+            .chain(order.trigger_price)
+            .find(|price| !price.is_multiple_of(self.tick_size))
+        {
+            OrderConstraint::TickSize {
+                price,
+                tick_size: self.tick_size,
+            }
+        } else if !order.amount.is_multiple_of(self.lot_size) {
+            OrderConstraint::LotSize {
+                amount: order.amount,
+                lot_size: self.lot_size,
+            }
+        } else if order.amount < self.min_amount {
+            OrderConstraint::MinAmount {
+                amount: order.amount,
+                min_amount: self.min_amount,
+            }
+        } else if let Some(max_amount) = self.max_amount.filter(|max| order.amount > *max) {
+            OrderConstraint::MaxAmount {
+                amount: order.amount,
+                max_amount,
+            }
+        } else {
+            return Ok(());
+        };
+        Err(ApplicationError::InvalidOrder(violated))
+    }
+
+    /// What to allocate of the market's book before its first order
+    pub fn book_capacity(&self) -> BookCapacity {
+        BookCapacity {
+            levels: self.preallocated_levels,
+            orders_per_level: self.orders_per_level,
+        }
+    }
+}
+
+/// A market's symbol with its configuration, as listed in configuration and seed files
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MarketDefinition {
+    pub symbol: String,
+    #[serde(flatten)]
+    pub config: MarketConfig,
+}
+
+/// The configuration of every market by symbol
+#[derive(Debug, Clone)]
+pub struct Markets {
+    markets: BTreeMap<String, MarketConfig>,
+}
+
+impl Default for Markets {
+    /// Only the [`DEFAULT_MARKET`] with the default configuration
+    fn default() -> Self {
+        Markets {
+            markets: BTreeMap::from([(DEFAULT_MARKET.to_string(), MarketConfig::default())]),
+        }
+    }
+}
+
+impl Markets {
+    pub fn new() -> Self {
+        Markets::default()
+    }
+
+    /// Adds a market or replaces its configuration
+    pub fn insert(&mut self, symbol: &str, config: MarketConfig) {
+        self.markets.insert(symbol.to_string(), config);
+    }
+
+    /// # Errors
+    /// There's no market with that symbol
+    pub fn get(&self, symbol: &str) -> Result<&MarketConfig, ApplicationError> {
+        self.markets
+            .get(symbol)
+            .ok_or_else(|| ApplicationError::MarketNotFound(symbol.to_string()))
+    }
+
+    /// Every market with its configuration, by symbol
+    pub fn iter(&self) -> impl Iterator<Item = (&String, &MarketConfig)> {
+        self.markets.iter()
+    }
+
+    /// Stops trading in every market
+    pub fn halt_all(&mut self) {
+        for config in self.markets.values_mut() {
+            config.status = MarketStatus::Halted;
+        }
+    }
+
+    /// The reference data of a market with the fee schedule that applies to it
+    pub fn info(
+        &self,
+        symbol: &str,
+        fee_tiers: Vec<FeeTier>,
+    ) -> Result<MarketInfo, ApplicationError> {
+        let config = self.get(symbol)?;
+        Ok(MarketInfo {
+            symbol: symbol.to_string(),
+            tick_size: config.tick_size,
+            lot_size: config.lot_size,
+            min_amount: config.min_amount,
+            max_amount: config.max_amount,
+            fee_tiers,
+            trading_hours: config.trading_hours.clone(),
+            status: config.status,
+            sandbox: config.sandbox,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    // reduce the warnings for naming tests
+    #![allow(non_snake_case)]
+
+    use super::*;
+
+    #[test]
+    fn test_Markets_info_of_default_market() {
+        let markets = Markets::new();
+        let info = markets.info(DEFAULT_MARKET, vec![]).unwrap();
+        assert_eq!(info.symbol, DEFAULT_MARKET);
+        assert_eq!((info.tick_size, info.lot_size), (1, 1));
+        assert_eq!(info.status, MarketStatus::Open);
+        assert_eq!(
+            markets.info("BTC-USD", vec![]),
+            Err(ApplicationError::MarketNotFound("BTC-USD".to_string()))
+        );
+    }
+}
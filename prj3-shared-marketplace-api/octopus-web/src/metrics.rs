@@ -0,0 +1,389 @@
+use std::fmt::{Display, Write};
+
+use octopus_common::types::{MarketAnalytics, DEFAULT_MARKET};
+
+use crate::{
+    activity::EngineActivity, book_updates::SideDepth, concurrency::RouteGroups, core::PoolStats,
+    tenants::Tenants,
+};
+
+/// Builds a scrape response in the Prometheus text exposition format
+#[derive(Debug, Default)]
+pub struct MetricsWriter {
+    out: String,
+}
+
+impl MetricsWriter {
+    pub fn new() -> Self {
+        MetricsWriter::default()
+    }
+
+    /// Starts a metric family. `kind` is a Prometheus type such as `gauge` or `counter`.
+    pub fn family(&mut self, name: &str, kind: &str, help: &str) {
+        let _ = writeln!(self.out, "# HELP {} {}", name, help);
+        let _ = writeln!(self.out, "# TYPE {} {}", name, kind);
+    }
+
+    /// Adds a sample to the current family
+    pub fn sample(&mut self, name: &str, labels: &[(&str, &str)], value: impl Display) {
+        self.out.push_str(name);
+        if !labels.is_empty() {
+            let labels: Vec<String> = labels
+                .iter()
+                .map(|(key, value)| format!("{}=\"{}\"", key, escape(value)))
+                .collect();
+            let _ = write!(self.out, "{{{}}}", labels.join(","));
+        }
+        let _ = writeln!(self.out, " {}", value);
+    }
+
+    pub fn finish(self) -> String {
+        self.out
+    }
+}
+
+fn escape(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+/// Renders the current metrics of all tenants and the route groups
+pub fn render(tenants: &Tenants, route_groups: &RouteGroups) -> String {
+    let queues: Vec<_> = tenants
+        .names()
+        .into_iter()
+        .filter_map(|name| tenants.orders(&name).ok().map(|queue| (name, queue)))
+        .collect();
+
+    let mut writer = MetricsWriter::new();
+    writer.family(
+        "octopus_order_queue_depth",
+        "gauge",
+        "Orders waiting for or in matching",
+    );
+    for (name, queue) in &queues {
+        writer.sample(
+            "octopus_order_queue_depth",
+            &[("tenant", name)],
+            queue.depth(),
+        );
+    }
+    writer.family(
+        "octopus_order_queue_capacity",
+        "gauge",
+        "Maximum order queue depth before orders are rejected",
+    );
+    for (name, queue) in &queues {
+        writer.sample(
+            "octopus_order_queue_capacity",
+            &[("tenant", name)],
+            queue.capacity(),
+        );
+    }
+    writer.family(
+        "octopus_orders_rejected_total",
+        "counter",
+        "Orders rejected because the order queue was full",
+    );
+    for (name, queue) in &queues {
+        writer.sample(
+            "octopus_orders_rejected_total",
+            &[("tenant", name)],
+            queue.rejected(),
+        );
+    }
+
+    let activities: Vec<_> = tenants
+        .names()
+        .into_iter()
+        .filter_map(|name| {
+            let platform = tenants.get(&name).ok()?;
+            let activity = platform.lock().unwrap().activity.clone();
+            Some((name, activity))
+        })
+        .collect();
+    let activity_counter = |writer: &mut MetricsWriter,
+                            metric: &str,
+                            help: &str,
+                            value: fn(&EngineActivity) -> u64| {
+        writer.family(metric, "counter", help);
+        for (name, activity) in &activities {
+            writer.sample(
+                metric,
+                &[("tenant", name), ("market", DEFAULT_MARKET)],
+                value(activity),
+            );
+        }
+    };
+    activity_counter(
+        &mut writer,
+        "octopus_orders_accepted_total",
+        "Orders accepted by the matching engine",
+        EngineActivity::orders,
+    );
+    activity_counter(
+        &mut writer,
+        "octopus_trades_total",
+        "Matches between two orders",
+        EngineActivity::trades,
+    );
+    activity_counter(
+        &mut writer,
+        "octopus_traded_quantity_total",
+        "Units exchanged in all matches",
+        EngineActivity::traded_quantity,
+    );
+    activity_counter(
+        &mut writer,
+        "octopus_orders_cancelled_total",
+        "Resting orders removed before they were filled",
+        EngineActivity::cancels,
+    );
+
+    writer.family(
+        "octopus_orders_throttled_total",
+        "counter",
+        "Orders rejected because their account exceeded its order rate or cancel ratio",
+    );
+    for name in tenants.names() {
+        if let Ok(platform) = tenants.get(&name) {
+            let throttled = platform.lock().unwrap().throttle.throttled();
+            writer.sample(
+                "octopus_orders_throttled_total",
+                &[("tenant", &name)],
+                throttled,
+            );
+        }
+    }
+
+    let depths: Vec<_> = tenants
+        .names()
+        .into_iter()
+        .filter_map(|name| {
+            let platform = tenants.get(&name).ok()?;
+            let platform = platform.lock().unwrap();
+            let depth = platform.book_updates.depth();
+            Some((
+                name,
+                depth.bids,
+                depth.asks,
+                depth.imbalance(),
+                depth.order_flow_imbalance,
+            ))
+        })
+        .collect();
+    let side_gauge = |writer: &mut MetricsWriter, metric: &str, value: fn(&SideDepth) -> u64| {
+        for (name, bids, asks, _, _) in &depths {
+            for (side, depth) in [("buy", bids), ("sell", asks)] {
+                writer.sample(
+                    metric,
+                    &[("tenant", name), ("market", DEFAULT_MARKET), ("side", side)],
+                    value(depth),
+                );
+            }
+        }
+    };
+    writer.family(
+        "octopus_book_quantity",
+        "gauge",
+        "Resting quantity on one side of the book",
+    );
+    side_gauge(&mut writer, "octopus_book_quantity", |depth| depth.quantity);
+    writer.family(
+        "octopus_book_levels",
+        "gauge",
+        "Price levels on one side of the book",
+    );
+    side_gauge(&mut writer, "octopus_book_levels", |depth| depth.levels);
+    writer.family(
+        "octopus_book_imbalance",
+        "gauge",
+        "Resting bids minus asks over their sum, from -1 to 1",
+    );
+    for (name, _, _, imbalance, _) in &depths {
+        writer.sample(
+            "octopus_book_imbalance",
+            &[("tenant", name), ("market", DEFAULT_MARKET)],
+            imbalance,
+        );
+    }
+    writer.family(
+        "octopus_order_flow_imbalance",
+        "gauge",
+        "Net quantity added to the bids or taken from the asks since the start",
+    );
+    for (name, _, _, _, order_flow_imbalance) in &depths {
+        writer.sample(
+            "octopus_order_flow_imbalance",
+            &[("tenant", name), ("market", DEFAULT_MARKET)],
+            order_flow_imbalance,
+        );
+    }
+
+    let pools: Vec<_> = tenants
+        .names()
+        .into_iter()
+        .filter_map(|name| {
+            let platform = tenants.get(&name).ok()?;
+            let stats = platform.lock().unwrap().pool_stats();
+            Some((name, stats))
+        })
+        .collect();
+    let pool_metric = |writer: &mut MetricsWriter,
+                       metric: &str,
+                       kind: &str,
+                       help: &str,
+                       value: fn(&PoolStats) -> u64| {
+        writer.family(metric, kind, help);
+        for (name, stats) in &pools {
+            for (market, stats) in stats {
+                writer.sample(
+                    metric,
+                    &[("tenant", name), ("market", market)],
+                    value(stats),
+                );
+            }
+        }
+    };
+    pool_metric(
+        &mut writer,
+        "octopus_book_levels_preallocated_total",
+        "counter",
+        "Price levels allocated before the first order",
+        |stats| stats.preallocated,
+    );
+    pool_metric(
+        &mut writer,
+        "octopus_book_levels_reused_total",
+        "counter",
+        "Price levels opened with an emptied level from the pool",
+        |stats| stats.reused,
+    );
+    pool_metric(
+        &mut writer,
+        "octopus_book_levels_allocated_total",
+        "counter",
+        "Price levels opened with a new allocation",
+        |stats| stats.allocated,
+    );
+    pool_metric(
+        &mut writer,
+        "octopus_book_levels_pooled",
+        "gauge",
+        "Emptied price levels waiting for reuse",
+        |stats| stats.idle as u64,
+    );
+
+    let analytics: Vec<_> = tenants
+        .names()
+        .into_iter()
+        .filter_map(|name| {
+            let platform = tenants.get(&name).ok()?;
+            // Turnover is in the counters above, only the positions and books are needed
+            let analytics = platform.lock().unwrap().analytics(u64::MAX);
+            Some((name, analytics))
+        })
+        .collect();
+    let market_gauge = |writer: &mut MetricsWriter,
+                        metric: &str,
+                        help: &str,
+                        value: fn(&MarketAnalytics) -> u64| {
+        writer.family(metric, "gauge", help);
+        for (name, markets) in &analytics {
+            for market in markets {
+                writer.sample(
+                    metric,
+                    &[
+                        ("tenant", name.as_str()),
+                        ("market", market.market.as_str()),
+                    ],
+                    value(market),
+                );
+            }
+        }
+    };
+    market_gauge(
+        &mut writer,
+        "octopus_open_interest",
+        "Units held in long positions",
+        |market| market.open_interest,
+    );
+    market_gauge(
+        &mut writer,
+        "octopus_book_top_share_bps",
+        "The largest share of the resting quantity one account holds, in basis points",
+        |market| market.top_book_share_bps,
+    );
+
+    writer.family(
+        "octopus_requests_in_flight",
+        "gauge",
+        "Requests of a route group with a concurrency limit being worked on",
+    );
+    for group in route_groups.all() {
+        writer.sample(
+            "octopus_requests_in_flight",
+            &[("group", group.name)],
+            group.in_flight(),
+        );
+    }
+    writer.family(
+        "octopus_requests_shed_total",
+        "counter",
+        "Requests rejected because their route group was at its concurrency limit",
+    );
+    for group in route_groups.all() {
+        writer.sample(
+            "octopus_requests_shed_total",
+            &[("group", group.name)],
+            group.shed(),
+        );
+    }
+    writer.finish()
+}
+
+#[cfg(test)]
+mod tests {
+    // reduce the warnings for naming tests
+    #![allow(non_snake_case)]
+
+    use super::*;
+    use crate::concurrency::ConcurrencyConfig;
+    use crate::ingest::DEFAULT_ORDER_QUEUE_CAPACITY;
+    use crate::tenants::TenantSettings;
+
+    #[test]
+    fn test_MetricsWriter_sample_formats_labels() {
+        let mut writer = MetricsWriter::new();
+        writer.family("up", "gauge", "Whether the server is up");
+        writer.sample("up", &[], 1);
+        writer.sample("up", &[("tenant", "a\"b"), ("zone", "eu")], 0);
+        assert_eq!(
+            writer.finish(),
+            "# HELP up Whether the server is up\n# TYPE up gauge\nup 1\nup{tenant=\"a\\\"b\",zone=\"eu\"} 0\n"
+        );
+    }
+
+    #[test]
+    fn test_render_labels_queues_by_tenant() {
+        let tenants = Tenants::new(&["acme"], TenantSettings::default()).unwrap();
+        let route_groups = RouteGroups::new(&ConcurrencyConfig::default());
+        let metrics = render(&tenants, &route_groups);
+        assert!(metrics.contains("octopus_order_queue_depth{tenant=\"acme\"} 0\n"));
+        assert!(metrics.contains("octopus_order_queue_depth{tenant=\"default\"} 0\n"));
+        assert!(metrics.contains(&format!(
+            "octopus_order_queue_capacity{{tenant=\"default\"}} {}\n",
+            DEFAULT_ORDER_QUEUE_CAPACITY
+        )));
+        assert!(metrics.contains(
+            "octopus_book_levels{tenant=\"acme\",market=\"OCTO-USD\",side=\"sell\"} 0\n"
+        ));
+        assert!(metrics.contains(
+            "octopus_book_levels_allocated_total{tenant=\"acme\",market=\"OCTO-USD\"} 0\n"
+        ));
+        assert!(metrics.contains("octopus_open_interest{tenant=\"acme\",market=\"OCTO-USD\"} 0\n"));
+        assert!(metrics.contains("octopus_requests_shed_total{group=\"exports\"} 0\n"));
+    }
+}
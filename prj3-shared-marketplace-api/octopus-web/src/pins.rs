@@ -0,0 +1,193 @@
+//! Account PINs, a second factor for moving funds out of an account until full authentication lands.
+//!
+//! Only salted hashes are kept. Too many wrong PINs in a row lock the PIN for a while.
+use octopus_common::errors::ApplicationError;
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+
+use crate::api_keys::random_hex;
+
+/// The header carrying the PIN of a withdrawal or send
+pub const PIN_HEADER: &str = "x-pin";
+
+/// Wrong PINs in a row before the PIN is locked
+pub const MAX_PIN_ATTEMPTS: u32 = 5;
+
+/// How long a PIN stays locked after [`MAX_PIN_ATTEMPTS`] wrong attempts
+pub const PIN_LOCKOUT_MILLIS: u64 = 15 * 60 * 1000;
+
+/// The allowed length of a PIN in characters
+const PIN_LENGTH: std::ops::RangeInclusive<usize> = 4..=64;
+
+/// Random bytes in the salt of a PIN
+const PIN_SALT_BYTES: usize = 16;
+
+/// Rounds of SHA-256, PINs are short so guessing them offline has to be slow
+const PIN_HASH_ROUNDS: usize = 10_000;
+
+fn hash_pin(salt: &str, pin: &str) -> String {
+    let mut digest = Sha256::digest(format!("{}{}", salt, pin).as_bytes());
+    for _ in 1..PIN_HASH_ROUNDS {
+        digest = Sha256::digest(digest);
+    }
+    hex::encode(digest)
+}
+
+/// The hash of a PIN and the wrong attempts since the last correct one
+#[derive(Debug)]
+struct StoredPin {
+    salt: String,
+    hash: String,
+    failures: u32,
+    /// Unix timestamp (ms), 0 if the PIN isn't locked
+    locked_until: u64,
+}
+
+/// The PINs of all accounts that set one
+#[derive(Debug, Default)]
+pub struct Pins {
+    pins: HashMap<String, StoredPin>,
+}
+
+impl Pins {
+    pub fn new() -> Self {
+        Pins::default()
+    }
+
+    /// Whether `signer` set a PIN
+    pub fn is_set(&self, signer: &str) -> bool {
+        self.pins.contains_key(signer)
+    }
+
+    /// Sets the PIN of `signer`. Changing an existing PIN requires the `current` one.
+    /// # Errors
+    /// - The PIN is too short or too long
+    /// - The current PIN is missing, wrong, or locked
+    pub fn set(
+        &mut self,
+        signer: &str,
+        pin: &str,
+        current: Option<&str>,
+        now: u64,
+    ) -> Result<(), ApplicationError> {
+        if !PIN_LENGTH.contains(&pin.chars().count()) {
+            return Err(ApplicationError::InvalidPin(format!(
+                "a PIN has {} to {} characters",
+                PIN_LENGTH.start(),
+                PIN_LENGTH.end()
+            )));
+        }
+        if self.is_set(signer) {
+            self.verify(signer, current, now)?;
+        }
+        let salt = random_hex(PIN_SALT_BYTES);
+        self.pins.insert(
+            signer.to_string(),
+            StoredPin {
+                hash: hash_pin(&salt, pin),
+                salt,
+                failures: 0,
+                locked_until: 0,
+            },
+        );
+        Ok(())
+    }
+
+    /// Checks the PIN of `signer`, accounts without a PIN pass. A correct PIN resets the wrong attempts.
+    /// # Errors
+    /// - The PIN is missing or wrong
+    /// - The PIN is locked after too many wrong attempts
+    pub fn verify(
+        &mut self,
+        signer: &str,
+        pin: Option<&str>,
+        now: u64,
+    ) -> Result<(), ApplicationError> {
+        let Some(stored) = self.pins.get_mut(signer) else {
+            return Ok(());
+        };
+        if stored.locked_until > now {
+            let secs = (stored.locked_until - now).div_ceil(1000);
+            return Err(ApplicationError::PinLocked(signer.to_string(), secs));
+        }
+        let pin = pin.ok_or(ApplicationError::PinRequired(signer.to_string()))?;
+        if hash_pin(&stored.salt, pin) == stored.hash {
+            stored.failures = 0;
+            return Ok(());
+        }
+        stored.failures += 1;
+        if stored.failures >= MAX_PIN_ATTEMPTS {
+            stored.failures = 0;
+            stored.locked_until = now + PIN_LOCKOUT_MILLIS;
+            return Err(ApplicationError::PinLocked(
+                signer.to_string(),
+                PIN_LOCKOUT_MILLIS / 1000,
+            ));
+        }
+        Err(ApplicationError::WrongPin(
+            signer.to_string(),
+            MAX_PIN_ATTEMPTS - stored.failures,
+        ))
+    }
+
+    /// Forgets the PIN of `signer`
+    pub fn remove(&mut self, signer: &str) {
+        self.pins.remove(signer);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    // reduce the warnings for naming tests
+    #![allow(non_snake_case)]
+
+    use super::*;
+
+    #[test]
+    fn test_Pins_verify_locks_after_repeated_failures() {
+        let mut pins = Pins::new();
+        assert_eq!(pins.verify("ALICE", None, 0), Ok(()));
+        pins.set("ALICE", "1234", None, 0).unwrap();
+        assert_eq!(
+            pins.verify("ALICE", None, 0),
+            Err(ApplicationError::PinRequired("ALICE".to_string()))
+        );
+        assert_eq!(
+            pins.verify("ALICE", Some("0000"), 0),
+            Err(ApplicationError::WrongPin("ALICE".to_string(), 4))
+        );
+        assert_eq!(pins.verify("ALICE", Some("1234"), 0), Ok(()));
+
+        for _ in 1..MAX_PIN_ATTEMPTS {
+            assert!(pins.verify("ALICE", Some("0000"), 0).is_err());
+        }
+        assert_eq!(
+            pins.verify("ALICE", Some("0000"), 0),
+            Err(ApplicationError::PinLocked(
+                "ALICE".to_string(),
+                PIN_LOCKOUT_MILLIS / 1000
+            ))
+        );
+        assert!(pins.verify("ALICE", Some("1234"), 1000).is_err());
+        assert_eq!(
+            pins.verify("ALICE", Some("1234"), PIN_LOCKOUT_MILLIS),
+            Ok(())
+        );
+    }
+
+    #[test]
+    fn test_Pins_set_requires_the_current_pin_to_change() {
+        let mut pins = Pins::new();
+        assert!(matches!(
+            pins.set("ALICE", "12", None, 0),
+            Err(ApplicationError::InvalidPin(_))
+        ));
+        pins.set("ALICE", "1234", None, 0).unwrap();
+        assert_eq!(
+            pins.set("ALICE", "5678", None, 0),
+            Err(ApplicationError::PinRequired("ALICE".to_string()))
+        );
+        pins.set("ALICE", "5678", Some("1234"), 0).unwrap();
+        assert_eq!(pins.verify("ALICE", Some("5678"), 0), Ok(()));
+    }
+}
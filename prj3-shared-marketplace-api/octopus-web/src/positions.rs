@@ -0,0 +1,137 @@
+use octopus_common::types::{Position, Side, Trade};
+use std::collections::HashMap;
+
+/// Tracks the [`Position`] of every account that traded
+#[derive(Debug, Default)]
+pub struct Positions {
+    positions: HashMap<String, Position>,
+}
+
+impl Positions {
+    pub fn new() -> Self {
+        Positions::default()
+    }
+
+    /// The position of `signer`, empty if the account never traded
+    pub fn of(&self, signer: &str) -> Position {
+        self.positions.get(signer).cloned().unwrap_or_default()
+    }
+
+    /// Forgets the position of `signer`
+    pub fn remove(&mut self, signer: &str) -> Option<Position> {
+        self.positions.remove(signer)
+    }
+
+    /// Replaces the position of `signer` with the one built from `trades` in the given order, busted trades and trades
+    /// of other accounts are skipped
+    pub fn rebuild<'a>(&mut self, signer: &str, trades: impl IntoIterator<Item = &'a Trade>) {
+        self.positions.remove(signer);
+        for trade in trades.into_iter().filter(|trade| trade.busted.is_none()) {
+            let maker_side = match trade.taker_side {
+                Side::Buy => Side::Sell,
+                Side::Sell => Side::Buy,
+            };
+            for (account, side) in [
+                (&trade.taker, &trade.taker_side),
+                (&trade.maker, &maker_side),
+            ] {
+                if account == signer {
+                    self.fill(signer, side, trade.amount, trade.price);
+                }
+            }
+        }
+    }
+
+    /// Whether an order would grow the absolute position of `signer`. `amount` should include the account's open
+    /// orders on the same side.
+    pub fn increases_risk(&self, signer: &str, side: &Side, amount: u64) -> bool {
+        let units = self.of(signer).units;
+        match side {
+            Side::Buy => units >= 0 || amount as i64 > -units,
+            Side::Sell => units <= 0 || amount as i64 > units,
+        }
+    }
+
+    /// Applies a fill to the position of `signer`. Reducing a position realizes its profit or loss at the average cost,
+    /// which is returned.
+    pub fn fill(&mut self, signer: &str, side: &Side, amount: u64, price: u64) -> i64 {
+        let position = self.positions.entry(signer.to_string()).or_default();
+        let direction = match side {
+            Side::Buy => 1,
+            Side::Sell => -1,
+        };
+        let price = price as i64;
+        let mut amount = amount as i64;
+        let mut realized = 0;
+
+        if position.units != 0 && position.units.signum() != direction {
+            let closing = amount.min(position.units.abs());
+            let closed_cost = position.cost * closing / position.units.abs();
+            realized = position.units.signum() * closing * price - closed_cost;
+            position.realized += realized;
+            position.units -= position.units.signum() * closing;
+            position.cost -= closed_cost;
+            amount -= closing;
+        }
+        // Open or grow the position with the rest
+        position.units += direction * amount;
+        position.cost += direction * amount * price;
+        realized
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    // reduce the warnings for naming tests
+    #![allow(non_snake_case)]
+
+    use super::*;
+
+    #[test]
+    fn test_Positions_fill_realizes_pnl_at_average_cost() {
+        let mut positions = Positions::new();
+        positions.fill("ALICE", &Side::Buy, 5, 10);
+        positions.fill("ALICE", &Side::Buy, 5, 14);
+        assert_eq!(
+            positions.of("ALICE"),
+            Position {
+                units: 10,
+                cost: 120,
+                realized: 0
+            }
+        );
+        assert_eq!(positions.of("ALICE").unrealized(15), 30);
+
+        assert_eq!(positions.fill("ALICE", &Side::Sell, 4, 15), 12);
+        assert_eq!(
+            positions.of("ALICE"),
+            Position {
+                units: 6,
+                cost: 72,
+                realized: 12
+            }
+        );
+
+        // Flip to a short position
+        positions.fill("ALICE", &Side::Sell, 8, 11);
+        assert_eq!(
+            positions.of("ALICE"),
+            Position {
+                units: -2,
+                cost: -22,
+                realized: 6
+            }
+        );
+        assert_eq!(positions.of("ALICE").pnl(12), 4);
+    }
+
+    #[test]
+    fn test_Positions_increases_risk() {
+        let mut positions = Positions::new();
+        assert!(positions.increases_risk("ALICE", &Side::Sell, 1));
+        positions.fill("ALICE", &Side::Buy, 5, 10);
+        assert!(positions.increases_risk("ALICE", &Side::Buy, 1));
+        assert!(!positions.increases_risk("ALICE", &Side::Sell, 5));
+        assert!(positions.increases_risk("ALICE", &Side::Sell, 6));
+    }
+}
@@ -0,0 +1,229 @@
+//! Private websocket channels: the updates of one account's orders, fills, and balance as they happen, apart from the
+//! public book and trade streams.
+//!
+//! The [`AccountFeed`] observes every book and hears of every transaction, it publishes an [`AccountUpdate`] for each
+//! account involved. A channel only forwards the updates of the account it was opened for, and of those only the ones
+//! the scopes of its credential permit.
+use futures_util::{SinkExt, StreamExt};
+use octopus_common::{
+    errors::ApplicationError,
+    types::{AccountUpdate, ApiKeyScope, OrderState, PartialOrder},
+};
+use tokio::sync::broadcast;
+use warp::ws::{Message, WebSocket};
+
+use crate::{auth::Credential, core::EngineObserver};
+
+/// The number of updates a slow private channel may fall behind before it's closed
+pub const ACCOUNT_FEED_CAPACITY: usize = 1024;
+
+/// Publishes the updates of every account to the open channels. Clones publish to the same channels, so a clone is
+/// registered with each book.
+#[derive(Debug, Clone)]
+pub struct AccountFeed(broadcast::Sender<AccountUpdate>);
+
+impl Default for AccountFeed {
+    fn default() -> Self {
+        AccountFeed(broadcast::channel(ACCOUNT_FEED_CAPACITY).0)
+    }
+}
+
+impl AccountFeed {
+    pub fn subscribe(&self) -> broadcast::Receiver<AccountUpdate> {
+        self.0.subscribe()
+    }
+
+    /// Whether any channel is open, updates aren't built for nobody
+    pub fn is_subscribed(&self) -> bool {
+        self.0.receiver_count() > 0
+    }
+
+    pub fn publish(&self, update: AccountUpdate) {
+        // Nobody listening is fine
+        let _ = self.0.send(update);
+    }
+
+    fn publish_order(&self, order: &PartialOrder, state: OrderState) {
+        if self.is_subscribed() {
+            self.publish(AccountUpdate::Order {
+                signer: order.signer.clone(),
+                state,
+                order: order.clone(),
+            });
+        }
+    }
+}
+
+impl EngineObserver for AccountFeed {
+    fn on_order_accepted(&mut self, order: &PartialOrder) {
+        self.publish_order(order, OrderState::Accepted);
+    }
+
+    fn on_trade(&mut self, taker: &PartialOrder, maker: &PartialOrder) {
+        if !self.is_subscribed() {
+            return;
+        }
+        for (order, is_taker) in [(taker, true), (maker, false)] {
+            self.publish(AccountUpdate::Fill {
+                signer: order.signer.clone(),
+                order_id: order.order_id,
+                market: order.market.clone(),
+                side: order.side.clone(),
+                price: maker.price,
+                amount: maker.amount,
+                taker: is_taker,
+            });
+        }
+    }
+
+    fn on_cancel(&mut self, order: &PartialOrder) {
+        self.publish_order(order, OrderState::Cancelled);
+    }
+}
+
+/// The subscription of a credential to the updates of one account
+#[derive(Debug)]
+pub struct PrivateChannel {
+    credential: Credential,
+    signer: String,
+}
+
+impl PrivateChannel {
+    /// # Errors
+    /// The credential may neither read nor trade for `signer`
+    pub fn open(credential: Credential, signer: String) -> Result<Self, ApplicationError> {
+        credential
+            .authorize(&signer, ApiKeyScope::Read)
+            .or_else(|_| credential.authorize(&signer, ApiKeyScope::Trade))?;
+        Ok(PrivateChannel { credential, signer })
+    }
+
+    /// Whether `update` is about the channel's account and the credential may receive it
+    pub fn admits(&self, update: &AccountUpdate) -> bool {
+        update.signer() == self.signer
+            && update
+                .scopes()
+                .iter()
+                .any(|scope| self.credential.authorize(&self.signer, *scope).is_ok())
+    }
+}
+
+/// Forwards the admitted updates until the connection closes. A channel that falls behind by more than
+/// [`ACCOUNT_FEED_CAPACITY`] updates is closed, its client reconnects and queries the current state.
+pub async fn serve(
+    socket: WebSocket,
+    channel: PrivateChannel,
+    mut updates: broadcast::Receiver<AccountUpdate>,
+) {
+    let (mut outgoing, mut incoming) = socket.split();
+    loop {
+        tokio::select! {
+            update = updates.recv() => match update {
+                Ok(update) if channel.admits(&update) => {
+                    let text = serde_json::to_string(&update).expect("account updates serialize");
+                    if outgoing.send(Message::text(text)).await.is_err() {
+                        return;
+                    }
+                }
+                Ok(_) => {}
+                Err(e) => {
+                    log::info!("Closing the private channel of {}: {}", channel.signer, e);
+                    let _ = outgoing.send(Message::close()).await;
+                    return;
+                }
+            },
+            // Anything the client sends is ignored, the channel ends when it closes the connection
+            message = incoming.next() => match message {
+                Some(Ok(message)) if !message.is_close() => {}
+                _ => return,
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    // reduce the warnings for naming tests
+    #![allow(non_snake_case)]
+
+    use super::*;
+    use crate::trading_platform::TradingPlatform;
+    use octopus_common::types::{Order, OrderType, Role, Side, TimeInForce, DEFAULT_MARKET};
+
+    fn credential(scopes: Vec<ApiKeyScope>) -> Credential {
+        Credential {
+            role: Role::Trader,
+            signer: Some("ALICE".to_string()),
+            scopes,
+        }
+    }
+
+    #[test]
+    fn test_PrivateChannel_admits_own_updates_within_the_scopes() {
+        let mut trading_platform = TradingPlatform::new();
+        let mut updates = trading_platform.account_feed.subscribe();
+        trading_platform.deposit("ALICE", 100).unwrap();
+        trading_platform.deposit("BOB", 100).unwrap();
+        for (signer, side) in [("ALICE", Side::Sell), ("BOB", Side::Buy)] {
+            trading_platform
+                .order(Order {
+                    price: 10,
+                    amount: 1,
+                    side,
+                    signer: signer.to_string(),
+                    client_order_id: None,
+                    signature: None,
+                    order_type: OrderType::Limit,
+                    time_in_force: TimeInForce::Gtc,
+                    trigger_price: None,
+                    market: DEFAULT_MARKET.to_string(),
+                })
+                .unwrap();
+        }
+        let published: Vec<_> = std::iter::from_fn(|| updates.try_recv().ok()).collect();
+
+        let reader =
+            PrivateChannel::open(credential(vec![ApiKeyScope::Read]), "ALICE".to_string()).unwrap();
+        let received: Vec<_> = published.iter().filter(|u| reader.admits(u)).collect();
+        assert!(matches!(
+            received[0],
+            AccountUpdate::Balance { balance: 100, .. }
+        ));
+        assert!(matches!(
+            received[1],
+            AccountUpdate::Order {
+                state: OrderState::Accepted,
+                ..
+            }
+        ));
+        assert!(received.iter().any(|u| matches!(
+            u,
+            AccountUpdate::Fill {
+                price: 10,
+                amount: 1,
+                taker: false,
+                ..
+            }
+        )));
+        assert!(received
+            .iter()
+            .any(|u| matches!(u, AccountUpdate::Balance { balance: 110, .. })));
+        assert!(received.iter().all(|u| u.signer() == "ALICE"));
+
+        let trader =
+            PrivateChannel::open(credential(vec![ApiKeyScope::Trade]), "ALICE".to_string())
+                .unwrap();
+        assert!(published
+            .iter()
+            .filter(|u| trader.admits(u))
+            .all(|u| !matches!(u, AccountUpdate::Balance { .. })));
+        assert!(matches!(
+            PrivateChannel::open(credential(vec![ApiKeyScope::Read]), "BOB".to_string()),
+            Err(ApplicationError::Forbidden(_))
+        ));
+        assert!(matches!(
+            PrivateChannel::open(credential(vec![ApiKeyScope::Withdraw]), "ALICE".to_string()),
+            Err(ApplicationError::Forbidden(_))
+        ));
+    }
+}
@@ -0,0 +1,236 @@
+use octopus_common::{
+    errors::ApplicationError,
+    types::{Receipt, RecurringBuy, RecurringBuyExecution, RecurringBuyRequest, DEFAULT_MARKET},
+};
+use std::collections::BTreeMap;
+
+/// Recurring buy plans and the history of their executions
+#[derive(Debug, Default)]
+pub struct RecurringBuys {
+    plans: BTreeMap<u64, RecurringBuy>,
+    executions: Vec<RecurringBuyExecution>,
+    last_id: u64,
+}
+
+impl RecurringBuys {
+    pub fn new() -> Self {
+        RecurringBuys::default()
+    }
+
+    /// Adds a plan for `signer`. The first execution is due right away.
+    /// # Errors
+    /// The amount or interval is zero, or the market is unknown
+    pub fn create(
+        &mut self,
+        signer: &str,
+        request: RecurringBuyRequest,
+        now: u64,
+    ) -> Result<RecurringBuy, ApplicationError> {
+        if request.amount == 0 || request.interval_secs == 0 {
+            return Err(ApplicationError::InvalidRecurringBuy(
+                "amount and interval must be positive".to_string(),
+            ));
+        }
+        if request.market != DEFAULT_MARKET {
+            return Err(ApplicationError::InvalidRecurringBuy(format!(
+                "unknown market {}",
+                request.market
+            )));
+        }
+        self.last_id += 1;
+        let id = self.last_id;
+        let plan = RecurringBuy {
+            id,
+            account: signer.to_string(),
+            market: request.market,
+            amount: request.amount,
+            interval_secs: request.interval_secs,
+            paused: false,
+            next_run: now,
+        };
+        self.plans.insert(id, plan.clone());
+        Ok(plan)
+    }
+
+    /// The id of the latest plan
+    pub fn last_id(&self) -> u64 {
+        self.last_id
+    }
+
+    /// Continues the plan ids after `last`
+    pub fn resume_ids(&mut self, last: u64) {
+        self.last_id = self.last_id.max(last);
+    }
+
+    /// The plans of `signer`
+    pub fn list(&self, signer: &str) -> Vec<RecurringBuy> {
+        self.plans
+            .values()
+            .filter(|plan| plan.account == signer)
+            .cloned()
+            .collect()
+    }
+
+    fn plan_of(&mut self, signer: &str, id: u64) -> Result<&mut RecurringBuy, ApplicationError> {
+        self.plans
+            .get_mut(&id)
+            .filter(|plan| plan.account == signer)
+            .ok_or(ApplicationError::RecurringBuyNotFound(id))
+    }
+
+    /// Stops executing a plan until it's resumed
+    pub fn pause(&mut self, signer: &str, id: u64) -> Result<RecurringBuy, ApplicationError> {
+        let plan = self.plan_of(signer, id)?;
+        plan.paused = true;
+        Ok(plan.clone())
+    }
+
+    /// Continues a paused plan. Executions missed while paused aren't made up for.
+    pub fn resume(
+        &mut self,
+        signer: &str,
+        id: u64,
+        now: u64,
+    ) -> Result<RecurringBuy, ApplicationError> {
+        let plan = self.plan_of(signer, id)?;
+        plan.paused = false;
+        plan.next_run = plan.next_run.max(now);
+        Ok(plan.clone())
+    }
+
+    /// The executions of one of `signer`'s plans, oldest first
+    pub fn executions(
+        &self,
+        signer: &str,
+        id: u64,
+    ) -> Result<Vec<RecurringBuyExecution>, ApplicationError> {
+        self.plans
+            .get(&id)
+            .filter(|plan| plan.account == signer)
+            .ok_or(ApplicationError::RecurringBuyNotFound(id))?;
+        Ok(self
+            .executions
+            .iter()
+            .filter(|execution| execution.plan_id == id)
+            .cloned()
+            .collect())
+    }
+
+    /// Pauses the plans of `signer` and moves them to `token`, and replaces `signer` in the receipts of all executions
+    pub fn anonymize(&mut self, signer: &str, token: &str) {
+        for plan in self
+            .plans
+            .values_mut()
+            .filter(|plan| plan.account == signer)
+        {
+            plan.account = token.to_string();
+            plan.paused = true;
+        }
+        for receipt in self
+            .executions
+            .iter_mut()
+            .filter_map(|e| e.receipt.as_mut())
+        {
+            receipt.anonymize(signer, token);
+        }
+    }
+
+    /// Active plans whose next execution is due at `now`
+    pub fn due(&self, now: u64) -> Vec<RecurringBuy> {
+        self.plans
+            .values()
+            .filter(|plan| !plan.paused && plan.next_run <= now)
+            .cloned()
+            .collect()
+    }
+
+    /// Records the outcome of an execution and schedules the next one
+    pub fn record(&mut self, id: u64, now: u64, result: Result<Receipt, ApplicationError>) {
+        if let Some(plan) = self.plans.get_mut(&id) {
+            plan.next_run = now + plan.interval_secs * 1000;
+        }
+        let (receipt, error) = match result {
+            Ok(receipt) => (Some(receipt), None),
+            Err(e) => (None, Some(format!("{:?}", e))),
+        };
+        self.executions.push(RecurringBuyExecution {
+            plan_id: id,
+            timestamp: now,
+            receipt,
+            error,
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    // reduce the warnings for naming tests
+    #![allow(non_snake_case)]
+
+    use super::*;
+
+    fn request(amount: u64, interval_secs: u64) -> RecurringBuyRequest {
+        RecurringBuyRequest {
+            amount,
+            interval_secs,
+            market: DEFAULT_MARKET.to_string(),
+        }
+    }
+
+    #[test]
+    fn test_RecurringBuys_create_validates_request() {
+        let mut recurring_buys = RecurringBuys::new();
+        assert!(recurring_buys.create("ALICE", request(0, 10), 0).is_err());
+        assert!(recurring_buys.create("ALICE", request(10, 0), 0).is_err());
+        assert_eq!(
+            recurring_buys.create(
+                "ALICE",
+                RecurringBuyRequest {
+                    market: "BTC-USD".to_string(),
+                    ..request(10, 10)
+                },
+                0
+            ),
+            Err(ApplicationError::InvalidRecurringBuy(
+                "unknown market BTC-USD".to_string()
+            ))
+        );
+        assert_eq!(
+            recurring_buys
+                .create("ALICE", request(10, 10), 0)
+                .unwrap()
+                .id,
+            1
+        );
+    }
+
+    #[test]
+    fn test_RecurringBuys_due_respects_interval_and_pause() {
+        let mut recurring_buys = RecurringBuys::new();
+        let plan = recurring_buys
+            .create("ALICE", request(10, 60), 1_000)
+            .unwrap();
+        assert_eq!(recurring_buys.due(1_000).len(), 1);
+
+        recurring_buys.record(
+            plan.id,
+            1_000,
+            Err(ApplicationError::NoLiquidity("ALICE".to_string())),
+        );
+        assert!(recurring_buys.due(60_999).is_empty());
+        assert_eq!(recurring_buys.due(61_000).len(), 1);
+
+        recurring_buys.pause("ALICE", plan.id).unwrap();
+        assert!(recurring_buys.due(100_000).is_empty());
+        assert_eq!(
+            recurring_buys.pause("BOB", plan.id),
+            Err(ApplicationError::RecurringBuyNotFound(plan.id))
+        );
+        recurring_buys.resume("ALICE", plan.id, 100_000).unwrap();
+        assert_eq!(recurring_buys.due(100_000).len(), 1);
+
+        let executions = recurring_buys.executions("ALICE", plan.id).unwrap();
+        assert_eq!(executions.len(), 1);
+        assert_eq!(executions[0].receipt, None);
+    }
+}
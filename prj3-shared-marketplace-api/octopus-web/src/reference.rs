@@ -0,0 +1,150 @@
+//! Reference prices: the price of each market that the collar, the stop-losses, and profit and loss are measured
+//! against.
+//!
+//! A single trade at an off-market price shouldn't move the reference, so it's the median of the market's trades
+//! within a window. A market without recent trades falls back to the oracle price an operator set, and to its last
+//! trade without one.
+use octopus_common::types::{ReferencePrice, ReferenceSource};
+use std::collections::{BTreeMap, VecDeque};
+
+/// How far back trades count towards the reference price unless configured otherwise
+pub const DEFAULT_REFERENCE_WINDOW_SECS: u64 = 5 * 60;
+
+/// The recent trades and oracle price of every market
+#[derive(Debug)]
+pub struct ReferencePrices {
+    window_millis: u64,
+    /// Timestamp and price of the trades within the window by market, oldest first. The last trade is kept even
+    /// once it's older.
+    trades: BTreeMap<String, VecDeque<(u64, u64)>>,
+    oracles: BTreeMap<String, u64>,
+}
+
+impl Default for ReferencePrices {
+    fn default() -> Self {
+        ReferencePrices::new(DEFAULT_REFERENCE_WINDOW_SECS * 1000)
+    }
+}
+
+impl ReferencePrices {
+    pub fn new(window_millis: u64) -> Self {
+        ReferencePrices {
+            window_millis,
+            trades: BTreeMap::new(),
+            oracles: BTreeMap::new(),
+        }
+    }
+
+    /// Records a trade of `market` at `price` and forgets the trades that left the window
+    pub fn record(&mut self, market: &str, price: u64, timestamp: u64) {
+        let trades = self.trades.entry(market.to_string()).or_default();
+        trades.push_back((timestamp, price));
+        while trades.len() > 1
+            && trades
+                .front()
+                .is_some_and(|(traded, _)| traded + self.window_millis <= timestamp)
+        {
+            trades.pop_front();
+        }
+    }
+
+    /// Sets the oracle price of `market`, used while it has no trades within the window
+    pub fn set_oracle(&mut self, market: &str, price: u64) {
+        self.oracles.insert(market.to_string(), price);
+    }
+
+    /// The price of the latest trade of `market`
+    pub fn last_price(&self, market: &str) -> Option<u64> {
+        self.trades
+            .get(market)
+            .and_then(|trades| trades.back())
+            .map(|(_, price)| *price)
+    }
+
+    /// The reference price of `market` at `now`, `None` if it never traded and has no oracle price
+    pub fn price_of(&self, market: &str, now: u64) -> Option<ReferencePrice> {
+        let mut recent: Vec<u64> = self
+            .trades
+            .get(market)
+            .into_iter()
+            .flatten()
+            .filter(|(traded, _)| traded + self.window_millis > now)
+            .map(|(_, price)| *price)
+            .collect();
+        if !recent.is_empty() {
+            recent.sort_unstable();
+            let middle = recent.len() / 2;
+            let price = match recent.len() % 2 {
+                0 => (recent[middle - 1] + recent[middle]) / 2,
+                _ => recent[middle],
+            };
+            return Some(ReferencePrice {
+                price,
+                source: ReferenceSource::Trades,
+            });
+        }
+        match self.oracles.get(market) {
+            Some(price) => Some(ReferencePrice {
+                price: *price,
+                source: ReferenceSource::Oracle,
+            }),
+            None => self.last_price(market).map(|price| ReferencePrice {
+                price,
+                source: ReferenceSource::LastTrade,
+            }),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    // reduce the warnings for naming tests
+    #![allow(non_snake_case)]
+
+    use super::*;
+
+    #[test]
+    fn test_ReferencePrices_price_of_is_the_median_of_recent_trades() {
+        let mut prices = ReferencePrices::new(1_000);
+        assert_eq!(prices.price_of("OCT", 0), None);
+
+        prices.record("OCT", 100, 0);
+        prices.record("OCT", 1_000, 100);
+        prices.record("OCT", 102, 200);
+        // The outlier doesn't move the reference
+        assert_eq!(
+            prices.price_of("OCT", 300),
+            Some(ReferencePrice {
+                price: 102,
+                source: ReferenceSource::Trades
+            })
+        );
+        assert_eq!(prices.price_of("OCT", 1_050).unwrap().price, 551);
+        assert_eq!(prices.price_of("OTHER", 300), None);
+    }
+
+    #[test]
+    fn test_ReferencePrices_price_of_falls_back_without_recent_trades() {
+        let mut prices = ReferencePrices::new(1_000);
+        prices.record("OCT", 100, 0);
+        prices.record("OCT", 110, 2_000);
+        assert_eq!(
+            prices.price_of("OCT", 5_000),
+            Some(ReferencePrice {
+                price: 110,
+                source: ReferenceSource::LastTrade
+            })
+        );
+
+        prices.set_oracle("OCT", 120);
+        assert_eq!(
+            prices.price_of("OCT", 5_000),
+            Some(ReferencePrice {
+                price: 120,
+                source: ReferenceSource::Oracle
+            })
+        );
+        assert_eq!(prices.price_of("OCT", 2_500).unwrap().price, 110);
+        assert_eq!(prices.last_price("OCT"), Some(110));
+    }
+}
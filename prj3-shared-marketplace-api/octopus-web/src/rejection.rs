@@ -0,0 +1,151 @@
+use octopus_common::{
+    errors::{ApplicationError, OctopusError},
+    types::ErrorResponse,
+};
+use std::convert::Infallible;
+
+use crate::ingest::RETRY_AFTER_SECS;
+use warp::http::{header::RETRY_AFTER, StatusCode};
+use warp::Reply;
+
+/// Maps an [`ApplicationError`] to the HTTP status reported to the client
+pub fn status_of(error: &ApplicationError) -> StatusCode {
+    match error {
+        ApplicationError::AccountNotFound(_)
+        | ApplicationError::ApiKeyNotFound(_)
+        | ApplicationError::TenantNotFound(_)
+        | ApplicationError::HoldNotFound(_)
+        | ApplicationError::RecurringBuyNotFound(_)
+        | ApplicationError::StopLossNotSet(_)
+        | ApplicationError::InvoiceNotFound(_)
+        | ApplicationError::ReportNotFound(_)
+        | ApplicationError::WithdrawalNotFound(_)
+        | ApplicationError::ArchiveNotFound(_)
+        | ApplicationError::MarketNotFound(_)
+        | ApplicationError::AliasNotFound(_)
+        | ApplicationError::WhitelistEntryNotFound(_)
+        | ApplicationError::WhitelistNotFound(_)
+        | ApplicationError::TradeNotFound(_)
+        | ApplicationError::OrderNotFound(_)
+        | ApplicationError::TransactionNotFound(_) => StatusCode::NOT_FOUND,
+        ApplicationError::AccountUnderFunded(_, _)
+        | ApplicationError::AccountOverFunded(_, _)
+        | ApplicationError::ApiKeyWithoutScopes(_)
+        | ApplicationError::InvalidTenantName(_)
+        | ApplicationError::InvalidDeposit(_)
+        | ApplicationError::InvalidRecurringBuy(_)
+        | ApplicationError::InvalidMonth(_)
+        | ApplicationError::InvalidDay(_)
+        | ApplicationError::InvalidDeadline(_)
+        | ApplicationError::InvalidExpiry(_)
+        | ApplicationError::InvalidSignatureExpiry(_)
+        | ApplicationError::InvalidOrder(_)
+        | ApplicationError::InvalidAmendment(_)
+        | ApplicationError::InvalidPointInTime(_)
+        | ApplicationError::OutsidePriceCollar(_, _)
+        | ApplicationError::SlippageExceeded(_, _)
+        | ApplicationError::PriceBandExceeded(_, _)
+        | ApplicationError::InvalidChaosSettings(_)
+        | ApplicationError::InvalidSeed(_)
+        | ApplicationError::InvalidPin(_)
+        | ApplicationError::InvalidPublicKey(_)
+        | ApplicationError::InvalidConfig(_)
+        | ApplicationError::InvalidSessionMessage(_)
+        | ApplicationError::InvalidAlias(_)
+        | ApplicationError::InvalidMemo(_)
+        | ApplicationError::InvalidTag(_)
+        | ApplicationError::InvalidPayout(_)
+        | ApplicationError::InvalidDeadmanTimeout(_)
+        | ApplicationError::InvalidQuote(_)
+        | ApplicationError::InvalidBracket(_)
+        | ApplicationError::FaucetLimitExceeded(_, _) => StatusCode::BAD_REQUEST,
+        ApplicationError::TenantAlreadyExists(_)
+        | ApplicationError::DuplicateNotification(_)
+        | ApplicationError::DuplicateOrder(_, _)
+        | ApplicationError::NoLiquidity(_)
+        | ApplicationError::AccountInUse(_)
+        | ApplicationError::WithdrawalAlreadyResolved(_)
+        | ApplicationError::WithdrawalDelayed(_, _)
+        | ApplicationError::WithdrawalNeedsConfirmation(_)
+        | ApplicationError::TradeAlreadyBusted(_)
+        | ApplicationError::AuctionInProgress(_)
+        | ApplicationError::NonceReused(_, _) => StatusCode::CONFLICT,
+        ApplicationError::Unauthorized(_)
+        | ApplicationError::PinRequired(_)
+        | ApplicationError::WrongPin(_, _)
+        | ApplicationError::SignatureRequired(_)
+        | ApplicationError::InvalidSignature(_) => StatusCode::UNAUTHORIZED,
+        ApplicationError::PinLocked(_, _) => StatusCode::LOCKED,
+        ApplicationError::FaucetCoolingDown(_, _) | ApplicationError::OrderThrottled(_, _) => {
+            StatusCode::TOO_MANY_REQUESTS
+        }
+        ApplicationError::Forbidden(_)
+        | ApplicationError::DestinationNotWhitelisted(_, _)
+        | ApplicationError::StopLossBreached(_)
+        | ApplicationError::OrderNotOwned(_)
+        | ApplicationError::SandboxViolation(_) => StatusCode::FORBIDDEN,
+        ApplicationError::Overloaded(_)
+        | ApplicationError::ConcurrencyLimited(_)
+        | ApplicationError::TradingHalted(_, _)
+        | ApplicationError::StartingUp(_)
+        | ApplicationError::Maintenance => StatusCode::SERVICE_UNAVAILABLE,
+        ApplicationError::DeltasUnavailable(_) => StatusCode::GONE,
+        ApplicationError::DeadlineExceeded(_, _) => StatusCode::REQUEST_TIMEOUT,
+        ApplicationError::ExportFailed(_) | ApplicationError::StorageFailed(_) => {
+            StatusCode::INTERNAL_SERVER_ERROR
+        }
+    }
+}
+
+/// Turns rejections into JSON [`ErrorResponse`]s with a matching status code. Overload, startup, PIN lockout,
+/// throttling, trading halt, and faucet cooldown errors carry a `Retry-After` header, duplicate orders the original
+/// receipt.
+pub async fn handle_rejection(err: warp::Rejection) -> Result<impl warp::Reply, Infallible> {
+    let retry_after = match err.find() {
+        Some(OctopusError(ApplicationError::Overloaded(secs)))
+        | Some(OctopusError(ApplicationError::PinLocked(_, secs)))
+        | Some(OctopusError(ApplicationError::FaucetCoolingDown(_, secs)))
+        | Some(OctopusError(ApplicationError::OrderThrottled(_, secs)))
+        | Some(OctopusError(ApplicationError::TradingHalted(_, secs))) => Some(*secs),
+        Some(OctopusError(ApplicationError::StartingUp(_)))
+        | Some(OctopusError(ApplicationError::ConcurrencyLimited(_))) => Some(RETRY_AFTER_SECS),
+        _ => None,
+    };
+    let receipt = match err.find() {
+        Some(OctopusError(ApplicationError::DuplicateOrder(_, receipt))) => {
+            Some(receipt.as_ref().clone())
+        }
+        _ => None,
+    };
+    let (code, message) = if let Some(OctopusError(e)) = err.find() {
+        (status_of(e), format!("{:?}", e))
+    } else if err.is_not_found() {
+        (StatusCode::NOT_FOUND, "Not found".to_string())
+    } else if let Some(e) = err.find::<warp::filters::body::BodyDeserializeError>() {
+        (StatusCode::BAD_REQUEST, e.to_string())
+    } else if let Some(e) = err.find::<async_graphql_warp::GraphQLBadRequest>() {
+        (e.status(), e.0.to_string())
+    } else if let Some(e) = err.find::<warp::reject::InvalidQuery>() {
+        (StatusCode::BAD_REQUEST, e.to_string())
+    } else if let Some(e) = err.find::<warp::reject::MethodNotAllowed>() {
+        (StatusCode::METHOD_NOT_ALLOWED, e.to_string())
+    } else {
+        (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            format!("Unhandled rejection: {:?}", err),
+        )
+    };
+
+    let response = ErrorResponse {
+        code: code.as_u16(),
+        message,
+        receipt,
+    };
+    let reply = warp::reply::with_status(warp::reply::json(&response), code);
+    Ok(match retry_after {
+        Some(secs) => {
+            warp::reply::with_header(reply, RETRY_AFTER, secs.to_string()).into_response()
+        }
+        None => reply.into_response(),
+    })
+}
@@ -0,0 +1,313 @@
+//! End-of-day market reports: open, high, low, close, and volume of every market, the markets that moved the most,
+//! and the fees charged that day.
+//!
+//! The scheduler generates the report of the previous day once it ended. Reports are kept in memory and, with a
+//! data directory, saved as `<day>.json` and `<day>.csv` in the tenant's [`REPORTS_DIR`].
+use chrono::{DateTime, NaiveDate};
+use octopus_common::{
+    errors::ApplicationError,
+    types::{DailyReport, MarketSummary, Trade},
+};
+use std::{collections::BTreeMap, path::PathBuf};
+
+use crate::counters::write_atomically;
+use crate::fees::FeeLedger;
+
+/// The directory of a tenant's saved reports within the data directory
+pub const REPORTS_DIR: &str = "reports";
+
+/// How many markets a report lists as top movers
+pub const TOP_MOVERS: usize = 3;
+
+const DAY_MILLIS: u64 = 24 * 60 * 60 * 1000;
+
+/// The calendar day (UTC) of a unix timestamp (ms) as `YYYY-MM-DD`
+pub fn day_of(timestamp: u64) -> String {
+    let date = DateTime::from_timestamp_millis(timestamp as i64).unwrap_or_default();
+    date.format("%Y-%m-%d").to_string()
+}
+
+/// The first millisecond of `day` and of the day after
+pub fn day_bounds(day: &str) -> Result<(u64, u64), ApplicationError> {
+    let start = NaiveDate::parse_from_str(day, "%Y-%m-%d")
+        .ok()
+        .and_then(|date| date.and_hms_opt(0, 0, 0))
+        .map(|d| d.and_utc().timestamp_millis())
+        .filter(|ms| *ms >= 0)
+        .ok_or_else(|| ApplicationError::InvalidDay(day.to_string()))?;
+    Ok((start as u64, start as u64 + DAY_MILLIS))
+}
+
+/// Summarizes the trades and fees of `day`
+pub fn summarize(
+    day: &str,
+    trades: &[Trade],
+    ledger: &FeeLedger,
+) -> Result<DailyReport, ApplicationError> {
+    let (from, to) = day_bounds(day)?;
+    let mut markets: BTreeMap<&str, MarketSummary> = BTreeMap::new();
+    // The tape is in the order of execution, so the first and last trade of a market are its open and close
+    for trade in trades
+        .iter()
+        .filter(|t| t.busted.is_none() && from <= t.timestamp && t.timestamp < to)
+    {
+        let summary = markets
+            .entry(&trade.market)
+            .or_insert_with(|| MarketSummary {
+                market: trade.market.clone(),
+                open: trade.price,
+                high: trade.price,
+                low: trade.price,
+                close: trade.price,
+                volume: 0,
+                notional: 0,
+                trades: 0,
+                change_bps: 0,
+            });
+        summary.high = summary.high.max(trade.price);
+        summary.low = summary.low.min(trade.price);
+        summary.close = trade.price;
+        summary.volume += trade.amount;
+        summary.notional += trade.amount * trade.price;
+        summary.trades += 1;
+    }
+    let markets: Vec<MarketSummary> = markets
+        .into_values()
+        .map(|mut summary| {
+            if summary.open > 0 {
+                summary.change_bps = ((summary.close as i128 - summary.open as i128) * 10_000
+                    / summary.open as i128) as i64;
+            }
+            summary
+        })
+        .collect();
+    let mut movers: Vec<&MarketSummary> = markets.iter().filter(|m| m.change_bps != 0).collect();
+    movers.sort_by_key(|m| std::cmp::Reverse(m.change_bps.unsigned_abs()));
+    Ok(DailyReport {
+        day: day.to_string(),
+        top_movers: movers
+            .into_iter()
+            .take(TOP_MOVERS)
+            .map(|m| m.market.clone())
+            .collect(),
+        fees: ledger.charges_between(from, to).map(|c| c.amount).sum(),
+        markets,
+    })
+}
+
+/// Renders a report as CSV with one line per market
+pub fn to_csv(report: &DailyReport) -> String {
+    let mut csv = String::from(
+        "day,market,open,high,low,close,volume,notional,trades,change_bps,top_mover\n",
+    );
+    for summary in report.markets.iter() {
+        csv.push_str(&format!(
+            "{},{},{},{},{},{},{},{},{},{},{}\n",
+            report.day,
+            summary.market,
+            summary.open,
+            summary.high,
+            summary.low,
+            summary.close,
+            summary.volume,
+            summary.notional,
+            summary.trades,
+            summary.change_bps,
+            report.top_movers.contains(&summary.market)
+        ));
+    }
+    csv.push_str(&format!("{},Fees,,,,,,{},,,\n", report.day, report.fees));
+    csv
+}
+
+/// The end-of-day reports of a tenant
+#[derive(Debug, Default)]
+pub struct Reports {
+    reports: BTreeMap<String, DailyReport>,
+    /// The latest day the scheduled job generated a report for
+    last_generated: Option<String>,
+    /// Where reports are saved, in memory only without
+    dir: Option<PathBuf>,
+}
+
+impl Reports {
+    pub fn new() -> Self {
+        Reports::default()
+    }
+
+    /// Reports that are saved to `dir` as well
+    pub fn saved_to(dir: PathBuf) -> Self {
+        Reports {
+            dir: Some(dir),
+            ..Reports::default()
+        }
+    }
+
+    /// Creates or replaces the report of `day` and saves it
+    /// # Errors
+    /// The day is invalid or the report couldn't be saved
+    pub fn generate(
+        &mut self,
+        day: &str,
+        trades: &[Trade],
+        ledger: &FeeLedger,
+    ) -> Result<DailyReport, ApplicationError> {
+        let report = summarize(day, trades, ledger)?;
+        self.reports.insert(day.to_string(), report.clone());
+        if let Some(dir) = &self.dir {
+            let json = dir.join(format!("{}.json", day));
+            serde_json::to_vec_pretty(&report)
+                .map_err(std::io::Error::from)
+                .and_then(|contents| write_atomically(&json, &contents))
+                .map_err(|e| {
+                    ApplicationError::StorageFailed(format!("{}: {}", json.display(), e))
+                })?;
+            let csv = dir.join(format!("{}.csv", day));
+            write_atomically(&csv, to_csv(&report).as_bytes()).map_err(|e| {
+                ApplicationError::StorageFailed(format!("{}: {}", csv.display(), e))
+            })?;
+        }
+        Ok(report)
+    }
+
+    /// Generates the report of the previous day once it has ended. Returns the report when it generated one.
+    /// # Errors
+    /// The report couldn't be saved, it's generated again on the next call
+    pub fn generate_due(
+        &mut self,
+        now: u64,
+        trades: &[Trade],
+        ledger: &FeeLedger,
+    ) -> Result<Option<DailyReport>, ApplicationError> {
+        let day = day_of(now.saturating_sub(DAY_MILLIS));
+        if self.last_generated.as_ref() == Some(&day) {
+            return Ok(None);
+        }
+        let report = self.generate(&day, trades, ledger)?;
+        self.last_generated = Some(day);
+        Ok(Some(report))
+    }
+
+    /// The report of `day`
+    pub fn get(&self, day: &str) -> Result<DailyReport, ApplicationError> {
+        day_bounds(day)?;
+        self.reports
+            .get(day)
+            .cloned()
+            .ok_or_else(|| ApplicationError::ReportNotFound(day.to_string()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    // reduce the warnings for naming tests
+    #![allow(non_snake_case)]
+
+    use super::*;
+    use octopus_common::types::{FeeCharge, FeeKind, Side};
+
+    fn trade(market: &str, timestamp: u64, price: u64, amount: u64) -> Trade {
+        Trade {
+            id: timestamp,
+            ordinal: 0,
+            maker_ordinal: 0,
+            timestamp,
+            market: market.to_string(),
+            price,
+            amount,
+            taker: "ALICE".to_string(),
+            maker: "BOB".to_string(),
+            taker_side: Side::Buy,
+            limit_price: None,
+            taker_fee: 0,
+            maker_fee: 0,
+            busted: None,
+        }
+    }
+
+    #[test]
+    fn test_summarize_reports_ohlc_volume_and_movers() {
+        // 2026-10-16T00:00:00Z
+        let day = 1_792_108_800_000;
+        let mut busted = trade("OCT", day + 4, 1, 100);
+        busted.busted = Some(day + 5);
+        let trades = vec![
+            trade("OCT", day - 1, 50, 1),
+            trade("OCT", day + 1, 10, 2),
+            trade("BTC", day + 2, 100, 1),
+            trade("OCT", day + 3, 14, 1),
+            busted,
+            trade("OCT", day + 6, 12, 3),
+            trade("BTC", day + 7, 99, 1),
+            trade("ETH", day + 8, 20, 1),
+            trade("OCT", day + DAY_MILLIS, 30, 1),
+        ];
+        let mut ledger = FeeLedger::new();
+        for timestamp in [day - 1, day + 1, day + 6] {
+            ledger.record(FeeCharge {
+                timestamp,
+                account: "ALICE".to_string(),
+                amount: 3,
+                kind: FeeKind::Taker,
+            });
+        }
+
+        let report = summarize("2026-10-16", &trades, &ledger).unwrap();
+        assert_eq!(
+            report.markets[2],
+            MarketSummary {
+                market: "OCT".to_string(),
+                open: 10,
+                high: 14,
+                low: 10,
+                close: 12,
+                volume: 6,
+                notional: 70,
+                trades: 3,
+                change_bps: 2_000,
+            }
+        );
+        assert_eq!(report.markets[0].change_bps, -100);
+        // Markets that didn't move aren't movers
+        assert_eq!(report.top_movers, vec!["OCT", "BTC"]);
+        assert_eq!(report.fees, 6);
+        let csv = to_csv(&report);
+        assert!(csv.contains("2026-10-16,OCT,10,14,10,12,6,70,3,2000,true\n"));
+        assert!(csv.ends_with("2026-10-16,Fees,,,,,,6,,,\n"));
+    }
+
+    #[test]
+    fn test_Reports_generate_due_saves_the_previous_day_once() {
+        let dir = std::env::temp_dir().join(format!("octopus-reports-{}", std::process::id()));
+        let mut reports = Reports::saved_to(dir.clone());
+        let trades = vec![trade("OCT", 1_792_108_800_000, 10, 1)];
+        let ledger = FeeLedger::new();
+        assert_eq!(
+            reports.get("2026-10-16"),
+            Err(ApplicationError::ReportNotFound("2026-10-16".to_string()))
+        );
+        assert_eq!(
+            reports.get("16.10.2026"),
+            Err(ApplicationError::InvalidDay("16.10.2026".to_string()))
+        );
+
+        // 2026-10-17T08:00:00Z
+        let now = 1_792_224_000_000;
+        let report = reports
+            .generate_due(now, &trades, &ledger)
+            .unwrap()
+            .unwrap();
+        assert_eq!(report.day, "2026-10-16");
+        assert_eq!(reports.generate_due(now + 1, &trades, &ledger), Ok(None));
+        assert_eq!(reports.get("2026-10-16"), Ok(report.clone()));
+
+        let saved: DailyReport =
+            serde_json::from_slice(&std::fs::read(dir.join("2026-10-16.json")).unwrap()).unwrap();
+        assert_eq!(saved, report);
+        assert_eq!(
+            std::fs::read_to_string(dir.join("2026-10-16.csv")).unwrap(),
+            to_csv(&report)
+        );
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,322 @@
+use std::collections::HashMap;
+
+use octopus_common::{
+    errors::ApplicationError,
+    types::{Order, OrderId, OrderType, PriceLevel, Side, StopLossStatus},
+};
+
+use crate::{core::StopGuard, settlement::SettlementPolicy};
+
+/// The length of a trading session. Sessions start at midnight UTC.
+pub const SESSION_MILLIS: u64 = 24 * 60 * 60 * 1000;
+
+/// Whether `price` is at most `bps` basis points away from `reference`
+pub fn within_collar(price: u64, reference: u64, bps: u64) -> bool {
+    price.abs_diff(reference) as u128 * 10_000 <= reference as u128 * bps as u128
+}
+
+/// The session a unix timestamp (ms) belongs to
+pub fn session_of(now: u64) -> u64 {
+    now / SESSION_MILLIS
+}
+
+/// An account's limit on the loss within one session
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StopLoss {
+    pub max_daily_loss: u64,
+    /// The session the baseline was taken in
+    pub session: u64,
+    /// Profit or loss at the start of the session
+    pub baseline: i64,
+    pub breached: bool,
+}
+
+/// The stop-losses of all accounts
+#[derive(Debug, Default)]
+pub struct StopLosses {
+    limits: HashMap<String, StopLoss>,
+}
+
+impl StopLosses {
+    pub fn new() -> Self {
+        StopLosses::default()
+    }
+
+    /// Sets the limit of `signer`. A new limit starts measuring from the current `pnl`, but doesn't lift a breach.
+    pub fn set(&mut self, signer: &str, max_daily_loss: u64, pnl: i64, now: u64) {
+        let session = session_of(now);
+        let limit = self
+            .limits
+            .entry(signer.to_string())
+            .or_insert_with(|| StopLoss {
+                max_daily_loss,
+                session,
+                baseline: pnl,
+                breached: false,
+            });
+        limit.max_daily_loss = max_daily_loss;
+        if !limit.breached {
+            limit.session = session;
+            limit.baseline = pnl;
+        }
+    }
+
+    /// Removes the limit of `signer`
+    pub fn remove(&mut self, signer: &str) -> Option<StopLoss> {
+        self.limits.remove(signer)
+    }
+
+    /// The limit of `signer` as it applies to the session at `now`
+    pub fn status(&self, signer: &str, pnl: i64, now: u64) -> Option<StopLossStatus> {
+        self.limits.get(signer).map(|limit| {
+            if limit.session == session_of(now) {
+                StopLossStatus {
+                    max_daily_loss: limit.max_daily_loss,
+                    daily_pnl: pnl - limit.baseline,
+                    breached: limit.breached,
+                }
+            } else {
+                StopLossStatus {
+                    max_daily_loss: limit.max_daily_loss,
+                    daily_pnl: 0,
+                    breached: false,
+                }
+            }
+        })
+    }
+
+    /// Whether `signer` breached the limit in the session at `now`
+    pub fn is_breached(&self, signer: &str, now: u64) -> bool {
+        self.limits
+            .get(signer)
+            .is_some_and(|limit| limit.breached && limit.session == session_of(now))
+    }
+
+    /// Starts new sessions where necessary and returns the accounts that breached their limit just now
+    pub fn check(&mut self, now: u64, pnl_of: impl Fn(&str) -> i64) -> Vec<String> {
+        let session = session_of(now);
+        let mut breached = vec![];
+        for (signer, limit) in self.limits.iter_mut() {
+            let pnl = pnl_of(signer);
+            if limit.session != session {
+                limit.session = session;
+                limit.baseline = pnl;
+                limit.breached = false;
+            }
+            if !limit.breached && limit.baseline - pnl > limit.max_daily_loss as i64 {
+                limit.breached = true;
+                breached.push(signer.clone());
+            }
+        }
+        breached.sort();
+        breached
+    }
+}
+
+/// Protects the markets from orders executing far from the last traded price: such an order is rejected, or halts its
+/// market for a cool-down period if one is set
+#[derive(Debug, Default)]
+pub struct CircuitBreaker {
+    /// Orders executing further than this many basis points from the last price trip the breaker, off unless set
+    pub band_bps: Option<u64>,
+    /// How long a tripped market halts, 0 only rejects the order
+    pub halt_millis: u64,
+    /// The halted markets with the unix timestamp (ms) they resume at
+    halted: HashMap<String, u64>,
+}
+
+impl CircuitBreaker {
+    pub fn new() -> Self {
+        CircuitBreaker::default()
+    }
+
+    /// When `market` resumes trading, `None` if it isn't halted at `now`
+    pub fn halted_until(&self, market: &str, now: u64) -> Option<u64> {
+        self.halted
+            .get(market)
+            .copied()
+            .filter(|resume_at| *resume_at > now)
+    }
+
+    /// Checks an order of `market` that would execute at worst at `worst_price`, halting the market if it trips the
+    /// breaker and a cool-down is set
+    /// # Errors
+    /// - The market is halted, or halts with this order
+    /// - The order would execute outside the band around `last_price`
+    pub fn check(
+        &mut self,
+        market: &str,
+        worst_price: Option<u64>,
+        last_price: Option<u64>,
+        now: u64,
+    ) -> Result<(), ApplicationError> {
+        if let Some(resume_at) = self.halted_until(market, now) {
+            return Err(ApplicationError::TradingHalted(
+                market.to_string(),
+                (resume_at - now).div_ceil(1000),
+            ));
+        }
+        let (Some(bps), Some(price), Some(last_price)) = (self.band_bps, worst_price, last_price)
+        else {
+            return Ok(());
+        };
+        if within_collar(price, last_price, bps) {
+            return Ok(());
+        }
+        if self.halt_millis == 0 {
+            return Err(ApplicationError::PriceBandExceeded(price, last_price));
+        }
+        log::warn!(
+            "Halting {} for {} ms, an order would execute at {} after the last price {}",
+            market,
+            self.halt_millis,
+            price,
+            last_price
+        );
+        self.halted
+            .insert(market.to_string(), now + self.halt_millis);
+        Err(ApplicationError::TradingHalted(
+            market.to_string(),
+            self.halt_millis.div_ceil(1000),
+        ))
+    }
+}
+
+/// Admits a triggered buy stop only if its signer can pay for what it fills at and the fee, out of the funds the signer
+/// had when the operation that triggered it started. Every admitted stop takes its cost out of its signer's funds, so
+/// two stops can't spend the same funds. Sell stops don't need funds.
+#[derive(Debug, Clone)]
+pub struct StopFunds {
+    funds: HashMap<String, u64>,
+    fee_bps: u64,
+    settlement: SettlementPolicy,
+}
+
+impl StopFunds {
+    /// Guards stops with the `funds` of their signers, charging `fee_bps` rounded by `settlement`
+    pub fn new(funds: HashMap<String, u64>, fee_bps: u64, settlement: SettlementPolicy) -> Self {
+        StopFunds {
+            funds,
+            fee_bps,
+            settlement,
+        }
+    }
+}
+
+impl StopGuard for StopFunds {
+    fn admit(&mut self, order_id: OrderId, order: &Order, fills: &[PriceLevel]) -> bool {
+        if order.side == Side::Sell {
+            return true;
+        }
+        let cost = match order.order_type {
+            // What doesn't fill rests at the limit price
+            OrderType::Limit => order.amount.saturating_mul(order.price),
+            OrderType::Market => fills
+                .iter()
+                .map(|fill| fill.price.saturating_mul(fill.quantity))
+                .fold(0, u64::saturating_add),
+        };
+        let cost = cost.saturating_add(self.settlement.fee(cost, self.fee_bps, &order.market));
+        match self.funds.get_mut(&order.signer) {
+            Some(funds) if *funds >= cost => {
+                *funds -= cost;
+                true
+            }
+            funds => {
+                log::info!(
+                    "Cancelled the stop {} of {}: it needs {}, {} is left",
+                    order_id,
+                    order.signer,
+                    cost,
+                    funds.map_or(0, |funds| *funds)
+                );
+                false
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    // reduce the warnings for naming tests
+    #![allow(non_snake_case)]
+
+    use super::*;
+
+    #[test]
+    fn test_within_collar_includes_the_bounds() {
+        assert!(within_collar(110, 100, 1_000));
+        assert!(within_collar(90, 100, 1_000));
+        assert!(!within_collar(111, 100, 1_000));
+        assert!(!within_collar(1, 0, 1_000));
+        assert!(within_collar(u64::MAX, u64::MAX, 0));
+    }
+
+    #[test]
+    fn test_CircuitBreaker_check_rejects_or_halts_outside_the_band() {
+        let mut breaker = CircuitBreaker::new();
+        assert_eq!(breaker.check("BTC-USD", Some(200), Some(100), 0), Ok(()));
+
+        breaker.band_bps = Some(1_000);
+        assert_eq!(breaker.check("BTC-USD", Some(110), Some(100), 0), Ok(()));
+        // Without a trade there's nothing to measure against
+        assert_eq!(breaker.check("BTC-USD", Some(200), None, 0), Ok(()));
+        assert_eq!(
+            breaker.check("BTC-USD", Some(111), Some(100), 0),
+            Err(ApplicationError::PriceBandExceeded(111, 100))
+        );
+        assert_eq!(breaker.halted_until("BTC-USD", 0), None);
+
+        breaker.halt_millis = 60_000;
+        assert_eq!(
+            breaker.check("BTC-USD", Some(89), Some(100), 1_000),
+            Err(ApplicationError::TradingHalted("BTC-USD".to_string(), 60))
+        );
+        // Even orders within the band wait for the cool-down, other markets don't
+        assert_eq!(
+            breaker.check("BTC-USD", Some(100), Some(100), 30_500),
+            Err(ApplicationError::TradingHalted("BTC-USD".to_string(), 31))
+        );
+        assert_eq!(breaker.check("ETH-USD", None, None, 30_500), Ok(()));
+        assert_eq!(breaker.halted_until("BTC-USD", 61_000), None);
+        assert_eq!(
+            breaker.check("BTC-USD", Some(100), Some(100), 61_000),
+            Ok(())
+        );
+    }
+
+    #[test]
+    fn test_StopLosses_check_breaches_until_next_session() {
+        let mut stop_losses = StopLosses::new();
+        stop_losses.set("ALICE", 50, 20, 0);
+
+        assert!(stop_losses.check(1_000, |_| -30).is_empty());
+        assert_eq!(stop_losses.check(2_000, |_| -31), vec!["ALICE"]);
+        assert!(stop_losses.is_breached("ALICE", 2_000));
+        // Only reported once
+        assert!(stop_losses.check(3_000, |_| -40).is_empty());
+        assert_eq!(
+            stop_losses.status("ALICE", -40, 3_000),
+            Some(StopLossStatus {
+                max_daily_loss: 50,
+                daily_pnl: -60,
+                breached: true
+            })
+        );
+
+        // Raising the limit doesn't lift the breach
+        stop_losses.set("ALICE", 100, -40, 4_000);
+        assert!(stop_losses.is_breached("ALICE", 4_000));
+
+        // The next session starts over
+        assert!(!stop_losses.is_breached("ALICE", SESSION_MILLIS));
+        assert!(stop_losses.check(SESSION_MILLIS, |_| -40).is_empty());
+        assert_eq!(
+            stop_losses
+                .status("ALICE", -40, SESSION_MILLIS)
+                .unwrap()
+                .daily_pnl,
+            0
+        );
+    }
+}
@@ -0,0 +1,59 @@
+use std::{
+    sync::Arc,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use crate::tenants::Tenants;
+
+/// How often the scheduler looks for due work
+pub const TICK: Duration = Duration::from_secs(1);
+
+/// The current time as a unix timestamp in milliseconds
+pub fn now_millis() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis() as u64)
+        .unwrap_or_default()
+}
+
+/// Runs periodic work, i.e. recurring buys, expiring good 'til time orders, dust sweeps, monthly invoices, and
+/// end-of-day reports, for every tenant in the background
+pub fn start(tenants: Arc<Tenants>, tick: Duration) -> tokio::task::JoinHandle<()> {
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(tick);
+        loop {
+            interval.tick().await;
+            let now = now_millis();
+            for (name, trading_platform) in tenants.platforms() {
+                let mut ledger_lock = trading_platform.lock().unwrap();
+                let executed = ledger_lock.run_recurring_buys(now);
+                if executed > 0 {
+                    log::debug!("Executed {} recurring buys for tenant {}", executed, name);
+                }
+                let expired = ledger_lock.expire_orders(now);
+                if !expired.is_empty() {
+                    log::debug!("Expired {} orders for tenant {}", expired.len(), name);
+                }
+                match ledger_lock.sweep_dust(now) {
+                    Ok(0) => {}
+                    Ok(swept) => log::info!("Swept {} of dust for tenant {}", swept, name),
+                    Err(e) => log::warn!("Failed to sweep the dust of tenant {}: {:?}", name, e),
+                }
+                let platform = &mut *ledger_lock;
+                if let Some(month) = platform.invoices.generate_due(now, &platform.fees) {
+                    log::info!("Generated the {} invoices for tenant {}", month, name);
+                }
+                match platform
+                    .reports
+                    .generate_due(now, &platform.trades, &platform.fees)
+                {
+                    Ok(Some(report)) => {
+                        log::info!("Generated the {} report for tenant {}", report.day, name)
+                    }
+                    Ok(None) => {}
+                    Err(e) => log::warn!("Failed to save the report of tenant {}: {:?}", name, e),
+                }
+            }
+        }
+    })
+}
@@ -0,0 +1,259 @@
+//! Search over the transaction log and the audit trail, so operators find an entry without downloading the logs.
+//!
+//! Transactions are indexed as they're logged: by account for signer prefixes, by amount for amount ranges, and by
+//! the words of their memos for the text. A search starts from the candidates of the narrowest index the query uses
+//! and checks every criterion on them. The audit trail is small, its entries are checked one by one.
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+
+use octopus_common::tx::Tx;
+use serde::{Deserialize, Serialize};
+
+use crate::{archives::ArchivedTx, audit::AuditEntry};
+
+/// The most results of each log in one search
+pub const MAX_SEARCH_RESULTS: usize = 1000;
+
+/// The criteria of a search, every one that is set has to match
+#[derive(Debug, Default, Clone, Deserialize)]
+pub struct SearchQuery {
+    /// A party of the transaction, or the actor of the audit entry, starts with it
+    pub signer_prefix: Option<String>,
+    pub min_amount: Option<u64>,
+    pub max_amount: Option<u64>,
+    /// Every word is in the memo of the transaction or the justification of the audit entry, ignoring case
+    pub text: Option<String>,
+    /// Unix timestamp (ms) of the earliest entry
+    pub from: Option<u64>,
+    /// Unix timestamp (ms) the entries are before
+    pub until: Option<u64>,
+    /// Results of each log, newest first, [`MAX_SEARCH_RESULTS`] at most
+    pub limit: Option<usize>,
+}
+
+impl SearchQuery {
+    /// The number of results to return of each log
+    pub fn limit(&self) -> usize {
+        self.limit
+            .unwrap_or(MAX_SEARCH_RESULTS)
+            .min(MAX_SEARCH_RESULTS)
+    }
+
+    /// Whether the entry at `timestamp` is in the time window
+    pub fn in_window(&self, timestamp: u64) -> bool {
+        self.from.is_none_or(|from| timestamp >= from)
+            && self.until.is_none_or(|until| timestamp < until)
+    }
+
+    /// Whether `tx` matches the criteria besides the time window
+    pub fn matches_tx(&self, tx: &Tx) -> bool {
+        let amount = tx.amount();
+        self.signer_prefix.as_deref().is_none_or(|prefix| {
+            tx.parties()
+                .into_iter()
+                .any(|party| party.starts_with(prefix))
+        }) && self.min_amount.is_none_or(|min| amount >= min)
+            && self.max_amount.is_none_or(|max| amount <= max)
+            && self.text.as_deref().is_none_or(|text| {
+                let memo_words = tx.memo().map(memo_words).unwrap_or_default();
+                words(text).all(|word| memo_words.contains(&word))
+            })
+    }
+
+    /// Whether the audit `entry` matches. Audit entries have no amount, they don't match a query for one.
+    pub fn matches_audit(&self, entry: &AuditEntry) -> bool {
+        self.min_amount.is_none()
+            && self.max_amount.is_none()
+            && self.in_window(entry.timestamp)
+            && self
+                .signer_prefix
+                .as_deref()
+                .is_none_or(|prefix| entry.actor.starts_with(prefix))
+            && self.text.as_deref().is_none_or(|text| {
+                let justification: BTreeSet<String> = entry
+                    .justification
+                    .as_deref()
+                    .map(|justification| words(justification).collect())
+                    .unwrap_or_default();
+                words(text).all(|word| justification.contains(&word))
+            })
+    }
+}
+
+/// What a search found, newest first
+#[derive(Debug, Clone, Serialize)]
+pub struct SearchResults {
+    pub transactions: Vec<ArchivedTx>,
+    pub audit_entries: Vec<AuditEntry>,
+}
+
+/// The lowercase words of `text`
+fn words(text: &str) -> impl Iterator<Item = String> + '_ {
+    text.split(|c: char| !c.is_alphanumeric())
+        .filter(|word| !word.is_empty())
+        .map(str::to_lowercase)
+}
+
+/// The words of a memo's text and of its metadata keys and values
+fn memo_words(memo: &octopus_common::tx::Memo) -> BTreeSet<String> {
+    memo.text
+        .iter()
+        .chain(memo.metadata.iter().flat_map(|(key, value)| [key, value]))
+        .flat_map(|text| words(text))
+        .collect()
+}
+
+/// The positions of the logged transactions by account, amount, and memo word
+#[derive(Debug, Default)]
+pub struct TxIndex {
+    accounts: BTreeMap<String, Vec<usize>>,
+    amounts: BTreeMap<u64, Vec<usize>>,
+    words: HashMap<String, Vec<usize>>,
+}
+
+impl TxIndex {
+    /// Indexes `tx`, logged at `position`. Positions are added in ascending order.
+    pub fn insert(&mut self, position: usize, tx: &Tx) {
+        let mut parties = tx.parties();
+        parties.dedup();
+        for party in parties {
+            self.accounts
+                .entry(party.to_string())
+                .or_default()
+                .push(position);
+        }
+        self.amounts.entry(tx.amount()).or_default().push(position);
+        for word in tx.memo().map(memo_words).unwrap_or_default() {
+            self.words.entry(word).or_default().push(position);
+        }
+    }
+
+    /// Files the transactions of `signer` under `token`, after they were anonymized
+    pub fn rename(&mut self, signer: &str, token: &str) {
+        if let Some(positions) = self.accounts.remove(signer) {
+            let renamed = self.accounts.entry(token.to_string()).or_default();
+            renamed.extend(positions);
+            renamed.sort_unstable();
+        }
+    }
+
+    /// The positions that may match `query`, ascending, from the index with the fewest. `None` if the query uses no
+    /// index.
+    pub fn candidates(&self, query: &SearchQuery) -> Option<Vec<usize>> {
+        let mut lists: Vec<Vec<usize>> = vec![];
+        if let Some(prefix) = &query.signer_prefix {
+            let mut positions: Vec<usize> = self
+                .accounts
+                .range(prefix.clone()..)
+                .take_while(|(account, _)| account.starts_with(prefix.as_str()))
+                .flat_map(|(_, positions)| positions.iter().copied())
+                .collect();
+            positions.sort_unstable();
+            positions.dedup();
+            lists.push(positions);
+        }
+        if query.min_amount.is_some() || query.max_amount.is_some() {
+            let range = query.min_amount.unwrap_or(0)..=query.max_amount.unwrap_or(u64::MAX);
+            let mut positions: Vec<usize> = match range.is_empty() {
+                true => vec![],
+                false => self
+                    .amounts
+                    .range(range)
+                    .flat_map(|(_, positions)| positions.iter().copied())
+                    .collect(),
+            };
+            positions.sort_unstable();
+            lists.push(positions);
+        }
+        if let Some(text) = &query.text {
+            let rarest = words(text)
+                .map(|word| self.words.get(&word).cloned().unwrap_or_default())
+                .min_by_key(Vec::len);
+            lists.extend(rarest);
+        }
+        lists.into_iter().min_by_key(Vec::len)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    // reduce the warnings for naming tests
+    #![allow(non_snake_case)]
+
+    use super::*;
+    use crate::balances::BalanceLog;
+    use octopus_common::tx::Memo;
+
+    #[test]
+    fn test_BalanceLog_search_combines_the_criteria() {
+        let mut balance_log = BalanceLog::default();
+        let memo = |text: &str| {
+            Some(Memo {
+                text: Some(text.to_string()),
+                ..Memo::default()
+            })
+        };
+        let txs = [
+            Tx::Deposit {
+                account: "acme_ALICE".to_string(),
+                amount: 100,
+                memo: memo("Invoice 2024-17"),
+            },
+            Tx::Deposit {
+                account: "acme_BOB".to_string(),
+                amount: 500,
+                memo: memo("invoice 2024-18"),
+            },
+            Tx::Withdraw {
+                account: "CAROL".to_string(),
+                amount: 100,
+                memo: memo("invoice 2024-17 refund"),
+            },
+            Tx::Settlement {
+                trade_id: 1,
+                from: "acme_BOB".to_string(),
+                to: "CAROL".to_string(),
+                amount: 50,
+            },
+        ];
+        for (i, tx) in txs.into_iter().enumerate() {
+            balance_log.record(i as u64, 1_000 * i as u64, tx);
+        }
+        let ordinals = |query: SearchQuery| {
+            balance_log
+                .search(&query)
+                .iter()
+                .map(|found| found.ordinal)
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(ordinals(SearchQuery::default()), [3, 2, 1, 0]);
+        let acme = SearchQuery {
+            signer_prefix: Some("acme_".to_string()),
+            ..SearchQuery::default()
+        };
+        assert_eq!(ordinals(acme.clone()), [3, 1, 0]);
+        assert_eq!(
+            ordinals(SearchQuery {
+                max_amount: Some(100),
+                ..acme.clone()
+            }),
+            [3, 0]
+        );
+        assert_eq!(
+            ordinals(SearchQuery {
+                text: Some("INVOICE 2024-17".to_string()),
+                ..SearchQuery::default()
+            }),
+            [2, 0]
+        );
+        assert_eq!(
+            ordinals(SearchQuery {
+                from: Some(1_000),
+                until: Some(3_000),
+                limit: Some(1),
+                ..acme
+            }),
+            [1]
+        );
+    }
+}
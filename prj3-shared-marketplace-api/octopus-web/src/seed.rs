@@ -0,0 +1,133 @@
+//! The initial state of an environment, loaded from the `storage.seed` file before the server accepts orders.
+//!
+//! Files ending in `.yaml` or `.yml` are read as YAML, everything else as JSON. See `seed.example.yaml`.
+use std::path::Path;
+
+use octopus_common::{errors::ApplicationError, types::Order};
+use serde::Deserialize;
+
+use crate::{markets::MarketDefinition, trading_platform::TradingPlatform};
+
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+pub struct SeedAccount {
+    pub signer: String,
+    pub balance: u64,
+}
+
+/// Accounts are created first, then the markets are configured, then the orders are placed in the given order
+#[derive(Debug, Default, Clone, PartialEq, Eq, Deserialize)]
+#[serde(default)]
+pub struct SeedData {
+    pub accounts: Vec<SeedAccount>,
+    pub markets: Vec<MarketDefinition>,
+    pub orders: Vec<Order>,
+}
+
+impl SeedData {
+    /// Reads a seed file
+    /// # Errors
+    /// The file can't be read or parsed
+    pub fn load(path: &Path) -> Result<Self, ApplicationError> {
+        let invalid =
+            |e: String| ApplicationError::InvalidSeed(format!("{}: {}", path.display(), e));
+        let contents = std::fs::read_to_string(path).map_err(|e| invalid(e.to_string()))?;
+        match path.extension().and_then(|extension| extension.to_str()) {
+            Some("yaml" | "yml") => {
+                serde_yaml::from_str(&contents).map_err(|e| invalid(e.to_string()))
+            }
+            _ => serde_json::from_str(&contents).map_err(|e| invalid(e.to_string())),
+        }
+    }
+
+    /// The number of deposits, market definitions and orders to apply
+    pub fn events(&self) -> usize {
+        self.accounts.len() + self.markets.len() + self.orders.len()
+    }
+
+    /// Applies the seed to a platform, reporting the number of events applied so far after each one
+    /// # Errors
+    /// A deposit or an order failed, e.g. because an order's account isn't funded
+    pub fn apply(
+        &self,
+        trading_platform: &mut TradingPlatform,
+        mut progress: impl FnMut(usize),
+    ) -> Result<(), ApplicationError> {
+        let mut applied = 0;
+        let mut step = || {
+            applied += 1;
+            progress(applied);
+        };
+        let failed = |what: String, e: ApplicationError| {
+            ApplicationError::InvalidSeed(format!("{} failed: {:?}", what, e))
+        };
+        for account in &self.accounts {
+            trading_platform
+                .deposit(&account.signer, account.balance)
+                .map_err(|e| failed(format!("funding {}", account.signer), e))?;
+            step();
+        }
+        for market in &self.markets {
+            trading_platform
+                .markets
+                .insert(&market.symbol, market.config.clone());
+            step();
+        }
+        for order in &self.orders {
+            trading_platform
+                .order(order.clone())
+                .map_err(|e| failed(format!("{:?}", order), e))?;
+            step();
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    // reduce the warnings for naming tests
+    #![allow(non_snake_case)]
+
+    use super::*;
+    use octopus_common::types::{MarketStatus, Side};
+
+    #[test]
+    fn test_SeedData_apply_yaml() {
+        let seed: SeedData = serde_yaml::from_str(
+            "
+accounts:
+  - signer: ALICE
+    balance: 1000
+markets:
+  - symbol: BTC-USD
+    tick_size: 5
+    status: halted
+orders:
+  - price: 10
+    amount: 2
+    side: Sell
+    signer: ALICE
+",
+        )
+        .unwrap();
+        let mut trading_platform = TradingPlatform::new();
+        let mut progress = vec![];
+        seed.apply(&mut trading_platform, |applied| progress.push(applied))
+            .unwrap();
+        assert_eq!(progress, vec![1, 2, 3]);
+        assert_eq!(seed.events(), 3);
+        assert_eq!(trading_platform.balance_of("ALICE"), Ok(&1000));
+        assert_eq!(trading_platform.orderbook()[0].side, Side::Sell);
+        let market = trading_platform.markets.get("BTC-USD").unwrap();
+        assert_eq!((market.tick_size, market.lot_size), (5, 1));
+        assert_eq!(market.status, MarketStatus::Halted);
+
+        let unfunded = SeedData {
+            orders: vec![seed.orders[0].clone()],
+            ..SeedData::default()
+        };
+        assert!(matches!(
+            unfunded.apply(&mut TradingPlatform::new(), |_| {}),
+            Err(ApplicationError::InvalidSeed(_))
+        ));
+    }
+}
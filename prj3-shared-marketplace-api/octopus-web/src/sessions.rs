@@ -0,0 +1,218 @@
+//! Trading over a websocket: orders are sent as messages and answered with receipts on the same connection.
+//!
+//! Sessions can opt into cancel-on-disconnect. Once such a connection drops, every open order of the accounts that
+//! traded in it is cancelled, so market makers don't leave stale quotes behind when they lose connectivity.
+use futures_util::{SinkExt, StreamExt};
+use octopus_common::{
+    errors::ApplicationError,
+    types::{ApiKeyScope, ErrorResponse, PartialOrder, SessionMessage, SessionRequest},
+};
+use std::{
+    collections::BTreeSet,
+    sync::{Arc, Mutex},
+};
+use warp::ws::{Message, WebSocket};
+
+use crate::{
+    auth::Credential, ingest::OrderQueue, rejection::status_of, scheduler::now_millis,
+    trading_platform::TradingPlatform,
+};
+
+fn rejected(e: ApplicationError) -> SessionMessage {
+    SessionMessage::Rejected(ErrorResponse {
+        code: status_of(&e).as_u16(),
+        message: format!("{:?}", e),
+        receipt: match e {
+            ApplicationError::DuplicateOrder(_, receipt) => Some(*receipt),
+            _ => None,
+        },
+    })
+}
+
+/// One authenticated trading connection
+pub struct TradingSession {
+    credential: Credential,
+    cancel_on_disconnect: bool,
+    /// The accounts whose orders are cancelled on disconnect
+    traders: BTreeSet<String>,
+    order_queue: Arc<OrderQueue>,
+    trading_platform: Arc<Mutex<TradingPlatform>>,
+}
+
+impl TradingSession {
+    pub fn new(
+        credential: Credential,
+        cancel_on_disconnect: bool,
+        order_queue: Arc<OrderQueue>,
+        trading_platform: Arc<Mutex<TradingPlatform>>,
+    ) -> Self {
+        TradingSession {
+            traders: credential.signer.iter().cloned().collect(),
+            credential,
+            cancel_on_disconnect,
+            order_queue,
+            trading_platform,
+        }
+    }
+
+    /// Processes one message of the client and returns the reply
+    pub async fn handle(&mut self, text: &str) -> SessionMessage {
+        let request = match serde_json::from_str::<SessionRequest>(text) {
+            Ok(request) => request,
+            Err(e) => return rejected(ApplicationError::InvalidSessionMessage(e.to_string())),
+        };
+        match request {
+            SessionRequest::Order(order) => {
+                if let Err(e) = self.credential.authorize(&order.signer, ApiKeyScope::Trade) {
+                    return rejected(e);
+                }
+                let signer = order.signer.clone();
+                match self.order_queue.submit(order).await {
+                    Ok(receipt) => {
+                        self.traders.insert(signer);
+                        SessionMessage::Receipt(receipt)
+                    }
+                    Err(e) => rejected(e),
+                }
+            }
+        }
+    }
+
+    /// Ends the session and applies cancel-on-disconnect, returns the cancelled orders
+    pub fn close(self) -> Vec<PartialOrder> {
+        if !self.cancel_on_disconnect {
+            return vec![];
+        }
+        let now = now_millis();
+        let mut ledger_lock = self.trading_platform.lock().unwrap();
+        let cancelled: Vec<_> = self
+            .traders
+            .iter()
+            .flat_map(|signer| {
+                let cancelled = ledger_lock.cancel_all(signer, now);
+                // The account chose to have its orders cancelled, they count towards its cancel ratio
+                ledger_lock
+                    .throttle
+                    .record_cancels(signer, cancelled.len(), now);
+                cancelled
+            })
+            .collect();
+        if !cancelled.is_empty() {
+            log::info!(
+                "Session of {:?} disconnected, cancelled {} open orders",
+                self.traders,
+                cancelled.len()
+            );
+        }
+        cancelled
+    }
+}
+
+/// Answers every text message of the client until the connection closes or fails, then closes the session
+pub async fn serve(socket: WebSocket, mut session: TradingSession) {
+    let (mut outgoing, mut incoming) = socket.split();
+    while let Some(Ok(message)) = incoming.next().await {
+        if message.is_close() {
+            break;
+        }
+        let Ok(text) = message.to_str() else {
+            continue;
+        };
+        let reply = session.handle(text).await;
+        let text = serde_json::to_string(&reply).expect("session messages serialize");
+        if outgoing.send(Message::text(text)).await.is_err() {
+            break;
+        }
+    }
+    session.close();
+}
+
+#[cfg(test)]
+mod tests {
+    // reduce the warnings for naming tests
+    #![allow(non_snake_case)]
+
+    use super::*;
+    use octopus_common::types::{Order, OrderType, Role, Side, TimeInForce, DEFAULT_MARKET};
+
+    fn order(signer: &str, price: u64) -> Order {
+        Order {
+            price,
+            amount: 1,
+            side: Side::Sell,
+            signer: signer.to_string(),
+            client_order_id: None,
+            signature: None,
+            order_type: OrderType::Limit,
+            time_in_force: TimeInForce::Gtc,
+            trigger_price: None,
+            market: DEFAULT_MARKET.to_string(),
+        }
+    }
+
+    fn request(order: Order) -> String {
+        serde_json::to_string(&SessionRequest::Order(order)).unwrap()
+    }
+
+    fn session(cancel_on_disconnect: bool) -> (TradingSession, Arc<Mutex<TradingPlatform>>) {
+        let trading_platform = Arc::new(Mutex::new(TradingPlatform::new()));
+        for signer in ["ALICE", "BOB"] {
+            trading_platform
+                .lock()
+                .unwrap()
+                .deposit(signer, 100)
+                .unwrap();
+        }
+        let credential = Credential {
+            role: Role::Trader,
+            signer: Some("ALICE".to_string()),
+            scopes: vec![ApiKeyScope::Trade],
+        };
+        let order_queue = Arc::new(OrderQueue::start(trading_platform.clone(), 8));
+        let session = TradingSession::new(
+            credential,
+            cancel_on_disconnect,
+            order_queue,
+            trading_platform.clone(),
+        );
+        (session, trading_platform)
+    }
+
+    #[tokio::test]
+    async fn test_TradingSession_close_cancels_the_open_orders() {
+        let (mut session, trading_platform) = session(true);
+        assert!(matches!(
+            session.handle(&request(order("ALICE", 10))).await,
+            SessionMessage::Receipt(_)
+        ));
+        assert!(matches!(
+            session.handle(&request(order("BOB", 10))).await,
+            SessionMessage::Rejected(ErrorResponse { code: 403, .. })
+        ));
+        assert!(matches!(
+            session.handle("{}").await,
+            SessionMessage::Rejected(ErrorResponse { code: 400, .. })
+        ));
+        // Orders placed elsewhere are cancelled too, it's the account's quotes that go stale
+        let mut ledger_lock = trading_platform.lock().unwrap();
+        ledger_lock.order(order("ALICE", 11)).unwrap();
+        ledger_lock.order(order("BOB", 12)).unwrap();
+        drop(ledger_lock);
+
+        assert_eq!(session.close().len(), 2);
+        let orderbook = trading_platform.lock().unwrap().orderbook();
+        assert_eq!(orderbook.len(), 1);
+        assert_eq!(orderbook[0].signer, "BOB");
+    }
+
+    #[tokio::test]
+    async fn test_TradingSession_close_keeps_orders_without_opt_in() {
+        let (mut session, trading_platform) = session(false);
+        assert!(matches!(
+            session.handle(&request(order("ALICE", 10))).await,
+            SessionMessage::Receipt(_)
+        ));
+        assert!(session.close().is_empty());
+        assert_eq!(trading_platform.lock().unwrap().orderbook().len(), 1);
+    }
+}
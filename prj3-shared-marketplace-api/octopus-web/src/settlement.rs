@@ -0,0 +1,142 @@
+//! Settlement math: every amount derived from another one, like a fee from a notional, is rounded here, once, to the
+//! smallest unit settled in its market. Amounts split over several entries are allocated so that the parts add up to
+//! the rounded total, no unit is lost or created on the way.
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+/// How a fraction of the smallest unit is settled
+#[derive(Clone, Copy, Default, PartialEq, Eq, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Rounding {
+    /// Down, in favor of the account paying
+    #[default]
+    Floor,
+    /// Up, in favor of the platform
+    Ceil,
+    /// To the nearest unit, halves to the even one (banker's rounding)
+    HalfEven,
+}
+
+impl Rounding {
+    /// `numerator / denominator` rounded to a whole number
+    pub fn divide(self, numerator: u128, denominator: u128) -> u128 {
+        let (quotient, remainder) = (numerator / denominator, numerator % denominator);
+        let up = match self {
+            Rounding::Floor => false,
+            Rounding::Ceil => remainder > 0,
+            Rounding::HalfEven => match (remainder * 2).cmp(&denominator) {
+                std::cmp::Ordering::Less => false,
+                std::cmp::Ordering::Equal => quotient % 2 == 1,
+                std::cmp::Ordering::Greater => true,
+            },
+        };
+        quotient + up as u128
+    }
+}
+
+/// How the amounts of a settlement are rounded
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SettlementPolicy {
+    pub rounding: Rounding,
+    /// The smallest amount settled by market symbol, 1 for markets not listed
+    pub units: BTreeMap<String, u64>,
+}
+
+impl SettlementPolicy {
+    /// The smallest amount settled in `market`
+    pub fn unit(&self, market: &str) -> u64 {
+        self.units.get(market).copied().unwrap_or(1)
+    }
+
+    /// The fee in basis points of `notional` in `market`, a whole number of its units
+    pub fn fee(&self, notional: u64, bps: u64, market: &str) -> u64 {
+        let unit = self.unit(market) as u128;
+        let units = self
+            .rounding
+            .divide(notional as u128 * bps as u128, 10_000 * unit);
+        (units * unit).min(u64::MAX as u128) as u64
+    }
+
+    /// Splits `total` of `market` in proportion to `weights`. Each part is a whole number of units, the units left
+    /// over go to the parts with the largest remainders, the earlier one on ties. The parts add up to `total` unless
+    /// it isn't a whole number of units, the rest of the last unit goes to the largest part then.
+    pub fn allocate(&self, total: u64, weights: &[u64], market: &str) -> Vec<u64> {
+        let weight: u128 = weights.iter().map(|w| *w as u128).sum();
+        if weight == 0 {
+            return vec![0; weights.len()];
+        }
+        let unit = self.unit(market);
+        let units = (total / unit) as u128;
+        let mut parts: Vec<(u128, u128)> = weights
+            .iter()
+            .map(|w| {
+                let share = units * *w as u128;
+                (share / weight, share % weight)
+            })
+            .collect();
+        let mut left = units - parts.iter().map(|(part, _)| part).sum::<u128>();
+        let mut by_remainder: Vec<usize> = (0..parts.len()).collect();
+        by_remainder.sort_by_key(|i| std::cmp::Reverse(parts[*i].1));
+        for i in by_remainder {
+            if left == 0 {
+                break;
+            }
+            parts[i].0 += 1;
+            left -= 1;
+        }
+        let mut allocated: Vec<u64> = parts
+            .into_iter()
+            .map(|(part, _)| part as u64 * unit)
+            .collect();
+        if let Some(largest) = (0..allocated.len()).max_by_key(|i| (allocated[*i], usize::MAX - i))
+        {
+            allocated[largest] += total % unit;
+        }
+        allocated
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    // reduce the warnings for naming tests
+    #![allow(non_snake_case)]
+
+    use super::*;
+
+    #[test]
+    fn test_SettlementPolicy_fee_rounds_to_the_unit_of_the_market() {
+        let floor = SettlementPolicy::default();
+        assert_eq!(floor.fee(10_000, 25, "X"), 25);
+        assert_eq!(floor.fee(399, 25, "X"), 0);
+        assert_eq!(floor.fee(u64::MAX, 10_000, "X"), u64::MAX);
+
+        let half_even = SettlementPolicy {
+            rounding: Rounding::HalfEven,
+            units: BTreeMap::from([("X".to_string(), 5)]),
+        };
+        // 12.5 units of 5 go to 12, 13.5 to 14
+        assert_eq!(half_even.fee(25_000, 25, "X"), 60);
+        assert_eq!(half_even.fee(27_000, 25, "X"), 70);
+        assert_eq!(half_even.fee(200, 25, "Y"), 0);
+        assert_eq!(half_even.fee(600, 25, "Y"), 2);
+        let ceil = SettlementPolicy {
+            rounding: Rounding::Ceil,
+            ..SettlementPolicy::default()
+        };
+        assert_eq!(ceil.fee(1, 25, "X"), 1);
+    }
+
+    #[test]
+    fn test_SettlementPolicy_allocate_adds_up_to_the_total() {
+        let policy = SettlementPolicy {
+            rounding: Rounding::Floor,
+            units: BTreeMap::from([("X".to_string(), 5)]),
+        };
+        assert_eq!(policy.allocate(10, &[1, 1, 1], "Y"), vec![4, 3, 3]);
+        assert_eq!(policy.allocate(10, &[1, 2, 7], "Y"), vec![1, 2, 7]);
+        assert_eq!(policy.allocate(20, &[1, 1, 1], "X"), vec![10, 5, 5]);
+        assert_eq!(policy.allocate(22, &[1, 3], "X"), vec![5, 17]);
+        assert_eq!(policy.allocate(7, &[0, 0], "Y"), vec![0, 0]);
+    }
+}
@@ -0,0 +1,178 @@
+//! Parking a tenant's open state on shutdown, so the next start can check that nothing was lost and restore the books.
+use octopus_common::{
+    errors::ApplicationError,
+    types::{Hold, OrderBookSnapshot, PartialOrder},
+};
+use serde::{Deserialize, Serialize};
+use std::{collections::BTreeMap, fs, path::Path};
+
+use crate::counters::write_atomically;
+
+/// The file in a tenant's data directory holding its [`ParkedState`]
+pub const SHUTDOWN_FILE: &str = "shutdown.json";
+
+/// Recorded last on shutdown, summarizing what was parked
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ShutdownMarker {
+    /// Unix timestamp in milliseconds
+    pub shut_down_at: u64,
+    /// The last ordinal issued before the shutdown
+    pub ordinal: u64,
+    pub resting_orders: usize,
+    pub holds: usize,
+}
+
+/// The resting orders and open holds of a tenant when the server shut down
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ParkedState {
+    pub orders: Vec<PartialOrder>,
+    pub holds: Vec<Hold>,
+    pub marker: ShutdownMarker,
+    /// The books by market symbol, restored on the next start
+    #[serde(default)]
+    pub books: BTreeMap<String, OrderBookSnapshot>,
+}
+
+impl ParkedState {
+    /// Reads the state parked by the previous run, if it shut down in order
+    /// # Errors
+    /// The file can't be read or parsed
+    pub fn load(path: &Path) -> Result<Option<Self>, ApplicationError> {
+        let failed =
+            |e: String| ApplicationError::StorageFailed(format!("{}: {}", path.display(), e));
+        match fs::read(path) {
+            Ok(bytes) => serde_json::from_slice(&bytes)
+                .map(Some)
+                .map_err(|e| failed(e.to_string())),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(failed(e.to_string())),
+        }
+    }
+
+    /// # Errors
+    /// Writing the file failed
+    pub fn write(&self, path: &Path) -> Result<(), ApplicationError> {
+        serde_json::to_vec_pretty(self)
+            .map_err(std::io::Error::from)
+            .and_then(|contents| write_atomically(path, &contents))
+            .map_err(|e| ApplicationError::StorageFailed(format!("{}: {}", path.display(), e)))
+    }
+
+    /// Removes the parked state once the books are restored, so a crash later doesn't bring back orders that have
+    /// changed since
+    /// # Errors
+    /// The file exists but can't be removed
+    pub fn discard(path: &Path) -> Result<(), ApplicationError> {
+        match fs::remove_file(path) {
+            Err(e) if e.kind() != std::io::ErrorKind::NotFound => Err(
+                ApplicationError::StorageFailed(format!("{}: {}", path.display(), e)),
+            ),
+            _ => Ok(()),
+        }
+    }
+
+    /// Checks the parked orders and holds against the marker, and that the identifiers resumed after the restart
+    /// (`resumed_ordinal`) don't reuse any of them
+    /// # Errors
+    /// Something was lost or would be issued twice
+    pub fn verify(&self, resumed_ordinal: u64) -> Result<(), ApplicationError> {
+        let marker = &self.marker;
+        let mismatch = |what: &str, parked: usize, recorded: usize| {
+            ApplicationError::StorageFailed(format!(
+                "{} {} parked, but the shutdown marker records {}",
+                parked, what, recorded
+            ))
+        };
+        if self.orders.len() != marker.resting_orders {
+            return Err(mismatch(
+                "resting orders",
+                self.orders.len(),
+                marker.resting_orders,
+            ));
+        }
+        if self.holds.len() != marker.holds {
+            return Err(mismatch("holds", self.holds.len(), marker.holds));
+        }
+        if let Some(order) = self.orders.iter().find(|o| o.ordinal > marker.ordinal) {
+            return Err(ApplicationError::StorageFailed(format!(
+                "parked order {} is newer than the shutdown marker's ordinal {}",
+                order.ordinal, marker.ordinal
+            )));
+        }
+        if resumed_ordinal < marker.ordinal {
+            return Err(ApplicationError::StorageFailed(format!(
+                "ordinals resume at {}, before the shutdown marker's ordinal {}",
+                resumed_ordinal, marker.ordinal
+            )));
+        }
+        Ok(())
+    }
+}
+
+/// Resolves on Ctrl-C, or on SIGTERM on Unix
+pub async fn signalled() {
+    #[cfg(unix)]
+    {
+        use tokio::signal::unix::{signal, SignalKind};
+
+        match signal(SignalKind::terminate()) {
+            Ok(mut terminate) => {
+                tokio::select! {
+                    _ = terminate.recv() => {}
+                    _ = tokio::signal::ctrl_c() => {}
+                }
+                return;
+            }
+            Err(e) => log::error!("Can't listen for SIGTERM, stop with Ctrl-C: {}", e),
+        }
+    }
+    if let Err(e) = tokio::signal::ctrl_c().await {
+        log::error!("Can't listen for Ctrl-C: {}", e);
+        std::future::pending::<()>().await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    // reduce the warnings for naming tests
+    #![allow(non_snake_case)]
+
+    use super::*;
+    use octopus_common::types::{Side, DEFAULT_MARKET};
+
+    #[test]
+    fn test_ParkedState_verify_detects_lost_orders() {
+        let parked = ParkedState {
+            orders: vec![PartialOrder {
+                price: 10,
+                amount: 2,
+                remaining: 2,
+                side: Side::Sell,
+                signer: "ALICE".to_string(),
+                ordinal: 3,
+                order_id: 3,
+                expires_at: None,
+                market: DEFAULT_MARKET.to_string(),
+            }],
+            holds: vec![],
+            marker: ShutdownMarker {
+                shut_down_at: 1,
+                ordinal: 3,
+                resting_orders: 1,
+                holds: 0,
+            },
+            books: BTreeMap::new(),
+        };
+        assert_eq!(parked.verify(1003), Ok(()));
+        assert!(parked.verify(2).is_err());
+
+        let lost = ParkedState {
+            orders: vec![],
+            ..parked
+        };
+        assert!(matches!(
+            lost.verify(1003),
+            Err(ApplicationError::StorageFailed(_))
+        ));
+    }
+}
@@ -0,0 +1,337 @@
+//! Public keys of accounts and the verification of signed orders.
+//!
+//! Once an account registers a public key, only orders signed with one of its keys are accepted for it, so knowing an
+//! account's name isn't enough to trade for it anymore. Accounts without keys keep trading unsigned. A signature
+//! covers the tenant, expires, and its nonce is accepted once, so a signed order can't be replayed to another tenant,
+//! later, or twice.
+use ed25519_dalek::{Signature, VerifyingKey};
+use octopus_common::{
+    errors::ApplicationError,
+    types::{
+        AmendRequest, Order, OrderId, OrderSignature, RegisteredPublicKey,
+        MAX_SIGNATURE_VALIDITY_MILLIS,
+    },
+};
+use std::collections::{BTreeMap, HashMap};
+
+/// Decodes a hex string of exactly `N` bytes
+fn decode_hex<const N: usize>(value: &str) -> Option<[u8; N]> {
+    hex::decode(value).ok()?.try_into().ok()
+}
+
+/// The registered public keys of all accounts, and the nonces of their signed orders that didn't expire yet
+#[derive(Debug, Default)]
+pub struct SigningKeys {
+    keys: HashMap<String, Vec<(VerifyingKey, RegisteredPublicKey)>>,
+    /// By signer, the expiry of each nonce
+    nonces: HashMap<String, BTreeMap<u64, u64>>,
+}
+
+impl SigningKeys {
+    pub fn new() -> Self {
+        SigningKeys::default()
+    }
+
+    /// Adds a public key of `signer`. Registering a key twice is fine.
+    /// # Errors
+    /// The key isn't a hex-encoded ed25519 public key
+    pub fn register(
+        &mut self,
+        signer: &str,
+        public_key: &str,
+        now: u64,
+    ) -> Result<RegisteredPublicKey, ApplicationError> {
+        let public_key = public_key.to_ascii_lowercase();
+        let key = decode_hex(&public_key)
+            .and_then(|bytes| VerifyingKey::from_bytes(&bytes).ok())
+            .filter(|key| !key.is_weak())
+            .ok_or(ApplicationError::InvalidPublicKey(public_key.clone()))?;
+        let keys = self.keys.entry(signer.to_string()).or_default();
+        if let Some((_, registered)) = keys.iter().find(|(known, _)| *known == key) {
+            return Ok(registered.clone());
+        }
+        let registered = RegisteredPublicKey {
+            public_key,
+            registered_at: now,
+        };
+        keys.push((key, registered.clone()));
+        Ok(registered)
+    }
+
+    /// The public keys of `signer`, oldest first
+    pub fn list(&self, signer: &str) -> Vec<RegisteredPublicKey> {
+        self.keys
+            .get(signer)
+            .map(|keys| {
+                keys.iter()
+                    .map(|(_, registered)| registered.clone())
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+
+    /// Checks the signature of `order` sent to `tenant` against the signer's public keys, and uses up its nonce.
+    /// Returns the key that signed it, `None` if the signer has no keys.
+    /// # Errors
+    /// The signer has keys, but the order isn't signed, the signature expired, is valid for longer than
+    /// [`MAX_SIGNATURE_VALIDITY_MILLIS`], doesn't match any of the keys, or its nonce was used before
+    pub fn verify(
+        &mut self,
+        order: &Order,
+        tenant: &str,
+        now: u64,
+    ) -> Result<Option<RegisteredPublicKey>, ApplicationError> {
+        self.check(
+            &order.signer,
+            order.signature.as_ref(),
+            now,
+            |nonce, expires_at| order.signing_payload(tenant, nonce, expires_at),
+        )
+    }
+
+    /// Checks the signature of amending the order `id` like [`SigningKeys::verify`]
+    /// # Errors
+    /// See [`SigningKeys::verify`]
+    pub fn verify_amend(
+        &mut self,
+        id: OrderId,
+        request: &AmendRequest,
+        tenant: &str,
+        now: u64,
+    ) -> Result<Option<RegisteredPublicKey>, ApplicationError> {
+        self.check(
+            &request.signer,
+            request.signature.as_ref(),
+            now,
+            |nonce, expires_at| request.signing_payload(id, tenant, nonce, expires_at),
+        )
+    }
+
+    /// Checks `signature` against the `payload` of its nonce and expiry, and uses up the nonce
+    fn check(
+        &mut self,
+        signer: &str,
+        signature: Option<&OrderSignature>,
+        now: u64,
+        payload: impl FnOnce(u64, u64) -> Vec<u8>,
+    ) -> Result<Option<RegisteredPublicKey>, ApplicationError> {
+        let Some(keys) = self.keys.get(signer).filter(|keys| !keys.is_empty()) else {
+            return Ok(None);
+        };
+        let signed = signature.ok_or(ApplicationError::SignatureRequired(signer.to_string()))?;
+        if signed.expires_at <= now
+            || signed.expires_at > now.saturating_add(MAX_SIGNATURE_VALIDITY_MILLIS)
+        {
+            return Err(ApplicationError::InvalidSignatureExpiry(signed.expires_at));
+        }
+        let signature = decode_hex(&signed.value)
+            .map(|bytes| Signature::from_bytes(&bytes))
+            .ok_or(ApplicationError::InvalidSignature(signer.to_string()))?;
+        let payload = payload(signed.nonce, signed.expires_at);
+        let registered = keys
+            .iter()
+            .find(|(key, _)| key.verify_strict(&payload, &signature).is_ok())
+            .map(|(_, registered)| registered.clone())
+            .ok_or(ApplicationError::InvalidSignature(signer.to_string()))?;
+
+        let nonces = self.nonces.entry(signer.to_string()).or_default();
+        nonces.retain(|_, expires_at| *expires_at > now);
+        if nonces.contains_key(&signed.nonce) {
+            return Err(ApplicationError::NonceReused(
+                signer.to_string(),
+                signed.nonce,
+            ));
+        }
+        nonces.insert(signed.nonce, signed.expires_at);
+        Ok(Some(registered))
+    }
+
+    /// Forgets the keys of `signer`
+    pub fn remove(&mut self, signer: &str) {
+        self.keys.remove(signer);
+        self.nonces.remove(signer);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    // reduce the warnings for naming tests
+    #![allow(non_snake_case)]
+
+    use super::*;
+    use ed25519_dalek::{Signer, SigningKey};
+    use octopus_common::types::{OrderType, Side, TimeInForce, DEFAULT_MARKET};
+
+    fn order(signature: Option<OrderSignature>) -> Order {
+        Order {
+            price: 10,
+            amount: 2,
+            side: Side::Buy,
+            signer: "ALICE".to_string(),
+            client_order_id: Some("c1".to_string()),
+            signature,
+            order_type: OrderType::Limit,
+            time_in_force: TimeInForce::Gtc,
+            trigger_price: None,
+            market: DEFAULT_MARKET.to_string(),
+        }
+    }
+
+    fn signed(key: &SigningKey, tenant: &str, nonce: u64, expires_at: u64) -> Order {
+        let signature = key.sign(&order(None).signing_payload(tenant, nonce, expires_at));
+        order(Some(OrderSignature {
+            nonce,
+            expires_at,
+            value: hex::encode(signature.to_bytes()),
+        }))
+    }
+
+    #[test]
+    fn test_SigningKeys_verify_requires_a_registered_key_signature() {
+        let signing_key = SigningKey::from_bytes(&[7; 32]);
+        let other_key = SigningKey::from_bytes(&[8; 32]);
+        let mut keys = SigningKeys::new();
+        assert_eq!(keys.verify(&order(None), "default", 0), Ok(None));
+
+        let public_key = hex::encode(signing_key.verifying_key().as_bytes());
+        keys.register("ALICE", &public_key, 5).unwrap();
+        assert_eq!(
+            keys.verify(&order(None), "default", 0),
+            Err(ApplicationError::SignatureRequired("ALICE".to_string()))
+        );
+        assert_eq!(
+            keys.verify(&signed(&signing_key, "default", 1, 100), "default", 0)
+                .unwrap()
+                .map(|k| k.public_key),
+            Some(public_key)
+        );
+        assert_eq!(
+            keys.verify(&signed(&other_key, "default", 2, 100), "default", 0),
+            Err(ApplicationError::InvalidSignature("ALICE".to_string()))
+        );
+        let mut tampered = signed(&signing_key, "default", 3, 100);
+        tampered.amount = 20;
+        assert!(keys.verify(&tampered, "default", 0).is_err());
+    }
+
+    #[test]
+    fn test_SigningKeys_verify_keeps_fields_apart() {
+        let signing_key = SigningKey::from_bytes(&[7; 32]);
+        let mut keys = SigningKeys::new();
+        keys.register(
+            "ALICE",
+            &hex::encode(signing_key.verifying_key().as_bytes()),
+            0,
+        )
+        .unwrap();
+        // A client order id with a line break can't pass for the fields of a market order
+        let mut limit = order(None);
+        limit.client_order_id = Some("c1\nmarket".to_string());
+        let signature = signing_key.sign(&limit.signing_payload("default", 1, 100));
+        let mut market = limit.clone();
+        market.client_order_id = Some("c1".to_string());
+        market.order_type = OrderType::Market;
+        market.signature = Some(OrderSignature {
+            nonce: 1,
+            expires_at: 100,
+            value: hex::encode(signature.to_bytes()),
+        });
+        assert_eq!(
+            keys.verify(&market, "default", 0),
+            Err(ApplicationError::InvalidSignature("ALICE".to_string()))
+        );
+    }
+
+    #[test]
+    fn test_SigningKeys_verify_rejects_replays() {
+        let signing_key = SigningKey::from_bytes(&[7; 32]);
+        let mut keys = SigningKeys::new();
+        keys.register(
+            "ALICE",
+            &hex::encode(signing_key.verifying_key().as_bytes()),
+            0,
+        )
+        .unwrap();
+        let order = signed(&signing_key, "default", 1, 100);
+        assert!(keys.verify(&order, "default", 10).is_ok());
+        assert_eq!(
+            keys.verify(&order, "default", 20),
+            Err(ApplicationError::NonceReused("ALICE".to_string(), 1))
+        );
+        // Signed for another tenant
+        assert_eq!(
+            keys.verify(&signed(&signing_key, "squid", 2, 100), "default", 10),
+            Err(ApplicationError::InvalidSignature("ALICE".to_string()))
+        );
+        // Expired, or valid for too long
+        assert_eq!(
+            keys.verify(&order, "default", 100),
+            Err(ApplicationError::InvalidSignatureExpiry(100))
+        );
+        let forever = signed(
+            &signing_key,
+            "default",
+            3,
+            MAX_SIGNATURE_VALIDITY_MILLIS + 11,
+        );
+        assert_eq!(
+            keys.verify(&forever, "default", 10),
+            Err(ApplicationError::InvalidSignatureExpiry(
+                MAX_SIGNATURE_VALIDITY_MILLIS + 11
+            ))
+        );
+        // Nonces are forgotten once their order expired
+        assert!(keys
+            .verify(&signed(&signing_key, "default", 1, 200), "default", 100)
+            .is_ok());
+    }
+
+    #[test]
+    fn test_SigningKeys_verify_amend_covers_the_order_id() {
+        let signing_key = SigningKey::from_bytes(&[7; 32]);
+        let mut keys = SigningKeys::new();
+        let mut request = AmendRequest {
+            signer: "ALICE".to_string(),
+            price: 11,
+            amount: 2,
+            signature: None,
+        };
+        assert_eq!(keys.verify_amend(4, &request, "default", 0), Ok(None));
+        keys.register(
+            "ALICE",
+            &hex::encode(signing_key.verifying_key().as_bytes()),
+            0,
+        )
+        .unwrap();
+        assert_eq!(
+            keys.verify_amend(4, &request, "default", 0),
+            Err(ApplicationError::SignatureRequired("ALICE".to_string()))
+        );
+        let signature = signing_key.sign(&request.signing_payload(4, "default", 1, 100));
+        request.signature = Some(OrderSignature {
+            nonce: 1,
+            expires_at: 100,
+            value: hex::encode(signature.to_bytes()),
+        });
+        assert_eq!(
+            keys.verify_amend(5, &request, "default", 0),
+            Err(ApplicationError::InvalidSignature("ALICE".to_string()))
+        );
+        assert!(keys.verify_amend(4, &request, "default", 0).is_ok());
+    }
+
+    #[test]
+    fn test_SigningKeys_register_rejects_malformed_keys() {
+        let mut keys = SigningKeys::new();
+        assert!(matches!(
+            keys.register("ALICE", "abcd", 0),
+            Err(ApplicationError::InvalidPublicKey(_))
+        ));
+        let public_key = hex::encode(SigningKey::from_bytes(&[7; 32]).verifying_key().as_bytes());
+        keys.register("ALICE", &public_key, 1).unwrap();
+        keys.register("ALICE", &public_key.to_uppercase(), 2)
+            .unwrap();
+        assert_eq!(keys.list("ALICE").len(), 1);
+        assert_eq!(keys.list("ALICE")[0].registered_at, 1);
+    }
+}
@@ -0,0 +1,149 @@
+//! The lifecycle phases of the server. The API is served from the start so `GET /status` can report the progress, but
+//! orders are only accepted once the state is fully restored, and no changes at all once shutdown has begun.
+use octopus_common::errors::{ApplicationError, OctopusError};
+use serde::Serialize;
+use std::{fmt, sync::Arc, sync::RwLock};
+use warp::{http::Method, Filter};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(tag = "phase", rename_all = "snake_case")]
+pub enum StartupPhase {
+    /// Reading the persisted state
+    LoadingSnapshot,
+    /// Applying the restored events, `applied` of `total` are done
+    Replaying { applied: usize, total: usize },
+    /// Accepting orders
+    Serving,
+    /// Parking the open state before the process exits
+    ShuttingDown,
+}
+
+impl fmt::Display for StartupPhase {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            StartupPhase::LoadingSnapshot => write!(f, "loading snapshot"),
+            StartupPhase::Replaying { applied, total } => {
+                write!(f, "replaying events {}/{}", applied, total)
+            }
+            StartupPhase::Serving => write!(f, "serving"),
+            StartupPhase::ShuttingDown => write!(f, "shutting down"),
+        }
+    }
+}
+
+/// The current [`StartupPhase`], shared between the recovery and the routes
+#[derive(Debug)]
+pub struct Startup {
+    phase: RwLock<StartupPhase>,
+}
+
+impl Default for Startup {
+    fn default() -> Self {
+        Startup {
+            phase: RwLock::new(StartupPhase::LoadingSnapshot),
+        }
+    }
+}
+
+impl Startup {
+    pub fn phase(&self) -> StartupPhase {
+        *self.phase.read().unwrap()
+    }
+
+    pub fn advance(&self, phase: StartupPhase) {
+        *self.phase.write().unwrap() = phase;
+    }
+
+    /// Ok while the server is serving
+    /// # Errors
+    /// Still starting up, the error names the current phase, or shutting down for maintenance
+    pub fn ensure_serving(&self) -> Result<(), ApplicationError> {
+        match self.phase() {
+            StartupPhase::Serving => Ok(()),
+            StartupPhase::ShuttingDown => Err(ApplicationError::Maintenance),
+            phase => Err(ApplicationError::StartingUp(phase.to_string())),
+        }
+    }
+}
+
+/// Rejects requests until the server is serving
+pub fn when_serving(
+    startup: Arc<Startup>,
+) -> impl Filter<Extract = (), Error = warp::Rejection> + Clone {
+    warp::any()
+        .and_then(move || {
+            let result = startup
+                .ensure_serving()
+                .map_err(|e| warp::reject::custom(OctopusError(e)));
+            async move { result }
+        })
+        .untuple_one()
+}
+
+/// Rejects every request but reads once shutdown has begun
+pub fn unless_shutting_down(
+    startup: Arc<Startup>,
+) -> impl Filter<Extract = (), Error = warp::Rejection> + Clone {
+    warp::method()
+        .and_then(move |method: Method| {
+            let read = method == Method::GET || method == Method::HEAD || method == Method::OPTIONS;
+            let result = match startup.phase() {
+                StartupPhase::ShuttingDown if !read => Err(warp::reject::custom(OctopusError(
+                    ApplicationError::Maintenance,
+                ))),
+                _ => Ok(()),
+            };
+            async move { result }
+        })
+        .untuple_one()
+}
+
+#[cfg(test)]
+mod tests {
+    // reduce the warnings for naming tests
+    #![allow(non_snake_case)]
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_when_serving_rejects_until_replay_completes() {
+        let startup = Arc::new(Startup::default());
+        let filter = when_serving(startup.clone()).map(|| "accepted");
+
+        startup.advance(StartupPhase::Replaying {
+            applied: 3,
+            total: 10,
+        });
+        let rejection = warp::test::request().filter(&filter).await.unwrap_err();
+        assert!(matches!(
+            rejection.find(),
+            Some(OctopusError(ApplicationError::StartingUp(progress))) if progress == "replaying events 3/10"
+        ));
+        assert_eq!(
+            serde_json::to_value(startup.phase()).unwrap(),
+            serde_json::json!({"phase": "replaying", "applied": 3, "total": 10})
+        );
+
+        startup.advance(StartupPhase::Serving);
+        assert!(warp::test::request().filter(&filter).await.is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_unless_shutting_down_only_allows_reads() {
+        let startup = Arc::new(Startup::default());
+        startup.advance(StartupPhase::ShuttingDown);
+        let filter = unless_shutting_down(startup.clone()).map(|| "accepted");
+
+        assert!(warp::test::request().filter(&filter).await.is_ok());
+        let rejection = warp::test::request()
+            .method("POST")
+            .filter(&filter)
+            .await
+            .unwrap_err();
+        assert!(matches!(
+            rejection.find(),
+            Some(OctopusError(ApplicationError::Maintenance))
+        ));
+        assert_eq!(startup.ensure_serving(), Err(ApplicationError::Maintenance));
+    }
+}
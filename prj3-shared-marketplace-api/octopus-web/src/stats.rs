@@ -0,0 +1,180 @@
+use octopus_common::types::{anonymize, AccountStats, LeaderboardSort, Side};
+use std::{
+    cmp::Reverse,
+    collections::{BTreeSet, HashMap},
+};
+
+/// One side of a match as seen by one of the accounts
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Fill {
+    /// Unix timestamp (ms)
+    pub timestamp: u64,
+    pub account: String,
+    pub side: Side,
+    pub amount: u64,
+    pub price: u64,
+    /// Profit or loss realized by the fill
+    pub realized: i64,
+}
+
+/// The fills of all accounts, the basis for trading statistics and the leaderboard
+#[derive(Debug, Default)]
+pub struct TradeStats {
+    fills: Vec<Fill>,
+    /// Accounts that opted in to the leaderboard
+    leaderboard: BTreeSet<String>,
+}
+
+impl TradeStats {
+    pub fn new() -> Self {
+        TradeStats::default()
+    }
+
+    pub fn record(&mut self, fill: Fill) {
+        self.fills.push(fill);
+    }
+
+    /// Adds or removes `signer` from the leaderboard
+    pub fn set_leaderboard_opt_in(&mut self, signer: &str, opt_in: bool) {
+        if opt_in {
+            self.leaderboard.insert(signer.to_string());
+        } else {
+            self.leaderboard.remove(signer);
+        }
+    }
+
+    /// Whether `signer` is on the leaderboard
+    pub fn is_opted_in(&self, signer: &str) -> bool {
+        self.leaderboard.contains(signer)
+    }
+
+    /// Replaces `signer` with `token` in the fills and takes it off the leaderboard
+    pub fn anonymize(&mut self, signer: &str, token: &str) {
+        for fill in self.fills.iter_mut() {
+            anonymize(&mut fill.account, signer, token);
+        }
+        self.leaderboard.remove(signer);
+    }
+
+    fn stats(&self, since: u64, accounts: impl Fn(&str) -> bool) -> HashMap<&str, AccountStats> {
+        let mut stats: HashMap<&str, (AccountStats, u64, u64)> = HashMap::new();
+        for fill in self
+            .fills
+            .iter()
+            .filter(|f| f.timestamp >= since && accounts(&f.account))
+        {
+            let (entry, wins, closes) = stats
+                .entry(&fill.account)
+                .or_insert_with(|| (AccountStats::new(&fill.account), 0, 0));
+            entry.volume += fill.amount * fill.price;
+            entry.trade_count += 1;
+            entry.pnl += fill.realized;
+            if fill.realized != 0 {
+                *closes += 1;
+                if fill.realized > 0 {
+                    *wins += 1;
+                }
+            }
+        }
+        stats
+            .into_iter()
+            .map(|(account, (mut entry, wins, closes))| {
+                if closes > 0 {
+                    entry.win_rate = wins as f64 / closes as f64;
+                }
+                (account, entry)
+            })
+            .collect()
+    }
+
+    /// The statistics of `signer` for fills at or after `since`
+    pub fn stats_of(&self, signer: &str, since: u64) -> AccountStats {
+        self.stats(since, |account| account == signer)
+            .remove(signer)
+            .unwrap_or_else(|| AccountStats::new(signer))
+    }
+
+    /// The statistics of the accounts that opted in, best first
+    pub fn leaderboard(&self, since: u64, sort_by: LeaderboardSort) -> Vec<AccountStats> {
+        let stats = self.stats(since, |account| self.leaderboard.contains(account));
+        let mut ranking: Vec<AccountStats> = self
+            .leaderboard
+            .iter()
+            .map(|account| {
+                stats
+                    .get(account.as_str())
+                    .cloned()
+                    .unwrap_or_else(|| AccountStats::new(account))
+            })
+            .collect();
+        // The sort is stable, so ties stay in alphabetical order
+        match sort_by {
+            LeaderboardSort::Pnl => ranking.sort_by_key(|s| Reverse(s.pnl)),
+            LeaderboardSort::Volume => ranking.sort_by_key(|s| Reverse(s.volume)),
+            LeaderboardSort::WinRate => ranking.sort_by(|a, b| b.win_rate.total_cmp(&a.win_rate)),
+        }
+        ranking
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    // reduce the warnings for naming tests
+    #![allow(non_snake_case)]
+
+    use super::*;
+
+    fn fill(timestamp: u64, account: &str, realized: i64) -> Fill {
+        Fill {
+            timestamp,
+            account: account.to_string(),
+            side: Side::Sell,
+            amount: 2,
+            price: 10,
+            realized,
+        }
+    }
+
+    #[test]
+    fn test_TradeStats_stats_of_counts_window() {
+        let mut trade_stats = TradeStats::new();
+        trade_stats.record(fill(1_000, "ALICE", 0));
+        trade_stats.record(fill(2_000, "ALICE", 5));
+        trade_stats.record(fill(3_000, "ALICE", -2));
+        trade_stats.record(fill(3_000, "BOB", 7));
+
+        assert_eq!(
+            trade_stats.stats_of("ALICE", 0),
+            AccountStats {
+                account: "ALICE".to_string(),
+                volume: 60,
+                trade_count: 3,
+                win_rate: 0.5,
+                pnl: 3,
+            }
+        );
+        assert_eq!(trade_stats.stats_of("ALICE", 3_000).pnl, -2);
+        assert_eq!(trade_stats.stats_of("CAROL", 0).trade_count, 0);
+    }
+
+    #[test]
+    fn test_TradeStats_leaderboard_lists_opted_in_accounts() {
+        let mut trade_stats = TradeStats::new();
+        trade_stats.record(fill(1_000, "ALICE", 5));
+        trade_stats.record(fill(1_000, "BOB", 7));
+        trade_stats.record(fill(1_000, "CAROL", 9));
+        trade_stats.set_leaderboard_opt_in("ALICE", true);
+        trade_stats.set_leaderboard_opt_in("BOB", true);
+        trade_stats.set_leaderboard_opt_in("DAVE", true);
+
+        let ranking: Vec<_> = trade_stats
+            .leaderboard(0, LeaderboardSort::Pnl)
+            .into_iter()
+            .map(|s| s.account)
+            .collect();
+        assert_eq!(ranking, vec!["BOB", "ALICE", "DAVE"]);
+
+        trade_stats.set_leaderboard_opt_in("BOB", false);
+        assert_eq!(trade_stats.leaderboard(0, LeaderboardSort::Volume).len(), 2);
+    }
+}
@@ -0,0 +1,117 @@
+//! Tags an account puts on its transactions after the fact, like `rent` or `tax:2026`, to organize them for its
+//! bookkeeping. The transaction log itself never changes, the tags are kept next to it by account and transaction id,
+//! so each party of a transfer tags it on its own.
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+
+use octopus_common::errors::ApplicationError;
+
+/// The most tags on one transaction
+pub const MAX_TAGS: usize = 8;
+
+/// The longest tag in characters
+pub const MAX_TAG_LENGTH: usize = 32;
+
+/// The tags of every account by transaction id
+#[derive(Debug, Default)]
+pub struct TxTags {
+    tags: HashMap<String, BTreeMap<u64, BTreeSet<String>>>,
+}
+
+impl TxTags {
+    pub fn new() -> Self {
+        TxTags::default()
+    }
+
+    /// The tags trimmed and in lowercase, without duplicates
+    /// # Errors
+    /// A tag is empty, too long, or has other characters than letters, digits, and `-_:/.`, or there are more than
+    /// [`MAX_TAGS`]
+    pub fn normalize(tags: &[String]) -> Result<BTreeSet<String>, ApplicationError> {
+        let mut normalized = BTreeSet::new();
+        for tag in tags {
+            let tag = tag.trim().to_lowercase();
+            let valid = tag
+                .chars()
+                .all(|c| c.is_alphanumeric() || "-_:/.".contains(c));
+            if tag.is_empty() || tag.chars().count() > MAX_TAG_LENGTH || !valid {
+                return Err(ApplicationError::InvalidTag(format!(
+                    "tags have 1 to {} letters, digits, and -_:/. but got {:?}",
+                    MAX_TAG_LENGTH, tag
+                )));
+            }
+            normalized.insert(tag);
+        }
+        if normalized.len() > MAX_TAGS {
+            return Err(ApplicationError::InvalidTag(format!(
+                "more than {} tags",
+                MAX_TAGS
+            )));
+        }
+        Ok(normalized)
+    }
+
+    /// Replaces the tags `signer` put on transaction `id`
+    pub fn set(&mut self, signer: &str, id: u64, tags: BTreeSet<String>) {
+        let own = self.tags.entry(signer.to_string()).or_default();
+        match tags.is_empty() {
+            true => own.remove(&id),
+            false => own.insert(id, tags),
+        };
+        if own.is_empty() {
+            self.tags.remove(signer);
+        }
+    }
+
+    /// The tags `signer` put on transaction `id`, in alphabetical order
+    pub fn of(&self, signer: &str, id: u64) -> Vec<String> {
+        self.tags
+            .get(signer)
+            .and_then(|own| own.get(&id))
+            .map(|tags| tags.iter().cloned().collect())
+            .unwrap_or_default()
+    }
+
+    /// Forgets the tags of a deleted account, they're its own notes
+    pub fn remove(&mut self, signer: &str) {
+        self.tags.remove(signer);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    // reduce the warnings for naming tests
+    #![allow(non_snake_case)]
+
+    use super::*;
+
+    #[test]
+    fn test_TxTags_set_replaces_the_normalized_tags() {
+        let tags = |tags: &[&str]| {
+            TxTags::normalize(&tags.iter().map(|t| t.to_string()).collect::<Vec<_>>())
+        };
+        let mut tx_tags = TxTags::new();
+        tx_tags.set("ALICE", 3, tags(&[" Rent ", "tax:2026", "rent"]).unwrap());
+        assert_eq!(tx_tags.of("ALICE", 3), vec!["rent", "tax:2026"]);
+        assert!(tx_tags.of("BOB", 3).is_empty());
+
+        tx_tags.set("ALICE", 3, tags(&["food"]).unwrap());
+        assert_eq!(tx_tags.of("ALICE", 3), vec!["food"]);
+        tx_tags.set("ALICE", 3, BTreeSet::new());
+        assert!(tx_tags.tags.is_empty());
+
+        assert!(matches!(tags(&[""]), Err(ApplicationError::InvalidTag(_))));
+        assert!(matches!(
+            tags(&["two words"]),
+            Err(ApplicationError::InvalidTag(_))
+        ));
+        assert!(matches!(
+            tags(&[&"x".repeat(MAX_TAG_LENGTH + 1)]),
+            Err(ApplicationError::InvalidTag(_))
+        ));
+        let many: Vec<String> = (0..=MAX_TAGS).map(|i| format!("t{}", i)).collect();
+        assert!(matches!(
+            TxTags::normalize(&many),
+            Err(ApplicationError::InvalidTag(_))
+        ));
+    }
+}
@@ -0,0 +1,586 @@
+use octopus_common::{
+    errors::{ApplicationError, OctopusError},
+    types::SelfMatchPolicy,
+};
+use std::{
+    collections::HashMap,
+    path::PathBuf,
+    sync::{Arc, Mutex, RwLock},
+    thread,
+    time::Duration,
+};
+use warp::Filter;
+
+use crate::audit::{AuditLog, Auditor};
+use crate::cold::DEFAULT_COLD_WITHDRAWAL_DELAY_SECS;
+use crate::config::IdStrategy;
+use crate::counters::{CounterStore, COUNTERS_FILE};
+use crate::duplicates::{RecentOrders, DEFAULT_DUPLICATE_WINDOW_SECS};
+use crate::ingest::{OrderQueue, DEFAULT_ORDER_QUEUE_CAPACITY};
+use crate::markets::MarketDefinition;
+use crate::reference::{ReferencePrices, DEFAULT_REFERENCE_WINDOW_SECS};
+use crate::reports::{Reports, REPORTS_DIR};
+use crate::settlement::SettlementPolicy;
+use crate::shutdown::{ParkedState, ShutdownMarker, SHUTDOWN_FILE};
+use crate::trading_platform::TradingPlatform;
+use crate::whitelist::DEFAULT_WHITELIST_DELAY_SECS;
+
+/// The header selecting the tenant of a request
+pub const TENANT_HEADER: &str = "x-tenant";
+
+/// The tenant serving requests without a [`TENANT_HEADER`]
+pub const DEFAULT_TENANT: &str = "default";
+
+/// Settings applied to every tenant's platform, see [`crate::config::Config::tenant_settings`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TenantSettings {
+    pub order_queue_capacity: usize,
+    pub withdrawal_approval_threshold: Option<u64>,
+    pub pin_threshold: u64,
+    /// How long withdrawals of cold accounts wait for their confirmation
+    pub cold_withdrawal_delay_secs: u64,
+    pub whitelist_threshold: u64,
+    /// How long new whitelist entries wait before they become active
+    pub whitelist_delay_secs: u64,
+    /// Balances below this are dust
+    pub dust_threshold: u64,
+    pub taker_fee_bps: u64,
+    pub maker_fee_bps: u64,
+    pub price_collar_bps: Option<u64>,
+    /// How long trades count towards the reference price
+    pub reference_window_secs: u64,
+    pub max_slippage_bps: Option<u64>,
+    pub circuit_breaker_bps: Option<u64>,
+    /// How long a market halts once its circuit breaker tripped
+    pub circuit_breaker_halt_secs: u64,
+    /// How long client order ids are remembered to reject duplicate orders
+    pub duplicate_window_secs: u64,
+    /// Per-account order rate and cancel ratio limits, see [`OrderThrottle`](crate::throttle::OrderThrottle)
+    pub max_orders_per_sec: Option<u64>,
+    pub max_cancel_ratio_percent: Option<u64>,
+    pub markets: Vec<MarketDefinition>,
+    /// Every tenant keeps its durable state in a subdirectory named after it. Without one, restarts reissue identifiers.
+    pub data_dir: Option<PathBuf>,
+    /// How every tenant issues its identifiers
+    pub ids: IdStrategy,
+    /// How the books of every tenant resolve self-matches
+    pub self_match_policy: SelfMatchPolicy,
+    /// How every tenant rounds its fees
+    pub settlement: SettlementPolicy,
+}
+
+impl Default for TenantSettings {
+    fn default() -> Self {
+        TenantSettings {
+            order_queue_capacity: DEFAULT_ORDER_QUEUE_CAPACITY,
+            withdrawal_approval_threshold: None,
+            pin_threshold: 0,
+            cold_withdrawal_delay_secs: DEFAULT_COLD_WITHDRAWAL_DELAY_SECS,
+            whitelist_threshold: 0,
+            whitelist_delay_secs: DEFAULT_WHITELIST_DELAY_SECS,
+            dust_threshold: 0,
+            taker_fee_bps: 0,
+            maker_fee_bps: 0,
+            price_collar_bps: None,
+            reference_window_secs: DEFAULT_REFERENCE_WINDOW_SECS,
+            max_slippage_bps: None,
+            circuit_breaker_bps: None,
+            circuit_breaker_halt_secs: 0,
+            duplicate_window_secs: DEFAULT_DUPLICATE_WINDOW_SECS,
+            max_orders_per_sec: None,
+            max_cancel_ratio_percent: None,
+            markets: vec![],
+            data_dir: None,
+            ids: IdStrategy::default(),
+            self_match_policy: SelfMatchPolicy::default(),
+            settlement: SettlementPolicy::default(),
+        }
+    }
+}
+
+impl TenantSettings {
+    /// Sets the fees and risk limits, the settings that may change while the platform runs
+    fn apply_limits(&self, platform: &mut TradingPlatform) {
+        platform.withdrawal_approval_threshold = self.withdrawal_approval_threshold;
+        platform.pin_threshold = self.pin_threshold;
+        platform.cold_accounts.delay_millis = self.cold_withdrawal_delay_secs * 1000;
+        platform.whitelist_threshold = self.whitelist_threshold;
+        platform.whitelists.delay_millis = self.whitelist_delay_secs * 1000;
+        platform.dust.threshold = self.dust_threshold;
+        platform.taker_fee_bps = self.taker_fee_bps;
+        platform.maker_fee_bps = self.maker_fee_bps;
+        platform.price_collar_bps = self.price_collar_bps;
+        platform.max_slippage_bps = self.max_slippage_bps;
+        platform.circuit_breaker.band_bps = self.circuit_breaker_bps;
+        platform.circuit_breaker.halt_millis = self.circuit_breaker_halt_secs * 1000;
+        platform.throttle.max_orders_per_sec = self.max_orders_per_sec;
+        platform.throttle.max_cancel_ratio_percent = self.max_cancel_ratio_percent;
+    }
+}
+
+/// A hosted [`TradingPlatform`] and the queue feeding its orders
+struct Tenant {
+    platform: Arc<Mutex<TradingPlatform>>,
+    orders: Arc<OrderQueue>,
+}
+
+/// Isolated [`TradingPlatform`] instances hosted by one process. Each tenant has its own accounts, keys, and order book.
+pub struct Tenants {
+    tenants: RwLock<HashMap<String, Tenant>>,
+    settings: RwLock<TenantSettings>,
+    /// Shared by all tenants, each entry names its tenant
+    audit_log: Arc<AuditLog>,
+}
+
+impl Tenants {
+    /// Creates the [`DEFAULT_TENANT`] and the additional tenants in `names`
+    pub fn new(names: &[&str], settings: TenantSettings) -> Result<Self, ApplicationError> {
+        let tenants = Tenants {
+            tenants: RwLock::new(HashMap::new()),
+            settings: RwLock::new(settings),
+            audit_log: Arc::default(),
+        };
+        tenants.create(DEFAULT_TENANT)?;
+        for name in names {
+            if *name != DEFAULT_TENANT {
+                tenants.create(name)?;
+            }
+        }
+        Ok(tenants)
+    }
+
+    /// Adds an empty tenant. Names are used as namespaces, so only ASCII letters, digits, `-` and `_` are allowed.
+    /// A tenant that existed before a restart continues its identifiers where they left off.
+    /// # Errors
+    /// The name is invalid or taken, the id strategy is invalid, the tenant's counters can't be read, or the state it parked on shutdown doesn't
+    /// match its shutdown marker
+    pub fn create(&self, name: &str) -> Result<(), ApplicationError> {
+        let valid = !name.is_empty()
+            && name
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_');
+        if !valid {
+            return Err(ApplicationError::InvalidTenantName(name.to_string()));
+        }
+        let mut tenants = self.tenants.write().unwrap();
+        if tenants.contains_key(name) {
+            return Err(ApplicationError::TenantAlreadyExists(name.to_string()));
+        }
+        let settings = self.settings.read().unwrap();
+        let mut platform = TradingPlatform::with_ids(settings.ids.generator()?);
+        platform.set_self_match_policy(settings.self_match_policy);
+        platform.settlement = settings.settlement.clone();
+        settings.apply_limits(&mut platform);
+        platform.auditor = Auditor {
+            log: self.audit_log.clone(),
+            tenant: name.to_string(),
+            justification: None,
+        };
+        platform.recent_orders = RecentOrders::new(settings.duplicate_window_secs * 1000);
+        platform.reference_prices = ReferencePrices::new(settings.reference_window_secs * 1000);
+        for market in &settings.markets {
+            platform
+                .markets
+                .insert(&market.symbol, market.config.clone());
+        }
+        platform.preallocate_books();
+        if let Some(data_dir) = &settings.data_dir {
+            let (store, issued) = CounterStore::open(&data_dir.join(name).join(COUNTERS_FILE))?;
+            if let Some(parked) = ParkedState::load(&data_dir.join(name).join(SHUTDOWN_FILE))? {
+                parked.verify(issued.ordinal)?;
+                log::info!("Tenant {} shut down in order: {:?}", name, parked.marker);
+            }
+            platform.resume_ids(issued);
+            platform.counter_store = Some(store);
+            platform.reports = Reports::saved_to(data_dir.join(name).join(REPORTS_DIR));
+        }
+        let platform = Arc::new(Mutex::new(platform));
+        let orders = Arc::new(OrderQueue::start(
+            platform.clone(),
+            settings.order_queue_capacity,
+        ));
+        tenants.insert(name.to_string(), Tenant { platform, orders });
+        Ok(())
+    }
+
+    /// The audit trail of all tenants
+    pub fn audit_log(&self) -> Arc<AuditLog> {
+        self.audit_log.clone()
+    }
+
+    /// Replaces the fees and risk limits of every tenant, including the ones created later. The order queue capacity
+    /// and the markets of `settings` are ignored. Open orders stay in the book as they are.
+    pub fn update_limits(&self, settings: &TenantSettings) {
+        // Same lock order as `create`, so no tenant is created with the old limits in between
+        let tenants = self.tenants.read().unwrap();
+        let mut current = self.settings.write().unwrap();
+        current.withdrawal_approval_threshold = settings.withdrawal_approval_threshold;
+        current.pin_threshold = settings.pin_threshold;
+        current.cold_withdrawal_delay_secs = settings.cold_withdrawal_delay_secs;
+        current.whitelist_threshold = settings.whitelist_threshold;
+        current.whitelist_delay_secs = settings.whitelist_delay_secs;
+        current.dust_threshold = settings.dust_threshold;
+        current.taker_fee_bps = settings.taker_fee_bps;
+        current.maker_fee_bps = settings.maker_fee_bps;
+        current.price_collar_bps = settings.price_collar_bps;
+        current.max_slippage_bps = settings.max_slippage_bps;
+        current.circuit_breaker_bps = settings.circuit_breaker_bps;
+        current.circuit_breaker_halt_secs = settings.circuit_breaker_halt_secs;
+        current.max_orders_per_sec = settings.max_orders_per_sec;
+        current.max_cancel_ratio_percent = settings.max_cancel_ratio_percent;
+        for tenant in tenants.values() {
+            current.apply_limits(&mut tenant.platform.lock().unwrap());
+        }
+    }
+
+    /// Parks every tenant once its order queue is drained, see [`TradingPlatform::park`]. With a data directory, the
+    /// parked state is written to the tenant's [`SHUTDOWN_FILE`]. Blocks until the queues are empty.
+    /// # Errors
+    /// Writing a tenant's file failed, the remaining tenants are parked anyway
+    pub fn park(&self, now: u64) -> Result<Vec<(String, ShutdownMarker)>, ApplicationError> {
+        let data_dir = self.settings.read().unwrap().data_dir.clone();
+        let mut parked = vec![];
+        let mut failure = Ok(());
+        for name in self.names() {
+            let (platform, orders) = (self.get(&name)?, self.orders(&name)?);
+            while orders.depth() > 0 {
+                thread::sleep(Duration::from_millis(10));
+            }
+            let state = platform.lock().unwrap().park(now);
+            if let Some(data_dir) = &data_dir {
+                if let Err(e) = state.write(&data_dir.join(&name).join(SHUTDOWN_FILE)) {
+                    failure = Err(e);
+                    continue;
+                }
+            }
+            parked.push((name, state.marker));
+        }
+        failure.map(|_| parked)
+    }
+
+    /// Restores the books every tenant parked on the previous shutdown, see [`TradingPlatform::restore_books`], and
+    /// discards the parked state. Run after the seed, so the accounts of the orders exist. Returns the number of
+    /// orders restored and dropped by tenant.
+    /// # Errors
+    /// A tenant's parked state can't be read or discarded
+    pub fn restore_books(&self) -> Result<Vec<(String, usize, usize)>, ApplicationError> {
+        let Some(data_dir) = self.settings.read().unwrap().data_dir.clone() else {
+            return Ok(vec![]);
+        };
+        let mut restored = vec![];
+        for name in self.names() {
+            let path = data_dir.join(&name).join(SHUTDOWN_FILE);
+            let Some(parked) = ParkedState::load(&path)? else {
+                continue;
+            };
+            let (orders, dropped) = self.get(&name)?.lock().unwrap().restore_books(parked.books);
+            for order in dropped.iter() {
+                log::warn!(
+                    "Dropped parked order {} of tenant {}, account {} is gone",
+                    order.order_id,
+                    name,
+                    order.signer
+                );
+            }
+            ParkedState::discard(&path)?;
+            restored.push((name, orders, dropped.len()));
+        }
+        Ok(restored)
+    }
+
+    /// Fetches the platform of a tenant
+    pub fn get(&self, name: &str) -> Result<Arc<Mutex<TradingPlatform>>, ApplicationError> {
+        self.tenants
+            .read()
+            .unwrap()
+            .get(name)
+            .map(|tenant| tenant.platform.clone())
+            .ok_or(ApplicationError::TenantNotFound(name.to_string()))
+    }
+
+    /// Fetches the order queue of a tenant
+    pub fn orders(&self, name: &str) -> Result<Arc<OrderQueue>, ApplicationError> {
+        self.tenants
+            .read()
+            .unwrap()
+            .get(name)
+            .map(|tenant| tenant.orders.clone())
+            .ok_or(ApplicationError::TenantNotFound(name.to_string()))
+    }
+
+    /// All tenants' platforms in alphabetical order of their names
+    pub fn platforms(&self) -> Vec<(String, Arc<Mutex<TradingPlatform>>)> {
+        let mut platforms: Vec<_> = self
+            .tenants
+            .read()
+            .unwrap()
+            .iter()
+            .map(|(name, tenant)| (name.clone(), tenant.platform.clone()))
+            .collect();
+        platforms.sort_by(|a, b| a.0.cmp(&b.0));
+        platforms
+    }
+
+    /// All tenant names in alphabetical order
+    pub fn names(&self) -> Vec<String> {
+        let mut names: Vec<String> = self.tenants.read().unwrap().keys().cloned().collect();
+        names.sort();
+        names
+    }
+}
+
+/// A filter that resolves the platform of the tenant selected by the [`TENANT_HEADER`]
+pub fn with_tenant(
+    tenants: Arc<Tenants>,
+) -> impl Filter<Extract = (Arc<Mutex<TradingPlatform>>,), Error = warp::Rejection> + Clone {
+    warp::header::optional::<String>(TENANT_HEADER).and_then(move |name: Option<String>| {
+        let tenants = tenants.clone();
+        async move {
+            tenants
+                .get(name.as_deref().unwrap_or(DEFAULT_TENANT))
+                .map_err(|e| warp::reject::custom(OctopusError(e)))
+        }
+    })
+}
+
+/// A filter that resolves the order queue of the tenant selected by the [`TENANT_HEADER`]
+pub fn with_order_queue(
+    tenants: Arc<Tenants>,
+) -> impl Filter<Extract = (Arc<OrderQueue>,), Error = warp::Rejection> + Clone {
+    warp::header::optional::<String>(TENANT_HEADER).and_then(move |name: Option<String>| {
+        let tenants = tenants.clone();
+        async move {
+            tenants
+                .orders(name.as_deref().unwrap_or(DEFAULT_TENANT))
+                .map_err(|e| warp::reject::custom(OctopusError(e)))
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    // reduce the warnings for naming tests
+    #![allow(non_snake_case)]
+
+    use super::*;
+    use octopus_common::types::{
+        MarketStatus, Order, OrderType, Side, TimeInForce, DEFAULT_MARKET,
+    };
+
+    #[test]
+    fn test_Tenants_new_always_has_default() {
+        let tenants = Tenants::new(&["acme", DEFAULT_TENANT], TenantSettings::default()).unwrap();
+        assert_eq!(tenants.names(), vec!["acme", DEFAULT_TENANT]);
+        assert_eq!(
+            tenants.get("globex").err(),
+            Some(ApplicationError::TenantNotFound("globex".to_string()))
+        );
+    }
+
+    #[test]
+    fn test_Tenants_create_validates_names() {
+        let tenants = Tenants::new(&[], TenantSettings::default()).unwrap();
+        assert_eq!(
+            tenants.create("../etc"),
+            Err(ApplicationError::InvalidTenantName("../etc".to_string()))
+        );
+        assert_eq!(
+            tenants.create(""),
+            Err(ApplicationError::InvalidTenantName("".to_string()))
+        );
+        assert_eq!(tenants.create("acme_2"), Ok(()));
+        assert_eq!(
+            tenants.create("acme_2"),
+            Err(ApplicationError::TenantAlreadyExists("acme_2".to_string()))
+        );
+    }
+
+    #[test]
+    fn test_Tenants_platforms_are_isolated() {
+        let tenants = Tenants::new(&["acme"], TenantSettings::default()).unwrap();
+        let acme = tenants.get("acme").unwrap();
+        acme.lock().unwrap().deposit("ALICE", 100).unwrap();
+
+        let default = tenants.get(DEFAULT_TENANT).unwrap();
+        assert_eq!(
+            default.lock().unwrap().balance_of("ALICE"),
+            Err(ApplicationError::AccountNotFound("ALICE".to_string()))
+        );
+        assert_eq!(acme.lock().unwrap().balance_of("ALICE"), Ok(&100));
+    }
+
+    #[test]
+    fn test_Tenants_create_continues_ids_after_restart() {
+        let data_dir = std::env::temp_dir().join(format!("octopus-tenants-{}", std::process::id()));
+        let settings = TenantSettings {
+            data_dir: Some(data_dir.clone()),
+            ..TenantSettings::default()
+        };
+        let order = |signer: &str| Order {
+            price: 10,
+            amount: 1,
+            side: Side::Sell,
+            signer: signer.to_string(),
+            client_order_id: None,
+            signature: None,
+            order_type: OrderType::Limit,
+            time_in_force: TimeInForce::Gtc,
+            trigger_price: None,
+            market: DEFAULT_MARKET.to_string(),
+        };
+        let tenants = Tenants::new(&[], settings.clone()).unwrap();
+        let platform = tenants.get(DEFAULT_TENANT).unwrap();
+        platform.lock().unwrap().deposit("ALICE", 100).unwrap();
+        let before = platform.lock().unwrap().order(order("ALICE")).unwrap();
+        drop((platform, tenants));
+
+        let restarted = Tenants::new(&[], settings).unwrap();
+        let platform = restarted.get(DEFAULT_TENANT).unwrap();
+        platform.lock().unwrap().deposit("ALICE", 100).unwrap();
+        let after = platform.lock().unwrap().order(order("ALICE")).unwrap();
+        assert!(after.ordinal > before.ordinal);
+        std::fs::remove_dir_all(&data_dir).unwrap();
+    }
+
+    #[test]
+    fn test_Tenants_park_is_verified_on_restart() {
+        let data_dir = std::env::temp_dir().join(format!("octopus-park-{}", std::process::id()));
+        let settings = TenantSettings {
+            data_dir: Some(data_dir.clone()),
+            ..TenantSettings::default()
+        };
+        let tenants = Tenants::new(&[], settings.clone()).unwrap();
+        let platform = tenants.get(DEFAULT_TENANT).unwrap();
+        {
+            let mut ledger_lock = platform.lock().unwrap();
+            ledger_lock.deposit("ALICE", 100).unwrap();
+            ledger_lock
+                .order(Order {
+                    price: 10,
+                    amount: 1,
+                    side: Side::Sell,
+                    signer: "ALICE".to_string(),
+                    client_order_id: None,
+                    signature: None,
+                    order_type: OrderType::Limit,
+                    time_in_force: TimeInForce::Gtc,
+                    trigger_price: None,
+                    market: DEFAULT_MARKET.to_string(),
+                })
+                .unwrap();
+            ledger_lock.hold("ALICE", 20).unwrap();
+        }
+        let parked = tenants.park(7).unwrap();
+        assert_eq!(parked.len(), 1);
+        assert_eq!((parked[0].1.resting_orders, parked[0].1.holds), (1, 1));
+        let market = platform
+            .lock()
+            .unwrap()
+            .market_info(DEFAULT_MARKET)
+            .unwrap();
+        assert_eq!(market.status, MarketStatus::Halted);
+        drop((platform, tenants));
+        assert!(Tenants::new(&[], settings.clone()).is_ok());
+
+        let path = data_dir.join(DEFAULT_TENANT).join(SHUTDOWN_FILE);
+        let mut state = ParkedState::load(&path).unwrap().unwrap();
+        state.orders.clear();
+        state.write(&path).unwrap();
+        assert!(matches!(
+            Tenants::new(&[], settings),
+            Err(ApplicationError::StorageFailed(_))
+        ));
+        std::fs::remove_dir_all(&data_dir).unwrap();
+    }
+
+    #[test]
+    fn test_Tenants_restore_books_brings_back_the_parked_orders() {
+        let data_dir = std::env::temp_dir().join(format!("octopus-restore-{}", std::process::id()));
+        let settings = TenantSettings {
+            data_dir: Some(data_dir.clone()),
+            ..TenantSettings::default()
+        };
+        let order = |signer: &str, price| Order {
+            price,
+            amount: 1,
+            side: Side::Sell,
+            signer: signer.to_string(),
+            client_order_id: None,
+            signature: None,
+            order_type: OrderType::Limit,
+            time_in_force: TimeInForce::Gtc,
+            trigger_price: None,
+            market: DEFAULT_MARKET.to_string(),
+        };
+        let tenants = Tenants::new(&[], settings.clone()).unwrap();
+        {
+            let platform = tenants.get(DEFAULT_TENANT).unwrap();
+            let mut ledger_lock = platform.lock().unwrap();
+            for signer in ["ALICE", "BOB"] {
+                ledger_lock.deposit(signer, 100).unwrap();
+            }
+            ledger_lock.order(order("ALICE", 10)).unwrap();
+            ledger_lock.order(order("BOB", 11)).unwrap();
+        }
+        tenants.park(7).unwrap();
+        drop(tenants);
+
+        // Only ALICE is seeded again
+        let tenants = Tenants::new(&[], settings).unwrap();
+        let platform = tenants.get(DEFAULT_TENANT).unwrap();
+        platform.lock().unwrap().deposit("ALICE", 100).unwrap();
+        assert_eq!(
+            tenants.restore_books().unwrap(),
+            vec![(DEFAULT_TENANT.to_string(), 1, 1)]
+        );
+        {
+            let ledger_lock = platform.lock().unwrap();
+            let orders = ledger_lock.orderbook();
+            assert_eq!(
+                orders.iter().map(|o| o.signer.as_str()).collect::<Vec<_>>(),
+                vec!["ALICE"]
+            );
+            assert_eq!(ledger_lock.book_updates.depth().asks.quantity, 1);
+            assert!(ledger_lock.book_snapshot().seq > 0);
+        }
+        // The restored ask fills, under an ordinal after the parked ones
+        let receipt = {
+            let mut ledger_lock = platform.lock().unwrap();
+            ledger_lock.deposit("CAROL", 100).unwrap();
+            ledger_lock
+                .order(Order {
+                    side: Side::Buy,
+                    ..order("CAROL", 10)
+                })
+                .unwrap()
+        };
+        assert_eq!(receipt.filled, 1);
+        assert!(receipt.ordinal > 2);
+        assert!(!data_dir.join(DEFAULT_TENANT).join(SHUTDOWN_FILE).exists());
+        assert!(tenants.restore_books().unwrap().is_empty());
+        std::fs::remove_dir_all(&data_dir).unwrap();
+    }
+
+    #[test]
+    fn test_Tenants_update_limits_applies_to_all_tenants() {
+        let tenants = Tenants::new(&["acme"], TenantSettings::default()).unwrap();
+        tenants.update_limits(&TenantSettings {
+            taker_fee_bps: 25,
+            price_collar_bps: Some(500),
+            pin_threshold: 100,
+            order_queue_capacity: 1,
+            ..TenantSettings::default()
+        });
+        tenants.create("globex").unwrap();
+        for (_, platform) in tenants.platforms() {
+            let platform = platform.lock().unwrap();
+            assert_eq!(platform.taker_fee_bps, 25);
+            assert_eq!(platform.price_collar_bps, Some(500));
+            assert_eq!(platform.pin_threshold, 100);
+        }
+        assert_eq!(
+            tenants.settings.read().unwrap().order_queue_capacity,
+            DEFAULT_ORDER_QUEUE_CAPACITY
+        );
+    }
+}
@@ -0,0 +1,167 @@
+//! Per-account order rate limits, enforced by the platform whichever way orders arrive, so one runaway bot can't starve
+//! the engine for everybody else.
+//!
+//! Accounts are limited in the orders they submit per second and in the share of their orders they cancel again. Both
+//! limits are off unless configured.
+use octopus_common::errors::ApplicationError;
+use std::collections::{HashMap, VecDeque};
+
+/// The window of the order rate limit (ms)
+pub const RATE_WINDOW_MILLIS: u64 = 1000;
+
+/// The window the cancel ratio is measured over (ms)
+pub const RATIO_WINDOW_MILLIS: u64 = 60 * 1000;
+
+/// Orders an account submits within the ratio window before its cancel ratio is enforced, so a few cancels of a
+/// quiet account don't throttle it
+pub const MIN_RATIO_ORDERS: usize = 20;
+
+/// The recent orders and cancellations of an account (ms)
+#[derive(Debug, Default)]
+struct Activity {
+    orders: VecDeque<u64>,
+    cancels: VecDeque<u64>,
+}
+
+impl Activity {
+    fn forget_before(&mut self, start: u64) {
+        while self.orders.front().is_some_and(|t| *t < start) {
+            self.orders.pop_front();
+        }
+        while self.cancels.front().is_some_and(|t| *t < start) {
+            self.cancels.pop_front();
+        }
+    }
+}
+
+/// Seconds until `millis` have passed, at least one
+fn retry_secs(millis: u64) -> u64 {
+    millis.div_ceil(1000).max(1)
+}
+
+/// Limits the orders of every account
+#[derive(Debug, Default)]
+pub struct OrderThrottle {
+    /// Orders an account may submit per second
+    pub max_orders_per_sec: Option<u64>,
+    /// Cancellations as a percentage of the orders an account may have within the ratio window
+    pub max_cancel_ratio_percent: Option<u64>,
+    accounts: HashMap<String, Activity>,
+    throttled: u64,
+}
+
+impl OrderThrottle {
+    pub fn new() -> Self {
+        OrderThrottle::default()
+    }
+
+    /// Counts an order of `signer` if the account is within its limits
+    /// # Errors
+    /// The account exceeds its order rate or cancel ratio, the error says when to retry
+    pub fn admit(&mut self, signer: &str, now: u64) -> Result<(), ApplicationError> {
+        if self.max_orders_per_sec.is_none() && self.max_cancel_ratio_percent.is_none() {
+            return Ok(());
+        }
+        let activity = self.accounts.entry(signer.to_string()).or_default();
+        activity.forget_before(now.saturating_sub(RATIO_WINDOW_MILLIS));
+
+        let rate_start = now.saturating_sub(RATE_WINDOW_MILLIS);
+        let recent = activity
+            .orders
+            .iter()
+            .rev()
+            .take_while(|t| **t > rate_start);
+        let retry_after = match (self.max_orders_per_sec, recent.count() as u64) {
+            (Some(max), count) if count >= max => {
+                // The oldest order in the window has to leave it first
+                let index = activity.orders.len() - count as usize;
+                let oldest = activity.orders.get(index).copied().unwrap_or(now);
+                Some(retry_secs(oldest + RATE_WINDOW_MILLIS - now))
+            }
+            _ => None,
+        };
+        let retry_after = retry_after.or_else(|| {
+            let max = self.max_cancel_ratio_percent?;
+            let orders = activity.orders.len();
+            let exceeded = orders >= MIN_RATIO_ORDERS
+                && activity.cancels.len() as u64 * 100 > orders as u64 * max;
+            let oldest = activity.cancels.front()?;
+            exceeded.then(|| retry_secs(oldest + RATIO_WINDOW_MILLIS - now))
+        });
+        match retry_after {
+            Some(secs) => {
+                self.throttled += 1;
+                Err(ApplicationError::OrderThrottled(signer.to_string(), secs))
+            }
+            None => {
+                activity.orders.push_back(now);
+                Ok(())
+            }
+        }
+    }
+
+    /// Counts `count` orders `signer` cancelled itself
+    pub fn record_cancels(&mut self, signer: &str, count: usize, now: u64) {
+        if self.max_cancel_ratio_percent.is_none() || count == 0 {
+            return;
+        }
+        let activity = self.accounts.entry(signer.to_string()).or_default();
+        activity.cancels.extend(std::iter::repeat_n(now, count));
+    }
+
+    /// The orders rejected since the start
+    pub fn throttled(&self) -> u64 {
+        self.throttled
+    }
+
+    /// Forgets the activity of `signer`
+    pub fn remove(&mut self, signer: &str) {
+        self.accounts.remove(signer);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    // reduce the warnings for naming tests
+    #![allow(non_snake_case)]
+
+    use super::*;
+
+    #[test]
+    fn test_OrderThrottle_admit_limits_orders_per_second() {
+        let mut throttle = OrderThrottle::new();
+        for now in 0..10 {
+            throttle.admit("ALICE", now).unwrap();
+        }
+        throttle.max_orders_per_sec = Some(3);
+        throttle.admit("ALICE", 2_000).unwrap();
+        throttle.admit("ALICE", 2_400).unwrap();
+        throttle.admit("ALICE", 2_800).unwrap();
+        assert_eq!(
+            throttle.admit("ALICE", 2_900),
+            Err(ApplicationError::OrderThrottled("ALICE".to_string(), 1))
+        );
+        // Other accounts have limits of their own
+        throttle.admit("BOB", 2_900).unwrap();
+        throttle.admit("ALICE", 3_001).unwrap();
+        assert_eq!(throttle.throttled(), 1);
+    }
+
+    #[test]
+    fn test_OrderThrottle_admit_limits_the_cancel_ratio() {
+        let mut throttle = OrderThrottle::new();
+        throttle.max_cancel_ratio_percent = Some(50);
+        for now in 0..MIN_RATIO_ORDERS as u64 {
+            throttle.admit("ALICE", now * 100).unwrap();
+        }
+        throttle.record_cancels("ALICE", 10, 2_000);
+        throttle.admit("ALICE", 2_100).unwrap();
+        throttle.record_cancels("ALICE", 2, 2_200);
+        assert_eq!(
+            throttle.admit("ALICE", 2_300),
+            Err(ApplicationError::OrderThrottled("ALICE".to_string(), 60))
+        );
+        // The cancels leave the window
+        throttle.admit("ALICE", 62_300).unwrap();
+    }
+}
@@ -1,40 +1,599 @@
 use octopus_common::{
     errors::ApplicationError,
-    tx::Tx,
-    types::{Order, PartialOrder, Receipt, Side},
+    tx::{Memo, Tx},
+    types::{
+        anonymize, AccountStats, AccountUpdate, AmendRequest, ApiKey, ApiKeyScope, AuctionReceipt,
+        BookQuery, BookSnapshot, BracketReceipt, BracketRequest, CancelFilter, DailyReport,
+        DeletedAccount, DepositNotification, DustReport, DustSource, DustSweep, Exposure,
+        FeeCharge, FeeKind, FeeTier, Invoice, MarketAnalytics, MarketExposure, MarketInfo,
+        MarketStatus, NewApiKey, Order, OrderBookSnapshot, OrderConstraint, OrderId,
+        OrderLifecycle, OrderType, PartialOrder, PayoutRequest, PendingWithdrawal, Position,
+        QueuePosition, QuoteReceipt, QuoteRequest, Receipt, RecurringBuy, RecurringBuyRequest,
+        ReferencePrice, RegisteredPublicKey, Role, SavedRecipient, SelfMatchPolicy, SendRequest,
+        Settlement, Side, StopLossStatus, TapeTrade, Ticker, TimeInForce, Trade, TradesPage,
+        TradesQuery, WhitelistEntry, WithdrawalStatus, DEFAULT_MARKET,
+    },
 };
+use std::{
+    collections::{BTreeMap, HashMap},
+    sync::Arc,
+};
+use tokio::sync::broadcast;
+
+use crate::{
+    accounting::Accounts,
+    activity::EngineActivity,
+    address_book::AddressBook,
+    analytics,
+    api_keys::{random_hex, ApiKeys},
+    archives::{ArchivedTx, Archives},
+    audit::{AuditAction, Auditor},
+    balances::BalanceLog,
+    book_updates::{BookUpdates, TouchedLevel},
+    cold::ColdAccounts,
+    core::{
+        BookEvent, EngineObserver, EventLog, IdGenerator, MatchingEngine, MonotonicIds,
+        PointInTime, PoolStats, StopGuard, TriggeredStop,
+    },
+    counters::{CounterStore, IdCounters},
+    duplicates::RecentOrders,
+    dust::{Dust, DUST_ACCOUNT},
+    engine_events::EngineEvents,
+    fees::{FeeLedger, FEE_ACCOUNT},
+    invoices::Invoices,
+    lifecycle::lifecycle,
+    markets::{MarketConfig, Markets},
+    pins::Pins,
+    positions::Positions,
+    private_channel::AccountFeed,
+    recurring::RecurringBuys,
+    reference::ReferencePrices,
+    reports::Reports,
+    risk::{within_collar, CircuitBreaker, StopFunds, StopLosses},
+    scheduler::now_millis,
+    settlement::SettlementPolicy,
+    shutdown::{ParkedState, ShutdownMarker},
+    signing::SigningKeys,
+    stats::{Fill, TradeStats},
+    tags::TxTags,
+    throttle::OrderThrottle,
+    verification::{BookDigest, StateDigest},
+    whitelist::Whitelists,
+};
+
+/// The number of matches a slow [`TradingPlatform::trade_feed`] subscriber may fall behind before missing some
+pub const TRADE_FEED_CAPACITY: usize = 1024;
+
+/// The most recipients of one payout
+pub const MAX_PAYOUT_RECIPIENTS: usize = 1000;
+
+/// Deleted accounts are renamed to this prefix and a random hex string
+pub const ANONYMIZED_PREFIX: &str = "anon_";
+/// Random bytes in the token of a deleted account
+const ANONYMIZED_TOKEN_BYTES: usize = 16;
+
+/// A book of a listed market other than the [`DEFAULT_MARKET`], with the ids, the private channels, and the
+/// self-match policy of the public book
+fn listed_book(
+    ids: Arc<dyn IdGenerator>,
+    feed: &AccountFeed,
+    events: &EngineEvents,
+    policy: SelfMatchPolicy,
+) -> MatchingEngine {
+    let mut book = MatchingEngine::with_ids(ids);
+    book.register_observer(Box::new(feed.clone()));
+    book.register_sink(Box::new(events.clone()));
+    book.set_self_match_policy(policy);
+    book
+}
 
-use crate::{accounting::Accounts, core::MatchingEngine};
+/// The currency `amount` units at `price` are worth
+/// # Errors
+/// The notional is more than a `u64` holds, [`ApplicationError::InvalidOrder`] names it
+fn notional(amount: u64, price: u64) -> Result<u64, ApplicationError> {
+    amount
+        .checked_mul(price)
+        .ok_or(ApplicationError::InvalidOrder(OrderConstraint::Notional {
+            amount,
+            price,
+        }))
+}
+
+/// The balances settling the matches of one receipt moves, see [`TradingPlatform::plan_fills`]
+struct FillPlan {
+    /// Buyer, seller, and notional of each match
+    legs: Vec<(String, String, u64)>,
+    /// The account, amount, and kind of each fee, the taker's first
+    charges: Vec<(String, u64, FeeKind)>,
+    taker_fees: Vec<u64>,
+    maker_fees: Vec<u64>,
+    gross: u64,
+    fee: u64,
+}
+
+impl FillPlan {
+    /// Every transfer of the plan in the order it's applied, fees go to the [`FEE_ACCOUNT`]
+    fn transfers(&self) -> impl Iterator<Item = (&str, &str, u64)> {
+        self.legs
+            .iter()
+            .map(|(buyer, seller, amount)| (buyer.as_str(), seller.as_str(), *amount))
+            .chain(
+                self.charges
+                    .iter()
+                    .map(|(account, fee, _)| (account.as_str(), FEE_ACCOUNT, *fee)),
+            )
+    }
+}
 
 /// The core of the core: the [`TradingPlatform`]. Manages accounts, validates-, and orchestrates the processing of each order.
 ///
 ///
 pub struct TradingPlatform {
+    /// The public book of the [`DEFAULT_MARKET`]
     pub matching_engine: MatchingEngine,
+    /// The books of the other listed markets by symbol, opened with their first order. Ordinals are shared with the
+    /// public book.
+    pub books: HashMap<String, MatchingEngine>,
+    /// The orders of sandbox accounts, apart from the public book. Ordinals are shared with the public book.
+    pub sandbox_book: MatchingEngine,
+    /// Every match in the sandbox book, oldest first
+    pub sandbox_trades: Vec<Trade>,
+    /// Every change to the order book
+    pub book_log: EventLog,
+    /// Sequence-numbered price level changes for clients following the book
+    pub book_updates: BookUpdates,
     pub accounts: Accounts,
     pub transactions: Vec<Tx>,
+    /// Every transaction with when it happened
+    pub balance_log: BalanceLog,
+    /// Every match, oldest first
+    pub trades: Vec<Trade>,
+    last_trade_id: u64,
+    /// Publishes every match as it happens
+    pub trade_feed: broadcast::Sender<Trade>,
+    /// Publishes the order updates, fills, and balance changes of every account to its private channels
+    pub account_feed: AccountFeed,
+    /// Streams the order events of the public books to operators
+    pub engine_events: EngineEvents,
+    pub api_keys: ApiKeys,
+    /// Processed gateway notifications and their transactions by notification id
+    pub gateway_deposits: HashMap<String, (DepositNotification, Tx)>,
+    /// Withdrawals above this amount need an admin's approval
+    pub withdrawal_approval_threshold: Option<u64>,
+    /// Withdrawals and sends above this amount need the PIN of accounts that set one
+    pub pin_threshold: u64,
+    pub pins: Pins,
+    /// Saved recipients of transfers by alias
+    pub address_book: AddressBook,
+    /// Destinations the accounts approved for their withdrawals and transfers
+    pub whitelists: Whitelists,
+    /// Withdrawals and sends above this amount may only go to the active whitelist entries of accounts that keep one
+    pub whitelist_threshold: u64,
+    /// Public keys verifying the signed orders of the accounts that registered one
+    pub signing_keys: SigningKeys,
+    /// Records signed orders and key registrations
+    pub auditor: Auditor,
+    /// Accounts whose withdrawals wait for a delay and their confirmation
+    pub cold_accounts: ColdAccounts,
+    pub dust: Dust,
+    /// The tags accounts put on their transactions
+    pub tx_tags: TxTags,
+    /// Withdrawals that needed approval or confirmation by id
+    pub withdrawals: BTreeMap<u64, PendingWithdrawal>,
+    last_withdrawal_id: u64,
+    last_payout_id: u64,
+    /// Issues the trade, withdrawal, and payout ids, the engines and accounts share it for the ordinals and hold ids
+    ids: Arc<dyn IdGenerator>,
+    pub recurring_buys: RecurringBuys,
+    pub positions: Positions,
+    /// The price of the latest match in the [`DEFAULT_MARKET`]
+    pub last_price: Option<u64>,
+    /// The prices positions are valued at and orders are collared around
+    pub reference_prices: ReferencePrices,
+    pub stop_losses: StopLosses,
+    /// Rejects orders executing far from the last price of their market, or halts the market
+    pub circuit_breaker: CircuitBreaker,
+    pub trade_stats: TradeStats,
+    /// The fee in basis points charged to the taker of each match
+    pub taker_fee_bps: u64,
+    /// The fee in basis points charged to the maker of each match
+    pub maker_fee_bps: u64,
+    /// How fees are rounded and split
+    pub settlement: SettlementPolicy,
+    /// Limit orders priced, and market orders filling, more than this many basis points away from the reference price
+    /// are rejected
+    pub price_collar_bps: Option<u64>,
+    /// Market orders that would fill more than this many basis points away from the best price are rejected
+    pub max_slippage_bps: Option<u64>,
+    pub fees: FeeLedger,
+    pub invoices: Invoices,
+    /// End-of-day market reports
+    pub reports: Reports,
+    pub markets: Markets,
+    /// Keeps the identifiers unique across restarts, in-memory platforms don't have one
+    pub counter_store: Option<CounterStore>,
+    /// Accepted orders with a client order id, to reject their resubmissions
+    pub recent_orders: RecentOrders,
+    /// Per-account order rate and cancel ratio limits
+    pub throttle: OrderThrottle,
+    /// The order ids of every account's latest quote, cancelled when it's replaced
+    quotes: HashMap<String, Vec<OrderId>>,
+    /// Account archives generated in the background
+    pub archives: Archives,
+    /// Counts what the public book's engine does, for the metrics
+    pub activity: EngineActivity,
+}
+
+impl Default for TradingPlatform {
+    fn default() -> Self {
+        TradingPlatform::new()
+    }
 }
 
 impl TradingPlatform {
     /// Creates a new instance without any data.
     pub fn new() -> Self {
-        TradingPlatform {
-            matching_engine: MatchingEngine::new(),
-            accounts: Accounts::new(),
+        TradingPlatform::with_ids(Arc::new(MonotonicIds))
+    }
+
+    /// Like [`TradingPlatform::new`], but ordinals, trade ids, hold ids, and withdrawal ids are issued by `ids`
+    pub fn with_ids(ids: Arc<dyn IdGenerator>) -> Self {
+        let mut platform = TradingPlatform {
+            matching_engine: MatchingEngine::with_ids(ids.clone()),
+            books: HashMap::new(),
+            sandbox_book: MatchingEngine::with_ids(ids.clone()),
+            sandbox_trades: vec![],
+            book_log: EventLog::default(),
+            book_updates: BookUpdates::default(),
+            accounts: Accounts::with_ids(ids.clone()),
             transactions: vec![],
+            balance_log: BalanceLog::default(),
+            trades: vec![],
+            last_trade_id: 0,
+            trade_feed: broadcast::channel(TRADE_FEED_CAPACITY).0,
+            account_feed: AccountFeed::default(),
+            engine_events: EngineEvents::default(),
+            api_keys: ApiKeys::new(),
+            gateway_deposits: HashMap::new(),
+            withdrawal_approval_threshold: None,
+            pin_threshold: 0,
+            pins: Pins::new(),
+            address_book: AddressBook::new(),
+            whitelists: Whitelists::new(),
+            whitelist_threshold: 0,
+            signing_keys: SigningKeys::new(),
+            auditor: Auditor::default(),
+            cold_accounts: ColdAccounts::new(),
+            dust: Dust::new(),
+            tx_tags: TxTags::new(),
+            withdrawals: BTreeMap::new(),
+            last_withdrawal_id: 0,
+            last_payout_id: 0,
+            recurring_buys: RecurringBuys::new(),
+            positions: Positions::new(),
+            last_price: None,
+            reference_prices: ReferencePrices::default(),
+            stop_losses: StopLosses::new(),
+            circuit_breaker: CircuitBreaker::new(),
+            trade_stats: TradeStats::new(),
+            taker_fee_bps: 0,
+            maker_fee_bps: 0,
+            settlement: SettlementPolicy::default(),
+            price_collar_bps: None,
+            max_slippage_bps: None,
+            fees: FeeLedger::new(),
+            invoices: Invoices::new(),
+            reports: Reports::new(),
+            markets: Markets::new(),
+            counter_store: None,
+            recent_orders: RecentOrders::default(),
+            throttle: OrderThrottle::new(),
+            quotes: HashMap::new(),
+            archives: Archives::default(),
+            activity: EngineActivity::default(),
+            ids,
+        };
+        platform.register_observer(Box::new(platform.activity.clone()));
+        platform.register_observer(Box::new(platform.account_feed.clone()));
+        platform
+            .matching_engine
+            .register_sink(Box::new(platform.engine_events.clone()));
+        platform
+            .sandbox_book
+            .register_observer(Box::new(platform.account_feed.clone()));
+        platform
+    }
+
+    /// Notifies `observer` of every order, match, cancellation, and level change of the public book. The sandbox book
+    /// isn't observed.
+    pub fn register_observer(&mut self, observer: Box<dyn EngineObserver>) {
+        self.matching_engine.register_observer(observer);
+    }
+
+    /// The last identifier issued of each kind
+    pub fn issued_ids(&self) -> IdCounters {
+        IdCounters {
+            ordinal: self.matching_engine.ordinal,
+            hold_id: self.accounts.last_hold_id(),
+            withdrawal_id: self.last_withdrawal_id,
+            recurring_buy_id: self.recurring_buys.last_id(),
+            trade_id: self.last_trade_id,
+            payout_id: self.last_payout_id,
         }
     }
 
-    /// Fetches the complete order book at this time
+    /// Continues every kind of identifier after `issued`, e.g. the counters of a [`CounterStore`] after a restart
+    pub fn resume_ids(&mut self, issued: IdCounters) {
+        self.matching_engine.ordinal = self.matching_engine.ordinal.max(issued.ordinal);
+        self.accounts.resume_hold_ids(issued.hold_id);
+        self.last_withdrawal_id = self.last_withdrawal_id.max(issued.withdrawal_id);
+        self.recurring_buys.resume_ids(issued.recurring_buy_id);
+        self.last_trade_id = self.last_trade_id.max(issued.trade_id);
+        self.last_payout_id = self.last_payout_id.max(issued.payout_id);
+    }
+
+    /// Makes the next identifier (set by `next` with the platform's generator) durable before it's issued
+    fn reserve_id(
+        &mut self,
+        next: impl FnOnce(&mut IdCounters, &dyn IdGenerator),
+    ) -> Result<(), ApplicationError> {
+        let mut ids = self.issued_ids();
+        next(&mut ids, self.ids.as_ref());
+        match &mut self.counter_store {
+            Some(store) => store.reserve(&ids),
+            None => Ok(()),
+        }
+    }
+
+    /// Fetches the complete order book of the [`DEFAULT_MARKET`] at this time
     pub fn orderbook(&self) -> Vec<PartialOrder> {
-        self.matching_engine
-            .asks
+        self.matching_engine.orders()
+    }
+
+    /// The open orders matching `query` in the book of its market
+    /// # Errors
+    /// There's no market with that symbol
+    pub fn orderbook_matching(
+        &self,
+        query: &BookQuery,
+    ) -> Result<Vec<PartialOrder>, ApplicationError> {
+        let market = query.market.as_deref().unwrap_or(DEFAULT_MARKET);
+        self.markets.get(market)?;
+        Ok(self
+            .book(market)
+            .map(|book| book.orders_matching(query))
+            .unwrap_or_default())
+    }
+
+    /// The book of `market`, `None` until a market other than the [`DEFAULT_MARKET`] got its first order
+    pub fn book(&self, market: &str) -> Option<&MatchingEngine> {
+        match market == DEFAULT_MARKET {
+            true => Some(&self.matching_engine),
+            false => self.books.get(market),
+        }
+    }
+
+    /// Resolves the self-matches of every book with `policy` from now on, including the books opened later
+    pub fn set_self_match_policy(&mut self, policy: SelfMatchPolicy) {
+        self.matching_engine.set_self_match_policy(policy);
+        self.sandbox_book.set_self_match_policy(policy);
+        for book in self.books.values_mut() {
+            book.set_self_match_policy(policy);
+        }
+    }
+
+    /// Allocates the books of the listed markets as configured, opening those of the other markets that preallocate
+    /// any levels, so the first orders after startup don't allocate
+    pub fn preallocate_books(&mut self) {
+        for (symbol, config) in self.markets.iter() {
+            let capacity = config.book_capacity();
+            if symbol == DEFAULT_MARKET {
+                self.matching_engine.preallocate(capacity);
+            } else if capacity.levels > 0 {
+                let (ids, feed, events) =
+                    (self.ids.clone(), &self.account_feed, &self.engine_events);
+                let policy = self.matching_engine.self_match_policy();
+                self.books
+                    .entry(symbol.clone())
+                    .or_insert_with(|| listed_book(ids, feed, events, policy))
+                    .preallocate(capacity);
+            }
+        }
+    }
+
+    /// Open interest, concentration, and turnover since `since` (unix ms) of every open book, see
+    /// [`analytics::analyze`]
+    pub fn analytics(&self, since: u64) -> Vec<MarketAnalytics> {
+        let books = self
+            .books
+            .iter()
+            .map(|(market, book)| (market.as_str(), book))
+            .chain([(DEFAULT_MARKET, &self.matching_engine)]);
+        analytics::analyze(books, &self.trades, since)
+    }
+
+    /// How the price levels of every open book were allocated, by market
+    pub fn pool_stats(&self) -> Vec<(String, PoolStats)> {
+        let mut stats: Vec<_> = self
+            .books
+            .iter()
+            .map(|(market, book)| (market.clone(), book.pool_stats()))
+            .collect();
+        stats.push((
+            DEFAULT_MARKET.to_string(),
+            self.matching_engine.pool_stats(),
+        ));
+        stats.sort_by(|a, b| a.0.cmp(&b.0));
+        stats
+    }
+
+    /// The price levels of the book with the sequence number of the latest delta they include
+    pub fn book_snapshot(&self) -> BookSnapshot {
+        self.book_snapshot_matching(&BookQuery::default())
+    }
+
+    /// Like [`TradingPlatform::book_snapshot`], with only the price levels matching `query`
+    pub fn book_snapshot_matching(&self, query: &BookQuery) -> BookSnapshot {
+        BookSnapshot {
+            seq: self.book_updates.seq(),
+            bids: self.matching_engine.levels_matching(&Side::Buy, query),
+            asks: self.matching_engine.levels_matching(&Side::Sell, query),
+        }
+    }
+
+    /// The open quantity of the best `levels` price levels of each side, without the orders and their signers
+    pub fn depth(&self, levels: usize) -> BookSnapshot {
+        self.book_snapshot_matching(&BookQuery {
+            limit: Some(levels),
+            ..BookQuery::default()
+        })
+    }
+
+    /// The reference data of a market. Every account pays the flat maker and taker fees. A market the circuit breaker
+    /// halted is reported halted until it resumes.
+    pub fn market_info(&self, symbol: &str) -> Result<MarketInfo, ApplicationError> {
+        let mut info = self.markets.info(
+            symbol,
+            vec![FeeTier {
+                min_volume: 0,
+                maker_fee_bps: self.maker_fee_bps,
+                taker_fee_bps: self.taker_fee_bps,
+            }],
+        )?;
+        if self
+            .circuit_breaker
+            .halted_until(symbol, now_millis())
+            .is_some()
+        {
+            info.status = MarketStatus::Halted;
+        }
+        if symbol == DEFAULT_MARKET && self.matching_engine.in_auction() {
+            info.status = MarketStatus::Auction;
+        }
+        Ok(info)
+    }
+
+    /// Halts every market and captures the resting orders and open holds with a [`ShutdownMarker`]
+    pub fn park(&mut self, now: u64) -> ParkedState {
+        self.markets.halt_all();
+        let mut orders = self.orderbook();
+        orders.extend(self.books.values().flat_map(|book| book.orders()));
+        orders.extend(self.sandbox_book.orders());
+        let holds = self.accounts.holds();
+        let marker = ShutdownMarker {
+            shut_down_at: now,
+            ordinal: self.issued_ids().ordinal,
+            resting_orders: orders.len(),
+            holds: holds.len(),
+        };
+        let mut books =
+            BTreeMap::from([(DEFAULT_MARKET.to_string(), self.matching_engine.snapshot())]);
+        books.extend(
+            self.books
+                .iter()
+                .map(|(symbol, book)| (symbol.clone(), book.snapshot())),
+        );
+        ParkedState {
+            orders,
+            holds,
+            marker,
+            books,
+        }
+    }
+
+    /// Restores the books parked by the previous run, see [`TradingPlatform::park`]. The orders and stops of accounts
+    /// that don't exist anymore are dropped. Clients following the public book get its restored levels as deltas, and
+    /// past books are rebuilt from it. Returns the number of orders restored and the dropped ones.
+    pub fn restore_books(
+        &mut self,
+        books: BTreeMap<String, OrderBookSnapshot>,
+    ) -> (usize, Vec<PartialOrder>) {
+        let mut dropped = vec![];
+        let policy = self.matching_engine.self_match_policy();
+        for (symbol, snapshot) in books {
+            let book = match symbol.as_str() {
+                DEFAULT_MARKET => &mut self.matching_engine,
+                _ => {
+                    let (ids, feed, events) =
+                        (self.ids.clone(), &self.account_feed, &self.engine_events);
+                    self.books
+                        .entry(symbol)
+                        .or_insert_with(|| listed_book(ids, feed, events, policy))
+                }
+            };
+            book.restore(snapshot);
+            let accounts = &self.accounts;
+            dropped.extend(book.cancel_where(|o| accounts.balance_of(&o.signer).is_err()));
+        }
+        // Ordinals are shared, they continue after the latest of any book
+        self.matching_engine.ordinal = self
+            .books
             .values()
-            .cloned()
+            .map(|book| book.ordinal)
+            .fold(self.matching_engine.ordinal, u64::max);
+        self.book_log.checkpoint(&self.matching_engine);
+        let touched = [Side::Buy, Side::Sell]
             .into_iter()
-            .chain(self.matching_engine.bids.values().cloned().into_iter())
-            .flatten()
-            .collect()
+            .flat_map(|side| {
+                self.matching_engine
+                    .levels(&side)
+                    .into_iter()
+                    .map(move |level| TouchedLevel {
+                        side: side.clone(),
+                        price: level.price,
+                        before: Some(0),
+                    })
+            })
+            .collect();
+        self.book_updates.publish(&self.matching_engine, touched);
+        let restored = self.orderbook().len()
+            + self
+                .books
+                .values()
+                .map(|book| book.orders().len())
+                .sum::<usize>();
+        (restored, dropped)
+    }
+
+    /// The balances, holds, books, and counters to compare with another instance, see [`StateDigest::compare`]. The
+    /// sandbox book isn't included.
+    pub fn digest(&self, now: u64) -> StateDigest {
+        let digest = |book: &MatchingEngine| BookDigest {
+            last_price: book.last_price(),
+            orders: book.orders(),
+        };
+        let mut books =
+            BTreeMap::from([(DEFAULT_MARKET.to_string(), digest(&self.matching_engine))]);
+        books.extend(
+            self.books
+                .iter()
+                .map(|(symbol, book)| (symbol.clone(), digest(book))),
+        );
+        StateDigest {
+            taken_at: now,
+            balances: self.accounts.balances(),
+            holds: self.accounts.holds(),
+            books,
+            counters: self.issued_ids(),
+            transactions: self.transactions.len(),
+        }
+    }
+
+    /// Rebuilds the order book as it was at a past point in time. Only the book of the [`DEFAULT_MARKET`] is logged, the
+    /// others have no past.
+    pub fn orderbook_at(&self, at: PointInTime, query: &BookQuery) -> Vec<PartialOrder> {
+        if query
+            .market
+            .as_deref()
+            .is_some_and(|market| market != DEFAULT_MARKET)
+        {
+            return vec![];
+        }
+        match at {
+            PointInTime::Ordinal(ordinal) => self.book_log.book_at_ordinal(ordinal),
+            PointInTime::Timestamp(timestamp) => self.book_log.book_at_time(timestamp),
+        }
+        .orders_matching(query)
     }
 
     /// Withdraw funds
@@ -42,19 +601,255 @@ impl TradingPlatform {
         self.accounts.balance_of(signer)
     }
 
-    /// Deposit funds
+    /// The balance `signer` had at a past point in time
+    /// # Errors
+    /// The account didn't exist at that point
+    pub fn balance_of_at(&self, signer: &str, at: PointInTime) -> Result<u64, ApplicationError> {
+        self.balance_log.balance_of_at(signer, at)
+    }
+
+    /// Appends a transaction to the log
+    fn record_tx(&mut self, tx: Tx) {
+        #[cfg(feature = "chaos")]
+        if crate::chaos::drop_write() {
+            log::warn!("Chaos: dropped the history write of {:?}", tx);
+            self.transactions.push(tx);
+            return;
+        }
+        self.balance_log
+            .record(self.matching_engine.ordinal, now_millis(), tx.clone());
+        if self.account_feed.is_subscribed() {
+            let mut parties = tx.parties();
+            parties.dedup();
+            for party in parties {
+                if let Ok(balance) = self.accounts.balance_of(party) {
+                    self.account_feed.publish(AccountUpdate::Balance {
+                        signer: party.to_string(),
+                        balance: *balance,
+                    });
+                }
+            }
+        }
+        self.transactions.push(tx);
+    }
+
+    /// Deposit funds. Sandbox accounts only get play funds from [`TradingPlatform::faucet`].
     pub fn deposit(&mut self, signer: &str, amount: u64) -> Result<Tx, ApplicationError> {
-        self.accounts.deposit(signer, amount).map(|tx| {
-            self.transactions.push(tx.clone());
-            tx
+        if self.accounts.is_sandbox(signer) {
+            return Err(ApplicationError::SandboxViolation(signer.to_string()));
+        }
+        self.accounts.deposit(signer, amount).inspect(|tx| {
+            self.record_tx(tx.clone());
         })
     }
 
-    /// Withdraw funds
-    pub fn withdraw(&mut self, signer: &str, amount: u64) -> Result<Tx, ApplicationError> {
-        self.accounts.withdraw(signer, amount).map(|tx| {
-            self.transactions.push(tx.clone());
-            tx
+    /// Credit a payment gateway deposit. Notifications are idempotent: resending one returns the original transaction.
+    ///
+    /// # Errors
+    /// - The id was used for a different notification
+    /// - The account would overflow
+    pub fn gateway_deposit(
+        &mut self,
+        notification: DepositNotification,
+    ) -> Result<Tx, ApplicationError> {
+        if let Some((processed, tx)) = self.gateway_deposits.get(&notification.id) {
+            return if *processed == notification {
+                Ok(tx.clone())
+            } else {
+                Err(ApplicationError::DuplicateNotification(notification.id))
+            };
+        }
+        let tx = self.deposit(&notification.account, notification.amount)?;
+        self.gateway_deposits
+            .insert(notification.id.clone(), (notification, tx.clone()));
+        Ok(tx)
+    }
+
+    /// Withdraw funds. Amounts above the [`TradingPlatform::withdrawal_approval_threshold`] are held
+    /// in a [`PendingWithdrawal`] until an admin approves or rejects it. Withdrawals of cold accounts are always
+    /// held, until the account confirms them after the delay, see [`TradingPlatform::confirm_withdrawal`].
+    ///
+    /// Accounts that keep a whitelist may only withdraw more than the [`TradingPlatform::whitelist_threshold`] to an
+    /// external `destination` active on it.
+    pub fn withdraw(
+        &mut self,
+        signer: &str,
+        amount: u64,
+        destination: Option<&str>,
+    ) -> Result<Tx, ApplicationError> {
+        // Play funds never leave the platform
+        if self.accounts.is_sandbox(signer) {
+            return Err(ApplicationError::SandboxViolation(signer.to_string()));
+        }
+        let now = now_millis();
+        self.check_destination(signer, destination, amount, now)?;
+        if self.cold_accounts.is_cold(signer, now) {
+            let release_at = now + self.cold_accounts.delay_millis;
+            return self.request_withdrawal(signer, amount, destination, Some(release_at));
+        }
+        match self.withdrawal_approval_threshold {
+            Some(threshold) if amount > threshold => {
+                self.request_withdrawal(signer, amount, destination, None)
+            }
+            _ => self.accounts.withdraw(signer, amount).inspect(|tx| {
+                self.record_tx(tx.clone());
+            }),
+        }
+    }
+
+    fn request_withdrawal(
+        &mut self,
+        signer: &str,
+        amount: u64,
+        destination: Option<&str>,
+        release_at: Option<u64>,
+    ) -> Result<Tx, ApplicationError> {
+        // Hold the funds so they can't be spent while waiting for approval
+        self.reserve_id(|ids, next| ids.withdrawal_id = next.next_id(ids.withdrawal_id))?;
+        self.accounts.withdraw(signer, amount)?;
+        self.last_withdrawal_id = self.ids.next_id(self.last_withdrawal_id);
+        let id = self.last_withdrawal_id;
+        self.withdrawals.insert(
+            id,
+            PendingWithdrawal {
+                id,
+                account: signer.to_string(),
+                amount,
+                status: WithdrawalStatus::Pending,
+                destination: destination.map(str::to_string),
+                release_at,
+            },
+        );
+        let tx = Tx::WithdrawalRequested {
+            id,
+            account: signer.to_string(),
+            amount,
+        };
+        self.record_tx(tx.clone());
+        Ok(tx)
+    }
+
+    /// Lists withdrawals waiting for approval
+    pub fn pending_withdrawals(&self) -> Vec<PendingWithdrawal> {
+        self.withdrawals
+            .values()
+            .filter(|w| w.status == WithdrawalStatus::Pending)
+            .cloned()
+            .collect()
+    }
+
+    /// The pending withdrawals of `signer`, oldest first
+    pub fn account_withdrawals(&self, signer: &str) -> Vec<PendingWithdrawal> {
+        self.withdrawals
+            .values()
+            .filter(|w| w.account == signer && w.status == WithdrawalStatus::Pending)
+            .cloned()
+            .collect()
+    }
+
+    /// Marks an existing account cold right away, or hot again once the cold withdrawal delay passed. Returns when
+    /// the account turns hot, `None` while it stays cold.
+    /// # Errors
+    /// The account doesn't exist
+    pub fn set_cold(
+        &mut self,
+        signer: &str,
+        cold: bool,
+        now: u64,
+    ) -> Result<Option<u64>, ApplicationError> {
+        self.accounts.balance_of(signer)?;
+        Ok(self.cold_accounts.set(signer, cold, now))
+    }
+
+    /// The account's confirmation of a withdrawal it requested while cold, the held funds leave the platform
+    ///
+    /// # Errors
+    /// - The withdrawal doesn't exist or isn't one of `signer`
+    /// - The withdrawal isn't pending anymore
+    /// - The delay hasn't passed yet
+    pub fn confirm_withdrawal(
+        &mut self,
+        id: u64,
+        signer: &str,
+        now: u64,
+    ) -> Result<Tx, ApplicationError> {
+        let withdrawal = self
+            .withdrawals
+            .get(&id)
+            .filter(|w| w.account == signer)
+            .ok_or(ApplicationError::WithdrawalNotFound(id))?;
+        if withdrawal.status != WithdrawalStatus::Pending {
+            return Err(ApplicationError::WithdrawalAlreadyResolved(id));
+        }
+        match withdrawal.release_at {
+            Some(release_at) if release_at > now => Err(ApplicationError::WithdrawalDelayed(
+                id,
+                (release_at - now).div_ceil(1000),
+            )),
+            Some(_) => self.close_withdrawal(id, true),
+            // Withdrawals above the approval threshold are up to an admin
+            None => Err(ApplicationError::Forbidden(signer.to_string())),
+        }
+    }
+
+    /// Approve or reject a pending withdrawal. Rejected withdrawals return the held funds to the account. Withdrawals
+    /// of cold accounts may only be rejected, the account confirms them.
+    ///
+    /// # Errors
+    /// - The withdrawal doesn't exist or isn't pending anymore
+    /// - The withdrawal waits for its cold account's confirmation
+    pub fn resolve_withdrawal(&mut self, id: u64, approve: bool) -> Result<Tx, ApplicationError> {
+        let withdrawal = self
+            .withdrawals
+            .get(&id)
+            .ok_or(ApplicationError::WithdrawalNotFound(id))?;
+        if withdrawal.status != WithdrawalStatus::Pending {
+            return Err(ApplicationError::WithdrawalAlreadyResolved(id));
+        }
+        if approve && withdrawal.release_at.is_some() {
+            return Err(ApplicationError::WithdrawalNeedsConfirmation(id));
+        }
+        self.close_withdrawal(id, approve)
+    }
+
+    /// Pays out or returns the funds of the pending withdrawal `id`
+    fn close_withdrawal(&mut self, id: u64, approve: bool) -> Result<Tx, ApplicationError> {
+        let Some(withdrawal) = self.withdrawals.get(&id) else {
+            return Err(ApplicationError::WithdrawalNotFound(id));
+        };
+        let account = withdrawal.account.clone();
+        let amount = withdrawal.amount;
+        let (status, tx) = if approve {
+            (
+                WithdrawalStatus::Approved,
+                Tx::WithdrawalApproved {
+                    id,
+                    account,
+                    amount,
+                },
+            )
+        } else {
+            self.accounts.deposit(&account, amount)?;
+            (
+                WithdrawalStatus::Rejected,
+                Tx::WithdrawalRejected {
+                    id,
+                    account,
+                    amount,
+                },
+            )
+        };
+        if let Some(withdrawal) = self.withdrawals.get_mut(&id) {
+            withdrawal.status = status;
+        }
+        self.record_tx(tx.clone());
+        Ok(tx)
+    }
+
+    /// Issue play funds to a sandbox account, opening it if necessary
+    pub fn faucet(&mut self, signer: &str, amount: u64) -> Result<Tx, ApplicationError> {
+        self.accounts.faucet(signer, amount).inspect(|tx| {
+            self.record_tx(tx.clone());
         })
     }
 
@@ -65,69 +860,4157 @@ impl TradingPlatform {
         recipient: &str,
         amount: u64,
     ) -> Result<(Tx, Tx), ApplicationError> {
-        self.accounts.send(sender, recipient, amount).map(|tx| {
-            self.transactions.push(tx.0.clone());
-            self.transactions.push(tx.1.clone());
-            tx
+        self.send_with(sender, recipient, amount, None)
+    }
+
+    /// Like [`TradingPlatform::send`], recording `memo` on both legs of the transfer
+    /// # Errors
+    /// The memo exceeds its size limits, either account doesn't exist, or the sender has insufficient funds
+    pub fn send_with(
+        &mut self,
+        sender: &str,
+        recipient: &str,
+        amount: u64,
+        memo: Option<Memo>,
+    ) -> Result<(Tx, Tx), ApplicationError> {
+        if let Some(memo) = &memo {
+            memo.validate()?;
+        }
+        self.accounts.ensure_same_funds(sender, recipient)?;
+        self.check_destination(sender, Some(recipient), amount, now_millis())?;
+        let (mut withdraw, mut deposit) = self.accounts.send(sender, recipient, amount)?;
+        withdraw.set_memo(memo.clone());
+        deposit.set_memo(memo);
+        self.record_tx(withdraw.clone());
+        self.record_tx(deposit.clone());
+        Ok((withdraw, deposit))
+    }
+
+    /// Pays every recipient of `request` from one account at once, all of them or none, see [`Accounts::payout`].
+    /// Each leg is recorded as a [`Tx::Payout`], the legs share a new payout id.
+    /// # Errors
+    /// - No or more than [`MAX_PAYOUT_RECIPIENTS`] recipients, a zero amount, or the payer among the recipients
+    /// - A recipient doesn't hold the same kind of funds as the payer, see [`Accounts::ensure_same_funds`]
+    /// - An account doesn't exist, the payer has insufficient funds, or a recipient would overflow
+    pub fn payout(&mut self, request: &PayoutRequest) -> Result<Vec<Tx>, ApplicationError> {
+        let invalid = |reason: &str| Err(ApplicationError::InvalidPayout(reason.to_string()));
+        if request.recipients.is_empty() {
+            return invalid("a payout needs at least one recipient");
+        }
+        if request.recipients.len() > MAX_PAYOUT_RECIPIENTS {
+            return Err(ApplicationError::InvalidPayout(format!(
+                "a payout has at most {} recipients",
+                MAX_PAYOUT_RECIPIENTS
+            )));
+        }
+        if request.recipients.iter().any(|leg| leg.amount == 0) {
+            return invalid("every recipient has to be paid more than zero");
+        }
+        if request.recipients.iter().any(|leg| leg.to == request.from) {
+            return invalid("the payer can't pay itself");
+        }
+        let now = now_millis();
+        // Split legs to one recipient count together against the whitelist threshold
+        let mut per_recipient: BTreeMap<&str, u64> = BTreeMap::new();
+        for leg in request.recipients.iter() {
+            self.accounts.ensure_same_funds(&request.from, &leg.to)?;
+            let total = per_recipient.entry(&leg.to).or_default();
+            *total = total.saturating_add(leg.amount);
+        }
+        for (recipient, amount) in per_recipient {
+            self.check_destination(&request.from, Some(recipient), amount, now)?;
+        }
+        self.reserve_id(|ids, next| ids.payout_id = next.next_id(ids.payout_id))?;
+        let id = self.ids.next_id(self.last_payout_id);
+        let legs = self
+            .accounts
+            .payout(id, &request.from, &request.recipients)?;
+        self.last_payout_id = id;
+        for tx in legs.iter() {
+            self.record_tx(tx.clone());
+        }
+        Ok(legs)
+    }
+
+    /// Save an existing account as a recipient of `owner` under `alias`
+    /// # Errors
+    /// Either account doesn't exist, or the alias is malformed
+    pub fn save_recipient(
+        &mut self,
+        owner: &str,
+        alias: &str,
+        signer: &str,
+    ) -> Result<SavedRecipient, ApplicationError> {
+        self.accounts.balance_of(owner)?;
+        self.accounts.balance_of(signer)?;
+        self.address_book.save(owner, alias, signer)
+    }
+
+    /// Whitelists `destination` for the withdrawals and transfers of an existing account, active once the delay passed
+    /// # Errors
+    /// The account doesn't exist
+    pub fn whitelist(
+        &mut self,
+        owner: &str,
+        destination: &str,
+        now: u64,
+    ) -> Result<WhitelistEntry, ApplicationError> {
+        self.accounts.balance_of(owner)?;
+        Ok(self.whitelists.add(owner, destination, now))
+    }
+
+    /// Checks that moving `amount` out of the `signer` account to `destination` is allowed by its whitelist, see
+    /// [`TradingPlatform::whitelist_threshold`]
+    fn check_destination(
+        &self,
+        signer: &str,
+        destination: Option<&str>,
+        amount: u64,
+        now: u64,
+    ) -> Result<(), ApplicationError> {
+        if amount <= self.whitelist_threshold {
+            return Ok(());
+        }
+        self.whitelists.check(signer, destination, now)
+    }
+
+    /// The recipient of a transfer, looked up in the sender's address book if it names an alias
+    /// # Errors
+    /// The request names both or neither a recipient and an alias, or the alias isn't saved
+    pub fn recipient_of(&self, request: &SendRequest) -> Result<String, ApplicationError> {
+        match (&request.to_alias, request.to.is_empty()) {
+            (Some(alias), true) => self
+                .address_book
+                .resolve(&request.from, alias)
+                .map(str::to_string),
+            (None, false) => Ok(request.to.clone()),
+            _ => Err(ApplicationError::InvalidAlias(
+                "name either a recipient or an alias".to_string(),
+            )),
+        }
+    }
+
+    /// Reserve funds for a later [`TradingPlatform::capture`] or [`TradingPlatform::release`]
+    pub fn hold(&mut self, signer: &str, amount: u64) -> Result<Tx, ApplicationError> {
+        self.reserve_id(|ids, next| ids.hold_id = next.next_id(ids.hold_id))?;
+        self.accounts.hold(signer, amount).inspect(|tx| {
+            self.record_tx(tx.clone());
         })
     }
 
-    /// Process a given order and apply the outcome to the accounts involved. Note that there are very few safeguards in place.
+    /// Transfer the held funds to the recipient. Like a send, the holder's whitelist has to allow the recipient.
+    pub fn capture(&mut self, id: u64, recipient: &str) -> Result<Tx, ApplicationError> {
+        let hold = self.accounts.hold_of(id)?;
+        let (holder, amount) = (hold.account.clone(), hold.amount);
+        self.accounts.ensure_same_funds(&holder, recipient)?;
+        self.check_destination(&holder, Some(recipient), amount, now_millis())?;
+        self.accounts.capture(id, recipient).inspect(|tx| {
+            self.record_tx(tx.clone());
+        })
+    }
+
+    /// Return the held funds to their account
+    pub fn release(&mut self, id: u64) -> Result<Tx, ApplicationError> {
+        self.accounts.release(id).inspect(|tx| {
+            self.record_tx(tx.clone());
+        })
+    }
+
+    /// Set or change the PIN of an existing account
+    /// # Errors
+    /// The account doesn't exist, the PIN is too short or too long, or the current PIN doesn't check out
+    pub fn set_pin(
+        &mut self,
+        signer: &str,
+        pin: &str,
+        current: Option<&str>,
+        now: u64,
+    ) -> Result<(), ApplicationError> {
+        self.accounts.balance_of(signer)?;
+        self.pins.set(signer, pin, current, now)
+    }
+
+    /// Checks the PIN for moving `amount` out of the `signer` account, see [`TradingPlatform::pin_threshold`]
+    /// # Errors
+    /// The PIN is needed but missing, wrong, or locked
+    pub fn verify_pin(
+        &mut self,
+        signer: &str,
+        amount: u64,
+        pin: Option<&str>,
+        now: u64,
+    ) -> Result<(), ApplicationError> {
+        if amount <= self.pin_threshold {
+            return Ok(());
+        }
+        self.pins.verify(signer, pin, now)
+    }
+
+    /// Register a public key of an existing account. From then on the account's orders have to be signed.
+    /// # Errors
+    /// The account doesn't exist or the key isn't a hex-encoded ed25519 public key
+    pub fn register_public_key(
+        &mut self,
+        signer: &str,
+        public_key: &str,
+        now: u64,
+    ) -> Result<RegisteredPublicKey, ApplicationError> {
+        self.accounts.balance_of(signer)?;
+        let before = self.signing_keys.list(signer);
+        let registered = self.signing_keys.register(signer, public_key, now)?;
+        self.auditor.record(
+            signer,
+            AuditAction::PublicKeyRegistered,
+            before,
+            self.signing_keys.list(signer),
+        );
+        Ok(registered)
+    }
+
+    /// Issue a new API key for an existing account
+    pub fn create_api_key(
+        &mut self,
+        signer: &str,
+        role: Role,
+        scopes: &[ApiKeyScope],
+    ) -> Result<NewApiKey, ApplicationError> {
+        self.accounts.balance_of(signer)?;
+        self.api_keys.create(signer, role, scopes)
+    }
+
+    /// Issue an API key without requiring an account, e.g. for operators
+    pub fn issue_api_key(
+        &mut self,
+        signer: &str,
+        role: Role,
+        scopes: &[ApiKeyScope],
+    ) -> Result<NewApiKey, ApplicationError> {
+        self.api_keys.create(signer, role, scopes)
+    }
+
+    /// List the API keys of an existing account
+    pub fn api_keys_of(&self, signer: &str) -> Result<Vec<ApiKey>, ApplicationError> {
+        self.accounts.balance_of(signer)?;
+        Ok(self.api_keys.list(signer))
+    }
+
+    /// Revoke one of an account's API keys
+    pub fn revoke_api_key(&mut self, signer: &str, id: &str) -> Result<ApiKey, ApplicationError> {
+        self.api_keys.revoke(signer, id)
+    }
+
+    /// Deletes an account: cancels its orders in both books, withdraws the remaining balance or moves it to the
+    /// [`DUST_ACCOUNT`] if it's dust, and replaces `signer`
+    /// with a random token throughout the stored history. The ledger still balances, the account's transactions and
+    /// trades just name the token instead. API keys are revoked, recurring buys paused, and the stop-loss dropped.
     ///
     /// # Errors
-    /// - Account has insufficient funds
-    pub fn order(&mut self, order: Order) -> Result<Receipt, ApplicationError> {
-        let total_amount = order.amount * order.price;
-        // Make sure the account has a deposit
-        match self.balance_of(&order.signer) {
-            Ok(balance) if &order.side == &Side::Buy && balance < &total_amount => {
-                return Err(ApplicationError::AccountUnderFunded(
-                    order.signer.clone(),
-                    total_amount,
-                ))
-            }
-            Ok(_) => {}
-            Err(e) => return Err(e),
+    /// - The account doesn't exist
+    /// - The account has open holds or pending withdrawals, or is cold: deleting it would sweep the balance out
+    ///   without the delay
+    /// - The account's whitelist doesn't allow withdrawing the balance, see [`TradingPlatform::whitelist_threshold`]
+    pub fn delete_account(&mut self, signer: &str) -> Result<DeletedAccount, ApplicationError> {
+        let balance = *self.accounts.balance_of(signer)?;
+        // The balance leaves without a destination the whitelist could name
+        self.check_destination(signer, None, balance, now_millis())?;
+        let pending = self.withdrawals.values().any(|withdrawal| {
+            withdrawal.account == signer && withdrawal.status == WithdrawalStatus::Pending
+        });
+        if pending
+            || self.cold_accounts.is_cold(signer, now_millis())
+            || self
+                .accounts
+                .holds()
+                .iter()
+                .any(|hold| hold.account == signer)
+        {
+            return Err(ApplicationError::AccountInUse(signer.to_string()));
         }
-        let signer = order.signer.clone();
-        let side = order.side.clone();
-        // Do the actual matching
-        let receipt = self.matching_engine.process(order)?;
 
-        receipt
-            .matches
-            .iter()
-            .map(|m| match side {
-                Side::Buy => self.send(&signer, &m.signer, m.amount * m.price),
-                Side::Sell => self.send(&m.signer, &signer, m.amount * m.price),
-            })
-            .collect::<Result<Vec<_>, ApplicationError>>()?;
-        Ok(receipt)
+        let now = now_millis();
+        self.cancel_all(signer, now);
+        let balance = *self.accounts.balance_of(signer)?;
+        let dust = match !self.accounts.is_sandbox(signer) && self.dust.is_dust(balance) {
+            true => self.sweep_dust_of(signer, DustSource::Closure, now)?,
+            false => 0,
+        };
+        let sweep = self.accounts.close(signer)?;
+        self.record_tx(sweep.clone());
+        let swept = match sweep {
+            Tx::Withdraw { amount, .. } => amount,
+            _ => 0,
+        };
+
+        let token = format!(
+            "{}{}",
+            ANONYMIZED_PREFIX,
+            random_hex(ANONYMIZED_TOKEN_BYTES)
+        );
+        for tx in self.transactions.iter_mut() {
+            tx.anonymize(signer, &token);
+        }
+        self.balance_log.anonymize(signer, &token);
+        for trade in self.trades.iter_mut().chain(self.sandbox_trades.iter_mut()) {
+            trade.anonymize(signer, &token);
+        }
+        self.book_log.anonymize(signer, &token);
+        self.matching_engine.anonymize(signer, &token);
+        self.sandbox_book.anonymize(signer, &token);
+        for (notification, tx) in self.gateway_deposits.values_mut() {
+            anonymize(&mut notification.account, signer, &token);
+            tx.anonymize(signer, &token);
+        }
+        for withdrawal in self.withdrawals.values_mut() {
+            anonymize(&mut withdrawal.account, signer, &token);
+        }
+        self.api_keys.anonymize(signer, &token);
+        self.pins.remove(signer);
+        self.cold_accounts.remove(signer);
+        self.signing_keys.remove(signer);
+        self.address_book.remove(signer);
+        self.whitelists.remove(signer);
+        self.recurring_buys.anonymize(signer, &token);
+        self.positions.remove(signer);
+        self.stop_losses.remove(signer);
+        self.trade_stats.anonymize(signer, &token);
+        self.fees.anonymize(signer, &token);
+        self.dust.anonymize(signer, &token);
+        self.tx_tags.remove(signer);
+        self.invoices.anonymize(signer, &token);
+        self.recent_orders.anonymize(signer, &token);
+        self.throttle.remove(signer);
+        self.quotes.remove(signer);
+        self.archives.discard(signer);
+        Ok(DeletedAccount { token, swept, dust })
     }
-}
 
-#[cfg(test)]
-mod tests {
-    // reduce the warnings for naming tests
-    #![allow(non_snake_case)]
+    /// Opts an existing account in or out of having its dust swept
+    /// # Errors
+    /// The account doesn't exist
+    pub fn set_dust_consent(
+        &mut self,
+        signer: &str,
+        consent: bool,
+    ) -> Result<(), ApplicationError> {
+        self.accounts.balance_of(signer)?;
+        self.dust.set_consent(signer, consent);
+        Ok(())
+    }
 
-    use super::*;
+    /// The dust threshold and every sweep so far
+    pub fn dust_report(&self) -> DustReport {
+        self.dust.report()
+    }
 
-    #[test]
-    fn test_TradingPlatform_order_requires_deposit_to_order() {
+    /// Replaces the tags `signer` put on its transaction `id` and returns them normalized, see [`TxTags::normalize`]
+    /// # Errors
+    /// The account doesn't exist, a tag is invalid, or there's no transaction with `id` that `signer` is a party of
+    pub fn tag_transaction(
+        &mut self,
+        signer: &str,
+        id: u64,
+        tags: &[String],
+    ) -> Result<Vec<String>, ApplicationError> {
+        self.accounts.balance_of(signer)?;
+        let tags = TxTags::normalize(tags)?;
+        let party = self
+            .balance_log
+            .get(id)
+            .is_some_and(|tx| tx.parties().contains(&signer));
+        if !party {
+            return Err(ApplicationError::TransactionNotFound(id));
+        }
+        self.tx_tags.set(signer, id, tags);
+        Ok(self.tx_tags.of(signer, id))
+    }
+
+    /// The transactions `signer` is a party of with its tags, oldest first. Only the ones it tagged with `tag` if set.
+    /// # Errors
+    /// The account doesn't exist
+    pub fn statement(
+        &self,
+        signer: &str,
+        tag: Option<&str>,
+    ) -> Result<Vec<ArchivedTx>, ApplicationError> {
+        self.accounts.balance_of(signer)?;
+        let tag = tag.map(|tag| tag.trim().to_lowercase());
+        Ok(self
+            .balance_log
+            .entries()
+            .enumerate()
+            .filter(|(_, (_, _, tx))| tx.parties().contains(&signer))
+            .map(|(position, (ordinal, timestamp, tx))| {
+                let id = position as u64 + 1;
+                ArchivedTx {
+                    id,
+                    ordinal,
+                    timestamp,
+                    tx: tx.clone(),
+                    tags: self.tx_tags.of(signer, id),
+                }
+            })
+            .filter(|archived| tag.as_ref().is_none_or(|tag| archived.tags.contains(tag)))
+            .collect())
+    }
+
+    /// Moves the dust of the accounts that consented to the [`DUST_ACCOUNT`]. Accounts with open buy orders keep
+    /// theirs, the orders may still need it. Returns the total swept.
+    /// # Errors
+    /// The dust account would overflow
+    pub fn sweep_dust(&mut self, now: u64) -> Result<u64, ApplicationError> {
+        let mut total = 0;
+        for signer in self.dust.consenting() {
+            let balance = match self.accounts.balance_of(&signer) {
+                Ok(balance) => *balance,
+                Err(_) => continue,
+            };
+            let buying = self
+                .books
+                .values()
+                .chain([&self.matching_engine])
+                .any(|book| book.open_notional(&signer, &Side::Buy) > 0);
+            if buying || self.accounts.is_sandbox(&signer) || !self.dust.is_dust(balance) {
+                continue;
+            }
+            total += self.sweep_dust_of(&signer, DustSource::Sweep, now)?;
+        }
+        Ok(total)
+    }
+
+    /// Moves the balance of `signer` to the [`DUST_ACCOUNT`] and records the sweep. Returns the amount moved.
+    fn sweep_dust_of(
+        &mut self,
+        signer: &str,
+        source: DustSource,
+        now: u64,
+    ) -> Result<u64, ApplicationError> {
+        let tx = self.accounts.sweep(signer, DUST_ACCOUNT)?;
+        let amount = tx.amount();
+        self.record_tx(tx);
+        self.dust.record(DustSweep {
+            timestamp: now,
+            account: signer.to_string(),
+            amount,
+            source,
+        });
+        Ok(amount)
+    }
+
+    /// Buy as many units as `budget` affords from the current asks, taker fee included. The buy is placed as an
+    /// immediate-or-cancel limit order at the highest price needed, so nothing is left in the book. Asks at a price of
+    /// zero are skipped.
+    ///
+    /// # Errors
+    /// - There are no asks from other accounts
+    /// - The highest price needed is outside the price collar
+    /// - Account has insufficient funds
+    pub fn market_buy(&mut self, signer: &str, budget: u64) -> Result<Receipt, ApplicationError> {
+        let mut amount = 0;
+        let mut price = 0;
+        let (book, taker_fee_bps) = match self.accounts.is_sandbox(signer) {
+            // Play funds settle without fees
+            true => (&self.sandbox_book, 0),
+            false => (&self.matching_engine, self.taker_fee_bps),
+        };
+        for (level, orders) in book.asks.iter() {
+            if *level == 0 {
+                continue;
+            }
+            // Every unit has to be affordable at the highest price in the order
+            let affordable = self.affordable_units(budget, *level, taker_fee_bps);
+            if affordable <= amount {
+                break;
+            }
+            let available: u64 = orders
+                .iter()
+                .filter(|o| o.signer != signer)
+                .map(|o| o.remaining)
+                .sum();
+            if available == 0 {
+                continue;
+            }
+            amount = (amount + available).min(affordable);
+            price = *level;
+        }
+        if amount == 0 {
+            return Err(ApplicationError::NoLiquidity(signer.to_string()));
+        }
+        self.order(Order {
+            price,
+            amount,
+            side: Side::Buy,
+            signer: signer.to_string(),
+            client_order_id: None,
+            signature: None,
+            order_type: OrderType::Limit,
+            time_in_force: TimeInForce::Ioc,
+            trigger_price: None,
+            market: DEFAULT_MARKET.to_string(),
+        })
+    }
+
+    /// The most units at `price` whose notional and the taker fee of `taker_fee_bps` on it fit the `budget`
+    fn affordable_units(&self, budget: u64, price: u64, taker_fee_bps: u64) -> u64 {
+        let cost = |units: u64| {
+            let notional = units.saturating_mul(price);
+            let fee = self.settlement.fee(notional, taker_fee_bps, DEFAULT_MARKET);
+            notional.saturating_add(fee)
+        };
+        // Start from the estimate without rounding, rounding the fee can only lower it
+        let estimate = budget as u128 * 10_000 / (price as u128 * (10_000 + taker_fee_bps as u128));
+        let mut units = estimate.min(u64::MAX as u128) as u64;
+        while units > 0 && cost(units) > budget {
+            units -= 1;
+        }
+        units
+    }
+
+    /// Schedule a recurring market buy for an existing account
+    pub fn create_recurring_buy(
+        &mut self,
+        signer: &str,
+        request: RecurringBuyRequest,
+        now: u64,
+    ) -> Result<RecurringBuy, ApplicationError> {
+        self.accounts.balance_of(signer)?;
+        self.reserve_id(|ids, _| ids.recurring_buy_id += 1)?;
+        self.recurring_buys.create(signer, request, now)
+    }
+
+    /// Execute the recurring buys due at `now` and return how many ran
+    pub fn run_recurring_buys(&mut self, now: u64) -> usize {
+        let due = self.recurring_buys.due(now);
+        for plan in due.iter() {
+            let result = self.market_buy(&plan.account, plan.amount);
+            self.recurring_buys.record(plan.id, now, result);
+        }
+        due.len()
+    }
+
+    /// The position of an existing account
+    pub fn position_of(&self, signer: &str) -> Result<Position, ApplicationError> {
+        self.accounts.balance_of(signer)?;
+        Ok(self.positions.of(signer))
+    }
+
+    /// The funds an existing account has in holds, pending withdrawals, and open orders, and what's left of its
+    /// balance to trade with
+    pub fn exposure_of(&self, signer: &str) -> Result<Exposure, ApplicationError> {
+        let balance = *self.accounts.balance_of(signer)?;
+        let held = self
+            .accounts
+            .holds()
+            .iter()
+            .filter(|hold| hold.account == signer)
+            .map(|hold| hold.amount)
+            .sum();
+        let reserved = self
+            .withdrawals
+            .values()
+            .filter(|w| w.account == signer && w.status == WithdrawalStatus::Pending)
+            .map(|w| w.amount)
+            .sum();
+        let book = match self.accounts.is_sandbox(signer) {
+            true => &self.sandbox_book,
+            false => &self.matching_engine,
+        };
+        let market = MarketExposure {
+            market: DEFAULT_MARKET.to_string(),
+            open_buy_notional: book.open_notional(signer, &Side::Buy),
+            open_sell_notional: book.open_notional(signer, &Side::Sell),
+            position: self.positions.of(signer),
+        };
+        let committed = market.open_buy_notional
+            + self
+                .settlement
+                .fee(market.open_buy_notional, self.max_fee_bps(), DEFAULT_MARKET);
+        Ok(Exposure {
+            signer: signer.to_string(),
+            balance,
+            held,
+            reserved,
+            trading_power: balance.saturating_sub(committed),
+            markets: vec![market],
+        })
+    }
+
+    /// The reference price of the public book at `now`
+    pub fn reference_price(&self, now: u64) -> Option<ReferencePrice> {
+        self.reference_prices.price_of(DEFAULT_MARKET, now)
+    }
+
+    /// Sets the oracle price of a market, its reference price while it doesn't trade
+    /// # Errors
+    /// There's no market with that symbol
+    pub fn set_oracle_price(&mut self, symbol: &str, price: u64) -> Result<(), ApplicationError> {
+        self.markets.get(symbol)?;
+        self.reference_prices.set_oracle(symbol, price);
+        Ok(())
+    }
+
+    /// Lists a market or replaces its configuration while the platform runs. Orders already resting in its book stay,
+    /// new ones are checked against the new configuration.
+    /// # Errors
+    /// The symbol is empty or the configuration is invalid
+    pub fn list_market(
+        &mut self,
+        symbol: &str,
+        config: MarketConfig,
+    ) -> Result<MarketInfo, ApplicationError> {
+        if symbol.is_empty() {
+            return Err(ApplicationError::InvalidConfig(
+                "a market needs a symbol".to_string(),
+            ));
+        }
+        config.validate(symbol)?;
+        self.markets.insert(symbol, config);
+        self.preallocate_books();
+        self.market_info(symbol)
+    }
+
+    /// A page of the trade tape of every market, oldest first. Busted trades stay on it.
+    pub fn trade_tape(&self, query: &TradesQuery) -> TradesPage {
+        // Trade ids increase with every trade
+        let start = query
+            .after
+            .map_or(0, |after| self.trades.partition_point(|t| t.id <= after));
+        let mut matching = self.trades[start..].iter().filter(|trade| {
+            query
+                .since_ordinal
+                .is_none_or(|since| trade.ordinal > since)
+        });
+        let trades: Vec<TapeTrade> = matching
+            .by_ref()
+            .take(query.limit())
+            .map(TapeTrade::from)
+            .collect();
+        let next = match matching.next() {
+            Some(_) => trades.last().map(|trade| trade.id),
+            None => None,
+        };
+        TradesPage { trades, next }
+    }
+
+    /// The last, reference, and best prices of a market at `now`, with the spread between the best prices
+    /// # Errors
+    /// There's no market with that symbol
+    pub fn ticker(&self, symbol: &str, now: u64) -> Result<Ticker, ApplicationError> {
+        self.markets.get(symbol)?;
+        let (best_bid, best_ask) = self
+            .book(symbol)
+            .map(|book| book.best_bid_offer())
+            .unwrap_or_default();
+        Ok(Ticker {
+            symbol: symbol.to_string(),
+            last_price: self.reference_prices.last_price(symbol),
+            reference: self.reference_prices.price_of(symbol, now),
+            best_bid: best_bid.as_ref().map(|level| level.price),
+            best_ask: best_ask.as_ref().map(|level| level.price),
+            spread: best_bid
+                .zip(best_ask)
+                .map(|(bid, ask)| ask.price.saturating_sub(bid.price)),
+        })
+    }
+
+    /// Realized and unrealized profit or loss of `signer` at the reference price at `now`
+    pub fn pnl_of(&self, signer: &str, now: u64) -> i64 {
+        self.positions.of(signer).pnl(self.reference_mark(now))
+    }
+
+    /// The price positions are valued at, zero before the first trade or oracle price
+    fn reference_mark(&self, now: u64) -> u64 {
+        self.reference_price(now)
+            .map(|reference| reference.price)
+            .unwrap_or_default()
+    }
+
+    /// Trading statistics of an existing account for trades at or after `since`
+    pub fn stats_of(&self, signer: &str, since: u64) -> Result<AccountStats, ApplicationError> {
+        self.accounts.balance_of(signer)?;
+        Ok(self.trade_stats.stats_of(signer, since))
+    }
+
+    /// Add or remove an existing account from the leaderboard
+    pub fn set_leaderboard_opt_in(
+        &mut self,
+        signer: &str,
+        opt_in: bool,
+    ) -> Result<(), ApplicationError> {
+        self.accounts.balance_of(signer)?;
+        self.trade_stats.set_leaderboard_opt_in(signer, opt_in);
+        Ok(())
+    }
+
+    /// Create or replace the fee invoices of all accounts for `month`
+    pub fn generate_invoices(&mut self, month: &str) -> Result<Vec<Invoice>, ApplicationError> {
+        self.invoices.generate(month, &self.fees)
+    }
+
+    /// Create or replace the market report of `day`
+    pub fn generate_report(&mut self, day: &str) -> Result<DailyReport, ApplicationError> {
+        self.reports.generate(day, &self.trades, &self.fees)
+    }
+
+    /// Set the maximum loss per session for an existing account
+    pub fn set_stop_loss(
+        &mut self,
+        signer: &str,
+        max_daily_loss: u64,
+        now: u64,
+    ) -> Result<StopLossStatus, ApplicationError> {
+        self.accounts.balance_of(signer)?;
+        let pnl = self.pnl_of(signer, now);
+        self.stop_losses.set(signer, max_daily_loss, pnl, now);
+        self.stop_loss_of(signer, now)
+    }
+
+    /// The stop-loss of `signer` in the session at `now`
+    pub fn stop_loss_of(&self, signer: &str, now: u64) -> Result<StopLossStatus, ApplicationError> {
+        self.stop_losses
+            .status(signer, self.pnl_of(signer, now), now)
+            .ok_or(ApplicationError::StopLossNotSet(signer.to_string()))
+    }
+
+    /// Cancel the open orders of every account whose stop-loss was breached, returns the accounts
+    pub fn enforce_stop_losses(&mut self, now: u64) -> Vec<String> {
+        let mark = self.reference_mark(now);
+        let positions = &self.positions;
+        let breached = self
+            .stop_losses
+            .check(now, |signer| positions.of(signer).pnl(mark));
+        for signer in breached.iter() {
+            self.cancel_all(signer, now);
+        }
+        breached
+    }
+
+    /// Bust the erroneous trade `id`: the seller pays the price back to the buyer, the positions of both accounts are
+    /// rebuilt without a public trade, and the trade stays on the tape marked as busted. A busted public trade is published
+    /// on the trade feed again. Fees aren't refunded and the book isn't touched.
+    ///
+    /// # Errors
+    /// - No trade with that id
+    /// - The trade was busted already
+    /// - The seller can't pay the price back anymore
+    pub fn bust_trade(&mut self, id: u64, now: u64) -> Result<Trade, ApplicationError> {
+        let public = self.trades.iter().any(|trade| trade.id == id);
+        let tape = match public {
+            true => &mut self.trades,
+            false => &mut self.sandbox_trades,
+        };
+        let trade = tape
+            .iter_mut()
+            .find(|trade| trade.id == id)
+            .ok_or(ApplicationError::TradeNotFound(id))?;
+        if trade.busted.is_some() {
+            return Err(ApplicationError::TradeAlreadyBusted(id));
+        }
+        let (buyer, seller) = match trade.taker_side {
+            Side::Buy => (&trade.taker, &trade.maker),
+            Side::Sell => (&trade.maker, &trade.taker),
+        };
+        let tx = self
+            .accounts
+            .reverse_settlement(id, seller, buyer, trade.amount * trade.price)?;
+        trade.busted = Some(now);
+        let trade = trade.clone();
+        self.record_tx(tx);
+
+        if public {
+            // Positions are kept in the default market of public trades only
+            for account in [&trade.taker, &trade.maker] {
+                let mut trades: Vec<_> = self
+                    .trades
+                    .iter()
+                    .filter(|trade| trade.market == DEFAULT_MARKET)
+                    .filter(|trade| &trade.taker == account || &trade.maker == account)
+                    .collect();
+                trades.sort_by_key(|trade| trade.id);
+                self.positions.rebuild(account, trades);
+            }
+            // Nobody listening is fine
+            let _ = self.trade_feed.send(trade.clone());
+        }
+        Ok(trade)
+    }
+
+    /// Cancel the open order `id` of `signer`, in the sandbox book for sandbox accounts. The cancellation counts towards
+    /// the signer's cancel ratio.
+    ///
+    /// # Errors
+    /// - No open order with that id
+    /// - The order belongs to another account
+    pub fn cancel_order(
+        &mut self,
+        id: OrderId,
+        signer: &str,
+        now: u64,
+    ) -> Result<PartialOrder, ApplicationError> {
+        if self.accounts.is_sandbox(signer) {
+            return self.sandbox_book.cancel(id, signer);
+        }
+        if let Some(book) = self
+            .books
+            .values_mut()
+            .find(|book| book.order(id).is_some() || book.stops().contains_key(&id))
+        {
+            let cancelled = book.cancel(id, signer)?;
+            self.throttle.record_cancels(signer, 1, now);
+            return Ok(cancelled);
+        }
+        let stop = self.matching_engine.stops().contains_key(&id);
+        let cancelled = self.matching_engine.cancel(id, signer)?;
+        self.book_log.record(
+            BookEvent::Cancel {
+                timestamp: now,
+                order_id: id,
+                signer: signer.to_string(),
+            },
+            &self.matching_engine,
+        );
+        // A waiting stop isn't in the book
+        if !stop {
+            self.book_updates.publish(
+                &self.matching_engine,
+                vec![TouchedLevel {
+                    side: cancelled.side.clone(),
+                    price: cancelled.price,
+                    before: Some(
+                        self.matching_engine
+                            .level_quantity(&cancelled.side, cancelled.price)
+                            + cancelled.remaining,
+                    ),
+                }],
+            );
+        }
+        self.throttle.record_cancels(signer, 1, now);
+        Ok(cancelled)
+    }
+
+    /// Change the price and open amount of the resting order `id` of `signer`, in the sandbox book for sandbox accounts.
+    /// The amended order passes the checks of a new order, see [`MatchingEngine::amend`] for its time priority. Its
+    /// matches at a new price are settled like an order's. Once the signer registered a public key the amendment must be
+    /// signed like an order, accepted signed amendments are recorded in the audit trail.
+    ///
+    /// # Errors
+    /// - The signer exceeds its order rate
+    /// - The amendment isn't signed with one of the signer's keys, see [`SigningKeys::verify_amend`]
+    /// - No resting order with that id in the books of the listed markets or the sandbox book
+    /// - The order belongs to another account
+    /// - The new amount is zero
+    /// - Any error of [`TradingPlatform::order`] for an order at the new price and amount
+    pub fn amend_order(
+        &mut self,
+        id: OrderId,
+        request: AmendRequest,
+    ) -> Result<Receipt, ApplicationError> {
+        let now = now_millis();
+        self.throttle.admit(&request.signer, now)?;
+        let tenant = self.auditor.tenant.clone();
+        let signed = self
+            .signing_keys
+            .verify_amend(id, &request, &tenant, now)?
+            .zip(request.signature.as_ref())
+            .map(|(key, signature)| {
+                let payload =
+                    request.signing_payload(id, &tenant, signature.nonce, signature.expires_at);
+                serde_json::json!({
+                    "public_key": key.public_key,
+                    "signature": signature.value,
+                    "nonce": signature.nonce,
+                    "expires_at": signature.expires_at,
+                    "payload": String::from_utf8_lossy(&payload),
+                    "order_id": id,
+                })
+            });
+        let AmendRequest {
+            signer,
+            price,
+            amount,
+            ..
+        } = request;
+        let sandbox = self.accounts.is_sandbox(&signer);
+        let resting = match sandbox {
+            true => self.sandbox_book.order(id),
+            false => std::iter::once(&self.matching_engine)
+                .chain(self.books.values())
+                .find_map(|book| book.order(id)),
+        };
+        let resting = match resting {
+            None => return Err(ApplicationError::OrderNotFound(id)),
+            Some(order) if order.signer != signer => {
+                return Err(ApplicationError::OrderNotOwned(id))
+            }
+            Some(order) => order.clone(),
+        };
+        let order = Order {
+            price,
+            amount,
+            side: resting.side.clone(),
+            signer: signer.clone(),
+            client_order_id: None,
+            signature: None,
+            order_type: OrderType::Limit,
+            time_in_force: TimeInForce::Gtc,
+            trigger_price: None,
+            market: resting.market.clone(),
+        };
+        if sandbox {
+            let total_amount = notional(amount, price)?;
+            if order.side == Side::Buy && self.accounts.balance_of(&signer)? < &total_amount {
+                return Err(ApplicationError::AccountUnderFunded(signer, total_amount));
+            }
+            let resting_count = match order.side {
+                Side::Buy => self.sandbox_book.order_count(&Side::Sell),
+                Side::Sell => self.sandbox_book.order_count(&Side::Buy),
+            };
+            let max_trades = amount.min(resting_count as u64);
+            self.reserve_id(|ids, next| {
+                ids.ordinal = next.next_id(ids.ordinal);
+                ids.trade_id = next.nth_after(ids.trade_id, max_trades);
+            })?;
+            let reserved = match order.side {
+                Side::Buy => total_amount,
+                Side::Sell => 0,
+            };
+            self.check_sandbox_fills(&order)?;
+            let guard = self.stop_funds(self.sandbox_book.stops(), (&signer, reserved), 0);
+            self.sandbox_book.set_stop_guard(guard);
+            self.sandbox_book.ordinal = self.matching_engine.ordinal;
+            let receipt = self.sandbox_book.amend(id, price, amount)?;
+            self.matching_engine.ordinal = self.sandbox_book.ordinal;
+            let triggered = self.sandbox_book.triggered().to_vec();
+            self.settle_sandbox_matches(&signer, &order.side, &receipt, now)?;
+            self.settle_triggered(&triggered, true, now);
+            self.audit_signed_amend(&signer, signed);
+            return Ok(receipt);
+        }
+        if order.market != DEFAULT_MARKET {
+            let receipt = self.amend_listed_order(id, order, now)?;
+            self.audit_signed_amend(&signer, signed);
+            return Ok(receipt);
+        }
+
+        let reserved = self.check_order(&order, now, false)?;
+        self.check_fills(Some(&self.matching_engine), &order)?;
+        let guard = self.stop_funds(
+            self.matching_engine.stops(),
+            (&signer, reserved),
+            self.max_fee_bps(),
+        );
+        self.matching_engine.set_stop_guard(guard);
+        let own_levels = [resting.price, price].map(|price| TouchedLevel {
+            side: resting.side.clone(),
+            price,
+            before: Some(self.matching_engine.level_quantity(&resting.side, price)),
+        });
+        let stop_levels = self.stop_levels();
+        let max_trades = self.max_trades(&order);
+        self.reserve_id(|ids, next| {
+            ids.ordinal = next.next_id(ids.ordinal);
+            ids.trade_id = next.nth_after(ids.trade_id, max_trades);
+        })?;
+        let mut receipt = self.matching_engine.amend(id, price, amount)?;
+        let triggered = self.matching_engine.triggered().to_vec();
+        self.book_log.record(
+            BookEvent::Amend {
+                timestamp: now,
+                order_id: id,
+                price,
+                amount,
+            },
+            &self.matching_engine,
+        );
+        let touched = receipt
+            .matches
+            .iter()
+            .map(|m| TouchedLevel {
+                side: m.side.clone(),
+                price: m.price,
+                before: None,
+            })
+            .chain(own_levels)
+            .chain(stop_levels)
+            .chain(TradingPlatform::matched_by(&triggered))
+            .chain(self.unlinked_levels())
+            .collect();
+        self.book_updates.publish(&self.matching_engine, touched);
+        receipt.settlement = self.settle_matches(&signer, &order.side, &receipt, now)?;
+        self.settle_triggered(&triggered, false, now);
+        self.audit_signed_amend(&signer, signed);
+        Ok(receipt)
+    }
+
+    /// Amends the resting order `id` in the book of a listed market other than the [`DEFAULT_MARKET`] to the price and
+    /// amount of `order`, see [`TradingPlatform::amend_order`]. Like for its orders, the book isn't in the book event
+    /// log or the deltas.
+    fn amend_listed_order(
+        &mut self,
+        id: OrderId,
+        order: Order,
+        now: u64,
+    ) -> Result<Receipt, ApplicationError> {
+        let reserved = self.check_order(&order, now, false)?;
+        self.check_fills(self.books.get(&order.market), &order)?;
+        let (signer, side) = (order.signer.clone(), order.side.clone());
+        let guard = self.books.get(&order.market).and_then(|book| {
+            self.stop_funds(book.stops(), (&signer, reserved), self.max_fee_bps())
+        });
+        let max_trades = self.max_trades(&order);
+        self.reserve_id(|ids, next| {
+            ids.ordinal = next.next_id(ids.ordinal);
+            ids.trade_id = next.nth_after(ids.trade_id, max_trades);
+        })?;
+        let book = self
+            .books
+            .get_mut(&order.market)
+            .ok_or(ApplicationError::OrderNotFound(id))?;
+        book.ordinal = self.matching_engine.ordinal;
+        book.set_stop_guard(guard);
+        let mut receipt = book.amend(id, order.price, order.amount)?;
+        self.matching_engine.ordinal = book.ordinal;
+        let triggered = book.triggered().to_vec();
+        receipt.settlement = self.settle_matches(&signer, &side, &receipt, now)?;
+        self.settle_triggered(&triggered, false, now);
+        Ok(receipt)
+    }
+
+    /// Records an accepted signed amendment, see [`TradingPlatform::amend_order`]
+    fn audit_signed_amend(&mut self, signer: &str, signed: Option<serde_json::Value>) {
+        if let Some(signed) = signed {
+            self.auditor
+                .record(signer, AuditAction::SignedAmend, (), signed);
+        }
+    }
+
+    /// What happened to the order `id` of `signer` in the public book, see [`lifecycle`]
+    ///
+    /// # Errors
+    /// - No order with that id was placed in the public book
+    /// - The order belongs to another account
+    pub fn order_lifecycle(
+        &self,
+        id: OrderId,
+        signer: &str,
+    ) -> Result<OrderLifecycle, ApplicationError> {
+        let lifecycle = lifecycle(&self.book_log, &self.trades, id)?;
+        if lifecycle.order.signer != signer {
+            return Err(ApplicationError::OrderNotOwned(id));
+        }
+        Ok(lifecycle)
+    }
+
+    /// Where the resting order `id` of `signer` stands in its price level, see [`MatchingEngine::queue_position`]. Orders
+    /// of sandbox accounts are looked up in the sandbox book.
+    ///
+    /// # Errors
+    /// - No order with that id rests in the book
+    /// - The order belongs to another account
+    pub fn queue_position(
+        &self,
+        id: OrderId,
+        signer: &str,
+    ) -> Result<QueuePosition, ApplicationError> {
+        let book = match self.accounts.is_sandbox(signer) {
+            true => &self.sandbox_book,
+            false => &self.matching_engine,
+        };
+        match book.order(id) {
+            None => Err(ApplicationError::OrderNotFound(id)),
+            Some(order) if order.signer != signer => Err(ApplicationError::OrderNotOwned(id)),
+            Some(_) => book
+                .queue_position(id)
+                .ok_or(ApplicationError::OrderNotFound(id)),
+        }
+    }
+
+    /// Cancel every open order of `signer` in every market's book and the sandbox book, returns the cancelled orders
+    pub fn cancel_all(&mut self, signer: &str, now: u64) -> Vec<PartialOrder> {
+        let mut cancelled = self.sandbox_book.cancel_all(signer);
+        for book in self.books.values_mut() {
+            cancelled.extend(book.cancel_all(signer));
+        }
+        let stops: Vec<OrderId> = self.matching_engine.stops().keys().copied().collect();
+        let public = self.matching_engine.cancel_all(signer);
+        if !public.is_empty() {
+            self.book_log.record(
+                BookEvent::CancelAll {
+                    timestamp: now,
+                    signer: signer.to_string(),
+                },
+                &self.matching_engine,
+            );
+            // Waiting stops weren't in the book
+            let touched = public
+                .iter()
+                .filter(|order| !stops.contains(&order.order_id))
+                .map(|order| TouchedLevel {
+                    side: order.side.clone(),
+                    price: order.price,
+                    before: None,
+                })
+                .collect();
+            self.book_updates.publish(&self.matching_engine, touched);
+        }
+        cancelled.extend(public);
+        cancelled
+    }
+
+    /// Cancel the open orders of the filter's signer that match it in one pass, in the sandbox book for sandbox accounts.
+    /// Returns the ids of the cancelled orders, they count towards the signer's cancel ratio. Sandbox orders and the
+    /// orders of markets other than the [`DEFAULT_MARKET`] aren't in the book event log, so their age is unknown and a
+    /// filter with an age keeps them.
+    ///
+    /// # Errors
+    /// The filter names a market that doesn't exist
+    pub fn cancel_matching(
+        &mut self,
+        filter: &CancelFilter,
+        now: u64,
+    ) -> Result<Vec<OrderId>, ApplicationError> {
+        if let Some(market) = &filter.market {
+            self.markets.get(market)?;
+        }
+        let placed_before = filter.older_than_ms.map(|age| now.saturating_sub(age));
+        let market = filter.market.as_deref().unwrap_or(DEFAULT_MARKET);
+        let cancelled = if market != DEFAULT_MARKET {
+            match (placed_before, self.books.get_mut(market)) {
+                (None, Some(book)) => book.cancel_where(|o| filter.matches(o)),
+                _ => vec![],
+            }
+        } else if self.accounts.is_sandbox(&filter.signer) {
+            match placed_before {
+                Some(_) => vec![],
+                None => self.sandbox_book.cancel_where(|o| filter.matches(o)),
+            }
+        } else {
+            let stops: Vec<OrderId> = self.matching_engine.stops().keys().copied().collect();
+            let book_log = &self.book_log;
+            let old_enough = |order_id| match placed_before {
+                Some(placed_before) => book_log.placement(order_id).is_some_and(|(index, _)| {
+                    book_log.events()[index].1.timestamp() < placed_before
+                }),
+                None => true,
+            };
+            let cancelled = self
+                .matching_engine
+                .cancel_where(|o| filter.matches(o) && old_enough(o.order_id));
+            if !cancelled.is_empty() {
+                self.book_log.record(
+                    BookEvent::CancelMany {
+                        timestamp: now,
+                        signer: filter.signer.clone(),
+                        order_ids: cancelled.iter().map(|o| o.order_id).collect(),
+                    },
+                    &self.matching_engine,
+                );
+                // Waiting stops weren't in the book
+                let touched = cancelled
+                    .iter()
+                    .filter(|order| !stops.contains(&order.order_id))
+                    .map(|order| TouchedLevel {
+                        side: order.side.clone(),
+                        price: order.price,
+                        before: None,
+                    })
+                    .collect();
+                self.book_updates.publish(&self.matching_engine, touched);
+            }
+            cancelled
+        };
+        self.throttle
+            .record_cancels(&filter.signer, cancelled.len(), now);
+        Ok(cancelled.iter().map(|o| o.order_id).collect())
+    }
+
+    /// Removes the good 'til time orders that expired at `now` from every market's book and the sandbox book, returns
+    /// them
+    pub fn expire_orders(&mut self, now: u64) -> Vec<PartialOrder> {
+        let mut expired = self.sandbox_book.expire(now);
+        for book in self.books.values_mut() {
+            expired.extend(book.expire(now));
+        }
+        let stops: Vec<OrderId> = self.matching_engine.stops().keys().copied().collect();
+        let public = self.matching_engine.expire(now);
+        if !public.is_empty() {
+            self.book_log
+                .record(BookEvent::Expire { timestamp: now }, &self.matching_engine);
+            // Waiting stops weren't in the book
+            let touched = public
+                .iter()
+                .filter(|order| !stops.contains(&order.order_id))
+                .map(|order| TouchedLevel {
+                    side: order.side.clone(),
+                    price: order.price,
+                    before: None,
+                })
+                .collect();
+            self.book_updates.publish(&self.matching_engine, touched);
+        }
+        expired.extend(public);
+        expired
+    }
+
+    /// Process a given order and apply the outcome to the accounts involved. Note that there are very few safeguards in place.
+    ///
+    /// # Errors
+    /// - The signer exceeds its order rate or cancel ratio
+    /// - The signer registered a public key and the order isn't signed with it
+    /// - The price is outside the price collar
+    /// - A good 'til time order already expired
+    /// - Account has insufficient funds
+    /// - Account breached its stop-loss and the order would increase its position
+    /// - The signer submitted an order with the same client order id within the duplicate window
+    pub fn order(&mut self, order: Order) -> Result<Receipt, ApplicationError> {
+        self.place_order(order, false)
+    }
+
+    /// Like [`TradingPlatform::order`], but `override_collar` skips the price collar check. Accepted signed orders are
+    /// recorded in the audit trail with their signature.
+    pub fn place_order(
+        &mut self,
+        order: Order,
+        override_collar: bool,
+    ) -> Result<Receipt, ApplicationError> {
+        // Throttled orders don't get to cost a signature check
+        self.throttle.admit(&order.signer, now_millis())?;
+        let signed = self.verify_signature(&order)?;
+        let signer = order.signer.clone();
+        let receipt = self.accept_order(order, override_collar)?;
+        if let Some(mut signed) = signed {
+            signed["ordinal"] = receipt.ordinal.into();
+            self.auditor
+                .record(&signer, AuditAction::SignedOrder, (), signed);
+        }
+        Ok(receipt)
+    }
+
+    /// Place a two-sided quote, replacing what's left of the signer's previous quote. Both sides pass the checks of
+    /// [`TradingPlatform::order`], and the settlement of both sides' matches is checked as one, before either is
+    /// processed, so they're accepted or rejected together. A quote counts as one order towards the throttle, client
+    /// order ids aren't checked for duplicates.
+    ///
+    /// # Errors
+    /// - The sides don't form a quote, see [`MatchingEngine::validate_quote`]
+    /// - The quote is for a market other than the [`DEFAULT_MARKET`]
+    /// - The signer is a sandbox account
+    /// - Any error of [`TradingPlatform::order`] for either side
+    pub fn quote(&mut self, request: QuoteRequest) -> Result<QuoteReceipt, ApplicationError> {
+        let QuoteRequest { bid, ask } = request;
+        MatchingEngine::validate_quote(&bid, &ask)?;
+        if bid.market != DEFAULT_MARKET {
+            return Err(ApplicationError::InvalidQuote(format!(
+                "quotes are only taken in {}",
+                DEFAULT_MARKET
+            )));
+        }
+        let now = now_millis();
+        self.throttle.admit(&bid.signer, now)?;
+        let signed = [self.verify_signature(&bid)?, self.verify_signature(&ask)?];
+        if self.accounts.is_sandbox(&bid.signer) {
+            return Err(ApplicationError::SandboxViolation(bid.signer));
+        }
+        let reserved = self.check_order(&bid, now, false)? + self.check_order(&ask, now, false)?;
+        let fees_bps = (self.taker_fee_bps, self.maker_fee_bps);
+        // Both sides settle together, so what they'd match now is checked as one before the book changes
+        let [bid_plan, ask_plan] = [&bid, &ask].map(|order| {
+            let matches = self.matching_engine.matches_of(order);
+            self.plan_fills(
+                &order.signer,
+                &order.side,
+                &matches,
+                &order.market,
+                fees_bps,
+            )
+        });
+        self.check_plans(&[&bid_plan?, &ask_plan?])?;
+        let guard = self.stop_funds(
+            self.matching_engine.stops(),
+            (&bid.signer, reserved),
+            self.max_fee_bps(),
+        );
+        self.matching_engine.set_stop_guard(guard);
+        let own_levels = [&bid, &ask].map(|order| TouchedLevel {
+            side: order.side.clone(),
+            price: order.price,
+            before: Some(
+                self.matching_engine
+                    .level_quantity(&order.side, order.price),
+            ),
+        });
+        let stop_levels = self.stop_levels();
+        let max_trades = self.max_trades(&bid) + self.max_trades(&ask);
+        self.reserve_id(|ids, next| {
+            ids.ordinal = next.nth_after(ids.ordinal, 2);
+            ids.trade_id = next.nth_after(ids.trade_id, max_trades);
+        })?;
+
+        let signer = bid.signer.clone();
+        // The previous quote is only forgotten once this one is in the book
+        let replace = self.quotes.get(&signer).cloned().unwrap_or_default();
+        let mut receipt = self
+            .matching_engine
+            .process_quote(bid.clone(), ask.clone(), &replace)?;
+        let triggered = self.matching_engine.triggered().to_vec();
+        self.book_log.record(
+            BookEvent::Quote {
+                timestamp: now,
+                bid,
+                ask,
+                replace,
+            },
+            &self.matching_engine,
+        );
+        let touched = own_levels
+            .into_iter()
+            .chain(
+                receipt
+                    .cancelled
+                    .iter()
+                    .chain(receipt.bid.matches.iter())
+                    .chain(receipt.ask.matches.iter())
+                    .map(|order| TouchedLevel {
+                        side: order.side.clone(),
+                        price: order.price,
+                        before: None,
+                    }),
+            )
+            .chain(stop_levels)
+            .chain(TradingPlatform::matched_by(&triggered))
+            .chain(self.unlinked_levels())
+            .collect();
+        self.book_updates.publish(&self.matching_engine, touched);
+        self.quotes.insert(
+            signer.clone(),
+            vec![receipt.bid.order_id, receipt.ask.order_id],
+        );
+
+        let [bid_plan, ask_plan] = [(&Side::Buy, &receipt.bid), (&Side::Sell, &receipt.ask)]
+            .map(|(side, r)| self.plan_fills(&signer, side, &r.matches, &r.market, fees_bps));
+        let (bid_plan, ask_plan) = (bid_plan?, ask_plan?);
+        self.check_plans(&[&bid_plan, &ask_plan])?;
+        receipt.bid.settlement =
+            self.apply_fills(&signer, &Side::Buy, &receipt.bid, bid_plan, now)?;
+        receipt.ask.settlement =
+            self.apply_fills(&signer, &Side::Sell, &receipt.ask, ask_plan, now)?;
+        self.settle_triggered(&triggered, false, now);
+        for (signed, ordinal) in signed
+            .into_iter()
+            .zip([receipt.bid.ordinal, receipt.ask.ordinal])
+        {
+            if let Some(mut signed) = signed {
+                signed["ordinal"] = ordinal.into();
+                self.auditor
+                    .record(&signer, AuditAction::SignedOrder, (), signed);
+            }
+        }
+        Ok(receipt)
+    }
+
+    /// Place a take-profit and a stop-loss as a bracket: once either of them fills or the stop triggers, the other one
+    /// is cancelled. Both orders pass the checks of [`TradingPlatform::order`], and the settlement of the take-profit's
+    /// matches is checked, before either is processed, so they're accepted or rejected together. A bracket counts as
+    /// one order towards the throttle.
+    ///
+    /// # Errors
+    /// - The orders don't form a bracket, see [`MatchingEngine::validate_bracket`]
+    /// - The bracket is for a market other than the [`DEFAULT_MARKET`]
+    /// - The signer is a sandbox account
+    /// - Any error of [`TradingPlatform::order`] for either order
+    pub fn bracket(&mut self, request: BracketRequest) -> Result<BracketReceipt, ApplicationError> {
+        let BracketRequest {
+            take_profit,
+            stop_loss,
+        } = request;
+        MatchingEngine::validate_bracket(&take_profit, &stop_loss)?;
+        if take_profit.market != DEFAULT_MARKET {
+            return Err(ApplicationError::InvalidBracket(format!(
+                "brackets are only taken in {}",
+                DEFAULT_MARKET
+            )));
+        }
+        let now = now_millis();
+        self.throttle.admit(&take_profit.signer, now)?;
+        let signed = [
+            self.verify_signature(&take_profit)?,
+            self.verify_signature(&stop_loss)?,
+        ];
+        if self.accounts.is_sandbox(&take_profit.signer) {
+            return Err(ApplicationError::SandboxViolation(take_profit.signer));
+        }
+        let reserved = self.check_order(&take_profit, now, false)?
+            + self.check_order(&stop_loss, now, false)?;
+        self.check_fills(Some(&self.matching_engine), &take_profit)?;
+        let guard = self.stop_funds(
+            self.matching_engine.stops(),
+            (&take_profit.signer, reserved),
+            self.max_fee_bps(),
+        );
+        self.matching_engine.set_stop_guard(guard);
+        let own_level = TouchedLevel {
+            side: take_profit.side.clone(),
+            price: take_profit.price,
+            before: Some(
+                self.matching_engine
+                    .level_quantity(&take_profit.side, take_profit.price),
+            ),
+        };
+        let stop_levels = self.stop_levels();
+        let max_trades = self.max_trades(&take_profit);
+        self.reserve_id(|ids, next| {
+            ids.ordinal = next.nth_after(ids.ordinal, 2);
+            ids.trade_id = next.nth_after(ids.trade_id, max_trades);
+        })?;
+
+        let signer = take_profit.signer.clone();
+        let side = take_profit.side.clone();
+        let mut receipt = self
+            .matching_engine
+            .process_bracket(take_profit.clone(), stop_loss.clone())?;
+        let triggered = self.matching_engine.triggered().to_vec();
+        self.book_log.record(
+            BookEvent::Bracket {
+                timestamp: now,
+                take_profit,
+                stop_loss,
+            },
+            &self.matching_engine,
+        );
+        let touched = receipt
+            .take_profit
+            .matches
+            .iter()
+            .map(|m| TouchedLevel {
+                side: m.side.clone(),
+                price: m.price,
+                before: None,
+            })
+            .chain([own_level])
+            .chain(stop_levels)
+            .chain(TradingPlatform::matched_by(&triggered))
+            .chain(self.unlinked_levels())
+            .collect();
+        self.book_updates.publish(&self.matching_engine, touched);
+
+        receipt.take_profit.settlement =
+            self.settle_matches(&signer, &side, &receipt.take_profit, now)?;
+        self.settle_triggered(&triggered, false, now);
+        for (signed, ordinal) in signed
+            .into_iter()
+            .zip([receipt.take_profit.ordinal, receipt.stop_loss.ordinal])
+        {
+            if let Some(mut signed) = signed {
+                signed["ordinal"] = ordinal.into();
+                self.auditor
+                    .record(&signer, AuditAction::SignedOrder, (), signed);
+            }
+        }
+        Ok(receipt)
+    }
+
+    /// Opens a call auction in the [`DEFAULT_MARKET`]: orders rest without matching until
+    /// [`TradingPlatform::run_auction`], market and immediate-or-cancel orders are rejected meanwhile
+    pub fn start_auction(&mut self) {
+        if self.matching_engine.in_auction() {
+            return;
+        }
+        self.matching_engine.start_auction();
+        self.book_log.record(
+            BookEvent::AuctionStart {
+                timestamp: now_millis(),
+            },
+            &self.matching_engine,
+        );
+    }
+
+    /// Runs the call auction of the [`DEFAULT_MARKET`], crossing the book at a single price, see
+    /// [`MatchingEngine::run_auction`]. Continuous trading resumes afterwards. Nobody takes liquidity in an auction,
+    /// so no taker fees are charged for its matches.
+    pub fn run_auction(&mut self) -> Result<AuctionReceipt, ApplicationError> {
+        let now = now_millis();
+        // Any level may cross
+        let touched: Vec<TouchedLevel> = [Side::Buy, Side::Sell]
+            .into_iter()
+            .flat_map(|side| {
+                self.matching_engine
+                    .levels(&side)
+                    .into_iter()
+                    .map(move |level| TouchedLevel {
+                        side: side.clone(),
+                        price: level.price,
+                        before: Some(level.quantity),
+                    })
+            })
+            .chain(self.stop_levels())
+            .collect();
+        let max_trades = (self.matching_engine.order_count(&Side::Buy)
+            + self.matching_engine.order_count(&Side::Sell)) as u64;
+        self.reserve_id(|ids, next| ids.trade_id = next.nth_after(ids.trade_id, max_trades))?;
+        let guard = self.stop_funds(self.matching_engine.stops(), ("", 0), self.max_fee_bps());
+        self.matching_engine.set_stop_guard(guard);
+
+        let mut receipt = self.matching_engine.run_auction()?;
+        let triggered = self.matching_engine.triggered().to_vec();
+        self.book_log
+            .record(BookEvent::Auction { timestamp: now }, &self.matching_engine);
+        let touched = touched
+            .into_iter()
+            .chain(TradingPlatform::matched_by(&triggered))
+            .chain(self.unlinked_levels())
+            .collect();
+        self.book_updates.publish(&self.matching_engine, touched);
+        for fill in receipt.fills.iter_mut() {
+            fill.receipt.settlement =
+                self.settle_fills(&fill.signer, &Side::Buy, &fill.receipt, (0, 0), now)?;
+        }
+        self.settle_triggered(&triggered, false, now);
+        Ok(receipt)
+    }
+
+    /// The signature of `order` for the audit trail, `None` if the signer didn't register a public key. Uses up the
+    /// nonce of the signature.
+    /// # Errors
+    /// The signer registered a public key and the order isn't signed with it for this tenant, the signature expired, or
+    /// its nonce was used before, see [`SigningKeys::verify`]
+    fn verify_signature(
+        &mut self,
+        order: &Order,
+    ) -> Result<Option<serde_json::Value>, ApplicationError> {
+        let tenant = self.auditor.tenant.clone();
+        let key = self.signing_keys.verify(order, &tenant, now_millis())?;
+        Ok(key.zip(order.signature.as_ref()).map(|(key, signature)| {
+            let payload = order.signing_payload(&tenant, signature.nonce, signature.expires_at);
+            serde_json::json!({
+                "public_key": key.public_key,
+                "signature": signature.value,
+                "nonce": signature.nonce,
+                "expires_at": signature.expires_at,
+                "payload": String::from_utf8_lossy(&payload),
+            })
+        }))
+    }
+
+    /// Checks the price collar, the stop-loss, and the funds of a public order. Positions, and so stop-losses, are kept
+    /// in the [`DEFAULT_MARKET`] only. Returns the funds a buy order commits including the fee, 0 for sell orders.
+    fn check_order(
+        &mut self,
+        order: &Order,
+        now: u64,
+        override_collar: bool,
+    ) -> Result<u64, ApplicationError> {
+        self.markets.get(&order.market)?.check(order)?;
+        self.check_circuit_breaker(order, now)?;
+        if let TimeInForce::Gtt(expiry) = order.time_in_force {
+            if expiry <= now {
+                return Err(ApplicationError::InvalidExpiry(expiry));
+            }
+        }
+        let total_amount = match order.order_type {
+            OrderType::Limit => {
+                self.check_collar(&order.market, order.price, now, override_collar)?;
+                notional(order.amount, order.price)?
+            }
+            OrderType::Market => match (order.trigger_price, self.book(&order.market)) {
+                // The book a stop-loss meets is only known once it triggers
+                (Some(trigger_price), _) => notional(order.amount, trigger_price)?,
+                (None, Some(book)) => self.check_market_order(book, order, now, override_collar)?,
+                (None, None) => return Err(ApplicationError::NoLiquidity(order.signer.clone())),
+            },
+        };
+        if order.market == DEFAULT_MARKET && self.stop_losses.is_breached(&order.signer, now) {
+            let open_amount = self.matching_engine.open_amount(&order.signer, &order.side);
+            if self
+                .positions
+                .increases_risk(&order.signer, &order.side, order.amount + open_amount)
+            {
+                return Err(ApplicationError::StopLossBreached(order.signer.clone()));
+            }
+        }
+        let total_amount = total_amount.saturating_add(self.settlement.fee(
+            total_amount,
+            self.max_fee_bps(),
+            &order.market,
+        ));
+        // Make sure the account has a deposit
+        match self.balance_of(&order.signer) {
+            Ok(balance) if order.side == Side::Buy && balance < &total_amount => Err(
+                ApplicationError::AccountUnderFunded(order.signer.clone(), total_amount),
+            ),
+            Ok(_) if order.side == Side::Buy => Ok(total_amount),
+            Ok(_) => Ok(0),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// A guard letting the waiting `stops` of a book execute only while their signers can pay for them out of their
+    /// balance now, less what the signer placing the operation `reserved` for it, see [`StopFunds`]
+    fn stop_funds(
+        &self,
+        stops: &BTreeMap<OrderId, Order>,
+        (placer, reserved): (&str, u64),
+        fee_bps: u64,
+    ) -> Option<Box<dyn StopGuard>> {
+        let funds = stops
+            .values()
+            .filter(|stop| stop.side == Side::Buy)
+            .filter_map(|stop| {
+                let balance = *self.accounts.balance_of(&stop.signer).ok()?;
+                let reserved = if stop.signer == placer { reserved } else { 0 };
+                Some((stop.signer.clone(), balance.saturating_sub(reserved)))
+            })
+            .collect();
+        Some(Box::new(StopFunds::new(
+            funds,
+            fee_bps,
+            self.settlement.clone(),
+        )))
+    }
+
+    /// Checks that the market of `order` isn't halted and the order wouldn't execute too far from its last price.
+    /// Stops are measured once they trigger.
+    fn check_circuit_breaker(&mut self, order: &Order, now: u64) -> Result<(), ApplicationError> {
+        let (worst_price, last_price) = match self.book(&order.market) {
+            // Orders don't fill while a call auction is open
+            Some(book) if order.trigger_price.is_none() && !book.in_auction() => (
+                book.fills_of(order).last().map(|fill| fill.price),
+                book.last_price(),
+            ),
+            _ => (None, None),
+        };
+        self.circuit_breaker
+            .check(&order.market, worst_price, last_price, now)
+    }
+
+    /// Checks that `price` is within the price collar around the reference price of `market` at `now`
+    fn check_collar(
+        &self,
+        market: &str,
+        price: u64,
+        now: u64,
+        override_collar: bool,
+    ) -> Result<(), ApplicationError> {
+        let reference = self
+            .reference_prices
+            .price_of(market, now)
+            .map(|reference| reference.price);
+        if let (Some(bps), Some(reference), false) =
+            (self.price_collar_bps, reference, override_collar)
+        {
+            if !within_collar(price, reference, bps) {
+                return Err(ApplicationError::OutsidePriceCollar(price, reference));
+            }
+        }
+        Ok(())
+    }
+
+    /// Checks that a market order finds liquidity in `book`, and that the worst price it would fill at is within the
+    /// slippage limit and the price collar. Returns the price of the units it would fill.
+    fn check_market_order(
+        &self,
+        book: &MatchingEngine,
+        order: &Order,
+        now: u64,
+        override_collar: bool,
+    ) -> Result<u64, ApplicationError> {
+        let fills = book.fills_of(order);
+        let (Some(best), Some(worst)) = (fills.first(), fills.last()) else {
+            return Err(ApplicationError::NoLiquidity(order.signer.clone()));
+        };
+        if let Some(bps) = self.max_slippage_bps {
+            if !within_collar(worst.price, best.price, bps) {
+                return Err(ApplicationError::SlippageExceeded(worst.price, best.price));
+            }
+        }
+        self.check_collar(&order.market, worst.price, now, override_collar)?;
+        let notional = fills
+            .iter()
+            .map(|fill| fill.price.checked_mul(fill.quantity))
+            .try_fold(0u64, |total, cost| total.checked_add(cost?));
+        notional.ok_or(ApplicationError::InvalidOrder(OrderConstraint::Notional {
+            amount: order.amount,
+            price: worst.price,
+        }))
+    }
+
+    /// The most trades `order` can cause in the book of its market
+    fn max_trades(&self, order: &Order) -> u64 {
+        let Some(book) = self.book(&order.market) else {
+            return 0;
+        };
+        // Every match fills at least one unit of a resting order on the other side
+        let resting = match order.side {
+            Side::Buy => book.order_count(&Side::Sell),
+            Side::Sell => book.order_count(&Side::Buy),
+        };
+        order.amount.min(resting as u64)
+    }
+
+    fn accept_order(
+        &mut self,
+        order: Order,
+        override_collar: bool,
+    ) -> Result<Receipt, ApplicationError> {
+        let now = now_millis();
+        if let Some(client_order_id) = &order.client_order_id {
+            if let Some(receipt) = self.recent_orders.get(&order.signer, client_order_id, now) {
+                return Err(ApplicationError::DuplicateOrder(
+                    client_order_id.clone(),
+                    Box::new(receipt.clone()),
+                ));
+            }
+        }
+        if self.accounts.is_sandbox(&order.signer) {
+            return self.place_sandbox_order(order, now);
+        }
+        if order.market != DEFAULT_MARKET {
+            return self.place_listed_order(order, now, override_collar);
+        }
+        let reserved = self.check_order(&order, now, override_collar)?;
+        self.check_fills(Some(&self.matching_engine), &order)?;
+        let signer = order.signer.clone();
+        let side = order.side.clone();
+        let client_order_id = order.client_order_id.clone();
+        let guard = self.stop_funds(
+            self.matching_engine.stops(),
+            (&signer, reserved),
+            self.max_fee_bps(),
+        );
+        self.matching_engine.set_stop_guard(guard);
+        let own_level = TouchedLevel {
+            side: order.side.clone(),
+            price: order.price,
+            before: Some(
+                self.matching_engine
+                    .level_quantity(&order.side, order.price),
+            ),
+        };
+        let stop_levels = self.stop_levels();
+        let max_trades = self.max_trades(&order);
+        self.reserve_id(|ids, next| {
+            ids.ordinal = next.next_id(ids.ordinal);
+            ids.trade_id = next.nth_after(ids.trade_id, max_trades);
+        })?;
+        // Do the actual matching
+        let mut receipt = self.matching_engine.process(order.clone())?;
+        let triggered = self.matching_engine.triggered().to_vec();
+        self.book_log.record(
+            BookEvent::Order {
+                timestamp: now,
+                order,
+            },
+            &self.matching_engine,
+        );
+        let touched = receipt
+            .matches
+            .iter()
+            .map(|m| TouchedLevel {
+                side: m.side.clone(),
+                price: m.price,
+                before: None,
+            })
+            .chain([own_level])
+            .chain(stop_levels)
+            .chain(TradingPlatform::matched_by(&triggered))
+            .chain(self.unlinked_levels())
+            .collect();
+        self.book_updates.publish(&self.matching_engine, touched);
+        receipt.settlement = self.settle_matches(&signer, &side, &receipt, now)?;
+        if let Some(client_order_id) = client_order_id {
+            self.recent_orders
+                .insert(&signer, &client_order_id, receipt.clone(), now);
+        }
+        self.settle_triggered(&triggered, false, now);
+        Ok(receipt)
+    }
+
+    /// Matches an order in the book of a listed market other than the [`DEFAULT_MARKET`], opening the book with the
+    /// market's first order. The order passes the checks of a public order and its matches are settled like one's,
+    /// but the book isn't in the book event log or the deltas.
+    fn place_listed_order(
+        &mut self,
+        order: Order,
+        now: u64,
+        override_collar: bool,
+    ) -> Result<Receipt, ApplicationError> {
+        let capacity = self.markets.get(&order.market)?.book_capacity();
+        let reserved = self.check_order(&order, now, override_collar)?;
+        self.check_fills(self.books.get(&order.market), &order)?;
+        let signer = order.signer.clone();
+        let side = order.side.clone();
+        let client_order_id = order.client_order_id.clone();
+        // A book opened by this order has no stops yet
+        let guard = self.books.get(&order.market).and_then(|book| {
+            self.stop_funds(book.stops(), (&signer, reserved), self.max_fee_bps())
+        });
+        let max_trades = self.max_trades(&order);
+        self.reserve_id(|ids, next| {
+            ids.ordinal = next.next_id(ids.ordinal);
+            ids.trade_id = next.nth_after(ids.trade_id, max_trades);
+        })?;
+        let (ids, feed, events) = (self.ids.clone(), &self.account_feed, &self.engine_events);
+        let policy = self.matching_engine.self_match_policy();
+        let book = self.books.entry(order.market.clone()).or_insert_with(|| {
+            let mut book = listed_book(ids, feed, events, policy);
+            book.preallocate(capacity);
+            book
+        });
+        book.ordinal = self.matching_engine.ordinal;
+        book.set_stop_guard(guard);
+        let mut receipt = book.process(order)?;
+        self.matching_engine.ordinal = book.ordinal;
+        let triggered = book.triggered().to_vec();
+        receipt.settlement = self.settle_matches(&signer, &side, &receipt, now)?;
+        if let Some(client_order_id) = client_order_id {
+            self.recent_orders
+                .insert(&signer, &client_order_id, receipt.clone(), now);
+        }
+        self.settle_triggered(&triggered, false, now);
+        Ok(receipt)
+    }
+
+    /// The higher of the taker and maker fee, what an order is charged at most whether it takes or rests
+    fn max_fee_bps(&self) -> u64 {
+        self.taker_fee_bps.max(self.maker_fee_bps)
+    }
+
+    /// Settles the matches of the public order `receipt` of `signer`, charges the taker fees, and records the trades
+    /// of its market. Only the [`DEFAULT_MARKET`] moves positions and stop-losses. Returns what the taker's matches
+    /// amounted to, `None` without matches.
+    fn settle_matches(
+        &mut self,
+        signer: &str,
+        side: &Side,
+        receipt: &Receipt,
+        now: u64,
+    ) -> Result<Option<Settlement>, ApplicationError> {
+        let fees_bps = (self.taker_fee_bps, self.maker_fee_bps);
+        self.settle_fills(signer, side, receipt, fees_bps, now)
+    }
+
+    /// Like [`TradingPlatform::settle_matches`], but with the `(taker, maker)` fees of `fees_bps`. Every leg and fee
+    /// is checked before the first balance changes, so either the whole receipt is settled or nothing is.
+    fn settle_fills(
+        &mut self,
+        signer: &str,
+        side: &Side,
+        receipt: &Receipt,
+        fees_bps: (u64, u64),
+        now: u64,
+    ) -> Result<Option<Settlement>, ApplicationError> {
+        let plan = self.plan_fills(signer, side, &receipt.matches, &receipt.market, fees_bps)?;
+        self.check_plans(&[&plan])?;
+        self.apply_fills(signer, side, receipt, plan, now)
+    }
+
+    /// What settling the `matches` of an order of `signer` in `market` moves with the `(taker, maker)` fees of
+    /// `fees_bps`
+    /// # Errors
+    /// The notional of a match or of all of them is more than a `u64` holds
+    fn plan_fills(
+        &self,
+        signer: &str,
+        side: &Side,
+        matches: &[PartialOrder],
+        market: &str,
+        (taker_fee_bps, maker_fee_bps): (u64, u64),
+    ) -> Result<FillPlan, ApplicationError> {
+        // The taker pays a fee on the whole fill, split over the matches so the trades add up to it. Each maker pays
+        // on its own match.
+        let notionals = matches
+            .iter()
+            .map(|m| notional(m.amount, m.price))
+            .collect::<Result<Vec<u64>, _>>()?;
+        let gross = notionals
+            .iter()
+            .try_fold(0u64, |gross, notional| gross.checked_add(*notional))
+            .ok_or(ApplicationError::InvalidOrder(OrderConstraint::Notional {
+                amount: matches
+                    .iter()
+                    .fold(0, |amount, m| amount.saturating_add(m.amount)),
+                price: matches.iter().map(|m| m.price).max().unwrap_or(0),
+            }))?;
+        let fee = self.settlement.fee(gross, taker_fee_bps, market);
+        let taker_fees = self.settlement.allocate(fee, &notionals, market);
+        let maker_fees: Vec<u64> = notionals
+            .iter()
+            .map(|notional| self.settlement.fee(*notional, maker_fee_bps, market))
+            .collect();
+        let legs = matches
+            .iter()
+            .zip(notionals.iter())
+            .map(|(m, notional)| match side {
+                Side::Buy => (signer.to_string(), m.signer.clone(), *notional),
+                Side::Sell => (m.signer.clone(), signer.to_string(), *notional),
+            })
+            .collect();
+        let charges = taker_fees
+            .iter()
+            .map(|fee| (signer.to_string(), *fee, FeeKind::Taker))
+            .chain(
+                matches
+                    .iter()
+                    .zip(maker_fees.iter())
+                    .map(|(m, fee)| (m.signer.clone(), *fee, FeeKind::Maker)),
+            )
+            .filter(|(_, fee, _)| *fee > 0)
+            .collect();
+        Ok(FillPlan {
+            legs,
+            charges,
+            taker_fees,
+            maker_fees,
+            gross,
+            fee,
+        })
+    }
+
+    /// Checks that what `order` would match in its `book` now can be settled with the public fees, before the book
+    /// changes. A book that isn't open yet has nothing to match.
+    fn check_fills(
+        &self,
+        book: Option<&MatchingEngine>,
+        order: &Order,
+    ) -> Result<(), ApplicationError> {
+        let matches = book.map(|book| book.matches_of(order)).unwrap_or_default();
+        let fees_bps = (self.taker_fee_bps, self.maker_fee_bps);
+        let plan = self.plan_fills(
+            &order.signer,
+            &order.side,
+            &matches,
+            &order.market,
+            fees_bps,
+        )?;
+        self.check_plans(&[&plan])
+    }
+
+    /// Checks that the `plans` can be applied one after the other without changing a balance
+    fn check_plans(&self, plans: &[&FillPlan]) -> Result<(), ApplicationError> {
+        let transfers: Vec<(&str, &str, u64)> =
+            plans.iter().flat_map(|plan| plan.transfers()).collect();
+        self.accounts.check_transfers(&transfers, FEE_ACCOUNT)
+    }
+
+    /// Applies the checked `plan` of the matches of `receipt`, see [`TradingPlatform::settle_matches`]
+    fn apply_fills(
+        &mut self,
+        signer: &str,
+        side: &Side,
+        receipt: &Receipt,
+        plan: FillPlan,
+        now: u64,
+    ) -> Result<Option<Settlement>, ApplicationError> {
+        let FillPlan {
+            legs,
+            charges,
+            taker_fees,
+            maker_fees,
+            gross,
+            fee,
+        } = plan;
+        let mut trade_ids = Vec::with_capacity(legs.len());
+        for (buyer, seller, amount) in legs {
+            self.last_trade_id = self.ids.next_id(self.last_trade_id);
+            let tx = self
+                .accounts
+                .settle(self.last_trade_id, &buyer, &seller, amount)?;
+            self.record_tx(tx);
+            trade_ids.push(self.last_trade_id);
+        }
+        for (account, fee, kind) in charges {
+            let tx = self.accounts.charge_fee(&account, FEE_ACCOUNT, fee)?;
+            self.record_tx(tx);
+            self.fees.record(FeeCharge {
+                timestamp: now,
+                account,
+                amount: fee,
+                kind,
+            });
+        }
+
+        for (((m, id), taker_fee), maker_fee) in receipt
+            .matches
+            .iter()
+            .zip(trade_ids)
+            .zip(taker_fees)
+            .zip(maker_fees)
+        {
+            let trade = Trade {
+                id,
+                ordinal: receipt.ordinal,
+                maker_ordinal: m.ordinal,
+                timestamp: now,
+                market: receipt.market.clone(),
+                price: m.price,
+                amount: m.amount,
+                taker: signer.to_string(),
+                maker: m.signer.clone(),
+                taker_side: side.clone(),
+                limit_price: receipt.limit_price,
+                taker_fee,
+                maker_fee,
+                busted: None,
+            };
+            // Nobody listening is fine
+            let _ = self.trade_feed.send(trade.clone());
+            self.trades.push(trade);
+            self.reference_prices.record(&receipt.market, m.price, now);
+            let default_market = receipt.market == DEFAULT_MARKET;
+            for (account, side) in [(signer, side), (m.signer.as_str(), &m.side)] {
+                let realized = match default_market {
+                    true => self.positions.fill(account, side, m.amount, m.price),
+                    false => 0,
+                };
+                self.trade_stats.record(Fill {
+                    timestamp: now,
+                    account: account.to_string(),
+                    side: side.clone(),
+                    amount: m.amount,
+                    price: m.price,
+                    realized,
+                });
+            }
+            if default_market {
+                self.last_price = Some(m.price);
+            }
+        }
+        if receipt.market == DEFAULT_MARKET && !receipt.matches.is_empty() {
+            self.enforce_stop_losses(now);
+        }
+        let net = match side {
+            Side::Buy => gross + fee,
+            Side::Sell => gross.saturating_sub(fee),
+        };
+        Ok((!receipt.matches.is_empty()).then_some(Settlement { gross, fee, net }))
+    }
+
+    /// The levels the waiting stop-limit orders rest at if they trigger, with their quantity now
+    fn stop_levels(&self) -> Vec<TouchedLevel> {
+        self.matching_engine
+            .stops()
+            .values()
+            .filter(|stop| stop.order_type == OrderType::Limit)
+            .map(|stop| TouchedLevel {
+                side: stop.side.clone(),
+                price: stop.price,
+                before: Some(self.matching_engine.level_quantity(&stop.side, stop.price)),
+            })
+            .collect()
+    }
+
+    /// The levels the matches of `triggered` stops took from
+    fn matched_by(triggered: &[TriggeredStop]) -> impl Iterator<Item = TouchedLevel> + '_ {
+        triggered
+            .iter()
+            .flat_map(|stop| stop.receipt.matches.iter())
+            .map(|m| TouchedLevel {
+                side: m.side.clone(),
+                price: m.price,
+                before: None,
+            })
+    }
+
+    /// The levels of the orders the latest operation cancelled because the other order of their bracket filled or
+    /// triggered
+    fn unlinked_levels(&self) -> Vec<TouchedLevel> {
+        self.matching_engine
+            .unlinked()
+            .iter()
+            .map(|order| TouchedLevel {
+                side: order.side.clone(),
+                price: order.price,
+                before: None,
+            })
+            .collect()
+    }
+
+    /// Settles the matches of the stops an order or a quote triggered, once the trade ids for them are durable.
+    /// `sandbox` stops were triggered in the sandbox book. Their signers' funds were checked when they triggered, see
+    /// [`StopFunds`]. Each stop is settled on its own after the operation that triggered it, so a stop failing to settle
+    /// is logged and doesn't fail that operation.
+    fn settle_triggered(&mut self, triggered: &[TriggeredStop], sandbox: bool, now: u64) {
+        let trades: u64 = triggered
+            .iter()
+            .map(|stop| stop.receipt.matches.len() as u64)
+            .sum();
+        if trades > 0 {
+            if let Err(e) =
+                self.reserve_id(|ids, next| ids.trade_id = next.nth_after(ids.trade_id, trades))
+            {
+                log::error!("Reserving the trade ids of triggered stops failed: {:?}", e);
+            }
+        }
+        for stop in triggered {
+            let TriggeredStop { order, receipt } = stop;
+            let settled = match sandbox {
+                true => self.settle_sandbox_matches(&order.signer, &order.side, receipt, now),
+                false => self
+                    .settle_matches(&order.signer, &order.side, receipt, now)
+                    .map(|_| ()),
+            };
+            if let Err(e) = settled {
+                log::error!(
+                    "Settling the triggered stop {} of {} failed: {:?}",
+                    receipt.order_id,
+                    order.signer,
+                    e
+                );
+            }
+        }
+    }
+
+    /// Matches a sandbox account's order against the other sandbox orders, in the [`DEFAULT_MARKET`] if it's open to sandbox accounts. The
+    /// trades are settled in play funds without fees and don't show in the public market data.
+    fn place_sandbox_order(&mut self, order: Order, now: u64) -> Result<Receipt, ApplicationError> {
+        if order.market != DEFAULT_MARKET || !self.markets.get(DEFAULT_MARKET)?.sandbox {
+            return Err(ApplicationError::SandboxViolation(order.signer));
+        }
+        let total_amount = match order.order_type {
+            OrderType::Limit => notional(order.amount, order.price)?,
+            OrderType::Market => match order.trigger_price {
+                Some(trigger_price) => notional(order.amount, trigger_price)?,
+                None => self.check_market_order(&self.sandbox_book, &order, now, true)?,
+            },
+        };
+        match self.accounts.balance_of(&order.signer) {
+            Ok(balance) if order.side == Side::Buy && balance < &total_amount => {
+                return Err(ApplicationError::AccountUnderFunded(
+                    order.signer.clone(),
+                    total_amount,
+                ))
+            }
+            Ok(_) => {}
+            Err(e) => return Err(e),
+        }
+        self.check_sandbox_fills(&order)?;
+        let signer = order.signer.clone();
+        let side = order.side.clone();
+        let client_order_id = order.client_order_id.clone();
+        let resting = match order.side {
+            Side::Buy => self.sandbox_book.order_count(&Side::Sell),
+            Side::Sell => self.sandbox_book.order_count(&Side::Buy),
+        };
+        let max_trades = order.amount.min(resting as u64);
+        self.reserve_id(|ids, next| {
+            ids.ordinal = next.next_id(ids.ordinal);
+            ids.trade_id = next.nth_after(ids.trade_id, max_trades);
+        })?;
+        // Play funds settle without fees
+        let reserved = if side == Side::Buy { total_amount } else { 0 };
+        let guard = self.stop_funds(self.sandbox_book.stops(), (&signer, reserved), 0);
+        self.sandbox_book.set_stop_guard(guard);
+        self.sandbox_book.ordinal = self.matching_engine.ordinal;
+        let receipt = self.sandbox_book.process(order)?;
+        self.matching_engine.ordinal = self.sandbox_book.ordinal;
+
+        let triggered = self.sandbox_book.triggered().to_vec();
+        self.settle_sandbox_matches(&signer, &side, &receipt, now)?;
+        if let Some(client_order_id) = client_order_id {
+            self.recent_orders
+                .insert(&signer, &client_order_id, receipt.clone(), now);
+        }
+        self.settle_triggered(&triggered, true, now);
+        Ok(receipt)
+    }
+
+    /// Checks that what the sandbox `order` would match now can be settled in play funds, before the sandbox book
+    /// changes
+    fn check_sandbox_fills(&self, order: &Order) -> Result<(), ApplicationError> {
+        let matches = self.sandbox_book.matches_of(order);
+        let plan = self.plan_fills(&order.signer, &order.side, &matches, DEFAULT_MARKET, (0, 0))?;
+        self.check_plans(&[&plan])
+    }
+
+    /// Settles the matches of the sandbox order `receipt` of `signer` in play funds and records the trades. Every leg
+    /// is checked before the first balance changes. Sandbox trades don't count towards positions or trade stats.
+    fn settle_sandbox_matches(
+        &mut self,
+        signer: &str,
+        side: &Side,
+        receipt: &Receipt,
+        now: u64,
+    ) -> Result<(), ApplicationError> {
+        let plan = self.plan_fills(signer, side, &receipt.matches, DEFAULT_MARKET, (0, 0))?;
+        self.check_plans(&[&plan])?;
+        for (m, (buyer, seller, amount)) in receipt.matches.iter().zip(plan.legs) {
+            self.last_trade_id = self.ids.next_id(self.last_trade_id);
+            let tx = self
+                .accounts
+                .settle(self.last_trade_id, &buyer, &seller, amount)?;
+            self.record_tx(tx);
+            self.sandbox_trades.push(Trade {
+                id: self.last_trade_id,
+                ordinal: receipt.ordinal,
+                maker_ordinal: m.ordinal,
+                timestamp: now,
+                market: DEFAULT_MARKET.to_string(),
+                price: m.price,
+                amount: m.amount,
+                taker: signer.to_string(),
+                maker: m.signer.clone(),
+                taker_side: side.clone(),
+                limit_price: receipt.limit_price,
+                taker_fee: 0,
+                maker_fee: 0,
+                busted: None,
+            });
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    // reduce the warnings for naming tests
+    #![allow(non_snake_case)]
+
+    use super::*;
+    use crate::{audit::AuditQuery, core::DeterministicIds, tenants::DEFAULT_TENANT};
+    use octopus_common::types::{
+        BookDelta, DeltaAction, OrderConstraint, OrderSignature, OrderState, PayoutLeg, PriceLevel,
+        ReferenceSource,
+    };
+
+    #[test]
+    fn test_TradingPlatform_order_charges_taker_fee() {
+        let mut trading_platform = TradingPlatform::new();
+        trading_platform.taker_fee_bps = 100;
+        trading_platform.deposit("ALICE", 1_000).unwrap();
+        trading_platform.deposit("BOB", 0).unwrap();
+        trading_platform
+            .order(Order {
+                price: 50,
+                amount: 10,
+                side: Side::Sell,
+                signer: "BOB".to_string(),
+                client_order_id: None,
+                signature: None,
+                order_type: OrderType::Limit,
+                time_in_force: TimeInForce::Gtc,
+                trigger_price: None,
+                market: DEFAULT_MARKET.to_string(),
+            })
+            .unwrap();
+        // 500 plus 1% doesn't fit into 500
+        trading_platform.withdraw("ALICE", 500, None).unwrap();
+        assert!(trading_platform
+            .order(Order {
+                price: 50,
+                amount: 10,
+                side: Side::Buy,
+                signer: "ALICE".to_string(),
+                client_order_id: None,
+                signature: None,
+                order_type: OrderType::Limit,
+                time_in_force: TimeInForce::Gtc,
+                trigger_price: None,
+                market: DEFAULT_MARKET.to_string(),
+            })
+            .is_err());
+
+        trading_platform.deposit("ALICE", 5).unwrap();
+        trading_platform
+            .order(Order {
+                price: 50,
+                amount: 10,
+                side: Side::Buy,
+                signer: "ALICE".to_string(),
+                client_order_id: None,
+                signature: None,
+                order_type: OrderType::Limit,
+                time_in_force: TimeInForce::Gtc,
+                trigger_price: None,
+                market: DEFAULT_MARKET.to_string(),
+            })
+            .unwrap();
+        assert_eq!(trading_platform.balance_of("ALICE"), Ok(&0));
+        assert_eq!(trading_platform.balance_of("BOB"), Ok(&500));
+        assert_eq!(trading_platform.balance_of(FEE_ACCOUNT), Ok(&5));
+        assert_eq!(
+            trading_platform.transactions.last(),
+            Some(&Tx::Fee {
+                account: "ALICE".to_string(),
+                amount: 5
+            })
+        );
+
+        let month = crate::invoices::month_of(now_millis());
+        let invoices = trading_platform.generate_invoices(&month).unwrap();
+        assert_eq!(invoices.len(), 1);
+        assert_eq!(invoices[0].total, 5);
+    }
+
+    #[test]
+    fn test_TradingPlatform_order_settles_price_improvement_at_the_resting_price() {
+        let mut trading_platform = TradingPlatform::new();
+        trading_platform.deposit("ALICE", 100).unwrap();
+        trading_platform.deposit("BOB", 100).unwrap();
+        let order = |side, signer: &str, price| Order {
+            price,
+            amount: 5,
+            side,
+            signer: signer.to_string(),
+            client_order_id: None,
+            signature: None,
+            order_type: OrderType::Limit,
+            time_in_force: TimeInForce::Gtc,
+            trigger_price: None,
+            market: DEFAULT_MARKET.to_string(),
+        };
+        trading_platform
+            .order(order(Side::Sell, "ALICE", 10))
+            .unwrap();
+        let buy = trading_platform.order(order(Side::Buy, "BOB", 12)).unwrap();
+        assert_eq!(buy.settlement.map(|s| s.gross), Some(50));
+        trading_platform
+            .order(order(Side::Buy, "ALICE", 12))
+            .unwrap();
+        trading_platform
+            .order(order(Side::Sell, "BOB", 10))
+            .unwrap();
+
+        // BOB paid 50 instead of 60 and got 60 instead of 50
+        assert_eq!(trading_platform.balance_of("BOB"), Ok(&110));
+        assert_eq!(trading_platform.balance_of("ALICE"), Ok(&90));
+        assert_eq!(
+            trading_platform
+                .trades
+                .iter()
+                .map(|t| (t.taker_side.clone(), t.limit_price, t.price))
+                .collect::<Vec<_>>(),
+            vec![(Side::Buy, Some(12), 10), (Side::Sell, Some(10), 12)]
+        );
+    }
+
+    #[test]
+    fn test_TradingPlatform_order_charges_maker_and_taker_fees() {
+        let mut trading_platform = TradingPlatform::new();
+        trading_platform.taker_fee_bps = 100;
+        trading_platform.maker_fee_bps = 20;
+        trading_platform.deposit("ALICE", 1_000).unwrap();
+        trading_platform.deposit("BOB", 1_000).unwrap();
+        let order = |side, signer: &str| Order {
+            price: 50,
+            amount: 10,
+            side,
+            signer: signer.to_string(),
+            client_order_id: None,
+            signature: None,
+            order_type: OrderType::Limit,
+            time_in_force: TimeInForce::Gtc,
+            trigger_price: None,
+            market: DEFAULT_MARKET.to_string(),
+        };
+        let resting = trading_platform.order(order(Side::Buy, "BOB")).unwrap();
+        assert_eq!(resting.settlement, None);
+
+        let receipt = trading_platform.order(order(Side::Sell, "ALICE")).unwrap();
+        assert_eq!(
+            receipt.settlement,
+            Some(Settlement {
+                gross: 500,
+                fee: 5,
+                net: 495
+            })
+        );
+        assert_eq!(trading_platform.balance_of("ALICE"), Ok(&1_495));
+        assert_eq!(trading_platform.balance_of("BOB"), Ok(&499));
+        assert_eq!(trading_platform.balance_of(FEE_ACCOUNT), Ok(&6));
+        assert_eq!(
+            trading_platform.transactions[trading_platform.transactions.len() - 2..],
+            [
+                Tx::Fee {
+                    account: "ALICE".to_string(),
+                    amount: 5
+                },
+                Tx::Fee {
+                    account: "BOB".to_string(),
+                    amount: 1
+                }
+            ]
+        );
+        assert_eq!(
+            (
+                trading_platform.trades[0].taker_fee,
+                trading_platform.trades[0].maker_fee
+            ),
+            (5, 1)
+        );
+    }
+
+    #[test]
+    fn test_TradingPlatform_order_changes_nothing_if_a_fee_fails() {
+        let mut trading_platform = TradingPlatform::new();
+        trading_platform.maker_fee_bps = 100;
+        trading_platform.deposit("ALICE", 1_000).unwrap();
+        trading_platform.deposit("BOB", 101).unwrap();
+        let order = |side, signer: &str| Order {
+            price: 10,
+            amount: 10,
+            side,
+            signer: signer.to_string(),
+            client_order_id: None,
+            signature: None,
+            order_type: OrderType::Limit,
+            time_in_force: TimeInForce::Gtc,
+            trigger_price: None,
+            market: DEFAULT_MARKET.to_string(),
+        };
+        trading_platform.order(order(Side::Buy, "BOB")).unwrap();
+        // BOB can still pay for the match, but not for his maker fee
+        trading_platform.accounts.withdraw("BOB", 1).unwrap();
+        let transactions = trading_platform.transactions.len();
+
+        assert_eq!(
+            trading_platform.order(order(Side::Sell, "ALICE")),
+            Err(ApplicationError::AccountUnderFunded("BOB".to_string(), 1))
+        );
+        assert_eq!(trading_platform.balance_of("ALICE"), Ok(&1_000));
+        assert_eq!(trading_platform.balance_of("BOB"), Ok(&100));
+        assert!(trading_platform.balance_of(FEE_ACCOUNT).is_err());
+        assert_eq!(trading_platform.transactions.len(), transactions);
+        assert!(trading_platform.trades.is_empty());
+        // BOB's bid is still there, in the book, its deltas, and the book log
+        assert_eq!(
+            trading_platform
+                .orderbook()
+                .iter()
+                .map(|o| (o.signer.as_str(), o.remaining))
+                .collect::<Vec<_>>(),
+            vec![("BOB", 10)]
+        );
+        assert_eq!(trading_platform.book_updates.depth().bids.quantity, 10);
+        assert_eq!(trading_platform.book_updates.depth().asks.quantity, 0);
+        assert_eq!(trading_platform.book_log.events().len(), 1);
+    }
+
+    #[test]
+    fn test_TradingPlatform_order_rejects_prices_outside_collar() {
+        let mut trading_platform = TradingPlatform::new();
+        trading_platform.price_collar_bps = Some(1_000);
+        trading_platform.deposit("ALICE", 1_000).unwrap();
+        trading_platform.deposit("BOB", 1_000).unwrap();
+        let order = |price, side, signer: &str| Order {
+            price,
+            amount: 1,
+            side,
+            signer: signer.to_string(),
+            client_order_id: None,
+            signature: None,
+            order_type: OrderType::Limit,
+            time_in_force: TimeInForce::Gtc,
+            trigger_price: None,
+            market: DEFAULT_MARKET.to_string(),
+        };
+
+        // Without a reference price there's nothing to compare to
+        trading_platform
+            .order(order(100, Side::Sell, "BOB"))
+            .unwrap();
+        trading_platform
+            .order(order(100, Side::Buy, "ALICE"))
+            .unwrap();
+        assert_eq!(
+            trading_platform.order(order(111, Side::Buy, "ALICE")),
+            Err(ApplicationError::OutsidePriceCollar(111, 100))
+        );
+        assert!(trading_platform
+            .order(order(110, Side::Buy, "ALICE"))
+            .is_ok());
+        assert!(trading_platform
+            .place_order(order(1_000, Side::Sell, "BOB"), true)
+            .is_ok());
+    }
+
+    #[test]
+    fn test_TradingPlatform_order_trips_the_circuit_breaker_away_from_the_last_price() {
+        let mut trading_platform = TradingPlatform::new();
+        trading_platform.circuit_breaker.band_bps = Some(1_000);
+        trading_platform.deposit("ALICE", 1_000).unwrap();
+        trading_platform.deposit("BOB", 1_000).unwrap();
+        let order = |price, side, signer: &str| Order {
+            price,
+            amount: 1,
+            side,
+            signer: signer.to_string(),
+            client_order_id: None,
+            signature: None,
+            order_type: OrderType::Limit,
+            time_in_force: TimeInForce::Gtc,
+            trigger_price: None,
+            market: DEFAULT_MARKET.to_string(),
+        };
+        trading_platform
+            .order(order(100, Side::Sell, "BOB"))
+            .unwrap();
+        trading_platform
+            .order(order(100, Side::Buy, "ALICE"))
+            .unwrap();
+        // Resting far away is fine, executing there isn't
+        trading_platform
+            .order(order(120, Side::Sell, "BOB"))
+            .unwrap();
+        assert_eq!(
+            trading_platform.order(order(120, Side::Buy, "ALICE")),
+            Err(ApplicationError::PriceBandExceeded(120, 100))
+        );
+
+        trading_platform.circuit_breaker.halt_millis = 60_000;
+        assert_eq!(
+            trading_platform.order(order(120, Side::Buy, "ALICE")),
+            Err(ApplicationError::TradingHalted(
+                DEFAULT_MARKET.to_string(),
+                60
+            ))
+        );
+        assert!(matches!(
+            trading_platform.order(order(90, Side::Buy, "ALICE")),
+            Err(ApplicationError::TradingHalted(_, _))
+        ));
+        assert_eq!(
+            trading_platform.market_info(DEFAULT_MARKET).unwrap().status,
+            MarketStatus::Halted
+        );
+        assert_eq!(trading_platform.orderbook().len(), 1);
+    }
+
+    #[test]
+    fn test_TradingPlatform_oracle_price_is_the_reference_before_trading() {
+        let mut trading_platform = TradingPlatform::new();
+        trading_platform.price_collar_bps = Some(1_000);
+        trading_platform.deposit("ALICE", 1_000).unwrap();
+        assert_eq!(
+            trading_platform.set_oracle_price("NOPE", 100),
+            Err(ApplicationError::MarketNotFound("NOPE".to_string()))
+        );
+        trading_platform
+            .set_oracle_price(DEFAULT_MARKET, 100)
+            .unwrap();
+
+        let order = Order {
+            price: 111,
+            amount: 1,
+            side: Side::Buy,
+            signer: "ALICE".to_string(),
+            client_order_id: None,
+            signature: None,
+            order_type: OrderType::Limit,
+            time_in_force: TimeInForce::Gtc,
+            trigger_price: None,
+            market: DEFAULT_MARKET.to_string(),
+        };
+        assert_eq!(
+            trading_platform.order(order),
+            Err(ApplicationError::OutsidePriceCollar(111, 100))
+        );
+        let ticker = trading_platform.ticker(DEFAULT_MARKET, 0).unwrap();
+        assert_eq!(ticker.last_price, None);
+        assert_eq!(
+            ticker.reference,
+            Some(ReferencePrice {
+                price: 100,
+                source: ReferenceSource::Oracle
+            })
+        );
+    }
+
+    #[test]
+    fn test_TradingPlatform_ticker_reports_the_top_of_the_book() {
+        let mut trading_platform = TradingPlatform::new();
+        trading_platform.deposit("ALICE", 1_000).unwrap();
+        trading_platform.deposit("BOB", 1_000).unwrap();
+        let order = |price, amount, side, signer: &str| Order {
+            price,
+            amount,
+            side,
+            signer: signer.to_string(),
+            client_order_id: None,
+            signature: None,
+            order_type: OrderType::Limit,
+            time_in_force: TimeInForce::Gtc,
+            trigger_price: None,
+            market: DEFAULT_MARKET.to_string(),
+        };
+        trading_platform
+            .order(order(9, 1, Side::Buy, "ALICE"))
+            .unwrap();
+        let ticker = trading_platform.ticker(DEFAULT_MARKET, 0).unwrap();
+        assert_eq!((ticker.best_bid, ticker.best_ask), (Some(9), None));
+        assert_eq!(ticker.spread, None);
+
+        trading_platform
+            .order(order(11, 2, Side::Sell, "ALICE"))
+            .unwrap();
+        trading_platform
+            .order(order(11, 1, Side::Buy, "BOB"))
+            .unwrap();
+        let ticker = trading_platform.ticker(DEFAULT_MARKET, 0).unwrap();
+        assert_eq!((ticker.best_bid, ticker.best_ask), (Some(9), Some(11)));
+        assert_eq!(ticker.spread, Some(2));
+        assert_eq!(ticker.last_price, Some(11));
+    }
+
+    #[test]
+    fn test_TradingPlatform_cancel_order_removes_the_order_from_the_book() {
+        let mut trading_platform = TradingPlatform::new();
+        trading_platform.deposit("ALICE", 1_000).unwrap();
+        let receipt = trading_platform
+            .order(Order {
+                price: 10,
+                amount: 3,
+                side: Side::Buy,
+                signer: "ALICE".to_string(),
+                client_order_id: None,
+                signature: None,
+                order_type: OrderType::Limit,
+                time_in_force: TimeInForce::Gtc,
+                trigger_price: None,
+                market: DEFAULT_MARKET.to_string(),
+            })
+            .unwrap();
+        let seq = trading_platform.book_snapshot().seq;
+
+        assert_eq!(
+            trading_platform.cancel_order(receipt.ordinal, "BOB", 0),
+            Err(ApplicationError::OrderNotOwned(receipt.ordinal))
+        );
+        let cancelled = trading_platform
+            .cancel_order(receipt.ordinal, "ALICE", 0)
+            .unwrap();
+        assert_eq!(cancelled.remaining, 3);
+        assert!(trading_platform.orderbook().is_empty());
+        assert_eq!(
+            trading_platform.book_updates.since(seq).unwrap().deltas,
+            vec![BookDelta {
+                seq: seq + 1,
+                side: Side::Buy,
+                price: 10,
+                quantity: 0,
+                action: DeltaAction::Remove,
+            }]
+        );
+        assert_eq!(
+            trading_platform.cancel_order(receipt.ordinal, "ALICE", 0),
+            Err(ApplicationError::OrderNotFound(receipt.ordinal))
+        );
+    }
+
+    #[test]
+    fn test_TradingPlatform_expire_orders_sweeps_expired_gtt_orders() {
+        let mut trading_platform = TradingPlatform::new();
+        trading_platform.deposit("ALICE", 1_000).unwrap();
+        let now = now_millis();
+        let order = |price, time_in_force| Order {
+            price,
+            amount: 3,
+            side: Side::Buy,
+            signer: "ALICE".to_string(),
+            client_order_id: None,
+            signature: None,
+            order_type: OrderType::Limit,
+            time_in_force,
+            trigger_price: None,
+            market: DEFAULT_MARKET.to_string(),
+        };
+        assert_eq!(
+            trading_platform.order(order(10, TimeInForce::Gtt(now - 1))),
+            Err(ApplicationError::InvalidExpiry(now - 1))
+        );
+        let expiring = trading_platform
+            .order(order(10, TimeInForce::Gtt(now + 60_000)))
+            .unwrap();
+        trading_platform.order(order(11, TimeInForce::Gtc)).unwrap();
+
+        assert!(trading_platform.expire_orders(now + 59_999).is_empty());
+        let expired = trading_platform.expire_orders(now + 60_000);
+        assert_eq!(expired.len(), 1);
+        assert_eq!(expired[0].order_id, expiring.order_id);
+        assert_eq!(trading_platform.orderbook().len(), 1);
+
+        // The expiry is part of the book's history
+        let before = trading_platform
+            .orderbook_at(PointInTime::Timestamp(now + 59_999), &BookQuery::default());
+        assert_eq!(before.len(), 2);
+        let after = trading_platform
+            .orderbook_at(PointInTime::Timestamp(now + 60_000), &BookQuery::default());
+        assert_eq!(after.len(), 1);
+    }
+
+    #[test]
+    fn test_TradingPlatform_order_settles_triggered_stop_orders() {
+        let mut trading_platform = TradingPlatform::new();
+        for signer in ["ALICE", "BOB", "CAROL"] {
+            trading_platform.deposit(signer, 1_000).unwrap();
+        }
+        let order = |signer: &str, side, price, order_type, trigger_price| Order {
+            price,
+            amount: 1,
+            side,
+            signer: signer.to_string(),
+            client_order_id: None,
+            signature: None,
+            order_type,
+            time_in_force: TimeInForce::Gtc,
+            trigger_price,
+            market: DEFAULT_MARKET.to_string(),
+        };
+        for price in [10, 10, 12] {
+            trading_platform
+                .order(order("ALICE", Side::Sell, price, OrderType::Limit, None))
+                .unwrap();
+        }
+        let stop_loss = trading_platform
+            .order(order("CAROL", Side::Buy, 0, OrderType::Market, Some(10)))
+            .unwrap();
+        let stop_limit = trading_platform
+            .order(order("BOB", Side::Sell, 9, OrderType::Limit, Some(9)))
+            .unwrap();
+        assert!(stop_loss.matches.is_empty());
+        assert_eq!(trading_platform.orderbook().len(), 3);
+        // Waiting stops aren't in the book
+        let seq = trading_platform.book_snapshot().seq;
+        trading_platform
+            .cancel_order(stop_limit.order_id, "BOB", 0)
+            .unwrap();
+        assert_eq!(trading_platform.book_snapshot().seq, seq);
+
+        trading_platform
+            .order(order("BOB", Side::Buy, 10, OrderType::Limit, None))
+            .unwrap();
+        let trades = trading_platform.trades.clone();
+        assert_eq!(trades.len(), 2);
+        assert_eq!(
+            (trades[1].ordinal, trades[1].taker.as_str(), trades[1].price),
+            (stop_loss.ordinal, "CAROL", 10)
+        );
+        assert_eq!(trading_platform.balance_of("CAROL"), Ok(&990));
+        assert_eq!(trading_platform.orderbook().len(), 1);
+
+        let lifecycle = trading_platform
+            .order_lifecycle(stop_loss.order_id, "CAROL")
+            .unwrap();
+        assert_eq!(
+            lifecycle.events.iter().map(|e| e.state).collect::<Vec<_>>(),
+            vec![
+                OrderState::Accepted,
+                OrderState::Triggered,
+                OrderState::Filled
+            ]
+        );
+    }
+
+    #[test]
+    fn test_TradingPlatform_order_cancels_triggered_stops_the_signer_cant_pay_for() {
+        let mut trading_platform = TradingPlatform::new();
+        trading_platform.deposit("MAKER", 1_000).unwrap();
+        trading_platform.deposit("ALICE", 100).unwrap();
+        trading_platform.deposit("BOB", 1_000).unwrap();
+        let order = |signer: &str, side, amount, price, order_type, trigger_price| Order {
+            price,
+            amount,
+            side,
+            signer: signer.to_string(),
+            client_order_id: None,
+            signature: None,
+            order_type,
+            time_in_force: TimeInForce::Gtc,
+            trigger_price,
+            market: DEFAULT_MARKET.to_string(),
+        };
+        for (amount, price) in [(1, 10), (20, 50)] {
+            trading_platform
+                .order(order(
+                    "MAKER",
+                    Side::Sell,
+                    amount,
+                    price,
+                    OrderType::Limit,
+                    None,
+                ))
+                .unwrap();
+        }
+        // ALICE can pay for 10 units at the trigger price, but not at 50
+        let stop = trading_platform
+            .order(order(
+                "ALICE",
+                Side::Buy,
+                10,
+                0,
+                OrderType::Market,
+                Some(10),
+            ))
+            .unwrap();
+
+        let bob_order = Order {
+            client_order_id: Some("b-1".to_string()),
+            ..order("BOB", Side::Buy, 1, 10, OrderType::Limit, None)
+        };
+        let receipt = trading_platform.order(bob_order.clone()).unwrap();
+        assert_eq!(receipt.filled, 1);
+        assert_eq!(trading_platform.balance_of("BOB"), Ok(&990));
+        assert_eq!(trading_platform.balance_of("MAKER"), Ok(&1_010));
+        assert_eq!(trading_platform.balance_of("ALICE"), Ok(&100));
+        assert_eq!(trading_platform.trades.len(), 1);
+        assert_eq!(
+            trading_platform
+                .matching_engine
+                .level_quantity(&Side::Sell, 50),
+            20
+        );
+        assert!(trading_platform.matching_engine.stops().is_empty());
+        // A retry is recognized as a duplicate
+        assert!(matches!(
+            trading_platform.order(bob_order),
+            Err(ApplicationError::DuplicateOrder(..))
+        ));
+
+        let lifecycle = trading_platform
+            .order_lifecycle(stop.order_id, "ALICE")
+            .unwrap();
+        assert_eq!(
+            lifecycle.events.iter().map(|e| e.state).collect::<Vec<_>>(),
+            vec![OrderState::Accepted, OrderState::Cancelled]
+        );
+    }
+
+    #[test]
+    fn test_TradingPlatform_amend_order_settles_and_records_the_amendment() {
+        let mut trading_platform = TradingPlatform::new();
+        trading_platform.deposit("ALICE", 1_000).unwrap();
+        trading_platform.deposit("BOB", 1_000).unwrap();
+        let order = |signer: &str, side, price| Order {
+            price,
+            amount: 2,
+            side,
+            signer: signer.to_string(),
+            client_order_id: None,
+            signature: None,
+            order_type: OrderType::Limit,
+            time_in_force: TimeInForce::Gtc,
+            trigger_price: None,
+            market: DEFAULT_MARKET.to_string(),
+        };
+        let ask = trading_platform
+            .order(order("ALICE", Side::Sell, 12))
+            .unwrap();
+        trading_platform.order(order("BOB", Side::Buy, 10)).unwrap();
+        let amend = |signer: &str, price, amount| AmendRequest {
+            signer: signer.to_string(),
+            price,
+            amount,
+            signature: None,
+        };
+        assert_eq!(
+            trading_platform.amend_order(ask.order_id, amend("BOB", 10, 2)),
+            Err(ApplicationError::OrderNotOwned(ask.order_id))
+        );
+
+        let receipt = trading_platform
+            .amend_order(ask.order_id, amend("ALICE", 10, 3))
+            .unwrap();
+        assert_eq!((receipt.order_id, receipt.filled), (ask.order_id, 2));
+        assert_eq!(trading_platform.balance_of("ALICE"), Ok(&1_020));
+        assert_eq!(trading_platform.trades[0].ordinal, receipt.ordinal);
+
+        let lifecycle = trading_platform
+            .order_lifecycle(ask.order_id, "ALICE")
+            .unwrap();
+        assert_eq!(
+            lifecycle
+                .events
+                .iter()
+                .map(|e| (e.state, e.amount, e.remaining))
+                .collect::<Vec<_>>(),
+            vec![
+                (OrderState::Accepted, 2, 2),
+                (OrderState::Amended, 3, 3),
+                (OrderState::PartiallyFilled, 2, 1)
+            ]
+        );
+        assert!(lifecycle.events[2].fill.as_ref().unwrap().taker);
+    }
+
+    #[test]
+    fn test_TradingPlatform_amend_order_amends_orders_of_listed_markets() {
+        let mut trading_platform = TradingPlatform::new();
+        trading_platform.deposit("ALICE", 1_000).unwrap();
+        trading_platform.deposit("BOB", 1_000).unwrap();
+        trading_platform
+            .list_market("ETH-USD", MarketConfig::default())
+            .unwrap();
+        let order = |signer: &str, side, price| Order {
+            price,
+            amount: 2,
+            side,
+            signer: signer.to_string(),
+            client_order_id: None,
+            signature: None,
+            order_type: OrderType::Limit,
+            time_in_force: TimeInForce::Gtc,
+            trigger_price: None,
+            market: "ETH-USD".to_string(),
+        };
+        let ask = trading_platform
+            .order(order("ALICE", Side::Sell, 12))
+            .unwrap();
+        trading_platform.order(order("BOB", Side::Buy, 10)).unwrap();
+
+        let receipt = trading_platform
+            .amend_order(
+                ask.order_id,
+                AmendRequest {
+                    signer: "ALICE".to_string(),
+                    price: 10,
+                    amount: 2,
+                    signature: None,
+                },
+            )
+            .unwrap();
+        assert_eq!((receipt.order_id, receipt.filled), (ask.order_id, 2));
+        assert_eq!(trading_platform.balance_of("ALICE"), Ok(&1_020));
+        assert_eq!(trading_platform.trades[0].market, "ETH-USD");
+        assert!(trading_platform
+            .book("ETH-USD")
+            .unwrap()
+            .orders()
+            .is_empty());
+    }
+
+    #[test]
+    fn test_TradingPlatform_cancel_matching_cancels_the_orders_matching_the_filter() {
+        let mut trading_platform = TradingPlatform::new();
+        trading_platform.deposit("ALICE", 1_000).unwrap();
+        trading_platform.deposit("BOB", 1_000).unwrap();
+        let order = |signer: &str, side, price| Order {
+            price,
+            amount: 1,
+            side,
+            signer: signer.to_string(),
+            client_order_id: None,
+            signature: None,
+            order_type: OrderType::Limit,
+            time_in_force: TimeInForce::Gtc,
+            trigger_price: None,
+            market: DEFAULT_MARKET.to_string(),
+        };
+        let mut ids = vec![];
+        for (side, price) in [(Side::Sell, 20), (Side::Sell, 30), (Side::Buy, 10)] {
+            ids.push(
+                trading_platform
+                    .order(order("ALICE", side, price))
+                    .unwrap()
+                    .order_id,
+            );
+        }
+        trading_platform
+            .order(order("BOB", Side::Sell, 25))
+            .unwrap();
+        let filter = CancelFilter {
+            signer: "ALICE".to_string(),
+            market: None,
+            side: Some(Side::Sell),
+            min_price: None,
+            max_price: Some(25),
+            older_than_ms: None,
+        };
+
+        let now = now_millis();
+        let unknown = CancelFilter {
+            market: Some("XYZ".to_string()),
+            ..filter.clone()
+        };
+        assert_eq!(
+            trading_platform.cancel_matching(&unknown, now),
+            Err(ApplicationError::MarketNotFound("XYZ".to_string()))
+        );
+        // The orders were placed just now
+        let old = CancelFilter {
+            older_than_ms: Some(60_000),
+            ..filter.clone()
+        };
+        assert_eq!(trading_platform.cancel_matching(&old, now), Ok(vec![]));
+
+        assert_eq!(
+            trading_platform.cancel_matching(&filter, now),
+            Ok(vec![ids[0]])
+        );
+        let all = CancelFilter {
+            side: None,
+            max_price: None,
+            ..filter
+        };
+        assert_eq!(
+            trading_platform.cancel_matching(&all, now + 1),
+            Ok(vec![ids[1], ids[2]])
+        );
+        assert_eq!(trading_platform.orderbook().len(), 1);
+        // The book's history knows of the cancellations
+        let book =
+            trading_platform.orderbook_at(PointInTime::Timestamp(now + 1), &BookQuery::default());
+        assert_eq!(book.len(), 1);
+    }
+
+    #[test]
+    fn test_TradingPlatform_order_rejects_duplicate_client_order_ids() {
+        let mut trading_platform = TradingPlatform::new();
+        trading_platform.deposit("ALICE", 1_000).unwrap();
+        trading_platform.deposit("BOB", 1_000).unwrap();
+        let order = |signer: &str, client_order_id: &str| Order {
+            price: 10,
+            amount: 1,
+            side: Side::Sell,
+            signer: signer.to_string(),
+            client_order_id: Some(client_order_id.to_string()),
+            signature: None,
+            order_type: OrderType::Limit,
+            time_in_force: TimeInForce::Gtc,
+            trigger_price: None,
+            market: DEFAULT_MARKET.to_string(),
+        };
+
+        let receipt = trading_platform.order(order("ALICE", "a-1")).unwrap();
+        assert_eq!(
+            trading_platform.order(order("ALICE", "a-1")),
+            Err(ApplicationError::DuplicateOrder(
+                "a-1".to_string(),
+                Box::new(receipt.clone())
+            ))
+        );
+        assert!(trading_platform.order(order("ALICE", "a-2")).is_ok());
+        assert!(trading_platform.order(order("BOB", "a-1")).is_ok());
+        assert_eq!(trading_platform.orderbook().len(), 3);
+    }
+
+    #[test]
+    fn test_TradingPlatform_order_throttles_accounts() {
+        let mut trading_platform = TradingPlatform::new();
+        trading_platform.throttle.max_orders_per_sec = Some(2);
+        trading_platform.deposit("ALICE", 1_000).unwrap();
+        trading_platform.deposit("BOB", 1_000).unwrap();
+        let order = |signer: &str| Order {
+            price: 10,
+            amount: 1,
+            side: Side::Sell,
+            signer: signer.to_string(),
+            client_order_id: None,
+            signature: None,
+            order_type: OrderType::Limit,
+            time_in_force: TimeInForce::Gtc,
+            trigger_price: None,
+            market: DEFAULT_MARKET.to_string(),
+        };
+
+        trading_platform.order(order("ALICE")).unwrap();
+        trading_platform.order(order("ALICE")).unwrap();
+        assert!(matches!(
+            trading_platform.order(order("ALICE")),
+            Err(ApplicationError::OrderThrottled(signer, _)) if signer == "ALICE"
+        ));
+        trading_platform.order(order("BOB")).unwrap();
+        assert_eq!(trading_platform.orderbook().len(), 3);
+        assert_eq!(trading_platform.throttle.throttled(), 1);
+    }
+
+    #[test]
+    fn test_TradingPlatform_quote_places_both_sides_or_neither() {
+        let mut trading_platform = TradingPlatform::new();
+        trading_platform.deposit("ALICE", 100).unwrap();
+        trading_platform.deposit("BOB", 1_000).unwrap();
+        let order = |price, amount, side, signer: &str| Order {
+            price,
+            amount,
+            side,
+            signer: signer.to_string(),
+            client_order_id: None,
+            signature: None,
+            order_type: OrderType::Limit,
+            time_in_force: TimeInForce::Gtc,
+            trigger_price: None,
+            market: DEFAULT_MARKET.to_string(),
+        };
+        let quote = |bid_price, ask_price| QuoteRequest {
+            bid: order(bid_price, 5, Side::Buy, "ALICE"),
+            ask: order(ask_price, 5, Side::Sell, "ALICE"),
+        };
+
+        let first = trading_platform.quote(quote(9, 11)).unwrap();
+        assert_eq!((first.bid.ordinal, first.ask.ordinal), (1, 2));
+        // The bid isn't funded, so the ask isn't placed either
+        assert_eq!(
+            trading_platform.quote(quote(30, 31)),
+            Err(ApplicationError::AccountUnderFunded(
+                "ALICE".to_string(),
+                150
+            ))
+        );
+        assert_eq!(trading_platform.orderbook().len(), 2);
+
+        trading_platform
+            .order(order(11, 2, Side::Buy, "BOB"))
+            .unwrap();
+        let second = trading_platform.quote(quote(10, 12)).unwrap();
+        assert_eq!(
+            second
+                .cancelled
+                .iter()
+                .map(|o| (o.ordinal, o.remaining))
+                .collect::<Vec<_>>(),
+            vec![(1, 5), (2, 3)]
+        );
+        assert_eq!(
+            trading_platform
+                .orderbook()
+                .iter()
+                .map(|o| (o.price, o.signer.as_str()))
+                .collect::<Vec<_>>(),
+            vec![(12, "ALICE"), (10, "ALICE")]
+        );
+        assert_eq!(trading_platform.book_updates.depth().bids.quantity, 5);
+        assert_eq!(trading_platform.book_updates.depth().asks.quantity, 5);
+    }
+
+    #[test]
+    fn test_TradingPlatform_quote_settles_neither_side_if_one_fails() {
+        let mut trading_platform = TradingPlatform::new();
+        trading_platform.deposit("ALICE", 1_000).unwrap();
+        trading_platform.deposit("BOB", 15).unwrap();
+        let order = |price, side, signer: &str| Order {
+            price,
+            amount: 1,
+            side,
+            signer: signer.to_string(),
+            client_order_id: None,
+            signature: None,
+            order_type: OrderType::Limit,
+            time_in_force: TimeInForce::Gtc,
+            trigger_price: None,
+            market: DEFAULT_MARKET.to_string(),
+        };
+        let quote = |bid_price, ask_price| QuoteRequest {
+            bid: order(bid_price, Side::Buy, "ALICE"),
+            ask: order(ask_price, Side::Sell, "ALICE"),
+        };
+        trading_platform.quote(quote(5, 20)).unwrap();
+        trading_platform.order(order(15, Side::Buy, "BOB")).unwrap();
+        // BOB can't pay for what the ask would sell him anymore
+        trading_platform.accounts.withdraw("BOB", 1).unwrap();
+
+        assert_eq!(
+            trading_platform.quote(quote(10, 14)),
+            Err(ApplicationError::AccountUnderFunded("BOB".to_string(), 15))
+        );
+        assert_eq!(trading_platform.balance_of("ALICE"), Ok(&1_000));
+        assert_eq!(
+            trading_platform
+                .orderbook()
+                .iter()
+                .map(|o| (o.price, o.signer.as_str()))
+                .collect::<Vec<_>>(),
+            vec![(20, "ALICE"), (5, "ALICE"), (15, "BOB")]
+        );
+        assert!(trading_platform.trades.is_empty());
+
+        // The failed quote didn't lose the previous one, which is still replaced
+        trading_platform.deposit("BOB", 1).unwrap();
+        let receipt = trading_platform.quote(quote(10, 14)).unwrap();
+        assert_eq!(
+            receipt
+                .cancelled
+                .iter()
+                .map(|o| o.ordinal)
+                .collect::<Vec<_>>(),
+            vec![1, 2]
+        );
+        assert_eq!(trading_platform.balance_of("ALICE"), Ok(&1_015));
+        assert_eq!(trading_platform.trades.len(), 1);
+    }
+
+    #[test]
+    fn test_TradingPlatform_bracket_cancels_the_take_profit_when_the_stop_triggers() {
+        let mut trading_platform = TradingPlatform::new();
+        trading_platform.deposit("ALICE", 100).unwrap();
+        trading_platform.deposit("BOB", 1_000).unwrap();
+        trading_platform.deposit("CAROL", 1_000).unwrap();
+        let order = |signer: &str, side, price, trigger_price| Order {
+            price,
+            amount: 1,
+            side,
+            signer: signer.to_string(),
+            client_order_id: None,
+            signature: None,
+            order_type: OrderType::Limit,
+            time_in_force: TimeInForce::Gtc,
+            trigger_price,
+            market: DEFAULT_MARKET.to_string(),
+        };
+        let bracket = |take_profit_price| BracketRequest {
+            take_profit: order("ALICE", Side::Sell, take_profit_price, None),
+            stop_loss: order("ALICE", Side::Sell, 8, Some(9)),
+        };
+        assert!(matches!(
+            trading_platform.bracket(bracket(9)),
+            Err(ApplicationError::InvalidBracket(_))
+        ));
+        let receipt = trading_platform.bracket(bracket(12)).unwrap();
+        assert_eq!(trading_platform.orderbook().len(), 1);
+
+        trading_platform
+            .order(order("CAROL", Side::Sell, 9, None))
+            .unwrap();
+        trading_platform
+            .order(order("BOB", Side::Buy, 9, None))
+            .unwrap();
+        // The stop-loss rests at its limit, the take-profit is gone
+        assert_eq!(
+            trading_platform
+                .orderbook()
+                .iter()
+                .map(|o| (o.price, o.signer.as_str()))
+                .collect::<Vec<_>>(),
+            vec![(8, "ALICE")]
+        );
+        assert_eq!(trading_platform.book_updates.depth().asks.quantity, 1);
+        let states = |order_id| {
+            trading_platform
+                .order_lifecycle(order_id, "ALICE")
+                .unwrap()
+                .events
+                .iter()
+                .map(|e| e.state)
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            states(receipt.take_profit.order_id),
+            vec![OrderState::Accepted, OrderState::Cancelled]
+        );
+        assert_eq!(
+            states(receipt.stop_loss.order_id),
+            vec![OrderState::Accepted, OrderState::Triggered]
+        );
+    }
+
+    #[test]
+    fn test_TradingPlatform_bracket_places_nothing_if_the_take_profit_cant_settle() {
+        let mut trading_platform = TradingPlatform::new();
+        trading_platform.deposit("ALICE", 100).unwrap();
+        trading_platform.deposit("BOB", 12).unwrap();
+        let order = |signer: &str, side, price, trigger_price| Order {
+            price,
+            amount: 1,
+            side,
+            signer: signer.to_string(),
+            client_order_id: None,
+            signature: None,
+            order_type: OrderType::Limit,
+            time_in_force: TimeInForce::Gtc,
+            trigger_price,
+            market: DEFAULT_MARKET.to_string(),
+        };
+        let bracket = BracketRequest {
+            take_profit: order("ALICE", Side::Sell, 12, None),
+            stop_loss: order("ALICE", Side::Sell, 8, Some(9)),
+        };
+        trading_platform
+            .order(order("BOB", Side::Buy, 12, None))
+            .unwrap();
+        // BOB can't pay for what the take-profit would sell him anymore
+        trading_platform.accounts.withdraw("BOB", 1).unwrap();
+
+        assert_eq!(
+            trading_platform.bracket(bracket.clone()),
+            Err(ApplicationError::AccountUnderFunded("BOB".to_string(), 12))
+        );
+        assert_eq!(
+            trading_platform
+                .orderbook()
+                .iter()
+                .map(|o| (o.price, o.signer.as_str()))
+                .collect::<Vec<_>>(),
+            vec![(12, "BOB")]
+        );
+        assert!(trading_platform.matching_engine.stops().is_empty());
+        assert_eq!(trading_platform.balance_of("ALICE"), Ok(&100));
+        assert!(trading_platform.trades.is_empty());
+
+        trading_platform.deposit("BOB", 1).unwrap();
+        let receipt = trading_platform.bracket(bracket).unwrap();
+        assert_eq!(receipt.take_profit.filled, 1);
+        assert_eq!(trading_platform.balance_of("ALICE"), Ok(&112));
+    }
+
+    #[test]
+    fn test_TradingPlatform_run_auction_settles_at_the_clearing_price() {
+        let mut trading_platform = TradingPlatform::new();
+        trading_platform.taker_fee_bps = 100;
+        trading_platform.deposit("ALICE", 100).unwrap();
+        trading_platform.deposit("BOB", 1_000).unwrap();
+        let order = |signer: &str, side, price, order_type| Order {
+            price,
+            amount: 2,
+            side,
+            signer: signer.to_string(),
+            client_order_id: None,
+            signature: None,
+            order_type,
+            time_in_force: TimeInForce::Gtc,
+            trigger_price: None,
+            market: DEFAULT_MARKET.to_string(),
+        };
+        trading_platform.start_auction();
+        assert_eq!(
+            trading_platform.market_info(DEFAULT_MARKET).unwrap().status,
+            MarketStatus::Auction
+        );
+        trading_platform
+            .order(order("ALICE", Side::Sell, 10, OrderType::Limit))
+            .unwrap();
+        let bid = trading_platform
+            .order(order("BOB", Side::Buy, 12, OrderType::Limit))
+            .unwrap();
+        assert!(bid.matches.is_empty());
+        assert_eq!(
+            trading_platform.order(order("BOB", Side::Buy, 0, OrderType::Market)),
+            Err(ApplicationError::AuctionInProgress(
+                DEFAULT_MARKET.to_string()
+            ))
+        );
+        assert_eq!(trading_platform.book_updates.depth().asks.quantity, 2);
+
+        let auction = trading_platform.run_auction().unwrap();
+        assert_eq!((auction.price, auction.volume), (Some(10), 2));
+        // Nobody took liquidity, so nobody paid a taker fee
+        assert_eq!(trading_platform.balance_of("BOB"), Ok(&980));
+        assert_eq!(trading_platform.balance_of("ALICE"), Ok(&120));
+        assert_eq!(trading_platform.trades[0].taker_fee, 0);
+        assert!(trading_platform.orderbook().is_empty());
+        assert_eq!(trading_platform.book_updates.depth().asks.quantity, 0);
+        assert_eq!(
+            trading_platform.market_info(DEFAULT_MARKET).unwrap().status,
+            MarketStatus::Open
+        );
+        let lifecycle = trading_platform
+            .order_lifecycle(bid.order_id, "BOB")
+            .unwrap();
+        assert_eq!(
+            lifecycle.events.iter().map(|e| e.state).collect::<Vec<_>>(),
+            vec![OrderState::Accepted, OrderState::Filled]
+        );
+    }
+
+    #[test]
+    fn test_TradingPlatform_bust_trade_reverses_the_settlement() {
+        let mut trading_platform = TradingPlatform::new();
+        trading_platform.deposit("ALICE", 1_000).unwrap();
+        trading_platform.deposit("BOB", 1_000).unwrap();
+        let order = |price, side, signer: &str| Order {
+            price,
+            amount: 5,
+            side,
+            signer: signer.to_string(),
+            client_order_id: None,
+            signature: None,
+            order_type: OrderType::Limit,
+            time_in_force: TimeInForce::Gtc,
+            trigger_price: None,
+            market: DEFAULT_MARKET.to_string(),
+        };
+        trading_platform
+            .order(order(10, Side::Sell, "BOB"))
+            .unwrap();
+        trading_platform
+            .order(order(10, Side::Buy, "ALICE"))
+            .unwrap();
+        // A fat finger
+        trading_platform
+            .order(order(100, Side::Sell, "BOB"))
+            .unwrap();
+        trading_platform
+            .order(order(100, Side::Buy, "ALICE"))
+            .unwrap();
+        assert_eq!(trading_platform.balance_of("ALICE"), Ok(&450));
+        let mut trades = trading_platform.trade_feed.subscribe();
+
+        let busted = trading_platform.bust_trade(2, 42).unwrap();
+        assert_eq!(busted.busted, Some(42));
+        assert_eq!(trades.try_recv(), Ok(busted.clone()));
+        assert_eq!(trading_platform.trades[1], busted);
+        assert_eq!(trading_platform.balance_of("ALICE"), Ok(&950));
+        assert_eq!(trading_platform.balance_of("BOB"), Ok(&1_050));
+        assert_eq!(
+            trading_platform.transactions.last(),
+            Some(&Tx::SettlementReversed {
+                trade_id: 2,
+                from: "BOB".to_string(),
+                to: "ALICE".to_string(),
+                amount: 500
+            })
+        );
+        assert_eq!(
+            trading_platform.position_of("ALICE").unwrap(),
+            Position {
+                units: 5,
+                cost: 50,
+                realized: 0
+            }
+        );
+        assert_eq!(trading_platform.position_of("BOB").unwrap().units, -5);
+
+        assert_eq!(
+            trading_platform.bust_trade(2, 43),
+            Err(ApplicationError::TradeAlreadyBusted(2))
+        );
+        assert_eq!(
+            trading_platform.bust_trade(3, 43),
+            Err(ApplicationError::TradeNotFound(3))
+        );
+    }
+
+    #[test]
+    fn test_TradingPlatform_bust_trade_rebuilds_positions_of_the_default_market() {
+        let mut trading_platform = TradingPlatform::new();
+        trading_platform.deposit("ALICE", 1_000).unwrap();
+        trading_platform.deposit("BOB", 1_000).unwrap();
+        trading_platform
+            .list_market("ETH-USD", MarketConfig::default())
+            .unwrap();
+        let order = |side, signer: &str, market: &str| Order {
+            price: 10,
+            amount: 5,
+            side,
+            signer: signer.to_string(),
+            client_order_id: None,
+            signature: None,
+            order_type: OrderType::Limit,
+            time_in_force: TimeInForce::Gtc,
+            trigger_price: None,
+            market: market.to_string(),
+        };
+        for market in [DEFAULT_MARKET, "ETH-USD"] {
+            trading_platform
+                .order(order(Side::Sell, "BOB", market))
+                .unwrap();
+            trading_platform
+                .order(order(Side::Buy, "ALICE", market))
+                .unwrap();
+        }
+        assert_eq!(trading_platform.position_of("ALICE").unwrap().units, 5);
+
+        trading_platform.bust_trade(1, 42).unwrap();
+        // The trade in ETH-USD never moved a position
+        assert_eq!(
+            trading_platform.position_of("ALICE").unwrap(),
+            Position::default()
+        );
+        assert_eq!(
+            trading_platform.position_of("BOB").unwrap(),
+            Position::default()
+        );
+    }
+
+    #[test]
+    fn test_TradingPlatform_order_enforces_stop_loss() {
+        let mut trading_platform = TradingPlatform::new();
+        trading_platform.deposit("ALICE", 1_000).unwrap();
+        trading_platform.deposit("BOB", 1_000).unwrap();
+        let order = |price, amount, side, signer: &str| Order {
+            price,
+            amount,
+            side,
+            signer: signer.to_string(),
+            client_order_id: None,
+            signature: None,
+            order_type: OrderType::Limit,
+            time_in_force: TimeInForce::Gtc,
+            trigger_price: None,
+            market: DEFAULT_MARKET.to_string(),
+        };
+
+        // ALICE buys 10 at 10 and places another bid
+        trading_platform
+            .order(order(10, 10, Side::Sell, "BOB"))
+            .unwrap();
+        trading_platform
+            .order(order(10, 10, Side::Buy, "ALICE"))
+            .unwrap();
+        trading_platform
+            .set_stop_loss("ALICE", 15, now_millis())
+            .unwrap();
+        trading_platform
+            .order(order(5, 1, Side::Buy, "ALICE"))
+            .unwrap();
+
+        // The reference price drops to 8 after two trades, ALICE is down 20
+        trading_platform.deposit("CAROL", 1_000).unwrap();
+        trading_platform
+            .order(order(8, 1, Side::Buy, "CAROL"))
+            .unwrap();
+        trading_platform
+            .order(order(8, 1, Side::Sell, "BOB"))
+            .unwrap();
+        trading_platform
+            .order(order(8, 1, Side::Buy, "BOB"))
+            .unwrap();
+        trading_platform
+            .order(order(8, 1, Side::Sell, "ALICE"))
+            .unwrap();
+        let status = trading_platform
+            .stop_loss_of("ALICE", now_millis())
+            .unwrap();
+        assert!(status.breached);
+        assert_eq!(status.daily_pnl, -20);
+        // The open bid was cancelled
+        assert!(trading_platform.matching_engine.bids.is_empty());
+
+        assert_eq!(
+            trading_platform.order(order(8, 1, Side::Buy, "ALICE")),
+            Err(ApplicationError::StopLossBreached("ALICE".to_string()))
+        );
+        // Reducing the position is still possible
+        assert!(trading_platform
+            .order(order(8, 9, Side::Sell, "ALICE"))
+            .is_ok());
+        assert!(trading_platform
+            .order(order(8, 1, Side::Sell, "ALICE"))
+            .is_err());
+    }
+
+    #[test]
+    fn test_TradingPlatform_market_buy_stays_within_budget() {
+        let mut trading_platform = TradingPlatform::new();
+        trading_platform.deposit("ALICE", 100).unwrap();
+        trading_platform.deposit("BOB", 0).unwrap();
+        assert_eq!(
+            trading_platform.market_buy("ALICE", 50),
+            Err(ApplicationError::NoLiquidity("ALICE".to_string()))
+        );
+
+        for (price, amount) in [(10, 2), (12, 5)] {
+            trading_platform
+                .order(Order {
+                    price,
+                    amount,
+                    side: Side::Sell,
+                    signer: "BOB".to_string(),
+                    client_order_id: None,
+                    signature: None,
+                    order_type: OrderType::Limit,
+                    time_in_force: TimeInForce::Gtc,
+                    trigger_price: None,
+                    market: DEFAULT_MARKET.to_string(),
+                })
+                .unwrap();
+        }
+        // 4 units at 12 at most: 2 at 10 and 2 at 12
+        let receipt = trading_platform.market_buy("ALICE", 50).unwrap();
+        assert_eq!(receipt.matches.iter().map(|m| m.amount).sum::<u64>(), 4);
+        assert_eq!(trading_platform.balance_of("ALICE"), Ok(&56));
+        assert!(trading_platform.matching_engine.bids.is_empty());
+    }
+
+    #[test]
+    fn test_TradingPlatform_market_buy_pays_the_taker_fee_from_the_budget() {
+        let mut trading_platform = TradingPlatform::new();
+        trading_platform.taker_fee_bps = 2_000;
+        trading_platform.deposit("ALICE", 100).unwrap();
+        trading_platform.deposit("BOB", 0).unwrap();
+        let ask = |price, amount| Order {
+            price,
+            amount,
+            side: Side::Sell,
+            signer: "BOB".to_string(),
+            client_order_id: None,
+            signature: None,
+            order_type: OrderType::Limit,
+            time_in_force: TimeInForce::Gtc,
+            trigger_price: None,
+            market: DEFAULT_MARKET.to_string(),
+        };
+        // An ask at no price can't size the buy
+        trading_platform.matching_engine.process(ask(0, 1)).unwrap();
+        for (price, amount) in [(10, 2), (12, 5)] {
+            trading_platform.order(ask(price, amount)).unwrap();
+        }
+
+        // 4 units at 12 would cost 44 and a fee of 8, 3 units are bought instead: the free one and 2 at 10 for 20 and
+        // a fee of 4
+        let receipt = trading_platform.market_buy("ALICE", 50).unwrap();
+        assert_eq!(receipt.matches.iter().map(|m| m.amount).sum::<u64>(), 3);
+        assert_eq!(trading_platform.balance_of("ALICE"), Ok(&76));
+        assert!(trading_platform.matching_engine.bids.is_empty());
+    }
+
+    #[test]
+    fn test_TradingPlatform_order_rejects_notionals_that_overflow() {
+        let mut trading_platform = TradingPlatform::new();
+        trading_platform.markets.insert(
+            DEFAULT_MARKET,
+            MarketConfig {
+                sandbox: true,
+                ..MarketConfig::default()
+            },
+        );
+        trading_platform.deposit("ALICE", 100).unwrap();
+        trading_platform.faucet("SANDY", 100).unwrap();
+        let order = |signer: &str| Order {
+            price: u64::MAX,
+            amount: 2,
+            side: Side::Buy,
+            signer: signer.to_string(),
+            client_order_id: None,
+            signature: None,
+            order_type: OrderType::Limit,
+            time_in_force: TimeInForce::Gtc,
+            trigger_price: None,
+            market: DEFAULT_MARKET.to_string(),
+        };
+
+        for signer in ["ALICE", "SANDY"] {
+            assert_eq!(
+                trading_platform.order(order(signer)),
+                Err(ApplicationError::InvalidOrder(OrderConstraint::Notional {
+                    amount: 2,
+                    price: u64::MAX,
+                }))
+            );
+        }
+    }
+
+    #[test]
+    fn test_TradingPlatform_order_checks_market_orders_against_the_book() {
+        let mut trading_platform = TradingPlatform::new();
+        trading_platform.deposit("ALICE", 50).unwrap();
+        trading_platform.deposit("BOB", 0).unwrap();
+        let market_buy = |amount| Order {
+            price: 0,
+            amount,
+            side: Side::Buy,
+            signer: "ALICE".to_string(),
+            client_order_id: None,
+            signature: None,
+            order_type: OrderType::Market,
+            time_in_force: TimeInForce::Gtc,
+            trigger_price: None,
+            market: DEFAULT_MARKET.to_string(),
+        };
+        assert_eq!(
+            trading_platform.order(market_buy(1)),
+            Err(ApplicationError::NoLiquidity("ALICE".to_string()))
+        );
+
+        for (price, amount) in [(10, 2), (20, 5)] {
+            trading_platform
+                .order(Order {
+                    price,
+                    amount,
+                    side: Side::Sell,
+                    signer: "BOB".to_string(),
+                    client_order_id: None,
+                    signature: None,
+                    order_type: OrderType::Limit,
+                    time_in_force: TimeInForce::Gtc,
+                    trigger_price: None,
+                    market: DEFAULT_MARKET.to_string(),
+                })
+                .unwrap();
+        }
+        // 2 at 10 and 2 at 20 cost more than the deposit
+        assert_eq!(
+            trading_platform.order(market_buy(4)),
+            Err(ApplicationError::AccountUnderFunded(
+                "ALICE".to_string(),
+                60
+            ))
+        );
+        trading_platform.max_slippage_bps = Some(5_000);
+        assert_eq!(
+            trading_platform.order(market_buy(3)),
+            Err(ApplicationError::SlippageExceeded(20, 10))
+        );
+
+        let receipt = trading_platform.order(market_buy(2)).unwrap();
+        assert_eq!(receipt.matches.iter().map(|m| m.amount).sum::<u64>(), 2);
+        assert_eq!(trading_platform.balance_of("ALICE"), Ok(&30));
+        assert!(trading_platform.matching_engine.bids.is_empty());
+    }
+
+    #[test]
+    fn test_TradingPlatform_run_recurring_buys_executes_due_plans() {
+        let mut trading_platform = TradingPlatform::new();
+        trading_platform.deposit("ALICE", 100).unwrap();
+        trading_platform.deposit("BOB", 0).unwrap();
+        trading_platform
+            .order(Order {
+                price: 10,
+                amount: 10,
+                side: Side::Sell,
+                signer: "BOB".to_string(),
+                client_order_id: None,
+                signature: None,
+                order_type: OrderType::Limit,
+                time_in_force: TimeInForce::Gtc,
+                trigger_price: None,
+                market: DEFAULT_MARKET.to_string(),
+            })
+            .unwrap();
+        let plan = trading_platform
+            .create_recurring_buy(
+                "ALICE",
+                RecurringBuyRequest {
+                    amount: 20,
+                    interval_secs: 60,
+                    market: octopus_common::types::DEFAULT_MARKET.to_string(),
+                },
+                0,
+            )
+            .unwrap();
+
+        assert_eq!(trading_platform.run_recurring_buys(0), 1);
+        assert_eq!(trading_platform.run_recurring_buys(30_000), 0);
+        assert_eq!(trading_platform.run_recurring_buys(60_000), 1);
+        assert_eq!(trading_platform.balance_of("ALICE"), Ok(&60));
+        let executions = trading_platform
+            .recurring_buys
+            .executions("ALICE", plan.id)
+            .unwrap();
+        assert_eq!(executions.len(), 2);
+        assert!(executions.iter().all(|e| e.receipt.is_some()));
+    }
+
+    #[test]
+    fn test_TradingPlatform_withdraw_above_threshold_needs_approval() {
+        let mut trading_platform = TradingPlatform::new();
+        trading_platform.withdrawal_approval_threshold = Some(50);
+        trading_platform.deposit("ALICE", 210).unwrap();
+
+        assert_eq!(
+            trading_platform.withdraw("ALICE", 50, None),
+            Ok(Tx::Withdraw {
+                account: "ALICE".to_string(),
+                amount: 50,
+                memo: None,
+            })
+        );
+        assert_eq!(
+            trading_platform.withdraw("ALICE", 100, None),
+            Ok(Tx::WithdrawalRequested {
+                id: 1,
+                account: "ALICE".to_string(),
+                amount: 100
+            })
+        );
+        assert_eq!(
+            trading_platform.withdraw("ALICE", 60, None).unwrap(),
+            Tx::WithdrawalRequested {
+                id: 2,
+                account: "ALICE".to_string(),
+                amount: 60
+            }
+        );
+        // The pending amounts are held
+        assert_eq!(trading_platform.balance_of("ALICE"), Ok(&0));
+        assert_eq!(trading_platform.pending_withdrawals().len(), 2);
+
+        assert!(trading_platform.resolve_withdrawal(1, true).is_ok());
+        assert_eq!(
+            trading_platform.resolve_withdrawal(2, false),
+            Ok(Tx::WithdrawalRejected {
+                id: 2,
+                account: "ALICE".to_string(),
+                amount: 60
+            })
+        );
+        assert_eq!(trading_platform.balance_of("ALICE"), Ok(&60));
+        assert!(trading_platform.pending_withdrawals().is_empty());
+
+        assert_eq!(
+            trading_platform.resolve_withdrawal(1, false),
+            Err(ApplicationError::WithdrawalAlreadyResolved(1))
+        );
+        assert_eq!(
+            trading_platform.resolve_withdrawal(3, true),
+            Err(ApplicationError::WithdrawalNotFound(3))
+        );
+    }
+
+    #[test]
+    fn test_TradingPlatform_withdraw_from_cold_account_waits_for_confirmation() {
+        let mut trading_platform = TradingPlatform::new();
+        trading_platform.cold_accounts.delay_millis = 60_000;
+        trading_platform.deposit("ALICE", 100).unwrap();
+        assert_eq!(
+            trading_platform.set_cold("BOB", true, 0),
+            Err(ApplicationError::AccountNotFound("BOB".to_string()))
+        );
+        assert_eq!(trading_platform.set_cold("ALICE", true, 0), Ok(None));
+
+        assert_eq!(
+            trading_platform.withdraw("ALICE", 10, None),
+            Ok(Tx::WithdrawalRequested {
+                id: 1,
+                account: "ALICE".to_string(),
+                amount: 10
+            })
+        );
+        assert_eq!(trading_platform.balance_of("ALICE"), Ok(&90));
+        let pending = trading_platform.account_withdrawals("ALICE");
+        let release_at = pending[0].release_at.unwrap();
+        assert_eq!(pending.len(), 1);
+        assert!(trading_platform.account_withdrawals("BOB").is_empty());
+
+        assert_eq!(
+            trading_platform.confirm_withdrawal(1, "ALICE", release_at - 1_500),
+            Err(ApplicationError::WithdrawalDelayed(1, 2))
+        );
+        assert_eq!(
+            trading_platform.resolve_withdrawal(1, true),
+            Err(ApplicationError::WithdrawalNeedsConfirmation(1))
+        );
+        assert_eq!(
+            trading_platform.confirm_withdrawal(1, "BOB", release_at),
+            Err(ApplicationError::WithdrawalNotFound(1))
+        );
+        assert_eq!(
+            trading_platform.confirm_withdrawal(1, "ALICE", release_at),
+            Ok(Tx::WithdrawalApproved {
+                id: 1,
+                account: "ALICE".to_string(),
+                amount: 10
+            })
+        );
+        assert!(trading_platform.account_withdrawals("ALICE").is_empty());
+        assert!(matches!(
+            trading_platform.delete_account("ALICE"),
+            Err(ApplicationError::AccountInUse(_))
+        ));
+
+        // Admins may still stop a cold withdrawal
+        trading_platform.withdraw("ALICE", 20, None).unwrap();
+        assert!(trading_platform.resolve_withdrawal(2, false).is_ok());
+        assert_eq!(trading_platform.balance_of("ALICE"), Ok(&90));
+    }
+
+    #[test]
+    fn test_TradingPlatform_exposure_of_sums_committed_funds() {
+        let mut trading_platform = TradingPlatform::new();
+        trading_platform.taker_fee_bps = 100;
+        trading_platform.withdrawal_approval_threshold = Some(50);
+        trading_platform.deposit("ALICE", 1_000).unwrap();
+        trading_platform.hold("ALICE", 100).unwrap();
+        trading_platform.withdraw("ALICE", 200, None).unwrap();
+        for (side, price) in [(Side::Buy, 10), (Side::Sell, 20)] {
+            trading_platform
+                .order(Order {
+                    price,
+                    amount: 30,
+                    side,
+                    signer: "ALICE".to_string(),
+                    client_order_id: None,
+                    signature: None,
+                    order_type: OrderType::Limit,
+                    time_in_force: TimeInForce::Gtc,
+                    trigger_price: None,
+                    market: DEFAULT_MARKET.to_string(),
+                })
+                .unwrap();
+        }
+
+        let exposure = trading_platform.exposure_of("ALICE").unwrap();
+        assert_eq!(exposure.balance, 700);
+        assert_eq!(exposure.held, 100);
+        assert_eq!(exposure.reserved, 200);
+        assert_eq!(exposure.markets[0].open_buy_notional, 300);
+        assert_eq!(exposure.markets[0].open_sell_notional, 600);
+        // 300 for the buy order plus 3 of fees
+        assert_eq!(exposure.trading_power, 397);
+        assert_eq!(
+            trading_platform.exposure_of("BOB"),
+            Err(ApplicationError::AccountNotFound("BOB".to_string()))
+        );
+    }
+
+    #[test]
+    fn test_TradingPlatform_gateway_deposit_is_idempotent() {
+        let mut trading_platform = TradingPlatform::new();
+        let notification = DepositNotification {
+            id: "n1".to_string(),
+            account: "ALICE".to_string(),
+            amount: 100,
+            currency: "USD".to_string(),
+        };
+
+        let tx = trading_platform
+            .gateway_deposit(notification.clone())
+            .unwrap();
+        assert_eq!(
+            trading_platform.gateway_deposit(notification.clone()),
+            Ok(tx)
+        );
+        assert_eq!(trading_platform.balance_of("ALICE"), Ok(&100));
+        assert_eq!(trading_platform.transactions.len(), 1);
+
+        assert_eq!(
+            trading_platform.gateway_deposit(DepositNotification {
+                amount: 200,
+                ..notification
+            }),
+            Err(ApplicationError::DuplicateNotification("n1".to_string()))
+        );
+        assert_eq!(trading_platform.balance_of("ALICE"), Ok(&100));
+    }
+
+    #[test]
+    fn test_TradingPlatform_order_requires_deposit_to_order() {
+        let mut trading_platform = TradingPlatform::new();
+
+        assert_eq!(
+            trading_platform.order(Order {
+                price: 10,
+                amount: 1,
+                side: Side::Sell,
+                signer: "ALICE".to_string(),
+                client_order_id: None,
+                signature: None,
+                order_type: OrderType::Limit,
+                time_in_force: TimeInForce::Gtc,
+                trigger_price: None,
+                market: DEFAULT_MARKET.to_string(),
+            }),
+            Err(ApplicationError::AccountNotFound("ALICE".to_string()))
+        );
+        assert!(trading_platform.matching_engine.asks.is_empty());
+        assert!(trading_platform.matching_engine.bids.is_empty());
+    }
+
+    #[test]
+    fn test_TradingPlatform_balance_of_at_returns_past_balances() {
+        let mut trading_platform = TradingPlatform::new();
+        trading_platform.deposit("ALICE", 100).unwrap();
+        trading_platform.deposit("BOB", 100).unwrap();
+        for (side, signer) in [(Side::Sell, "ALICE"), (Side::Buy, "BOB")] {
+            trading_platform
+                .order(Order {
+                    price: 10,
+                    amount: 1,
+                    side,
+                    signer: signer.to_string(),
+                    client_order_id: None,
+                    signature: None,
+                    order_type: OrderType::Limit,
+                    time_in_force: TimeInForce::Gtc,
+                    trigger_price: None,
+                    market: DEFAULT_MARKET.to_string(),
+                })
+                .unwrap();
+        }
+        trading_platform.withdraw("ALICE", 50, None).unwrap();
+
+        assert_eq!(
+            trading_platform.balance_of_at("ALICE", PointInTime::Ordinal(1)),
+            Ok(100)
+        );
+        assert_eq!(
+            trading_platform.balance_of_at("BOB", PointInTime::Ordinal(2)),
+            Ok(90)
+        );
+        assert_eq!(
+            trading_platform.balance_of_at("ALICE", PointInTime::Timestamp(now_millis())),
+            Ok(60)
+        );
+        assert_eq!(
+            trading_platform.balance_of_at("ALICE", PointInTime::Timestamp(0)),
+            Err(ApplicationError::AccountNotFound("ALICE".to_string()))
+        );
+    }
+
+    #[test]
+    fn test_TradingPlatform_order_publishes_book_deltas() {
+        let mut trading_platform = TradingPlatform::new();
+        trading_platform.deposit("ALICE", 100).unwrap();
+        trading_platform.deposit("BOB", 100).unwrap();
+        for (amount, side, signer) in [(2, Side::Sell, "ALICE"), (1, Side::Buy, "BOB")] {
+            trading_platform
+                .order(Order {
+                    price: 10,
+                    amount,
+                    side,
+                    signer: signer.to_string(),
+                    client_order_id: None,
+                    signature: None,
+                    order_type: OrderType::Limit,
+                    time_in_force: TimeInForce::Gtc,
+                    trigger_price: None,
+                    market: DEFAULT_MARKET.to_string(),
+                })
+                .unwrap();
+        }
+
+        let update = trading_platform.book_updates.since(0).unwrap();
+        assert_eq!(
+            update.deltas,
+            vec![
+                BookDelta {
+                    seq: 1,
+                    side: Side::Sell,
+                    price: 10,
+                    quantity: 2,
+                    action: DeltaAction::Add
+                },
+                BookDelta {
+                    seq: 2,
+                    side: Side::Sell,
+                    price: 10,
+                    quantity: 1,
+                    action: DeltaAction::Amend
+                }
+            ]
+        );
+    }
+
+    #[test]
+    fn test_TradingPlatform_depth_aggregates_the_best_levels() {
+        let mut trading_platform = TradingPlatform::new();
+        trading_platform.deposit("ALICE", 1_000).unwrap();
+        let order = |price, amount, side| Order {
+            price,
+            amount,
+            side,
+            signer: "ALICE".to_string(),
+            client_order_id: None,
+            signature: None,
+            order_type: OrderType::Limit,
+            time_in_force: TimeInForce::Gtc,
+            trigger_price: None,
+            market: DEFAULT_MARKET.to_string(),
+        };
+        for (price, amount, side) in [
+            (9, 1, Side::Buy),
+            (9, 2, Side::Buy),
+            (8, 5, Side::Buy),
+            (7, 1, Side::Buy),
+            (11, 4, Side::Sell),
+        ] {
+            trading_platform.order(order(price, amount, side)).unwrap();
+        }
+
+        let depth = trading_platform.depth(2);
+        assert_eq!(
+            depth.bids,
+            [
+                PriceLevel {
+                    price: 9,
+                    quantity: 3
+                },
+                PriceLevel {
+                    price: 8,
+                    quantity: 5
+                }
+            ]
+        );
+        assert_eq!(
+            depth.asks,
+            [PriceLevel {
+                price: 11,
+                quantity: 4
+            }]
+        );
+        assert_eq!(depth.seq, trading_platform.book_updates.seq());
+    }
+
+    #[test]
+    fn test_TradingPlatform_trade_tape_pages_through_the_trades() {
+        let mut trading_platform = TradingPlatform::new();
+        trading_platform.deposit("ALICE", 1_000).unwrap();
+        trading_platform.deposit("BOB", 1_000).unwrap();
+        let order = |amount, side, signer: &str| Order {
+            price: 10,
+            amount,
+            side,
+            signer: signer.to_string(),
+            client_order_id: None,
+            signature: None,
+            order_type: OrderType::Limit,
+            time_in_force: TimeInForce::Gtc,
+            trigger_price: None,
+            market: DEFAULT_MARKET.to_string(),
+        };
+        for _ in 0..3 {
+            trading_platform
+                .order(order(1, Side::Sell, "ALICE"))
+                .unwrap();
+        }
+        trading_platform.order(order(2, Side::Buy, "BOB")).unwrap();
+        trading_platform.order(order(1, Side::Buy, "BOB")).unwrap();
+
+        let first = trading_platform.trade_tape(&TradesQuery {
+            limit: Some(2),
+            ..TradesQuery::default()
+        });
+        assert_eq!(
+            first.trades.iter().map(|t| t.ordinal).collect::<Vec<_>>(),
+            [4, 4]
+        );
+        assert_eq!(
+            (
+                first.trades[0].buyer.as_str(),
+                first.trades[0].seller.as_str()
+            ),
+            ("BOB", "ALICE")
+        );
+        assert_eq!(first.next, Some(first.trades[1].id));
+        let second = trading_platform.trade_tape(&TradesQuery {
+            after: first.next,
+            limit: Some(2),
+            ..TradesQuery::default()
+        });
+        assert_eq!(second.trades.len(), 1);
+        assert_eq!(second.next, None);
+        let since = trading_platform.trade_tape(&TradesQuery {
+            since_ordinal: Some(4),
+            ..TradesQuery::default()
+        });
+        assert_eq!(since.trades, second.trades);
+    }
+
+    #[test]
+    fn test_TradingPlatform_book_snapshot_follows_deltas() {
         let mut trading_platform = TradingPlatform::new();
+        trading_platform.deposit("ALICE", 1_000).unwrap();
+        trading_platform.deposit("BOB", 1_000).unwrap();
+        let order = |price, amount, side, signer: &str| Order {
+            price,
+            amount,
+            side,
+            signer: signer.to_string(),
+            client_order_id: None,
+            signature: None,
+            order_type: OrderType::Limit,
+            time_in_force: TimeInForce::Gtc,
+            trigger_price: None,
+            market: DEFAULT_MARKET.to_string(),
+        };
+        trading_platform
+            .order(order(12, 3, Side::Sell, "ALICE"))
+            .unwrap();
+        let mut local = trading_platform.book_snapshot();
+        for next in [
+            order(11, 2, Side::Sell, "ALICE"),
+            order(9, 4, Side::Buy, "BOB"),
+            order(12, 4, Side::Buy, "BOB"),
+            order(8, 1, Side::Buy, "BOB"),
+        ] {
+            trading_platform.order(next).unwrap();
+        }
 
-        assert_eq!(
-            trading_platform.order(Order {
-                price: 10,
-                amount: 1,
-                side: Side::Sell,
-                signer: "ALICE".to_string(),
-            }),
-            Err(ApplicationError::AccountNotFound("ALICE".to_string()))
-        );
-        assert!(trading_platform.matching_engine.asks.is_empty());
-        assert!(trading_platform.matching_engine.bids.is_empty());
+        for delta in trading_platform
+            .book_updates
+            .since(local.seq)
+            .unwrap()
+            .deltas
+        {
+            local.apply(&delta);
+        }
+        assert_eq!(local, trading_platform.book_snapshot());
+        assert_eq!(local.bids.len(), 2);
+        assert_eq!(local.asks.len(), 1);
     }
 
     #[test]
@@ -144,6 +5027,12 @@ mod tests {
                 amount: 1,
                 side: Side::Sell,
                 signer: "ALICE".to_string(),
+                client_order_id: None,
+                signature: None,
+                order_type: OrderType::Limit,
+                time_in_force: TimeInForce::Gtc,
+                trigger_price: None,
+                market: DEFAULT_MARKET.to_string(),
             })
             .unwrap();
         assert_eq!(alice_receipt.matches, vec![]);
@@ -155,6 +5044,12 @@ mod tests {
                 amount: 2,
                 side: Side::Buy,
                 signer: "BOB".to_string(),
+                client_order_id: None,
+                signature: None,
+                order_type: OrderType::Limit,
+                time_in_force: TimeInForce::Gtc,
+                trigger_price: None,
+                market: DEFAULT_MARKET.to_string(),
             })
             .unwrap();
 
@@ -166,7 +5061,10 @@ mod tests {
                 remaining: 0,
                 side: Side::Sell,
                 signer: "ALICE".to_string(),
-                ordinal: 1
+                ordinal: 1,
+                order_id: 1,
+                expires_at: None,
+                market: DEFAULT_MARKET.to_string(),
             }]
         );
         assert!(trading_platform.matching_engine.asks.is_empty());
@@ -191,6 +5089,12 @@ mod tests {
                 amount: 2,
                 side: Side::Sell,
                 signer: "ALICE".to_string(),
+                client_order_id: None,
+                signature: None,
+                order_type: OrderType::Limit,
+                time_in_force: TimeInForce::Gtc,
+                trigger_price: None,
+                market: DEFAULT_MARKET.to_string(),
             })
             .unwrap();
         assert_eq!(alice_receipt.matches, vec![]);
@@ -202,6 +5106,12 @@ mod tests {
                 amount: 2,
                 side: Side::Buy,
                 signer: "BOB".to_string(),
+                client_order_id: None,
+                signature: None,
+                order_type: OrderType::Limit,
+                time_in_force: TimeInForce::Gtc,
+                trigger_price: None,
+                market: DEFAULT_MARKET.to_string(),
             })
             .unwrap();
 
@@ -213,7 +5123,10 @@ mod tests {
                 remaining: 0,
                 side: Side::Sell,
                 signer: "ALICE".to_string(),
-                ordinal: 1
+                ordinal: 1,
+                order_id: 1,
+                expires_at: None,
+                market: DEFAULT_MARKET.to_string(),
             }]
         );
 
@@ -241,6 +5154,12 @@ mod tests {
                 amount: 1,
                 side: Side::Sell,
                 signer: "ALICE".to_string(),
+                client_order_id: None,
+                signature: None,
+                order_type: OrderType::Limit,
+                time_in_force: TimeInForce::Gtc,
+                trigger_price: None,
+                market: DEFAULT_MARKET.to_string(),
             })
             .unwrap();
         assert_eq!(alice_receipt.matches, vec![]);
@@ -252,6 +5171,12 @@ mod tests {
                 amount: 1,
                 side: Side::Sell,
                 signer: "CHARLIE".to_string(),
+                client_order_id: None,
+                signature: None,
+                order_type: OrderType::Limit,
+                time_in_force: TimeInForce::Gtc,
+                trigger_price: None,
+                market: DEFAULT_MARKET.to_string(),
             })
             .unwrap();
         assert_eq!(charlie_receipt.matches, vec![]);
@@ -263,6 +5188,12 @@ mod tests {
                 amount: 2,
                 side: Side::Buy,
                 signer: "BOB".to_string(),
+                client_order_id: None,
+                signature: None,
+                order_type: OrderType::Limit,
+                time_in_force: TimeInForce::Gtc,
+                trigger_price: None,
+                market: DEFAULT_MARKET.to_string(),
             })
             .unwrap();
 
@@ -275,7 +5206,10 @@ mod tests {
                     remaining: 0,
                     side: Side::Sell,
                     signer: "ALICE".to_string(),
-                    ordinal: 1
+                    ordinal: 1,
+                    order_id: 1,
+                    expires_at: None,
+                    market: DEFAULT_MARKET.to_string(),
                 },
                 PartialOrder {
                     price: 10,
@@ -283,7 +5217,10 @@ mod tests {
                     remaining: 0,
                     side: Side::Sell,
                     signer: "CHARLIE".to_string(),
-                    ordinal: 2
+                    ordinal: 2,
+                    order_id: 2,
+                    expires_at: None,
+                    market: DEFAULT_MARKET.to_string(),
                 }
             ]
         );
@@ -297,6 +5234,413 @@ mod tests {
         assert_eq!(trading_platform.accounts.balance_of("CHARLIE"), Ok(&110));
     }
 
+    #[test]
+    fn test_TradingPlatform_with_ids_issues_ids_from_the_generator() {
+        let ids = DeterministicIds { seed: 7 };
+        let mut trading_platform = TradingPlatform::with_ids(Arc::new(ids));
+        trading_platform.deposit("ALICE", 100).unwrap();
+        trading_platform.deposit("BOB", 100).unwrap();
+        let mut receipts = vec![];
+        for (signer, side) in [("ALICE", Side::Sell), ("BOB", Side::Buy)] {
+            receipts.push(
+                trading_platform
+                    .order(Order {
+                        price: 10,
+                        amount: 1,
+                        side,
+                        signer: signer.to_string(),
+                        client_order_id: None,
+                        signature: None,
+                        order_type: OrderType::Limit,
+                        time_in_force: TimeInForce::Gtc,
+                        trigger_price: None,
+                        market: DEFAULT_MARKET.to_string(),
+                    })
+                    .unwrap(),
+            );
+        }
+        let Tx::Hold { id: hold_id, .. } = trading_platform.hold("ALICE", 5).unwrap() else {
+            panic!("not a hold");
+        };
+
+        assert_eq!(receipts[0].ordinal, ids.next_id(0));
+        assert_eq!(receipts[1].ordinal, ids.next_id(receipts[0].ordinal));
+        assert_eq!(trading_platform.trades[0].id, ids.next_id(0));
+        assert_eq!(hold_id, ids.next_id(0));
+        // Replays issue the same ordinals
+        let replayed = trading_platform.orderbook_at(
+            PointInTime::Ordinal(receipts[0].ordinal),
+            &BookQuery::default(),
+        );
+        assert_eq!(replayed[0].ordinal, receipts[0].ordinal);
+    }
+
+    #[test]
+    fn test_TradingPlatform_order_records_settlements_by_trade_id() {
+        let mut trading_platform = TradingPlatform::new();
+        trading_platform.deposit("ALICE", 100).unwrap();
+        trading_platform.deposit("BOB", 100).unwrap();
+        for (signer, side) in [
+            ("ALICE", Side::Sell),
+            ("ALICE", Side::Sell),
+            ("BOB", Side::Buy),
+        ] {
+            trading_platform
+                .order(Order {
+                    price: 10,
+                    amount: 1 + (side == Side::Buy) as u64,
+                    side,
+                    signer: signer.to_string(),
+                    client_order_id: None,
+                    signature: None,
+                    order_type: OrderType::Limit,
+                    time_in_force: TimeInForce::Gtc,
+                    trigger_price: None,
+                    market: DEFAULT_MARKET.to_string(),
+                })
+                .unwrap();
+        }
+
+        let ids: Vec<u64> = trading_platform.trades.iter().map(|t| t.id).collect();
+        assert_eq!(ids, vec![1, 2]);
+        let settlement = |trade_id| Tx::Settlement {
+            trade_id,
+            from: "BOB".to_string(),
+            to: "ALICE".to_string(),
+            amount: 10,
+        };
+        assert_eq!(
+            trading_platform.transactions[2..],
+            [settlement(1), settlement(2)]
+        );
+        assert_eq!(
+            trading_platform.balance_of_at("ALICE", PointInTime::Ordinal(3)),
+            Ok(120)
+        );
+        assert_eq!(trading_platform.issued_ids().trade_id, 2);
+    }
+
+    #[test]
+    fn test_TradingPlatform_sandbox_orders_settle_apart_from_real_trading() {
+        let mut trading_platform = TradingPlatform::new();
+        let order = |signer: &str, side, amount| Order {
+            price: 10,
+            amount,
+            side,
+            signer: signer.to_string(),
+            client_order_id: None,
+            signature: None,
+            order_type: OrderType::Limit,
+            time_in_force: TimeInForce::Gtc,
+            trigger_price: None,
+            market: DEFAULT_MARKET.to_string(),
+        };
+        trading_platform.markets.insert(
+            DEFAULT_MARKET,
+            MarketConfig {
+                sandbox: true,
+                ..MarketConfig::default()
+            },
+        );
+        trading_platform.faucet("SANDY", 100).unwrap();
+        trading_platform.faucet("SAM", 100).unwrap();
+        // Each bid is covered on its own, both together aren't
+        trading_platform
+            .order(order("SANDY", Side::Buy, 10))
+            .unwrap();
+        trading_platform
+            .order(order("SANDY", Side::Buy, 10))
+            .unwrap();
+
+        assert!(trading_platform
+            .order(order("SAM", Side::Sell, 20))
+            .is_err());
+        assert_eq!(trading_platform.sandbox_book.orders().len(), 2);
+        assert_eq!(trading_platform.balance_of("SANDY"), Ok(&100));
+        assert!(trading_platform.sandbox_trades.is_empty());
+
+        trading_platform
+            .order(order("SAM", Side::Sell, 10))
+            .unwrap();
+        assert_eq!(trading_platform.balance_of("SAM"), Ok(&200));
+        for account in ["SANDY", "SAM"] {
+            assert_eq!(trading_platform.positions.of(account).units, 0);
+            assert_eq!(
+                trading_platform
+                    .trade_stats
+                    .stats_of(account, 0)
+                    .trade_count,
+                0
+            );
+        }
+
+        trading_platform.delete_account("SAM").unwrap();
+        assert!(matches!(
+            trading_platform.transactions.last(),
+            Some(Tx::SandboxClosed { amount: 200, .. })
+        ));
+    }
+
+    #[test]
+    fn test_TradingPlatform_sandbox_orders_only_match_sandbox_orders() {
+        let mut trading_platform = TradingPlatform::new();
+        let order = |signer: &str, side| Order {
+            price: 10,
+            amount: 1,
+            side,
+            signer: signer.to_string(),
+            client_order_id: None,
+            signature: None,
+            order_type: OrderType::Limit,
+            time_in_force: TimeInForce::Gtc,
+            trigger_price: None,
+            market: DEFAULT_MARKET.to_string(),
+        };
+        trading_platform.deposit("ALICE", 100).unwrap();
+        trading_platform.faucet("SANDY", 100).unwrap();
+        trading_platform.faucet("SAM", 100).unwrap();
+        assert_eq!(
+            trading_platform.order(order("SANDY", Side::Buy)),
+            Err(ApplicationError::SandboxViolation("SANDY".to_string()))
+        );
+        trading_platform.markets.insert(
+            DEFAULT_MARKET,
+            MarketConfig {
+                sandbox: true,
+                ..MarketConfig::default()
+            },
+        );
+
+        trading_platform.order(order("ALICE", Side::Sell)).unwrap();
+        let receipt = trading_platform.order(order("SANDY", Side::Buy)).unwrap();
+        assert!(receipt.matches.is_empty());
+        let receipt = trading_platform.order(order("SAM", Side::Sell)).unwrap();
+        assert_eq!(receipt.matches[0].signer, "SANDY");
+        assert_eq!(receipt.ordinal, 3);
+        assert_eq!(trading_platform.balance_of("SAM"), Ok(&110));
+        assert_eq!(trading_platform.orderbook().len(), 1);
+        assert!(trading_platform.trades.is_empty());
+        assert_eq!(trading_platform.sandbox_trades.len(), 1);
+
+        // Play funds and real funds never mix
+        for result in [
+            trading_platform.deposit("SAM", 1),
+            trading_platform.withdraw("SAM", 1, None),
+            trading_platform.faucet("ALICE", 1),
+            trading_platform.send("SAM", "ALICE", 1).map(|(tx, _)| tx),
+        ] {
+            assert!(matches!(result, Err(ApplicationError::SandboxViolation(_))));
+        }
+    }
+
+    #[test]
+    fn test_TradingPlatform_list_market_checks_orders_against_its_constraints() {
+        let mut trading_platform = TradingPlatform::new();
+        let order = |price, amount| Order {
+            price,
+            amount,
+            side: Side::Sell,
+            signer: "ALICE".to_string(),
+            client_order_id: None,
+            signature: None,
+            order_type: OrderType::Limit,
+            time_in_force: TimeInForce::Gtc,
+            trigger_price: None,
+            market: "ETH-USD".to_string(),
+        };
+        trading_platform.deposit("ALICE", 1_000).unwrap();
+        assert_eq!(
+            trading_platform.order(order(10, 10)),
+            Err(ApplicationError::MarketNotFound("ETH-USD".to_string()))
+        );
+        assert!(matches!(
+            trading_platform.list_market(
+                "ETH-USD",
+                MarketConfig {
+                    lot_size: 0,
+                    ..MarketConfig::default()
+                }
+            ),
+            Err(ApplicationError::InvalidConfig(_))
+        ));
+        let info = trading_platform
+            .list_market(
+                "ETH-USD",
+                MarketConfig {
+                    tick_size: 5,
+                    lot_size: 2,
+                    min_amount: 4,
+                    max_amount: Some(20),
+                    ..MarketConfig::default()
+                },
+            )
+            .unwrap();
+        assert_eq!(
+            (info.tick_size, info.min_amount, info.max_amount),
+            (5, 4, Some(20))
+        );
+
+        for (price, amount, violated) in [
+            (
+                12,
+                10,
+                OrderConstraint::TickSize {
+                    price: 12,
+                    tick_size: 5,
+                },
+            ),
+            (
+                10,
+                5,
+                OrderConstraint::LotSize {
+                    amount: 5,
+                    lot_size: 2,
+                },
+            ),
+            (
+                10,
+                2,
+                OrderConstraint::MinAmount {
+                    amount: 2,
+                    min_amount: 4,
+                },
+            ),
+            (
+                10,
+                22,
+                OrderConstraint::MaxAmount {
+                    amount: 22,
+                    max_amount: 20,
+                },
+            ),
+        ] {
+            assert_eq!(
+                trading_platform.order(order(price, amount)),
+                Err(ApplicationError::InvalidOrder(violated))
+            );
+        }
+        assert!(trading_platform.order(order(10, 10)).is_ok());
+        assert_eq!(
+            trading_platform
+                .orderbook_matching(&BookQuery {
+                    market: Some("ETH-USD".to_string()),
+                    ..BookQuery::default()
+                })
+                .unwrap()
+                .len(),
+            1
+        );
+    }
+
+    #[test]
+    fn test_TradingPlatform_order_matches_only_orders_of_the_same_market() {
+        let mut trading_platform = TradingPlatform::new();
+        let order = |signer: &str, side, market: &str| Order {
+            price: 10,
+            amount: 1,
+            side,
+            signer: signer.to_string(),
+            client_order_id: None,
+            signature: None,
+            order_type: OrderType::Limit,
+            time_in_force: TimeInForce::Gtc,
+            trigger_price: None,
+            market: market.to_string(),
+        };
+        trading_platform.deposit("ALICE", 100).unwrap();
+        trading_platform.deposit("BOB", 100).unwrap();
+        assert_eq!(
+            trading_platform.order(order("ALICE", Side::Sell, "ETH-USD")),
+            Err(ApplicationError::MarketNotFound("ETH-USD".to_string()))
+        );
+        trading_platform
+            .markets
+            .insert("ETH-USD", MarketConfig::default());
+
+        trading_platform
+            .order(order("ALICE", Side::Sell, DEFAULT_MARKET))
+            .unwrap();
+        let receipt = trading_platform
+            .order(order("BOB", Side::Buy, "ETH-USD"))
+            .unwrap();
+        assert!(receipt.matches.is_empty());
+        assert_eq!(receipt.market, "ETH-USD");
+        let eth = BookQuery {
+            market: Some("ETH-USD".to_string()),
+            ..BookQuery::default()
+        };
+        assert_eq!(trading_platform.orderbook_matching(&eth).unwrap().len(), 1);
+        assert_eq!(trading_platform.orderbook().len(), 1);
+
+        let receipt = trading_platform
+            .order(order("ALICE", Side::Sell, "ETH-USD"))
+            .unwrap();
+        assert_eq!(receipt.matches[0].signer, "BOB");
+        assert_eq!(receipt.ordinal, 3);
+        assert_eq!(trading_platform.trades[0].market, "ETH-USD");
+        assert_eq!(trading_platform.balance_of("ALICE"), Ok(&110));
+        assert!(trading_platform
+            .orderbook_matching(&eth)
+            .unwrap()
+            .is_empty());
+        assert_eq!(
+            trading_platform
+                .ticker("ETH-USD", now_millis())
+                .unwrap()
+                .last_price,
+            Some(10)
+        );
+        // Positions are kept in the default market only
+        assert_eq!(trading_platform.last_price, None);
+        assert_eq!(trading_platform.position_of("BOB").unwrap().units, 0);
+    }
+
+    #[test]
+    fn test_TradingPlatform_preallocate_books_opens_the_configured_books() {
+        let mut trading_platform = TradingPlatform::new();
+        let preallocated = MarketConfig {
+            preallocated_levels: 4,
+            orders_per_level: 8,
+            ..MarketConfig::default()
+        };
+        trading_platform
+            .markets
+            .insert(DEFAULT_MARKET, preallocated.clone());
+        trading_platform.markets.insert("ETH-USD", preallocated);
+        trading_platform
+            .markets
+            .insert("SOL-USD", MarketConfig::default());
+        trading_platform.preallocate_books();
+
+        let stats = trading_platform.pool_stats();
+        assert_eq!(
+            stats
+                .iter()
+                .map(|(market, _)| market.as_str())
+                .collect::<Vec<_>>(),
+            ["ETH-USD", DEFAULT_MARKET]
+        );
+        assert!(stats.iter().all(|(_, stats)| stats.idle == 8));
+
+        trading_platform.deposit("ALICE", 100).unwrap();
+        trading_platform
+            .order(Order {
+                price: 10,
+                amount: 1,
+                side: Side::Buy,
+                signer: "ALICE".to_string(),
+                client_order_id: None,
+                signature: None,
+                order_type: OrderType::Limit,
+                time_in_force: TimeInForce::Gtc,
+                trigger_price: None,
+                market: "ETH-USD".to_string(),
+            })
+            .unwrap();
+        let eth = trading_platform.book("ETH-USD").unwrap().pool_stats();
+        assert_eq!((eth.reused, eth.allocated, eth.idle), (1, 0, 7));
+    }
+
     #[test]
     fn test_TradingPlatform_order_fully_match_order_no_self_match_updates_accounts() {
         let mut trading_platform = TradingPlatform::new();
@@ -311,6 +5655,12 @@ mod tests {
                 amount: 1,
                 side: Side::Sell,
                 signer: "ALICE".to_string(),
+                client_order_id: None,
+                signature: None,
+                order_type: OrderType::Limit,
+                time_in_force: TimeInForce::Gtc,
+                trigger_price: None,
+                market: DEFAULT_MARKET.to_string(),
             })
             .unwrap();
         assert_eq!(alice_receipt.matches, vec![]);
@@ -322,6 +5672,12 @@ mod tests {
                 amount: 1,
                 side: Side::Sell,
                 signer: "CHARLIE".to_string(),
+                client_order_id: None,
+                signature: None,
+                order_type: OrderType::Limit,
+                time_in_force: TimeInForce::Gtc,
+                trigger_price: None,
+                market: DEFAULT_MARKET.to_string(),
             })
             .unwrap();
         assert_eq!(charlie_receipt.matches, vec![]);
@@ -333,6 +5689,12 @@ mod tests {
                 amount: 2,
                 side: Side::Buy,
                 signer: "ALICE".to_string(),
+                client_order_id: None,
+                signature: None,
+                order_type: OrderType::Limit,
+                time_in_force: TimeInForce::Gtc,
+                trigger_price: None,
+                market: DEFAULT_MARKET.to_string(),
             })
             .unwrap();
 
@@ -344,7 +5706,10 @@ mod tests {
                 remaining: 0,
                 side: Side::Sell,
                 signer: "CHARLIE".to_string(),
-                ordinal: 2
+                ordinal: 2,
+                order_id: 2,
+                expires_at: None,
+                market: DEFAULT_MARKET.to_string(),
             }]
         );
         // A fully matched order doesn't remain in the book
@@ -369,6 +5734,12 @@ mod tests {
                 amount: 2,
                 side: Side::Sell,
                 signer: "ALICE".to_string(),
+                client_order_id: None,
+                signature: None,
+                order_type: OrderType::Limit,
+                time_in_force: TimeInForce::Gtc,
+                trigger_price: None,
+                market: DEFAULT_MARKET.to_string(),
             })
             .unwrap();
         assert_eq!(alice_receipt.matches, vec![]);
@@ -380,6 +5751,12 @@ mod tests {
                 amount: 2,
                 side: Side::Sell,
                 signer: "BOB".to_string(),
+                client_order_id: None,
+                signature: None,
+                order_type: OrderType::Limit,
+                time_in_force: TimeInForce::Gtc,
+                trigger_price: None,
+                market: DEFAULT_MARKET.to_string(),
             })
             .unwrap();
 
@@ -390,4 +5767,561 @@ mod tests {
         assert_eq!(trading_platform.accounts.balance_of("ALICE"), Ok(&100));
         assert_eq!(trading_platform.accounts.balance_of("BOB"), Ok(&100));
     }
+
+    #[test]
+    fn test_TradingPlatform_delete_account_anonymizes_history() {
+        let mut trading_platform = TradingPlatform::new();
+        trading_platform.deposit("ALICE", 100).unwrap();
+        trading_platform.deposit("BOB", 100).unwrap();
+        for (amount, side, signer) in [(2, Side::Sell, "ALICE"), (1, Side::Buy, "BOB")] {
+            trading_platform
+                .order(Order {
+                    price: 10,
+                    amount,
+                    side,
+                    signer: signer.to_string(),
+                    client_order_id: None,
+                    signature: None,
+                    order_type: OrderType::Limit,
+                    time_in_force: TimeInForce::Gtc,
+                    trigger_price: None,
+                    market: DEFAULT_MARKET.to_string(),
+                })
+                .unwrap();
+        }
+        let hold = trading_platform.hold("ALICE", 10).unwrap();
+        assert_eq!(
+            trading_platform.delete_account("ALICE"),
+            Err(ApplicationError::AccountInUse("ALICE".to_string()))
+        );
+        if let Tx::Hold { id, .. } = hold {
+            trading_platform.release(id).unwrap();
+        }
+
+        let deleted = trading_platform.delete_account("ALICE").unwrap();
+        assert_eq!(deleted.swept, 110);
+        assert!(deleted.token.starts_with(ANONYMIZED_PREFIX));
+        assert!(trading_platform.orderbook().is_empty());
+        assert!(trading_platform.balance_of("ALICE").is_err());
+        let named = |account: &str| account == "ALICE";
+        assert!(!trading_platform
+            .transactions
+            .iter()
+            .any(|tx| format!("{:?}", tx).contains("ALICE")));
+        assert!(!trading_platform
+            .trades
+            .iter()
+            .any(|t| named(&t.taker) || named(&t.maker)));
+        assert_eq!(trading_platform.trades[0].maker, deleted.token);
+        assert_eq!(
+            trading_platform.balance_of_at(&deleted.token, PointInTime::Ordinal(1)),
+            Ok(100)
+        );
+        assert_eq!(
+            trading_platform.balance_of_at(&deleted.token, PointInTime::Timestamp(now_millis())),
+            Ok(0)
+        );
+        assert_eq!(
+            trading_platform
+                .book_log
+                .book_at_ordinal(1)
+                .orders()
+                .first()
+                .map(|o| o.signer.clone()),
+            Some(deleted.token)
+        );
+        assert_eq!(trading_platform.balance_of("BOB"), Ok(&90));
+    }
+
+    #[test]
+    fn test_TradingPlatform_delete_account_is_limited_by_the_whitelist() {
+        let mut trading_platform = TradingPlatform::new();
+        trading_platform.whitelist_threshold = 10;
+        trading_platform.deposit("ALICE", 100).unwrap();
+        trading_platform.deposit("BOB", 0).unwrap();
+        trading_platform
+            .whitelist("ALICE", "BOB", now_millis())
+            .unwrap();
+
+        // Closing would sweep the balance out without the whitelist delay
+        assert_eq!(
+            trading_platform.delete_account("ALICE"),
+            Err(ApplicationError::DestinationNotWhitelisted(
+                "ALICE".to_string(),
+                String::new()
+            ))
+        );
+        assert_eq!(trading_platform.balance_of("ALICE"), Ok(&100));
+        // Down to the threshold it may close
+        trading_platform.accounts.withdraw("ALICE", 90).unwrap();
+        assert!(trading_platform.delete_account("ALICE").is_ok());
+    }
+
+    #[test]
+    fn test_TradingPlatform_sweep_dust_moves_consenting_dust_to_the_dust_account() {
+        let mut trading_platform = TradingPlatform::new();
+        trading_platform.dust.threshold = 10;
+        for (signer, amount) in [("ALICE", 5), ("BOB", 5), ("CAROL", 50), ("DAVE", 3)] {
+            trading_platform.deposit(signer, amount).unwrap();
+        }
+        for signer in ["ALICE", "BOB", "CAROL"] {
+            trading_platform.set_dust_consent(signer, true).unwrap();
+        }
+        trading_platform.set_dust_consent("BOB", false).unwrap();
+        assert!(trading_platform.set_dust_consent("ERIN", true).is_err());
+
+        assert_eq!(trading_platform.sweep_dust(1), Ok(5));
+        assert_eq!(trading_platform.balance_of("ALICE"), Ok(&0));
+        assert_eq!(trading_platform.balance_of("BOB"), Ok(&5));
+        assert_eq!(trading_platform.balance_of("CAROL"), Ok(&50));
+        assert_eq!(
+            trading_platform.transactions.last(),
+            Some(&Tx::DustSwept {
+                from: "ALICE".to_string(),
+                to: DUST_ACCOUNT.to_string(),
+                amount: 5
+            })
+        );
+        // Nothing left to sweep
+        assert_eq!(trading_platform.sweep_dust(2), Ok(0));
+
+        // Deleted accounts leave their dust behind without consent
+        let deleted = trading_platform.delete_account("DAVE").unwrap();
+        assert_eq!((deleted.swept, deleted.dust), (0, 3));
+        assert_eq!(trading_platform.balance_of(DUST_ACCOUNT), Ok(&8));
+        let report = trading_platform.dust_report();
+        assert_eq!(
+            (report.threshold, report.consenting, report.total),
+            (10, 2, 8)
+        );
+        assert_eq!(
+            report
+                .sweeps
+                .iter()
+                .map(|sweep| (sweep.account == deleted.token, sweep.source))
+                .collect::<Vec<_>>(),
+            vec![(false, DustSource::Sweep), (true, DustSource::Closure)]
+        );
+    }
+
+    #[test]
+    fn test_TradingPlatform_create_api_key_requires_account() {
+        let mut trading_platform = TradingPlatform::new();
+
+        assert_eq!(
+            trading_platform.create_api_key("ALICE", Role::Trader, &[ApiKeyScope::Read]),
+            Err(ApplicationError::AccountNotFound("ALICE".to_string()))
+        );
+
+        assert!(trading_platform.deposit("ALICE", 100).is_ok());
+        let new_key = trading_platform
+            .create_api_key("ALICE", Role::Trader, &[ApiKeyScope::Read])
+            .unwrap();
+        assert_eq!(trading_platform.api_keys_of("ALICE"), Ok(vec![new_key.key]));
+    }
+
+    #[test]
+    fn test_TradingPlatform_order_requires_a_signature_once_a_key_is_registered() {
+        use ed25519_dalek::{Signer, SigningKey};
+
+        let mut trading_platform = TradingPlatform::new();
+        trading_platform.deposit("ALICE", 100).unwrap();
+        let signing_key = SigningKey::from_bytes(&[3; 32]);
+        let public_key = hex::encode(signing_key.verifying_key().as_bytes());
+        assert!(matches!(
+            trading_platform.register_public_key("BOB", &public_key, 0),
+            Err(ApplicationError::AccountNotFound(_))
+        ));
+        trading_platform
+            .register_public_key("ALICE", &public_key, 0)
+            .unwrap();
+
+        let mut order = Order {
+            price: 10,
+            amount: 1,
+            side: Side::Buy,
+            signer: "ALICE".to_string(),
+            client_order_id: None,
+            signature: None,
+            order_type: OrderType::Limit,
+            time_in_force: TimeInForce::Gtc,
+            trigger_price: None,
+            market: DEFAULT_MARKET.to_string(),
+        };
+        assert_eq!(
+            trading_platform.order(order.clone()),
+            Err(ApplicationError::SignatureRequired("ALICE".to_string()))
+        );
+        let expires_at = now_millis() + 60_000;
+        let payload = order.signing_payload(DEFAULT_TENANT, 7, expires_at);
+        let signature = hex::encode(signing_key.sign(&payload).to_bytes());
+        order.signature = Some(OrderSignature {
+            nonce: 7,
+            expires_at,
+            value: signature.clone(),
+        });
+        let receipt = trading_platform.order(order.clone()).unwrap();
+        // The same signed order again
+        assert_eq!(
+            trading_platform.order(order),
+            Err(ApplicationError::NonceReused("ALICE".to_string(), 7))
+        );
+
+        let audited = trading_platform.auditor.log.query(&AuditQuery {
+            actor: Some("ALICE".to_string()),
+            action: Some(AuditAction::SignedOrder),
+        });
+        assert_eq!(audited.len(), 1);
+        assert_eq!(audited[0].after["signature"], signature);
+        assert_eq!(audited[0].after["ordinal"], receipt.ordinal);
+        assert_eq!(audited[0].after["nonce"], 7);
+    }
+
+    #[test]
+    fn test_TradingPlatform_amend_order_requires_a_signature_once_a_key_is_registered() {
+        use ed25519_dalek::{Signer, SigningKey};
+
+        let mut trading_platform = TradingPlatform::new();
+        trading_platform.deposit("ALICE", 100).unwrap();
+        let receipt = trading_platform
+            .order(Order {
+                price: 10,
+                amount: 1,
+                side: Side::Buy,
+                signer: "ALICE".to_string(),
+                client_order_id: None,
+                signature: None,
+                order_type: OrderType::Limit,
+                time_in_force: TimeInForce::Gtc,
+                trigger_price: None,
+                market: DEFAULT_MARKET.to_string(),
+            })
+            .unwrap();
+        let signing_key = SigningKey::from_bytes(&[3; 32]);
+        trading_platform
+            .register_public_key(
+                "ALICE",
+                &hex::encode(signing_key.verifying_key().as_bytes()),
+                0,
+            )
+            .unwrap();
+
+        let mut request = AmendRequest {
+            signer: "ALICE".to_string(),
+            price: 11,
+            amount: 2,
+            signature: None,
+        };
+        assert_eq!(
+            trading_platform.amend_order(receipt.order_id, request.clone()),
+            Err(ApplicationError::SignatureRequired("ALICE".to_string()))
+        );
+        let expires_at = now_millis() + 60_000;
+        let payload = request.signing_payload(receipt.order_id, DEFAULT_TENANT, 1, expires_at);
+        request.signature = Some(OrderSignature {
+            nonce: 1,
+            expires_at,
+            value: hex::encode(signing_key.sign(&payload).to_bytes()),
+        });
+        trading_platform
+            .amend_order(receipt.order_id, request)
+            .unwrap();
+
+        let audited = trading_platform.auditor.log.query(&AuditQuery {
+            actor: Some("ALICE".to_string()),
+            action: Some(AuditAction::SignedAmend),
+        });
+        assert_eq!(audited.len(), 1);
+        assert_eq!(audited[0].after["order_id"], receipt.order_id);
+    }
+
+    #[test]
+    fn test_TradingPlatform_recipient_of_resolves_saved_aliases() {
+        let mut trading_platform = TradingPlatform::new();
+        trading_platform.deposit("ALICE", 100).unwrap();
+        trading_platform.deposit("BOB", 0).unwrap();
+        assert!(matches!(
+            trading_platform.save_recipient("ALICE", "carol", "CAROL"),
+            Err(ApplicationError::AccountNotFound(_))
+        ));
+        trading_platform
+            .save_recipient("ALICE", "bob", "BOB")
+            .unwrap();
+
+        let mut request = SendRequest {
+            from: "ALICE".to_string(),
+            to: String::new(),
+            amount: 10,
+            to_alias: Some("bob".to_string()),
+            memo: None,
+        };
+        assert_eq!(
+            trading_platform.recipient_of(&request),
+            Ok("BOB".to_string())
+        );
+        request.to = "BOB".to_string();
+        assert!(matches!(
+            trading_platform.recipient_of(&request),
+            Err(ApplicationError::InvalidAlias(_))
+        ));
+        request.from = "BOB".to_string();
+        request.to = String::new();
+        assert_eq!(
+            trading_platform.recipient_of(&request),
+            Err(ApplicationError::AliasNotFound("bob".to_string()))
+        );
+    }
+
+    #[test]
+    fn test_TradingPlatform_whitelist_limits_the_destinations_above_the_threshold() {
+        let mut trading_platform = TradingPlatform::new();
+        trading_platform.whitelist_threshold = 10;
+        trading_platform.deposit("ALICE", 100).unwrap();
+        trading_platform.deposit("BOB", 0).unwrap();
+        trading_platform.deposit("CAROL", 0).unwrap();
+        let now = now_millis();
+        // An account without a whitelist sends anywhere
+        assert!(trading_platform.send("ALICE", "CAROL", 20).is_ok());
+
+        let entry = trading_platform.whitelist("ALICE", "BOB", now).unwrap();
+        assert!(entry.active_at > now);
+        // The new entry isn't active yet, so nothing above the threshold may leave
+        for result in [
+            trading_platform.send("ALICE", "BOB", 20).map(|(tx, _)| tx),
+            trading_platform
+                .send("ALICE", "CAROL", 20)
+                .map(|(tx, _)| tx),
+            trading_platform.withdraw("ALICE", 20, Some("iban:DE00")),
+        ] {
+            assert!(matches!(
+                result,
+                Err(ApplicationError::DestinationNotWhitelisted(_, _))
+            ));
+        }
+        assert!(trading_platform.send("ALICE", "CAROL", 10).is_ok());
+
+        trading_platform.whitelists.delay_millis = 0;
+        trading_platform
+            .whitelist("ALICE", "iban:DE00", now)
+            .unwrap();
+        assert!(trading_platform.send("ALICE", "BOB", 20).is_err());
+        assert!(trading_platform
+            .withdraw("ALICE", 20, Some("iban:DE00"))
+            .is_ok());
+        assert_eq!(
+            trading_platform.withdraw("ALICE", 20, None),
+            Err(ApplicationError::DestinationNotWhitelisted(
+                "ALICE".to_string(),
+                String::new()
+            ))
+        );
+        assert_eq!(trading_platform.balance_of("ALICE"), Ok(&50));
+
+        // Legs at the threshold to one recipient add up
+        let split = PayoutRequest {
+            from: "ALICE".to_string(),
+            recipients: vec![
+                PayoutLeg {
+                    to: "CAROL".to_string(),
+                    amount: 10,
+                },
+                PayoutLeg {
+                    to: "CAROL".to_string(),
+                    amount: 10,
+                },
+            ],
+        };
+        assert!(matches!(
+            trading_platform.payout(&split),
+            Err(ApplicationError::DestinationNotWhitelisted(_, _))
+        ));
+
+        // Removing the entries leaves the whitelist on, disabling it waits for the delay
+        trading_platform.whitelists.delay_millis = 60_000;
+        let now = now_millis();
+        trading_platform
+            .whitelists
+            .delete("ALICE", "iban:DE00", now)
+            .unwrap();
+        trading_platform
+            .whitelists
+            .delete("ALICE", "BOB", now)
+            .unwrap();
+        trading_platform.whitelists.disable("ALICE", now).unwrap();
+        assert!(trading_platform.send("ALICE", "CAROL", 20).is_err());
+        assert!(trading_platform
+            .withdraw("ALICE", 20, Some("iban:DE00"))
+            .is_ok());
+    }
+
+    #[test]
+    fn test_TradingPlatform_capture_is_limited_by_the_whitelist() {
+        let mut trading_platform = TradingPlatform::new();
+        trading_platform.whitelist_threshold = 10;
+        trading_platform.whitelists.delay_millis = 0;
+        trading_platform.deposit("ALICE", 100).unwrap();
+        trading_platform.deposit("BOB", 0).unwrap();
+        trading_platform.deposit("CAROL", 0).unwrap();
+        trading_platform
+            .whitelist("ALICE", "BOB", now_millis())
+            .unwrap();
+        trading_platform.hold("ALICE", 20).unwrap();
+        trading_platform.hold("ALICE", 10).unwrap();
+
+        assert_eq!(
+            trading_platform.capture(1, "CAROL"),
+            Err(ApplicationError::DestinationNotWhitelisted(
+                "ALICE".to_string(),
+                "CAROL".to_string()
+            ))
+        );
+        assert!(trading_platform.accounts.hold_of(1).is_ok());
+        assert!(trading_platform.capture(1, "BOB").is_ok());
+        // At the threshold anyone may receive the hold
+        assert!(trading_platform.capture(2, "CAROL").is_ok());
+        assert_eq!(trading_platform.balance_of("BOB"), Ok(&20));
+        assert_eq!(trading_platform.balance_of("CAROL"), Ok(&10));
+    }
+
+    #[test]
+    fn test_TradingPlatform_send_with_records_the_memo_on_both_legs() {
+        let mut trading_platform = TradingPlatform::new();
+        trading_platform.deposit("ALICE", 100).unwrap();
+        trading_platform.deposit("BOB", 0).unwrap();
+        let memo = Memo {
+            text: Some("INV-2024-0042".to_string()),
+            metadata: [("order".to_string(), "PO-7".to_string())].into(),
+        };
+
+        let (withdraw, deposit) = trading_platform
+            .send_with("ALICE", "BOB", 10, Some(memo.clone()))
+            .unwrap();
+        assert_eq!(withdraw.memo(), Some(&memo));
+        assert_eq!(deposit.memo(), Some(&memo));
+        let logged: Vec<_> = trading_platform.transactions.iter().rev().take(2).collect();
+        assert!(logged.iter().all(|tx| tx.memo() == Some(&memo)));
+
+        let too_long = Memo {
+            text: Some("x".repeat(octopus_common::tx::MAX_MEMO_LENGTH + 1)),
+            ..Memo::default()
+        };
+        assert!(matches!(
+            trading_platform.send_with("ALICE", "BOB", 10, Some(too_long)),
+            Err(ApplicationError::InvalidMemo(_))
+        ));
+        assert_eq!(trading_platform.balance_of("ALICE"), Ok(&90));
+    }
+
+    #[test]
+    fn test_TradingPlatform_tag_transaction_filters_the_statement() {
+        let mut trading_platform = TradingPlatform::new();
+        trading_platform.deposit("ALICE", 100).unwrap();
+        trading_platform.deposit("BOB", 0).unwrap();
+        trading_platform.deposit("CHARLIE", 0).unwrap();
+        trading_platform.send("ALICE", "BOB", 10).unwrap();
+        let statement = trading_platform.statement("ALICE", None).unwrap();
+        assert!(statement.len() >= 2);
+        let sent = statement.last().unwrap().id;
+
+        let tags = vec!["Rent".to_string(), "tax:2026".to_string()];
+        assert_eq!(
+            trading_platform.tag_transaction("ALICE", sent, &tags),
+            Ok(vec!["rent".to_string(), "tax:2026".to_string()])
+        );
+        let rent = trading_platform.statement("ALICE", Some("RENT")).unwrap();
+        assert_eq!(rent.len(), 1);
+        assert_eq!(rent[0].id, sent);
+        assert_eq!(rent[0].tags, vec!["rent", "tax:2026"]);
+        // The other party tags it on its own
+        assert!(trading_platform
+            .statement("BOB", Some("rent"))
+            .unwrap()
+            .is_empty());
+
+        assert_eq!(
+            trading_platform.tag_transaction("CHARLIE", sent, &tags),
+            Err(ApplicationError::TransactionNotFound(sent))
+        );
+        assert_eq!(
+            trading_platform.tag_transaction("ALICE", sent + 100, &tags),
+            Err(ApplicationError::TransactionNotFound(sent + 100))
+        );
+        assert!(matches!(
+            trading_platform.tag_transaction("ALICE", sent, &["a b".to_string()]),
+            Err(ApplicationError::InvalidTag(_))
+        ));
+    }
+
+    #[test]
+    fn test_TradingPlatform_payout_credits_every_recipient_or_none() {
+        let mut trading_platform = TradingPlatform::new();
+        trading_platform.deposit("ALICE", 100).unwrap();
+        trading_platform.deposit("BOB", 0).unwrap();
+        trading_platform.deposit("CHARLIE", u64::MAX - 5).unwrap();
+        let leg = |to: &str, amount| PayoutLeg {
+            to: to.to_string(),
+            amount,
+        };
+        let request = |recipients| PayoutRequest {
+            from: "ALICE".to_string(),
+            recipients,
+        };
+
+        let legs = trading_platform
+            .payout(&request(vec![leg("BOB", 30), leg("CHARLIE", 5)]))
+            .unwrap();
+        assert_eq!(
+            legs,
+            vec![
+                Tx::Payout {
+                    id: 1,
+                    from: "ALICE".to_string(),
+                    to: "BOB".to_string(),
+                    amount: 30,
+                },
+                Tx::Payout {
+                    id: 1,
+                    from: "ALICE".to_string(),
+                    to: "CHARLIE".to_string(),
+                    amount: 5,
+                },
+            ]
+        );
+        assert_eq!(trading_platform.transactions[3..], legs[..]);
+        assert_eq!(trading_platform.balance_of("ALICE"), Ok(&65));
+        assert_eq!(trading_platform.balance_of("BOB"), Ok(&30));
+
+        // CHARLIE would overflow, so BOB isn't paid either
+        assert_eq!(
+            trading_platform.payout(&request(vec![leg("BOB", 10), leg("CHARLIE", 1)])),
+            Err(ApplicationError::AccountOverFunded(
+                "CHARLIE".to_string(),
+                1
+            ))
+        );
+        assert_eq!(
+            trading_platform.payout(&request(vec![leg("BOB", 60), leg("BOB", 6)])),
+            Err(ApplicationError::AccountUnderFunded(
+                "ALICE".to_string(),
+                66
+            ))
+        );
+        assert_eq!(
+            trading_platform.payout(&request(vec![leg("BOB", 1), leg("DAVE", 1)])),
+            Err(ApplicationError::AccountNotFound("DAVE".to_string()))
+        );
+        for invalid in [vec![], vec![leg("BOB", 0)], vec![leg("ALICE", 1)]] {
+            assert!(matches!(
+                trading_platform.payout(&request(invalid)),
+                Err(ApplicationError::InvalidPayout(_))
+            ));
+        }
+        assert_eq!(trading_platform.balance_of("ALICE"), Ok(&65));
+        assert_eq!(trading_platform.balance_of("BOB"), Ok(&30));
+
+        let legs = trading_platform
+            .payout(&request(vec![leg("BOB", 1)]))
+            .unwrap();
+        assert!(matches!(legs[0], Tx::Payout { id: 2, .. }));
+    }
 }
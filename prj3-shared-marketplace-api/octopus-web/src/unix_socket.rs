@@ -0,0 +1,66 @@
+//! Serving the API on a Unix domain socket, for a reverse proxy or sidecar on the same host.
+use futures_util::TryStream;
+use std::{
+    fs,
+    io::{self, ErrorKind},
+    os::unix::fs::FileTypeExt,
+    path::Path,
+};
+use tokio::net::{UnixListener, UnixStream};
+
+/// Binds a socket at `path`, replacing the socket file a previous run left behind. Other files are never removed.
+pub fn bind(path: &Path) -> io::Result<impl TryStream<Ok = UnixStream, Error = io::Error>> {
+    match fs::symlink_metadata(path) {
+        Ok(metadata) if metadata.file_type().is_socket() => fs::remove_file(path)?,
+        Ok(_) => {
+            return Err(io::Error::new(
+                ErrorKind::AlreadyExists,
+                format!("{} exists and isn't a socket", path.display()),
+            ))
+        }
+        Err(e) if e.kind() == ErrorKind::NotFound => {}
+        Err(e) => return Err(e),
+    }
+    let listener = UnixListener::bind(path)?;
+    Ok(futures_util::stream::unfold(
+        listener,
+        |listener| async move {
+            let accepted = listener.accept().await.map(|(stream, _)| stream);
+            Some((accepted, listener))
+        },
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    // reduce the warnings for naming tests
+    #![allow(non_snake_case)]
+
+    use super::*;
+    use futures_util::TryStreamExt;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    #[tokio::test]
+    async fn test_bind_accepts_connections_and_keeps_other_files() {
+        let dir = std::env::temp_dir().join(format!("octopus-unix-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("octopus.sock");
+
+        drop(bind(&path).unwrap());
+        // The socket file of the dropped listener is stale and gets replaced
+        let mut incoming = Box::pin(bind(&path).unwrap().into_stream());
+        let mut client = UnixStream::connect(&path).await.unwrap();
+        let mut server = incoming.try_next().await.unwrap().unwrap();
+        client.write_all(b"ping").await.unwrap();
+        let mut received = [0; 4];
+        server.read_exact(&mut received).await.unwrap();
+        assert_eq!(&received, b"ping");
+        drop(incoming);
+
+        let file = dir.join("octopus.toml");
+        fs::write(&file, "").unwrap();
+        assert!(bind(&file).is_err());
+        assert!(file.exists());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,252 @@
+//! Comparing the state of two instances, e.g. the blue and green deployment of a tenant before switching traffic. One
+//! instance's [`StateDigest`] from `GET /admin/state`, or one saved earlier as a snapshot, is posted to the other's
+//! `POST /admin/state/compare`, which lists every balance, hold, resting order, and counter that differs.
+use octopus_common::types::{Hold, PartialOrder};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+use crate::counters::IdCounters;
+
+/// The most divergences listed in a [`StateComparison`], the rest are only counted
+pub const MAX_DIVERGENCES: usize = 1000;
+
+/// The resting orders and last price of one market
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct BookDigest {
+    pub last_price: Option<u64>,
+    /// In the order of the book
+    pub orders: Vec<PartialOrder>,
+}
+
+/// What two instances of a tenant should agree on
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct StateDigest {
+    /// Unix timestamp in milliseconds
+    pub taken_at: u64,
+    pub balances: BTreeMap<String, u64>,
+    pub holds: Vec<Hold>,
+    /// By market symbol
+    pub books: BTreeMap<String, BookDigest>,
+    pub counters: IdCounters,
+    /// The length of the transaction log
+    pub transactions: usize,
+}
+
+/// One difference between this instance (`here`) and the other one (`there`), `None` where it's missing
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum Divergence {
+    Balance {
+        signer: String,
+        here: Option<u64>,
+        there: Option<u64>,
+    },
+    Hold {
+        id: u64,
+        here: Option<Hold>,
+        there: Option<Hold>,
+    },
+    Order {
+        market: String,
+        ordinal: u64,
+        here: Option<PartialOrder>,
+        there: Option<PartialOrder>,
+    },
+    LastPrice {
+        market: String,
+        here: Option<u64>,
+        there: Option<u64>,
+    },
+    Counter {
+        name: String,
+        here: u64,
+        there: u64,
+    },
+    Transactions {
+        here: usize,
+        there: usize,
+    },
+}
+
+/// The outcome of [`StateDigest::compare`]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct StateComparison {
+    /// When each side's digest was taken
+    pub here_at: u64,
+    pub there_at: u64,
+    pub matches: bool,
+    /// All divergences, even those not listed
+    pub total: usize,
+    /// The first [`MAX_DIVERGENCES`]
+    pub divergences: Vec<Divergence>,
+}
+
+/// The entries of `here` and `there` that differ, as `(key, here, there)`
+fn diff<K: Ord + Clone, V: PartialEq + Clone>(
+    here: &BTreeMap<K, V>,
+    there: &BTreeMap<K, V>,
+) -> Vec<(K, Option<V>, Option<V>)> {
+    let mut keys: Vec<&K> = here.keys().chain(there.keys()).collect();
+    keys.sort();
+    keys.dedup();
+    keys.into_iter()
+        .filter(|key| here.get(key) != there.get(key))
+        .map(|key| (key.clone(), here.get(key).cloned(), there.get(key).cloned()))
+        .collect()
+}
+
+impl StateDigest {
+    /// Lists where this digest and `other` differ, balances first, then holds, books, and counters
+    pub fn compare(&self, other: &StateDigest) -> StateComparison {
+        let mut divergences: Vec<Divergence> = diff(&self.balances, &other.balances)
+            .into_iter()
+            .map(|(signer, here, there)| Divergence::Balance {
+                signer,
+                here,
+                there,
+            })
+            .collect();
+
+        let holds = |holds: &[Hold]| -> BTreeMap<u64, Hold> {
+            holds.iter().map(|hold| (hold.id, hold.clone())).collect()
+        };
+        divergences.extend(
+            diff(&holds(&self.holds), &holds(&other.holds))
+                .into_iter()
+                .map(|(id, here, there)| Divergence::Hold { id, here, there }),
+        );
+
+        let empty = BookDigest::default();
+        let mut markets: Vec<&String> = self.books.keys().chain(other.books.keys()).collect();
+        markets.sort();
+        markets.dedup();
+        for market in markets {
+            let here = self.books.get(market).unwrap_or(&empty);
+            let there = other.books.get(market).unwrap_or(&empty);
+            if here.last_price != there.last_price {
+                divergences.push(Divergence::LastPrice {
+                    market: market.clone(),
+                    here: here.last_price,
+                    there: there.last_price,
+                });
+            }
+            let orders = |book: &BookDigest| -> BTreeMap<u64, PartialOrder> {
+                book.orders
+                    .iter()
+                    .map(|order| (order.ordinal, order.clone()))
+                    .collect()
+            };
+            divergences.extend(diff(&orders(here), &orders(there)).into_iter().map(
+                |(ordinal, here, there)| Divergence::Order {
+                    market: market.clone(),
+                    ordinal,
+                    here,
+                    there,
+                },
+            ));
+        }
+
+        divergences.extend(
+            self.counters
+                .named()
+                .into_iter()
+                .zip(other.counters.named())
+                .filter(|((_, here), (_, there))| here != there)
+                .map(|((name, here), (_, there))| Divergence::Counter {
+                    name: name.to_string(),
+                    here,
+                    there,
+                }),
+        );
+        if self.transactions != other.transactions {
+            divergences.push(Divergence::Transactions {
+                here: self.transactions,
+                there: other.transactions,
+            });
+        }
+
+        let total = divergences.len();
+        divergences.truncate(MAX_DIVERGENCES);
+        StateComparison {
+            here_at: self.taken_at,
+            there_at: other.taken_at,
+            matches: total == 0,
+            total,
+            divergences,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    // reduce the warnings for naming tests
+    #![allow(non_snake_case)]
+
+    use super::*;
+    use crate::trading_platform::TradingPlatform;
+    use octopus_common::types::{Order, OrderType, Side, TimeInForce, DEFAULT_MARKET};
+
+    fn platform() -> TradingPlatform {
+        let mut trading_platform = TradingPlatform::new();
+        trading_platform.deposit("ALICE", 1_000).unwrap();
+        trading_platform.deposit("BOB", 1_000).unwrap();
+        trading_platform
+            .order(Order {
+                price: 10,
+                amount: 5,
+                side: Side::Sell,
+                signer: "BOB".to_string(),
+                client_order_id: None,
+                signature: None,
+                order_type: OrderType::Limit,
+                time_in_force: TimeInForce::Gtc,
+                trigger_price: None,
+                market: DEFAULT_MARKET.to_string(),
+            })
+            .unwrap();
+        trading_platform
+    }
+
+    #[test]
+    fn test_StateDigest_compare_lists_the_divergences() {
+        let blue = platform();
+        let mut green = platform();
+        let digest = blue.digest(1);
+        assert!(digest.compare(&green.digest(2)).matches);
+        // A snapshot reads back the same
+        let snapshot: StateDigest =
+            serde_json::from_slice(&serde_json::to_vec(&digest).unwrap()).unwrap();
+        assert_eq!(snapshot, digest);
+
+        green.deposit("ALICE", 1).unwrap();
+        green.deposit("CHARLIE", 5).unwrap();
+        green.cancel_all("BOB", 3);
+        let comparison = digest.compare(&green.digest(2));
+        assert!(!comparison.matches);
+        assert_eq!(comparison.total, comparison.divergences.len());
+        assert_eq!(
+            comparison.divergences[..2],
+            [
+                Divergence::Balance {
+                    signer: "ALICE".to_string(),
+                    here: Some(1_000),
+                    there: Some(1_001),
+                },
+                Divergence::Balance {
+                    signer: "CHARLIE".to_string(),
+                    here: None,
+                    there: Some(5),
+                },
+            ]
+        );
+        assert!(comparison.divergences.iter().any(|divergence| matches!(
+            divergence,
+            Divergence::Order { here: Some(order), there: None, .. } if order.signer == "BOB"
+        )));
+        assert!(comparison
+            .divergences
+            .contains(&Divergence::Transactions { here: 2, there: 4 }));
+    }
+}
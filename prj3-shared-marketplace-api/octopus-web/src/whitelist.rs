@@ -0,0 +1,251 @@
+//! Withdrawal whitelists: the destinations an account approved for moving its funds, other accounts for transfers and
+//! external identifiers for withdrawals. Once an account whitelisted a destination, its withdrawals and transfers above
+//! the threshold may only go to the active entries.
+//!
+//! Every change that loosens a whitelist waits [`Whitelists::delay_millis`]: new entries become active, removed entries
+//! and a disabled whitelist stop applying only after the delay. A leaked withdrawal key can neither whitelist a
+//! destination of its own nor drop the whitelist to empty the account right away. Changes that tighten it, like removing
+//! an entry that isn't active yet, take effect at once.
+use octopus_common::{errors::ApplicationError, types::WhitelistEntry};
+use std::collections::{BTreeMap, HashMap};
+
+/// How long new whitelist entries wait unless configured otherwise
+pub const DEFAULT_WHITELIST_DELAY_SECS: u64 = 24 * 60 * 60;
+
+/// The whitelist of one account. It's on from its first entry until disabling it took effect, even without entries.
+#[derive(Debug, Default)]
+struct Whitelist {
+    /// By destination
+    entries: BTreeMap<String, WhitelistEntry>,
+    /// When the whitelist stops applying, unix timestamp (ms)
+    disabled_at: Option<u64>,
+}
+
+impl Whitelist {
+    fn is_on(&self, now: u64) -> bool {
+        self.disabled_at.is_none_or(|disabled_at| now < disabled_at)
+    }
+
+    /// Forgets the entries whose removal took effect
+    fn prune(&mut self, now: u64) {
+        self.entries
+            .retain(|_, entry| entry.removed_at.is_none_or(|removed_at| now < removed_at));
+    }
+}
+
+/// The whitelisted destinations of every account
+#[derive(Debug)]
+pub struct Whitelists {
+    /// How long loosening a whitelist waits before it takes effect
+    pub delay_millis: u64,
+    whitelists: HashMap<String, Whitelist>,
+}
+
+impl Default for Whitelists {
+    fn default() -> Self {
+        Whitelists {
+            delay_millis: DEFAULT_WHITELIST_DELAY_SECS * 1000,
+            whitelists: HashMap::new(),
+        }
+    }
+}
+
+impl Whitelists {
+    pub fn new() -> Self {
+        Whitelists::default()
+    }
+
+    /// Whitelists `destination` for `owner`, active once the delay passed. Adding an entry again keeps its activation
+    /// and withdraws a pending removal. Adding to a disabled whitelist starts a new one, adding to one being disabled
+    /// keeps it on.
+    pub fn add(&mut self, owner: &str, destination: &str, now: u64) -> WhitelistEntry {
+        let whitelist = self.whitelists.entry(owner.to_string()).or_default();
+        if !whitelist.is_on(now) {
+            *whitelist = Whitelist::default();
+        }
+        whitelist.disabled_at = None;
+        whitelist.prune(now);
+        let entry = whitelist
+            .entries
+            .entry(destination.to_string())
+            .or_insert(WhitelistEntry {
+                destination: destination.to_string(),
+                active_at: now + self.delay_millis,
+                removed_at: None,
+            });
+        entry.removed_at = None;
+        entry.clone()
+    }
+
+    /// Removes `destination` from the whitelist of `owner`. An active entry stays usable until the delay passed, one
+    /// that isn't active yet is dropped right away.
+    /// # Errors
+    /// The owner didn't whitelist the destination
+    pub fn delete(
+        &mut self,
+        owner: &str,
+        destination: &str,
+        now: u64,
+    ) -> Result<WhitelistEntry, ApplicationError> {
+        let delay_millis = self.delay_millis;
+        let not_found = || ApplicationError::WhitelistEntryNotFound(destination.to_string());
+        let whitelist = self
+            .whitelists
+            .get_mut(owner)
+            .filter(|whitelist| whitelist.is_on(now))
+            .ok_or_else(not_found)?;
+        whitelist.prune(now);
+        let entry = whitelist
+            .entries
+            .get_mut(destination)
+            .ok_or_else(not_found)?;
+        if entry.active_at > now {
+            return Ok(whitelist
+                .entries
+                .remove(destination)
+                .map(|entry| WhitelistEntry {
+                    removed_at: Some(now),
+                    ..entry
+                })
+                .expect("the entry was just found"));
+        }
+        entry.removed_at.get_or_insert(now + delay_millis);
+        Ok(entry.clone())
+    }
+
+    /// Turns the whitelist of `owner` off once the delay passed, returns when. Disabling it again keeps the time.
+    /// # Errors
+    /// The owner keeps no whitelist
+    pub fn disable(&mut self, owner: &str, now: u64) -> Result<u64, ApplicationError> {
+        let delay_millis = self.delay_millis;
+        self.whitelists
+            .get_mut(owner)
+            .filter(|whitelist| whitelist.is_on(now))
+            .map(|whitelist| *whitelist.disabled_at.get_or_insert(now + delay_millis))
+            .ok_or(ApplicationError::WhitelistNotFound(owner.to_string()))
+    }
+
+    /// The whitelisted destinations of `owner` at `now`, pending additions and removals included
+    pub fn list(&self, owner: &str, now: u64) -> Vec<WhitelistEntry> {
+        self.whitelists
+            .get(owner)
+            .filter(|whitelist| whitelist.is_on(now))
+            .into_iter()
+            .flat_map(|whitelist| whitelist.entries.values())
+            .filter(|entry| entry.removed_at.is_none_or(|removed_at| now < removed_at))
+            .cloned()
+            .collect()
+    }
+
+    /// Checks that `owner` may move funds to `destination` at `now`. Accounts without a whitelist, or whose whitelist
+    /// was disabled, may move funds anywhere. `None` is a withdrawal that names no destination.
+    /// # Errors
+    /// The owner keeps a whitelist and the destination isn't active on it
+    pub fn check(
+        &self,
+        owner: &str,
+        destination: Option<&str>,
+        now: u64,
+    ) -> Result<(), ApplicationError> {
+        let Some(whitelist) = self
+            .whitelists
+            .get(owner)
+            .filter(|whitelist| whitelist.is_on(now))
+        else {
+            return Ok(());
+        };
+        let active = |entry: &WhitelistEntry| {
+            entry.active_at <= now && entry.removed_at.is_none_or(|removed_at| now < removed_at)
+        };
+        match destination.and_then(|destination| whitelist.entries.get(destination)) {
+            Some(entry) if active(entry) => Ok(()),
+            _ => Err(ApplicationError::DestinationNotWhitelisted(
+                owner.to_string(),
+                destination.unwrap_or_default().to_string(),
+            )),
+        }
+    }
+
+    /// Forgets the whitelist of `signer`. Entries of other accounts naming it stay, they expire with nothing.
+    pub fn remove(&mut self, signer: &str) {
+        self.whitelists.remove(signer);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    // reduce the warnings for naming tests
+    #![allow(non_snake_case)]
+
+    use super::*;
+
+    #[test]
+    fn test_Whitelists_check_admits_only_active_entries() {
+        let mut whitelists = Whitelists::new();
+        whitelists.delay_millis = 1_000;
+        // Without a whitelist anything goes
+        assert_eq!(whitelists.check("ALICE", Some("BOB"), 0), Ok(()));
+        assert_eq!(whitelists.check("ALICE", None, 0), Ok(()));
+
+        assert_eq!(whitelists.add("ALICE", "BOB", 0).active_at, 1_000);
+        // Adding it again doesn't push the activation out
+        assert_eq!(whitelists.add("ALICE", "BOB", 500).active_at, 1_000);
+        assert_eq!(
+            whitelists.check("ALICE", Some("BOB"), 999),
+            Err(ApplicationError::DestinationNotWhitelisted(
+                "ALICE".to_string(),
+                "BOB".to_string()
+            ))
+        );
+        assert_eq!(whitelists.check("ALICE", Some("BOB"), 1_000), Ok(()));
+        assert!(whitelists.check("ALICE", Some("CAROL"), 1_000).is_err());
+        assert!(whitelists.check("ALICE", None, 1_000).is_err());
+        assert_eq!(
+            whitelists.delete("ALICE", "CAROL", 1_000),
+            Err(ApplicationError::WhitelistEntryNotFound(
+                "CAROL".to_string()
+            ))
+        );
+    }
+
+    #[test]
+    fn test_Whitelists_delete_and_disable_wait_for_the_delay() {
+        let mut whitelists = Whitelists::new();
+        whitelists.delay_millis = 1_000;
+        whitelists.add("ALICE", "BOB", 0);
+        whitelists.add("ALICE", "CAROL", 500);
+
+        // Removing the active entry waits, the whitelist stays on without entries
+        assert_eq!(
+            whitelists.delete("ALICE", "BOB", 1_000).unwrap().removed_at,
+            Some(2_000)
+        );
+        // Removing one that isn't active yet only tightens the whitelist
+        assert_eq!(
+            whitelists
+                .delete("ALICE", "CAROL", 1_000)
+                .unwrap()
+                .removed_at,
+            Some(1_000)
+        );
+        assert_eq!(whitelists.check("ALICE", Some("BOB"), 1_999), Ok(()));
+        assert!(whitelists.check("ALICE", Some("BOB"), 2_000).is_err());
+        assert!(whitelists.check("ALICE", Some("DAVE"), 2_000).is_err());
+        assert!(whitelists.list("ALICE", 2_000).is_empty());
+
+        assert_eq!(whitelists.disable("ALICE", 2_000), Ok(3_000));
+        assert_eq!(whitelists.disable("ALICE", 2_500), Ok(3_000));
+        assert!(whitelists.check("ALICE", Some("DAVE"), 2_999).is_err());
+        assert_eq!(whitelists.check("ALICE", Some("DAVE"), 3_000), Ok(()));
+        assert_eq!(
+            whitelists.disable("ALICE", 3_000),
+            Err(ApplicationError::WhitelistNotFound("ALICE".to_string()))
+        );
+
+        // Adding while it's being disabled keeps it on
+        whitelists.add("BOB", "ALICE", 0);
+        whitelists.disable("BOB", 0).unwrap();
+        whitelists.add("BOB", "CAROL", 500);
+        assert!(whitelists.check("BOB", Some("DAVE"), 5_000).is_err());
+    }
+}
@@ -0,0 +1,151 @@
+use std::collections::BTreeMap;
+
+use octopus_common::types::{BookSnapshot, PartialOrder, PriceLevel, Side};
+
+/// Money only enters through deposits and never leaves without a withdrawal, so the balances of all accounts,
+/// including the fee account, add up to what was deposited.
+pub fn check_conservation(deposited: u64, balances: &[u64]) -> Result<(), String> {
+    let total: u128 = balances.iter().map(|balance| *balance as u128).sum();
+    if total != deposited as u128 {
+        return Err(format!(
+            "balances add up to {} but {} was deposited",
+            total, deposited
+        ));
+    }
+    Ok(())
+}
+
+/// The book must not be crossed, and the price levels must match the open orders
+pub fn check_book(snapshot: &BookSnapshot, orders: &[PartialOrder]) -> Result<(), String> {
+    if let (Some(bid), Some(ask)) = (snapshot.bids.first(), snapshot.asks.first()) {
+        if bid.price >= ask.price {
+            return Err(format!(
+                "the book is crossed: best bid {} >= best ask {}",
+                bid.price, ask.price
+            ));
+        }
+    }
+    let mut bids = BTreeMap::new();
+    let mut asks = BTreeMap::new();
+    for order in orders {
+        let side = match order.side {
+            Side::Buy => &mut bids,
+            Side::Sell => &mut asks,
+        };
+        *side.entry(order.price).or_insert(0) += order.remaining;
+    }
+    let levels = |quantities: BTreeMap<u64, u64>| -> Vec<PriceLevel> {
+        quantities
+            .into_iter()
+            .map(|(price, quantity)| PriceLevel { price, quantity })
+            .collect()
+    };
+    let mut expected_bids = levels(bids);
+    expected_bids.reverse();
+    if snapshot.bids != expected_bids || snapshot.asks != levels(asks) {
+        return Err(format!(
+            "the price levels at seq {} don't match the open orders",
+            snapshot.seq
+        ));
+    }
+    Ok(())
+}
+
+/// Resident memory may grow with the retained history, but not faster than a fixed number of bytes per request
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MemoryBudget {
+    pub baseline_rss: u64,
+    pub baseline_requests: u64,
+    pub bytes_per_request: u64,
+}
+
+impl MemoryBudget {
+    pub fn check(&self, rss: u64, requests: u64) -> Result<(), String> {
+        let allowed = self.baseline_rss
+            + requests.saturating_sub(self.baseline_requests) * self.bytes_per_request;
+        if rss > allowed {
+            return Err(format!(
+                "resident memory grew to {} bytes, {} allowed after {} requests",
+                rss, allowed, requests
+            ));
+        }
+        Ok(())
+    }
+}
+
+/// The resident set size of a process in bytes
+pub fn rss_of(pid: u32) -> Option<u64> {
+    let status = std::fs::read_to_string(format!("/proc/{}/status", pid)).ok()?;
+    let line = status.lines().find(|line| line.starts_with("VmRSS:"))?;
+    let kilobytes: u64 = line.split_whitespace().nth(1)?.parse().ok()?;
+    Some(kilobytes * 1024)
+}
+
+#[cfg(test)]
+mod tests {
+    // reduce the warnings for naming tests
+    #![allow(non_snake_case)]
+
+    use super::*;
+    use octopus_common::types::DEFAULT_MARKET;
+
+    fn order(price: u64, remaining: u64, side: Side) -> PartialOrder {
+        PartialOrder {
+            price,
+            amount: remaining,
+            remaining,
+            side,
+            signer: "ALICE".to_string(),
+            ordinal: 1,
+            order_id: 1,
+            expires_at: None,
+            market: DEFAULT_MARKET.to_string(),
+        }
+    }
+
+    #[test]
+    fn test_check_book_detects_crossed_and_stale_levels() {
+        let orders = vec![
+            order(11, 2, Side::Sell),
+            order(9, 1, Side::Buy),
+            order(8, 3, Side::Buy),
+        ];
+        let mut snapshot = BookSnapshot {
+            seq: 3,
+            bids: vec![
+                PriceLevel {
+                    price: 9,
+                    quantity: 1,
+                },
+                PriceLevel {
+                    price: 8,
+                    quantity: 3,
+                },
+            ],
+            asks: vec![PriceLevel {
+                price: 11,
+                quantity: 2,
+            }],
+        };
+        assert_eq!(check_book(&snapshot, &orders), Ok(()));
+        snapshot.asks[0].quantity = 1;
+        assert!(check_book(&snapshot, &orders).is_err());
+        snapshot.asks[0].price = 9;
+        assert!(check_book(&snapshot, &orders)
+            .unwrap_err()
+            .contains("crossed"));
+    }
+
+    #[test]
+    fn test_MemoryBudget_check_allows_growth_per_request() {
+        let budget = MemoryBudget {
+            baseline_rss: 1_000,
+            baseline_requests: 10,
+            bytes_per_request: 100,
+        };
+        assert_eq!(budget.check(2_000, 20), Ok(()));
+        assert!(budget.check(2_001, 20).is_err());
+        assert_eq!(check_conservation(30, &[10, 20]), Ok(()));
+        assert!(check_conservation(30, &[10, 19]).is_err());
+    }
+}
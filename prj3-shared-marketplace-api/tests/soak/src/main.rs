@@ -0,0 +1,313 @@
+//! Soak test for octopus-web: runs the server under sustained randomized load and periodically pauses the load to
+//! check the invariants and the server's memory. Fails on drift, unbounded memory growth, server errors, or a crash.
+//!
+//! ```sh
+//! cargo build -p octopus-web && cargo run -p octopus-soak -- --duration-secs 14400
+//! ```
+mod invariants;
+
+use std::{
+    path::PathBuf,
+    process::{Child, Command},
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+    time::{Duration, Instant},
+};
+
+use clap::Parser;
+use octopus_common::types::{
+    AccountBalanceRequest, AccountUpdateRequest, BookSnapshot, Order, OrderType, PartialOrder,
+    SendRequest, Side, TimeInForce, DEFAULT_MARKET,
+};
+use rand::{rngs::StdRng, Rng, SeedableRng};
+use reqwest::StatusCode;
+use tokio::sync::RwLock;
+
+use crate::invariants::MemoryBudget;
+
+/// The address octopus-web listens on
+const URL: &str = "http://localhost:3000";
+
+/// The account collecting the fees in octopus-web
+const FEE_ACCOUNT: &str = "octopus-fees";
+
+/// The balance every account starts with
+const INITIAL_DEPOSIT: u64 = 1_000_000;
+
+#[derive(Parser, Debug)]
+struct Args {
+    /// The octopus-web binary to run
+    #[arg(long, default_value = "target/debug/octopus-web")]
+    server: PathBuf,
+
+    /// How long to apply load
+    #[arg(long, default_value_t = 4 * 60 * 60)]
+    duration_secs: u64,
+
+    /// Seconds between invariant checks
+    #[arg(long, default_value_t = 60)]
+    check_interval_secs: u64,
+
+    #[arg(long, default_value_t = 20)]
+    accounts: usize,
+
+    /// Concurrent clients sending requests
+    #[arg(long, default_value_t = 8)]
+    clients: usize,
+
+    /// Resident memory may grow by this many bytes per request after the first check
+    #[arg(long, default_value_t = 4096)]
+    max_bytes_per_request: u64,
+
+    /// Seed of the random load, for reproducing a failure
+    #[arg(long)]
+    seed: Option<u64>,
+}
+
+/// What the clients did so far
+#[derive(Debug, Default)]
+struct Counters {
+    requests: AtomicU64,
+    deposited: AtomicU64,
+    server_errors: AtomicU64,
+}
+
+struct Soak {
+    client: reqwest::Client,
+    admin_key: String,
+    accounts: Vec<String>,
+    counters: Counters,
+    /// Clients hold it for reading during a request, checks take it for writing to pause the load
+    pause: RwLock<()>,
+}
+
+impl Soak {
+    fn post(&self, path: &str) -> reqwest::RequestBuilder {
+        self.client
+            .post(format!("{}{}", URL, path))
+            .header("x-api-key", &self.admin_key)
+    }
+
+    async fn deposit(&self, signer: &str, amount: u64) -> Result<(), String> {
+        let response = self
+            .post("/account/deposit")
+            .json(&AccountUpdateRequest {
+                signer: signer.to_string(),
+                amount,
+            })
+            .send()
+            .await
+            .map_err(|e| e.to_string())?;
+        if response.status().is_success() {
+            self.counters.deposited.fetch_add(amount, Ordering::SeqCst);
+        }
+        self.record(response.status());
+        Ok(())
+    }
+
+    fn record(&self, status: StatusCode) {
+        self.counters.requests.fetch_add(1, Ordering::Relaxed);
+        // A full order queue answers 503, which is expected under load
+        if status.is_server_error() && status != StatusCode::SERVICE_UNAVAILABLE {
+            self.counters.server_errors.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    /// One random order, transfer, or deposit
+    async fn act(&self, rng: &mut StdRng) -> Result<(), String> {
+        let _running = self.pause.read().await;
+        let signer = &self.accounts[rng.gen_range(0..self.accounts.len())];
+        let request = match rng.gen_range(0..100) {
+            0..=79 => self.post("/order").json(&Order {
+                price: rng.gen_range(90..=110),
+                amount: rng.gen_range(1..=10),
+                side: if rng.gen_bool(0.5) {
+                    Side::Buy
+                } else {
+                    Side::Sell
+                },
+                signer: signer.clone(),
+                client_order_id: None,
+                signature: None,
+                order_type: OrderType::Limit,
+                time_in_force: TimeInForce::Gtc,
+                trigger_price: None,
+                market: DEFAULT_MARKET.to_string(),
+            }),
+            80..=94 => self.post("/account/send").json(&SendRequest {
+                from: signer.clone(),
+                to: self.accounts[rng.gen_range(0..self.accounts.len())].clone(),
+                amount: rng.gen_range(1..=500),
+                to_alias: None,
+                memo: None,
+            }),
+            _ => return self.deposit(signer, rng.gen_range(1..=1_000)).await,
+        };
+        let response = request.send().await.map_err(|e| e.to_string())?;
+        self.record(response.status());
+        Ok(())
+    }
+
+    async fn balance_of(&self, signer: &str) -> Result<u64, String> {
+        let response = self
+            .post("/account")
+            .json(&AccountBalanceRequest {
+                signer: signer.to_string(),
+            })
+            .send()
+            .await
+            .map_err(|e| e.to_string())?;
+        match response.status() {
+            // The fee account only exists after the first fee
+            StatusCode::NOT_FOUND if signer == FEE_ACCOUNT => Ok(0),
+            status if status.is_success() => response.json().await.map_err(|e| e.to_string()),
+            status => Err(format!("balance of {} failed with {}", signer, status)),
+        }
+    }
+
+    async fn get<T: serde::de::DeserializeOwned>(&self, path: &str) -> Result<T, String> {
+        self.client
+            .get(format!("{}{}", URL, path))
+            .send()
+            .await
+            .and_then(|response| response.error_for_status())
+            .map_err(|e| e.to_string())?
+            .json()
+            .await
+            .map_err(|e| e.to_string())
+    }
+
+    /// Pauses the load and checks the invariants
+    async fn check(&self) -> Result<(), String> {
+        let _paused = self.pause.write().await;
+        let mut balances = vec![self.balance_of(FEE_ACCOUNT).await?];
+        for account in &self.accounts {
+            balances.push(self.balance_of(account).await?);
+        }
+        invariants::check_conservation(self.counters.deposited.load(Ordering::SeqCst), &balances)?;
+        let snapshot: BookSnapshot = self.get("/orderbook/snapshot").await?;
+        let orders: Vec<PartialOrder> = self.get("/orderbook").await?;
+        invariants::check_book(&snapshot, &orders)?;
+        let server_errors = self.counters.server_errors.load(Ordering::Relaxed);
+        if server_errors > 0 {
+            return Err(format!(
+                "{} requests failed with a server error",
+                server_errors
+            ));
+        }
+        Ok(())
+    }
+}
+
+fn start_server(args: &Args, admin_key: &str) -> Result<Child, String> {
+    Command::new(&args.server)
+        .env("OCTOPUS_ADMIN_KEY", admin_key)
+        .env("OCTOPUS_TAKER_FEE_BPS", "10")
+        .spawn()
+        .map_err(|e| format!("can't start {}: {}", args.server.display(), e))
+}
+
+async fn run(args: &Args, admin_key: &str, server: &mut Child) -> Result<(), String> {
+    let seed = args.seed.unwrap_or_else(rand::random);
+    println!("Soaking for {}s with seed {}", args.duration_secs, seed);
+    let soak = Arc::new(Soak {
+        client: reqwest::Client::new(),
+        admin_key: admin_key.to_string(),
+        accounts: (0..args.accounts).map(|i| format!("soak-{}", i)).collect(),
+        counters: Counters::default(),
+        pause: RwLock::new(()),
+    });
+
+    // Wait for the server to come up
+    let started = Instant::now();
+    while soak.get::<Vec<PartialOrder>>("/orderbook").await.is_err() {
+        if started.elapsed() > Duration::from_secs(30) {
+            return Err("the server didn't start within 30s".to_string());
+        }
+        tokio::time::sleep(Duration::from_millis(200)).await;
+    }
+    for account in &soak.accounts {
+        soak.deposit(account, INITIAL_DEPOSIT).await?;
+    }
+
+    let deadline = Instant::now() + Duration::from_secs(args.duration_secs);
+    let clients: Vec<_> = (0..args.clients as u64)
+        .map(|client| {
+            let soak = soak.clone();
+            tokio::spawn(async move {
+                let mut rng = StdRng::seed_from_u64(seed.wrapping_add(client));
+                while Instant::now() < deadline {
+                    soak.act(&mut rng).await?;
+                }
+                Ok::<(), String>(())
+            })
+        })
+        .collect();
+
+    let mut budget: Option<MemoryBudget> = None;
+    let mut interval = tokio::time::interval(Duration::from_secs(args.check_interval_secs));
+    interval.tick().await;
+    loop {
+        interval.tick().await;
+        if let Ok(Some(status)) = server.try_wait() {
+            return Err(format!("the server exited with {}", status));
+        }
+        for client in clients.iter().filter(|client| client.is_finished()) {
+            if Instant::now() < deadline {
+                return Err(format!("a client stopped early: {:?}", client));
+            }
+        }
+        soak.check().await?;
+
+        let requests = soak.counters.requests.load(Ordering::Relaxed);
+        let rss = invariants::rss_of(server.id()).ok_or("can't read the server's memory")?;
+        match budget {
+            Some(budget) => budget.check(rss, requests)?,
+            None => {
+                budget = Some(MemoryBudget {
+                    baseline_rss: rss,
+                    baseline_requests: requests,
+                    bytes_per_request: args.max_bytes_per_request,
+                })
+            }
+        }
+        println!(
+            "{:>6}s: {} requests, {} deposited, {} KiB resident, invariants hold",
+            started.elapsed().as_secs(),
+            requests,
+            soak.counters.deposited.load(Ordering::SeqCst),
+            rss / 1024
+        );
+        if Instant::now() >= deadline {
+            break;
+        }
+    }
+    for client in clients {
+        client.await.map_err(|e| e.to_string())??;
+    }
+    Ok(())
+}
+
+#[tokio::main]
+async fn main() {
+    let args = Args::parse();
+    let admin_key = format!("soak-admin-{}", rand::random::<u64>());
+    let mut server = match start_server(&args, &admin_key) {
+        Ok(server) => server,
+        Err(e) => {
+            eprintln!("{}", e);
+            std::process::exit(2);
+        }
+    };
+    let result = run(&args, &admin_key, &mut server).await;
+    let _ = server.kill();
+    match result {
+        Ok(()) => println!("Soak test passed"),
+        Err(e) => {
+            eprintln!("Soak test failed: {}", e);
+            std::process::exit(1);
+        }
+    }
+}